@@ -0,0 +1,50 @@
+//! Check the golden encoding vectors against the encodings of this crate, or regenerate them:
+//!
+//!     `cargo run --bin golden_encodings -- [--regenerate] [--out <path>]`
+//!
+//! The vectors are `tendermintx::golden`'s, and regenerating them is only for a deliberate change
+//! to the step or skip encodings: the contract has to change with them.
+
+use std::path::PathBuf;
+
+use anyhow::{ensure, Context, Result};
+use clap::Parser;
+use tendermintx::golden::{vectors_path, EncodingVectors};
+
+#[derive(Parser)]
+#[command(about = "Golden vectors of the TendermintX step and skip encodings")]
+struct Cli {
+    /// Write the vectors as this crate encodes them now, instead of checking them.
+    #[arg(long)]
+    regenerate: bool,
+    /// The vectors file. Defaults to the one the tests and the Foundry tests read.
+    #[arg(long)]
+    out: Option<PathBuf>,
+}
+
+fn main() -> Result<()> {
+    let cli = Cli::parse();
+    let path = cli.out.unwrap_or_else(vectors_path);
+    let generated = EncodingVectors::generate();
+    if cli.regenerate {
+        std::fs::write(&path, generated.to_json())
+            .with_context(|| format!("failed to write {}", path.display()))?;
+        println!(
+            "Wrote {} step and {} skip vectors to {}",
+            generated.step.len(),
+            generated.skip.len(),
+            path.display()
+        );
+        return Ok(());
+    }
+
+    let differences = generated.diff(&EncodingVectors::read(&path)?);
+    ensure!(
+        differences.is_empty(),
+        "{} doesn't match the encodings:\n{}\nIf the change is deliberate, rerun with --regenerate",
+        path.display(),
+        differences.join("\n")
+    );
+    println!("{} matches the encodings", path.display());
+    Ok(())
+}
@@ -2,388 +2,1290 @@
 //!
 //!     `cargo build --release --bin tendermintx`
 //!
+//! The operator itself is `tendermintx::operator`: this is its command line.
 
-use std::env;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
 use alloy_primitives::{Address, Bytes, B256};
-use alloy_sol_types::{sol, SolType};
-use anyhow::Result;
-use ethers::abi::AbiEncode;
-use ethers::contract::abigen;
-use ethers::providers::{Http, Provider};
-use log::{error, info};
-use subtle_encoding::hex;
-use succinct_client::request::SuccinctClient;
+use anyhow::{anyhow, Context, Result};
+use clap::{Parser, Subcommand};
+use ethers::providers::Middleware;
+use tendermintx::audit::unix_timestamp_ms;
+use tendermintx::backend::RequestKind;
+use tendermintx::backfill::Backfill;
+use tendermintx::contract::TendermintXContract;
+use tendermintx::control::{self, ControlCommand};
+use tendermintx::correlation::CorrelationId;
+use tendermintx::encoding::InputDigest;
+use tendermintx::export::RequestInputs;
+use tendermintx::field_proof::{FieldProof, HeaderField};
+use tendermintx::groth16::{self, ExpectedRange, ProvedRange, VerifyingKeyFile};
+use tendermintx::input::light_blocks::LightBlocks;
 use tendermintx::input::InputDataFetcher;
+use tendermintx::metrics::{write_request_stats, write_turnarounds, MetricsWriter};
+use tendermintx::operator::env::{env_opt, ethereum_providers, leader_election, signer_source};
+#[cfg(any(feature = "api", feature = "grpc"))]
+use tendermintx::operator::{ApiConfig, SharedOperator};
+use tendermintx::operator::{TendermintXConfig, TendermintXOperator};
+use tendermintx::proof::ProofDocument;
+use tendermintx::registry::ChainRegistry;
+use tendermintx::repair::{self, ScanBounds};
+use tendermintx::signer::{self_test, signer_client};
+use tendermintx::store::{parse_age, unix_timestamp, RequestStore};
+use tendermintx::summary::{RunSummary, EXIT_FAILED};
+use tendermintx::types::{HeaderHash, Height};
+use tendermintx::{attestation, audit, dashboard, ibc, logging, migrate, reporting, snapshot};
+use tracing::{error, info};
 
-// Note: Update ABI when updating contract.
-abigen!(TendermintX, "./abi/TendermintX.abi.json");
+/// How long a one-shot run waits for its background writes before it fails.
+const ONESHOT_FLUSH_TIMEOUT: Duration = Duration::from_secs(30);
 
-struct TendermintXConfig {
-    address: Address,
-    chain_id: u32,
-    step_function_id: B256,
-    skip_function_id: B256,
+#[derive(Parser)]
+#[command(about = "Operator for the TendermintX light client")]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
 }
 
-struct TendermintXOperator {
-    config: TendermintXConfig,
-    contract: TendermintX<Provider<Http>>,
-    client: SuccinctClient,
-    data_fetcher: InputDataFetcher,
+#[derive(Subcommand)]
+enum Command {
+    /// Request a proof from a trusted block to a target block.
+    Prove {
+        /// The trusted block the proof starts from.
+        trusted_block: Height,
+        /// The block to prove.
+        target_block: Height,
+        /// The header hash of the trusted block, as hex.
+        trusted_hash: HeaderHash,
+        /// Wait for the requests to be fulfilled: relayed on-chain, or proved for off-chain targets.
+        #[arg(long)]
+        wait: bool,
+        /// The maximum time to wait for fulfillment, in seconds.
+        #[arg(long, default_value_t = 3600)]
+        timeout: u64,
+        /// Once the off-chain request is proved, write its proof and public values to this file
+        /// as JSON, for verifiers outside the EVM. Implies --wait.
+        #[arg(long, value_name = "PATH")]
+        emit_proof: Option<PathBuf>,
+        /// Build the input from the light blocks in this directory or bundle (- for stdin)
+        /// instead of the Tendermint RPC. The range isn't checked against the skip_max of the
+        /// contracts.
+        #[arg(long, value_name = "PATH")]
+        light_blocks: Option<PathBuf>,
+    },
+    /// Request a commitment to the data hashes of the headers from a trusted block to a target
+    /// block, for contracts that take data commitments (DATA_COMMITMENTS).
+    ProveCommitment {
+        /// The trusted block the commitment starts from.
+        trusted_block: Height,
+        /// The last block of the commitment.
+        target_block: Height,
+        /// The header hash of the trusted block, as hex.
+        trusted_hash: HeaderHash,
+        /// Wait for the requests to be fulfilled: relayed on-chain, or proved for off-chain targets.
+        #[arg(long)]
+        wait: bool,
+        /// The maximum time to wait for fulfillment, in seconds.
+        #[arg(long, default_value_t = 3600)]
+        timeout: u64,
+        /// Once the off-chain request is proved, write its proof and public values to this file
+        /// as JSON, for verifiers outside the EVM. Implies --wait.
+        #[arg(long, value_name = "PATH")]
+        emit_proof: Option<PathBuf>,
+    },
+    /// Continuously update the light client.
+    Run {
+        /// Submit skips back to back, each as soon as the previous one landed, until the targets
+        /// are less than CATCH_UP_EXIT_LAG_BLOCKS behind. Also automatic when a target is more
+        /// than skip_max behind.
+        #[arg(long)]
+        catch_up: bool,
+        /// Run a single iteration and exit once its background writes completed, e.g. from a
+        /// CronJob. Exits with 0 if a request was submitted, 3 if there was nothing to do and 1 if
+        /// it failed.
+        #[arg(long)]
+        once: bool,
+        /// Write the result of the one-shot run to this file as JSON.
+        #[arg(long, value_name = "PATH", requires = "once")]
+        summary_file: Option<PathBuf>,
+    },
+    /// Continuously update the light client, and serve the REST API at API_BIND_ADDR and the
+    /// gRPC service at GRPC_BIND_ADDR to request proofs and read their status remotely.
+    #[cfg(any(feature = "api", feature = "grpc"))]
+    Serve {
+        /// As for `run`.
+        #[arg(long)]
+        catch_up: bool,
+    },
+    /// Print how far each target is behind the chain head.
+    Status {
+        /// Instead, print the requests of the attempt with this correlation ID, and their status
+        /// with the backend.
+        #[arg(long)]
+        correlation_id: Option<CorrelationId>,
+    },
+    /// Check the settings in the environment, and that the circuits behind the function IDs are
+    /// those of CIRCUIT_DIGESTS.
+    CheckConfig,
+    /// Print the header hash of a block, as input generation computes it.
+    Hash {
+        #[arg(long)]
+        height: Height,
+    },
+    /// Print the value of a field of the header of a block, and the Merkle proof of it against the
+    /// block's header hash, e.g. to prove its `app_hash` against the header hash a TendermintX
+    /// contract stores.
+    FieldProof {
+        #[arg(long)]
+        height: Height,
+        /// The field, named as in the protobuf definition of the header (e.g. `app_hash`,
+        /// `data_hash`).
+        #[arg(long)]
+        field: HeaderField,
+        /// Print the proof as JSON, as `verify-field-proof` reads it.
+        #[arg(long)]
+        json: bool,
+    },
+    /// Verify a field proof written by `field-proof --json`, and print the field's value.
+    VerifyFieldProof {
+        /// The field proof.
+        #[arg(long)]
+        proof: PathBuf,
+        /// The header hash the proof is expected to verify against, as hex.
+        #[arg(long, conflicts_with = "contract")]
+        header_hash: Option<HeaderHash>,
+        /// Expect the header hash a target's contract stores for the proof's block.
+        #[arg(long)]
+        contract: bool,
+        /// The chain ID of the target with `--contract`, the first target by default.
+        #[arg(long, requires = "contract")]
+        chain_id: Option<u32>,
+    },
+    /// Write the IBC client header updating a Tendermint client from a trusted block to a target
+    /// block, as the protobuf `Any` of a `MsgUpdateClient`.
+    IbcHeader {
+        /// The block the client trusts.
+        #[arg(long)]
+        trusted: Height,
+        /// The block to update the client to.
+        #[arg(long)]
+        target: Height,
+        /// The file to write.
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Verify the proof of a proof document (as written by `prove --emit-proof`) and print the
+    /// range its public values are for. Fails saying whether the proof or the binding of its
+    /// public inputs to the expected range is at fault.
+    VerifyProof {
+        /// The proof document.
+        #[arg(long)]
+        proof: PathBuf,
+        /// The verifying key of the circuit, as JSON.
+        #[arg(long, value_name = "PATH")]
+        vk: PathBuf,
+        /// The trusted block the proof is expected to start from.
+        #[arg(long)]
+        trusted_block: Option<Height>,
+        /// The header hash the trusted block is expected to have, as hex.
+        #[arg(long)]
+        trusted_hash: Option<HeaderHash>,
+        /// The block the proof is expected to prove.
+        #[arg(long)]
+        target_block: Option<Height>,
+        /// The header hash the target block is expected to have, as hex.
+        #[arg(long)]
+        target_hash: Option<HeaderHash>,
+    },
+    /// Seed a new TendermintX contract with the latest block of the contract it replaces, once
+    /// that is verified against the chain, and check the new deployment. Without a signing key,
+    /// prints the transaction to seed it with instead: run the command again once it is sent.
+    Migrate {
+        /// The contract being replaced.
+        #[arg(long)]
+        old: Address,
+        /// The new contract.
+        #[arg(long)]
+        new: Address,
+    },
+    /// Find the newest block whose header stored by a target's contract is the chain's, scanning
+    /// backwards from its latest block, and print where the stored headers diverge and the skips
+    /// from that block to the chain head. Reads only, unless `--execute`: with a signing key, that
+    /// block is then set back as the latest one, after which the run loop proves the skips.
+    /// Without one, prints the transaction to send instead.
+    Repair {
+        /// The chain ID of the target to repair. Defaults to the first target.
+        #[arg(long)]
+        chain_id: Option<u32>,
+        /// Read the stored blocks from the `HeadUpdate` events since this Ethereum block, instead
+        /// of probing every block.
+        #[arg(long)]
+        events_from: Option<u64>,
+        /// The number of blocks read at once.
+        #[arg(long, default_value_t = repair::DEFAULT_BATCH)]
+        batch: u64,
+        /// The depth of the scan below the latest block, in blocks.
+        #[arg(long, default_value_t = repair::DEFAULT_MAX_BLOCKS)]
+        max_blocks: u64,
+        /// Set the consistent block back as the latest one.
+        #[arg(long)]
+        execute: bool,
+    },
+    /// Record the Tendermint RPC responses the step and skip inputs of a range are built from
+    /// into a snapshot directory, for the tests.
+    Snapshot {
+        /// The Tendermint RPC to record from.
+        #[arg(long)]
+        rpc: String,
+        /// The trusted block of the range.
+        #[arg(long)]
+        from: Height,
+        /// The target block of the range.
+        #[arg(long)]
+        to: Height,
+        /// The snapshot directory.
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Show the status of the targets, the pending requests and the recent errors, refreshed
+    /// live. Prints the status once when stdout is not a terminal.
+    Dashboard {
+        /// The time between refreshes, in seconds.
+        #[arg(long, default_value_t = dashboard::DEFAULT_REFRESH.as_secs())]
+        refresh: u64,
+    },
+    /// Build the input of a request without submitting it.
+    ExportInput {
+        /// The trusted block the proof starts from.
+        #[arg(long)]
+        trusted: Height,
+        /// The block to prove.
+        #[arg(long)]
+        target: Height,
+        /// The file to write.
+        #[arg(long)]
+        out: PathBuf,
+        /// Write the whole request as JSON, including the function ID and calldata, instead of
+        /// the raw input bytes.
+        #[arg(long)]
+        json: bool,
+        /// The chain ID of the target to build the request for. Defaults to the first target.
+        #[arg(long)]
+        chain_id: Option<u32>,
+        /// The header hash of the trusted block, as hex, instead of the one the contract stores.
+        #[arg(long)]
+        trusted_hash: Option<HeaderHash>,
+        /// Build the input from the light blocks in this directory or bundle (- for stdin)
+        /// instead of the Tendermint RPC and the contract, without any network access.
+        #[arg(long, value_name = "PATH", requires = "trusted_hash")]
+        light_blocks: Option<PathBuf>,
+        /// Also write the light blocks the request reads to this file, as a bundle for
+        /// --light-blocks.
+        #[arg(long, value_name = "PATH", conflicts_with = "light_blocks")]
+        save_light_blocks: Option<PathBuf>,
+    },
+    /// Print the packed input of a request, the digests of it that the gateway and the verifier
+    /// check, and the calldata of its callback. Needs no settings.
+    InputDigest {
+        /// The trusted block the proof starts from.
+        #[arg(long)]
+        trusted: Height,
+        /// The header hash of the trusted block, as the contract stores it.
+        #[arg(long)]
+        hash: HeaderHash,
+        /// The block to prove.
+        #[arg(long)]
+        target: Height,
+        /// The input of a data commitment over the range instead of a step or skip.
+        #[arg(long)]
+        data_commitment: bool,
+    },
+    /// Prove historical checkpoints into a target's contract (e.g. an archive contract), one
+    /// after the other and independently of the chain head. Run it again to resume.
+    Backfill {
+        /// The first block, which the contract must already store.
+        #[arg(long)]
+        start: u64,
+        /// The last checkpoint.
+        #[arg(long)]
+        end: u64,
+        /// The distance between checkpoints, in blocks.
+        #[arg(long, default_value_t = 50_000)]
+        stride: u64,
+        /// The chain ID of the target to backfill. Defaults to the first target.
+        #[arg(long)]
+        chain_id: Option<u32>,
+    },
+    /// Submit a request exported with `export-input --json`.
+    SubmitInput {
+        #[arg(long = "in")]
+        input: PathBuf,
+    },
+    /// Inspect the requests recorded in the request store.
+    Requests {
+        #[command(subcommand)]
+        command: RequestsCommand,
+    },
+    /// Inspect the audit log.
+    Audit {
+        #[command(subcommand)]
+        command: AuditCommand,
+    },
+    /// Control the running operator over its control socket.
+    Ctl {
+        /// The control socket. Defaults to CONTROL_SOCKET.
+        #[arg(long)]
+        socket: Option<PathBuf>,
+        #[command(subcommand)]
+        command: CtlCommand,
+    },
 }
 
-type StepInputTuple = sol! { tuple(uint64, bytes32) };
-
-type SkipInputTuple = sol! { tuple(uint64, bytes32, uint64) };
+#[derive(Subcommand)]
+enum CtlCommand {
+    /// Stop submitting requests. The operator keeps monitoring the targets.
+    Pause,
+    /// Submit requests again.
+    Resume,
+    /// Print whether submissions are paused.
+    Status,
+    /// List the chains of the chain registry and their priorities.
+    Chains,
+    /// Add a chain to the chain registry.
+    AddChain {
+        /// The chain, as a JSON object like the entries of CHAIN_REGISTRY.
+        file: PathBuf,
+    },
+    /// Remove a chain from the chain registry.
+    RemoveChain {
+        /// The chain ID of the chain.
+        chain_id: String,
+    },
+}
 
-impl TendermintXOperator {
-    pub fn new() -> Self {
-        let config = Self::get_config();
+#[derive(Subcommand)]
+enum AuditCommand {
+    /// Check that every entry of the audit log and its rotated files parses, and that their
+    /// timestamps never decrease.
+    Verify {
+        /// The audit log. Defaults to AUDIT_LOG_PATH.
+        #[arg(long)]
+        path: Option<PathBuf>,
+    },
+    /// Check the attestation signed with every submission of the audit log and its rotated
+    /// files against the operator's public key.
+    VerifySignatures {
+        /// The operator's ed25519 public key, as hex.
+        #[arg(long)]
+        pubkey: String,
+        /// The audit log. Defaults to AUDIT_LOG_PATH.
+        #[arg(long)]
+        path: Option<PathBuf>,
+    },
+}
 
-        let ethereum_rpc_url = env::var("ETHEREUM_RPC_URL").expect("ETHEREUM_RPC_URL must be set");
-        let provider =
-            Provider::<Http>::try_from(ethereum_rpc_url).expect("could not connect to client");
+#[derive(Subcommand)]
+enum RequestsCommand {
+    /// List the most recent requests, newest first.
+    List {
+        #[arg(long, default_value_t = 20)]
+        limit: usize,
+    },
+    /// Show a single request, or the requests of an attempt given its correlation ID.
+    Show { request_id: String },
+    /// Resubmit exactly what a stored request contained, as a retry of it.
+    Replay {
+        request_id: String,
+        /// Replay even if the contract is already past the request's target block.
+        #[arg(long)]
+        force: bool,
+    },
+    /// Print the totals and averages of the requests per chain, and the distribution of the step
+    /// and skip sizes of the last day.
+    Stats {
+        /// Only include requests submitted within this age, e.g. 30d or 12h.
+        #[arg(long, default_value = "30d")]
+        since: String,
+        /// Print the statistics as Prometheus metrics.
+        #[arg(long)]
+        prometheus: bool,
+    },
+}
 
-        let contract = TendermintX::new(config.address.0 .0, provider.into());
+/// Print the requests recorded in the store at REQUEST_STORE_PATH.
+fn print_requests(command: RequestsCommand) -> Result<()> {
+    let path =
+        env_opt("REQUEST_STORE_PATH").ok_or_else(|| anyhow!("REQUEST_STORE_PATH must be set"))?;
+    let store = RequestStore::open(path)?;
+    match command {
+        RequestsCommand::List { limit } => {
+            for record in store.list(limit)? {
+                println!("{}", record);
+            }
+        }
+        RequestsCommand::Show { request_id } => {
+            if let Some(record) = store.get(&request_id)? {
+                println!("{:#?}", record);
+                if let Some(attestation) = store.attestation(&request_id)? {
+                    println!("{:#?}", attestation);
+                }
+                return Ok(());
+            }
+            let records = match request_id.parse::<CorrelationId>() {
+                Ok(correlation_id) => store.by_correlation_id(correlation_id)?,
+                Err(_) => Vec::new(),
+            };
+            if records.is_empty() {
+                return Err(anyhow!("request {} is not in the store", request_id));
+            }
+            for record in records {
+                println!("{:#?}", record);
+            }
+        }
+        RequestsCommand::Replay { .. } => unreachable!("replays are submitted by the operator"),
+        RequestsCommand::Stats { since, prometheus } => {
+            let since = unix_timestamp().saturating_sub(parse_age(&since)?.as_secs());
+            let stats = store.stats(since)?;
+            let turnarounds = store.turnarounds(since)?;
+            // The sizes are for tuning skip_max and the loop delay, so only recent ones matter.
+            let sizes = store.request_sizes(unix_timestamp().saturating_sub(24 * 60 * 60))?;
+            if prometheus {
+                let mut writer = MetricsWriter::new();
+                write_request_stats(&mut writer, &stats);
+                write_turnarounds(&mut writer, &turnarounds);
+                print!("{}", writer.finish());
+                return Ok(());
+            }
+            for chain in stats {
+                println!(
+                    "chain {}: {} submitted, {} relayed, {} delivered, {} failed, {} abandoned, \
+                     {} pending",
+                    chain.chain_id,
+                    chain.submitted,
+                    chain.relayed,
+                    chain.delivered,
+                    chain.failed,
+                    chain.abandoned,
+                    chain.pending
+                );
+                println!(
+                    "  cost: {} total, {} average; average time to relay: {}",
+                    chain
+                        .total_cost
+                        .map_or("unknown".to_string(), |c| c.to_string()),
+                    chain
+                        .average_cost
+                        .map_or("unknown".to_string(), |c| format!("{:.4}", c)),
+                    chain
+                        .average_duration
+                        .map_or("unknown".to_string(), |d| format!("{:?}", d))
+                );
+                if let Some(last) = turnarounds
+                    .iter()
+                    .rev()
+                    .find(|t| t.chain_id == chain.chain_id)
+                {
+                    println!(
+                        "  last turnaround ({}, block {}): fulfilled after {}, on-chain after {}",
+                        last.request_id,
+                        last.target_block,
+                        last.fulfilled
+                            .map_or("unknown".to_string(), |d| format!("{:?}", d)),
+                        last.onchain
+                            .map_or("unknown".to_string(), |d| format!("{:?}", d))
+                    );
+                }
+                for size in sizes.iter().filter(|s| s.chain_id == chain.chain_id) {
+                    println!(
+                        "  {} sizes in the last 24h: {} requests, min {}, median {}, max {} blocks",
+                        size.kind, size.count, size.min, size.median, size.max
+                    );
+                }
+            }
+        }
+    }
+    Ok(())
+}
 
-        let data_fetcher = InputDataFetcher::default();
+/// Run an audit log command.
+fn audit_command(command: AuditCommand) -> Result<()> {
+    let log_files = |path: Option<PathBuf>| {
+        let path = path
+            .or_else(|| env_opt("AUDIT_LOG_PATH").map(PathBuf::from))
+            .ok_or_else(|| anyhow!("AUDIT_LOG_PATH must be set"))?;
+        let files = audit::log_files(&path);
+        if files.is_empty() {
+            return Err(anyhow!("audit log {} does not exist", path.display()));
+        }
+        Ok((path, files))
+    };
+    match command {
+        AuditCommand::Verify { path } => {
+            let (path, files) = log_files(path)?;
+            let summary = audit::verify(&files)?;
+            println!("{}: ok, {}", path.display(), summary);
+        }
+        AuditCommand::VerifySignatures { pubkey, path } => {
+            let key = attestation::parse_public_key(&pubkey)?;
+            let (path, files) = log_files(path)?;
+            let summary = audit::verify_signatures(&files, &key)?;
+            println!("{}: ok, {}", path.display(), summary);
+        }
+    }
+    Ok(())
+}
 
-        let succinct_rpc_url = env::var("SUCCINCT_RPC_URL").expect("SUCCINCT_RPC_URL must be set");
-        let succinct_api_key = env::var("SUCCINCT_API_KEY").expect("SUCCINCT_API_KEY must be set");
-        let client = SuccinctClient::new(succinct_rpc_url, succinct_api_key, false, false);
+async fn ctl_command(socket: Option<PathBuf>, command: CtlCommand) -> Result<()> {
+    let socket = socket
+        .or_else(|| env_opt("CONTROL_SOCKET").map(PathBuf::from))
+        .ok_or_else(|| anyhow!("CONTROL_SOCKET must be set"))?;
+    let command = match command {
+        CtlCommand::Pause => ControlCommand::Pause,
+        CtlCommand::Resume => ControlCommand::Resume,
+        CtlCommand::Status => ControlCommand::Status,
+        CtlCommand::Chains => ControlCommand::Chains,
+        CtlCommand::AddChain { file } => {
+            let json = std::fs::read_to_string(&file)
+                .with_context(|| format!("failed to read {}", file.display()))?;
+            let entry = serde_json::from_str(&json)
+                .with_context(|| format!("invalid chain in {}", file.display()))?;
+            ControlCommand::AddChain(Box::new(entry))
+        }
+        CtlCommand::RemoveChain { chain_id } => ControlCommand::RemoveChain(chain_id),
+    };
+    println!("{}", control::send(&socket, command).await?);
+    Ok(())
+}
 
-        Self {
-            config,
-            contract,
-            client,
-            data_fetcher,
+/// Migrate from the contract `old` to `new`, on the first Ethereum RPC of ETHEREUM_RPC_URL.
+async fn migrate_command(old: Address, new: Address) -> Result<()> {
+    env_opt("TENDERMINT_RPC_URL").ok_or_else(|| anyhow!("TENDERMINT_RPC_URL must be set"))?;
+    let fetcher = InputDataFetcher::default();
+    let (providers, _) = ethereum_providers(1)?;
+    let provider = providers[0].clone();
+    let old = TendermintXContract::new(old, provider.clone());
+    let Some(source) = signer_source()? else {
+        let new = TendermintXContract::new(new, provider);
+        let plan = migrate::plan(&old, &new, &fetcher).await?;
+        if plan.seeded {
+            migrate::check_deployment(&new, &fetcher, &plan.state).await?;
+            println!(
+                "{} is seeded with {} and consistent",
+                new.address(),
+                plan.state
+            );
+            return Ok(());
         }
+        println!(
+            "Verified {} of {}. Without a signing key, seed {} with:",
+            plan.state, plan.old, plan.new
+        );
+        println!(
+            "  cast send {} 'setGenesisHeader(uint64,bytes32)' {} {}",
+            plan.new, plan.state.block, plan.state.header_hash
+        );
+        println!("  (calldata {})", plan.seed_calldata());
+        println!("and run this command again to check the new deployment.");
+        return Ok(());
+    };
+    let signer = source.signer().await?;
+    self_test(&signer).await?;
+    let client = signer_client(provider.as_ref().clone(), signer).await?;
+    let new = TendermintXContract::new(new, Arc::new(client));
+    let migration = migrate::migrate(&old, &new, &fetcher).await?;
+    match migration.tx_hash {
+        Some(tx_hash) => println!(
+            "Seeded {} with {} of {} in tx {}",
+            migration.plan.new,
+            migration.plan.state,
+            migration.plan.old,
+            B256::from(tx_hash)
+        ),
+        None => println!(
+            "{} was already seeded with {}",
+            migration.plan.new, migration.plan.state
+        ),
     }
+    println!("The consistency check of {} passed", migration.plan.new);
+    Ok(())
+}
 
-    fn get_config() -> TendermintXConfig {
-        let contract_address = env::var("CONTRACT_ADDRESS").expect("CONTRACT_ADDRESS must be set");
-        let chain_id = env::var("CHAIN_ID").expect("CHAIN_ID must be set");
-        let address = contract_address
-            .parse::<Address>()
-            .expect("invalid address");
-
-        // Load the function IDs.
-        let step_id_env = env::var("STEP_FUNCTION_ID").expect("STEP_FUNCTION_ID must be set");
-        let step_function_id = B256::from_slice(
-            &hex::decode(step_id_env.strip_prefix("0x").unwrap_or(&step_id_env))
-                .expect("invalid hex for step_function_id, expected 0x prefix"),
-        );
-        let skip_id_env = env::var("SKIP_FUNCTION_ID").expect("SKIP_FUNCTION_ID must be set");
-        let skip_function_id = B256::from_slice(
-            &hex::decode(skip_id_env.strip_prefix("0x").unwrap_or(&skip_id_env))
-                .expect("invalid hex for skip_function_id, expected 0x prefix"),
+/// Verify the field proof at `path` against `header_hash`, or with `contract` against the header
+/// hash the target of `chain_id` stores.
+async fn verify_field_proof(
+    path: &Path,
+    header_hash: Option<HeaderHash>,
+    contract: bool,
+    chain_id: Option<u32>,
+) -> Result<FieldProof> {
+    let json = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let proof: FieldProof = serde_json::from_str(&json)
+        .with_context(|| format!("invalid field proof {}", path.display()))?;
+    let expected = match contract {
+        true => {
+            let operator = TendermintXOperator::from_env()?;
+            let stored = operator.stored_header_hash(chain_id, proof.height).await?;
+            Some(stored.ok_or_else(|| {
+                anyhow!(
+                    "the contract stores no header hash for block {}",
+                    proof.height
+                )
+            })?)
+        }
+        false => header_hash,
+    };
+    proof.verify(expected)?;
+    Ok(proof)
+}
+
+/// Plan the repair of the target of `chain_id` and print it, then carry it out if `execute`.
+async fn repair_command(
+    chain_id: Option<u32>,
+    events_from: Option<u64>,
+    bounds: ScanBounds,
+    execute: bool,
+) -> Result<()> {
+    let operator = TendermintXOperator::from_env()?;
+    let plan = operator.repair_plan(chain_id, events_from, bounds).await?;
+    print!("{}", plan);
+    if !plan.needs_rewind() {
+        return Ok(());
+    }
+    if !execute {
+        println!("Run this command again with --execute to set it back.");
+        return Ok(());
+    }
+    let source = match signer_source()? {
+        Some(source) => source,
+        None => {
+            let address = operator.target_address(chain_id)?;
+            println!("Without a signing key, set it back with:");
+            println!(
+                "  cast send {} 'setGenesisHeader(uint64,bytes32)' {} {}",
+                address, plan.state.block, plan.state.header_hash
+            );
+            println!("  (calldata {})", plan.rewind_calldata());
+            println!("and run this command again to check the contract.");
+            return Ok(());
+        }
+    };
+    let config = TendermintXConfig::from_env()?;
+    let operator = TendermintXOperator::from_env_with_signer(config, source).await?;
+    if let Some(tx_hash) = operator.repair(chain_id, &plan).await? {
+        println!(
+            "Set {} back as the latest block in tx {}: the run loop proves the skips from there",
+            plan.state,
+            B256::from(tx_hash)
         );
+    }
+    Ok(())
+}
 
-        TendermintXConfig {
-            address,
-            chain_id: chain_id.parse::<u32>().expect("invalid chain id"),
-            step_function_id,
-            skip_function_id,
+/// Resolves on Ctrl-C or, on Unix, SIGTERM.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut sigterm = signal(SignalKind::terminate()).expect("could not listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = sigterm.recv() => {}
         }
     }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await.ok();
+}
 
-    async fn request_step(&self, trusted_block: u64) -> Result<String> {
-        let trusted_header_hash = self
-            .contract
-            .block_height_to_header_hash(trusted_block)
-            .await
-            .unwrap();
-
-        let input = StepInputTuple::abi_encode_packed(&(trusted_block, trusted_header_hash));
-
-        let step_call = StepCall { trusted_block };
-        let function_data = step_call.encode();
-
-        let request_id = self
-            .client
-            .submit_platform_request(
-                self.config.chain_id,
-                self.config.address,
-                function_data.into(),
-                self.config.step_function_id,
-                Bytes::copy_from_slice(&input),
-            )
-            .await?;
-        Ok(request_id)
+/// Run the loop of `operator` until it fails or the process is signaled.
+async fn run<M: Middleware + 'static>(mut operator: TendermintXOperator<M>) {
+    if let Some(election) = or_exit(leader_election(operator.metrics().clone())) {
+        operator.set_election(election);
+    }
+    // The run loop only returns on a failure, or once the submissions of the current iteration
+    // are recorded after the signal.
+    let failure = operator.run_until(shutdown_signal()).await.err();
+    match failure.as_ref() {
+        Some(e) => error!("The run loop stopped: {:#}", e),
+        None => {
+            info!("Shutting down");
+            operator.drain(shutdown_signal()).await;
+        }
     }
+    operator.stop().await;
+    logging::shutdown();
+    if failure.is_some() {
+        std::process::exit(1);
+    }
+}
 
-    async fn request_step_2(&self, trusted_hash: [u8; 32], trusted_block: u64) -> Result<String> {
-        let trusted_header_hash = trusted_hash;
-
-        let input = StepInputTuple::abi_encode_packed(&(trusted_block, trusted_header_hash));
-
-        let step_call = StepCall { trusted_block };
-        let function_data = step_call.encode();
-
-        let request_id = self
-            .client
-            .submit_platform_request(
-                self.config.chain_id,
-                self.config.address,
-                function_data.into(),
-                self.config.step_function_id,
-                Bytes::copy_from_slice(&input),
-            )
-            .await?;
-        Ok(request_id)
+/// Run a single iteration of `operator`, write its summary to `summary_file` if set, and exit with
+/// the code of its outcome. A run started at `started_at_ms` whose operator couldn't be built
+/// fails.
+async fn run_oneshot<M: Middleware + 'static>(
+    operator: Result<TendermintXOperator<M>>,
+    started_at_ms: u64,
+    summary_file: Option<PathBuf>,
+) {
+    let summary = match operator {
+        Ok(mut operator) => operator.run_oneshot(ONESHOT_FLUSH_TIMEOUT).await,
+        Err(e) => {
+            let mut summary = RunSummary::of(started_at_ms, Err(e));
+            summary.flushed(Duration::ZERO, Ok(()));
+            summary
+        }
+    };
+    match summary.error.as_ref() {
+        Some(e) => error!("The one-shot run failed: {}", e),
+        None => info!("One-shot run finished: {}", summary.outcome),
+    }
+    let mut exit_code = summary.exit_code;
+    if let Some(path) = summary_file {
+        if let Err(e) = summary.write(&path) {
+            error!("{:#}", e);
+            exit_code = EXIT_FAILED;
+        }
     }
+    logging::shutdown();
+    std::process::exit(exit_code)
+}
 
-    async fn request_skip(&self, trusted_block: u64, target_block: u64) -> Result<String> {
-        let trusted_header_hash = self
-            .contract
-            .block_height_to_header_hash(trusted_block)
-            .await
-            .unwrap();
+/// Run the loop with `config`, serving the APIs beside it, until it fails or the process is
+/// signaled.
+#[cfg(any(feature = "api", feature = "grpc"))]
+async fn serve(config: TendermintXConfig) -> Result<()> {
+    if env_opt("CHAIN_REGISTRY").is_some() {
+        return Err(anyhow!("serve does not take a CHAIN_REGISTRY"));
+    }
+    if signer_source()?.is_some() {
+        return Err(anyhow!("serve does not take a signing key, use run"));
+    }
+    let (rest, grpc) = (config.api.clone(), config.grpc.clone());
+    if rest.is_none() && grpc.is_none() {
+        return Err(anyhow!("serve requires API_BIND_ADDR or GRPC_BIND_ADDR"));
+    }
+    let (operator, mut api_operator) = TendermintXOperator::with_api_from_env(config)?;
+    api_operator.resolve_function_ids().await?;
+    let api_operator = Arc::new(tokio::sync::RwLock::new(api_operator));
+    spawn_rest(&api_operator, rest)?;
+    spawn_grpc(&api_operator, grpc)?;
+    run(operator).await;
+    Ok(())
+}
 
-        let input =
-            SkipInputTuple::abi_encode_packed(&(trusted_block, trusted_header_hash, target_block));
+/// Serve the REST API with `operator` as `config` says, if set.
+#[cfg(feature = "api")]
+fn spawn_rest(operator: &SharedOperator, config: Option<ApiConfig>) -> Result<()> {
+    let Some(config) = config else {
+        return Ok(());
+    };
+    let listener = std::net::TcpListener::bind(config.addr)
+        .with_context(|| format!("could not bind the API to {}", config.addr))?;
+    let router = tendermintx::api::router(operator.clone(), config.token);
+    tokio::spawn(async move {
+        if let Err(e) = tendermintx::api::serve(router, listener).await {
+            error!("The API stopped: {:#}", e);
+        }
+    });
+    Ok(())
+}
 
-        let skip_call = SkipCall {
-            trusted_block,
-            target_block,
-        };
-        let function_data = skip_call.encode();
-
-        let request_id = self
-            .client
-            .submit_platform_request(
-                self.config.chain_id,
-                self.config.address,
-                function_data.into(),
-                self.config.skip_function_id,
-                Bytes::copy_from_slice(&input),
-            )
-            .await?;
-        Ok(request_id)
+#[cfg(not(feature = "api"))]
+fn spawn_rest(_operator: &SharedOperator, config: Option<ApiConfig>) -> Result<()> {
+    match config {
+        Some(_) => Err(anyhow!("API_BIND_ADDR requires the api feature")),
+        None => Ok(()),
     }
+}
+
+/// Serve the gRPC service with `operator` as `config` says, if set.
+#[cfg(feature = "grpc")]
+fn spawn_grpc(operator: &SharedOperator, config: Option<ApiConfig>) -> Result<()> {
+    let Some(config) = config else {
+        return Ok(());
+    };
+    let operator = operator.clone();
+    tokio::spawn(async move {
+        let served = tendermintx::grpc::serve(operator, &config.token, config.addr);
+        if let Err(e) = served.await {
+            error!("The gRPC service stopped: {:#}", e);
+        }
+    });
+    Ok(())
+}
 
-    async fn request_skip_2(
-        &self,
-        trusted_hash: [u8; 32],
-        trusted_block: u64,
-        target_block: u64,
-    ) -> Result<String> {
-        let trusted_header_hash = trusted_hash;
+#[cfg(not(feature = "grpc"))]
+fn spawn_grpc(_operator: &SharedOperator, config: Option<ApiConfig>) -> Result<()> {
+    match config {
+        Some(_) => Err(anyhow!("GRPC_BIND_ADDR requires the grpc feature")),
+        None => Ok(()),
+    }
+}
 
-        let input =
-            SkipInputTuple::abi_encode_packed(&(trusted_block, trusted_header_hash, target_block));
+/// Run the loops of the chains of `registry` until it fails or the process is signaled.
+async fn run_registry(registry: ChainRegistry) {
+    let failure = tokio::select! {
+        result = registry.run() => result.err(),
+        _ = shutdown_signal() => None,
+    };
+    match failure.as_ref() {
+        Some(e) => error!("The chain registry stopped: {:#}", e),
+        None => info!("Shutting down"),
+    }
+    logging::shutdown();
+    if failure.is_some() {
+        std::process::exit(1);
+    }
+}
+
+/// Verify the proof document at `path` against the key at `vk`.
+fn verify_proof(path: &Path, vk: &Path, expected: &ExpectedRange) -> Result<ProvedRange> {
+    let json = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+    let document: ProofDocument = serde_json::from_str(&json)
+        .with_context(|| format!("invalid proof document {}", path.display()))?;
+    let key = VerifyingKeyFile::open(vk)?;
+    groth16::verify_document(&document, &key.to_key()?, expected)
+}
 
-        let skip_call = SkipCall {
+/// The operator of `prove` and `export-input`, reading the chain from the light blocks at
+/// `light_blocks` if set.
+async fn input_operator(light_blocks: Option<&Path>) -> Result<TendermintXOperator> {
+    let Some(path) = light_blocks else {
+        return resolved(TendermintXOperator::from_env()).await;
+    };
+    let source = path.display().to_string();
+    resolved(TendermintXOperator::from_env_light_blocks(
+        LightBlocks::load(path)?,
+        &source,
+    ))
+    .await
+}
+
+/// `operator`, with the function IDs of its targets resolved.
+async fn resolved(operator: Result<TendermintXOperator>) -> Result<TendermintXOperator> {
+    let mut operator = operator?;
+    operator.resolve_function_ids().await?;
+    Ok(operator)
+}
+
+/// The value of `result`, or log its error and exit.
+fn or_exit<T>(result: Result<T>) -> T {
+    result.unwrap_or_else(|e| {
+        error!("{:#}", e);
+        std::process::exit(1);
+    })
+}
+
+#[tokio::main]
+async fn main() {
+    /*
+        cargo run --package tendermintx --bin tendermintx --release prove 123456 654321 <trusted_hash>
+    */
+    let mut args: Vec<String> = std::env::args().collect();
+    // Support the original `tendermintx <trusted_block> <target_block> <trusted_hash>` invocation.
+    if args.get(1).map_or(false, |arg| arg.parse::<u64>().is_ok()) {
+        args.insert(1, "prove".to_string());
+    }
+    let cli = Cli::parse_from(args);
+
+    dotenv::dotenv().ok();
+    let log_format = env_opt("LOG_FORMAT")
+        .map(|format| format.parse().expect("invalid LOG_FORMAT"))
+        .unwrap_or_default();
+    // Log lines would draw over the live dashboard.
+    let live_dashboard =
+        matches!(cli.command, Command::Dashboard { .. }) && std::io::stdout().is_terminal();
+    if !live_dashboard {
+        logging::init(log_format).expect("could not initialize logging");
+    }
+    let _reporting = reporting::init();
+
+    match cli.command {
+        Command::Prove {
             trusted_block,
             target_block,
-        };
-        let function_data = skip_call.encode();
-
-        let request_id = self
-            .client
-            .submit_platform_request(
-                self.config.chain_id,
-                self.config.address,
-                function_data.into(),
-                self.config.skip_function_id,
-                Bytes::copy_from_slice(&input),
-            )
-            .await?;
-        Ok(request_id)
-    }
+            trusted_hash,
+            wait,
+            timeout,
+            emit_proof,
+            light_blocks,
+        } => {
+            info!(%trusted_block, %target_block, %trusted_hash, "Proving");
 
-    async fn is_consistent(&mut self, current_block: u64) {
-        let expected_current_signed_header = self
-            .data_fetcher
-            .get_signed_header_from_number(current_block)
-            .await;
-        let expected_header = expected_current_signed_header.header.hash();
-        let expected_header_bytes = expected_header.as_bytes();
-        let contract_current_header = self
-            .contract
-            .block_height_to_header_hash(current_block)
-            .await
-            .unwrap();
-        if expected_header_bytes != contract_current_header {
-            panic!(
-                "Current header in the contract does not match chain's header hash for block {:?}\n 
-                From Tendermint RPC: {:?}\n 
-                From contract: {:?}",
-                current_block,
-                String::from_utf8(hex::encode(expected_header)),
-                String::from_utf8(hex::encode(contract_current_header))
+            let operator = or_exit(input_operator(light_blocks.as_deref()).await);
+            let requests = or_exit(
+                operator
+                    .prove(trusted_block..=target_block, trusted_hash)
+                    .await,
             );
+            if wait || emit_proof.is_some() {
+                let outcomes = operator
+                    .wait_for_requests(&requests, Duration::from_secs(timeout))
+                    .await;
+                if let Some(path) = emit_proof {
+                    or_exit(operator.emit_proof(&requests, &outcomes, &path).await);
+                }
+            }
         }
-    }
+        Command::ProveCommitment {
+            trusted_block,
+            target_block,
+            trusted_hash,
+            wait,
+            timeout,
+            emit_proof,
+        } => {
+            info!(%trusted_block, %target_block, %trusted_hash, "Proving a data commitment");
 
-    async fn run(&mut self) {
-        // Loop every 240 minutes.
-        const LOOP_DELAY: u64 = 240;
-
-        // The upper limit of the largest skip that can be requested. This is bounded by the unbonding
-        // period, which for most Tendermint chains is ~2 weeks, or ~100K blocks with a block time
-        // of 12s.
-        let skip_max = self.contract.skip_max().await.unwrap();
-        loop {
-            let current_block = self.contract.latest_block().await.unwrap();
-
-            // Consistency check for the headers (this should only happen if an invalid header,
-            // typically the genesis header, is pushed to the contract). If this is triggered,
-            // double check the genesis header in the contract.
-            self.is_consistent(current_block).await;
-
-            // Get the head of the chain.
-            let latest_signed_header = self.data_fetcher.get_latest_signed_header().await;
-            let latest_block = latest_signed_header.header.height.value();
-
-            // Get the maximum block height we can request.
-            let max_end_block = std::cmp::min(latest_block, current_block + skip_max);
-
-            let target_block = self
-                .data_fetcher
-                .find_block_to_request(current_block, max_end_block)
-                .await;
-            println!("Current block: {}", current_block);
-            println!("Target block: {}", target_block);
-
-            if target_block - current_block == 1 {
-                // Request the step if the target block is the next block.
-                match self.request_step(current_block).await {
-                    Ok(request_id) => {
-                        info!("Step request submitted: {}", request_id)
-                    }
-                    Err(e) => {
-                        error!("Step request failed: {}", e);
-                        continue;
-                    }
-                };
-            } else {
-                // Request a skip if the target block is not the next block.
-                match self.request_skip(current_block, target_block).await {
-                    Ok(request_id) => {
-                        info!("Skip request submitted: {}", request_id)
-                    }
-                    Err(e) => {
-                        error!("Skip request failed: {}", e);
-                        continue;
-                    }
-                };
+            let operator = or_exit(resolved(TendermintXOperator::from_env()).await);
+            let requests = or_exit(
+                operator
+                    .prove_data_commitment(trusted_block..=target_block, trusted_hash)
+                    .await,
+            );
+            if wait || emit_proof.is_some() {
+                let outcomes = operator
+                    .wait_for_requests(&requests, Duration::from_secs(timeout))
+                    .await;
+                if let Some(path) = emit_proof {
+                    or_exit(operator.emit_proof(&requests, &outcomes, &path).await);
+                }
             }
-
-            tokio::time::sleep(tokio::time::Duration::from_secs(60 * LOOP_DELAY)).await;
         }
-    }
-
-    async fn create_proof(
-        &mut self,
-        trusted_hash: [u8; 32],
-        current_block_input: u64,
-        target_block_input: u64,
-    ) {
-        if current_block_input >= target_block_input {
-            error!("Invalid block input");
-            error!("Current block: {}", current_block_input);
-            error!("Target block: {}", target_block_input);
-            error!("trusted_hash: {:?}", trusted_hash);
-            return;
-        }
-        // The upper limit of the largest skip that can be requested. This is bounded by the unbonding
-        // period, which for most Tendermint chains is ~2 weeks, or ~100K blocks with a block time
-        // of 12s.
-        // let skip_max = self.contract.skip_max().await.unwrap();
-        // let current_block = self.contract.latest_block().await.unwrap();
-        let current_block = current_block_input;
-
-        // Consistency check for the headers (this should only happen if an invalid header,
-        // typically the genesis header, is pushed to the contract). If this is triggered,
-        // double check the genesis header in the contract.
-        // self.is_consistent(current_block).await;
-
-        // Get the head of the chain.
-        // let latest_signed_header = self.data_fetcher.get_latest_signed_header().await;
-        // let latest_block = latest_signed_header.header.height.value();
-
-        // Get the maximum block height we can request.
-        // let max_end_block = std::cmp::min(latest_block, current_block + skip_max);
-
-        // let target_block = self
-        //     .data_fetcher
-        //     .find_block_to_request(current_block, max_end_block)
-        //     .await;
-
-        let target_block = target_block_input;
-
-        println!("Current block: {}", current_block);
-        println!("Target block: {}", target_block);
-
-        // let target_block = target_block_input;
-        println!("New target block: {}", target_block);
-
-        // info!("request____start:{}request____end", "123123123");
-        // return;
-
-        if target_block - current_block == 1 {
-            // Request the step if the target block is the next block.
-            match self.request_step_2(trusted_hash, current_block).await {
-                Ok(request_id) => {
-                    info!("request____start{}request____end", request_id);
-                    info!("Step request submitted: {}", request_id)
+        Command::Run { once: true, .. } if env_opt("CHAIN_REGISTRY").is_some() => {
+            error!("run --once does not take a CHAIN_REGISTRY");
+            std::process::exit(EXIT_FAILED);
+        }
+        Command::Run { .. } if env_opt("CHAIN_REGISTRY").is_some() => {
+            run_registry(or_exit(ChainRegistry::from_env())).await
+        }
+        Command::Run {
+            catch_up,
+            once: true,
+            summary_file,
+        } => {
+            let started_at_ms = unix_timestamp_ms();
+            let config = TendermintXConfig::from_env().map(|mut config| {
+                if catch_up {
+                    config.catch_up.force();
+                }
+                config
+            });
+            match config.and_then(|config| Ok((signer_source()?, config))) {
+                Ok((Some(source), config)) => {
+                    let operator = TendermintXOperator::from_env_with_signer(config, source).await;
+                    run_oneshot(operator, started_at_ms, summary_file).await
+                }
+                Ok((None, config)) => {
+                    let operator = TendermintXOperator::from_env_config(config);
+                    run_oneshot(operator, started_at_ms, summary_file).await
                 }
                 Err(e) => {
-                    error!("Step request failed: {}", e);
-                    return;
+                    let operator = Err::<TendermintXOperator, _>(e);
+                    run_oneshot(operator, started_at_ms, summary_file).await
                 }
-            };
-        } else {
-            // Request a skip if the target block is not the next block.
-            match self
-                .request_skip_2(trusted_hash, current_block, target_block)
+            }
+        }
+        Command::Run { catch_up, .. } => {
+            let mut config = or_exit(TendermintXConfig::from_env());
+            if catch_up {
+                config.catch_up.force();
+            }
+            match or_exit(signer_source()) {
+                Some(source) => {
+                    let operator = TendermintXOperator::from_env_with_signer(config, source).await;
+                    run(or_exit(operator)).await
+                }
+                None => run(or_exit(TendermintXOperator::from_env_config(config))).await,
+            }
+        }
+        #[cfg(any(feature = "api", feature = "grpc"))]
+        Command::Serve { catch_up } => {
+            let mut config = or_exit(TendermintXConfig::from_env());
+            if catch_up {
+                config.catch_up.force();
+            }
+            or_exit(serve(config).await)
+        }
+        Command::ExportInput {
+            trusted,
+            target,
+            out,
+            json,
+            chain_id,
+            trusted_hash,
+            light_blocks,
+            save_light_blocks,
+        } => {
+            let operator = or_exit(input_operator(light_blocks.as_deref()).await);
+            if let Some(path) = save_light_blocks {
+                or_exit(operator.export_light_blocks(trusted, target, &path).await);
+            }
+            if let Err(e) = operator
+                .export_input(chain_id, trusted, trusted_hash, target, &out, json)
                 .await
             {
-                Ok(request_id) => {
-                    info!("request____start{}request____end", request_id);
-                    info!("Skip request submitted: {}", request_id)
+                error!("{:#}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::InputDigest {
+            trusted,
+            hash,
+            target,
+            data_commitment,
+        } => {
+            if target <= trusted {
+                error!("The target block must be after the trusted block");
+                std::process::exit(1);
+            }
+            let inputs = or_exit(match data_commitment {
+                true => RequestInputs::data_commitment(trusted, hash, target),
+                false => RequestInputs::new(trusted, hash, target),
+            });
+            let digest = InputDigest::of(&inputs.input);
+            let callback = match inputs.kind {
+                RequestKind::Step => "step(uint64)",
+                RequestKind::Skip => "skip(uint64,uint64)",
+                RequestKind::DataCommitment => "commitHeaderRange(uint64,uint64)",
+            };
+            println!("kind:         {}", inputs.kind);
+            println!("input:        {}", inputs.input);
+            println!("input hash:   {}", digest.input_hash);
+            println!("public input: {}", digest.public_input);
+            println!(
+                "selector:     {} {}",
+                Bytes::copy_from_slice(&inputs.calldata[..4]),
+                callback
+            );
+            println!("calldata:     {}", inputs.calldata);
+        }
+        Command::Backfill {
+            start,
+            end,
+            stride,
+            chain_id,
+        } => {
+            let operator = or_exit(resolved(TendermintXOperator::from_env()).await);
+            let backfill = Backfill::new(start, end, stride);
+            match operator.backfill(chain_id, backfill).await {
+                Ok(summary) => info!(
+                    "Backfill done: {} checkpoints proved, {} already stored",
+                    summary.proved, summary.skipped
+                ),
+                Err(e) => {
+                    error!("Backfill stopped, run it again to resume: {:#}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::SubmitInput { input } => {
+            let operator = or_exit(resolved(TendermintXOperator::from_env()).await);
+            if let Err(e) = operator.submit_input(&input).await {
+                error!("{:#}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Status {
+            correlation_id: Some(correlation_id),
+        } => {
+            let operator = or_exit(TendermintXOperator::from_env());
+            for (record, status) in or_exit(operator.attempt_status(correlation_id).await) {
+                match status {
+                    Ok(status) => println!("{}: {:?}", record, status),
+                    Err(e) => println!("{}: unknown ({:#})", record, e),
+                }
+            }
+        }
+        Command::Status {
+            correlation_id: None,
+        } if env_opt("CHAIN_REGISTRY").is_some() => {
+            let mut registry = or_exit(ChainRegistry::from_env());
+            let mut failed = false;
+            for (chain_id, status) in registry.collect_status().await {
+                println!("chain {}:", chain_id);
+                match status {
+                    Ok(status) => print!("{}", status),
+                    Err(e) => {
+                        error!("{}: {:#}", chain_id, e);
+                        failed = true;
+                    }
+                }
+            }
+            if failed {
+                std::process::exit(1);
+            }
+        }
+        Command::Status {
+            correlation_id: None,
+        } => {
+            let mut operator = or_exit(TendermintXOperator::from_env());
+            match operator.collect_status().await {
+                Ok(status) => print!("{}", status),
+                Err(e) => {
+                    error!("{:#}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::CheckConfig => {
+            let mut operator = or_exit(resolved(TendermintXOperator::from_env()).await);
+            match operator.verify_artifacts().await {
+                Ok(verification) if verification.mismatches.is_empty() => println!(
+                    "The configuration is valid, {} circuits verified",
+                    verification.verified.len()
+                ),
+                Ok(verification) => {
+                    for mismatch in verification.mismatches.iter() {
+                        println!("Mismatch (allowed): {}", mismatch);
+                    }
                 }
                 Err(e) => {
-                    error!("Skip request failed: {}", e);
-                    return;
+                    error!("{:#}", e);
+                    std::process::exit(1);
                 }
+            }
+        }
+        Command::Hash { height } => {
+            or_exit(
+                env_opt("TENDERMINT_RPC_URL")
+                    .ok_or_else(|| anyhow!("TENDERMINT_RPC_URL must be set")),
+            );
+            let data_fetcher = InputDataFetcher::default();
+            println!(
+                "{}",
+                or_exit(data_fetcher.compute_header_hash(height).await)
+            );
+        }
+        Command::FieldProof {
+            height,
+            field,
+            json,
+        } => {
+            or_exit(
+                env_opt("TENDERMINT_RPC_URL")
+                    .ok_or_else(|| anyhow!("TENDERMINT_RPC_URL must be set")),
+            );
+            let data_fetcher = InputDataFetcher::default();
+            let proof = or_exit(FieldProof::fetch(&data_fetcher, height, field).await);
+            match json {
+                true => println!("{}", or_exit(serde_json::to_string_pretty(&proof))),
+                false => println!("{}", proof),
+            }
+        }
+        Command::VerifyFieldProof {
+            proof,
+            header_hash,
+            contract,
+            chain_id,
+        } => {
+            let proof = or_exit(verify_field_proof(&proof, header_hash, contract, chain_id).await);
+            println!("{}", proof);
+            println!("The field proof verifies");
+        }
+        Command::IbcHeader {
+            trusted,
+            target,
+            out,
+        } => {
+            or_exit(
+                env_opt("TENDERMINT_RPC_URL")
+                    .ok_or_else(|| anyhow!("TENDERMINT_RPC_URL must be set")),
+            );
+            let data_fetcher = InputDataFetcher::default();
+            or_exit(ibc::write_ibc_header(&data_fetcher, trusted, target, &out).await);
+            println!(
+                "Wrote the header from block {} to block {} to {}",
+                trusted,
+                target,
+                out.display()
+            );
+        }
+        Command::VerifyProof {
+            proof,
+            vk,
+            trusted_block,
+            trusted_hash,
+            target_block,
+            target_hash,
+        } => {
+            let expected = ExpectedRange {
+                trusted_block,
+                trusted_header_hash: trusted_hash,
+                target_block,
+                target_header_hash: target_hash,
             };
+            let range = or_exit(verify_proof(&proof, &vk, &expected));
+            println!("{}", range);
+            println!("The proof verifies");
+        }
+        Command::Migrate { old, new } => or_exit(migrate_command(old, new).await),
+        Command::Repair {
+            chain_id,
+            events_from,
+            batch,
+            max_blocks,
+            execute,
+        } => {
+            let bounds = ScanBounds { batch, max_blocks };
+            or_exit(repair_command(chain_id, events_from, bounds, execute).await)
+        }
+        Command::Snapshot { rpc, from, to, out } => {
+            let manifest = or_exit(snapshot::record_snapshot(vec![rpc], from, to, &out).await);
+            println!(
+                "Recorded {} blocks of {} into {}",
+                manifest.blocks.len(),
+                manifest.chain_id,
+                out.display()
+            );
+        }
+        Command::Dashboard { refresh } => {
+            let result = match TendermintXOperator::from_env() {
+                Ok(mut operator) if live_dashboard => {
+                    dashboard::run(&mut operator, Duration::from_secs(refresh)).await
+                }
+                Ok(mut operator) => operator
+                    .collect_status()
+                    .await
+                    .map(|status| print!("{}", status)),
+                Err(e) => Err(e),
+            };
+            if let Err(e) = result {
+                // Nothing is logged while the dashboard runs.
+                eprintln!("{:#}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Requests {
+            command: RequestsCommand::Replay { request_id, force },
+        } => {
+            let operator = or_exit(resolved(TendermintXOperator::from_env()).await);
+            if let Err(e) = operator.replay(&request_id, force).await {
+                error!("{:#}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Requests { command } => {
+            if let Err(e) = print_requests(command) {
+                error!("{:#}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Audit { command } => {
+            if let Err(e) = audit_command(command) {
+                error!("{:#}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Ctl { socket, command } => {
+            if let Err(e) = ctl_command(socket, command).await {
+                error!("{:#}", e);
+                std::process::exit(1);
+            }
         }
     }
 }
-
-// #[tokio::main]
-// async fn main() {
-//     env::set_var("RUST_LOG", "info");
-//     dotenv::dotenv().ok();
-//     env_logger::init();
-
-//     let mut operator = TendermintXOperator::new();
-//     operator.run().await;
-// }
-
-#[tokio::main]
-async fn main() {
-    /*
-        cargo run --package tendermintx --bin tendermintx --release 123456 654321
-    */
-    let arg = std::env::args().nth(3).expect("Expected a third argument");
-    let args: Vec<String> = std::env::args().collect();
-    let trusted = args[1].parse::<u64>().unwrap();
-    let height = args[2].parse::<u64>().unwrap();
-    let bytes = hex::decode(arg).expect("Invalid hex string");
-    let mut array: [u8; 32] = [0; 32];
-    array.copy_from_slice(&bytes);
-
-    println!("Trusted block: {:?}", bytes);
-
-    println!("Proof height: {}", height);
-    env::set_var("RUST_LOG", "info");
-    dotenv::dotenv().ok();
-    env_logger::init();
-
-    let mut operator = TendermintXOperator::new();
-    operator.create_proof(array, trusted, height).await;
-}
@@ -0,0 +1,24 @@
+//! Fails the build if the committed ABI no longer declares the functions and events the contract
+//! bindings expect (see `circuits/abi.rs`). With the `grpc` feature, also generates the gRPC
+//! service of `proto/tendermintx.proto`.
+
+#[path = "circuits/abi.rs"]
+mod abi;
+
+fn main() {
+    println!("cargo:rerun-if-changed={}", abi::ABI_PATH);
+    println!("cargo:rerun-if-changed=circuits/abi.rs");
+    let json = std::fs::read_to_string(abi::ABI_PATH)
+        .unwrap_or_else(|e| panic!("failed to read {}: {}", abi::ABI_PATH, e));
+    let differences = abi::diff(&json);
+    if !differences.is_empty() {
+        panic!("{}", abi::report(&differences));
+    }
+
+    #[cfg(feature = "grpc")]
+    {
+        println!("cargo:rerun-if-changed=proto/tendermintx.proto");
+        tonic_build::compile_protos("proto/tendermintx.proto")
+            .unwrap_or_else(|e| panic!("failed to compile proto/tendermintx.proto: {}", e));
+    }
+}
@@ -0,0 +1,174 @@
+//! The functions and events of the `TendermintX` ABI the operator depends on.
+//!
+//! The `sol!` bindings of `contract::bindings` are written by hand from the committed
+//! `abi/TendermintX.abi.json`. `EXPECTED` lists what they declare, one line each as `describe`
+//! renders the ABI: the build script checks the ABI against it, so that a contract change the
+//! bindings weren't updated for fails the build rather than a call at runtime, and the tests check
+//! it against the bindings. As the build script compiles this file too, it only uses `serde_json`.
+
+use serde_json::Value;
+
+/// The ABI, from the root of the crate.
+pub const ABI_PATH: &str = "abi/TendermintX.abi.json";
+
+/// The functions and events of the bindings.
+pub const EXPECTED: [&str; 9] = [
+    "event HeadUpdate(uint64,bytes32)",
+    "function SKIP_MAX() view returns (uint64)",
+    "function blockHeightToHeaderHash(uint64) view returns (bytes32)",
+    "function latestBlock() view returns (uint64)",
+    "function setGenesisHeader(uint64,bytes32) nonpayable",
+    "function skip(uint64,uint64) nonpayable",
+    "function skipFunctionId() view returns (bytes32)",
+    "function step(uint64) nonpayable",
+    "function stepFunctionId() view returns (bytes32)",
+];
+
+/// Each function and event of `abi`, one line each, e.g. `function step(uint64) nonpayable` or
+/// `event StepRequested(uint64 indexed,bytes32 indexed)`.
+pub fn describe(abi: &Value) -> Result<Vec<String>, String> {
+    let entries = abi.as_array().ok_or("the ABI is not an array")?;
+    let mut lines = Vec::new();
+    for entry in entries {
+        let field = |key: &str| entry[key].as_str().unwrap_or_default();
+        let types = |key: &str| {
+            let params = entry[key].as_array().into_iter().flatten();
+            let types: Vec<String> = params
+                .map(|param| {
+                    let ty = param["type"].as_str().unwrap_or_default();
+                    match param["indexed"] == true {
+                        true => format!("{} indexed", ty),
+                        false => ty.to_string(),
+                    }
+                })
+                .collect();
+            types.join(",")
+        };
+        match field("type") {
+            "function" => {
+                let mut line = format!(
+                    "function {}({}) {}",
+                    field("name"),
+                    types("inputs"),
+                    field("stateMutability")
+                );
+                let outputs = types("outputs");
+                if !outputs.is_empty() {
+                    line.push_str(&format!(" returns ({})", outputs));
+                }
+                lines.push(line);
+            }
+            "event" => lines.push(format!("event {}({})", field("name"), types("inputs"))),
+            _ => {}
+        }
+    }
+    Ok(lines)
+}
+
+/// The differences of the ABI `json` from `EXPECTED`, diff style: `-` for a line the bindings
+/// expect, followed by `+` for what the ABI has under the same name instead. Empty if it matches.
+pub fn diff(json: &str) -> Vec<String> {
+    let abi = serde_json::from_str(json).map_err(|e| e.to_string());
+    let lines = match abi.and_then(|abi| describe(&abi)) {
+        Ok(lines) => lines,
+        Err(e) => return vec![format!("invalid ABI: {}", e)],
+    };
+    let mut differences = Vec::new();
+    for expected in EXPECTED {
+        if lines.iter().any(|line| line == expected) {
+            continue;
+        }
+        // The kind and name, e.g. `function skip(`.
+        let name = &expected[..=expected.find('(').unwrap()];
+        let actual: Vec<_> = lines.iter().filter(|line| line.starts_with(name)).collect();
+        differences.push(format!("- {}", expected));
+        if actual.is_empty() {
+            differences.push("+ (not in the ABI)".to_string());
+        }
+        differences.extend(actual.into_iter().map(|line| format!("+ {}", line)));
+    }
+    differences
+}
+
+/// The message the build and the tests fail with for `differences`.
+pub fn report(differences: &[String]) -> String {
+    format!(
+        "{} no longer matches the contract bindings:\n{}\nUpdate the bindings in \
+         circuits/contract.rs and `abi::EXPECTED` with the contract.",
+        ABI_PATH,
+        differences.join("\n")
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use alloy_sol_types::{SolCall, SolEvent};
+
+    use super::*;
+    use crate::contract::bindings::{
+        blockHeightToHeaderHashCall, latestBlockCall, setGenesisHeaderCall, skipCall,
+        skipFunctionIdCall, stepCall, stepFunctionIdCall, HeadUpdate, SKIP_MAXCall,
+    };
+
+    fn committed_abi() -> String {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join(ABI_PATH);
+        std::fs::read_to_string(path).unwrap()
+    }
+
+    #[test]
+    fn test_committed_abi() {
+        let differences = diff(&committed_abi());
+        assert!(differences.is_empty(), "{}", report(&differences));
+    }
+
+    #[test]
+    fn test_expected_matches_bindings() {
+        let signatures = [
+            HeadUpdate::SIGNATURE,
+            SKIP_MAXCall::SIGNATURE,
+            blockHeightToHeaderHashCall::SIGNATURE,
+            latestBlockCall::SIGNATURE,
+            setGenesisHeaderCall::SIGNATURE,
+            skipCall::SIGNATURE,
+            skipFunctionIdCall::SIGNATURE,
+            stepCall::SIGNATURE,
+            stepFunctionIdCall::SIGNATURE,
+        ];
+        for (expected, signature) in EXPECTED.into_iter().zip(signatures) {
+            let (_, declared) = expected.split_once(' ').unwrap();
+            assert!(declared.starts_with(signature), "{}", expected);
+        }
+    }
+
+    #[test]
+    fn test_diff() {
+        let mut abi: Value = serde_json::from_str(&committed_abi()).unwrap();
+        let entries = abi.as_array_mut().unwrap();
+        entries.retain(|entry| entry["name"] != "latestBlock");
+        for entry in entries.iter_mut() {
+            match entry["name"].as_str().unwrap_or_default() {
+                "HeadUpdate" => entry["inputs"][0]["indexed"] = true.into(),
+                "skip" => {
+                    let proof = serde_json::json!({"name": "proof", "type": "bytes"});
+                    entry["inputs"].as_array_mut().unwrap().push(proof);
+                }
+                _ => {}
+            }
+        }
+        assert_eq!(
+            diff(&abi.to_string()),
+            [
+                "- event HeadUpdate(uint64,bytes32)",
+                "+ event HeadUpdate(uint64 indexed,bytes32)",
+                "- function latestBlock() view returns (uint64)",
+                "+ (not in the ABI)",
+                "- function skip(uint64,uint64) nonpayable",
+                "+ function skip(uint64,uint64,bytes) nonpayable",
+            ]
+        );
+        assert!(diff("{}")[0].starts_with("invalid ABI: the ABI is not an array"));
+        assert!(report(&diff("[]")).starts_with("abi/TendermintX.abi.json no longer matches"));
+    }
+}
@@ -0,0 +1,365 @@
+//! Alerts posted to webhooks when the operator needs a human.
+//!
+//! Each alert is posted to every configured webhook, as generic JSON or formatted for Slack's
+//! incoming webhooks. Identical alerts (the same kind and summary) are sent at most once per
+//! deduplication window, so a condition that persists across iterations doesn't flood the
+//! channel. Delivery failures are logged and otherwise ignored: alerting never stops the loop.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use log::{debug, info, warn};
+use serde_json::{json, Value};
+
+use crate::pagerduty::PagerDuty;
+use crate::reporting;
+use crate::store::unix_timestamp;
+
+/// The maximum time the delivery of an alert to a single webhook may take.
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AlertKind {
+    /// The contract's header for its latest block doesn't match the chain's.
+    ConsistencyMismatch,
+    /// Submissions kept failing for the configured number of consecutive attempts.
+    CircuitBreakerOpen,
+    /// A request failed on every attempt allowed by the retry policy.
+    RetriesExhausted,
+    /// The relayer balance is below its threshold.
+    BalanceLow,
+    /// A target stayed too far behind the chain head.
+    LagExceeded,
+    /// The chain head stopped advancing.
+    ChainHalted,
+    /// The chain advances, but a target's light client stopped following it.
+    LightClientStalled,
+    /// Skips from a trusted block kept failing, so steps are requested instead.
+    StepFallback,
+    /// A target's trusted header is older than the chain's trusting period, so nothing can be
+    /// proved from it until its contract is reset.
+    TrustedStateExpired,
+    Startup,
+    Shutdown,
+}
+
+impl AlertKind {
+    pub const ALL: [AlertKind; 11] = [
+        AlertKind::ConsistencyMismatch,
+        AlertKind::CircuitBreakerOpen,
+        AlertKind::RetriesExhausted,
+        AlertKind::BalanceLow,
+        AlertKind::LagExceeded,
+        AlertKind::ChainHalted,
+        AlertKind::LightClientStalled,
+        AlertKind::StepFallback,
+        AlertKind::TrustedStateExpired,
+        AlertKind::Startup,
+        AlertKind::Shutdown,
+    ];
+
+    pub fn severity(&self) -> Severity {
+        match self {
+            AlertKind::ConsistencyMismatch
+            | AlertKind::CircuitBreakerOpen
+            | AlertKind::LightClientStalled
+            | AlertKind::TrustedStateExpired => Severity::Critical,
+            AlertKind::RetriesExhausted
+            | AlertKind::BalanceLow
+            | AlertKind::LagExceeded
+            | AlertKind::ChainHalted
+            | AlertKind::StepFallback => Severity::Warning,
+            AlertKind::Startup | AlertKind::Shutdown => Severity::Info,
+        }
+    }
+}
+
+impl fmt::Display for AlertKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            AlertKind::ConsistencyMismatch => "consistency_mismatch",
+            AlertKind::CircuitBreakerOpen => "circuit_breaker_open",
+            AlertKind::RetriesExhausted => "retries_exhausted",
+            AlertKind::BalanceLow => "balance_low",
+            AlertKind::LagExceeded => "lag_exceeded",
+            AlertKind::ChainHalted => "chain_halted",
+            AlertKind::LightClientStalled => "light_client_stalled",
+            AlertKind::StepFallback => "step_fallback",
+            AlertKind::TrustedStateExpired => "trusted_state_expired",
+            AlertKind::Startup => "startup",
+            AlertKind::Shutdown => "shutdown",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for AlertKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        AlertKind::ALL
+            .into_iter()
+            .find(|kind| kind.to_string() == s)
+            .ok_or_else(|| anyhow!("unknown alert kind {:?}", s))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Info,
+    Warning,
+    Critical,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Info => f.write_str("info"),
+            Severity::Warning => f.write_str("warning"),
+            Severity::Critical => f.write_str("critical"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Alert {
+    pub kind: AlertKind,
+    pub summary: String,
+    /// Context for the alert, e.g. the target and blocks involved.
+    pub details: BTreeMap<String, String>,
+}
+
+impl Alert {
+    pub fn new(kind: AlertKind, summary: impl Into<String>) -> Self {
+        Self {
+            kind,
+            summary: summary.into(),
+            details: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_detail(mut self, key: &str, value: impl ToString) -> Self {
+        self.details.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Alerts with the same key are deduplicated.
+    fn dedup_key(&self) -> String {
+        format!("{}:{}", self.kind, self.summary)
+    }
+}
+
+/// How an alert is rendered for a webhook.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WebhookFormat {
+    /// The alert's fields as a JSON object.
+    Json,
+    /// A Slack incoming webhook message.
+    Slack,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AlertWebhook {
+    pub url: String,
+    pub format: WebhookFormat,
+}
+
+/// The body posted to a webhook in `format` for `alert`, sent at unix time `timestamp`.
+pub fn render(alert: &Alert, format: WebhookFormat, timestamp: u64) -> Value {
+    match format {
+        WebhookFormat::Json => json!({
+            "kind": alert.kind.to_string(),
+            "severity": alert.kind.severity().to_string(),
+            "summary": alert.summary,
+            "details": alert.details,
+            "timestamp": timestamp,
+        }),
+        WebhookFormat::Slack => {
+            let mut text = format!(
+                "*[{}] {}*: {}",
+                alert.kind.severity().to_string().to_uppercase(),
+                alert.kind,
+                alert.summary
+            );
+            for (key, value) in alert.details.iter() {
+                text.push_str(&format!("\n• {}: `{}`", key, value));
+            }
+            json!({ "text": text })
+        }
+    }
+}
+
+pub struct Alerter {
+    webhooks: Vec<AlertWebhook>,
+    /// Pages on-call for the alert classes it is configured for, if set.
+    pagerduty: Option<PagerDuty>,
+    dedup_window: Duration,
+    client: reqwest::Client,
+    /// When each alert was last sent, by its deduplication key.
+    last_sent: Mutex<HashMap<String, Instant>>,
+}
+
+impl Alerter {
+    pub fn new(webhooks: Vec<AlertWebhook>, dedup_window: Duration) -> Self {
+        Self {
+            webhooks,
+            pagerduty: None,
+            dedup_window,
+            client: reqwest::Client::new(),
+            last_sent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn with_pagerduty(mut self, pagerduty: PagerDuty) -> Self {
+        self.pagerduty = Some(pagerduty);
+        self
+    }
+
+    /// Whether `alert` should be sent at `now`, i.e. no identical alert was sent within the
+    /// deduplication window. Records the alert as sent if so.
+    pub fn should_send(&self, alert: &Alert, now: Instant) -> bool {
+        let mut last_sent = self.last_sent.lock().unwrap();
+        let key = alert.dedup_key();
+        if let Some(sent) = last_sent.get(&key) {
+            if now.saturating_duration_since(*sent) < self.dedup_window {
+                return false;
+            }
+        }
+        last_sent.insert(key, now);
+        true
+    }
+
+    /// Log `alert`, post it to every webhook and report it, unless an identical alert was sent
+    /// recently.
+    pub async fn send(&self, alert: &Alert) {
+        if !self.should_send(alert, Instant::now()) {
+            debug!(
+                "Suppressed duplicate alert {}: {}",
+                alert.kind, alert.summary
+            );
+            return;
+        }
+        info!(
+            "Alert {} ({}): {}",
+            alert.kind,
+            alert.kind.severity(),
+            alert.summary
+        );
+        reporting::capture_alert(alert);
+        let timestamp = unix_timestamp();
+        for webhook in self.webhooks.iter() {
+            let body = render(alert, webhook.format, timestamp);
+            if let Err(e) = self.post(&webhook.url, &body).await {
+                warn!("Failed to deliver alert {}: {:#}", alert.kind, e);
+            }
+        }
+        if let Some(pagerduty) = self.pagerduty.as_ref() {
+            if let Err(e) = pagerduty.trigger(alert).await {
+                warn!(
+                    "Failed to trigger PagerDuty incident {}: {:#}",
+                    alert.kind, e
+                );
+            }
+        }
+    }
+
+    /// Record that the condition of `kind` alerts cleared: resolve its PagerDuty incident, and
+    /// send the next `kind` alert without waiting for the deduplication window.
+    pub async fn resolve(&self, kind: AlertKind) {
+        let prefix = format!("{}:", kind);
+        self.last_sent
+            .lock()
+            .unwrap()
+            .retain(|key, _| !key.starts_with(&prefix));
+        if let Some(pagerduty) = self.pagerduty.as_ref() {
+            if let Err(e) = pagerduty.resolve(kind).await {
+                warn!("Failed to resolve PagerDuty incident {}: {:#}", kind, e);
+            }
+        }
+    }
+
+    /// Whether an alert of `kind` was sent within the deduplication window, and not resolved.
+    #[cfg(test)]
+    pub(crate) fn is_sent(&self, kind: AlertKind) -> bool {
+        let prefix = format!("{}:", kind);
+        let last_sent = self.last_sent.lock().unwrap();
+        last_sent.keys().any(|key| key.starts_with(&prefix))
+    }
+
+    async fn post(&self, url: &str, body: &Value) -> Result<()> {
+        self.client
+            .post(url)
+            .timeout(DELIVERY_TIMEOUT)
+            .header("content-type", "application/json")
+            .body(body.to_string())
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mismatch() -> Alert {
+        Alert::new(
+            AlertKind::ConsistencyMismatch,
+            "header mismatch on 5:0x1111111111111111111111111111111111111111",
+        )
+        .with_detail("block", 1000)
+        .with_detail("contract_header", "0xab")
+    }
+
+    #[test]
+    fn test_render() {
+        let alert = mismatch();
+        assert_eq!(
+            render(&alert, WebhookFormat::Json, 1700000000),
+            json!({
+                "kind": "consistency_mismatch",
+                "severity": "critical",
+                "summary": "header mismatch on 5:0x1111111111111111111111111111111111111111",
+                "details": {"block": "1000", "contract_header": "0xab"},
+                "timestamp": 1700000000,
+            })
+        );
+        assert_eq!(
+            render(&alert, WebhookFormat::Slack, 1700000000),
+            json!({
+                "text": "*[CRITICAL] consistency_mismatch*: header mismatch on \
+                         5:0x1111111111111111111111111111111111111111\n• block: `1000`\n\
+                         • contract_header: `0xab`",
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_dedup_window() {
+        let alerter = Alerter::new(Vec::new(), Duration::from_secs(600));
+        let start = Instant::now();
+        assert!(alerter.should_send(&mismatch(), start));
+        assert!(!alerter.should_send(&mismatch(), start + Duration::from_secs(599)));
+        // A different alert is not suppressed.
+        let other = Alert::new(AlertKind::BalanceLow, "relayer balance low");
+        assert!(alerter.should_send(&other, start + Duration::from_secs(10)));
+        // Neither is the same alert once the window passed.
+        assert!(alerter.should_send(&mismatch(), start + Duration::from_secs(600)));
+        assert!(!alerter.should_send(&mismatch(), start + Duration::from_secs(900)));
+
+        // Failing deliveries are logged and ignored.
+        let alerter = Alerter::new(
+            vec![AlertWebhook {
+                url: "http://127.0.0.1:1/alerts".to_string(),
+                format: WebhookFormat::Json,
+            }],
+            Duration::from_secs(600),
+        );
+        alerter.send(&mismatch()).await;
+    }
+}
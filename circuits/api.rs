@@ -0,0 +1,508 @@
+//! The REST API of `tendermintx serve`, to trigger and inspect proofs remotely.
+//!
+//! Every route takes the token of the config as a bearer token in the `Authorization` header:
+//!
+//! - `POST /prove` with `{"trusted_block": 10000, "target_block": 10500}` requests a proof as
+//!   `tendermintx prove` does, through the same checks. The trusted block defaults to the latest
+//!   block of the first target, and its header hash is the one its contract stores. Answers 202
+//!   with the correlation ID of the attempt and its requests, 409 if a request for the range is
+//!   already pending for every target, 400 if the target block is past the chain head, 422 if the
+//!   range is refused, and 502 if the chain, a contract or the backend failed.
+//! - `GET /requests/{id}` answers the stored requests of a correlation ID, or the request of a
+//!   request ID.
+//! - `GET /status` answers what `tendermintx status` prints, as JSON.
+//!
+//! The API submits with an operator of its own, which shares the backend, rate limit and request
+//! store of the run loop's (`TendermintXOperator::with_api_from_env`).
+
+use std::sync::Arc;
+
+use alloy_primitives::B256;
+use anyhow::{Context, Result};
+use axum::extract::{Path, State};
+use axum::http::{header, Request, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use axum::routing::{get, post};
+use axum::{Json, Router};
+use ethers::providers::Middleware;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tracing::{error, info};
+
+use crate::correlation::CorrelationId;
+use crate::dashboard::StatusSnapshot;
+use crate::operator::{BeyondChainHead, RefusedRequest, SharedOperator, TendermintXOperator};
+use crate::store::{RequestRecord, RequestStatus};
+use crate::target::FunctionIdSource;
+use crate::types::Height;
+
+/// The body of `POST /prove`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProveBody {
+    #[serde(rename = "trusted_block", default)]
+    pub trusted_block: Option<Height>,
+    #[serde(rename = "target_block")]
+    pub target_block: Height,
+}
+
+/// A request submitted for a target.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubmittedBody {
+    #[serde(rename = "target")]
+    pub target: String,
+    #[serde(rename = "request_id")]
+    pub request_id: String,
+}
+
+/// The response of `POST /prove`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProveResponse {
+    #[serde(rename = "correlation_id")]
+    pub correlation_id: CorrelationId,
+    #[serde(rename = "trusted_block")]
+    pub trusted_block: Height,
+    #[serde(rename = "target_block")]
+    pub target_block: Height,
+    #[serde(rename = "requests")]
+    pub requests: Vec<SubmittedBody>,
+}
+
+/// The response of `GET /requests/{id}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RequestsResponse {
+    #[serde(rename = "requests")]
+    pub requests: Vec<RequestRecord>,
+}
+
+/// A target in `StatusResponse`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TargetBody {
+    #[serde(rename = "target")]
+    pub target: String,
+    #[serde(rename = "latest_block")]
+    pub latest_block: u64,
+    #[serde(rename = "updated_at")]
+    pub updated_at: i64,
+    #[serde(rename = "lag_blocks")]
+    pub lag_blocks: u64,
+    #[serde(rename = "lag_secs")]
+    pub lag_secs: u64,
+    /// The balance of the relayer of the target in wei, if it has one and the check succeeded.
+    #[serde(rename = "relayer_balance")]
+    pub relayer_balance: Option<String>,
+    /// The seconds left in the trusting period of the latest block, if the chain spec is known.
+    /// Zero or negative once the trusted state expired.
+    #[serde(rename = "trusting_margin_secs")]
+    pub trusting_margin_secs: Option<i64>,
+    #[serde(rename = "step_function_id")]
+    pub step_function_id: B256,
+    #[serde(rename = "skip_function_id")]
+    pub skip_function_id: B256,
+    /// Where the function IDs come from: the contract or the configuration.
+    #[serde(rename = "function_ids_from")]
+    pub function_ids_from: Option<FunctionIdSource>,
+}
+
+/// A pending request in `StatusResponse`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PendingBody {
+    #[serde(rename = "request_id")]
+    pub request_id: String,
+    #[serde(rename = "target")]
+    pub target: String,
+    #[serde(rename = "target_block")]
+    pub target_block: u64,
+    #[serde(rename = "status")]
+    pub status: RequestStatus,
+    #[serde(rename = "age_secs")]
+    pub age_secs: u64,
+}
+
+/// The response of `GET /status`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StatusResponse {
+    #[serde(rename = "head_block")]
+    pub head_block: u64,
+    #[serde(rename = "head_time")]
+    pub head_time: i64,
+    #[serde(rename = "targets")]
+    pub targets: Vec<TargetBody>,
+    /// The health of each endpoint, as `status` prints it.
+    #[serde(rename = "endpoints")]
+    pub endpoints: Vec<String>,
+    #[serde(rename = "pending")]
+    pub pending: Vec<PendingBody>,
+    #[serde(rename = "errors")]
+    pub errors: Vec<String>,
+}
+
+impl From<StatusSnapshot> for StatusResponse {
+    fn from(snapshot: StatusSnapshot) -> Self {
+        let targets = snapshot.targets.into_iter().map(|target| TargetBody {
+            target: target.target,
+            latest_block: target.latest_block,
+            updated_at: target.updated_at,
+            lag_blocks: target.lag.blocks,
+            lag_secs: target.lag.seconds,
+            relayer_balance: target.balance.map(|report| report.balance.to_string()),
+            trusting_margin_secs: target.trusting_margin,
+            step_function_id: target.step_function_id,
+            skip_function_id: target.skip_function_id,
+            function_ids_from: target.function_ids_from,
+        });
+        let pending = snapshot.pending.into_iter().map(|pending| PendingBody {
+            request_id: pending.request_id,
+            target: pending.target,
+            target_block: pending.target_block,
+            status: pending.status,
+            age_secs: pending.age.as_secs(),
+        });
+        Self {
+            head_block: snapshot.head_block,
+            head_time: snapshot.head_time,
+            targets: targets.collect(),
+            endpoints: snapshot.endpoints.iter().map(|e| e.to_string()).collect(),
+            pending: pending.collect(),
+            errors: snapshot.errors,
+        }
+    }
+}
+
+/// An error answered as `{"error": message}`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ErrorBody {
+    #[serde(rename = "error")]
+    pub error: String,
+}
+
+struct ApiError(StatusCode, String);
+
+impl ApiError {
+    fn new(status: StatusCode, error: &anyhow::Error) -> Self {
+        Self(status, format!("{:#}", error))
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.0, Json(ErrorBody { error: self.1 })).into_response()
+    }
+}
+
+/// The routes of the API, submitting with `operator` and taking `token`.
+pub fn router<M: Middleware + 'static>(operator: SharedOperator<M>, token: String) -> Router {
+    let token: Arc<str> = token.into();
+    Router::new()
+        .route("/prove", post(prove::<M>))
+        .route("/requests/:id", get(requests::<M>))
+        .route("/status", get(status::<M>))
+        .with_state(operator)
+        .layer(middleware::from_fn_with_state(token, authorize))
+}
+
+/// Serve `router` on `listener` until it fails.
+pub async fn serve(router: Router, listener: std::net::TcpListener) -> Result<()> {
+    listener.set_nonblocking(true)?;
+    info!("Serving the API on {}", listener.local_addr()?);
+    axum::Server::from_tcp(listener)
+        .context("could not start the API")?
+        .serve(router.into_make_service())
+        .await
+        .context("the API failed")
+}
+
+/// Refuse requests without the bearer `token`. The tokens are compared by digest, so that the
+/// time taken doesn't tell how much of one matched.
+async fn authorize<B>(
+    State(token): State<Arc<str>>,
+    request: Request<B>,
+    next: Next<B>,
+) -> Response {
+    let bearer = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match bearer {
+        Some(bearer) if Sha256::digest(bearer) == Sha256::digest(token.as_bytes()) => {
+            next.run(request).await
+        }
+        _ => ApiError(
+            StatusCode::UNAUTHORIZED,
+            "missing or wrong bearer token".into(),
+        )
+        .into_response(),
+    }
+}
+
+async fn prove<M: Middleware + 'static>(
+    State(operator): State<SharedOperator<M>>,
+    Json(body): Json<ProveBody>,
+) -> Result<(StatusCode, Json<ProveResponse>), ApiError> {
+    let operator = operator.read().await;
+    let (trusted_block, trusted_hash) = operator
+        .stored_trusted_header(body.trusted_block)
+        .await
+        .map_err(|e| ApiError::new(prove_status(&e), &e))?;
+    info!(%trusted_block, target_block = %body.target_block, "Proving for the API");
+    let submitted = operator
+        .prove(trusted_block..=body.target_block, trusted_hash)
+        .await
+        .map_err(|e| {
+            error!("The request of the API failed: {:#}", e);
+            ApiError::new(prove_status(&e), &e)
+        })?;
+    // Every target already has a request pending for the range.
+    let Some(first) = submitted.first() else {
+        let error = format!(
+            "a request from {} to {} is already pending for every target",
+            trusted_block, body.target_block
+        );
+        return Err(ApiError(StatusCode::CONFLICT, error));
+    };
+    let response = ProveResponse {
+        correlation_id: first.correlation_id,
+        trusted_block,
+        target_block: body.target_block,
+        requests: submitted
+            .iter()
+            .map(|request| SubmittedBody {
+                target: request.target.to_string(),
+                request_id: request.request_id.clone(),
+            })
+            .collect(),
+    };
+    Ok((StatusCode::ACCEPTED, Json(response)))
+}
+
+/// The status of a proof request that failed with `error`: 400 past the chain head, 422 if
+/// refused, and 502 if the chain, a contract or the backend failed, which may pass on a retry.
+fn prove_status(error: &anyhow::Error) -> StatusCode {
+    if error.is::<BeyondChainHead>() {
+        StatusCode::BAD_REQUEST
+    } else if RefusedRequest::is(error) {
+        StatusCode::UNPROCESSABLE_ENTITY
+    } else {
+        StatusCode::BAD_GATEWAY
+    }
+}
+
+async fn requests<M: Middleware + 'static>(
+    State(operator): State<SharedOperator<M>>,
+    Path(id): Path<String>,
+) -> Result<Json<RequestsResponse>, ApiError> {
+    let operator = operator.read().await;
+    let store = operator.store().ok_or_else(|| {
+        let error = "looking up requests requires a request store";
+        ApiError(StatusCode::NOT_IMPLEMENTED, error.into())
+    })?;
+    let internal = |e| ApiError::new(StatusCode::INTERNAL_SERVER_ERROR, &e);
+    let mut requests = match id.parse::<CorrelationId>() {
+        Ok(correlation_id) => store.by_correlation_id(correlation_id).map_err(internal)?,
+        Err(_) => Vec::new(),
+    };
+    if requests.is_empty() {
+        requests.extend(store.get(&id).map_err(internal)?);
+    }
+    if requests.is_empty() {
+        let error = format!("no requests stored for {}", id);
+        return Err(ApiError(StatusCode::NOT_FOUND, error));
+    }
+    Ok(Json(RequestsResponse { requests }))
+}
+
+async fn status<M: Middleware + 'static>(
+    State(operator): State<SharedOperator<M>>,
+) -> Result<Json<StatusResponse>, ApiError> {
+    let snapshot = TendermintXOperator::shared_status(&operator)
+        .await
+        .map_err(|e| ApiError::new(StatusCode::BAD_GATEWAY, &e))?;
+    Ok(Json(snapshot.into()))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::B256;
+    use axum::body::Body;
+    use ethers::providers::{MockProvider, Provider};
+    use tokio::sync::RwLock;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::backend::mock::MockBackend;
+    use crate::input::{header_hash, InputDataFetcher, InputDataMode};
+    use crate::operator::TendermintXConfig;
+    use crate::target::RequestTarget;
+    use crate::testing::{MockTendermintServer, SyntheticChain};
+    use crate::trusted::LocalTrustedState;
+
+    const TOKEN: &str = "api-token";
+
+    /// The router of an operator of the chain of `server` with a request store in `dir`,
+    /// trusting its block 100.
+    fn test_router(server: &MockTendermintServer, dir: &std::path::Path) -> Router {
+        let mut fetcher = InputDataFetcher::new(vec![server.url()], "");
+        fetcher.mode = InputDataMode::Rpc;
+        let mut config = TendermintXConfig::new(vec![RequestTarget::for_test(5)]);
+        config.store_path = Some(dir.join("requests.db"));
+        let (provider, _) = Provider::<MockProvider>::mocked();
+        let backend = Box::new(MockBackend::new());
+        let mut operator =
+            TendermintXOperator::new(config, fetcher, backend, vec![Arc::new(provider)]).unwrap();
+        let trusted = Arc::new(LocalTrustedState::new(1000));
+        let hash = header_hash(&server.chain().header(100));
+        trusted.insert(Height(100), hash).unwrap();
+        operator.set_trusted_state(0, trusted).unwrap();
+        router(Arc::new(RwLock::new(operator)), TOKEN.to_string())
+    }
+
+    fn request(method: &str, uri: &str, body: Option<serde_json::Value>) -> Request<Body> {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", TOKEN));
+        match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        }
+        .unwrap()
+    }
+
+    async fn call<T: serde::de::DeserializeOwned>(
+        router: &Router,
+        request: Request<Body>,
+    ) -> (StatusCode, T) {
+        let response = router.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        (status, serde_json::from_slice(&body).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_authorization() {
+        let server = MockTendermintServer::start(SyntheticChain::new(1))
+            .await
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let router = test_router(&server, dir.path());
+
+        for authorization in [None, Some("Bearer wrong"), Some(TOKEN)] {
+            let mut request = Request::builder().uri("/status");
+            if let Some(authorization) = authorization {
+                request = request.header(header::AUTHORIZATION, authorization);
+            }
+            let request = request.body(Body::empty()).unwrap();
+            let (status, body): (_, ErrorBody) = call(&router, request).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+            assert_eq!(body.error, "missing or wrong bearer token");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prove_and_lookup() {
+        let server = MockTendermintServer::start(SyntheticChain::new(1))
+            .await
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let router = test_router(&server, dir.path());
+
+        // Without a trusted block, the proof starts from the latest block of the target.
+        let body = serde_json::json!({"target_block": 200});
+        let (status, proved): (_, ProveResponse) =
+            call(&router, request("POST", "/prove", Some(body))).await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(
+            (proved.trusted_block, proved.target_block),
+            (Height(100), Height(200))
+        );
+        assert_eq!(
+            proved.requests,
+            [SubmittedBody {
+                target: RequestTarget::for_test(5).to_string(),
+                request_id: "mock-1".to_string(),
+            }]
+        );
+
+        // The same range is still pending in the store, as it would be for the run loop.
+        let body = serde_json::json!({"trusted_block": 100, "target_block": 200});
+        let (status, error): (_, ErrorBody) =
+            call(&router, request("POST", "/prove", Some(body))).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(
+            error.error,
+            "a request from 100 to 200 is already pending for every target"
+        );
+
+        // Refused as by `prove`.
+        let body = serde_json::json!({"trusted_block": 100, "target_block": 100});
+        let (status, error): (_, ErrorBody) =
+            call(&router, request("POST", "/prove", Some(body))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            error.error,
+            "invalid range: target block 100 is not after trusted block 100"
+        );
+        let body = serde_json::json!({"trusted_block": 150, "target_block": 200});
+        let (status, error): (_, ErrorBody) =
+            call(&router, request("POST", "/prove", Some(body))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(error.error, "no header stored for trusted block 150");
+        // A block the chain hasn't produced yet is a bad request.
+        let body = serde_json::json!({"trusted_block": 100, "target_block": 1001});
+        let (status, error): (_, ErrorBody) =
+            call(&router, request("POST", "/prove", Some(body))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(
+            error.error,
+            "target block 1001 is after the chain head 1000"
+        );
+        // The chain failing isn't the request's fault: it may pass on a retry.
+        server.fail_heights(100..=100);
+        let body = serde_json::json!({"trusted_block": 100, "target_block": 300});
+        let (status, _): (_, ErrorBody) =
+            call(&router, request("POST", "/prove", Some(body))).await;
+        assert_eq!(status, StatusCode::BAD_GATEWAY);
+        server.clear_failures();
+
+        // The requests are looked up by correlation ID or request ID.
+        for id in [proved.correlation_id.to_string(), "mock-1".to_string()] {
+            let uri = format!("/requests/{}", id);
+            let (status, found): (_, RequestsResponse) =
+                call(&router, request("GET", &uri, None)).await;
+            assert_eq!(status, StatusCode::OK);
+            assert_eq!(found.requests.len(), 1);
+            assert_eq!(found.requests[0].request_id, "mock-1");
+            assert_eq!(found.requests[0].status, RequestStatus::Pending);
+            assert_eq!(
+                found.requests[0].correlation_id,
+                Some(proved.correlation_id)
+            );
+        }
+        let (status, error): (_, ErrorBody) =
+            call(&router, request("GET", "/requests/mock-2", None)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(error.error, "no requests stored for mock-2");
+
+        let (status, snapshot): (_, StatusResponse) =
+            call(&router, request("GET", "/status", None)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(snapshot.head_block, 1000);
+        assert_eq!(snapshot.targets.len(), 1);
+        assert_eq!(snapshot.targets[0].latest_block, 100);
+        assert_eq!(snapshot.targets[0].lag_blocks, 900);
+        // Without getters, the configured function IDs.
+        assert_eq!(
+            snapshot.targets[0].step_function_id,
+            B256::repeat_byte(0x22)
+        );
+        assert_eq!(
+            snapshot.targets[0].function_ids_from,
+            Some(FunctionIdSource::Config)
+        );
+        assert_eq!(snapshot.pending.len(), 1);
+        assert_eq!(snapshot.pending[0].request_id, "mock-1");
+    }
+}
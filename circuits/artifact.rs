@@ -0,0 +1,289 @@
+//! The identity of the circuits behind the function IDs.
+//!
+//! A function ID names a circuit deployed on the platform, built from some commit. If that isn't
+//! the commit the operator's input encoding comes from, requests are proved against a circuit that
+//! may read their inputs differently. The operator can be given the digest of the circuit
+//! artifact expected for each function ID, and compares it at startup with the digest the platform
+//! registered for the function, or with a local artifact manifest written when the circuits were
+//! built.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::path::Path;
+
+use alloy_primitives::B256;
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// The expected artifact digest of each function ID.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ExpectedDigests(BTreeMap<B256, B256>);
+
+impl ExpectedDigests {
+    /// Parse comma separated `function_id=digest` pairs, both as 0x-prefixed hex.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut digests = BTreeMap::new();
+        for pair in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (function_id, digest) = pair.split_once('=').ok_or_else(|| {
+                anyhow!(
+                    "invalid circuit digest {:?}, expected function_id=digest",
+                    pair
+                )
+            })?;
+            let function_id: B256 = function_id
+                .trim()
+                .parse()
+                .with_context(|| format!("invalid function ID {:?}", function_id))?;
+            let digest = digest
+                .trim()
+                .parse()
+                .with_context(|| format!("invalid circuit digest for {}", function_id))?;
+            if digests.insert(function_id, digest).is_some() {
+                return Err(anyhow!("duplicate circuit digest for {}", function_id));
+            }
+        }
+        Ok(Self(digests))
+    }
+
+    pub fn insert(&mut self, function_id: B256, digest: B256) {
+        self.0.insert(function_id, digest);
+    }
+
+    pub fn get(&self, function_id: &B256) -> Option<&B256> {
+        self.0.get(function_id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// The artifacts of a build of the circuits, as written next to them: the digest of each
+/// deployed function's artifact, by function ID.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ArtifactManifest {
+    #[serde(rename = "functions")]
+    pub functions: BTreeMap<B256, ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    /// The circuit, e.g. `step` or `skip`.
+    #[serde(rename = "name", default)]
+    pub name: Option<String>,
+    #[serde(rename = "digest")]
+    pub digest: B256,
+    /// The commit the artifact was built from.
+    #[serde(rename = "commit", default)]
+    pub commit: Option<String>,
+}
+
+impl ArtifactManifest {
+    pub fn parse(json: &str) -> Result<Self> {
+        serde_json::from_str(json).context("invalid artifact manifest")
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Self::parse(&json).with_context(|| format!("in {}", path.display()))
+    }
+
+    /// The digest of the artifact of `function_id`, if the manifest has it.
+    pub fn digest(&self, function_id: &B256) -> Option<B256> {
+        self.functions.get(function_id).map(|entry| entry.digest)
+    }
+}
+
+/// A function whose artifact isn't the expected one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mismatch {
+    pub function_id: B256,
+    pub expected: B256,
+    /// The digest found, or none if none is registered.
+    pub found: Option<B256>,
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.found {
+            Some(found) => write!(
+                f,
+                "function {} has circuit digest {}, expected {}",
+                self.function_id, found, self.expected
+            ),
+            None => write!(
+                f,
+                "function {} has no circuit digest, expected {}",
+                self.function_id, self.expected
+            ),
+        }
+    }
+}
+
+/// The result of comparing the expected digests with those found.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Verification {
+    /// The digest of each function that matched.
+    pub verified: BTreeMap<B256, B256>,
+    pub mismatches: Vec<Mismatch>,
+}
+
+impl Verification {
+    /// An error listing the mismatches, if there are any.
+    pub fn ensure_matching(&self) -> Result<()> {
+        if self.mismatches.is_empty() {
+            return Ok(());
+        }
+        let mismatches = self
+            .mismatches
+            .iter()
+            .map(Mismatch::to_string)
+            .collect::<Vec<_>>();
+        Err(anyhow!(
+            "circuit artifacts don't match the expected digests: {}",
+            mismatches.join("; ")
+        ))
+    }
+}
+
+/// Compare the digest `lookup` finds for each function of `expected`. Failing lookups are
+/// errors, not mismatches.
+pub async fn verify<F, Fut>(expected: &ExpectedDigests, mut lookup: F) -> Result<Verification>
+where
+    F: FnMut(B256) -> Fut,
+    Fut: Future<Output = Result<Option<B256>>>,
+{
+    let mut verification = Verification::default();
+    for (&function_id, &digest) in expected.0.iter() {
+        let found = lookup(function_id)
+            .await
+            .with_context(|| format!("failed to look up the circuit of {}", function_id))?;
+        if found == Some(digest) {
+            verification.verified.insert(function_id, digest);
+        } else {
+            verification.mismatches.push(Mismatch {
+                function_id,
+                expected: digest,
+                found,
+            });
+        }
+    }
+    Ok(verification)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MANIFEST: &str = r#"{
+        "functions": {
+            "0x1111111111111111111111111111111111111111111111111111111111111111": {
+                "name": "step",
+                "digest": "0xaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa",
+                "commit": "d4e21c0"
+            },
+            "0x2222222222222222222222222222222222222222222222222222222222222222": {
+                "digest": "0xbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbbb"
+            }
+        }
+    }"#;
+
+    #[test]
+    fn test_parse_expected_digests() {
+        let spec = format!(
+            "{}={}, {}={}",
+            B256::repeat_byte(0x11),
+            B256::repeat_byte(0xaa),
+            B256::repeat_byte(0x22),
+            B256::repeat_byte(0xbb)
+        );
+        let digests = ExpectedDigests::parse(&spec).unwrap();
+        assert_eq!(
+            digests.get(&B256::repeat_byte(0x11)),
+            Some(&B256::repeat_byte(0xaa))
+        );
+        assert_eq!(
+            digests.get(&B256::repeat_byte(0x22)),
+            Some(&B256::repeat_byte(0xbb))
+        );
+        assert!(ExpectedDigests::parse("").unwrap().is_empty());
+
+        assert!(ExpectedDigests::parse("0x11").is_err());
+        assert!(ExpectedDigests::parse(&format!("0x11={}", B256::ZERO)).is_err());
+        let duplicate = format!("{}={}", B256::ZERO, B256::ZERO);
+        assert!(ExpectedDigests::parse(&format!("{},{}", duplicate, duplicate)).is_err());
+    }
+
+    #[test]
+    fn test_parse_manifest() {
+        let manifest = ArtifactManifest::parse(MANIFEST).unwrap();
+        let step = &manifest.functions[&B256::repeat_byte(0x11)];
+        assert_eq!(step.name.as_deref(), Some("step"));
+        assert_eq!(step.commit.as_deref(), Some("d4e21c0"));
+        assert_eq!(
+            manifest.digest(&B256::repeat_byte(0x22)),
+            Some(B256::repeat_byte(0xbb))
+        );
+        assert_eq!(manifest.digest(&B256::repeat_byte(0x33)), None);
+
+        assert!(ArtifactManifest::parse("{}").is_err());
+        assert!(ArtifactManifest::parse(r#"{"functions": {"0x11": {"digest": "0x"}}}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_verify_against_manifest() {
+        let manifest = ArtifactManifest::parse(MANIFEST).unwrap();
+        let lookup = |function_id| {
+            let digest = manifest.digest(&function_id);
+            async move { Ok(digest) }
+        };
+
+        // Matching digests.
+        let mut expected = ExpectedDigests::default();
+        expected.insert(B256::repeat_byte(0x11), B256::repeat_byte(0xaa));
+        let verification = verify(&expected, lookup).await.unwrap();
+        assert_eq!(verification.mismatches, vec![]);
+        assert_eq!(
+            verification.verified,
+            BTreeMap::from([(B256::repeat_byte(0x11), B256::repeat_byte(0xaa))])
+        );
+        verification.ensure_matching().unwrap();
+
+        // A different digest, and a function the manifest doesn't have.
+        expected.insert(B256::repeat_byte(0x22), B256::repeat_byte(0xcc));
+        expected.insert(B256::repeat_byte(0x33), B256::repeat_byte(0xdd));
+        let verification = verify(&expected, lookup).await.unwrap();
+        assert_eq!(verification.verified.len(), 1);
+        assert_eq!(
+            verification.mismatches,
+            vec![
+                Mismatch {
+                    function_id: B256::repeat_byte(0x22),
+                    expected: B256::repeat_byte(0xcc),
+                    found: Some(B256::repeat_byte(0xbb)),
+                },
+                Mismatch {
+                    function_id: B256::repeat_byte(0x33),
+                    expected: B256::repeat_byte(0xdd),
+                    found: None,
+                },
+            ]
+        );
+        let error = verification.ensure_matching().unwrap_err().to_string();
+        assert!(error.contains(&format!(
+            "function {} has circuit digest {}, expected {}",
+            B256::repeat_byte(0x22),
+            B256::repeat_byte(0xbb),
+            B256::repeat_byte(0xcc)
+        )));
+        assert!(error.contains("has no circuit digest"));
+
+        // A failing lookup is an error.
+        let failing = |_| async { Err(anyhow!("platform unavailable")) };
+        assert!(verify(&expected, failing).await.is_err());
+    }
+}
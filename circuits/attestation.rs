@@ -0,0 +1,304 @@
+//! Operator attestations: a signature by the operator's ed25519 key over what each submission
+//! requests, so that a third party can check offline who requested what.
+//!
+//! An `Attestation` is signed as its canonical JSON: the keys sorted, no whitespace, the address
+//! and hashes as lowercase 0x-prefixed hex and the timestamp in unix seconds. The operator signs
+//! every submission before handing it to the backend, records the signed attestation with the
+//! submission in the audit log and, once the backend accepted it, with the request in the
+//! request store. `audit::verify_signatures` checks a log against the operator's public key.
+//!
+//! The signing key is read from a file holding its 32-byte seed as hex. Neither `Attester` nor
+//! the errors of loading it ever print the key.
+
+use std::fmt;
+use std::path::Path;
+
+use alloy_primitives::{hex, Address, Bytes, B256};
+use anyhow::{anyhow, Context, Result};
+use ed25519_consensus::{Signature, SigningKey, VerificationKey};
+use serde::{Deserialize, Serialize};
+
+use crate::backend::{ProofRequest, RequestKind};
+use crate::correlation::CorrelationId;
+use crate::export;
+
+/// What the operator attests to for a submission.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attestation {
+    #[serde(rename = "chain_id")]
+    pub chain_id: u32,
+    #[serde(rename = "contract")]
+    pub contract: Address,
+    #[serde(rename = "trusted_block")]
+    pub trusted_block: u64,
+    #[serde(rename = "trusted_hash")]
+    pub trusted_hash: B256,
+    #[serde(rename = "target_block")]
+    pub target_block: u64,
+    #[serde(rename = "function_id")]
+    pub function_id: B256,
+    /// Unix timestamp (seconds) of the signature.
+    #[serde(rename = "timestamp")]
+    pub timestamp: u64,
+    #[serde(rename = "correlation_id")]
+    pub correlation_id: Option<CorrelationId>,
+}
+
+/// The fields of an `Attestation` as they are signed, in the order of their keys.
+#[derive(Serialize)]
+struct Canonical {
+    chain_id: u32,
+    contract: String,
+    correlation_id: Option<String>,
+    function_id: String,
+    target_block: u64,
+    timestamp: u64,
+    trusted_block: u64,
+    trusted_hash: String,
+}
+
+impl Attestation {
+    /// The attestation of the `kind` request `request` at unix timestamp `timestamp`. The trusted
+    /// header hash is read from the packed input.
+    pub fn of(kind: RequestKind, request: &ProofRequest<'_>, timestamp: u64) -> Result<Self> {
+        let trusted_hash = export::trusted_header_hash(kind, &request.input)?;
+        Ok(Self {
+            chain_id: request.target.chain_id,
+            contract: request.target.address,
+            trusted_block: request.trusted_block,
+            trusted_hash: B256::from(trusted_hash.to_bytes()),
+            target_block: request.target_block,
+            function_id: request.function_id,
+            timestamp,
+            correlation_id: request.correlation_id,
+        })
+    }
+
+    /// The bytes that are signed.
+    pub fn canonical_json(&self) -> String {
+        let canonical = Canonical {
+            chain_id: self.chain_id,
+            contract: hex::encode_prefixed(self.contract),
+            correlation_id: self.correlation_id.map(|id| id.to_string()),
+            function_id: hex::encode_prefixed(self.function_id),
+            target_block: self.target_block,
+            timestamp: self.timestamp,
+            trusted_block: self.trusted_block,
+            trusted_hash: hex::encode_prefixed(self.trusted_hash),
+        };
+        serde_json::to_string(&canonical).expect("an attestation always serializes")
+    }
+}
+
+/// An attestation and the operator's signature of its canonical JSON.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedAttestation {
+    #[serde(flatten)]
+    pub attestation: Attestation,
+    /// The 64-byte ed25519 signature, as 0x-prefixed hex.
+    #[serde(rename = "signature")]
+    pub signature: Bytes,
+}
+
+impl SignedAttestation {
+    /// Check the signature with `key`.
+    pub fn verify(&self, key: &VerificationKey) -> Result<()> {
+        let signature = Signature::try_from(self.signature.as_ref())
+            .map_err(|_| anyhow!("the signature is not 64 bytes"))?;
+        key.verify(&signature, self.attestation.canonical_json().as_bytes())
+            .map_err(|_| anyhow!("invalid signature"))
+    }
+}
+
+/// Signs attestations with the operator's key. Its `Debug` shows the public key only.
+pub struct Attester {
+    key: SigningKey,
+}
+
+impl fmt::Debug for Attester {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Attester({})", public_key_hex(&self.public_key()))
+    }
+}
+
+impl Attester {
+    pub fn from_seed(seed: [u8; 32]) -> Self {
+        Self {
+            key: SigningKey::from(seed),
+        }
+    }
+
+    /// The key in the file at `path`: its 32-byte seed as hex, optionally 0x-prefixed. Errors
+    /// don't include the contents of the file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read the attestation key {}", path.display()))?;
+        let seed = decode_hex_32(contents.trim())
+            .ok_or_else(|| anyhow!("{} is not a 32-byte hex seed", path.display()))?;
+        Ok(Self::from_seed(seed))
+    }
+
+    pub fn public_key(&self) -> VerificationKey {
+        self.key.verification_key()
+    }
+
+    pub fn sign(&self, attestation: Attestation) -> SignedAttestation {
+        let signature = self.key.sign(attestation.canonical_json().as_bytes());
+        SignedAttestation {
+            attestation,
+            signature: Bytes::from(signature.to_bytes().to_vec()),
+        }
+    }
+}
+
+/// `key` as 0x-prefixed hex.
+pub fn public_key_hex(key: &VerificationKey) -> String {
+    hex::encode_prefixed(key.to_bytes())
+}
+
+/// The public key `key`, as hex, optionally 0x-prefixed.
+pub fn parse_public_key(key: &str) -> Result<VerificationKey> {
+    let bytes = decode_hex_32(key.trim()).ok_or_else(|| anyhow!("invalid public key {}", key))?;
+    VerificationKey::try_from(bytes).map_err(|_| anyhow!("invalid public key {}", key))
+}
+
+fn decode_hex_32(value: &str) -> Option<[u8; 32]> {
+    let bytes = hex::decode(value.strip_prefix("0x").unwrap_or(value)).ok()?;
+    bytes.try_into().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::RequestInputs;
+    use crate::target::RequestTarget;
+    use crate::types::{HeaderHash, Height};
+
+    fn target() -> RequestTarget {
+        RequestTarget {
+            address: Address::repeat_byte(0xab),
+            ..RequestTarget::for_test(5)
+        }
+    }
+
+    fn attestation() -> Attestation {
+        Attestation {
+            chain_id: 5,
+            contract: Address::repeat_byte(0xab),
+            trusted_block: 100,
+            trusted_hash: B256::repeat_byte(0xcd),
+            target_block: 500,
+            function_id: B256::repeat_byte(0x33),
+            timestamp: 1_700_000_000,
+            correlation_id: Some("01HF3B8Y5ZQK4V9C2T7N6M1R0S".parse().unwrap()),
+        }
+    }
+
+    #[test]
+    fn test_load_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("attestation.key");
+        let seed_hex = format!("0x{}\n", "07".repeat(32));
+        std::fs::write(&path, &seed_hex).unwrap();
+        let attester = Attester::load(&path).unwrap();
+        let expected = Attester::from_seed([0x07; 32]);
+        assert_eq!(
+            attester.public_key().to_bytes(),
+            expected.public_key().to_bytes()
+        );
+        // Without the prefix too.
+        std::fs::write(&path, "07".repeat(32)).unwrap();
+        let attester = Attester::load(&path).unwrap();
+        assert_eq!(
+            attester.public_key().to_bytes(),
+            expected.public_key().to_bytes()
+        );
+
+        // Neither the Debug of the attester nor a load error shows the seed.
+        let debug = format!("{:?}", attester);
+        assert_eq!(
+            debug,
+            format!("Attester({})", public_key_hex(&attester.public_key()))
+        );
+        assert!(!debug.contains(&"07".repeat(32)));
+        std::fs::write(&path, "07".repeat(31)).unwrap();
+        let error = format!("{:#}", Attester::load(&path).unwrap_err());
+        assert_eq!(
+            error,
+            format!("{} is not a 32-byte hex seed", path.display())
+        );
+        assert!(Attester::load(&dir.path().join("missing.key")).is_err());
+    }
+
+    #[test]
+    fn test_canonical_json() {
+        assert_eq!(
+            attestation().canonical_json(),
+            format!(
+                "{{\"chain_id\":5,\"contract\":\"0x{}\",\
+                 \"correlation_id\":\"01HF3B8Y5ZQK4V9C2T7N6M1R0S\",\"function_id\":\"0x{}\",\
+                 \"target_block\":500,\"timestamp\":1700000000,\"trusted_block\":100,\
+                 \"trusted_hash\":\"0x{}\"}}",
+                "ab".repeat(20),
+                "33".repeat(32),
+                "cd".repeat(32)
+            )
+        );
+        let mut attestation = attestation();
+        attestation.correlation_id = None;
+        assert!(attestation
+            .canonical_json()
+            .contains("\"correlation_id\":null,"));
+
+        // Built from a request, with the trusted hash of its input.
+        let target = target();
+        let inputs = RequestInputs::new(Height(100), HeaderHash([0xcd; 32]), Height(500)).unwrap();
+        let request = inputs.proof_request(&target);
+        let attestation = Attestation::of(inputs.kind, &request, 1_700_000_000).unwrap();
+        assert_eq!(attestation.trusted_hash, B256::repeat_byte(0xcd));
+        assert_eq!(attestation.function_id, B256::repeat_byte(0x33));
+        assert_eq!(attestation.correlation_id, None);
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let attester = Attester::from_seed([0x07; 32]);
+        let signed = attester.sign(attestation());
+        assert_eq!(signed.signature.len(), 64);
+        signed.verify(&attester.public_key()).unwrap();
+
+        // It round-trips through its JSON, flattened with its signature.
+        let json = serde_json::to_string(&signed).unwrap();
+        let parsed: SignedAttestation = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, signed);
+        parsed.verify(&attester.public_key()).unwrap();
+
+        // Another key, or any change to what was signed, fails.
+        let other = Attester::from_seed([0x08; 32]);
+        let error = signed.verify(&other.public_key()).unwrap_err();
+        assert_eq!(error.to_string(), "invalid signature");
+        let mut tampered = signed.clone();
+        tampered.attestation.target_block += 1;
+        assert!(tampered.verify(&attester.public_key()).is_err());
+        let mut truncated = signed;
+        truncated.signature = Bytes::from(vec![0; 63]);
+        let error = truncated.verify(&attester.public_key()).unwrap_err();
+        assert_eq!(error.to_string(), "the signature is not 64 bytes");
+    }
+
+    #[test]
+    fn test_parse_public_key() {
+        let attester = Attester::from_seed([0x07; 32]);
+        let hex = public_key_hex(&attester.public_key());
+        let parsed = parse_public_key(&hex).unwrap();
+        assert_eq!(parsed.to_bytes(), attester.public_key().to_bytes());
+        assert_eq!(
+            parse_public_key(hex.trim_start_matches("0x"))
+                .unwrap()
+                .to_bytes(),
+            parsed.to_bytes()
+        );
+        assert!(parse_public_key("0x1234").is_err());
+        assert!(parse_public_key("not hex").is_err());
+    }
+}
@@ -0,0 +1,843 @@
+//! An append-only JSONL audit log of every submission and its outcome, kept independently of the
+//! request store.
+//!
+//! Each entry is one JSON line: a submission attempt (with all of its inputs and the backend's
+//! answer), a fulfillment status observed for a request, a header landing on-chain, or the summary
+//! of an iteration of the run loop. Every line is synced to disk before `record` returns, so a
+//! crash loses at most the entry being written. Once the file reaches its maximum size it is
+//! rotated to `<path>.1`, the older files shifting to `<path>.2` and so on, and the oldest beyond
+//! the configured count is deleted.
+
+use std::fmt;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use alloy_primitives::{Address, Bytes, B256};
+use anyhow::{anyhow, ensure, Context, Result};
+use ed25519_consensus::VerificationKey;
+use serde::{Deserialize, Serialize};
+
+use crate::attestation::SignedAttestation;
+use crate::backend::{ProofRequest, RequestKind};
+use crate::export;
+use crate::platform::FulfillmentStatus;
+use crate::store::RequestStatus;
+use crate::summary::IterationSummary;
+use crate::target::RequestMode;
+
+/// The default size at which the log is rotated.
+pub const DEFAULT_MAX_BYTES: u64 = 100 * 1024 * 1024;
+
+/// The default number of rotated files kept.
+pub const DEFAULT_MAX_FILES: usize = 10;
+
+/// How much of the end of the log `recent_errors` and `AuditLog::open` read.
+const TAIL_BYTES: u64 = 256 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum AuditEvent {
+    /// A submission attempt, accepted or not.
+    Submission {
+        kind: String,
+        chain_id: u32,
+        address: String,
+        trusted_block: u64,
+        target_block: u64,
+        function_id: String,
+        /// The callback calldata, as 0x-prefixed hex.
+        calldata: String,
+        /// The packed circuit input, as 0x-prefixed hex.
+        input: String,
+        backend: String,
+        /// The request ID, if the backend accepted the request.
+        request_id: Option<String>,
+        /// Why the submission failed, if it did.
+        error: Option<String>,
+        /// The digest of the circuit artifact verified for the function at startup, if one was.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        circuit_digest: Option<String>,
+        /// The attempt the request is part of, if the operator planned it.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        correlation_id: Option<String>,
+        /// The operator's signed attestation of the submission, if it signs them.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        attestation: Option<SignedAttestation>,
+    },
+    /// A status observed for a request, from `source` (`wait`, `poll` or `callback`).
+    Fulfillment {
+        request_id: String,
+        source: String,
+        status: String,
+        proof_id: Option<String>,
+        tx_hash: Option<String>,
+        error: Option<String>,
+    },
+    /// A `HeadUpdate` event of a target contract.
+    HeadUpdate {
+        chain_id: u32,
+        address: String,
+        block_number: u64,
+        header_hash: String,
+        tx_hash: String,
+        eth_block_number: u64,
+        /// The number of stored requests the update was correlated with.
+        correlated: usize,
+    },
+    /// The summary of an iteration of the run loop.
+    Iteration(IterationSummary),
+}
+
+impl AuditEvent {
+    /// The submission of `request` as a `kind` request to `backend`, with its `result`, the
+    /// verified digest of the circuit proving it and the operator's attestation, if any.
+    pub fn submission(
+        kind: RequestKind,
+        request: &ProofRequest<'_>,
+        backend: &str,
+        result: &Result<String>,
+        circuit_digest: Option<B256>,
+        attestation: Option<SignedAttestation>,
+    ) -> Self {
+        AuditEvent::Submission {
+            kind: kind.to_string(),
+            chain_id: request.target.chain_id,
+            address: request.target.address.to_string(),
+            trusted_block: request.trusted_block,
+            target_block: request.target_block,
+            function_id: request.function_id.to_string(),
+            calldata: request.calldata.to_string(),
+            input: request.input.to_string(),
+            backend: backend.to_string(),
+            request_id: result.as_ref().ok().cloned(),
+            error: result.as_ref().err().map(|e| format!("{:#}", e)),
+            circuit_digest: circuit_digest.map(|digest| digest.to_string()),
+            correlation_id: request.correlation_id.map(|id| id.to_string()),
+            attestation,
+        }
+    }
+
+    /// The `status` of `request_id` in `mode`, observed from `source`.
+    pub fn fulfillment(
+        request_id: &str,
+        source: &str,
+        status: &FulfillmentStatus,
+        mode: RequestMode,
+    ) -> Self {
+        let (proof_id, tx_hash, error) = match status {
+            FulfillmentStatus::Proved { proof_id } => (proof_id.clone(), None, None),
+            FulfillmentStatus::Relayed { proof_id, tx_hash } => {
+                (proof_id.clone(), tx_hash.clone(), None)
+            }
+            FulfillmentStatus::Failed { error } => (None, None, error.clone()),
+            FulfillmentStatus::Proving | FulfillmentStatus::TimedOut => (None, None, None),
+        };
+        let status = match status {
+            FulfillmentStatus::TimedOut => "timed_out",
+            _ => RequestStatus::from_fulfillment(status, mode).as_str(),
+        };
+        AuditEvent::Fulfillment {
+            request_id: request_id.to_string(),
+            source: source.to_string(),
+            status: status.to_string(),
+            proof_id,
+            tx_hash,
+            error,
+        }
+    }
+}
+
+impl AuditEvent {
+    /// The error of a failed submission or fulfillment, if this is one.
+    pub fn error(&self) -> Option<&str> {
+        match self {
+            AuditEvent::Submission { error, .. } | AuditEvent::Fulfillment { error, .. } => {
+                error.as_deref()
+            }
+            AuditEvent::HeadUpdate { .. } | AuditEvent::Iteration(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for AuditEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuditEvent::Submission {
+                kind,
+                chain_id,
+                address,
+                target_block,
+                backend,
+                request_id,
+                error,
+                ..
+            } => {
+                write!(
+                    f,
+                    "{} request for {}:{} block {} to {}",
+                    kind, chain_id, address, target_block, backend
+                )?;
+                match (request_id, error) {
+                    (Some(request_id), _) => write!(f, ": {}", request_id),
+                    (None, Some(error)) => write!(f, " failed: {}", error),
+                    (None, None) => Ok(()),
+                }
+            }
+            AuditEvent::Fulfillment {
+                request_id,
+                source,
+                status,
+                error,
+                ..
+            } => {
+                write!(f, "request {} {} ({})", request_id, status, source)?;
+                match error {
+                    Some(error) => write!(f, ": {}", error),
+                    None => Ok(()),
+                }
+            }
+            AuditEvent::HeadUpdate {
+                chain_id,
+                address,
+                block_number,
+                tx_hash,
+                ..
+            } => write!(
+                f,
+                "header {} landed on {}:{} in tx {}",
+                block_number, chain_id, address, tx_hash
+            ),
+            AuditEvent::Iteration(summary) => write!(f, "{}", summary),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditEntry {
+    /// Unix time in milliseconds. Never decreases within a log.
+    pub timestamp_ms: u64,
+    #[serde(flatten)]
+    pub event: AuditEvent,
+}
+
+pub(crate) fn unix_timestamp_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |elapsed| elapsed.as_millis() as u64)
+}
+
+/// The path of the `n`th rotated file of the log at `path`.
+fn rotated_path(path: &Path, n: usize) -> PathBuf {
+    let mut name = path.as_os_str().to_os_string();
+    name.push(format!(".{}", n));
+    PathBuf::from(name)
+}
+
+/// The files of the log at `path`, oldest first: the rotated files, then the current one.
+pub fn log_files(path: &Path) -> Vec<PathBuf> {
+    let mut files = (1..)
+        .map(|n| rotated_path(path, n))
+        .take_while(|file| file.exists())
+        .collect::<Vec<_>>();
+    files.reverse();
+    if path.exists() {
+        files.push(path.to_path_buf());
+    }
+    files
+}
+
+struct Writer {
+    file: File,
+    size: u64,
+    last_timestamp_ms: u64,
+}
+
+pub struct AuditLog {
+    path: PathBuf,
+    max_bytes: u64,
+    max_files: usize,
+    writer: Mutex<Writer>,
+}
+
+impl AuditLog {
+    /// Open the log at `path` for appending, creating it if needed. The log is rotated once it
+    /// reaches `max_bytes`, keeping `max_files` rotated files. Entries are never timestamped
+    /// before the last entry already in the log, even if the clock stepped back while the
+    /// process was down.
+    pub fn open(path: impl Into<PathBuf>, max_bytes: u64, max_files: usize) -> Result<Self> {
+        ensure!(
+            max_files > 0,
+            "the audit log must keep at least one rotated file"
+        );
+        let path = path.into();
+        let file = Self::open_file(&path)?;
+        let size = file.metadata()?.len();
+        // The current file is empty right after a rotation.
+        let mut last_timestamp_ms = 0;
+        for file in [path.clone(), rotated_path(&path, 1)] {
+            if let Some(entries) = file.exists().then(|| tail_entries(&file)).transpose()? {
+                if let Some(entry) = entries.last() {
+                    last_timestamp_ms = entry.timestamp_ms;
+                    break;
+                }
+            }
+        }
+        Ok(Self {
+            path,
+            max_bytes,
+            max_files,
+            writer: Mutex::new(Writer {
+                file,
+                size,
+                last_timestamp_ms,
+            }),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn open_file(path: &Path) -> Result<File> {
+        OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("could not open audit log {}", path.display()))
+    }
+
+    /// Append `event`, and sync it to disk.
+    pub fn record(&self, event: AuditEvent) -> Result<()> {
+        let mut writer = self.writer.lock().unwrap();
+        // A clock stepping back doesn't reorder the entries.
+        let timestamp_ms = unix_timestamp_ms().max(writer.last_timestamp_ms);
+        let mut line = serde_json::to_string(&AuditEntry {
+            timestamp_ms,
+            event,
+        })?;
+        line.push('\n');
+        if writer.size > 0 && writer.size + line.len() as u64 > self.max_bytes {
+            self.rotate(&mut writer)?;
+        }
+        writer.file.write_all(line.as_bytes())?;
+        writer.file.sync_data()?;
+        writer.size += line.len() as u64;
+        writer.last_timestamp_ms = timestamp_ms;
+        Ok(())
+    }
+
+    /// Sync the log and its metadata to disk, e.g. before the process exits.
+    pub fn sync(&self) -> Result<()> {
+        let writer = self.writer.lock().unwrap();
+        writer
+            .file
+            .sync_all()
+            .with_context(|| format!("could not sync audit log {}", self.path.display()))
+    }
+
+    fn rotate(&self, writer: &mut Writer) -> Result<()> {
+        let oldest = rotated_path(&self.path, self.max_files);
+        if oldest.exists() {
+            fs::remove_file(&oldest)?;
+        }
+        for n in (1..self.max_files).rev() {
+            let from = rotated_path(&self.path, n);
+            if from.exists() {
+                fs::rename(&from, rotated_path(&self.path, n + 1))?;
+            }
+        }
+        fs::rename(&self.path, rotated_path(&self.path, 1))?;
+        writer.file = Self::open_file(&self.path)?;
+        writer.size = 0;
+        Ok(())
+    }
+}
+
+/// The last `limit` entries with an error among the most recent entries of the log at `path`,
+/// oldest first. Only the end of the current file is read.
+pub fn recent_errors(path: &Path, limit: usize) -> Result<Vec<AuditEntry>> {
+    let errors = tail_entries(path)?
+        .into_iter()
+        .filter(|entry| entry.event.error().is_some())
+        .collect::<Vec<_>>();
+    Ok(errors[errors.len().saturating_sub(limit)..].to_vec())
+}
+
+/// The entries in the last `TAIL_BYTES` of the file at `path`, skipping invalid lines.
+fn tail_entries(path: &Path) -> Result<Vec<AuditEntry>> {
+    let mut file =
+        File::open(path).with_context(|| format!("could not open audit log {}", path.display()))?;
+    let len = file.metadata()?.len();
+    file.seek(SeekFrom::Start(len.saturating_sub(TAIL_BYTES)))?;
+    let mut tail = Vec::new();
+    file.read_to_end(&mut tail)?;
+    let tail = String::from_utf8_lossy(&tail);
+    // The first line is partial unless the whole file was read.
+    let skip = usize::from(len > TAIL_BYTES);
+    Ok(tail
+        .lines()
+        .skip(skip)
+        .filter_map(|line| serde_json::from_str::<AuditEntry>(line).ok())
+        .collect())
+}
+
+/// The entries checked by `verify`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifySummary {
+    #[serde(rename = "files")]
+    pub files: usize,
+    #[serde(rename = "submissions")]
+    pub submissions: usize,
+    #[serde(rename = "fulfillments")]
+    pub fulfillments: usize,
+    #[serde(rename = "head_updates")]
+    pub head_updates: usize,
+    #[serde(rename = "iterations")]
+    pub iterations: usize,
+}
+
+impl fmt::Display for VerifySummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} files: {} submissions, {} fulfillments, {} head updates, {} iterations",
+            self.files, self.submissions, self.fulfillments, self.head_updates, self.iterations
+        )
+    }
+}
+
+/// Check that every line of `files` (in order) is an entry, and that their timestamps never
+/// decrease. Fails on the first invalid line.
+pub fn verify(files: &[PathBuf]) -> Result<VerifySummary> {
+    let mut summary = VerifySummary {
+        files: files.len(),
+        ..Default::default()
+    };
+    let mut last_timestamp_ms = 0;
+    for_each_entry(files, |at, entry| {
+        ensure!(
+            entry.timestamp_ms >= last_timestamp_ms,
+            "{}: timestamp {} is before the previous entry's {}",
+            at,
+            entry.timestamp_ms,
+            last_timestamp_ms
+        );
+        last_timestamp_ms = entry.timestamp_ms;
+        match entry.event {
+            AuditEvent::Submission { .. } => summary.submissions += 1,
+            AuditEvent::Fulfillment { .. } => summary.fulfillments += 1,
+            AuditEvent::HeadUpdate { .. } => summary.head_updates += 1,
+            AuditEvent::Iteration(_) => summary.iterations += 1,
+        }
+        Ok(())
+    })?;
+    Ok(summary)
+}
+
+/// The submissions checked by `verify_signatures`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignatureSummary {
+    #[serde(rename = "files")]
+    pub files: usize,
+    #[serde(rename = "signed")]
+    pub signed: usize,
+    #[serde(rename = "unsigned")]
+    pub unsigned: usize,
+}
+
+impl fmt::Display for SignatureSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} files: {} signed submissions, {} unsigned",
+            self.files, self.signed, self.unsigned
+        )
+    }
+}
+
+/// Check that the attestation of every signed submission of `files` is signed by `key`, and
+/// attests to the submission it is recorded with. Submissions without one, e.g. those recorded
+/// before the operator signed them, are only counted. Fails on the first invalid line.
+pub fn verify_signatures(files: &[PathBuf], key: &VerificationKey) -> Result<SignatureSummary> {
+    let mut summary = SignatureSummary {
+        files: files.len(),
+        ..Default::default()
+    };
+    for_each_entry(files, |at, entry| {
+        let AuditEvent::Submission {
+            kind,
+            chain_id,
+            address,
+            trusted_block,
+            target_block,
+            function_id,
+            input,
+            correlation_id,
+            attestation,
+            ..
+        } = entry.event
+        else {
+            return Ok(());
+        };
+        let Some(signed) = attestation else {
+            summary.unsigned += 1;
+            return Ok(());
+        };
+        let attested = &signed.attestation;
+        let trusted_hash = kind
+            .parse::<RequestKind>()
+            .and_then(|kind| export::trusted_header_hash(kind, &input.parse::<Bytes>()?))
+            .with_context(|| format!("{}: invalid submission input", at))?;
+        ensure!(
+            attested.chain_id == chain_id
+                && address.parse::<Address>().ok() == Some(attested.contract)
+                && attested.trusted_block == trusted_block
+                && B256::from(trusted_hash.to_bytes()) == attested.trusted_hash
+                && attested.target_block == target_block
+                && attested.function_id.to_string() == function_id
+                && attested.correlation_id.map(|id| id.to_string()) == correlation_id,
+            "{}: the attestation is not of the submission it is recorded with",
+            at
+        );
+        signed.verify(key).map_err(|e| anyhow!("{}: {}", at, e))?;
+        summary.signed += 1;
+        Ok(())
+    })?;
+    Ok(summary)
+}
+
+/// Call `f` with every entry of `files`, in order, and where it is. Fails on the first line that
+/// isn't an entry.
+fn for_each_entry(
+    files: &[PathBuf],
+    mut f: impl FnMut(&str, AuditEntry) -> Result<()>,
+) -> Result<()> {
+    for path in files {
+        let file = File::open(path)
+            .with_context(|| format!("could not open audit log {}", path.display()))?;
+        for (i, line) in BufReader::new(file).lines().enumerate() {
+            let at = format!("{}:{}", path.display(), i + 1);
+            let entry: AuditEntry = serde_json::from_str(&line?)
+                .map_err(|e| anyhow!("{}: invalid entry: {}", at, e))?;
+            f(&at, entry)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::B256;
+
+    use super::*;
+    use crate::attestation::{Attestation, Attester};
+    use crate::correlation::CorrelationId;
+    use crate::export::RequestInputs;
+    use crate::target::RequestTarget;
+    use crate::types::{HeaderHash, Height};
+    use crate::wire::assert_snapshot;
+
+    #[test]
+    fn test_record_submissions() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let target = RequestTarget::for_test(5);
+        let inputs = RequestInputs::new(Height(100), HeaderHash([0xab; 32]), Height(500)).unwrap();
+        let correlation_id = CorrelationId::generate();
+        let request = inputs
+            .proof_request(&target)
+            .with_correlation_id(correlation_id);
+
+        let log = AuditLog::open(&path, DEFAULT_MAX_BYTES, DEFAULT_MAX_FILES).unwrap();
+        let accepted = Ok("mock-0".to_string());
+        log.record(AuditEvent::submission(
+            inputs.kind,
+            &request,
+            "mock",
+            &accepted,
+            Some(B256::repeat_byte(0x44)),
+            None,
+        ))
+        .unwrap();
+        let failed = Err(anyhow!("rate limited"));
+        log.record(AuditEvent::submission(
+            inputs.kind,
+            &request,
+            "mock",
+            &failed,
+            None,
+            None,
+        ))
+        .unwrap();
+        drop(log);
+
+        // Reopening appends to the log.
+        let log = AuditLog::open(&path, DEFAULT_MAX_BYTES, DEFAULT_MAX_FILES).unwrap();
+        let status = FulfillmentStatus::Failed {
+            error: Some("prover crashed".to_string()),
+        };
+        log.record(AuditEvent::fulfillment(
+            "mock-0",
+            "poll",
+            &status,
+            RequestMode::Platform,
+        ))
+        .unwrap();
+
+        let files = log_files(&path);
+        let summary = verify(&files).unwrap();
+        assert_eq!(
+            summary.to_string(),
+            "1 files: 2 submissions, 1 fulfillments, 0 head updates, 0 iterations"
+        );
+
+        let contents = fs::read_to_string(&path).unwrap();
+        let entries = contents
+            .lines()
+            .map(|line| serde_json::from_str::<AuditEntry>(line).unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(
+            entries[1].event,
+            AuditEvent::Submission {
+                kind: "skip".to_string(),
+                chain_id: 5,
+                address: "0x1111111111111111111111111111111111111111".to_string(),
+                trusted_block: 100,
+                target_block: 500,
+                function_id: B256::repeat_byte(0x33).to_string(),
+                calldata: inputs.calldata.to_string(),
+                input: inputs.input.to_string(),
+                backend: "mock".to_string(),
+                request_id: None,
+                error: Some("rate limited".to_string()),
+                circuit_digest: None,
+                correlation_id: Some(correlation_id.to_string()),
+                attestation: None,
+            }
+        );
+        // The digest is only recorded when verified.
+        let accepted: serde_json::Value =
+            serde_json::from_str(contents.lines().next().unwrap()).unwrap();
+        assert_eq!(
+            accepted["circuit_digest"],
+            B256::repeat_byte(0x44).to_string()
+        );
+        assert!(!contents.lines().nth(1).unwrap().contains("circuit_digest"));
+        let line: serde_json::Value =
+            serde_json::from_str(contents.lines().last().unwrap()).unwrap();
+        assert_eq!(line["event"], "fulfillment");
+        assert_eq!(line["status"], "failed");
+        assert_eq!(line["error"], "prover crashed");
+
+        let errors = recent_errors(&path, 8).unwrap();
+        assert_eq!(errors.len(), 2);
+        assert_eq!(
+            errors[1].event.to_string(),
+            "request mock-0 failed (poll): prover crashed"
+        );
+    }
+
+    #[test]
+    fn test_verify_signatures() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let target = RequestTarget::for_test(5);
+        let inputs = RequestInputs::new(Height(100), HeaderHash([0xab; 32]), Height(500)).unwrap();
+        let request = inputs
+            .proof_request(&target)
+            .with_correlation_id(CorrelationId::generate());
+        let attester = Attester::from_seed([0x07; 32]);
+        let attestation = Attestation::of(inputs.kind, &request, 1_700_000_000).unwrap();
+        let signed = attester.sign(attestation);
+
+        let log = AuditLog::open(&path, DEFAULT_MAX_BYTES, DEFAULT_MAX_FILES).unwrap();
+        let submission = |result: &Result<String>, attestation| {
+            AuditEvent::submission(inputs.kind, &request, "mock", result, None, attestation)
+        };
+        log.record(submission(&Ok("mock-0".to_string()), Some(signed.clone())))
+            .unwrap();
+        log.record(submission(
+            &Err(anyhow!("rate limited")),
+            Some(signed.clone()),
+        ))
+        .unwrap();
+        log.record(submission(&Ok("mock-1".to_string()), None))
+            .unwrap();
+        let files = log_files(&path);
+        let summary = verify_signatures(&files, &attester.public_key()).unwrap();
+        assert_eq!(
+            summary,
+            SignatureSummary {
+                files: 1,
+                signed: 2,
+                unsigned: 1,
+            }
+        );
+        assert_eq!(
+            summary.to_string(),
+            "1 files: 2 signed submissions, 1 unsigned"
+        );
+
+        let other = Attester::from_seed([0x08; 32]);
+        let e = verify_signatures(&files, &other.public_key()).unwrap_err();
+        assert!(e.to_string().ends_with("audit.jsonl:1: invalid signature"));
+
+        // A valid attestation recorded with a submission from another trusted header.
+        let tampered = dir.path().join("tampered.jsonl");
+        let tampered_log = AuditLog::open(&tampered, DEFAULT_MAX_BYTES, DEFAULT_MAX_FILES).unwrap();
+        let forged = RequestInputs::new(Height(100), HeaderHash([0xcd; 32]), Height(500)).unwrap();
+        let mut forged_request = forged.proof_request(&target);
+        forged_request.correlation_id = request.correlation_id;
+        let accepted = Ok("mock-0".to_string());
+        let event = AuditEvent::submission(
+            forged.kind,
+            &forged_request,
+            "mock",
+            &accepted,
+            None,
+            Some(signed.clone()),
+        );
+        tampered_log.record(event).unwrap();
+        let e = verify_signatures(&log_files(&tampered), &attester.public_key()).unwrap_err();
+        assert!(e.to_string().ends_with(
+            "tampered.jsonl:1: the attestation is not of the submission it is recorded with"
+        ));
+
+        // A valid attestation recorded with another submission.
+        let mut moved = signed;
+        moved.attestation.target_block = 600;
+        let moved = attester.sign(moved.attestation);
+        log.record(submission(&Ok("mock-2".to_string()), Some(moved)))
+            .unwrap();
+        let e = verify_signatures(&files, &attester.public_key()).unwrap_err();
+        assert!(e.to_string().ends_with(
+            "audit.jsonl:4: the attestation is not of the submission it is recorded with"
+        ));
+    }
+
+    #[test]
+    fn test_rotation() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let event = |i: usize| AuditEvent::Fulfillment {
+            request_id: format!("mock-{}", i),
+            source: "callback".to_string(),
+            status: "relayed".to_string(),
+            proof_id: None,
+            tx_hash: None,
+            error: None,
+        };
+        let line_len = serde_json::to_string(&AuditEntry {
+            timestamp_ms: unix_timestamp_ms(),
+            event: event(0),
+        })
+        .unwrap()
+        .len() as u64
+            + 1;
+
+        // Two entries per file, keeping two rotated files.
+        let log = AuditLog::open(&path, 2 * line_len, 2).unwrap();
+        for i in 0..7 {
+            log.record(event(i)).unwrap();
+        }
+        let files = log_files(&path);
+        assert_eq!(
+            files,
+            [rotated_path(&path, 2), rotated_path(&path, 1), path.clone()]
+        );
+        // The oldest file, with the first two entries, was deleted.
+        assert_eq!(verify(&files).unwrap().fulfillments, 5);
+        let oldest: AuditEntry = serde_json::from_str(
+            fs::read_to_string(&files[0])
+                .unwrap()
+                .lines()
+                .next()
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(oldest.event, event(2));
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 1);
+    }
+
+    #[test]
+    fn test_reopen_keeps_timestamps_ordered() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("audit.jsonl");
+        let event = AuditEvent::Fulfillment {
+            request_id: "mock-0".to_string(),
+            source: "callback".to_string(),
+            status: "relayed".to_string(),
+            proof_id: None,
+            tx_hash: None,
+            error: None,
+        };
+        // An entry written before the clock stepped back an hour.
+        let ahead = unix_timestamp_ms() + 60 * 60 * 1000;
+        let line = serde_json::to_string(&AuditEntry {
+            timestamp_ms: ahead,
+            event: event.clone(),
+        })
+        .unwrap();
+        fs::write(&path, format!("{}\n", line)).unwrap();
+
+        let log = AuditLog::open(&path, DEFAULT_MAX_BYTES, 1).unwrap();
+        log.record(event.clone()).unwrap();
+        let files = log_files(&path);
+        assert_eq!(verify(&files).unwrap().fulfillments, 2);
+
+        // Right after a rotation, the last entry is in the rotated file.
+        fs::rename(&path, rotated_path(&path, 1)).unwrap();
+        let log = AuditLog::open(&path, DEFAULT_MAX_BYTES, 1).unwrap();
+        log.record(event).unwrap();
+        let last: AuditEntry =
+            serde_json::from_str(fs::read_to_string(&path).unwrap().trim_end()).unwrap();
+        assert_eq!(last.timestamp_ms, ahead);
+    }
+
+    #[test]
+    fn test_serde_verify_summary() {
+        let summary = VerifySummary {
+            files: 2,
+            submissions: 10,
+            fulfillments: 8,
+            head_updates: 7,
+            iterations: 12,
+        };
+        assert_snapshot(&summary, include_str!("fixtures/serde/verify_summary.json"));
+    }
+
+    #[test]
+    fn test_verify_rejects_invalid_logs() {
+        let dir = tempfile::tempdir().unwrap();
+        let entry = |timestamp_ms| {
+            serde_json::to_string(&AuditEntry {
+                timestamp_ms,
+                event: AuditEvent::Fulfillment {
+                    request_id: "mock-0".to_string(),
+                    source: "poll".to_string(),
+                    status: "failed".to_string(),
+                    proof_id: None,
+                    tx_hash: None,
+                    error: None,
+                },
+            })
+            .unwrap()
+        };
+
+        let path = dir.path().join("backwards.jsonl");
+        fs::write(&path, format!("{}\n{}\n", entry(2000), entry(1000))).unwrap();
+        let e = verify(&[path.clone()]).unwrap_err();
+        assert!(e
+            .to_string()
+            .ends_with("backwards.jsonl:2: timestamp 1000 is before the previous entry's 2000"));
+
+        let path = dir.path().join("truncated.jsonl");
+        let truncated = entry(1000);
+        fs::write(&path, &truncated[..truncated.len() / 2]).unwrap();
+        let e = verify(&[path]).unwrap_err();
+        assert!(e.to_string().contains("truncated.jsonl:1: invalid entry"));
+    }
+}
@@ -0,0 +1,358 @@
+//! A backend that fails over from a primary to a secondary backend.
+//!
+//! Every submission goes to the primary first, so the primary is preferred again as soon as it
+//! recovers. Only once all attempts to submit to the primary have failed before reaching it (see
+//! `failed_before_sending`) is the request submitted to the secondary, so a range is never
+//! submitted to both backends. Any other failure may have left the request with the primary, and
+//! is returned as is. The recent requests of both backends are listed for deduplication.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use alloy_primitives::B256;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use log::warn;
+
+use super::{
+    failed_before_sending, ProofBackend, ProofPayload, ProofRequest, RecentRequest, RequestKind,
+};
+use crate::platform::FulfillmentStatus;
+use crate::retry::RetryPolicy;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Served {
+    Primary,
+    Secondary,
+}
+
+pub struct FailoverBackend {
+    primary: Box<dyn ProofBackend>,
+    secondary: Box<dyn ProofBackend>,
+    /// The attempts to submit to the primary before failing over.
+    policy: RetryPolicy,
+    /// The backend that served each request submitted by this process.
+    served: Mutex<HashMap<String, Served>>,
+}
+
+impl FailoverBackend {
+    pub fn new(
+        primary: Box<dyn ProofBackend>,
+        secondary: Box<dyn ProofBackend>,
+        policy: RetryPolicy,
+    ) -> Self {
+        Self {
+            primary,
+            secondary,
+            policy,
+            served: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn backend(&self, served: Served) -> &dyn ProofBackend {
+        match served {
+            Served::Primary => self.primary.as_ref(),
+            Served::Secondary => self.secondary.as_ref(),
+        }
+    }
+
+    /// The backend that served a request, if it was submitted by this process.
+    fn served(&self, request_id: &str) -> Option<Served> {
+        self.served.lock().unwrap().get(request_id).copied()
+    }
+
+    async fn submit_to(
+        backend: &dyn ProofBackend,
+        kind: RequestKind,
+        request: &ProofRequest<'_>,
+    ) -> Result<String> {
+        match kind {
+            RequestKind::Step => backend.request_step(request).await,
+            RequestKind::Skip => backend.request_skip(request).await,
+            RequestKind::DataCommitment => backend.request_data_commitment(request).await,
+        }
+    }
+
+    async fn submit(&self, kind: RequestKind, request: &ProofRequest<'_>) -> Result<String> {
+        let mut attempt = 1;
+        let served = loop {
+            match Self::submit_to(self.primary.as_ref(), kind, request).await {
+                Ok(request_id) => break (request_id, Served::Primary),
+                // The primary may have accepted the request: submitting it again could prove the
+                // range twice.
+                Err(e) if !failed_before_sending(&e) => {
+                    return Err(e).with_context(|| {
+                        format!(
+                            "submission to {} failed, not failing over as it may have accepted it",
+                            self.primary.name()
+                        )
+                    })
+                }
+                Err(e) if attempt < self.policy.max_attempts => {
+                    let backoff = self.policy.backoff(attempt);
+                    warn!(
+                        "Submission to {} failed (attempt {}), retrying in {:?}: {:#}",
+                        self.primary.name(),
+                        attempt,
+                        backoff,
+                        e
+                    );
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    warn!(
+                        "Submission to {} failed after {} attempts, failing over to {}: {:#}",
+                        self.primary.name(),
+                        attempt,
+                        self.secondary.name(),
+                        e
+                    );
+                    let request_id = Self::submit_to(self.secondary.as_ref(), kind, request)
+                        .await
+                        .with_context(|| {
+                            format!("failover submission to {}", self.secondary.name())
+                        })?;
+                    break (request_id, Served::Secondary);
+                }
+            }
+        };
+        let (request_id, backend) = served;
+        self.served
+            .lock()
+            .unwrap()
+            .insert(request_id.clone(), backend);
+        Ok(request_id)
+    }
+}
+
+#[async_trait]
+impl ProofBackend for FailoverBackend {
+    fn name(&self) -> &str {
+        "failover"
+    }
+
+    fn served_by(&self, request_id: &str) -> Option<String> {
+        self.served(request_id)
+            .map(|served| self.backend(served).name().to_string())
+    }
+
+    async fn request_step(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.submit(RequestKind::Step, request).await
+    }
+
+    async fn request_skip(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.submit(RequestKind::Skip, request).await
+    }
+
+    async fn request_data_commitment(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.submit(RequestKind::DataCommitment, request).await
+    }
+
+    /// Requests submitted before a restart are looked up on the primary, then the secondary.
+    async fn status(&self, request_id: &str) -> Result<FulfillmentStatus> {
+        match self.served(request_id) {
+            Some(served) => self.backend(served).status(request_id).await,
+            None => match self.primary.status(request_id).await {
+                Ok(status) => Ok(status),
+                Err(_) => self.secondary.status(request_id).await,
+            },
+        }
+    }
+
+    /// The requests of both backends. Failing to list the primary's falls back to the
+    /// secondary's, which is also where the requests go while the primary is down.
+    async fn recent_requests(&self, function_id: B256) -> Result<Vec<RecentRequest>> {
+        let mut requests = match self.primary.recent_requests(function_id).await {
+            Ok(requests) => requests,
+            Err(e) => {
+                warn!(
+                    "Failed to list the requests of {}, listing {} only: {:#}",
+                    self.primary.name(),
+                    self.secondary.name(),
+                    e
+                );
+                Vec::new()
+            }
+        };
+        requests.extend(self.secondary.recent_requests(function_id).await?);
+        Ok(requests)
+    }
+
+    async fn request_cost(&self, request_id: &str) -> Result<Option<f64>> {
+        match self.served(request_id) {
+            Some(served) => self.backend(served).request_cost(request_id).await,
+            None => Ok(None),
+        }
+    }
+
+    /// The secondary proves with the same circuits, so the primary's digest is the one that counts.
+    async fn artifact_digest(&self, function_id: B256) -> Result<Option<B256>> {
+        self.primary.artifact_digest(function_id).await
+    }
+
+    async fn cancel(&self, request_id: &str) -> Result<bool> {
+        match self.served(request_id) {
+            Some(served) => self.backend(served).cancel(request_id).await,
+            None => Ok(false),
+        }
+    }
+
+    async fn proof(&self, request_id: &str) -> Result<Option<ProofPayload>> {
+        match self.served(request_id) {
+            Some(served) => self.backend(served).proof(request_id).await,
+            None => match self.primary.proof(request_id).await {
+                Ok(Some(proof)) => Ok(Some(proof)),
+                _ => self.secondary.proof(request_id).await,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use alloy_primitives::{Address, Bytes};
+
+    use super::*;
+    use crate::backend::mock::MockBackend;
+    use crate::target::RequestTarget;
+
+    #[tokio::test]
+    async fn test_failover() {
+        let target = RequestTarget {
+            address: Address::repeat_byte(1),
+            step_function_id: B256::repeat_byte(2),
+            skip_function_id: B256::repeat_byte(3),
+            ..RequestTarget::for_test(5)
+        };
+        let request = ProofRequest {
+            target: &target,
+            trusted_block: 10,
+            target_block: 20,
+            function_id: target.skip_function_id,
+            calldata: Bytes::new(),
+            input: Bytes::new(),
+            correlation_id: None,
+        };
+
+        let primary = Arc::new(MockBackend::with_name("primary"));
+        let secondary = Arc::new(MockBackend::with_name("secondary"));
+        primary.fail_chain(5);
+        let backend = FailoverBackend::new(
+            Box::new(primary.clone()),
+            Box::new(secondary.clone()),
+            RetryPolicy {
+                max_attempts: 3,
+                initial_backoff: Duration::ZERO,
+                max_backoff: Duration::ZERO,
+            },
+        );
+
+        // The primary is attempted three times, then the request lands on the secondary once.
+        let request_id = backend.request_skip(&request).await.unwrap();
+        assert!(primary.requests().is_empty());
+        assert_eq!(primary.submission_attempts(), 3);
+        assert_eq!(secondary.requests().len(), 1);
+        assert_eq!(secondary.requests()[0].request_id, request_id);
+        assert_eq!(backend.served_by(&request_id).as_deref(), Some("secondary"));
+        assert_eq!(
+            backend.status(&request_id).await.unwrap(),
+            FulfillmentStatus::Proving
+        );
+
+        // The primary is preferred again once it recovers.
+        primary.recover_chain(5);
+        let request_id = backend.request_step(&request).await.unwrap();
+        assert_eq!(primary.requests().len(), 1);
+        assert_eq!(secondary.requests().len(), 1);
+        assert_eq!(backend.served_by(&request_id).as_deref(), Some("primary"));
+
+        // A submission the primary may have accepted isn't retried, nor failed over.
+        primary.time_out_chain(5);
+        let attempts = primary.submission_attempts();
+        let error = backend.request_step(&request).await.unwrap_err();
+        assert!(format!("{:#}", error).contains("timed out"), "{:#}", error);
+        assert_eq!(primary.submission_attempts(), attempts + 1);
+        assert_eq!(primary.requests().len(), 2);
+        assert_eq!(secondary.requests().len(), 1);
+    }
+
+    /// A backend that can only list its recent requests, or fail to if it has none.
+    struct Listing(Option<Vec<RecentRequest>>);
+
+    #[async_trait]
+    impl ProofBackend for Listing {
+        fn name(&self) -> &str {
+            "listing"
+        }
+
+        async fn request_step(&self, _request: &ProofRequest<'_>) -> Result<String> {
+            Err(anyhow::anyhow!("listing only"))
+        }
+
+        async fn request_skip(&self, _request: &ProofRequest<'_>) -> Result<String> {
+            Err(anyhow::anyhow!("listing only"))
+        }
+
+        async fn status(&self, _request_id: &str) -> Result<FulfillmentStatus> {
+            Err(anyhow::anyhow!("listing only"))
+        }
+
+        async fn recent_requests(&self, _function_id: B256) -> Result<Vec<RecentRequest>> {
+            self.0
+                .clone()
+                .ok_or_else(|| anyhow::anyhow!("listing failed"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_recent_requests_fall_back_to_secondary() {
+        let listed = RecentRequest {
+            request_id: "secondary-1".to_string(),
+            chain_id: 5,
+            address: Address::repeat_byte(1),
+            function_id: B256::repeat_byte(3),
+            input: Bytes::new(),
+            status: FulfillmentStatus::Proving,
+        };
+        let policy = RetryPolicy::default();
+        let backend = FailoverBackend::new(
+            Box::new(Listing(None)),
+            Box::new(Listing(Some(vec![listed.clone()]))),
+            policy.clone(),
+        );
+        let function_id = B256::repeat_byte(3);
+        assert_eq!(
+            backend.recent_requests(function_id).await.unwrap(),
+            vec![listed.clone()]
+        );
+
+        // Both listings when both succeed, and a failure when the secondary fails.
+        let backend = FailoverBackend::new(
+            Box::new(Listing(Some(vec![listed.clone()]))),
+            Box::new(Listing(Some(vec![listed.clone()]))),
+            policy.clone(),
+        );
+        assert_eq!(backend.recent_requests(function_id).await.unwrap().len(), 2);
+        let backend = FailoverBackend::new(
+            Box::new(Listing(Some(vec![listed]))),
+            Box::new(Listing(None)),
+            policy,
+        );
+        assert!(backend.recent_requests(function_id).await.is_err());
+    }
+
+    #[test]
+    fn test_failed_before_sending() {
+        let refused = std::io::Error::from(std::io::ErrorKind::ConnectionRefused);
+        assert!(failed_before_sending(
+            &anyhow::Error::new(refused).context("submission")
+        ));
+        let timed_out = std::io::Error::from(std::io::ErrorKind::TimedOut);
+        assert!(!failed_before_sending(&anyhow::Error::new(timed_out)));
+        assert!(!failed_before_sending(&anyhow::anyhow!("HTTP 500")));
+    }
+}
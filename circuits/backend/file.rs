@@ -0,0 +1,158 @@
+//! A backend that writes the inputs of each request to disk instead of proving them.
+//!
+//! Each request is written to `<dir>/<request_id>.json`. A request is `Proving` until a proof is
+//! placed next to it at `<dir>/<request_id>.proof`, e.g. by an external prover.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+use super::{ProofBackend, ProofRequest, RequestKind};
+use crate::platform::FulfillmentStatus;
+
+/// The contents of a request file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestFile {
+    pub kind: String,
+    pub chain_id: u32,
+    pub address: String,
+    pub trusted_block: u64,
+    pub target_block: u64,
+    pub function_id: String,
+    /// The callback calldata, as 0x-prefixed hex.
+    pub calldata: String,
+    /// The packed circuit input, as 0x-prefixed hex.
+    pub input: String,
+    /// The attempt the request is part of, if the operator planned it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub correlation_id: Option<String>,
+}
+
+pub struct FileBackend {
+    dir: PathBuf,
+}
+
+impl FileBackend {
+    /// Write requests to `dir`, creating it if needed.
+    pub fn new(dir: impl AsRef<Path>) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create request directory {}", dir.display()))?;
+        Ok(Self { dir })
+    }
+
+    fn request_path(&self, request_id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", request_id))
+    }
+
+    fn proof_path(&self, request_id: &str) -> PathBuf {
+        self.dir.join(format!("{}.proof", request_id))
+    }
+
+    fn write(&self, kind: RequestKind, request: &ProofRequest<'_>) -> Result<String> {
+        let request_id = format!(
+            "{}-{}-{}-{}-{}",
+            kind,
+            request.target.chain_id,
+            request.target.address,
+            request.trusted_block,
+            request.target_block
+        );
+        let file = RequestFile {
+            kind: kind.to_string(),
+            chain_id: request.target.chain_id,
+            address: request.target.address.to_string(),
+            trusted_block: request.trusted_block,
+            target_block: request.target_block,
+            function_id: request.function_id.to_string(),
+            calldata: request.calldata.to_string(),
+            input: request.input.to_string(),
+            correlation_id: request.correlation_id.map(|id| id.to_string()),
+        };
+        let path = self.request_path(&request_id);
+        fs::write(&path, serde_json::to_string_pretty(&file)?)
+            .with_context(|| format!("failed to write request to {}", path.display()))?;
+        Ok(request_id)
+    }
+}
+
+#[async_trait]
+impl ProofBackend for FileBackend {
+    fn name(&self) -> &str {
+        "file"
+    }
+
+    async fn request_step(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.write(RequestKind::Step, request)
+    }
+
+    async fn request_skip(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.write(RequestKind::Skip, request)
+    }
+
+    async fn request_data_commitment(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.write(RequestKind::DataCommitment, request)
+    }
+
+    async fn status(&self, request_id: &str) -> Result<FulfillmentStatus> {
+        if !self.request_path(request_id).exists() {
+            return Err(anyhow!("unknown request {}", request_id));
+        }
+        let proof_path = self.proof_path(request_id);
+        if proof_path.exists() {
+            Ok(FulfillmentStatus::Proved {
+                proof_id: Some(proof_path.display().to_string()),
+            })
+        } else {
+            Ok(FulfillmentStatus::Proving)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::Bytes;
+
+    use super::*;
+    use crate::target::RequestTarget;
+
+    #[tokio::test]
+    async fn test_file_backend() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = FileBackend::new(dir.path().join("requests")).unwrap();
+        let target = RequestTarget::for_test(5);
+        let request = ProofRequest {
+            target: &target,
+            trusted_block: 10,
+            target_block: 11,
+            function_id: target.step_function_id,
+            calldata: Bytes::from_static(&[0xab]),
+            input: Bytes::from_static(&[0xcd, 0xef]),
+            correlation_id: None,
+        };
+
+        let request_id = backend.request_step(&request).await.unwrap();
+        let file: RequestFile =
+            serde_json::from_str(&fs::read_to_string(backend.request_path(&request_id)).unwrap())
+                .unwrap();
+        assert_eq!(file.kind, "step");
+        assert_eq!(file.trusted_block, 10);
+        assert_eq!(file.target_block, 11);
+        assert_eq!(file.calldata, "0xab");
+        assert_eq!(file.input, "0xcdef");
+
+        assert_eq!(
+            backend.status(&request_id).await.unwrap(),
+            FulfillmentStatus::Proving
+        );
+        fs::write(backend.proof_path(&request_id), b"proof").unwrap();
+        assert!(matches!(
+            backend.status(&request_id).await.unwrap(),
+            FulfillmentStatus::Proved { .. }
+        ));
+        assert!(backend.status("missing").await.is_err());
+    }
+}
@@ -0,0 +1,922 @@
+//! A backend for custom relayer services that front the prover with their own HTTP API.
+//!
+//! The service is described by an `HttpBackendConfig`, read from a JSON file. A submission POSTs a
+//! JSON object with the function ID, the packed input and the callback calldata as hex, and the
+//! target contract, each at the field the config names (dotted names nest, e.g. `payload.input`),
+//! and reads the ticket ID at a field of the response. The status of a ticket is read from
+//! `status_path`, with `{id}` replaced by the ticket ID, and its state, at another field, is mapped
+//! to a `FulfillmentStatus` by the config's lists of states. Every call carries the bearer token,
+//! if one is set.
+//!
+//! Submissions that fail on a connection error, a 429 or a 503 response are retried as the
+//! config's retry policy says, waiting for the service's `Retry-After` if it is longer. Other
+//! errors may come after the service accepted the request, so retrying them could submit it twice.
+//! The
+//! operator's limit on submissions (`MAX_REQUESTS_PER_HOUR`) and its resubmission of failed
+//! requests apply as for any backend.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{anyhow, ensure, Context, Result};
+use async_trait::async_trait;
+use log::warn;
+use reqwest::header::RETRY_AFTER;
+use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use super::{ProofBackend, ProofRequest, RequestKind};
+use crate::platform::{ApiKey, FulfillmentStatus};
+use crate::retry::RetryPolicy;
+
+/// The timeout of a single call to the service.
+const CALL_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The number of characters of an error response kept in the error.
+const MAX_ERROR_BODY: usize = 200;
+
+/// Where the values of a submission go. The optional fields are left out unless named.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestFields {
+    #[serde(rename = "function_id", default = "default_function_id_field")]
+    pub function_id: String,
+    /// The packed circuit input, as 0x-prefixed hex.
+    #[serde(rename = "input", default = "default_input_field")]
+    pub input: String,
+    /// The calldata of the callback on the target contract, as 0x-prefixed hex.
+    #[serde(rename = "calldata", default = "default_calldata_field")]
+    pub calldata: String,
+    /// The target contract, as `{"chain_id": .., "address": ..}`.
+    #[serde(rename = "target", default = "default_target_field")]
+    pub target: String,
+    /// The kind of request: `step`, `skip` or `data_commitment`.
+    #[serde(rename = "kind", default)]
+    pub kind: Option<String>,
+    #[serde(rename = "trusted_block", default)]
+    pub trusted_block: Option<String>,
+    #[serde(rename = "target_block", default)]
+    pub target_block: Option<String>,
+    #[serde(rename = "correlation_id", default)]
+    pub correlation_id: Option<String>,
+    /// Fields sent as is with every submission, e.g. the service's name for the program.
+    #[serde(rename = "constants", default)]
+    pub constants: BTreeMap<String, Value>,
+}
+
+impl Default for RequestFields {
+    fn default() -> Self {
+        Self {
+            function_id: default_function_id_field(),
+            input: default_input_field(),
+            calldata: default_calldata_field(),
+            target: default_target_field(),
+            kind: None,
+            trusted_block: None,
+            target_block: None,
+            correlation_id: None,
+            constants: BTreeMap::new(),
+        }
+    }
+}
+
+fn default_function_id_field() -> String {
+    "function_id".to_string()
+}
+
+fn default_input_field() -> String {
+    "input_hex".to_string()
+}
+
+fn default_calldata_field() -> String {
+    "calldata_hex".to_string()
+}
+
+fn default_target_field() -> String {
+    "target".to_string()
+}
+
+/// Where the values of the service's responses are read from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResponseFields {
+    /// The ticket ID in a submission response, a string or a number.
+    #[serde(rename = "ticket_id", default = "default_ticket_id_field")]
+    pub ticket_id: String,
+    /// The state in a status response.
+    #[serde(rename = "state", default = "default_state_field")]
+    pub state: String,
+    /// The hash of the relaying transaction in a status response, if the service reports it.
+    #[serde(rename = "tx_hash", default)]
+    pub tx_hash: Option<String>,
+    #[serde(rename = "proof_id", default)]
+    pub proof_id: Option<String>,
+    /// The error message of a failed ticket in a status response.
+    #[serde(rename = "error", default)]
+    pub error: Option<String>,
+}
+
+impl Default for ResponseFields {
+    fn default() -> Self {
+        Self {
+            ticket_id: default_ticket_id_field(),
+            state: default_state_field(),
+            tx_hash: None,
+            proof_id: None,
+            error: None,
+        }
+    }
+}
+
+fn default_ticket_id_field() -> String {
+    "ticket_id".to_string()
+}
+
+fn default_state_field() -> String {
+    "status".to_string()
+}
+
+/// The states of the service that map to each fulfillment status, compared case-insensitively. A
+/// state in none of them is logged and taken as proving.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StateMapping {
+    #[serde(rename = "proving", default = "default_proving_states")]
+    pub proving: Vec<String>,
+    #[serde(rename = "proved", default = "default_proved_states")]
+    pub proved: Vec<String>,
+    #[serde(rename = "relayed", default = "default_relayed_states")]
+    pub relayed: Vec<String>,
+    #[serde(rename = "failed", default = "default_failed_states")]
+    pub failed: Vec<String>,
+}
+
+impl Default for StateMapping {
+    fn default() -> Self {
+        Self {
+            proving: default_proving_states(),
+            proved: default_proved_states(),
+            relayed: default_relayed_states(),
+            failed: default_failed_states(),
+        }
+    }
+}
+
+fn states(states: &[&str]) -> Vec<String> {
+    states.iter().map(|state| state.to_string()).collect()
+}
+
+fn default_proving_states() -> Vec<String> {
+    states(&["pending", "queued", "running", "proving"])
+}
+
+fn default_proved_states() -> Vec<String> {
+    states(&["proved"])
+}
+
+fn default_relayed_states() -> Vec<String> {
+    states(&["relayed", "fulfilled"])
+}
+
+fn default_failed_states() -> Vec<String> {
+    states(&["failed", "error"])
+}
+
+/// How submissions are retried by default: for about half a minute.
+fn default_retry_policy() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 4,
+        initial_backoff: Duration::from_secs(2),
+        max_backoff: Duration::from_secs(15),
+    }
+}
+
+/// The API of a custom relayer service.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HttpBackendConfig {
+    /// The base URL of the service.
+    #[serde(rename = "url")]
+    pub url: String,
+    /// The path submissions are POSTed to.
+    #[serde(rename = "submit_path", default = "default_submit_path")]
+    pub submit_path: String,
+    /// The path of the status of a ticket, in which `{id}` is the ticket ID.
+    #[serde(rename = "status_path", default = "default_status_path")]
+    pub status_path: String,
+    #[serde(rename = "request", default)]
+    pub request: RequestFields,
+    #[serde(rename = "response", default)]
+    pub response: ResponseFields,
+    #[serde(rename = "states", default)]
+    pub states: StateMapping,
+    /// How submissions failing on a connection error, a 429 or a 503 are retried.
+    #[serde(rename = "retry", default = "default_retry_policy")]
+    pub retry: RetryPolicy,
+}
+
+fn default_submit_path() -> String {
+    "/requests".to_string()
+}
+
+fn default_status_path() -> String {
+    "/requests/{id}".to_string()
+}
+
+impl HttpBackendConfig {
+    /// The config of the service at `url`, with the default paths, fields and states.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            submit_path: default_submit_path(),
+            status_path: default_status_path(),
+            request: RequestFields::default(),
+            response: ResponseFields::default(),
+            states: StateMapping::default(),
+            retry: default_retry_policy(),
+        }
+    }
+
+    /// Read the config in the JSON file at `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let config: Self = serde_json::from_str(&json)
+            .with_context(|| format!("invalid HTTP backend config {}", path.display()))?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        reqwest::Url::parse(&self.url)
+            .with_context(|| format!("invalid HTTP backend URL {:?}", self.url))?;
+        ensure!(
+            self.status_path.contains("{id}"),
+            "the status path {:?} has no {{id}}",
+            self.status_path
+        );
+        ensure!(
+            self.retry.max_attempts > 0,
+            "the HTTP backend must make at least one attempt"
+        );
+        let mapping = &self.states;
+        let mut seen = BTreeMap::new();
+        for (status, states) in [
+            ("proving", &mapping.proving),
+            ("proved", &mapping.proved),
+            ("relayed", &mapping.relayed),
+            ("failed", &mapping.failed),
+        ] {
+            for state in states {
+                if let Some(other) = seen.insert(state.to_ascii_lowercase(), status) {
+                    return Err(anyhow!(
+                        "the state {:?} maps to both {} and {}",
+                        state,
+                        other,
+                        status
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// A failed call to the service, and whether it may succeed if retried.
+struct CallError {
+    error: anyhow::Error,
+    retriable: bool,
+    /// How long the service asked to wait before retrying, if it did.
+    retry_after: Option<Duration>,
+}
+
+impl CallError {
+    fn fatal(error: anyhow::Error) -> Self {
+        Self {
+            error,
+            retriable: false,
+            retry_after: None,
+        }
+    }
+}
+
+pub struct HttpBackend {
+    http: reqwest::Client,
+    config: HttpBackendConfig,
+    token: Option<ApiKey>,
+}
+
+impl HttpBackend {
+    /// Submit to the service `config` describes, authenticated with the bearer `token` if set.
+    pub fn new(mut config: HttpBackendConfig, token: Option<String>) -> Result<Self> {
+        config.validate()?;
+        config.url = config.url.trim_end_matches('/').to_string();
+        let http = reqwest::Client::builder().timeout(CALL_TIMEOUT).build()?;
+        Ok(Self {
+            http,
+            config,
+            token: token.map(ApiKey::new),
+        })
+    }
+
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.token {
+            Some(token) => request.bearer_auth(token.expose()),
+            None => request,
+        }
+    }
+
+    fn url(&self, path: &str) -> String {
+        match path.starts_with('/') {
+            true => format!("{}{}", self.config.url, path),
+            false => format!("{}/{}", self.config.url, path),
+        }
+    }
+
+    /// The body of the submission of the `kind` request `request`.
+    pub fn body(&self, kind: RequestKind, request: &ProofRequest<'_>) -> Value {
+        let fields = &self.config.request;
+        let mut body = Value::Object(Map::new());
+        for (field, value) in &fields.constants {
+            set_field(&mut body, field, value.clone());
+        }
+        let target = serde_json::json!({
+            "chain_id": request.target.chain_id,
+            "address": request.target.address.to_string(),
+        });
+        set_field(
+            &mut body,
+            &fields.function_id,
+            request.function_id.to_string().into(),
+        );
+        set_field(&mut body, &fields.input, request.input.to_string().into());
+        set_field(
+            &mut body,
+            &fields.calldata,
+            request.calldata.to_string().into(),
+        );
+        set_field(&mut body, &fields.target, target);
+        let optional = [
+            (&fields.kind, Value::from(kind.to_string())),
+            (&fields.trusted_block, Value::from(request.trusted_block)),
+            (&fields.target_block, Value::from(request.target_block)),
+            (
+                &fields.correlation_id,
+                request.correlation_id.map(|id| id.to_string()).into(),
+            ),
+        ];
+        for (field, value) in optional {
+            if let Some(field) = field {
+                set_field(&mut body, field, value);
+            }
+        }
+        body
+    }
+
+    async fn submit(&self, kind: RequestKind, request: &ProofRequest<'_>) -> Result<String> {
+        let body = self.body(kind, request);
+        let url = self.url(&self.config.submit_path);
+        let policy = &self.config.retry;
+        let mut attempt = 1;
+        let response = loop {
+            let sent = self
+                .authorize(self.http.post(&url))
+                .json(&body)
+                .send()
+                .await;
+            match check(sent).await {
+                Ok(response) => break response,
+                Err(e) if e.retriable && attempt < policy.max_attempts => {
+                    let backoff = policy
+                        .backoff(attempt)
+                        .max(e.retry_after.unwrap_or_default().min(policy.max_backoff));
+                    warn!(
+                        "Failed to submit {} request to {} (attempt {}), retrying in {:?}: {:#}",
+                        kind, url, attempt, backoff, e.error
+                    );
+                    tokio::time::sleep(backoff).await;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    return Err(e.error).with_context(|| {
+                        format!(
+                            "failed to submit {} request to {} after {} attempts",
+                            kind, url, attempt
+                        )
+                    })
+                }
+            }
+        };
+        let response: Value =
+            serde_json::from_str(&response).context("failed to parse submission response")?;
+        let field = &self.config.response.ticket_id;
+        match field_value(&response, field) {
+            Some(Value::String(id)) if !id.is_empty() => Ok(id.clone()),
+            Some(Value::Number(id)) => Ok(id.to_string()),
+            _ => Err(anyhow!(
+                "the submission response has no ticket ID at {}",
+                field
+            )),
+        }
+    }
+
+    /// The fulfillment status of the status response `response`.
+    pub fn parse_status(&self, response: &Value) -> Result<FulfillmentStatus> {
+        let fields = &self.config.response;
+        let state = field_value(response, &fields.state)
+            .and_then(Value::as_str)
+            .ok_or_else(|| anyhow!("the status response has no state at {}", fields.state))?;
+        let string = |field: &Option<String>| {
+            let value = field_value(response, field.as_deref()?)?;
+            match value {
+                Value::String(value) => Some(value.clone()),
+                Value::Null => None,
+                value => Some(value.to_string()),
+            }
+        };
+        let is = |states: &[String]| states.iter().any(|s| s.eq_ignore_ascii_case(state));
+        let mapping = &self.config.states;
+        let status = if is(&mapping.proving) {
+            FulfillmentStatus::Proving
+        } else if is(&mapping.proved) {
+            FulfillmentStatus::Proved {
+                proof_id: string(&fields.proof_id),
+            }
+        } else if is(&mapping.relayed) {
+            FulfillmentStatus::Relayed {
+                proof_id: string(&fields.proof_id),
+                tx_hash: string(&fields.tx_hash),
+            }
+        } else if is(&mapping.failed) {
+            FulfillmentStatus::Failed {
+                error: string(&fields.error),
+            }
+        } else {
+            warn!("Unknown state {:?} of the HTTP backend", state);
+            FulfillmentStatus::Proving
+        };
+        Ok(status)
+    }
+}
+
+/// The body of the successful response `sent`, or why it failed.
+async fn check(sent: reqwest::Result<reqwest::Response>) -> Result<String, CallError> {
+    let response = match sent {
+        Ok(response) => response,
+        // Only a request that never reached the service, or that it turned away, is surely safe to
+        // send again.
+        Err(e) => {
+            return Err(CallError {
+                retriable: e.is_connect(),
+                error: e.into(),
+                retry_after: None,
+            })
+        }
+    };
+    let status = response.status();
+    if status.is_success() {
+        return response
+            .text()
+            .await
+            .map_err(|e| CallError::fatal(e.into()));
+    }
+    let retry_after = response
+        .headers()
+        .get(RETRY_AFTER)
+        .and_then(|value| value.to_str().ok()?.trim().parse().ok())
+        .map(Duration::from_secs);
+    let body = response.text().await.unwrap_or_default();
+    let body: String = body.chars().take(MAX_ERROR_BODY).collect();
+    Err(CallError {
+        error: anyhow!("HTTP {}: {}", status, body.trim()),
+        retriable: status == StatusCode::TOO_MANY_REQUESTS
+            || status == StatusCode::SERVICE_UNAVAILABLE,
+        retry_after,
+    })
+}
+
+/// `segment` percent-encoded as a segment of a URL path.
+fn path_segment(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => {
+                encoded.push(byte as char)
+            }
+            byte => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// The value at the dotted `field` of `value`, if any.
+fn field_value<'a>(value: &'a Value, field: &str) -> Option<&'a Value> {
+    field
+        .split('.')
+        .try_fold(value, |value, name| value.as_object()?.get(name))
+}
+
+/// Set the dotted `field` of `object` to `value`, creating the objects on the way.
+fn set_field(object: &mut Value, field: &str, value: Value) {
+    let mut names = field.split('.').peekable();
+    let mut object = object;
+    while let Some(name) = names.next() {
+        if !object.is_object() {
+            *object = Value::Object(Map::new());
+        }
+        let fields = object.as_object_mut().unwrap();
+        if names.peek().is_none() {
+            fields.insert(name.to_string(), value);
+            return;
+        }
+        object = fields
+            .entry(name.to_string())
+            .or_insert_with(|| Value::Object(Map::new()));
+    }
+}
+
+#[async_trait]
+impl ProofBackend for HttpBackend {
+    fn name(&self) -> &str {
+        "http"
+    }
+
+    async fn request_step(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.submit(RequestKind::Step, request).await
+    }
+
+    async fn request_skip(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.submit(RequestKind::Skip, request).await
+    }
+
+    async fn request_data_commitment(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.submit(RequestKind::DataCommitment, request).await
+    }
+
+    async fn status(&self, request_id: &str) -> Result<FulfillmentStatus> {
+        let path = self
+            .config
+            .status_path
+            .replace("{id}", &path_segment(request_id));
+        let url = self.url(&path);
+        let sent = self.authorize(self.http.get(&url)).send().await;
+        let body = check(sent)
+            .await
+            .map_err(|e| e.error)
+            .with_context(|| format!("failed to query status of ticket {}", request_id))?;
+        let response: Value =
+            serde_json::from_str(&body).context("failed to parse the status response")?;
+        self.parse_status(&response)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::convert::Infallible;
+    use std::sync::{Arc, Mutex};
+
+    use alloy_primitives::Bytes;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response, Server};
+    use serde_json::json;
+
+    use super::*;
+    use crate::target::RequestTarget;
+
+    /// A call received by the mock service.
+    #[derive(Debug, Clone)]
+    struct Call {
+        method: String,
+        path: String,
+        authorization: Option<String>,
+        body: Option<Value>,
+    }
+
+    /// A mock service answering with `responses` in turn, and the calls it received. Once the
+    /// responses run out, answers 500.
+    fn mock_service(responses: Vec<(u16, &str)>) -> (String, Arc<Mutex<Vec<Call>>>) {
+        let responses: VecDeque<_> = responses
+            .into_iter()
+            .map(|(status, body)| (status, body.to_string()))
+            .collect();
+        let responses = Arc::new(Mutex::new(responses));
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let recorded = calls.clone();
+        let make_service = make_service_fn(move |_| {
+            let (responses, recorded) = (responses.clone(), recorded.clone());
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let (responses, recorded) = (responses.clone(), recorded.clone());
+                    async move {
+                        let method = request.method().to_string();
+                        let path = request.uri().path().to_string();
+                        let authorization = request
+                            .headers()
+                            .get("authorization")
+                            .map(|value| value.to_str().unwrap().to_string());
+                        let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                        recorded.lock().unwrap().push(Call {
+                            method,
+                            path,
+                            authorization,
+                            body: serde_json::from_slice(&body).ok(),
+                        });
+                        let (status, body) = responses
+                            .lock()
+                            .unwrap()
+                            .pop_front()
+                            .unwrap_or((500, "no more responses".to_string()));
+                        let response = Response::builder()
+                            .status(status)
+                            .header("retry-after", "0")
+                            .body(Body::from(body))
+                            .unwrap();
+                        Ok::<_, Infallible>(response)
+                    }
+                }))
+            }
+        });
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        listener.set_nonblocking(true).unwrap();
+        tokio::spawn(Server::from_tcp(listener).unwrap().serve(make_service));
+        (format!("http://{}/", addr), calls)
+    }
+
+    fn request(target: &RequestTarget) -> ProofRequest<'_> {
+        ProofRequest {
+            target,
+            trusted_block: 100,
+            target_block: 200,
+            function_id: target.skip_function_id,
+            calldata: Bytes::from_static(&[1, 2]),
+            input: Bytes::from_static(&[3, 4]),
+            correlation_id: Some("01HF3B8Y5ZQK4V9C2T7N6M1R0S".parse().unwrap()),
+        }
+    }
+
+    fn fast_retries(mut config: HttpBackendConfig) -> HttpBackendConfig {
+        config.retry = RetryPolicy {
+            max_attempts: 3,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(10),
+        };
+        config
+    }
+
+    #[tokio::test]
+    async fn test_default_submission() {
+        let (url, calls) = mock_service(vec![(200, r#"{"ticket_id": "t-1"}"#)]);
+        let backend = HttpBackend::new(HttpBackendConfig::new(url), Some("secret".into())).unwrap();
+        let target = RequestTarget::for_test(5);
+        let ticket = backend.request_skip(&request(&target)).await.unwrap();
+        assert_eq!(ticket, "t-1");
+
+        let calls = calls.lock().unwrap().clone();
+        assert_eq!(calls.len(), 1);
+        assert_eq!(
+            (calls[0].method.as_str(), calls[0].path.as_str()),
+            ("POST", "/requests")
+        );
+        assert_eq!(calls[0].authorization.as_deref(), Some("Bearer secret"));
+        assert_eq!(
+            calls[0].body,
+            Some(json!({
+                "function_id": format!("0x{}", "33".repeat(32)),
+                "input_hex": "0x0304",
+                "calldata_hex": "0x0102",
+                "target": {"chain_id": 5, "address": format!("0x{}", "11".repeat(20))},
+            }))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_templated_submission() {
+        let (url, calls) = mock_service(vec![(201, r#"{"data": {"ticket": 42}}"#)]);
+        let mut config = HttpBackendConfig::new(url);
+        config.submit_path = "/v2/proofs".to_string();
+        config.request = RequestFields {
+            function_id: "circuit".to_string(),
+            input: "payload.input".to_string(),
+            calldata: "payload.callback".to_string(),
+            target: "destination".to_string(),
+            kind: Some("payload.kind".to_string()),
+            trusted_block: None,
+            target_block: Some("height".to_string()),
+            correlation_id: Some("trace_id".to_string()),
+            constants: BTreeMap::from([("program".to_string(), json!("tendermintx"))]),
+        };
+        config.response.ticket_id = "data.ticket".to_string();
+        let backend = HttpBackend::new(config, None).unwrap();
+        let target = RequestTarget::for_test(5);
+        let ticket = backend.request_skip(&request(&target)).await.unwrap();
+        assert_eq!(ticket, "42");
+
+        let calls = calls.lock().unwrap().clone();
+        assert_eq!(calls[0].path, "/v2/proofs");
+        assert_eq!(calls[0].authorization, None);
+        assert_eq!(
+            calls[0].body,
+            Some(json!({
+                "program": "tendermintx",
+                "circuit": format!("0x{}", "33".repeat(32)),
+                "payload": {"input": "0x0304", "callback": "0x0102", "kind": "skip"},
+                "destination": {"chain_id": 5, "address": format!("0x{}", "11".repeat(20))},
+                "height": 200,
+                "trace_id": "01HF3B8Y5ZQK4V9C2T7N6M1R0S",
+            }))
+        );
+
+        // A response without a ticket ID fails the submission.
+        let (url, _) = mock_service(vec![(200, r#"{"ticket_id": "t-1"}"#)]);
+        let mut config = HttpBackendConfig::new(url);
+        config.response.ticket_id = "id".to_string();
+        let backend = HttpBackend::new(config, None).unwrap();
+        let error = backend.request_step(&request(&target)).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the submission response has no ticket ID at id"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_submission_retries() {
+        // Overloaded, then rate limited, then accepted.
+        let responses = vec![
+            (503, "overloaded"),
+            (429, "slow down"),
+            (200, r#"{"ticket_id": "t-1"}"#),
+        ];
+        let (url, calls) = mock_service(responses);
+        let config = fast_retries(HttpBackendConfig::new(url));
+        let backend = HttpBackend::new(config, None).unwrap();
+        let target = RequestTarget::for_test(5);
+        assert_eq!(
+            backend.request_skip(&request(&target)).await.unwrap(),
+            "t-1"
+        );
+        assert_eq!(calls.lock().unwrap().len(), 3);
+
+        // Until the attempts run out.
+        let (url, calls) = mock_service(vec![(503, "overloaded"); 3]);
+        let config = fast_retries(HttpBackendConfig::new(url.clone()));
+        let backend = HttpBackend::new(config, None).unwrap();
+        let error = backend.request_skip(&request(&target)).await.unwrap_err();
+        assert_eq!(
+            format!("{:#}", error),
+            format!(
+                "failed to submit skip request to {}requests after 3 attempts: HTTP 503 \
+                 Service Unavailable: overloaded",
+                url
+            )
+        );
+        assert_eq!(calls.lock().unwrap().len(), 3);
+
+        // The service may have queued the request before failing, so sending it again could
+        // request the proof twice.
+        for status in [500, 502, 504] {
+            let (url, calls) = mock_service(vec![(status, "failed")]);
+            let config = fast_retries(HttpBackendConfig::new(url));
+            let backend = HttpBackend::new(config, None).unwrap();
+            assert!(backend.request_skip(&request(&target)).await.is_err());
+            assert_eq!(calls.lock().unwrap().len(), 1, "{}", status);
+        }
+
+        // A rejected request isn't retried.
+        let (url, calls) = mock_service(vec![(400, "bad input")]);
+        let backend = HttpBackend::new(fast_retries(HttpBackendConfig::new(url)), None).unwrap();
+        let error = backend.request_skip(&request(&target)).await.unwrap_err();
+        assert!(format!("{:#}", error).ends_with("HTTP 400 Bad Request: bad input"));
+        assert_eq!(calls.lock().unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_status_mapping() {
+        let responses = vec![
+            (200, r#"{"job": {"state": "QUEUED"}}"#),
+            (200, r#"{"job": {"state": "done", "tx": "0xabc"}}"#),
+            (
+                200,
+                r#"{"job": {"state": "crashed", "reason": "out of memory"}}"#,
+            ),
+            (200, r#"{"job": {"state": "proof_ready", "proof": 7}}"#),
+            (200, r#"{"job": {"state": "paused"}}"#),
+            (200, r#"{"job": {}}"#),
+            (404, "no such ticket"),
+        ];
+        let (url, calls) = mock_service(responses);
+        let mut config = HttpBackendConfig::new(url);
+        config.status_path = "/tickets/{id}/status".to_string();
+        config.response = ResponseFields {
+            ticket_id: "ticket_id".to_string(),
+            state: "job.state".to_string(),
+            tx_hash: Some("job.tx".to_string()),
+            proof_id: Some("job.proof".to_string()),
+            error: Some("job.reason".to_string()),
+        };
+        config.states = StateMapping {
+            proving: states(&["queued"]),
+            proved: states(&["proof_ready"]),
+            relayed: states(&["done"]),
+            failed: states(&["crashed"]),
+        };
+        let backend = HttpBackend::new(config, Some("secret".into())).unwrap();
+
+        assert_eq!(
+            backend.status("t-1").await.unwrap(),
+            FulfillmentStatus::Proving
+        );
+        assert_eq!(
+            backend.status("t-1").await.unwrap(),
+            FulfillmentStatus::Relayed {
+                proof_id: None,
+                tx_hash: Some("0xabc".to_string()),
+            }
+        );
+        assert_eq!(
+            backend.status("t-1").await.unwrap(),
+            FulfillmentStatus::Failed {
+                error: Some("out of memory".to_string()),
+            }
+        );
+        assert_eq!(
+            backend.status("t-1").await.unwrap(),
+            FulfillmentStatus::Proved {
+                proof_id: Some("7".to_string()),
+            }
+        );
+        // An unknown state is still proving.
+        assert_eq!(
+            backend.status("t-1").await.unwrap(),
+            FulfillmentStatus::Proving
+        );
+        let error = backend.status("t-1").await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the status response has no state at job.state"
+        );
+        let error = backend.status("t-1").await.unwrap_err();
+        assert_eq!(
+            format!("{:#}", error),
+            "failed to query status of ticket t-1: HTTP 404 Not Found: no such ticket"
+        );
+
+        let calls = calls.lock().unwrap().clone();
+        assert!(calls.iter().all(|call| call.method == "GET"
+            && call.path == "/tickets/t-1/status"
+            && call.authorization.as_deref() == Some("Bearer secret")));
+
+        // The ticket ID is a single segment of the path, whatever it holds.
+        let (url, calls) = mock_service(vec![(200, r#"{"status": "queued"}"#)]);
+        let backend = HttpBackend::new(HttpBackendConfig::new(url), None).unwrap();
+        backend.status("t 1/../x?y").await.unwrap();
+        assert_eq!(
+            calls.lock().unwrap()[0].path,
+            "/requests/t%201%2F..%2Fx%3Fy"
+        );
+    }
+
+    #[test]
+    fn test_load_config() {
+        let config =
+            HttpBackendConfig::load(Path::new("./circuits/fixtures/http_backend/relayer.json"))
+                .unwrap();
+        assert_eq!(config.url, "https://relayer.example.com/api");
+        assert_eq!(config.status_path, "/tickets/{id}");
+        assert_eq!(config.request.input, "input_hex");
+        assert_eq!(config.request.kind.as_deref(), Some("kind"));
+        assert_eq!(config.response.ticket_id, "ticket.id");
+        assert_eq!(config.states.relayed, ["completed"]);
+        // Unset, the proving states are the defaults.
+        assert_eq!(config.states.proving, default_proving_states());
+        assert_eq!(config.retry.max_attempts, 5);
+
+        // Only the URL is required.
+        let config: HttpBackendConfig =
+            serde_json::from_str(r#"{"url": "http://localhost:8080"}"#).unwrap();
+        assert_eq!(config, HttpBackendConfig::new("http://localhost:8080"));
+
+        let mut invalid = config.clone();
+        invalid.status_path = "/tickets".to_string();
+        assert_eq!(
+            invalid.validate().unwrap_err().to_string(),
+            "the status path \"/tickets\" has no {id}"
+        );
+        let mut invalid = config;
+        invalid.states.failed.push("Proving".to_string());
+        assert_eq!(
+            invalid.validate().unwrap_err().to_string(),
+            "the state \"Proving\" maps to both proving and failed"
+        );
+    }
+
+    #[test]
+    fn test_fields() {
+        let mut body = json!({"a": 1});
+        set_field(&mut body, "b.c.d", json!("x"));
+        set_field(&mut body, "b.e", json!(2));
+        assert_eq!(body, json!({"a": 1, "b": {"c": {"d": "x"}, "e": 2}}));
+        assert_eq!(field_value(&body, "b.c.d"), Some(&json!("x")));
+        assert_eq!(field_value(&body, "a.b"), None);
+        assert_eq!(field_value(&body, "missing"), None);
+    }
+}
@@ -0,0 +1,264 @@
+//! A backend that proves requests locally by running the circuit binaries.
+//!
+//! Each request gets a working directory `<dir>/<request_id>` holding the serialized input
+//! (`input.json`), the prover's output (`prover.log`) and, on success, the proof (`output.json`).
+//! The prover is invoked as `<prover> prove --input-json input.json`, the interface of the `step`
+//! and `skip` binaries. Proofs are written to disk only; they are not relayed on-chain.
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use log::{error, info};
+
+use super::{ProofBackend, ProofRequest, RequestKind};
+use crate::platform::FulfillmentStatus;
+use crate::target::RequestMode;
+
+/// How often the progress of a running prover is logged.
+const PROGRESS_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The number of trailing lines of the prover output included in a failure.
+const LOG_TAIL_LINES: usize = 5;
+
+pub struct LocalBackend {
+    step_prover: PathBuf,
+    skip_prover: PathBuf,
+    dir: PathBuf,
+    /// The final status of each request proved by this backend.
+    statuses: Mutex<HashMap<String, FulfillmentStatus>>,
+}
+
+impl LocalBackend {
+    /// Prove requests with the given step and skip circuit binaries, writing the results to
+    /// `dir`.
+    pub fn new(
+        step_prover: impl Into<PathBuf>,
+        skip_prover: impl Into<PathBuf>,
+        dir: impl AsRef<Path>,
+    ) -> Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)
+            .with_context(|| format!("failed to create proof directory {}", dir.display()))?;
+        Ok(Self {
+            step_prover: step_prover.into(),
+            skip_prover: skip_prover.into(),
+            dir,
+            statuses: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Run the prover for a request to completion. Returns the request ID; the outcome of the
+    /// proof is reported by `status`.
+    async fn prove(&self, kind: RequestKind, request: &ProofRequest<'_>) -> Result<String> {
+        let request_id = format!(
+            "local-{}-{}-{}-{}",
+            kind, request.target.chain_id, request.trusted_block, request.target_block
+        );
+        let dir = self.dir.join(&request_id);
+        fs::create_dir_all(&dir).with_context(|| format!("failed to create {}", dir.display()))?;
+        let input = serde_json::json!({
+            "type": "req_bytes",
+            "data": { "input": request.input.to_string() },
+        });
+        fs::write(dir.join("input.json"), input.to_string())?;
+        let log_path = dir.join("prover.log");
+        let log = fs::File::create(&log_path)?;
+
+        let prover = match kind {
+            RequestKind::Step => &self.step_prover,
+            RequestKind::Skip => &self.skip_prover,
+            RequestKind::DataCommitment => {
+                return Err(anyhow!("data commitments are not proved locally"))
+            }
+        };
+        info!("Proving {} locally with {}", request_id, prover.display());
+        let start = Instant::now();
+        let mut child = tokio::process::Command::new(prover)
+            .args(["prove", "--input-json", "input.json"])
+            .current_dir(&dir)
+            .stdout(log.try_clone()?)
+            .stderr(log)
+            .kill_on_drop(true)
+            .spawn()
+            .with_context(|| format!("failed to start prover {}", prover.display()))?;
+
+        let pid = child.id();
+        let mut peak_memory_kb = None;
+        let mut progress = tokio::time::interval(PROGRESS_INTERVAL);
+        progress.tick().await;
+        let exit_status = loop {
+            tokio::select! {
+                exit_status = child.wait() => break exit_status?,
+                _ = progress.tick() => {
+                    peak_memory_kb = pid.and_then(peak_memory).or(peak_memory_kb);
+                    info!(
+                        "Proving {}: running for {:?}, peak memory {:?} kB",
+                        request_id,
+                        start.elapsed(),
+                        peak_memory_kb
+                    );
+                }
+            }
+        };
+        info!(
+            "Prover for {} exited with {} after {:?} (peak memory {:?} kB)",
+            request_id,
+            exit_status,
+            start.elapsed(),
+            peak_memory_kb
+        );
+
+        let output_path = dir.join("output.json");
+        let status = if !exit_status.success() {
+            let error = format!(
+                "prover exited with {}: {}",
+                exit_status,
+                log_tail(&log_path)
+            );
+            error!("Proving {} failed: {}", request_id, error);
+            FulfillmentStatus::Failed { error: Some(error) }
+        } else if !output_path.exists() {
+            FulfillmentStatus::Failed {
+                error: Some(format!(
+                    "prover wrote no proof to {}",
+                    output_path.display()
+                )),
+            }
+        } else {
+            FulfillmentStatus::Proved {
+                proof_id: Some(output_path.display().to_string()),
+            }
+        };
+        self.statuses
+            .lock()
+            .unwrap()
+            .insert(request_id.clone(), status);
+        Ok(request_id)
+    }
+}
+
+/// The peak resident memory of a running process in kB, on Linux.
+fn peak_memory(pid: u32) -> Option<u64> {
+    let status = fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmHWM:"))
+        .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+}
+
+/// The last lines of a log file, joined into a single line.
+fn log_tail(path: &Path) -> String {
+    let log = fs::read_to_string(path).unwrap_or_default();
+    let lines = log.lines().collect::<Vec<_>>();
+    lines[lines.len().saturating_sub(LOG_TAIL_LINES)..].join(" | ")
+}
+
+#[async_trait]
+impl ProofBackend for LocalBackend {
+    fn name(&self) -> &str {
+        "local"
+    }
+
+    async fn request_step(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.prove(RequestKind::Step, request).await
+    }
+
+    async fn request_skip(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.prove(RequestKind::Skip, request).await
+    }
+
+    async fn status(&self, request_id: &str) -> Result<FulfillmentStatus> {
+        self.statuses
+            .lock()
+            .unwrap()
+            .get(request_id)
+            .cloned()
+            .ok_or_else(|| anyhow!("unknown request {}", request_id))
+    }
+
+    /// Requests are proved to completion when they are submitted, so there is nothing to wait
+    /// for.
+    async fn wait_for_fulfillment(
+        &self,
+        request_id: &str,
+        _mode: RequestMode,
+        _timeout: Duration,
+    ) -> Result<FulfillmentStatus> {
+        self.status(request_id).await
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::os::unix::fs::PermissionsExt;
+
+    use alloy_primitives::Bytes;
+
+    use super::*;
+    use crate::target::RequestTarget;
+
+    /// Write an executable shell script to `path`.
+    fn stub_prover(path: &Path, script: &str) -> PathBuf {
+        fs::write(path, format!("#!/bin/sh\n{}", script)).unwrap();
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+        path.to_path_buf()
+    }
+
+    #[tokio::test]
+    async fn test_local_backend() {
+        let dir = tempfile::tempdir().unwrap();
+        // The stub checks its arguments and copies the input to the proof.
+        let step_prover = stub_prover(
+            &dir.path().join("step"),
+            "[ \"$1 $2 $3\" = \"prove --input-json input.json\" ] || exit 2\n\
+             echo proving\n\
+             cp input.json output.json\n",
+        );
+        let skip_prover = stub_prover(
+            &dir.path().join("skip"),
+            "echo loading circuit\necho out of memory >&2\nexit 3\n",
+        );
+        let backend =
+            LocalBackend::new(step_prover, skip_prover, dir.path().join("proofs")).unwrap();
+
+        let target = RequestTarget::for_test(5);
+        let mut request = ProofRequest {
+            target: &target,
+            trusted_block: 10,
+            target_block: 11,
+            function_id: target.step_function_id,
+            calldata: Bytes::new(),
+            input: Bytes::from_static(&[0xab, 0xcd]),
+            correlation_id: None,
+        };
+
+        let request_id = backend.request_step(&request).await.unwrap();
+        let status = backend
+            .wait_for_fulfillment(&request_id, RequestMode::Offchain, Duration::ZERO)
+            .await
+            .unwrap();
+        let FulfillmentStatus::Proved {
+            proof_id: Some(proof),
+        } = status
+        else {
+            panic!("unexpected status {:?}", status);
+        };
+        assert!(fs::read_to_string(proof).unwrap().contains("0xabcd"));
+
+        request.target_block = 20;
+        let request_id = backend.request_skip(&request).await.unwrap();
+        match backend.status(&request_id).await.unwrap() {
+            FulfillmentStatus::Failed { error: Some(error) } => {
+                assert!(error.contains("out of memory"), "{}", error)
+            }
+            status => panic!("unexpected status {:?}", status),
+        }
+
+        assert!(backend.status("missing").await.is_err());
+    }
+}
@@ -0,0 +1,243 @@
+//! An in-memory backend that records requests, for tests.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use alloy_primitives::{Bytes, B256};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+
+use super::{ProofBackend, ProofPayload, ProofRequest, RequestKind};
+use crate::platform::FulfillmentStatus;
+use crate::target::RequestTarget;
+
+/// A request received by the `MockBackend`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MockRequest {
+    pub request_id: String,
+    pub kind: RequestKind,
+    pub target: RequestTarget,
+    pub trusted_block: u64,
+    pub target_block: u64,
+    pub function_id: B256,
+    pub calldata: Bytes,
+    pub input: Bytes,
+}
+
+/// Records every request and assigns sequential IDs prefixed with its name (`mock-1`, `mock-2`,
+/// ...). Requests are `Proving` until their status is set with `set_status`, and have no proof
+/// until one is set with `set_proof`.
+#[derive(Debug)]
+pub struct MockBackend {
+    name: String,
+    requests: Mutex<Vec<MockRequest>>,
+    /// The number of submissions, including failed ones.
+    submission_attempts: Mutex<u64>,
+    statuses: Mutex<HashMap<String, FulfillmentStatus>>,
+    proofs: Mutex<HashMap<String, ProofPayload>>,
+    /// The chain IDs for which submissions fail, before reaching the backend.
+    failing_chains: Mutex<Vec<u32>>,
+    /// The chain IDs for which submissions are accepted but time out.
+    timing_out_chains: Mutex<Vec<u32>>,
+    /// The number of status queries.
+    status_calls: Mutex<u64>,
+}
+
+impl Default for MockBackend {
+    fn default() -> Self {
+        Self::with_name("mock")
+    }
+}
+
+impl MockBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_name(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            requests: Mutex::new(Vec::new()),
+            submission_attempts: Mutex::new(0),
+            statuses: Mutex::new(HashMap::new()),
+            proofs: Mutex::new(HashMap::new()),
+            failing_chains: Mutex::new(Vec::new()),
+            timing_out_chains: Mutex::new(Vec::new()),
+            status_calls: Mutex::new(0),
+        }
+    }
+
+    /// The number of submissions so far, including failed ones.
+    pub fn submission_attempts(&self) -> u64 {
+        *self.submission_attempts.lock().unwrap()
+    }
+
+    /// The number of status queries so far.
+    pub fn status_calls(&self) -> u64 {
+        *self.status_calls.lock().unwrap()
+    }
+
+    /// The requests received so far, in order.
+    pub fn requests(&self) -> Vec<MockRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    pub fn set_status(&self, request_id: &str, status: FulfillmentStatus) {
+        self.statuses
+            .lock()
+            .unwrap()
+            .insert(request_id.to_string(), status);
+    }
+
+    pub fn set_proof(&self, request_id: &str, proof: ProofPayload) {
+        self.proofs
+            .lock()
+            .unwrap()
+            .insert(request_id.to_string(), proof);
+    }
+
+    /// Make every submission for `chain_id` fail, as if the backend refused the connection.
+    pub fn fail_chain(&self, chain_id: u32) {
+        self.failing_chains.lock().unwrap().push(chain_id);
+    }
+
+    /// Undo `fail_chain`.
+    pub fn recover_chain(&self, chain_id: u32) {
+        self.failing_chains
+            .lock()
+            .unwrap()
+            .retain(|&c| c != chain_id);
+    }
+
+    /// Make every submission for `chain_id` time out once the backend accepted it: the request is
+    /// recorded, but the submission fails.
+    pub fn time_out_chain(&self, chain_id: u32) {
+        self.timing_out_chains.lock().unwrap().push(chain_id);
+    }
+
+    fn record(&self, kind: RequestKind, request: &ProofRequest<'_>) -> Result<String> {
+        *self.submission_attempts.lock().unwrap() += 1;
+        if self
+            .failing_chains
+            .lock()
+            .unwrap()
+            .contains(&request.target.chain_id)
+        {
+            let refused = std::io::Error::new(
+                std::io::ErrorKind::ConnectionRefused,
+                format!("mock submission failure for {}", request.target.chain_id),
+            );
+            return Err(refused.into());
+        }
+        let mut requests = self.requests.lock().unwrap();
+        let request_id = format!("{}-{}", self.name, requests.len() + 1);
+        requests.push(MockRequest {
+            request_id: request_id.clone(),
+            kind,
+            target: request.target.clone(),
+            trusted_block: request.trusted_block,
+            target_block: request.target_block,
+            function_id: request.function_id,
+            calldata: request.calldata.clone(),
+            input: request.input.clone(),
+        });
+        let chain_id = request.target.chain_id;
+        if self.timing_out_chains.lock().unwrap().contains(&chain_id) {
+            return Err(anyhow!("mock submission for {} timed out", chain_id));
+        }
+        Ok(request_id)
+    }
+}
+
+#[async_trait]
+impl ProofBackend for MockBackend {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn request_step(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.record(RequestKind::Step, request)
+    }
+
+    async fn request_skip(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.record(RequestKind::Skip, request)
+    }
+
+    async fn request_data_commitment(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.record(RequestKind::DataCommitment, request)
+    }
+
+    async fn status(&self, request_id: &str) -> Result<FulfillmentStatus> {
+        *self.status_calls.lock().unwrap() += 1;
+        if !self
+            .requests
+            .lock()
+            .unwrap()
+            .iter()
+            .any(|r| r.request_id == request_id)
+        {
+            return Err(anyhow!("unknown request {}", request_id));
+        }
+        Ok(self
+            .statuses
+            .lock()
+            .unwrap()
+            .get(request_id)
+            .cloned()
+            .unwrap_or(FulfillmentStatus::Proving))
+    }
+
+    async fn proof(&self, request_id: &str) -> Result<Option<ProofPayload>> {
+        Ok(self.proofs.lock().unwrap().get(request_id).cloned())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::target::RequestMode;
+
+    #[tokio::test]
+    async fn test_mock_backend() {
+        let target = RequestTarget::for_test(5);
+        let request = ProofRequest {
+            target: &target,
+            trusted_block: 10,
+            target_block: 20,
+            function_id: target.skip_function_id,
+            calldata: Bytes::from_static(&[1, 2]),
+            input: Bytes::from_static(&[3, 4]),
+            correlation_id: None,
+        };
+
+        let backend = MockBackend::new();
+        let request_id = backend.request_skip(&request).await.unwrap();
+        assert_eq!(request_id, "mock-1");
+        assert_eq!(backend.requests()[0].kind, RequestKind::Skip);
+        assert_eq!(backend.requests()[0].input, request.input);
+        assert_eq!(
+            backend.status(&request_id).await.unwrap(),
+            FulfillmentStatus::Proving
+        );
+        assert!(backend.status("mock-2").await.is_err());
+
+        let relayed = FulfillmentStatus::Relayed {
+            proof_id: None,
+            tx_hash: None,
+        };
+        backend.set_status(&request_id, relayed.clone());
+        assert_eq!(
+            backend
+                .wait_for_fulfillment(&request_id, RequestMode::Platform, Duration::from_secs(1))
+                .await
+                .unwrap(),
+            relayed
+        );
+
+        backend.fail_chain(5);
+        assert!(backend.request_step(&request).await.is_err());
+        assert_eq!(backend.requests().len(), 1);
+    }
+}
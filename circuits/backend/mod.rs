@@ -0,0 +1,482 @@
+//! Proving backends: where proof requests are sent to be proved.
+//!
+//! The operator builds the packed inputs and callback calldata for a request and hands them to a
+//! `ProofBackend`. The hosted platform (`PlatformClient`) is the default backend. An operator uses
+//! a single backend, so a request is either submitted to the platform or proved locally, never
+//! both.
+
+pub mod failover;
+pub mod file;
+pub mod http;
+pub mod local;
+pub mod mock;
+pub mod ratelimit;
+#[cfg(feature = "sp1")]
+pub mod sp1;
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use alloy_primitives::{Address, Bytes, B256};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use futures::stream::{self, StreamExt};
+use log::{debug, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::correlation::CorrelationId;
+use crate::encoding::{SkipInput, StepInput};
+use crate::platform::FulfillmentStatus;
+use crate::target::{RequestMode, RequestTarget};
+
+/// The first delay between two status polls. The delay doubles after every poll.
+const INITIAL_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// The maximum delay between two status polls.
+const MAX_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The maximum number of concurrent status queries of `ProofBackend::statuses`.
+const STATUS_CONCURRENCY: usize = 8;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RequestKind {
+    Step,
+    Skip,
+    /// A commitment to the data hashes of a range of headers, for contracts that take them.
+    #[serde(rename = "data_commitment")]
+    DataCommitment,
+}
+
+impl RequestKind {
+    /// The kind of request that proves `target_block` from `trusted_block`. Data commitments are
+    /// only ever requested explicitly.
+    pub fn for_range(trusted_block: u64, target_block: u64) -> Self {
+        if target_block == trusted_block + 1 {
+            RequestKind::Step
+        } else {
+            RequestKind::Skip
+        }
+    }
+
+    /// The function ID of the circuit that proves this kind of request for `target`: zero for a
+    /// data commitment if the target doesn't take them, which is never requested.
+    pub fn function_id(&self, target: &RequestTarget) -> B256 {
+        match self {
+            RequestKind::Step => target.step_function_id,
+            RequestKind::Skip => target.skip_function_id,
+            RequestKind::DataCommitment => target.data_commitment_function_id.unwrap_or_default(),
+        }
+    }
+}
+
+impl fmt::Display for RequestKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestKind::Step => f.write_str("step"),
+            RequestKind::Skip => f.write_str("skip"),
+            RequestKind::DataCommitment => f.write_str("data_commitment"),
+        }
+    }
+}
+
+impl FromStr for RequestKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "step" => Ok(RequestKind::Step),
+            "skip" => Ok(RequestKind::Skip),
+            "data_commitment" => Ok(RequestKind::DataCommitment),
+            _ => Err(anyhow!(
+                "unknown request kind {:?}, expected step, skip or data_commitment",
+                s
+            )),
+        }
+    }
+}
+
+/// A request to prove the header at `target_block` from the trusted header at `trusted_block`.
+#[derive(Debug, Clone)]
+pub struct ProofRequest<'a> {
+    pub target: &'a RequestTarget,
+    pub trusted_block: u64,
+    pub target_block: u64,
+    /// The function ID of the circuit that proves the request.
+    pub function_id: B256,
+    /// The calldata of the callback on the target contract.
+    pub calldata: Bytes,
+    /// The packed circuit input.
+    pub input: Bytes,
+    /// The attempt the request is part of, if the operator planned it.
+    pub correlation_id: Option<CorrelationId>,
+}
+
+impl ProofRequest<'_> {
+    /// This request, as part of the attempt `correlation_id`.
+    pub fn with_correlation_id(mut self, correlation_id: CorrelationId) -> Self {
+        self.correlation_id = Some(correlation_id);
+        self
+    }
+}
+
+/// A request known to a backend, as listed by `ProofBackend::recent_requests`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecentRequest {
+    pub request_id: String,
+    pub chain_id: u32,
+    /// The contract the request is relayed to.
+    pub address: Address,
+    pub function_id: B256,
+    /// The packed circuit input.
+    pub input: Bytes,
+    pub status: FulfillmentStatus,
+}
+
+impl RecentRequest {
+    /// The `(trusted_block, target_block)` range of the request, decoded from its packed step or
+    /// skip input. The input of a data commitment has the layout of a skip input.
+    pub fn range(&self) -> Option<(u64, u64)> {
+        match self.input.len() {
+            StepInput::LEN => {
+                let input = StepInput::decode(&self.input).ok()?;
+                Some((
+                    input.trusted_block.value(),
+                    input.trusted_block.next().value(),
+                ))
+            }
+            SkipInput::LEN => {
+                let input = SkipInput::decode(&self.input).ok()?;
+                Some((input.trusted_block.value(), input.target_block.value()))
+            }
+            _ => None,
+        }
+    }
+
+    /// Whether the request may still be fulfilled.
+    pub fn is_unfulfilled(&self) -> bool {
+        matches!(
+            self.status,
+            FulfillmentStatus::Proving | FulfillmentStatus::Proved { .. }
+        )
+    }
+}
+
+/// The proof of a proved request and the public values it commits to, as
+/// `ProofBackend::proof` fetches them.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProofPayload {
+    pub proof: Bytes,
+    /// The packed circuit input.
+    pub input: Bytes,
+    /// The circuit output, e.g. the header hash of the target block.
+    pub output: Bytes,
+}
+
+/// The unfulfilled `kind` request in `requests` for the range from `trusted_block` to
+/// `target_block` on `target`, if any.
+pub fn find_unfulfilled<'a>(
+    requests: &'a [RecentRequest],
+    target: &RequestTarget,
+    kind: RequestKind,
+    trusted_block: u64,
+    target_block: u64,
+) -> Option<&'a RecentRequest> {
+    let function_id = kind.function_id(target);
+    requests.iter().find(|request| {
+        request.chain_id == target.chain_id
+            && request.address == target.address
+            && request.function_id == function_id
+            && request.range() == Some((trusted_block, target_block))
+            && request.is_unfulfilled()
+    })
+}
+
+/// Whether a submission surely failed before reaching the backend: the connection was never
+/// established. Submitting the request again, or elsewhere, can't duplicate it then. After any
+/// other failure, like a timeout once sent, the backend may have accepted the request.
+pub fn failed_before_sending(error: &anyhow::Error) -> bool {
+    error.chain().any(|cause| {
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            return e.is_connect();
+        }
+        cause.downcast_ref::<std::io::Error>().is_some_and(|e| {
+            matches!(
+                e.kind(),
+                std::io::ErrorKind::ConnectionRefused | std::io::ErrorKind::NotConnected
+            )
+        })
+    })
+}
+
+#[async_trait]
+pub trait ProofBackend: Send + Sync {
+    /// A short name for the backend, recorded with each request it serves.
+    fn name(&self) -> &str;
+
+    /// The name of the backend that served a request, if it differs from this backend's name
+    /// (e.g. when this backend delegates to others).
+    fn served_by(&self, _request_id: &str) -> Option<String> {
+        None
+    }
+
+    /// Request a step proof. Returns the request ID.
+    async fn request_step(&self, request: &ProofRequest<'_>) -> Result<String>;
+
+    /// Request a skip proof. Returns the request ID.
+    async fn request_skip(&self, request: &ProofRequest<'_>) -> Result<String>;
+
+    /// Request a data commitment proof. Returns the request ID. Backends that can't prove data
+    /// commitments fail the request.
+    async fn request_data_commitment(&self, _request: &ProofRequest<'_>) -> Result<String> {
+        Err(anyhow!(
+            "the {} backend does not prove data commitments",
+            self.name()
+        ))
+    }
+
+    /// Query the current status of a request.
+    async fn status(&self, request_id: &str) -> Result<FulfillmentStatus>;
+
+    /// Query the current status of several requests, in order. Backends with a batch status query
+    /// should override this; by default up to `STATUS_CONCURRENCY` requests are queried
+    /// concurrently.
+    async fn statuses(&self, request_ids: &[String]) -> Vec<Result<FulfillmentStatus>> {
+        stream::iter(request_ids)
+            .map(|request_id| self.status(request_id))
+            .buffered(STATUS_CONCURRENCY)
+            .collect()
+            .await
+    }
+
+    /// The recent requests for `function_id`, used to avoid resubmitting a request that is
+    /// already in flight. Backends that keep no record of requests list none.
+    async fn recent_requests(&self, _function_id: B256) -> Result<Vec<RecentRequest>> {
+        Ok(Vec::new())
+    }
+
+    /// The cost of a request, if the backend reports it.
+    async fn request_cost(&self, _request_id: &str) -> Result<Option<f64>> {
+        Ok(None)
+    }
+
+    /// The digest of the circuit artifact registered for `function_id`, if the backend knows it.
+    async fn artifact_digest(&self, _function_id: B256) -> Result<Option<B256>> {
+        Ok(None)
+    }
+
+    /// Cancel a request that is no longer needed. Returns false if the backend does not support
+    /// cancellation, in which case the request may still be fulfilled.
+    async fn cancel(&self, _request_id: &str) -> Result<bool> {
+        Ok(false)
+    }
+
+    /// The proof of a proved request, if the backend can fetch it. `None` while the request isn't
+    /// proved, and for backends that don't return proofs.
+    async fn proof(&self, _request_id: &str) -> Result<Option<ProofPayload>> {
+        Ok(None)
+    }
+
+    /// Poll the status of a request with exponential backoff until it is fulfilled in `mode` (see
+    /// `FulfillmentStatus::is_settled`), fails, or `timeout` elapses. Errors while polling are
+    /// logged and retried until the timeout.
+    async fn wait_for_fulfillment(
+        &self,
+        request_id: &str,
+        mode: RequestMode,
+        timeout: Duration,
+    ) -> Result<FulfillmentStatus> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        let mut interval = INITIAL_POLL_INTERVAL;
+        loop {
+            match self.status(request_id).await {
+                Ok(status) if status.is_settled(mode) => return Ok(status),
+                Ok(status) => debug!("Request {} status: {:?}", request_id, status),
+                Err(e) => warn!("Failed to poll request {}: {:#}", request_id, e),
+            }
+
+            let now = tokio::time::Instant::now();
+            if now >= deadline {
+                return Ok(FulfillmentStatus::TimedOut);
+            }
+            tokio::time::sleep(std::cmp::min(interval, deadline - now)).await;
+            interval = std::cmp::min(interval * 2, MAX_POLL_INTERVAL);
+        }
+    }
+}
+
+#[async_trait]
+impl<B: ProofBackend + ?Sized> ProofBackend for Arc<B> {
+    fn name(&self) -> &str {
+        self.as_ref().name()
+    }
+
+    fn served_by(&self, request_id: &str) -> Option<String> {
+        self.as_ref().served_by(request_id)
+    }
+
+    async fn request_step(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.as_ref().request_step(request).await
+    }
+
+    async fn request_skip(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.as_ref().request_skip(request).await
+    }
+
+    async fn request_data_commitment(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.as_ref().request_data_commitment(request).await
+    }
+
+    async fn status(&self, request_id: &str) -> Result<FulfillmentStatus> {
+        self.as_ref().status(request_id).await
+    }
+
+    async fn statuses(&self, request_ids: &[String]) -> Vec<Result<FulfillmentStatus>> {
+        self.as_ref().statuses(request_ids).await
+    }
+
+    async fn recent_requests(&self, function_id: B256) -> Result<Vec<RecentRequest>> {
+        self.as_ref().recent_requests(function_id).await
+    }
+
+    async fn request_cost(&self, request_id: &str) -> Result<Option<f64>> {
+        self.as_ref().request_cost(request_id).await
+    }
+
+    async fn artifact_digest(&self, function_id: B256) -> Result<Option<B256>> {
+        self.as_ref().artifact_digest(function_id).await
+    }
+
+    async fn cancel(&self, request_id: &str) -> Result<bool> {
+        self.as_ref().cancel(request_id).await
+    }
+
+    async fn proof(&self, request_id: &str) -> Result<Option<ProofPayload>> {
+        self.as_ref().proof(request_id).await
+    }
+
+    async fn wait_for_fulfillment(
+        &self,
+        request_id: &str,
+        mode: RequestMode,
+        timeout: Duration,
+    ) -> Result<FulfillmentStatus> {
+        self.as_ref()
+            .wait_for_fulfillment(request_id, mode, timeout)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn target() -> RequestTarget {
+        RequestTarget {
+            data_commitment_function_id: Some(B256::repeat_byte(0x44)),
+            ..RequestTarget::for_test(5)
+        }
+    }
+
+    fn recent_request(
+        request_id: &str,
+        trusted_block: u64,
+        target_block: u64,
+        status: FulfillmentStatus,
+    ) -> RecentRequest {
+        let target = target();
+        let mut input = trusted_block.to_be_bytes().to_vec();
+        input.extend_from_slice(&[0xab; 32]);
+        let kind = RequestKind::for_range(trusted_block, target_block);
+        if kind == RequestKind::Skip {
+            input.extend_from_slice(&target_block.to_be_bytes());
+        }
+        RecentRequest {
+            request_id: request_id.to_string(),
+            chain_id: target.chain_id,
+            address: target.address,
+            function_id: kind.function_id(&target),
+            input: input.into(),
+            status,
+        }
+    }
+
+    #[test]
+    fn test_find_unfulfilled() {
+        let target = target();
+        let failed = FulfillmentStatus::Failed { error: None };
+        let relayed = FulfillmentStatus::Relayed {
+            proof_id: None,
+            tx_hash: None,
+        };
+        let mut other_contract = recent_request("other", 100, 200, FulfillmentStatus::Proving);
+        other_contract.address = Address::repeat_byte(9);
+        let requests = vec![
+            recent_request("failed", 100, 200, failed),
+            recent_request("relayed", 100, 200, relayed),
+            other_contract,
+            recent_request("step", 100, 101, FulfillmentStatus::Proving),
+            recent_request(
+                "skip",
+                100,
+                200,
+                FulfillmentStatus::Proved { proof_id: None },
+            ),
+        ];
+
+        assert_eq!(requests[3].range(), Some((100, 101)));
+        assert_eq!(requests[4].range(), Some((100, 200)));
+
+        let find = |trusted_block, target_block| {
+            let kind = RequestKind::for_range(trusted_block, target_block);
+            find_unfulfilled(&requests, &target, kind, trusted_block, target_block)
+                .map(|r| r.request_id.as_str())
+        };
+        // Failed and relayed requests, and requests for other contracts, are ignored.
+        assert_eq!(find(100, 200), Some("skip"));
+        assert_eq!(find(100, 101), Some("step"));
+        assert_eq!(find(100, 150), None);
+        assert_eq!(find(101, 200), None);
+        let skip = RequestKind::Skip;
+        assert_eq!(
+            find_unfulfilled(&requests[..3], &target, skip, 100, 200),
+            None
+        );
+        // A data commitment of the range isn't the skip.
+        let commitment = RequestKind::DataCommitment;
+        assert_eq!(
+            find_unfulfilled(&requests, &target, commitment, 100, 200),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_fulfillment_modes() {
+        let target = target();
+        let request = ProofRequest {
+            target: &target,
+            trusted_block: 100,
+            target_block: 101,
+            function_id: target.step_function_id,
+            calldata: Bytes::new(),
+            input: Bytes::new(),
+            correlation_id: None,
+        };
+        let backend = mock::MockBackend::new();
+        let request_id = backend.request_step(&request).await.unwrap();
+        let proved = FulfillmentStatus::Proved {
+            proof_id: Some("p1".to_string()),
+        };
+        backend.set_status(&request_id, proved.clone());
+
+        // A proved request is done off-chain, but still waits to be relayed by the platform.
+        let wait = |mode| backend.wait_for_fulfillment(&request_id, mode, Duration::ZERO);
+        assert_eq!(wait(RequestMode::Offchain).await.unwrap(), proved);
+        assert_eq!(
+            wait(RequestMode::Platform).await.unwrap(),
+            FulfillmentStatus::TimedOut
+        );
+    }
+}
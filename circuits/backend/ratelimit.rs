@@ -0,0 +1,230 @@
+//! A backend wrapper that limits the rate of submissions.
+//!
+//! The operator wraps its backend once, so the limit is shared by every target and by every path
+//! that submits requests, including retries. A submission over the limit waits until the oldest
+//! submission in the window expires rather than failing.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use alloy_primitives::B256;
+use anyhow::Result;
+use async_trait::async_trait;
+use log::info;
+
+use super::{ProofBackend, ProofPayload, ProofRequest, RecentRequest};
+use crate::metrics::{Histogram, RATE_LIMIT_WAIT_BUCKETS};
+use crate::platform::FulfillmentStatus;
+use crate::target::RequestMode;
+
+/// Allows at most `max_submissions` submissions in any sliding `window`.
+#[derive(Debug)]
+pub struct RateLimiter {
+    max_submissions: usize,
+    window: Duration,
+    /// The times of the submissions in the current window, oldest first.
+    submissions: Mutex<VecDeque<Instant>>,
+    /// The time each submission waited for the limit.
+    waits: Mutex<Histogram>,
+}
+
+impl RateLimiter {
+    pub fn new(max_submissions: usize, window: Duration) -> Self {
+        Self {
+            max_submissions,
+            window,
+            submissions: Mutex::new(VecDeque::new()),
+            waits: Mutex::new(Histogram::new(RATE_LIMIT_WAIT_BUCKETS)),
+        }
+    }
+
+    pub fn per_hour(max_submissions: usize) -> Self {
+        Self::new(max_submissions, Duration::from_secs(60 * 60))
+    }
+
+    /// The total time submissions have waited for the limit.
+    pub fn waited(&self) -> Duration {
+        Duration::from_secs_f64(self.waits.lock().unwrap().sum())
+    }
+
+    /// The time each submission waited for the limit, as exported by the metrics.
+    pub fn waits(&self) -> Histogram {
+        self.waits.lock().unwrap().clone()
+    }
+
+    /// Record that a submission waited `wait` for the limit, zero if it didn't.
+    pub fn observe_wait(&self, wait: Duration) {
+        self.waits.lock().unwrap().observe(wait.as_secs_f64());
+    }
+
+    /// Count a submission at `now` if it is within the limit. Otherwise returns how long to wait
+    /// before trying again.
+    pub fn try_reserve_at(&self, now: Instant) -> Result<(), Duration> {
+        let mut submissions = self.submissions.lock().unwrap();
+        while let Some(&oldest) = submissions.front() {
+            if now.saturating_duration_since(oldest) < self.window {
+                break;
+            }
+            submissions.pop_front();
+        }
+        if submissions.len() < self.max_submissions {
+            submissions.push_back(now);
+            return Ok(());
+        }
+        // A limit of zero never frees up; retry after a full window.
+        let wait = submissions.front().map_or(self.window, |&oldest| {
+            (oldest + self.window).saturating_duration_since(now)
+        });
+        Err(wait)
+    }
+
+    /// Wait until a submission is within the limit, and count it.
+    pub async fn reserve(&self) {
+        let start = Instant::now();
+        let mut waited = Duration::ZERO;
+        loop {
+            let wait = match self.try_reserve_at(Instant::now()) {
+                Ok(()) => break,
+                Err(wait) => wait,
+            };
+            info!(
+                "Submission rate limit of {} per {:?} reached, waiting {:?}",
+                self.max_submissions, self.window, wait
+            );
+            tokio::time::sleep(wait).await;
+            waited = start.elapsed();
+        }
+        self.observe_wait(waited);
+    }
+}
+
+pub struct RateLimitedBackend {
+    inner: Box<dyn ProofBackend>,
+    limiter: Arc<RateLimiter>,
+}
+
+impl RateLimitedBackend {
+    pub fn new(inner: Box<dyn ProofBackend>, limiter: Arc<RateLimiter>) -> Self {
+        Self { inner, limiter }
+    }
+}
+
+#[async_trait]
+impl ProofBackend for RateLimitedBackend {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn served_by(&self, request_id: &str) -> Option<String> {
+        self.inner.served_by(request_id)
+    }
+
+    async fn request_step(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.limiter.reserve().await;
+        self.inner.request_step(request).await
+    }
+
+    async fn request_skip(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.limiter.reserve().await;
+        self.inner.request_skip(request).await
+    }
+
+    async fn request_data_commitment(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.limiter.reserve().await;
+        self.inner.request_data_commitment(request).await
+    }
+
+    async fn status(&self, request_id: &str) -> Result<FulfillmentStatus> {
+        self.inner.status(request_id).await
+    }
+
+    async fn statuses(&self, request_ids: &[String]) -> Vec<Result<FulfillmentStatus>> {
+        self.inner.statuses(request_ids).await
+    }
+
+    async fn recent_requests(&self, function_id: B256) -> Result<Vec<RecentRequest>> {
+        self.inner.recent_requests(function_id).await
+    }
+
+    async fn request_cost(&self, request_id: &str) -> Result<Option<f64>> {
+        self.inner.request_cost(request_id).await
+    }
+
+    async fn artifact_digest(&self, function_id: B256) -> Result<Option<B256>> {
+        self.inner.artifact_digest(function_id).await
+    }
+
+    async fn cancel(&self, request_id: &str) -> Result<bool> {
+        self.inner.cancel(request_id).await
+    }
+
+    async fn proof(&self, request_id: &str) -> Result<Option<ProofPayload>> {
+        self.inner.proof(request_id).await
+    }
+
+    async fn wait_for_fulfillment(
+        &self,
+        request_id: &str,
+        mode: RequestMode,
+        timeout: Duration,
+    ) -> Result<FulfillmentStatus> {
+        self.inner
+            .wait_for_fulfillment(request_id, mode, timeout)
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::{write_rate_limit_waits, MetricsWriter};
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::per_hour(2);
+        let start = Instant::now();
+        let at = |secs| start + Duration::from_secs(secs);
+
+        assert_eq!(limiter.try_reserve_at(at(0)), Ok(()));
+        assert_eq!(limiter.try_reserve_at(at(10)), Ok(()));
+        // Full until the first submission leaves the window.
+        assert_eq!(
+            limiter.try_reserve_at(at(20)),
+            Err(Duration::from_secs(3580))
+        );
+        // Rejected attempts are not counted.
+        assert_eq!(
+            limiter.try_reserve_at(at(3599)),
+            Err(Duration::from_secs(1))
+        );
+        assert_eq!(limiter.try_reserve_at(at(3600)), Ok(()));
+        assert_eq!(
+            limiter.try_reserve_at(at(3605)),
+            Err(Duration::from_secs(5))
+        );
+        assert_eq!(limiter.try_reserve_at(at(3610)), Ok(()));
+
+        let limiter = RateLimiter::per_hour(0);
+        assert_eq!(
+            limiter.try_reserve_at(start),
+            Err(Duration::from_secs(3600))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_wait_histogram() {
+        let limiter = RateLimiter::new(1, Duration::from_millis(50));
+        limiter.reserve().await;
+        limiter.reserve().await;
+        // The first submission didn't wait, the second one waited for the window.
+        assert_eq!(limiter.waits().count(), 2);
+        assert!(limiter.waited() >= Duration::from_millis(40));
+
+        let mut writer = MetricsWriter::new();
+        write_rate_limit_waits(&mut writer, &limiter);
+        let out = writer.finish();
+        assert!(out.contains("tendermintx_rate_limit_wait_seconds_bucket{le=\"0\"} 1\n"));
+        assert!(out.contains("tendermintx_rate_limit_wait_seconds_count 2\n"));
+    }
+}
@@ -0,0 +1,509 @@
+//! A backend for the SP1 Tendermint program, which proves ranges from light blocks.
+//!
+//! The SP1 program doesn't take the packed `(trusted_block, trusted_header_hash[, target_block])`
+//! tuple of the plonky2x circuits: it verifies the light blocks themselves. `Sp1Input` holds the
+//! trusted and target light blocks (signed header, validators and next validators), built from
+//! the same responses the operator fetches for its own inputs, and is sent bincode encoded. The
+//! planning of requests is the operator's, as with any backend; only their encoding and
+//! submission differ.
+//!
+//! Requests are POSTed to `<url>/requests` and followed at `<url>/requests/<id>`, whose status
+//! responses have the format of the platform's.
+
+use alloy_primitives::Bytes;
+use anyhow::{anyhow, ensure, Context, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tendermint::block::signed_header::SignedHeader;
+use tendermint::block::{self, CommitSig};
+use tendermint::validator::Info;
+use tendermint::Hash;
+
+use super::{ProofBackend, ProofRequest, RequestKind};
+use crate::encoding::{SkipInput, StepInput};
+use crate::input::{header_hash, InputDataFetcher};
+use crate::platform::{parse_request_status, ApiKey, FulfillmentStatus};
+use crate::types::{HeaderHash, Height};
+
+/// A block ID: the header hash and the part set header.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sp1BlockId {
+    pub hash: Vec<u8>,
+    pub part_set_total: u32,
+    pub part_set_hash: Vec<u8>,
+}
+
+impl From<&block::Id> for Sp1BlockId {
+    fn from(id: &block::Id) -> Self {
+        Self {
+            hash: id.hash.as_bytes().to_vec(),
+            part_set_total: id.part_set_header.total,
+            part_set_hash: id.part_set_header.hash.as_bytes().to_vec(),
+        }
+    }
+}
+
+/// A header. Hashes are empty when unset, and times are nanoseconds since the Unix epoch.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sp1Header {
+    pub version_block: u64,
+    pub version_app: u64,
+    pub chain_id: String,
+    pub height: u64,
+    pub time: i128,
+    pub last_block_id: Option<Sp1BlockId>,
+    pub last_commit_hash: Vec<u8>,
+    pub data_hash: Vec<u8>,
+    pub validators_hash: Vec<u8>,
+    pub next_validators_hash: Vec<u8>,
+    pub consensus_hash: Vec<u8>,
+    pub app_hash: Vec<u8>,
+    pub last_results_hash: Vec<u8>,
+    pub evidence_hash: Vec<u8>,
+    pub proposer_address: Vec<u8>,
+}
+
+/// A commit signature. The address, time and signature of an absent validator are empty.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sp1CommitSig {
+    /// 1 for absent, 2 for a commit, 3 for a nil vote, as in Tendermint's `BlockIDFlag`.
+    pub block_id_flag: u8,
+    pub validator_address: Vec<u8>,
+    pub timestamp: i128,
+    pub signature: Vec<u8>,
+}
+
+impl From<&CommitSig> for Sp1CommitSig {
+    fn from(sig: &CommitSig) -> Self {
+        let (block_id_flag, vote) = match sig {
+            CommitSig::BlockIdFlagAbsent => (1, None),
+            CommitSig::BlockIdFlagCommit {
+                validator_address,
+                timestamp,
+                signature,
+            } => (2, Some((validator_address, timestamp, signature))),
+            CommitSig::BlockIdFlagNil {
+                validator_address,
+                timestamp,
+                signature,
+            } => (3, Some((validator_address, timestamp, signature))),
+        };
+        match vote {
+            None => Self {
+                block_id_flag,
+                validator_address: Vec::new(),
+                timestamp: 0,
+                signature: Vec::new(),
+            },
+            Some((address, timestamp, signature)) => Self {
+                block_id_flag,
+                validator_address: address.as_bytes().to_vec(),
+                timestamp: timestamp.unix_timestamp_nanos(),
+                signature: signature
+                    .as_ref()
+                    .map(|s| s.as_bytes().to_vec())
+                    .unwrap_or_default(),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sp1Commit {
+    pub height: u64,
+    pub round: u32,
+    pub block_id: Sp1BlockId,
+    pub signatures: Vec<Sp1CommitSig>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sp1Validator {
+    pub address: Vec<u8>,
+    /// The raw Ed25519 public key.
+    pub pub_key: Vec<u8>,
+    pub voting_power: u64,
+}
+
+impl From<&Info> for Sp1Validator {
+    fn from(validator: &Info) -> Self {
+        Self {
+            address: validator.address.as_bytes().to_vec(),
+            pub_key: validator.pub_key.to_bytes(),
+            voting_power: validator.power.value(),
+        }
+    }
+}
+
+/// A block, with the validators that sign it and those of the next block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sp1LightBlock {
+    pub header: Sp1Header,
+    pub commit: Sp1Commit,
+    pub validators: Vec<Sp1Validator>,
+    pub next_validators: Vec<Sp1Validator>,
+}
+
+fn hash_bytes(hash: Option<Hash>) -> Vec<u8> {
+    hash.map(|h| h.as_bytes().to_vec()).unwrap_or_default()
+}
+
+impl Sp1LightBlock {
+    pub fn new(
+        signed_header: &SignedHeader,
+        validators: &[Info],
+        next_validators: &[Info],
+    ) -> Self {
+        let (header, commit) = (&signed_header.header, &signed_header.commit);
+        Self {
+            header: Sp1Header {
+                version_block: header.version.block,
+                version_app: header.version.app,
+                chain_id: header.chain_id.to_string(),
+                height: header.height.value(),
+                time: header.time.unix_timestamp_nanos(),
+                last_block_id: header.last_block_id.as_ref().map(Sp1BlockId::from),
+                last_commit_hash: hash_bytes(header.last_commit_hash),
+                data_hash: hash_bytes(header.data_hash),
+                validators_hash: header.validators_hash.as_bytes().to_vec(),
+                next_validators_hash: header.next_validators_hash.as_bytes().to_vec(),
+                consensus_hash: header.consensus_hash.as_bytes().to_vec(),
+                app_hash: header.app_hash.as_bytes().to_vec(),
+                last_results_hash: hash_bytes(header.last_results_hash),
+                evidence_hash: hash_bytes(header.evidence_hash),
+                proposer_address: header.proposer_address.as_bytes().to_vec(),
+            },
+            commit: Sp1Commit {
+                height: commit.height.value(),
+                round: commit.round.value(),
+                block_id: Sp1BlockId::from(&commit.block_id),
+                signatures: commit.signatures.iter().map(Sp1CommitSig::from).collect(),
+            },
+            validators: validators.iter().map(Sp1Validator::from).collect(),
+            next_validators: next_validators.iter().map(Sp1Validator::from).collect(),
+        }
+    }
+
+    /// Fetch the light block at `height`.
+    pub async fn fetch(fetcher: &InputDataFetcher, height: Height) -> Result<Self> {
+        let signed_header = fetcher.get_signed_header_from_number(height).await?;
+        let validators = fetcher.get_validator_set_from_number(height).await?;
+        let next_validators = fetcher.get_validator_set_from_number(height.next()).await?;
+        Ok(Self::new(&signed_header, &validators, &next_validators))
+    }
+}
+
+/// The input of the SP1 program for a step or a skip.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Sp1Input {
+    pub trusted: Sp1LightBlock,
+    pub target: Sp1LightBlock,
+}
+
+impl Sp1Input {
+    /// Fetch the light blocks of a range, checking that the trusted block is the one with
+    /// `trusted_header_hash`.
+    pub async fn fetch(
+        fetcher: &InputDataFetcher,
+        trusted_block: Height,
+        trusted_header_hash: HeaderHash,
+        target_block: Height,
+    ) -> Result<Self> {
+        ensure!(
+            trusted_block < target_block,
+            "the target block {} is not after the trusted block {}",
+            target_block,
+            trusted_block
+        );
+        let trusted = fetcher.get_signed_header_from_number(trusted_block).await?;
+        let hash = header_hash(&trusted.header);
+        ensure!(
+            hash == trusted_header_hash,
+            "the header of the trusted block {} hashes to {}, not {}",
+            trusted_block,
+            hash,
+            trusted_header_hash
+        );
+        let validators = fetcher.get_validator_set_from_number(trusted_block).await?;
+        let next_validators = fetcher
+            .get_validator_set_from_number(trusted_block.next())
+            .await?;
+        Ok(Self {
+            trusted: Sp1LightBlock::new(&trusted, &validators, &next_validators),
+            target: Sp1LightBlock::fetch(fetcher, target_block).await?,
+        })
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        bincode::serialize(self).expect("an SP1 input serializes")
+    }
+
+    pub fn decode(bytes: &[u8]) -> Result<Self> {
+        bincode::deserialize(bytes).context("invalid SP1 input")
+    }
+}
+
+/// The prover's response to a submission.
+#[derive(Debug, Deserialize)]
+struct SubmitResponse {
+    #[serde(alias = "id")]
+    request_id: String,
+}
+
+pub struct Sp1Backend {
+    http: reqwest::Client,
+    url: String,
+    api_key: Option<ApiKey>,
+    fetcher: InputDataFetcher,
+}
+
+impl Sp1Backend {
+    /// Submit to the prover at `url`, building inputs from the chain `fetcher` reads.
+    pub fn new(url: String, api_key: Option<String>, fetcher: InputDataFetcher) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            url: url.trim_end_matches('/').to_string(),
+            api_key: api_key.map(ApiKey::new),
+            fetcher,
+        }
+    }
+
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        match &self.api_key {
+            Some(api_key) => request.bearer_auth(api_key.expose()),
+            None => request,
+        }
+    }
+
+    async fn submit(&self, kind: RequestKind, request: &ProofRequest<'_>) -> Result<String> {
+        let trusted_header_hash = match kind {
+            RequestKind::Step => StepInput::decode(&request.input)?.trusted_header_hash,
+            RequestKind::Skip => SkipInput::decode(&request.input)?.trusted_header_hash,
+            RequestKind::DataCommitment => {
+                return Err(anyhow!("the sp1 backend does not prove data commitments"))
+            }
+        };
+        let input = Sp1Input::fetch(
+            &self.fetcher,
+            Height(request.trusted_block),
+            trusted_header_hash,
+            Height(request.target_block),
+        )
+        .await?;
+        let body = json!({
+            "program": kind.to_string(),
+            "chain_id": request.target.chain_id,
+            "to": request.target.address.to_string(),
+            "function_id": request.function_id.to_string(),
+            "calldata": request.calldata.to_string(),
+            "input": Bytes::from(input.encode()).to_string(),
+            "correlation_id": request.correlation_id.map(|id| id.to_string()),
+        });
+        let url = format!("{}/requests", self.url);
+        let response = self
+            .authorize(self.http.post(&url))
+            .json(&body)
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .with_context(|| format!("failed to submit {} request to {}", kind, self.url))?
+            .text()
+            .await?;
+        let response: SubmitResponse =
+            serde_json::from_str(&response).context("failed to parse submission response")?;
+        Ok(response.request_id)
+    }
+}
+
+#[async_trait]
+impl ProofBackend for Sp1Backend {
+    fn name(&self) -> &str {
+        "sp1"
+    }
+
+    async fn request_step(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.submit(RequestKind::Step, request).await
+    }
+
+    async fn request_skip(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.submit(RequestKind::Skip, request).await
+    }
+
+    async fn status(&self, request_id: &str) -> Result<FulfillmentStatus> {
+        let url = format!("{}/requests/{}", self.url, request_id);
+        let body = self
+            .authorize(self.http.get(&url))
+            .send()
+            .await
+            .and_then(|res| res.error_for_status())
+            .with_context(|| format!("failed to query status of request {}", request_id))?
+            .text()
+            .await?;
+        parse_request_status(&body)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::path::Path;
+    use std::sync::{Arc, Mutex};
+
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Method, Request, Response, Server};
+    use serde_json::Value;
+
+    use super::*;
+    use crate::encoding::encode_skip_input;
+    use crate::input::light_blocks::{self, LightBlocks};
+    use crate::target::RequestTarget;
+    use crate::testing::{fixture_fetcher, MockTendermintServer, SyntheticChain};
+
+    /// The bincode encoding of the skip of the fixtures, from 10000 to 10500.
+    const GOLDEN_SKIP: &str = include_str!("../fixtures/sp1/skip_10000_10500.hex");
+
+    async fn fixture_hash(fetcher: &InputDataFetcher, height: u64) -> HeaderHash {
+        let signed_header = fetcher
+            .get_signed_header_from_number(Height(height))
+            .await
+            .unwrap();
+        header_hash(&signed_header.header)
+    }
+
+    /// A mocked prover recording the requests it receives.
+    fn mock_prover() -> (String, Arc<Mutex<Vec<Value>>>) {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        let make_service = make_service_fn(move |_| {
+            let recorded = recorded.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let recorded = recorded.clone();
+                    async move {
+                        let body = if request.method() == Method::POST {
+                            let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                            let mut recorded = recorded.lock().unwrap();
+                            recorded.push(serde_json::from_slice(&body).unwrap());
+                            format!(r#"{{"request_id": "sp1_{}"}}"#, recorded.len())
+                        } else {
+                            assert_eq!(request.uri().path(), "/requests/sp1_1");
+                            r#"{"status": "PROVING"}"#.to_string()
+                        };
+                        Ok::<_, Infallible>(Response::new(Body::from(body)))
+                    }
+                }))
+            }
+        });
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        listener.set_nonblocking(true).unwrap();
+        tokio::spawn(Server::from_tcp(listener).unwrap().serve(make_service));
+        (format!("http://{}/", addr), requests)
+    }
+
+    #[tokio::test]
+    async fn test_golden_encoding() {
+        let fetcher = fixture_fetcher();
+        let hash = fixture_hash(&fetcher, 10000).await;
+        let input = Sp1Input::fetch(&fetcher, Height(10000), hash, Height(10500))
+            .await
+            .unwrap();
+        let encoded = input.encode();
+        assert_eq!(alloy_primitives::hex::encode(&encoded), GOLDEN_SKIP.trim());
+        assert_eq!(Sp1Input::decode(&encoded).unwrap(), input);
+
+        assert_eq!(input.trusted.header.height, 10000);
+        assert_eq!(input.target.header.chain_id, "mocha-4");
+        assert_eq!(input.target.commit.signatures.len(), 3);
+        assert_eq!(input.trusted.validators, input.trusted.next_validators);
+        assert_eq!(
+            input.target.commit.block_id.hash,
+            fixture_hash(&fetcher, 10500).await.as_bytes().to_vec()
+        );
+
+        let error = Sp1Input::fetch(
+            &fetcher,
+            Height(10000),
+            HeaderHash([0xab; 32]),
+            Height(10500),
+        )
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("hashes to"), "{:#}", error);
+    }
+
+    #[tokio::test]
+    async fn test_light_blocks_encoding() {
+        // The fixtures are in the layout of a light block directory.
+        let light_blocks = LightBlocks::load(Path::new("./circuits/fixtures/mocha-4")).unwrap();
+        let fetcher = InputDataFetcher::with_light_blocks(Arc::new(light_blocks), "mocha-4");
+        let hash = fixture_hash(&fixture_fetcher(), 10000).await;
+        let input = Sp1Input::fetch(&fetcher, Height(10000), hash, Height(10500))
+            .await
+            .unwrap();
+        assert_eq!(
+            alloy_primitives::hex::encode(input.encode()),
+            GOLDEN_SKIP.trim()
+        );
+
+        // Exported from a chain, then built from the bundle once it's unreachable.
+        let server = MockTendermintServer::start(SyntheticChain::new(7))
+            .await
+            .unwrap();
+        let online = server.fetcher();
+        let hash = fixture_hash(&online, 100).await;
+        let expected = Sp1Input::fetch(&online, Height(100), hash, Height(400))
+            .await
+            .unwrap();
+        let needs = light_blocks::needed_for_range(Height(100), Height(400));
+        let bundle = LightBlocks::fetch(&online, &needs)
+            .await
+            .unwrap()
+            .to_bundle()
+            .unwrap();
+        drop(server);
+        let light_blocks = LightBlocks::from_bundle(&bundle).unwrap();
+        let offline = InputDataFetcher::with_light_blocks(Arc::new(light_blocks), "bundle");
+        let input = Sp1Input::fetch(&offline, Height(100), hash, Height(400))
+            .await
+            .unwrap();
+        assert_eq!(input.encode(), expected.encode());
+    }
+
+    #[tokio::test]
+    async fn test_sp1_backend() {
+        let (url, requests) = mock_prover();
+        let backend = Sp1Backend::new(url, Some("key".to_string()), fixture_fetcher());
+        let target = RequestTarget::for_test(5);
+        let hash = fixture_hash(&backend.fetcher, 10000).await;
+        let request = ProofRequest {
+            target: &target,
+            trusted_block: 10000,
+            target_block: 10500,
+            function_id: target.skip_function_id,
+            calldata: Bytes::from_static(&[1, 2]),
+            input: encode_skip_input(Height(10000), hash, Height(10500)).into(),
+            correlation_id: None,
+        };
+
+        let request_id = backend.request_skip(&request).await.unwrap();
+        assert_eq!(request_id, "sp1_1");
+        assert_eq!(
+            backend.status(&request_id).await.unwrap(),
+            FulfillmentStatus::Proving
+        );
+        let requests = requests.lock().unwrap().clone();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0]["program"], "skip");
+        assert_eq!(requests[0]["chain_id"], 5);
+        assert_eq!(requests[0]["calldata"], "0x0102");
+        assert_eq!(requests[0]["input"], format!("0x{}", GOLDEN_SKIP.trim()));
+
+        // Not for the plonky2x circuits' data commitments.
+        let error = backend.request_data_commitment(&request).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the sp1 backend does not prove data commitments"
+        );
+    }
+}
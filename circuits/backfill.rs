@@ -0,0 +1,369 @@
+//! Proving historical checkpoints into an archive contract.
+//!
+//! A backfill proves the blocks `start + stride`, `start + 2 * stride`, ... up to `end` one after
+//! the other, each from the header the contract stores for the previous checkpoint, independently
+//! of the chain head. A checkpoint further than a single skip can reach is proved in several hops,
+//! each as far as the validator set allows.
+//!
+//! The progress is the contract itself: a backfill that is run again (e.g. after a crash) skips the
+//! checkpoints and hops the contract already stores, and resumes from the last one.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use log::info;
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::landing::{self, Landing};
+use crate::platform::FulfillmentStatus;
+
+/// The contract a backfill proves checkpoints into, and the proving machinery.
+#[async_trait]
+pub trait Checkpoints: Send + Sync {
+    /// The header hash the contract stores for `height`, if any.
+    async fn stored_hash(&self, height: u64) -> Result<Option<[u8; 32]>>;
+
+    /// The contract's latest block.
+    async fn latest_block(&self) -> Result<u64>;
+
+    /// The furthest block, up to `max_block`, that can be proved from `trusted_block` at once.
+    async fn next_target(&mut self, trusted_block: u64, max_block: u64) -> Result<u64>;
+
+    /// Request a proof from `trusted_block`, whose header hash is `trusted_hash`, to
+    /// `target_block`. Returns the request ID, or `None` if a request for the range is already
+    /// pending (e.g. submitted before a crash).
+    async fn request(
+        &self,
+        trusted_block: u64,
+        trusted_hash: [u8; 32],
+        target_block: u64,
+    ) -> Result<Option<String>>;
+
+    /// The status of the request `request_id`.
+    async fn status(&self, request_id: &str) -> Result<FulfillmentStatus>;
+}
+
+/// The checkpoints of a backfill from `start`, which the contract must already store: every
+/// `stride` blocks, and `end` itself.
+pub fn checkpoints(start: u64, end: u64, stride: u64) -> Vec<u64> {
+    let stride = stride.max(1);
+    let mut checkpoints = Vec::new();
+    let mut checkpoint = start;
+    while checkpoint < end {
+        checkpoint = end.min(checkpoint.saturating_add(stride));
+        checkpoints.push(checkpoint);
+    }
+    checkpoints
+}
+
+/// What a backfill did.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackfillSummary {
+    /// The checkpoints the contract already stored.
+    #[serde(rename = "skipped")]
+    pub skipped: usize,
+    #[serde(rename = "proved")]
+    pub proved: usize,
+    /// The IDs of the requests submitted, in order.
+    #[serde(rename = "request_ids")]
+    pub request_ids: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Backfill {
+    pub start: u64,
+    pub end: u64,
+    pub stride: u64,
+    /// The time between checks of a request while waiting for it to land.
+    pub poll_interval: Duration,
+    /// The maximum time to wait for a request to land.
+    pub timeout: Duration,
+}
+
+impl Backfill {
+    pub fn new(start: u64, end: u64, stride: u64) -> Self {
+        Self {
+            start,
+            end,
+            stride,
+            poll_interval: landing::DEFAULT_POLL_INTERVAL,
+            timeout: landing::DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Prove every checkpoint the contract doesn't store yet, in order. Stops at the first
+    /// request that fails or doesn't land in time.
+    pub async fn run(&self, contract: &mut impl Checkpoints) -> Result<BackfillSummary> {
+        if contract.stored_hash(self.start).await?.is_none() {
+            return Err(anyhow!(
+                "the contract stores no header for block {}",
+                self.start
+            ));
+        }
+        let checkpoints = checkpoints(self.start, self.end, self.stride);
+        let mut summary = BackfillSummary::default();
+        let mut previous = self.start;
+        for (stage, &checkpoint) in checkpoints.iter().enumerate() {
+            if contract.stored_hash(checkpoint).await?.is_some() {
+                summary.skipped += 1;
+                previous = checkpoint;
+                continue;
+            }
+            info!(
+                "Backfill stage {}/{}: proving block {} from {}",
+                stage + 1,
+                checkpoints.len(),
+                checkpoint,
+                previous
+            );
+            self.prove_checkpoint(contract, previous, checkpoint, &mut summary.request_ids)
+                .await?;
+            summary.proved += 1;
+            previous = checkpoint;
+        }
+        Ok(summary)
+    }
+
+    /// Prove `checkpoint` from `previous`, in as many hops as needed.
+    async fn prove_checkpoint(
+        &self,
+        contract: &mut impl Checkpoints,
+        previous: u64,
+        checkpoint: u64,
+        request_ids: &mut Vec<String>,
+    ) -> Result<()> {
+        let mut trusted_block = previous;
+        while trusted_block < checkpoint {
+            let target_block = contract.next_target(trusted_block, checkpoint).await?;
+            if contract.stored_hash(target_block).await?.is_some() {
+                trusted_block = target_block;
+                continue;
+            }
+            let trusted_hash = contract.stored_hash(trusted_block).await?.ok_or_else(|| {
+                anyhow!("the contract stores no header for block {}", trusted_block)
+            })?;
+            let request_id = contract
+                .request(trusted_block, trusted_hash, target_block)
+                .await?;
+            match request_id.as_ref() {
+                Some(request_id) => info!(
+                    "Requested block {} from {} [{}]",
+                    target_block, trusted_block, request_id
+                ),
+                None => info!(
+                    "A request for block {} from {} is already pending, waiting for it",
+                    target_block, trusted_block
+                ),
+            }
+
+            let (contract, request) = (&*contract, request_id.as_deref());
+            let status = move || async move {
+                match request {
+                    Some(request_id) => contract.status(request_id).await,
+                    None => Ok(FulfillmentStatus::Proving),
+                }
+            };
+            let landing = landing::wait_for_landing(
+                || contract.latest_block(),
+                status,
+                target_block,
+                self.poll_interval,
+                Instant::now() + self.timeout,
+            )
+            .await;
+            match landing {
+                Landing::Landed { latest_block } => {
+                    // The contract's latest block only tells that a later block was stored.
+                    if contract.stored_hash(target_block).await?.is_none() {
+                        return Err(anyhow!(
+                            "the contract is at block {} but stores no header for block {}",
+                            latest_block,
+                            target_block
+                        ));
+                    }
+                }
+                Landing::Failed { error } => {
+                    return Err(anyhow!(
+                        "the request for block {} failed: {}",
+                        target_block,
+                        error.as_deref().unwrap_or("unknown error")
+                    ))
+                }
+                Landing::TimedOut => {
+                    return Err(anyhow!(
+                        "block {} was not stored within {:?}",
+                        target_block,
+                        self.timeout
+                    ))
+                }
+            }
+            request_ids.extend(request_id);
+            trusted_block = target_block;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::wire::assert_snapshot;
+
+    fn hash(height: u64) -> [u8; 32] {
+        let mut hash = [0; 32];
+        hash[..8].copy_from_slice(&height.to_be_bytes());
+        hash
+    }
+
+    /// An archive contract whose requests land as soon as they are submitted.
+    struct MockContract {
+        stored: Mutex<BTreeMap<u64, [u8; 32]>>,
+        /// The longest hop the validator set allows.
+        max_hop: u64,
+        /// The (trusted block, trusted hash, target block) of each request.
+        requests: Mutex<Vec<(u64, [u8; 32], u64)>>,
+        /// The target block at which the operator crashes.
+        crash_at: Option<u64>,
+    }
+
+    impl MockContract {
+        fn new(max_hop: u64) -> Self {
+            Self {
+                stored: Mutex::new(BTreeMap::from([(0, hash(0))])),
+                max_hop,
+                requests: Mutex::new(Vec::new()),
+                crash_at: None,
+            }
+        }
+
+        fn requested(&self) -> Vec<(u64, u64)> {
+            let requests = self.requests.lock().unwrap();
+            requests
+                .iter()
+                .map(|&(trusted, _, target)| (trusted, target))
+                .collect()
+        }
+    }
+
+    #[async_trait]
+    impl Checkpoints for MockContract {
+        async fn stored_hash(&self, height: u64) -> Result<Option<[u8; 32]>> {
+            Ok(self.stored.lock().unwrap().get(&height).copied())
+        }
+
+        async fn latest_block(&self) -> Result<u64> {
+            Ok(*self.stored.lock().unwrap().keys().last().unwrap())
+        }
+
+        async fn next_target(&mut self, trusted_block: u64, max_block: u64) -> Result<u64> {
+            Ok(max_block.min(trusted_block + self.max_hop))
+        }
+
+        async fn request(
+            &self,
+            trusted_block: u64,
+            trusted_hash: [u8; 32],
+            target_block: u64,
+        ) -> Result<Option<String>> {
+            if self.crash_at == Some(target_block) {
+                return Err(anyhow!("operator crashed"));
+            }
+            let mut requests = self.requests.lock().unwrap();
+            requests.push((trusted_block, trusted_hash, target_block));
+            self.stored
+                .lock()
+                .unwrap()
+                .insert(target_block, hash(target_block));
+            Ok(Some(format!("request-{}", requests.len())))
+        }
+
+        async fn status(&self, _request_id: &str) -> Result<FulfillmentStatus> {
+            Ok(FulfillmentStatus::Relayed {
+                proof_id: None,
+                tx_hash: None,
+            })
+        }
+    }
+
+    #[test]
+    fn test_serde_snapshot() {
+        let summary = BackfillSummary {
+            skipped: 4,
+            proved: 2,
+            request_ids: vec!["req_1".to_string(), "req_2".to_string()],
+        };
+        assert_snapshot(
+            &summary,
+            include_str!("fixtures/serde/backfill_summary.json"),
+        );
+    }
+
+    #[test]
+    fn test_checkpoints() {
+        assert_eq!(
+            checkpoints(0, 250_000, 50_000),
+            [50_000, 100_000, 150_000, 200_000, 250_000]
+        );
+        assert_eq!(checkpoints(1000, 2500, 1000), [2000, 2500]);
+        assert!(checkpoints(1000, 1000, 1000).is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_resume_after_crash() {
+        let backfill = Backfill::new(0, 250_000, 50_000);
+        let mut contract = MockContract::new(100_000);
+
+        // The operator crashes at stage 3.
+        contract.crash_at = Some(150_000);
+        let err = backfill.run(&mut contract).await.unwrap_err();
+        assert!(err.to_string().contains("crashed"));
+        assert_eq!(contract.requested(), [(0, 50_000), (50_000, 100_000)]);
+
+        // Run again, the backfill resumes at stage 3 from the stored hash of stage 2.
+        contract.crash_at = None;
+        let summary = backfill.run(&mut contract).await.unwrap();
+        assert_eq!(summary.skipped, 2);
+        assert_eq!(summary.proved, 3);
+        assert_eq!(summary.request_ids, ["request-3", "request-4", "request-5"]);
+        assert_eq!(
+            contract.requested()[2..],
+            [(100_000, 150_000), (150_000, 200_000), (200_000, 250_000)]
+        );
+        let requests = contract.requests.lock().unwrap();
+        assert!(requests
+            .iter()
+            .all(|&(trusted, trusted_hash, _)| trusted_hash == hash(trusted)));
+        drop(requests);
+
+        // Once done, there is nothing left to prove.
+        let summary = backfill.run(&mut contract).await.unwrap();
+        assert_eq!((summary.skipped, summary.proved), (5, 0));
+    }
+
+    #[tokio::test]
+    async fn test_hops() {
+        // The validator set changes too much for a single skip between checkpoints.
+        let backfill = Backfill::new(0, 100_000, 50_000);
+        let mut contract = MockContract::new(30_000);
+        let summary = backfill.run(&mut contract).await.unwrap();
+        assert_eq!(summary.proved, 2);
+        assert_eq!(
+            contract.requested(),
+            [
+                (0, 30_000),
+                (30_000, 50_000),
+                (50_000, 80_000),
+                (80_000, 100_000)
+            ]
+        );
+
+        // The start must be stored.
+        let backfill = Backfill::new(10, 100_000, 50_000);
+        assert!(backfill.run(&mut MockContract::new(30_000)).await.is_err());
+    }
+}
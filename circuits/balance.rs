@@ -0,0 +1,234 @@
+//! Relayer account balance monitoring.
+//!
+//! When requests are relayed from (or paid for by) our own account, running out of funds silently
+//! stalls updates. The monitor samples the account balance each iteration, estimates how many more
+//! transactions it can pay for based on recent gas prices, and flags when it drops below a
+//! threshold.
+
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use alloy_primitives::Address;
+use anyhow::{anyhow, Context, Result};
+use ethers::providers::Middleware;
+use ethers::types::U256;
+use ethers::utils::{format_ether, parse_ether};
+use log::{info, warn};
+
+/// The number of recent gas cost samples used to estimate the cost of a transaction.
+const GAS_COST_WINDOW: usize = 20;
+
+/// The maximum time a balance check may take before it is abandoned for this iteration.
+const BALANCE_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The gas used by the callback of a request, which is the `TendermintX` request gas limit.
+pub const DEFAULT_GAS_PER_TRANSACTION: u64 = 500000;
+
+/// The result of a single balance check.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BalanceReport {
+    pub balance: U256,
+    /// The estimated number of transactions the balance can pay for, if any gas costs have been
+    /// observed.
+    pub transactions_remaining: Option<u64>,
+    pub below_threshold: bool,
+}
+
+impl BalanceReport {
+    /// The balance in native tokens, as exported by the metrics.
+    pub fn balance_native(&self) -> f64 {
+        format_ether(self.balance).parse().unwrap_or(f64::MAX)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct BalanceMonitor {
+    pub address: Address,
+    /// The balance (in wei) below which the monitor alerts.
+    pub threshold: U256,
+    pub gas_per_transaction: u64,
+    gas_costs: VecDeque<U256>,
+    /// The last successful report, if any.
+    pub last_report: Option<BalanceReport>,
+}
+
+impl BalanceMonitor {
+    pub fn new(address: Address, threshold: U256, gas_per_transaction: u64) -> Self {
+        Self {
+            address,
+            threshold,
+            gas_per_transaction,
+            gas_costs: VecDeque::new(),
+            last_report: None,
+        }
+    }
+
+    /// Build a monitor from a threshold denominated in native tokens (e.g. "0.5").
+    pub fn from_native_threshold(
+        address: Address,
+        threshold: &str,
+        gas_per_transaction: u64,
+    ) -> Result<Self> {
+        let threshold = parse_ether(threshold)
+            .map_err(|e| anyhow!("invalid balance threshold {:?}: {}", threshold, e))?;
+        Ok(Self::new(address, threshold, gas_per_transaction))
+    }
+
+    /// Record the cost of a transaction at the given gas price.
+    pub fn record_gas_price(&mut self, gas_price: U256) {
+        if self.gas_costs.len() == GAS_COST_WINDOW {
+            self.gas_costs.pop_front();
+        }
+        self.gas_costs
+            .push_back(gas_price.saturating_mul(U256::from(self.gas_per_transaction)));
+    }
+
+    /// Evaluate a balance against the threshold and the recent gas costs.
+    pub fn evaluate(&self, balance: U256) -> BalanceReport {
+        BalanceReport {
+            balance,
+            transactions_remaining: transactions_remaining(balance, &self.gas_costs),
+            below_threshold: balance < self.threshold,
+        }
+    }
+
+    /// Sample the gas price and balance from `client` and evaluate the balance. Never takes longer
+    /// than `BALANCE_CHECK_TIMEOUT`.
+    pub async fn check<M: Middleware>(&mut self, client: &M) -> Result<BalanceReport> {
+        let address = ethers::types::Address::from(self.address.0 .0);
+        let (gas_price, balance) = tokio::time::timeout(BALANCE_CHECK_TIMEOUT, async {
+            let gas_price = client
+                .get_gas_price()
+                .await
+                .map_err(|e| anyhow!("failed to get gas price: {}", e))?;
+            let balance = client
+                .get_balance(address, None)
+                .await
+                .map_err(|e| anyhow!("failed to get balance of {}: {}", self.address, e))?;
+            Ok::<_, anyhow::Error>((gas_price, balance))
+        })
+        .await
+        .context("balance check timed out")??;
+
+        self.record_gas_price(gas_price);
+        let report = self.evaluate(balance);
+        if report.below_threshold {
+            warn!(
+                "Relayer balance of {} is {}, below the threshold of {} ({:?} transactions remaining)",
+                self.address,
+                format_ether(report.balance),
+                format_ether(self.threshold),
+                report.transactions_remaining
+            );
+        } else {
+            info!(
+                "Relayer balance of {} is {} ({:?} transactions remaining)",
+                self.address,
+                format_ether(report.balance),
+                report.transactions_remaining
+            );
+        }
+        self.last_report = Some(report.clone());
+        Ok(report)
+    }
+}
+
+/// The number of transactions `balance` can pay for at the average of `gas_costs`. Returns `None`
+/// if there are no samples or the average cost is zero.
+pub fn transactions_remaining<'a>(
+    balance: U256,
+    gas_costs: impl IntoIterator<Item = &'a U256>,
+) -> Option<u64> {
+    let (total, count) = gas_costs
+        .into_iter()
+        .fold((U256::zero(), 0u64), |(total, count), cost| {
+            (total.saturating_add(*cost), count + 1)
+        });
+    if count == 0 {
+        return None;
+    }
+    let average = total / U256::from(count);
+    if average.is_zero() {
+        return None;
+    }
+    let remaining = balance / average;
+    Some(if remaining > U256::from(u64::MAX) {
+        u64::MAX
+    } else {
+        remaining.as_u64()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const GWEI: u64 = 1_000_000_000;
+
+    #[test]
+    fn test_threshold() {
+        let monitor = BalanceMonitor::from_native_threshold(
+            Address::ZERO,
+            "0.5",
+            DEFAULT_GAS_PER_TRANSACTION,
+        )
+        .unwrap();
+        assert_eq!(monitor.threshold, parse_ether("0.5").unwrap());
+
+        assert!(
+            monitor
+                .evaluate(parse_ether("0.49").unwrap())
+                .below_threshold
+        );
+        assert!(
+            !monitor
+                .evaluate(parse_ether("0.5").unwrap())
+                .below_threshold
+        );
+        assert!(!monitor.evaluate(parse_ether("3").unwrap()).below_threshold);
+        assert_eq!(
+            monitor
+                .evaluate(parse_ether("0.49").unwrap())
+                .balance_native(),
+            0.49
+        );
+
+        assert!(BalanceMonitor::from_native_threshold(Address::ZERO, "abc", 1).is_err());
+    }
+
+    #[test]
+    fn test_transactions_remaining() {
+        assert_eq!(transactions_remaining(U256::from(100), &[]), None);
+        assert_eq!(
+            transactions_remaining(U256::from(100), &[U256::zero()]),
+            None
+        );
+
+        // The average of 10 and 30 is 20.
+        let costs = [U256::from(10), U256::from(30)];
+        assert_eq!(transactions_remaining(U256::from(100), &costs), Some(5));
+        assert_eq!(transactions_remaining(U256::from(119), &costs), Some(5));
+        assert_eq!(transactions_remaining(U256::zero(), &costs), Some(0));
+
+        assert_eq!(
+            transactions_remaining(U256::MAX, &[U256::one()]),
+            Some(u64::MAX)
+        );
+    }
+
+    #[test]
+    fn test_gas_cost_window() {
+        let mut monitor = BalanceMonitor::new(Address::ZERO, U256::zero(), 100000);
+        // 1 ETH at 10 gwei * 100000 gas = 0.001 ETH per transaction.
+        monitor.record_gas_price(U256::from(10 * GWEI));
+        let report = monitor.evaluate(parse_ether("1").unwrap());
+        assert_eq!(report.transactions_remaining, Some(1000));
+
+        // Old samples are evicted once the window is full.
+        for _ in 0..GAS_COST_WINDOW {
+            monitor.record_gas_price(U256::from(20 * GWEI));
+        }
+        let report = monitor.evaluate(parse_ether("1").unwrap());
+        assert_eq!(report.transactions_remaining, Some(500));
+    }
+}
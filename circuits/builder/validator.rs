@@ -271,6 +271,7 @@ pub(crate) mod tests {
     use crate::input::tendermint_utils::{generate_proofs_from_header, proofs_from_byte_slices};
     use crate::input::utils::{convert_to_h256, get_path_indices};
     use crate::input::InputDataFetcher;
+    use crate::types::Height;
 
     type F = GoldilocksField;
 
@@ -404,8 +405,9 @@ pub(crate) mod tests {
         let rt = Runtime::new().expect("failed to create tokio runtime");
         let signed_header = rt.block_on(async {
             input_data_fetcher
-                .get_signed_header_from_number(10000u64)
+                .get_signed_header_from_number(Height(10000))
                 .await
+                .unwrap()
         });
 
         let (root, proofs) = generate_proofs_from_header(&signed_header.header);
@@ -0,0 +1,227 @@
+//! Catching up with the chain after an outage.
+//!
+//! A single skip can't be longer than the contract's `skip_max`, so a target far behind would only
+//! close `skip_max` blocks per iteration of the run loop. In catch-up mode the operator instead
+//! waits for each skip to land on-chain and submits the next one right away, until the target is
+//! less than `exit_lag` blocks behind. Every chunk starts from the contract's actual latest block,
+//! so a failed or late chunk is simply retried from wherever the contract is.
+
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use log::{debug, info, warn};
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DurationSeconds};
+
+/// The default lag in blocks under which catch-up mode ends.
+pub const DEFAULT_EXIT_LAG: u64 = 1000;
+
+/// The default time between reads of the contract's latest block while waiting for a chunk.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The default time to wait for a chunk to land on-chain.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2 * 60 * 60);
+
+/// The ranges of the consecutive skips of at most `skip_max` blocks from `latest_block` to
+/// `chain_head`. The actual target of each skip may be lower, where the validator set allows it.
+pub fn plan(latest_block: u64, chain_head: u64, skip_max: u64) -> Vec<(u64, u64)> {
+    let skip_max = skip_max.max(1);
+    let mut chunks = Vec::new();
+    let mut trusted_block = latest_block;
+    while trusted_block < chain_head {
+        let target_block = chain_head.min(trusted_block.saturating_add(skip_max));
+        chunks.push((trusted_block, target_block));
+        trusted_block = target_block;
+    }
+    chunks
+}
+
+/// Whether the operator is catching up, and when it stops. Serializes its settings only.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatchUp {
+    /// The lag in blocks under which catch-up mode ends.
+    #[serde(rename = "exit_lag_blocks")]
+    pub exit_lag: u64,
+    #[serde(rename = "poll_interval_secs")]
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub poll_interval: Duration,
+    /// The maximum time to wait for a chunk to land on-chain.
+    #[serde(rename = "timeout_secs")]
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub timeout: Duration,
+    #[serde(skip)]
+    active: bool,
+}
+
+impl CatchUp {
+    pub fn new(exit_lag: u64) -> Self {
+        Self {
+            exit_lag,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            timeout: DEFAULT_TIMEOUT,
+            active: false,
+        }
+    }
+
+    /// Start catching up now, even if no target is more than `skip_max` behind.
+    pub fn force(&mut self) {
+        self.active = true;
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.active
+    }
+
+    /// Record the chunks submitted in an iteration, each made when its targets were `lag` blocks
+    /// behind the chain head with a `skip_max` of their contract, and leaving them `remaining`
+    /// blocks behind. Returns whether to wait for the chunks and submit the next ones right away.
+    ///
+    /// Catch-up mode starts when a target is more than `skip_max` behind (or when forced), and
+    /// ends once no chunk leaves its targets more than `exit_lag` behind. An iteration without
+    /// chunks (e.g. as the previous ones are still pending) doesn't change the mode.
+    pub fn record_chunks(&mut self, chunks: impl IntoIterator<Item = (u64, u64, u64)>) -> bool {
+        let (mut any, mut behind) = (false, false);
+        for (lag, skip_max, remaining) in chunks {
+            any = true;
+            if lag > skip_max && !self.active {
+                info!(
+                    "Catching up: {} blocks behind with a skip_max of {}",
+                    lag, skip_max
+                );
+                self.active = true;
+            }
+            behind |= remaining > self.exit_lag;
+        }
+        if self.active && any && !behind {
+            info!("Caught up: less than {} blocks behind", self.exit_lag);
+            self.active = false;
+        }
+        self.active
+    }
+}
+
+/// Wait until `latest_block` returns at least `target_block`, reading it every `interval`.
+/// Failed reads are logged and retried. Returns the latest block, or an error after `timeout`.
+pub async fn wait_for_block<F, Fut>(
+    mut latest_block: F,
+    target_block: u64,
+    interval: Duration,
+    timeout: Duration,
+) -> Result<u64>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<u64>>,
+{
+    let wait = async {
+        loop {
+            match latest_block().await {
+                Ok(block) if block >= target_block => return block,
+                Ok(block) => debug!("Waiting for block {}, at {}", target_block, block),
+                Err(e) => warn!("Failed to read the latest block: {:#}", e),
+            }
+            tokio::time::sleep(interval).await;
+        }
+    };
+    tokio::time::timeout(timeout, wait)
+        .await
+        .map_err(|_| anyhow!("block {} was not stored within {:?}", target_block, timeout))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::Arc;
+
+    use super::*;
+
+    #[test]
+    fn test_plan() {
+        // The outage: 400k blocks behind with a skip_max of 100k takes four chunks.
+        assert_eq!(
+            plan(1_000_000, 1_400_000, 100_000),
+            [
+                (1_000_000, 1_100_000),
+                (1_100_000, 1_200_000),
+                (1_200_000, 1_300_000),
+                (1_300_000, 1_400_000),
+            ]
+        );
+        assert_eq!(
+            plan(1000, 1250, 100),
+            [(1000, 1100), (1100, 1200), (1200, 1250)]
+        );
+        assert_eq!(plan(1000, 1050, 100), [(1000, 1050)]);
+        assert!(plan(1000, 1000, 100).is_empty());
+        assert!(plan(1000, 900, 100).is_empty());
+    }
+
+    #[test]
+    fn test_record_chunks() {
+        let mut catch_up = CatchUp::new(1000);
+        // Slightly behind: a normal iteration.
+        assert!(!catch_up.record_chunks([(2400, 100_000, 0)]));
+        // After the outage, chunks follow each other until the target is caught up.
+        assert!(catch_up.record_chunks([(400_000, 100_000, 300_000)]));
+        assert!(catch_up.record_chunks([(300_050, 100_000, 200_050)]));
+        assert!(catch_up.record_chunks([]));
+        assert!(catch_up.record_chunks([(200_100, 100_000, 100_100)]));
+        assert!(!catch_up.record_chunks([(100_150, 100_000, 150)]));
+        assert!(!catch_up.is_active());
+
+        // With several targets, catching up goes on until the furthest one is caught up.
+        assert!(catch_up.record_chunks([(500, 100_000, 0), (150_000, 100_000, 50_000)]));
+        assert!(catch_up.record_chunks([(600, 100_000, 0), (50_020, 100_000, 1500)]));
+        assert!(!catch_up.record_chunks([(600, 100_000, 0), (1520, 100_000, 20)]));
+
+        // A forced catch-up starts right away and ends like an automatic one, after which catching
+        // up is automatic.
+        let mut catch_up = CatchUp::new(1000);
+        catch_up.force();
+        assert!(catch_up.is_active());
+        assert!(catch_up.record_chunks([(50_000, 100_000, 40_000)]));
+        assert!(!catch_up.record_chunks([(40_010, 100_000, 10)]));
+        assert!(!catch_up.record_chunks([(50_000, 100_000, 40_000)]));
+        assert!(!catch_up.record_chunks([]));
+    }
+
+    /// A contract whose latest block is advanced by a relayer in the background.
+    fn advancing_contract(blocks: &[u64], every: Duration) -> Arc<AtomicU64> {
+        let latest = Arc::new(AtomicU64::new(1000));
+        let (relayed, blocks) = (latest.clone(), blocks.to_vec());
+        tokio::spawn(async move {
+            for block in blocks {
+                tokio::time::sleep(every).await;
+                relayed.store(block, Ordering::SeqCst);
+            }
+        });
+        latest
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_block() {
+        let latest = advancing_contract(&[1100, 1200, 1300], Duration::from_millis(20));
+        let reads = AtomicU64::new(0);
+        let (reads, latest) = (&reads, latest.as_ref());
+        let read = move || async move {
+            // The RPC fails now and then.
+            if reads.fetch_add(1, Ordering::SeqCst) % 3 == 1 {
+                return Err(anyhow!("connection reset"));
+            }
+            Ok(latest.load(Ordering::SeqCst))
+        };
+        let interval = Duration::from_millis(5);
+        let timeout = Duration::from_secs(5);
+        assert_eq!(
+            wait_for_block(read, 1200, interval, timeout).await.unwrap(),
+            1200
+        );
+        assert!(reads.load(Ordering::SeqCst) > 3);
+
+        // A chunk that never lands times out, at the block the contract reached.
+        let result = wait_for_block(read, 1400, interval, Duration::from_millis(100)).await;
+        assert!(result.unwrap_err().to_string().contains("block 1400"));
+        assert_eq!(latest.load(Ordering::SeqCst), 1300);
+    }
+}
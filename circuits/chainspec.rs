@@ -0,0 +1,442 @@
+//! The constants of a Tendermint chain that bound what the operator requests: how long a trusted
+//! header can be skipped from, how fast blocks come, and how deep a header must be before it is
+//! proved.
+//!
+//! Presets cover common chains and are selected by name; every field can be overridden. Without
+//! a spec the operator only knows the contract's `skip_max`, as before.
+//!
+//! A skip whose trusted header is older than the trusting period at the target block is refused
+//! with a `TrustedStateExpired` error: no proof can update the light client from that header
+//! anymore, and the contract has to be reset by hand.
+//!
+//! The spec also selects how the responses of the chain's RPC are parsed (see `input::adapter`),
+//! for chains running a CometBFT variant.
+
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{anyhow, ensure, Result};
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DurationMilliSeconds, DurationSeconds};
+
+use crate::input::adapter::RpcAdapterKind;
+
+const HOUR: u64 = 60 * 60;
+const DAY: u64 = 24 * HOUR;
+
+/// The constants of a chain.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainSpec {
+    #[serde(rename = "name")]
+    pub name: String,
+    /// The time validators stay slashable after they unbond.
+    #[serde(rename = "unbonding_period_secs")]
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub unbonding_period: Duration,
+    /// The time a trusted header can be skipped from. Shorter than the unbonding period, so that
+    /// the validators that signed it are still slashable when the skip is verified.
+    #[serde(rename = "trusting_period_secs")]
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub trusting_period: Duration,
+    /// The expected time between blocks.
+    #[serde(rename = "block_time_ms")]
+    #[serde_as(as = "DurationMilliSeconds<u64>")]
+    pub block_time: Duration,
+    /// The size of the active validator set.
+    #[serde(rename = "max_validators")]
+    pub max_validators: usize,
+    /// The number of blocks a header must be behind the chain head before it is proved.
+    #[serde(rename = "confirmation_depth")]
+    pub confirmation_depth: u64,
+    /// The adapter parsing the responses of the chain's RPC.
+    #[serde(rename = "rpc_adapter", default)]
+    pub rpc_adapter: RpcAdapterKind,
+}
+
+/// The fields of a `ChainSpec` to override, if set.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChainSpecOverrides {
+    pub unbonding_period: Option<Duration>,
+    pub trusting_period: Option<Duration>,
+    pub block_time: Option<Duration>,
+    pub max_validators: Option<usize>,
+    pub confirmation_depth: Option<u64>,
+    pub rpc_adapter: Option<RpcAdapterKind>,
+}
+
+/// The names of the presets.
+pub const PRESETS: [&str; 3] = ["cosmoshub", "osmosis", "celestia"];
+
+impl ChainSpec {
+    /// The preset named `name`, if any. The trusting period is two thirds of the unbonding
+    /// period, as for IBC clients.
+    pub fn preset(name: &str) -> Option<Self> {
+        let (unbonding_days, block_time_ms, max_validators) = match name {
+            "cosmoshub" => (21, 6000, 180),
+            "osmosis" => (14, 5000, 150),
+            "celestia" => (21, 12000, 100),
+            _ => return None,
+        };
+        let unbonding_period = Duration::from_secs(unbonding_days * DAY);
+        Some(Self {
+            name: name.to_string(),
+            unbonding_period,
+            trusting_period: unbonding_period * 2 / 3,
+            block_time: Duration::from_millis(block_time_ms),
+            max_validators,
+            confirmation_depth: 1,
+            rpc_adapter: RpcAdapterKind::Tendermint,
+        })
+    }
+
+    /// `base` (a custom spec named "custom" if not given) with `overrides` applied. Without a
+    /// base, every field but the RPC adapter must be overridden.
+    pub fn with_overrides(base: Option<Self>, overrides: ChainSpecOverrides) -> Result<Self> {
+        let spec = match base {
+            Some(base) => Self {
+                name: base.name,
+                unbonding_period: overrides.unbonding_period.unwrap_or(base.unbonding_period),
+                trusting_period: overrides.trusting_period.unwrap_or(base.trusting_period),
+                block_time: overrides.block_time.unwrap_or(base.block_time),
+                max_validators: overrides.max_validators.unwrap_or(base.max_validators),
+                confirmation_depth: overrides
+                    .confirmation_depth
+                    .unwrap_or(base.confirmation_depth),
+                rpc_adapter: overrides.rpc_adapter.unwrap_or(base.rpc_adapter),
+            },
+            None => {
+                let missing = |field| anyhow!("a custom chain spec must set its {}", field);
+                Self {
+                    name: "custom".to_string(),
+                    unbonding_period: overrides
+                        .unbonding_period
+                        .ok_or_else(|| missing("unbonding period"))?,
+                    trusting_period: overrides
+                        .trusting_period
+                        .ok_or_else(|| missing("trusting period"))?,
+                    block_time: overrides.block_time.ok_or_else(|| missing("block time"))?,
+                    max_validators: overrides
+                        .max_validators
+                        .ok_or_else(|| missing("maximum validators"))?,
+                    confirmation_depth: overrides
+                        .confirmation_depth
+                        .ok_or_else(|| missing("confirmation depth"))?,
+                    rpc_adapter: overrides.rpc_adapter.unwrap_or_default(),
+                }
+            }
+        };
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    fn validate(&self) -> Result<()> {
+        ensure!(
+            self.trusting_period < self.unbonding_period,
+            "the trusting period of {} must be shorter than its unbonding period",
+            self.name
+        );
+        ensure!(
+            !self.block_time.is_zero(),
+            "the block time of {} must not be zero",
+            self.name
+        );
+        ensure!(
+            self.max_validators > 0,
+            "{} must have at least one validator",
+            self.name
+        );
+        Ok(())
+    }
+
+    /// The most blocks a skip can span: those the chain produces in the trusting period.
+    pub fn max_skip(&self) -> u64 {
+        self.blocks_in(self.trusting_period)
+    }
+
+    /// The number of blocks the chain produces in `duration`.
+    pub fn blocks_in(&self, duration: Duration) -> u64 {
+        (duration.as_millis() / self.block_time.as_millis()) as u64
+    }
+
+    /// The time the chain takes to produce `blocks` blocks.
+    pub fn duration_of(&self, blocks: u64) -> Duration {
+        Duration::from_millis((self.block_time.as_millis() as u64).saturating_mul(blocks))
+    }
+
+    /// Whether a header with time `header_time` can still be skipped from at `now`, both in unix
+    /// seconds.
+    pub fn is_trusted(&self, header_time: i64, now: i64) -> bool {
+        self.trusting_margin(header_time, now) > 0
+    }
+
+    /// The seconds left at `now` before a header with time `header_time` can't be skipped from
+    /// anymore, both in unix seconds. Zero or negative once the trusting period is over.
+    pub fn trusting_margin(&self, header_time: i64, now: i64) -> i64 {
+        (self.trusting_period.as_secs() as i64).saturating_sub(now.saturating_sub(header_time))
+    }
+
+    /// Check that a skip from `trusted_block` (with header time `trusted_time`) to `target_block`
+    /// (with header time `target_time`) is within the trusting period, so that the validators
+    /// that signed the trusted header can't have unbonded by the time it is verified.
+    pub fn validate_skip(
+        &self,
+        trusted_block: u64,
+        trusted_time: i64,
+        target_block: u64,
+        target_time: i64,
+    ) -> Result<()> {
+        ensure!(
+            target_block > trusted_block,
+            "target block {} is not after trusted block {}",
+            target_block,
+            trusted_block
+        );
+        if !self.is_trusted(trusted_time, target_time) {
+            return Err(TrustedStateExpired {
+                chain: self.name.clone(),
+                trusted_block,
+                target_block,
+                age_secs: target_time.saturating_sub(trusted_time),
+                trusting_period_secs: self.trusting_period.as_secs(),
+            }
+            .into());
+        }
+        Ok(())
+    }
+}
+
+/// A skip from a trusted header older than the trusting period. Retrying can't succeed: the
+/// contract's trusted state stays expired until it is reset.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TrustedStateExpired {
+    pub chain: String,
+    pub trusted_block: u64,
+    pub target_block: u64,
+    /// The seconds between the trusted header and the target header.
+    pub age_secs: i64,
+    pub trusting_period_secs: u64,
+}
+
+impl fmt::Display for TrustedStateExpired {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "trusted state expired: the header of block {} is {}s older than block {}, more than \
+             the trusting period of {} ({}s)",
+            self.trusted_block,
+            self.age_secs,
+            self.target_block,
+            self.chain,
+            self.trusting_period_secs
+        )
+    }
+}
+
+impl std::error::Error for TrustedStateExpired {}
+
+impl FromStr for ChainSpec {
+    type Err = anyhow::Error;
+
+    /// A preset, by name.
+    fn from_str(s: &str) -> Result<Self> {
+        Self::preset(&s.trim().to_lowercase()).ok_or_else(|| {
+            anyhow!(
+                "unknown chain spec {:?}, expected one of {}",
+                s,
+                PRESETS.join(", ")
+            )
+        })
+    }
+}
+
+impl fmt::Display for ChainSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} (unbonding {}h, trusting {}h, block time {:?}, skips of up to {} blocks)",
+            self.name,
+            self.unbonding_period.as_secs() / HOUR,
+            self.trusting_period.as_secs() / HOUR,
+            self.block_time,
+            self.max_skip()
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire::assert_snapshot;
+
+    #[test]
+    fn test_presets() {
+        for name in PRESETS {
+            let spec: ChainSpec = name.parse().unwrap();
+            assert_eq!(spec.name, name);
+            spec.validate().unwrap();
+        }
+        let celestia: ChainSpec = " Celestia ".parse().unwrap();
+        assert_eq!(celestia.trusting_period, Duration::from_secs(14 * DAY));
+        // 14 days of 12 second blocks.
+        assert_eq!(celestia.max_skip(), 100800);
+
+        let error = "cosmos".parse::<ChainSpec>().unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unknown chain spec \"cosmos\", expected one of cosmoshub, osmosis, celestia"
+        );
+    }
+
+    #[test]
+    fn test_overrides() {
+        let overrides = ChainSpecOverrides {
+            block_time: Some(Duration::from_secs(6)),
+            confirmation_depth: Some(3),
+            ..Default::default()
+        };
+        let spec = ChainSpec::with_overrides(ChainSpec::preset("celestia"), overrides).unwrap();
+        assert_eq!(spec.name, "celestia");
+        assert_eq!(spec.unbonding_period, Duration::from_secs(21 * DAY));
+        assert_eq!(spec.block_time, Duration::from_secs(6));
+        assert_eq!(spec.confirmation_depth, 3);
+        assert_eq!(spec.max_skip(), 201600);
+
+        // A trusting period must stay under the unbonding period.
+        let overrides = ChainSpecOverrides {
+            trusting_period: Some(Duration::from_secs(21 * DAY)),
+            ..Default::default()
+        };
+        assert!(ChainSpec::with_overrides(ChainSpec::preset("celestia"), overrides).is_err());
+
+        // A custom spec sets every field.
+        let mut overrides = ChainSpecOverrides {
+            unbonding_period: Some(Duration::from_secs(7 * DAY)),
+            trusting_period: Some(Duration::from_secs(5 * DAY)),
+            block_time: Some(Duration::from_millis(2500)),
+            max_validators: Some(50),
+            confirmation_depth: None,
+            rpc_adapter: None,
+        };
+        let error = ChainSpec::with_overrides(None, overrides.clone()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "a custom chain spec must set its confirmation depth"
+        );
+        overrides.confirmation_depth = Some(0);
+        let spec = ChainSpec::with_overrides(None, overrides.clone()).unwrap();
+        assert_eq!(spec.name, "custom");
+        assert_eq!(spec.rpc_adapter, RpcAdapterKind::Tendermint);
+        overrides.rpc_adapter = Some(RpcAdapterKind::Namada);
+        let spec = ChainSpec::with_overrides(None, overrides).unwrap();
+        assert_eq!(spec.rpc_adapter, RpcAdapterKind::Namada);
+        assert_eq!(spec.max_skip(), 5 * DAY * 1000 / 2500);
+    }
+
+    #[test]
+    fn test_serde_snapshot() {
+        let spec = ChainSpec::preset("celestia").unwrap();
+        assert_snapshot(&spec, include_str!("fixtures/serde/chain_spec.json"));
+
+        // Specs written before the adapters parse with the default one.
+        let json = r#"{"name": "custom", "unbonding_period_secs": 100, "trusting_period_secs": 50,
+            "block_time_ms": 1000, "max_validators": 4, "confirmation_depth": 0}"#;
+        let spec: ChainSpec = serde_json::from_str(json).unwrap();
+        assert_eq!(spec.rpc_adapter, RpcAdapterKind::Tendermint);
+    }
+
+    #[test]
+    fn test_conversions() {
+        let spec = ChainSpec::preset("osmosis").unwrap();
+        assert_eq!(spec.duration_of(12), Duration::from_secs(60));
+        assert_eq!(spec.blocks_in(Duration::from_secs(60)), 12);
+        assert_eq!(spec.duration_of(u64::MAX), Duration::from_millis(u64::MAX));
+    }
+
+    #[test]
+    fn test_validate_skip_respects_trusting_period() {
+        let spec = ChainSpec::preset("cosmoshub").unwrap();
+        let trusted_time = 1_700_000_000;
+        let trusting = spec.trusting_period.as_secs() as i64;
+
+        spec.validate_skip(100, trusted_time, 200, trusted_time + 6000)
+            .unwrap();
+        spec.validate_skip(100, trusted_time, 200, trusted_time + trusting - 1)
+            .unwrap();
+        // Past the trusting period, though still within the unbonding period.
+        assert!(spec
+            .validate_skip(100, trusted_time, 200, trusted_time + trusting)
+            .is_err());
+        assert!(spec
+            .validate_skip(
+                100,
+                trusted_time,
+                200,
+                trusted_time + spec.unbonding_period.as_secs() as i64
+            )
+            .is_err());
+        assert!(spec
+            .validate_skip(200, trusted_time, 100, trusted_time)
+            .is_err());
+
+        assert!(spec.is_trusted(trusted_time, trusted_time + trusting - 1));
+        assert!(!spec.is_trusted(trusted_time, trusted_time + trusting));
+    }
+
+    #[test]
+    fn test_trusted_state_expiry_boundary() {
+        let spec = ChainSpec::preset("celestia").unwrap();
+        let trusted_time = 1_700_000_000;
+        let trusting = spec.trusting_period.as_secs() as i64;
+
+        // Just inside: one second left.
+        assert_eq!(
+            spec.trusting_margin(trusted_time, trusted_time + trusting - 1),
+            1
+        );
+        spec.validate_skip(100, trusted_time, 200, trusted_time + trusting - 1)
+            .unwrap();
+
+        // At the boundary the trusted header has expired.
+        assert_eq!(
+            spec.trusting_margin(trusted_time, trusted_time + trusting),
+            0
+        );
+        let error = spec
+            .validate_skip(100, trusted_time, 200, trusted_time + trusting)
+            .unwrap_err();
+        let expired = error.downcast_ref::<TrustedStateExpired>().unwrap();
+        assert_eq!(expired.age_secs, trusting);
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "trusted state expired: the header of block 100 is {}s older than block 200, \
+                 more than the trusting period of celestia ({}s)",
+                trusting, trusting
+            )
+        );
+
+        // Just outside.
+        assert_eq!(
+            spec.trusting_margin(trusted_time, trusted_time + trusting + 1),
+            -1
+        );
+        let error = spec
+            .validate_skip(100, trusted_time, 200, trusted_time + trusting + 1)
+            .unwrap_err();
+        assert_eq!(
+            error
+                .downcast_ref::<TrustedStateExpired>()
+                .unwrap()
+                .age_secs,
+            trusting + 1
+        );
+
+        // A target before the trusted block isn't an expiry.
+        let error = spec
+            .validate_skip(200, trusted_time, 100, trusted_time + trusting + 1)
+            .unwrap_err();
+        assert!(error.downcast_ref::<TrustedStateExpired>().is_none());
+    }
+}
@@ -0,0 +1,382 @@
+//! A typed wrapper around the `TendermintX` light client contract.
+//!
+//! Downstream services should read the light client state through [`TendermintXContract`] rather
+//! than the raw `sol!` bindings, so that conventions like the zero-hash mapping live in one place.
+//! Calls are encoded and decoded with the bindings below and sent through any ethers
+//! [`Middleware`], which is only the transport.
+
+use std::sync::Arc;
+
+use alloy_primitives::{Address, B256};
+use alloy_sol_types::{SolCall, SolEvent};
+use anyhow::{anyhow, Context, Result};
+use ethers::providers::{Middleware, MiddlewareError};
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::{Filter, TransactionRequest, H160, H256};
+use tracing::instrument;
+
+use self::bindings::{
+    blockHeightToHeaderHashCall, latestBlockCall, setGenesisHeaderCall, skipFunctionIdCall,
+    stepFunctionIdCall, SKIP_MAXCall,
+};
+use crate::target::FunctionIds;
+
+/// The functions and events of the contract used by the operator.
+pub mod bindings {
+    use alloy_sol_types::sol;
+
+    // Note: Update the bindings, `abi/TendermintX.abi.json` and `abi::EXPECTED` when updating the
+    // contract. The build fails if the ABI no longer matches `abi::EXPECTED`.
+    sol! {
+        event HeadUpdate(uint64 blockNumber, bytes32 headerHash);
+
+        function latestBlock() external view returns (uint64);
+        function blockHeightToHeaderHash(uint64) external view returns (bytes32);
+        function SKIP_MAX() external view returns (uint64);
+        /// The function IDs the contract requests proofs with. Deployments predating them don't
+        /// have these getters.
+        function stepFunctionId() external view returns (bytes32);
+        function skipFunctionId() external view returns (bytes32);
+
+        /// Store the header of `_height` and make it the latest block, to seed a deployment.
+        function setGenesisHeader(uint64 _height, bytes32 _header) external;
+
+        /// The callback of a step request.
+        function step(uint64 _trustedBlock) external;
+        /// The callback of a skip request.
+        function skip(uint64 _trustedBlock, uint64 _targetBlock) external;
+    }
+}
+
+/// The entrypoint of contracts that also commit to the data hashes of header ranges
+/// (Blobstream-style). It isn't in `abi/TendermintX.abi.json`: only data commitment requests call
+/// it, and only when they are enabled.
+pub mod data_commitment_bindings {
+    use alloy_sol_types::sol;
+
+    sol! {
+        /// The callback of a data commitment request.
+        function commitHeaderRange(uint64 _trustedBlock, uint64 _targetBlock) external;
+    }
+}
+
+/// A `HeadUpdate` event emitted by the contract when a new header is stored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeadUpdate {
+    /// The Tendermint block height of the new header.
+    pub block_number: u64,
+    /// The header hash stored for `block_number`.
+    pub header_hash: [u8; 32],
+    /// The Ethereum block the event was emitted in.
+    pub eth_block_number: u64,
+    /// The Ethereum transaction that emitted the event.
+    pub tx_hash: [u8; 32],
+}
+
+/// Typed access to a deployed `TendermintX` contract through any ethers [`Middleware`].
+#[derive(Debug, Clone)]
+pub struct TendermintXContract<M> {
+    address: Address,
+    client: Arc<M>,
+}
+
+impl<M: Middleware + 'static> TendermintXContract<M> {
+    pub fn new(address: Address, client: Arc<M>) -> Self {
+        Self { address, client }
+    }
+
+    /// The address of the contract.
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    /// The latest block height stored by the light client.
+    #[instrument(skip_all, fields(contract = %self.address))]
+    pub async fn latest_block(&self) -> Result<u64> {
+        let output = self
+            .call(latestBlockCall {})
+            .await
+            .context("failed to read latestBlock from the TendermintX contract")?;
+        Ok(output._0)
+    }
+
+    /// The header hash stored for `height`.
+    ///
+    /// The contract returns the zero hash for heights that it has never stored, so the zero hash
+    /// is mapped to `None`. A `Some` value is always a header hash that was pushed to the contract.
+    #[instrument(skip(self), fields(contract = %self.address))]
+    pub async fn header_hash(&self, height: u64) -> Result<Option<[u8; 32]>> {
+        let output = self
+            .call(blockHeightToHeaderHashCall { _0: height })
+            .await
+            .with_context(|| {
+                format!(
+                    "failed to read blockHeightToHeaderHash({}) from the TendermintX contract",
+                    height
+                )
+            })?;
+        Ok(stored_header_hash(output._0.0))
+    }
+
+    /// The maximum number of blocks the contract allows a single skip to cover.
+    #[instrument(skip_all, fields(contract = %self.address))]
+    pub async fn skip_max(&self) -> Result<u64> {
+        let output = self
+            .call(SKIP_MAXCall {})
+            .await
+            .context("failed to read SKIP_MAX from the TendermintX contract")?;
+        Ok(output._0)
+    }
+
+    /// The step and skip function IDs the contract requests proofs with, `None` if it doesn't
+    /// expose them: if either getter reverts or returns nothing, as calls to functions a contract
+    /// doesn't have do, or returns the zero ID of a function that was never registered.
+    #[instrument(skip_all, fields(contract = %self.address))]
+    pub async fn function_ids(&self) -> Result<Option<FunctionIds>> {
+        let Some(step) = self
+            .call_optional(stepFunctionIdCall {})
+            .await
+            .context("failed to read stepFunctionId from the TendermintX contract")?
+        else {
+            return Ok(None);
+        };
+        let Some(skip) = self
+            .call_optional(skipFunctionIdCall {})
+            .await
+            .context("failed to read skipFunctionId from the TendermintX contract")?
+        else {
+            return Ok(None);
+        };
+        let (step, skip) = (step._0, skip._0);
+        if step == B256::ZERO || skip == B256::ZERO {
+            return Ok(None);
+        }
+        Ok(Some(FunctionIds { step, skip }))
+    }
+
+    /// All `HeadUpdate` events emitted since the Ethereum block `from_block`.
+    #[instrument(skip(self), fields(contract = %self.address))]
+    pub async fn head_updates(&self, from_block: u64) -> Result<Vec<HeadUpdate>> {
+        let filter = Filter::new()
+            .address(H160(self.address.0 .0))
+            .topic0(H256(bindings::HeadUpdate::SIGNATURE_HASH.0))
+            .from_block(from_block);
+        let logs =
+            self.client.get_logs(&filter).await.with_context(|| {
+                format!("failed to query HeadUpdate events from {}", from_block)
+            })?;
+        logs.into_iter()
+            .map(|log| {
+                let (block_number, header_hash) =
+                    bindings::HeadUpdate::abi_decode_data(&log.data, true)
+                        .context("invalid HeadUpdate event")?;
+                let (Some(eth_block_number), Some(tx_hash)) =
+                    (log.block_number, log.transaction_hash)
+                else {
+                    return Err(anyhow!("HeadUpdate event of a pending transaction"));
+                };
+                Ok(HeadUpdate {
+                    block_number,
+                    header_hash: header_hash.0,
+                    eth_block_number: eth_block_number.as_u64(),
+                    tx_hash: tx_hash.0,
+                })
+            })
+            .collect()
+    }
+
+    /// Store `header` as the header of `height` and make it the latest block, through a
+    /// transaction sent by the client, which must sign it. Returns the transaction hash once it
+    /// succeeded.
+    #[instrument(skip(self), fields(contract = %self.address))]
+    pub async fn set_genesis_header(&self, height: u64, header: [u8; 32]) -> Result<[u8; 32]> {
+        let call = setGenesisHeaderCall {
+            _height: height,
+            _header: header.into(),
+        };
+        let tx = TransactionRequest::new()
+            .to(H160(self.address.0 .0))
+            .data(call.abi_encode());
+        let pending = self
+            .client
+            .send_transaction(tx, None)
+            .await
+            .context("failed to send setGenesisHeader to the TendermintX contract")?;
+        let tx_hash = pending.tx_hash();
+        let receipt = pending
+            .await
+            .with_context(|| format!("failed to wait for setGenesisHeader tx {:?}", tx_hash))?
+            .ok_or_else(|| anyhow!("setGenesisHeader tx {:?} was dropped", tx_hash))?;
+        if receipt.status.map_or(true, |status| status.is_zero()) {
+            return Err(anyhow!("setGenesisHeader tx {:?} reverted", tx_hash));
+        }
+        Ok(tx_hash.0)
+    }
+
+    /// Call a view function of the contract.
+    async fn call<C: SolCall>(&self, call: C) -> Result<C::Return> {
+        let tx: TypedTransaction = TransactionRequest::new()
+            .to(H160(self.address.0 .0))
+            .data(call.abi_encode())
+            .into();
+        let output = self
+            .client
+            .call(&tx, None)
+            .await
+            .context("eth_call to the TendermintX contract failed")?;
+        Ok(C::abi_decode_returns(&output, true)?)
+    }
+
+    /// Call a view function the contract may not have: `None` if the call reverts or returns
+    /// nothing.
+    async fn call_optional<C: SolCall>(&self, call: C) -> Result<Option<C::Return>> {
+        let tx: TypedTransaction = TransactionRequest::new()
+            .to(H160(self.address.0 .0))
+            .data(call.abi_encode())
+            .into();
+        let output = match self.client.call(&tx, None).await {
+            Ok(output) => output,
+            Err(e) if is_revert(&e) => return Ok(None),
+            Err(e) => return Err(e).context("eth_call to the TendermintX contract failed"),
+        };
+        if output.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(C::abi_decode_returns(&output, true)?))
+    }
+}
+
+/// Whether `error` is the node reporting that the call reverted.
+fn is_revert<E: MiddlewareError>(error: &E) -> bool {
+    error
+        .as_error_response()
+        .is_some_and(|response| response.message.contains("revert"))
+}
+
+/// Map a raw `blockHeightToHeaderHash` value to `None` if the contract has no header stored.
+pub fn stored_header_hash(raw: [u8; 32]) -> Option<[u8; 32]> {
+    if raw == [0u8; 32] {
+        None
+    } else {
+        Some(raw)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::providers::{JsonRpcError, MockResponse, Provider};
+    use ethers::types::{Bytes, Log, U64};
+
+    use super::*;
+
+    #[test]
+    fn test_stored_header_hash() {
+        assert_eq!(stored_header_hash([0u8; 32]), None);
+
+        let mut hash = [0u8; 32];
+        hash[31] = 1;
+        assert_eq!(stored_header_hash(hash), Some(hash));
+    }
+
+    #[tokio::test]
+    async fn test_header_hash_maps_zero_to_none() {
+        let (provider, mock) = Provider::mocked();
+        let contract = TendermintXContract::new(Address::ZERO, Arc::new(provider));
+
+        // Responses are popped in reverse order of pushing.
+        let stored = [0xabu8; 32];
+        mock.push::<Bytes, _>(Bytes::from(stored.to_vec())).unwrap();
+        mock.push::<Bytes, _>(Bytes::from(vec![0u8; 32])).unwrap();
+
+        assert_eq!(contract.header_hash(10).await.unwrap(), None);
+        assert_eq!(contract.header_hash(11).await.unwrap(), Some(stored));
+    }
+
+    #[tokio::test]
+    async fn test_reads_decode_words() {
+        let (provider, mock) = Provider::mocked();
+        let contract = TendermintXContract::new(Address::ZERO, Arc::new(provider));
+
+        let mut word = [0u8; 32];
+        word[24..].copy_from_slice(&10500u64.to_be_bytes());
+        mock.push::<Bytes, _>(Bytes::from(word.to_vec())).unwrap();
+        mock.push::<Bytes, _>(Bytes::from(word.to_vec())).unwrap();
+        assert_eq!(contract.latest_block().await.unwrap(), 10500);
+        assert_eq!(contract.skip_max().await.unwrap(), 10500);
+
+        // A truncated word is not a valid return value.
+        mock.push::<Bytes, _>(Bytes::from(vec![0u8; 31])).unwrap();
+        assert!(contract.latest_block().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_function_ids() {
+        let (provider, mock) = Provider::mocked();
+        let contract = TendermintXContract::new(Address::ZERO, Arc::new(provider));
+
+        // A contract exposing the getters.
+        mock.push::<Bytes, _>(Bytes::from(vec![0x22; 32])).unwrap();
+        mock.push::<Bytes, _>(Bytes::from(vec![0x11; 32])).unwrap();
+        assert_eq!(
+            contract.function_ids().await.unwrap(),
+            Some(FunctionIds {
+                step: B256::repeat_byte(0x11),
+                skip: B256::repeat_byte(0x22),
+            })
+        );
+
+        // One without them: the call reverts, or returns nothing.
+        let revert = JsonRpcError {
+            code: 3,
+            message: "execution reverted".to_string(),
+            data: None,
+        };
+        mock.push_response(MockResponse::Error(revert));
+        assert_eq!(contract.function_ids().await.unwrap(), None);
+        mock.push::<Bytes, _>(Bytes::new()).unwrap();
+        assert_eq!(contract.function_ids().await.unwrap(), None);
+
+        // One that never registered a skip function.
+        mock.push::<Bytes, _>(Bytes::from(vec![0u8; 32])).unwrap();
+        mock.push::<Bytes, _>(Bytes::from(vec![0x11; 32])).unwrap();
+        assert_eq!(contract.function_ids().await.unwrap(), None);
+
+        // Other errors aren't mistaken for missing getters.
+        let unavailable = JsonRpcError {
+            code: -32000,
+            message: "header not found".to_string(),
+            data: None,
+        };
+        mock.push_response(MockResponse::Error(unavailable));
+        let error = contract.function_ids().await.unwrap_err();
+        assert!(format!("{:#}", error).contains("header not found"));
+    }
+
+    #[tokio::test]
+    async fn test_head_updates() {
+        let (provider, mock) = Provider::mocked();
+        let contract = TendermintXContract::new(Address::ZERO, Arc::new(provider));
+
+        let mut data = vec![0u8; 64];
+        data[24..32].copy_from_slice(&10500u64.to_be_bytes());
+        data[32..].copy_from_slice(&[0xab; 32]);
+        let log = Log {
+            topics: vec![H256(bindings::HeadUpdate::SIGNATURE_HASH.0)],
+            data: Bytes::from(data),
+            block_number: Some(U64::from(18_000_000)),
+            transaction_hash: Some(H256([0xcd; 32])),
+            ..Default::default()
+        };
+        mock.push::<Vec<Log>, _>(vec![log]).unwrap();
+
+        assert_eq!(
+            contract.head_updates(17_990_000).await.unwrap(),
+            vec![HeadUpdate {
+                block_number: 10500,
+                header_hash: [0xab; 32],
+                eth_block_number: 18_000_000,
+                tx_hash: [0xcd; 32],
+            }]
+        );
+    }
+}
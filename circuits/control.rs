@@ -0,0 +1,307 @@
+//! Pausing and resuming the operator's submissions at runtime.
+//!
+//! While paused the run loop keeps monitoring the targets (lag, consistency, balances, metrics and
+//! alerts) but never submits a request, so a contract can be maintained without restarting the
+//! operator and losing its state. The flag is toggled over a local Unix socket, one command per
+//! connection: `pause`, `resume` or `status`, answered with the resulting state.
+//!
+//! A chain registry also takes `chains`, `add-chain` followed by the `ChainEntry` of the chain as
+//! JSON, and `remove-chain` followed by a chain ID.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context, Result};
+
+use crate::registry::{ChainEntry, RegistryChanges};
+
+/// A command sent over the control socket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlCommand {
+    Pause,
+    Resume,
+    Status,
+    /// List the chains of the registry.
+    Chains,
+    AddChain(Box<ChainEntry>),
+    RemoveChain(String),
+}
+
+impl fmt::Display for ControlCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ControlCommand::Pause => f.write_str("pause"),
+            ControlCommand::Resume => f.write_str("resume"),
+            ControlCommand::Status => f.write_str("status"),
+            ControlCommand::Chains => f.write_str("chains"),
+            ControlCommand::AddChain(entry) => write!(
+                f,
+                "add-chain {}",
+                serde_json::to_string(entry).map_err(|_| fmt::Error)?
+            ),
+            ControlCommand::RemoveChain(chain_id) => write!(f, "remove-chain {}", chain_id),
+        }
+    }
+}
+
+impl FromStr for ControlCommand {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let s = s.trim();
+        let (command, argument) = s.split_once(' ').unwrap_or((s, ""));
+        match (command, argument.trim()) {
+            ("pause", "") => Ok(ControlCommand::Pause),
+            ("resume", "") => Ok(ControlCommand::Resume),
+            ("status", "") => Ok(ControlCommand::Status),
+            ("chains", "") => Ok(ControlCommand::Chains),
+            ("add-chain", entry) if !entry.is_empty() => {
+                let entry = serde_json::from_str(entry).context("invalid chain entry")?;
+                Ok(ControlCommand::AddChain(Box::new(entry)))
+            }
+            ("remove-chain", chain_id) if !chain_id.is_empty() => {
+                Ok(ControlCommand::RemoveChain(chain_id.to_string()))
+            }
+            _ => Err(anyhow!("unknown control command {:?}", s)),
+        }
+    }
+}
+
+/// The runtime controls of the operator, shared by the run loop and the control socket.
+#[derive(Debug, Default)]
+pub struct Control {
+    paused: AtomicBool,
+    /// The chains of the registry the operator runs in, if any.
+    registry: Option<Arc<RegistryChanges>>,
+}
+
+impl Control {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The controls of a chain registry, which also add and remove its chains.
+    pub fn with_registry(registry: Arc<RegistryChanges>) -> Self {
+        Self {
+            paused: AtomicBool::new(false),
+            registry: Some(registry),
+        }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::SeqCst)
+    }
+
+    /// The state of the operator, as answered on the control socket.
+    pub fn state(&self) -> &'static str {
+        if self.is_paused() {
+            "paused"
+        } else {
+            "running"
+        }
+    }
+
+    /// Apply `command`, returning the resulting state, or the chains of the registry for the
+    /// registry commands.
+    pub fn apply(&self, command: ControlCommand) -> Result<String> {
+        let registry = || {
+            self.registry
+                .as_ref()
+                .ok_or_else(|| anyhow!("the operator does not run a chain registry"))
+        };
+        match command {
+            ControlCommand::Pause => {
+                if !self.paused.swap(true, Ordering::SeqCst) {
+                    log::warn!("Submissions paused");
+                }
+            }
+            ControlCommand::Resume => {
+                if self.paused.swap(false, Ordering::SeqCst) {
+                    log::info!("Submissions resumed");
+                }
+            }
+            ControlCommand::Status => {}
+            ControlCommand::Chains => return Ok(registry()?.describe()),
+            ControlCommand::AddChain(entry) => return registry()?.add(*entry),
+            ControlCommand::RemoveChain(chain_id) => return registry()?.remove(&chain_id),
+        }
+        Ok(self.state().to_string())
+    }
+}
+
+#[cfg(unix)]
+mod socket {
+    use std::path::Path;
+    use std::sync::Arc;
+
+    use anyhow::{Context, Result};
+    use log::{info, warn};
+    use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+    use tokio::net::{UnixListener, UnixStream};
+
+    use super::{Control, ControlCommand};
+
+    async fn handle(control: &Control, stream: UnixStream) -> Result<()> {
+        let (read, mut write) = stream.into_split();
+        let mut line = String::new();
+        BufReader::new(read).read_line(&mut line).await?;
+        let reply = match line
+            .parse::<ControlCommand>()
+            .and_then(|c| control.apply(c))
+        {
+            Ok(reply) => reply,
+            Err(e) => format!("error: {:#}", e),
+        };
+        write.write_all(format!("{}\n", reply).as_bytes()).await?;
+        Ok(())
+    }
+
+    /// Serve `control` on a Unix socket at `path` until it fails. A stale socket file left by a
+    /// previous run is replaced.
+    pub async fn serve(control: Arc<Control>, path: impl AsRef<Path>) -> Result<()> {
+        let path = path.as_ref();
+        if path.exists() {
+            std::fs::remove_file(path)
+                .with_context(|| format!("failed to remove {}", path.display()))?;
+        }
+        let listener = UnixListener::bind(path)
+            .with_context(|| format!("failed to bind the control socket {}", path.display()))?;
+        info!("Serving controls on {}", path.display());
+        loop {
+            let (stream, _) = listener.accept().await?;
+            let control = control.clone();
+            tokio::spawn(async move {
+                if let Err(e) = handle(&control, stream).await {
+                    warn!("Control connection failed: {:#}", e);
+                }
+            });
+        }
+    }
+
+    /// Send `command` to the operator serving controls at `path`, returning its state.
+    pub async fn send(path: impl AsRef<Path>, command: ControlCommand) -> Result<String> {
+        let path = path.as_ref();
+        let mut stream = UnixStream::connect(path)
+            .await
+            .with_context(|| format!("failed to connect to {}", path.display()))?;
+        stream
+            .write_all(format!("{}\n", command).as_bytes())
+            .await?;
+        let mut reply = String::new();
+        BufReader::new(stream).read_line(&mut reply).await?;
+        let reply = reply.trim().to_string();
+        match reply.strip_prefix("error: ") {
+            Some(e) => Err(anyhow::anyhow!("{}", e)),
+            None => Ok(reply),
+        }
+    }
+}
+
+#[cfg(unix)]
+pub use socket::{send, serve};
+
+/// Control sockets are Unix sockets: serving them fails on other platforms.
+#[cfg(not(unix))]
+pub async fn serve(
+    _control: std::sync::Arc<Control>,
+    path: impl AsRef<std::path::Path>,
+) -> Result<()> {
+    Err(anyhow!("{} needs Unix sockets", path.as_ref().display()))
+}
+
+#[cfg(not(unix))]
+pub async fn send(path: impl AsRef<std::path::Path>, _command: ControlCommand) -> Result<String> {
+    Err(anyhow!("{} needs Unix sockets", path.as_ref().display()))
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::backend::mock::MockBackend;
+    use crate::backend::ProofBackend;
+    use crate::export::RequestInputs;
+    use crate::target::RequestTarget;
+    use crate::types::{HeaderHash, Height};
+
+    #[test]
+    fn test_apply() {
+        let control = Control::new();
+        let apply = |command| control.apply(command).unwrap();
+        assert_eq!(apply(ControlCommand::Status), "running");
+        assert_eq!(apply(ControlCommand::Pause), "paused");
+        assert_eq!(apply(ControlCommand::Pause), "paused");
+        assert!(control.is_paused());
+        assert_eq!(apply(ControlCommand::Resume), "running");
+        assert!("stop".parse::<ControlCommand>().is_err());
+        assert!("pause now".parse::<ControlCommand>().is_err());
+        assert_eq!(
+            "pause\n".parse::<ControlCommand>().unwrap(),
+            ControlCommand::Pause
+        );
+        assert_eq!(
+            control
+                .apply(ControlCommand::Chains)
+                .unwrap_err()
+                .to_string(),
+            "the operator does not run a chain registry"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_pause_mid_loop() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("control.sock");
+        let control = Arc::new(Control::new());
+        tokio::spawn(serve(control.clone(), path.clone()));
+        while !path.exists() {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+
+        // A loop submitting a request every iteration unless paused, like the run loop.
+        let backend = Arc::new(MockBackend::new());
+        let target = RequestTarget::for_test(5);
+        let (looping, submitting) = (control.clone(), backend.clone());
+        let iterations = tokio::spawn(async move {
+            for trusted_block in 1000.. {
+                if !looping.is_paused() {
+                    let trusted_block = Height(trusted_block);
+                    let inputs = RequestInputs::new(
+                        trusted_block,
+                        HeaderHash([0xab; 32]),
+                        trusted_block.next(),
+                    );
+                    let request = inputs.unwrap().proof_request(&target);
+                    submitting.request_step(&request).await.unwrap();
+                }
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        });
+
+        let until = |submissions: u64| {
+            let backend = backend.clone();
+            async move {
+                while backend.submission_attempts() < submissions {
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                }
+            }
+        };
+        until(3).await;
+        assert_eq!(send(&path, ControlCommand::Pause).await.unwrap(), "paused");
+        let paused_at = backend.submission_attempts();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(backend.submission_attempts(), paused_at);
+        assert_eq!(send(&path, ControlCommand::Status).await.unwrap(), "paused");
+
+        assert_eq!(
+            send(&path, ControlCommand::Resume).await.unwrap(),
+            "running"
+        );
+        until(paused_at + 3).await;
+        iterations.abort();
+    }
+}
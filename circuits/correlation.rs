@@ -0,0 +1,83 @@
+//! Correlation IDs, telling apart the proof attempts in flight at the same time.
+//!
+//! An ID is generated for each attempt when its range is planned. The attempt's span carries it,
+//! so every line logged for the attempt does, and it is recorded with the requests of the attempt
+//! in the request store and the audit log, in the alerts about them, and in the request itself
+//! for the backends that keep metadata.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Result};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use ulid::Ulid;
+
+/// The ID of a proof attempt: a ULID, so that IDs sort by the time they were generated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CorrelationId(Ulid);
+
+impl CorrelationId {
+    /// A new ID, for an attempt planned now.
+    pub fn generate() -> Self {
+        Self(Ulid::new())
+    }
+}
+
+impl fmt::Display for CorrelationId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for CorrelationId {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let ulid = Ulid::from_string(s.trim())
+            .map_err(|e| anyhow!("invalid correlation ID {:?}: {}", s, e))?;
+        Ok(Self(ulid))
+    }
+}
+
+impl Serialize for CorrelationId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for CorrelationId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_correlation_id() {
+        let id = CorrelationId::generate();
+        assert_eq!(id.to_string().len(), 26);
+        assert_eq!(id.to_string().parse::<CorrelationId>().unwrap(), id);
+        assert_ne!(CorrelationId::generate(), id);
+
+        let id: CorrelationId = "01HF3B8Y5ZQK4V9C2T7N6M1R0S".parse().unwrap();
+        assert_eq!(id.to_string(), "01HF3B8Y5ZQK4V9C2T7N6M1R0S");
+        // Crockford base32 is case insensitive.
+        assert_eq!(
+            "01hf3b8y5zqk4v9c2t7n6m1r0s"
+                .parse::<CorrelationId>()
+                .unwrap(),
+            id
+        );
+        assert!("req_1".parse::<CorrelationId>().is_err());
+
+        let json = "\"01HF3B8Y5ZQK4V9C2T7N6M1R0S\"";
+        assert_eq!(serde_json::to_string(&id).unwrap(), json);
+        assert_eq!(serde_json::from_str::<CorrelationId>(json).unwrap(), id);
+    }
+}
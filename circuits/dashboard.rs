@@ -0,0 +1,462 @@
+//! A live terminal view of the operator's targets for incidents: the lag and last update of each
+//! target, the relayer balances, the pending requests and the recent errors.
+//!
+//! The view is read-only. It is refreshed every few seconds from a `StatusSource`, the same
+//! collection as the plain `status` output (the `Display` of a `StatusSnapshot`), and `r` refreshes
+//! it immediately. `q`, Esc or Ctrl-C quit.
+
+use std::fmt;
+use std::io::{self, Stdout};
+use std::time::Duration;
+
+use alloy_primitives::B256;
+use anyhow::Result;
+use async_trait::async_trait;
+use crossterm::event::{Event, EventStream, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use ethers::utils::format_ether;
+use futures::StreamExt;
+use ratatui::backend::CrosstermBackend;
+use ratatui::layout::{Constraint, Direction, Layout};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::widgets::{Block, Borders, Paragraph, Row, Table};
+use ratatui::{Frame, Terminal};
+
+use crate::balance::BalanceReport;
+use crate::endpoint::EndpointHealth;
+use crate::lag::Lag;
+use crate::store::RequestStatus;
+use crate::target::FunctionIdSource;
+
+/// The default time between refreshes.
+pub const DEFAULT_REFRESH: Duration = Duration::from_secs(5);
+
+/// The number of recent errors shown.
+pub const RECENT_ERRORS: usize = 8;
+
+/// The state of a target.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TargetStatus {
+    pub target: String,
+    pub latest_block: u64,
+    /// The unix time of the header of the latest block.
+    pub updated_at: i64,
+    pub lag: Lag,
+    /// The balance of the target's relayer, if it has one and the check succeeded.
+    pub balance: Option<BalanceReport>,
+    /// The seconds left before the header of the latest block can't be skipped from at the chain
+    /// head, if the chain spec is known. Zero or negative once the trusted state expired.
+    pub trusting_margin: Option<i64>,
+    pub step_function_id: B256,
+    pub skip_function_id: B256,
+    /// Where the function IDs come from, once they were resolved.
+    pub function_ids_from: Option<FunctionIdSource>,
+}
+
+/// A request that is still pending.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PendingStatus {
+    pub request_id: String,
+    pub target: String,
+    pub target_block: u64,
+    pub status: RequestStatus,
+    /// The time since the request was submitted.
+    pub age: Duration,
+}
+
+/// Everything the dashboard shows, collected at once.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusSnapshot {
+    pub head_block: u64,
+    /// The unix time of the header of the chain head.
+    pub head_time: i64,
+    pub targets: Vec<TargetStatus>,
+    /// The health of the Tendermint and Ethereum RPC endpoints.
+    pub endpoints: Vec<EndpointHealth>,
+    /// The pending requests, empty without a request store.
+    pub pending: Vec<PendingStatus>,
+    /// The most recent errors, oldest first.
+    pub errors: Vec<String>,
+}
+
+/// Collects the snapshots shown by the dashboard.
+#[async_trait]
+pub trait StatusSource: Send {
+    async fn collect(&mut self) -> Result<StatusSnapshot>;
+}
+
+fn format_balance(report: &BalanceReport) -> String {
+    let mut balance = format_ether(report.balance);
+    if let Some(remaining) = report.transactions_remaining {
+        balance.push_str(&format!(" ({} transactions)", remaining));
+    }
+    if report.below_threshold {
+        balance.push_str(" LOW");
+    }
+    balance
+}
+
+impl StatusSnapshot {
+    /// The time between the header of the target's latest block and the chain head's.
+    fn since_update(&self, target: &TargetStatus) -> Duration {
+        Duration::from_secs(self.head_time.saturating_sub(target.updated_at).max(0) as u64)
+    }
+}
+
+impl fmt::Display for StatusSnapshot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Chain head: {}", self.head_block)?;
+        for target in self.targets.iter() {
+            write!(
+                f,
+                "{}: latest block {}, lag {}",
+                target.target, target.latest_block, target.lag
+            )?;
+            if let Some(margin) = target.trusting_margin {
+                if margin > 0 {
+                    write!(f, ", trusting period left {}s", margin)?;
+                } else {
+                    write!(f, ", TRUSTED STATE EXPIRED {}s ago", -margin)?;
+                }
+            }
+            if let Some(balance) = target.balance.as_ref() {
+                write!(f, ", relayer balance {}", format_balance(balance))?;
+            }
+            writeln!(f)?;
+            if let Some(source) = target.function_ids_from {
+                writeln!(
+                    f,
+                    "  step function ID {}, skip function ID {} (from the {})",
+                    target.step_function_id, target.skip_function_id, source
+                )?;
+            }
+        }
+        if !self.endpoints.is_empty() {
+            writeln!(f, "Endpoints:")?;
+            for endpoint in self.endpoints.iter() {
+                writeln!(f, "  {}", endpoint)?;
+            }
+        }
+        if !self.pending.is_empty() {
+            writeln!(f, "Pending requests:")?;
+            for pending in self.pending.iter() {
+                writeln!(
+                    f,
+                    "  {} {} block {}: {} for {:?}",
+                    pending.request_id,
+                    pending.target,
+                    pending.target_block,
+                    pending.status,
+                    pending.age
+                )?;
+            }
+        }
+        if !self.errors.is_empty() {
+            writeln!(f, "Recent errors:")?;
+            for error in self.errors.iter() {
+                writeln!(f, "  {}", error)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+const TARGET_WIDTHS: [Constraint; 5] = [
+    Constraint::Percentage(35),
+    Constraint::Percentage(12),
+    Constraint::Percentage(20),
+    Constraint::Percentage(13),
+    Constraint::Percentage(20),
+];
+
+const PENDING_WIDTHS: [Constraint; 5] = [
+    Constraint::Percentage(30),
+    Constraint::Percentage(35),
+    Constraint::Percentage(12),
+    Constraint::Percentage(11),
+    Constraint::Percentage(12),
+];
+
+fn header(cells: &[&'static str]) -> Row<'static> {
+    Row::new(cells.to_vec()).style(Style::default().add_modifier(Modifier::BOLD))
+}
+
+/// Draw `snapshot`, or a placeholder if none was collected yet, with `footer` below it.
+pub fn draw(frame: &mut Frame, snapshot: Option<&StatusSnapshot>, footer: &str) {
+    let Some(snapshot) = snapshot else {
+        let waiting = Paragraph::new(format!("Collecting the status...\n{}", footer));
+        frame.render_widget(waiting, frame.size());
+        return;
+    };
+    let errors = snapshot.errors.len().min(RECENT_ERRORS) as u16;
+    let areas = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(1),
+            Constraint::Length(snapshot.targets.len() as u16 + 3),
+            Constraint::Min(4),
+            Constraint::Length(errors.max(1) + 2),
+            Constraint::Length(1),
+        ])
+        .split(frame.size());
+
+    let title = format!("TendermintX: chain head {}", snapshot.head_block);
+    frame.render_widget(
+        Paragraph::new(title).style(Style::default().add_modifier(Modifier::BOLD)),
+        areas[0],
+    );
+
+    let targets = snapshot.targets.iter().map(|target| {
+        let balance = target
+            .balance
+            .as_ref()
+            .map_or("-".to_string(), format_balance);
+        let style = match target.balance.as_ref() {
+            Some(balance) if balance.below_threshold => Style::default().fg(Color::Red),
+            _ => Style::default(),
+        };
+        Row::new(vec![
+            target.target.clone(),
+            target.latest_block.to_string(),
+            target.lag.to_string(),
+            format!("{:?} ago", snapshot.since_update(target)),
+            balance,
+        ])
+        .style(style)
+    });
+    let targets = Table::new(targets)
+        .header(header(&[
+            "Target",
+            "Block",
+            "Lag",
+            "Updated",
+            "Relayer balance",
+        ]))
+        .block(Block::default().borders(Borders::ALL).title("Targets"))
+        .widths(&TARGET_WIDTHS);
+    frame.render_widget(targets, areas[1]);
+
+    let pending = snapshot.pending.iter().map(|pending| {
+        Row::new(vec![
+            pending.request_id.clone(),
+            pending.target.clone(),
+            pending.target_block.to_string(),
+            pending.status.to_string(),
+            format!("{:?}", pending.age),
+        ])
+    });
+    let pending = Table::new(pending)
+        .header(header(&["Request", "Target", "Block", "Status", "Age"]))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(format!("Pending requests ({})", snapshot.pending.len())),
+        )
+        .widths(&PENDING_WIDTHS);
+    frame.render_widget(pending, areas[2]);
+
+    let skip = snapshot.errors.len().saturating_sub(RECENT_ERRORS);
+    let errors = snapshot.errors[skip..].join("\n");
+    frame.render_widget(
+        Paragraph::new(errors)
+            .style(Style::default().fg(Color::Red))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title("Recent errors"),
+            ),
+        areas[3],
+    );
+
+    frame.render_widget(Paragraph::new(footer.to_string()), areas[4]);
+}
+
+/// The terminal in raw mode on the alternate screen, restored when dropped.
+struct TerminalGuard {
+    terminal: Terminal<CrosstermBackend<Stdout>>,
+}
+
+impl TerminalGuard {
+    fn enter() -> Result<Self> {
+        terminal::enable_raw_mode()?;
+        crossterm::execute!(io::stdout(), EnterAlternateScreen)?;
+        let mut terminal = Terminal::new(CrosstermBackend::new(io::stdout()))?;
+        terminal.hide_cursor()?;
+        Ok(Self { terminal })
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        let _ = terminal::disable_raw_mode();
+        let _ = crossterm::execute!(io::stdout(), LeaveAlternateScreen);
+        let _ = self.terminal.show_cursor();
+    }
+}
+
+/// What a key press asks for.
+enum Key {
+    Quit,
+    Refresh,
+    Ignored,
+}
+
+fn key(event: Event) -> Key {
+    let Event::Key(key) = event else {
+        return Key::Ignored;
+    };
+    if key.kind != KeyEventKind::Press {
+        return Key::Ignored;
+    }
+    match key.code {
+        KeyCode::Char('q') | KeyCode::Esc => Key::Quit,
+        KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => Key::Quit,
+        KeyCode::Char('r') => Key::Refresh,
+        _ => Key::Ignored,
+    }
+}
+
+/// Show the dashboard until the user quits, collecting a snapshot from `source` every `refresh`.
+pub async fn run(source: &mut dyn StatusSource, refresh: Duration) -> Result<()> {
+    let mut guard = TerminalGuard::enter()?;
+    let mut events = EventStream::new();
+    let mut snapshot = None;
+    loop {
+        let footer = match source.collect().await {
+            Ok(collected) => {
+                snapshot = Some(collected);
+                format!("Refreshing every {:?}. r: refresh now, q: quit", refresh)
+            }
+            Err(e) => format!("Refresh failed: {:#}. r: retry now, q: quit", e),
+        };
+        let next_refresh = tokio::time::sleep(refresh);
+        tokio::pin!(next_refresh);
+        loop {
+            guard
+                .terminal
+                .draw(|frame| draw(frame, snapshot.as_ref(), &footer))?;
+            tokio::select! {
+                _ = &mut next_refresh => break,
+                event = events.next() => match event {
+                    Some(Ok(event)) => match key(event) {
+                        Key::Quit => return Ok(()),
+                        Key::Refresh => break,
+                        // Redraw, e.g. after a resize.
+                        Key::Ignored => {}
+                    },
+                    Some(Err(e)) => return Err(e.into()),
+                    None => return Ok(()),
+                },
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ethers::types::U256;
+    use ratatui::backend::TestBackend;
+
+    use super::*;
+
+    fn snapshot() -> StatusSnapshot {
+        StatusSnapshot {
+            head_block: 1500,
+            head_time: 1_700_003_000,
+            targets: vec![
+                TargetStatus {
+                    target: "5:0x1111111111111111111111111111111111111111".to_string(),
+                    latest_block: 1000,
+                    updated_at: 1_700_000_000,
+                    lag: Lag::new(1500, 1_700_003_000, 1000, 1_700_000_000),
+                    balance: Some(BalanceReport {
+                        balance: U256::exp10(17),
+                        transactions_remaining: Some(20),
+                        below_threshold: true,
+                    }),
+                    trusting_margin: Some(-120),
+                    step_function_id: B256::repeat_byte(0x11),
+                    skip_function_id: B256::repeat_byte(0x12),
+                    function_ids_from: Some(FunctionIdSource::Contract),
+                },
+                TargetStatus {
+                    target: "10:0x2222222222222222222222222222222222222222".to_string(),
+                    latest_block: 1490,
+                    updated_at: 1_700_002_940,
+                    lag: Lag::new(1500, 1_700_003_000, 1490, 1_700_002_940),
+                    balance: None,
+                    trusting_margin: Some(2940),
+                    step_function_id: B256::repeat_byte(0x21),
+                    skip_function_id: B256::repeat_byte(0x22),
+                    function_ids_from: None,
+                },
+            ],
+            endpoints: vec![EndpointHealth {
+                kind: "tendermint",
+                host: "rpc.example.com".to_string(),
+                requests: 40,
+                errors: 3,
+                p95: Some(Duration::from_millis(250)),
+                demoted: true,
+            }],
+            pending: vec![PendingStatus {
+                request_id: "mock-0".to_string(),
+                target: "5:0x1111111111111111111111111111111111111111".to_string(),
+                target_block: 1500,
+                status: RequestStatus::Pending,
+                age: Duration::from_secs(600),
+            }],
+            errors: vec!["skip request failed for 5:0x11..: rate limited".to_string()],
+        }
+    }
+
+    #[test]
+    fn test_status_text() {
+        assert_eq!(
+            snapshot().to_string(),
+            format!(
+                "Chain head: 1500\n\
+                 5:0x1111111111111111111111111111111111111111: latest block 1000, lag 500 blocks \
+                 (3000s), TRUSTED STATE EXPIRED 120s ago, relayer balance 0.100000000000000000 \
+                 (20 transactions) LOW\n  \
+                 step function ID {}, skip function ID {} (from the contract)\n\
+                 10:0x2222222222222222222222222222222222222222: latest block 1490, lag 10 blocks \
+                 (60s), trusting period left 2940s\n\
+                 Endpoints:\n  \
+                 tendermint rpc.example.com: 40 requests, 3 errors, p95 250ms (demoted)\n\
+                 Pending requests:\n  \
+                 mock-0 5:0x1111111111111111111111111111111111111111 block 1500: pending for 600s\n\
+                 Recent errors:\n  \
+                 skip request failed for 5:0x11..: rate limited\n",
+                B256::repeat_byte(0x11),
+                B256::repeat_byte(0x12)
+            )
+        );
+    }
+
+    #[test]
+    fn test_draw() {
+        let mut terminal = Terminal::new(TestBackend::new(160, 20)).unwrap();
+        terminal
+            .draw(|frame| draw(frame, Some(&snapshot()), "r: refresh now, q: quit"))
+            .unwrap();
+        let buffer = terminal.backend().buffer();
+        let lines = buffer
+            .content
+            .chunks(buffer.area.width as usize)
+            .map(|row| {
+                row.iter()
+                    .map(|cell| cell.symbol.as_str())
+                    .collect::<String>()
+            })
+            .collect::<Vec<_>>();
+        let screen = lines.join("\n");
+        assert!(lines[0].starts_with("TendermintX: chain head 1500"));
+        assert!(screen.contains("500 blocks (3000s)"));
+        assert!(screen.contains("3000s ago"));
+        assert!(screen.contains("Pending requests (1)"));
+        assert!(screen.contains("mock-0"));
+        assert!(screen.contains("rate limited"));
+        assert!(lines[19].starts_with("r: refresh now, q: quit"));
+    }
+}
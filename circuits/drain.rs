@@ -0,0 +1,313 @@
+//! Draining the in-flight requests on shutdown.
+//!
+//! On shutdown the run loop stops planning new work, but the requests it was waiting for may still
+//! be fulfilled. The drain keeps polling them until each is settled or failed, or until the drain
+//! timeout, and records their final known status in the request store, so that the next run starts
+//! from an accurate record. A second shutdown signal aborts the drain right away, even while a
+//! status poll is hanging.
+
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use tokio::time::Instant;
+
+use crate::backend::ProofBackend;
+use crate::platform::FulfillmentStatus;
+use crate::store::{RequestStatus, RequestStore};
+use crate::target::RequestMode;
+
+/// The default maximum time to drain the in-flight requests.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// A request the run loop was waiting for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InFlight {
+    pub request_id: String,
+    pub mode: RequestMode,
+}
+
+/// What a drain did.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DrainSummary {
+    /// The requests fulfilled during the drain.
+    #[serde(rename = "settled")]
+    pub settled: usize,
+    #[serde(rename = "failed")]
+    pub failed: usize,
+    /// The requests still not settled when the drain ended.
+    #[serde(rename = "outstanding")]
+    pub outstanding: usize,
+    /// Whether the drain was aborted by a second signal.
+    #[serde(rename = "aborted")]
+    pub aborted: bool,
+}
+
+impl fmt::Display for DrainSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} settled, {} failed, {} still outstanding",
+            self.settled, self.failed, self.outstanding
+        )?;
+        if self.aborted {
+            f.write_str(" (aborted)")?;
+        }
+        Ok(())
+    }
+}
+
+/// Record the status of `request` in `store`, if there is one. Failures are logged.
+fn persist(store: Option<&RequestStore>, request: &InFlight, status: &FulfillmentStatus) {
+    let Some(store) = store else {
+        return;
+    };
+    let status = RequestStatus::from_fulfillment(status, request.mode);
+    if let Err(e) = store.update_status(&request.request_id, status) {
+        error!(
+            "Failed to record the status of request {}: {:#}",
+            request.request_id, e
+        );
+    }
+}
+
+/// Poll the status of `requests` every `poll_interval` until all of them are settled, `timeout`
+/// elapsed or `abort` resolves, recording each final known status in `store`.
+pub async fn drain(
+    requests: &[InFlight],
+    backend: &dyn ProofBackend,
+    store: Option<&RequestStore>,
+    poll_interval: Duration,
+    timeout: Duration,
+    abort: impl Future<Output = ()>,
+) -> DrainSummary {
+    let deadline = Instant::now() + timeout;
+    tokio::pin!(abort);
+    let mut summary = DrainSummary::default();
+    let mut remaining = requests
+        .iter()
+        .map(|request| (request, None))
+        .collect::<Vec<_>>();
+    'drain: loop {
+        let mut unsettled = Vec::new();
+        let mut polling = remaining.into_iter();
+        while let Some((request, last)) = polling.next() {
+            // A hanging poll doesn't hold up the abort or the timeout.
+            let status = tokio::select! {
+                status = backend.status(&request.request_id) => Some(status),
+                _ = &mut abort => {
+                    summary.aborted = true;
+                    None
+                }
+                _ = tokio::time::sleep_until(deadline) => None,
+            };
+            let Some(status) = status else {
+                unsettled.push((request, last));
+                unsettled.extend(polling);
+                remaining = unsettled;
+                break 'drain;
+            };
+            match status {
+                Ok(status) if status.is_settled(request.mode) => {
+                    persist(store, request, &status);
+                    match status {
+                        FulfillmentStatus::Failed { .. } => summary.failed += 1,
+                        _ => summary.settled += 1,
+                    }
+                }
+                Ok(status) => unsettled.push((request, Some(status))),
+                Err(e) => {
+                    warn!(
+                        "Failed to query the status of request {}: {:#}",
+                        request.request_id, e
+                    );
+                    unsettled.push((request, last));
+                }
+            }
+        }
+        remaining = unsettled;
+
+        let now = Instant::now();
+        if remaining.is_empty() || now >= deadline {
+            break;
+        }
+        tokio::select! {
+            _ = tokio::time::sleep(poll_interval.min(deadline - now)) => {}
+            _ = &mut abort => {
+                summary.aborted = true;
+                break;
+            }
+        }
+    }
+    for (request, last) in remaining.iter() {
+        if let Some(status) = last {
+            persist(store, request, status);
+        }
+    }
+    summary.outstanding = remaining.len();
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::backend::mock::MockBackend;
+    use crate::fault::{Calls, Fault, FaultInjecting, FaultSchedule};
+    use crate::store::tests::new_request;
+    use crate::wire::assert_snapshot;
+
+    /// Long enough that a poll never answers during a test.
+    const HANG: Duration = Duration::from_secs(60);
+
+    fn in_flight(request_id: &str) -> InFlight {
+        InFlight {
+            request_id: request_id.to_string(),
+            mode: RequestMode::Platform,
+        }
+    }
+
+    fn stored_status(store: &RequestStore, request_id: &str) -> RequestStatus {
+        store.get(request_id).unwrap().unwrap().status
+    }
+
+    #[test]
+    fn test_serde_snapshot() {
+        let summary = DrainSummary {
+            settled: 3,
+            failed: 1,
+            outstanding: 2,
+            aborted: false,
+        };
+        assert_snapshot(&summary, include_str!("fixtures/serde/drain_summary.json"));
+    }
+
+    #[tokio::test]
+    async fn test_drain() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RequestStore::open(dir.path().join("requests.db")).unwrap();
+        store.insert(&new_request("req_1", 100, 200)).unwrap();
+        store.insert(&new_request("req_2", 200, 300)).unwrap();
+        let backend = Arc::new(MockBackend::new());
+
+        // The requests are fulfilled and fail shortly after the shutdown signal.
+        let fulfilling = backend.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            fulfilling.set_status("req_1", FulfillmentStatus::Proved { proof_id: None });
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            let relayed = FulfillmentStatus::Relayed {
+                proof_id: None,
+                tx_hash: None,
+            };
+            fulfilling.set_status("req_1", relayed);
+            let failed = FulfillmentStatus::Failed { error: None };
+            fulfilling.set_status("req_2", failed);
+        });
+        let start = std::time::Instant::now();
+        let requests = [in_flight("req_1"), in_flight("req_2")];
+        let interval = Duration::from_millis(5);
+        let timeout = Duration::from_secs(5);
+        let summary = drain(
+            &requests,
+            backend.as_ref(),
+            Some(&store),
+            interval,
+            timeout,
+            std::future::pending(),
+        )
+        .await;
+        assert_eq!(
+            summary,
+            DrainSummary {
+                settled: 1,
+                failed: 1,
+                ..Default::default()
+            }
+        );
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(stored_status(&store, "req_1"), RequestStatus::Relayed);
+        assert_eq!(stored_status(&store, "req_2"), RequestStatus::Failed);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_and_abort() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RequestStore::open(dir.path().join("requests.db")).unwrap();
+        store.insert(&new_request("req_1", 100, 200)).unwrap();
+        let backend = MockBackend::new();
+        backend.set_status("req_1", FulfillmentStatus::Proved { proof_id: None });
+        let requests = [in_flight("req_1")];
+        let interval = Duration::from_millis(5);
+
+        // The drain ends at the timeout, with the last known status recorded.
+        let timeout = Duration::from_millis(50);
+        let summary = drain(
+            &requests,
+            &backend,
+            Some(&store),
+            interval,
+            timeout,
+            std::future::pending(),
+        )
+        .await;
+        assert_eq!((summary.outstanding, summary.aborted), (1, false));
+        assert_eq!(stored_status(&store, "req_1"), RequestStatus::Proved);
+
+        // A second signal aborts it right away.
+        let start = std::time::Instant::now();
+        let signal = tokio::time::sleep(Duration::from_millis(20));
+        let timeout = Duration::from_secs(5);
+        let summary = drain(&requests, &backend, Some(&store), interval, timeout, signal).await;
+        assert_eq!((summary.outstanding, summary.aborted), (1, true));
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(
+            summary.to_string(),
+            "0 settled, 0 failed, 1 still outstanding (aborted)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_hanging_poll() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RequestStore::open(dir.path().join("requests.db")).unwrap();
+        store.insert(&new_request("req_1", 100, 200)).unwrap();
+        store.insert(&new_request("req_2", 200, 300)).unwrap();
+        // The first poll of each request answers, the later ones hang.
+        let schedule = FaultSchedule::new().inject("status", Calls::From(3), Fault::Latency(HANG));
+        let backend = FaultInjecting::new(MockBackend::new(), schedule);
+        let proved = FulfillmentStatus::Proved { proof_id: None };
+        backend.inner().set_status("req_1", proved.clone());
+        backend.inner().set_status("req_2", proved);
+        let requests = [in_flight("req_1"), in_flight("req_2")];
+        let interval = Duration::from_millis(5);
+
+        // A second signal aborts the drain in the middle of a poll.
+        let start = std::time::Instant::now();
+        let signal = tokio::time::sleep(Duration::from_millis(50));
+        let timeout = Duration::from_secs(5);
+        let summary = drain(&requests, &backend, Some(&store), interval, timeout, signal).await;
+        assert_eq!((summary.outstanding, summary.aborted), (2, true));
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(stored_status(&store, "req_1"), RequestStatus::Proved);
+        assert_eq!(stored_status(&store, "req_2"), RequestStatus::Proved);
+
+        // So does the timeout.
+        let start = std::time::Instant::now();
+        let timeout = Duration::from_millis(50);
+        let summary = drain(
+            &requests,
+            &backend,
+            Some(&store),
+            interval,
+            timeout,
+            std::future::pending(),
+        )
+        .await;
+        assert_eq!((summary.outstanding, summary.aborted), (2, false));
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+}
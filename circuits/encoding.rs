@@ -0,0 +1,479 @@
+//! The packed inputs of the step, skip and data commitment circuits.
+//!
+//! The operator encodes these inputs with the `sol!` tuples below, and the circuits read them back
+//! field by field with `evm_read` (see `step.rs` and `skip.rs`). `decode` mirrors the circuits'
+//! byte layout independently of the `sol!` tuples, so `encode_checked` catches the two diverging
+//! before a request with a garbage input is submitted.
+//!
+//! The data commitment (header range) circuit takes the same fields as the skip circuit, in the
+//! same layout, and commits to the data hashes of the blocks from the trusted block to the target
+//! block.
+//!
+//! The free functions are what every request path (the run loop, `prove`, `export-input` and
+//! replays) encodes with: the packed circuit inputs, and the calldata of the callback the platform
+//! calls on the contract with the proof. They take `Height`s and `HeaderHash`es, and unwrap them
+//! into the raw ABI values here.
+//!
+//! `InputDigest` is what the gateway and the verifier check of an input: the gateway hashes the
+//! input with SHA-256 (the `inputHash` of its `Call` events and `InvalidProof` reverts), and the
+//! Groth16 verifier takes that hash truncated to its low 253 bits as its public input.
+
+use alloy_primitives::{hex, Bytes, B256};
+use alloy_sol_types::{sol, SolCall, SolType};
+use anyhow::{anyhow, ensure, Result};
+use log::debug;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::contract::bindings::{skipCall, stepCall};
+use crate::contract::data_commitment_bindings::commitHeaderRangeCall;
+use crate::types::{HeaderHash, Height};
+
+type StepInputTuple = sol! { tuple(uint64, bytes32) };
+
+type SkipInputTuple = sol! { tuple(uint64, bytes32, uint64) };
+
+type HeaderRangeInputTuple = sol! { tuple(uint64, bytes32, uint64) };
+
+/// The input of the step circuit: `(uint64 trusted_block, bytes32 trusted_header_hash)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StepInput {
+    #[serde(rename = "trusted_block")]
+    pub trusted_block: Height,
+    #[serde(rename = "trusted_header_hash")]
+    pub trusted_header_hash: HeaderHash,
+}
+
+/// The input of the skip circuit:
+/// `(uint64 trusted_block, bytes32 trusted_header_hash, uint64 target_block)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkipInput {
+    #[serde(rename = "trusted_block")]
+    pub trusted_block: Height,
+    #[serde(rename = "trusted_header_hash")]
+    pub trusted_header_hash: HeaderHash,
+    #[serde(rename = "target_block")]
+    pub target_block: Height,
+}
+
+/// The input of the data commitment circuit:
+/// `(uint64 trusted_block, bytes32 trusted_header_hash, uint64 target_block)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HeaderRangeInput {
+    #[serde(rename = "trusted_block")]
+    pub trusted_block: Height,
+    #[serde(rename = "trusted_header_hash")]
+    pub trusted_header_hash: HeaderHash,
+    #[serde(rename = "target_block")]
+    pub target_block: Height,
+}
+
+/// The packed input of a step from `trusted_block`.
+pub fn encode_step_input(trusted_block: Height, trusted_header_hash: HeaderHash) -> Vec<u8> {
+    StepInputTuple::abi_encode_packed(&(trusted_block.value(), trusted_header_hash.to_bytes()))
+}
+
+/// The packed input of a skip from `trusted_block` to `target_block`.
+pub fn encode_skip_input(
+    trusted_block: Height,
+    trusted_header_hash: HeaderHash,
+    target_block: Height,
+) -> Vec<u8> {
+    SkipInputTuple::abi_encode_packed(&(
+        trusted_block.value(),
+        trusted_header_hash.to_bytes(),
+        target_block.value(),
+    ))
+}
+
+/// The packed input of a data commitment from `trusted_block` to `target_block`.
+pub fn encode_header_range_input(
+    trusted_block: Height,
+    trusted_header_hash: HeaderHash,
+    target_block: Height,
+) -> Vec<u8> {
+    HeaderRangeInputTuple::abi_encode_packed(&(
+        trusted_block.value(),
+        trusted_header_hash.to_bytes(),
+        target_block.value(),
+    ))
+}
+
+/// The calldata of the `step(uint64)` callback.
+pub fn encode_step_calldata(trusted_block: Height) -> Vec<u8> {
+    stepCall {
+        _trustedBlock: trusted_block.value(),
+    }
+    .abi_encode()
+}
+
+/// The calldata of the `skip(uint64,uint64)` callback.
+pub fn encode_skip_calldata(trusted_block: Height, target_block: Height) -> Vec<u8> {
+    skipCall {
+        _trustedBlock: trusted_block.value(),
+        _targetBlock: target_block.value(),
+    }
+    .abi_encode()
+}
+
+/// The calldata of the `commitHeaderRange(uint64,uint64)` callback.
+pub fn encode_commit_header_range_calldata(trusted_block: Height, target_block: Height) -> Vec<u8> {
+    commitHeaderRangeCall {
+        _trustedBlock: trusted_block.value(),
+        _targetBlock: target_block.value(),
+    }
+    .abi_encode()
+}
+
+/// The digests of a packed input that the contracts check.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct InputDigest {
+    /// The SHA-256 of the input, as the gateway computes it.
+    #[serde(rename = "input_hash")]
+    pub input_hash: B256,
+    /// The input hash with its top 3 bits cleared, the public input of the Groth16 verifier.
+    #[serde(rename = "public_input")]
+    pub public_input: B256,
+}
+
+impl InputDigest {
+    pub fn of(input: &[u8]) -> Self {
+        let input_hash: [u8; 32] = Sha256::digest(input).into();
+        let mut public_input = input_hash;
+        public_input[0] &= 0x1f;
+        Self {
+            input_hash: B256::from(input_hash),
+            public_input: B256::from(public_input),
+        }
+    }
+}
+
+impl StepInput {
+    /// The length of the packed input in bytes.
+    pub const LEN: usize = 8 + 32;
+
+    pub fn encode(&self) -> Vec<u8> {
+        encode_step_input(self.trusted_block, self.trusted_header_hash)
+    }
+
+    pub fn decode(input: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(input, Self::LEN, "step")?;
+        Ok(Self {
+            trusted_block: reader.height(),
+            trusted_header_hash: reader.header_hash(),
+        })
+    }
+
+    /// Encode the input and check that it decodes back to the same fields.
+    pub fn encode_checked(&self) -> Result<Bytes> {
+        let input = self.encode();
+        check_round_trip("step", self, &Self::decode(&input)?, &input)?;
+        Ok(input.into())
+    }
+}
+
+impl SkipInput {
+    /// The length of the packed input in bytes.
+    pub const LEN: usize = 8 + 32 + 8;
+
+    pub fn encode(&self) -> Vec<u8> {
+        encode_skip_input(
+            self.trusted_block,
+            self.trusted_header_hash,
+            self.target_block,
+        )
+    }
+
+    pub fn decode(input: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(input, Self::LEN, "skip")?;
+        Ok(Self {
+            trusted_block: reader.height(),
+            trusted_header_hash: reader.header_hash(),
+            target_block: reader.height(),
+        })
+    }
+
+    /// Encode the input and check that it decodes back to the same fields.
+    pub fn encode_checked(&self) -> Result<Bytes> {
+        let input = self.encode();
+        check_round_trip("skip", self, &Self::decode(&input)?, &input)?;
+        Ok(input.into())
+    }
+}
+
+impl HeaderRangeInput {
+    /// The length of the packed input in bytes.
+    pub const LEN: usize = 8 + 32 + 8;
+
+    pub fn encode(&self) -> Vec<u8> {
+        encode_header_range_input(
+            self.trusted_block,
+            self.trusted_header_hash,
+            self.target_block,
+        )
+    }
+
+    pub fn decode(input: &[u8]) -> Result<Self> {
+        let mut reader = Reader::new(input, Self::LEN, "header range")?;
+        Ok(Self {
+            trusted_block: reader.height(),
+            trusted_header_hash: reader.header_hash(),
+            target_block: reader.height(),
+        })
+    }
+
+    /// Encode the input and check that it decodes back to the same fields.
+    pub fn encode_checked(&self) -> Result<Bytes> {
+        let input = self.encode();
+        check_round_trip("header range", self, &Self::decode(&input)?, &input)?;
+        Ok(input.into())
+    }
+}
+
+fn check_round_trip<T: PartialEq + std::fmt::Debug>(
+    kind: &str,
+    expected: &T,
+    decoded: &T,
+    input: &[u8],
+) -> Result<()> {
+    debug!(
+        "Encoded {} input {:?}: 0x{}",
+        kind,
+        expected,
+        hex::encode(input)
+    );
+    if expected != decoded {
+        return Err(anyhow!(
+            "{} input 0x{} decodes to {:?}, expected {:?}",
+            kind,
+            hex::encode(input),
+            decoded,
+            expected
+        ));
+    }
+    Ok(())
+}
+
+/// Reads big-endian fields in order, like `evm_read` in the circuits.
+struct Reader<'a> {
+    input: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn new(input: &'a [u8], len: usize, kind: &str) -> Result<Self> {
+        ensure!(
+            input.len() == len,
+            "{} input is {} bytes, expected {}",
+            kind,
+            input.len(),
+            len
+        );
+        Ok(Self { input })
+    }
+
+    fn take<const N: usize>(&mut self) -> [u8; N] {
+        let (field, rest) = self.input.split_at(N);
+        self.input = rest;
+        field.try_into().unwrap()
+    }
+
+    fn height(&mut self) -> Height {
+        Height(u64::from_be_bytes(self.take()))
+    }
+
+    fn header_hash(&mut self) -> HeaderHash {
+        HeaderHash(self.take())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wire::assert_snapshot;
+
+    /// Golden files pinning the packed layout the contracts and circuits expect.
+    const STEP_GOLDEN: &str = include_str!("fixtures/inputs/step.hex");
+    const SKIP_GOLDEN: &str = include_str!("fixtures/inputs/skip.hex");
+    const STEP_CALLDATA_GOLDEN: &str = include_str!("fixtures/inputs/step_calldata.hex");
+    const SKIP_CALLDATA_GOLDEN: &str = include_str!("fixtures/inputs/skip_calldata.hex");
+    const HEADER_RANGE_GOLDEN: &str = include_str!("fixtures/inputs/header_range.hex");
+    const COMMIT_HEADER_RANGE_CALLDATA_GOLDEN: &str =
+        include_str!("fixtures/inputs/commit_header_range_calldata.hex");
+
+    fn header_hash() -> HeaderHash {
+        let mut hash = [0u8; 32];
+        for (i, byte) in hash.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        HeaderHash(hash)
+    }
+
+    fn golden(file: &str) -> Vec<u8> {
+        hex::decode(file.trim()).unwrap()
+    }
+
+    #[test]
+    fn test_step_input_golden() {
+        let input = StepInput {
+            trusted_block: Height(0x0102030405060708),
+            trusted_header_hash: header_hash(),
+        };
+        let golden = golden(STEP_GOLDEN);
+        assert_eq!(input.encode(), golden);
+        assert_eq!(
+            encode_step_input(input.trusted_block, input.trusted_header_hash),
+            golden
+        );
+        assert_eq!(input.encode_checked().unwrap().as_ref(), &golden[..]);
+        assert_eq!(StepInput::decode(&golden).unwrap(), input);
+    }
+
+    #[test]
+    fn test_skip_input_golden() {
+        let input = SkipInput {
+            trusted_block: Height(10000),
+            trusted_header_hash: header_hash(),
+            target_block: Height(10500),
+        };
+        let golden = golden(SKIP_GOLDEN);
+        assert_eq!(input.encode(), golden);
+        assert_eq!(
+            encode_skip_input(Height(10000), header_hash(), Height(10500)),
+            golden
+        );
+        assert_eq!(input.encode_checked().unwrap().as_ref(), &golden[..]);
+        assert_eq!(SkipInput::decode(&golden).unwrap(), input);
+    }
+
+    #[test]
+    fn test_header_range_input_golden() {
+        let input = HeaderRangeInput {
+            trusted_block: Height(10000),
+            trusted_header_hash: header_hash(),
+            target_block: Height(10400),
+        };
+        let golden = golden(HEADER_RANGE_GOLDEN);
+        assert_eq!(input.encode(), golden);
+        assert_eq!(
+            encode_header_range_input(Height(10000), header_hash(), Height(10400)),
+            golden
+        );
+        assert_eq!(input.encode_checked().unwrap().as_ref(), &golden[..]);
+        assert_eq!(HeaderRangeInput::decode(&golden).unwrap(), input);
+    }
+
+    #[test]
+    fn test_step_calldata_golden() {
+        // `step(uint64)` has the selector 0x1f30e7c5.
+        assert_eq!(
+            encode_step_calldata(Height(0x0102030405060708)),
+            golden(STEP_CALLDATA_GOLDEN)
+        );
+    }
+
+    #[test]
+    fn test_skip_calldata_golden() {
+        // `skip(uint64,uint64)` has the selector 0xdbfaa342.
+        assert_eq!(
+            encode_skip_calldata(Height(10000), Height(10500)),
+            golden(SKIP_CALLDATA_GOLDEN)
+        );
+    }
+
+    #[test]
+    fn test_commit_header_range_calldata_golden() {
+        // `commitHeaderRange(uint64,uint64)` has the selector 0xb9feeae5.
+        assert_eq!(
+            encode_commit_header_range_calldata(Height(10000), Height(10400)),
+            golden(COMMIT_HEADER_RANGE_CALLDATA_GOLDEN)
+        );
+    }
+
+    #[test]
+    fn test_input_digest_golden() {
+        use crate::golden::CASES;
+
+        // The SHA-256 of the step and skip inputs of each case, computed independently of this
+        // crate over the packed big-endian fields.
+        let expected = [
+            (
+                "3d0ad12b8ee8928edf248ca91ca55600fb383f07c32bff1d6dec472b25cf59a7",
+                "611c901ea7925c5c4e8b8e1402b069af99e92c88f214f68866a6531694c571c8",
+            ),
+            (
+                "43bf37f102d23d4394523b6135586424fa87fb248871b0b8822ae80529d27bd0",
+                "0e34a82465a5b85065b8bed04acb7cab24ef9fcb8e9e3d49ee1ff09c149d82e2",
+            ),
+            (
+                "6b3b5d67c9d9ef71b94b1e81b88a4761ef4ca4cff5bb0030a485825229d1f135",
+                "2cd33432b27b90662f126cd19c56d023bf942b3588ce736d8e43c9a3a92717ca",
+            ),
+            (
+                "f685d3bd67e48ebf8e5597046a187b7adb03a7aa0b39a2b27d8a4860de8b6225",
+                "119ee744c10f249af458206fa2847ee5329fa816cc8859b0263df97460a801c0",
+            ),
+        ];
+        for (case, (step, skip)) in CASES.iter().zip(expected) {
+            let input = encode_step_input(case.trusted_block, case.trusted_header_hash);
+            let digest = InputDigest::of(&input);
+            assert_eq!(
+                digest.input_hash,
+                step.parse::<B256>().unwrap(),
+                "{}",
+                case.name
+            );
+            let input = encode_skip_input(
+                case.trusted_block,
+                case.trusted_header_hash,
+                case.target_block,
+            );
+            let digest = InputDigest::of(&input);
+            assert_eq!(
+                digest.input_hash,
+                skip.parse::<B256>().unwrap(),
+                "{}",
+                case.name
+            );
+        }
+
+        // The public input only differs from the hash in its top 3 bits.
+        let digest = InputDigest::of(&encode_step_input(
+            CASES[0].trusted_block,
+            CASES[0].trusted_header_hash,
+        ));
+        assert_eq!(
+            digest.public_input,
+            "0x1d0ad12b8ee8928edf248ca91ca55600fb383f07c32bff1d6dec472b25cf59a7"
+                .parse::<B256>()
+                .unwrap()
+        );
+        let digest = InputDigest::of(&encode_skip_input(
+            CASES[1].trusted_block,
+            CASES[1].trusted_header_hash,
+            CASES[1].target_block,
+        ));
+        assert_eq!(digest.public_input, digest.input_hash);
+    }
+
+    #[test]
+    fn test_serde_snapshots() {
+        let step = StepInput {
+            trusted_block: Height(0x0102030405060708),
+            trusted_header_hash: header_hash(),
+        };
+        assert_snapshot(&step, include_str!("fixtures/serde/step_input.json"));
+        let skip = SkipInput {
+            trusted_block: Height(10000),
+            trusted_header_hash: header_hash(),
+            target_block: Height(10500),
+        };
+        assert_snapshot(&skip, include_str!("fixtures/serde/skip_input.json"));
+    }
+
+    #[test]
+    fn test_decode_wrong_length() {
+        let golden = golden(SKIP_GOLDEN);
+        assert!(StepInput::decode(&golden).is_err());
+        assert!(SkipInput::decode(&golden[..StepInput::LEN]).is_err());
+    }
+}
@@ -0,0 +1,405 @@
+//! Failover across the RPC endpoints of a provider, with per-endpoint health.
+//!
+//! Calls go to the endpoints in their configured order, skipping to the next one when a call
+//! fails. An endpoint that fails `demote_after` consecutive calls is demoted behind the healthy
+//! ones, and promoted back to its place once a call to it succeeds again; both are logged with a
+//! summary of the endpoint's health. The request and error counts and the recent latencies of
+//! every endpoint are exported as metrics (labeled by host, never by the full URL, which often
+//! holds an API key) and shown by `status`.
+//!
+//! Ethereum providers use `FailoverHttp` (with the `operator` feature), a JSON-RPC transport over
+//! one `Http` client per endpoint. Errors returned by the node itself (e.g. a reverted call) are
+//! answers, not endpoint failures, and are returned as is.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[cfg(feature = "operator")]
+use anyhow::Context;
+#[cfg(feature = "operator")]
+use async_trait::async_trait;
+#[cfg(feature = "operator")]
+use ethers::providers::{Http, HttpClientError, JsonRpcClient};
+use log::{info, warn};
+#[cfg(feature = "operator")]
+use serde::de::DeserializeOwned;
+#[cfg(feature = "operator")]
+use serde::Serialize;
+
+/// The default number of consecutive failures after which an endpoint is demoted.
+pub const DEFAULT_DEMOTE_AFTER: u32 = 3;
+
+/// The number of recent latencies kept per endpoint for its p95.
+const LATENCY_WINDOW: usize = 100;
+
+/// The host (and port) of `url`, without its credentials, path or query.
+pub fn host(url: &str) -> String {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) => match (parsed.host_str(), parsed.port()) {
+            (Some(host), Some(port)) => format!("{}:{}", host, port),
+            (Some(host), None) => host.to_string(),
+            (None, _) => "unknown".to_string(),
+        },
+        Err(_) => "unknown".to_string(),
+    }
+}
+
+#[derive(Debug, Default)]
+struct EndpointState {
+    requests: u64,
+    errors: u64,
+    consecutive_failures: u32,
+    demoted: bool,
+    /// The latencies of the most recent calls, in seconds.
+    latencies: VecDeque<f64>,
+}
+
+impl EndpointState {
+    fn p95(&self) -> Option<Duration> {
+        if self.latencies.is_empty() {
+            return None;
+        }
+        let mut sorted = self.latencies.iter().copied().collect::<Vec<_>>();
+        sorted.sort_by(f64::total_cmp);
+        let rank = (sorted.len() as f64 * 0.95).ceil() as usize;
+        Some(Duration::from_secs_f64(
+            sorted[rank.clamp(1, sorted.len()) - 1],
+        ))
+    }
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    url: String,
+    host: String,
+    state: Mutex<EndpointState>,
+}
+
+/// The health of an endpoint, as exported and shown by `status`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EndpointHealth {
+    /// The kind of provider, `tendermint` or `ethereum`.
+    pub kind: &'static str,
+    pub host: String,
+    pub requests: u64,
+    pub errors: u64,
+    /// The 95th percentile of the recent latencies, if there were any calls.
+    pub p95: Option<Duration>,
+    pub demoted: bool,
+}
+
+impl fmt::Display for EndpointHealth {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}: {} requests, {} errors, p95 {}",
+            self.kind,
+            self.host,
+            self.requests,
+            self.errors,
+            self.p95
+                .map_or("unknown".to_string(), |p95| format!("{:?}", p95))
+        )?;
+        if self.demoted {
+            write!(f, " (demoted)")?;
+        }
+        Ok(())
+    }
+}
+
+/// The endpoints of a provider, in order of preference.
+#[derive(Debug)]
+pub struct EndpointPool {
+    kind: &'static str,
+    endpoints: Vec<Endpoint>,
+    demote_after: u32,
+}
+
+impl EndpointPool {
+    /// A pool of `urls` for a `kind` provider. Panics if `urls` is empty.
+    pub fn new(kind: &'static str, urls: Vec<String>) -> Self {
+        assert!(!urls.is_empty(), "no {} RPC endpoints", kind);
+        let endpoints = urls
+            .into_iter()
+            .map(|url| Endpoint {
+                host: host(&url),
+                url,
+                state: Mutex::new(EndpointState::default()),
+            })
+            .collect();
+        Self {
+            kind,
+            endpoints,
+            demote_after: DEFAULT_DEMOTE_AFTER,
+        }
+    }
+
+    pub fn with_demote_after(mut self, demote_after: u32) -> Self {
+        self.demote_after = demote_after.max(1);
+        self
+    }
+
+    pub fn urls(&self) -> Vec<String> {
+        self.endpoints.iter().map(|e| e.url.clone()).collect()
+    }
+
+    /// The indices of the endpoints in the order they are tried: the healthy endpoints, then the
+    /// demoted ones, each in their configured order.
+    fn order(&self) -> Vec<usize> {
+        let demoted = self
+            .endpoints
+            .iter()
+            .map(|e| e.state.lock().unwrap().demoted)
+            .collect::<Vec<_>>();
+        let mut order = (0..self.endpoints.len()).collect::<Vec<_>>();
+        order.sort_by_key(|&i| demoted[i]);
+        order
+    }
+
+    fn record(&self, index: usize, ok: bool, elapsed: Duration) {
+        let endpoint = &self.endpoints[index];
+        let mut state = endpoint.state.lock().unwrap();
+        state.requests += 1;
+        if state.latencies.len() == LATENCY_WINDOW {
+            state.latencies.pop_front();
+        }
+        state.latencies.push_back(elapsed.as_secs_f64());
+        if ok {
+            state.consecutive_failures = 0;
+            if state.demoted {
+                state.demoted = false;
+                info!("Promoted {}", self.health_of(endpoint, &state));
+            }
+            return;
+        }
+        state.errors += 1;
+        state.consecutive_failures += 1;
+        if !state.demoted && state.consecutive_failures >= self.demote_after {
+            state.demoted = true;
+            warn!(
+                "Demoted {} after {} consecutive failures",
+                self.health_of(endpoint, &state),
+                state.consecutive_failures
+            );
+        }
+    }
+
+    fn health_of(&self, endpoint: &Endpoint, state: &EndpointState) -> EndpointHealth {
+        EndpointHealth {
+            kind: self.kind,
+            host: endpoint.host.clone(),
+            requests: state.requests,
+            errors: state.errors,
+            p95: state.p95(),
+            demoted: state.demoted,
+        }
+    }
+
+    /// The health of every endpoint, in configured order.
+    pub fn health(&self) -> Vec<EndpointHealth> {
+        self.endpoints
+            .iter()
+            .map(|e| self.health_of(e, &e.state.lock().unwrap()))
+            .collect()
+    }
+
+    /// Call `call` with the index and URL of each endpoint in turn until it succeeds, or fails
+    /// with an error that isn't `retryable` (an answer of the endpoint rather than a failure of
+    /// it). Returns the last error if every endpoint failed.
+    pub async fn call<T, E, F, Fut>(
+        &self,
+        mut call: F,
+        retryable: impl Fn(&E) -> bool,
+    ) -> Result<T, E>
+    where
+        F: FnMut(usize, &str) -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: fmt::Display,
+    {
+        let mut last_error = None;
+        for index in self.order() {
+            let url = &self.endpoints[index].url;
+            let start = Instant::now();
+            let result = call(index, url).await;
+            match result {
+                Ok(value) => {
+                    self.record(index, true, start.elapsed());
+                    return Ok(value);
+                }
+                Err(e) if !retryable(&e) => {
+                    self.record(index, true, start.elapsed());
+                    return Err(e);
+                }
+                Err(e) => {
+                    self.record(index, false, start.elapsed());
+                    warn!(
+                        "{} RPC call to {} failed: {}",
+                        self.kind, self.endpoints[index].host, e
+                    );
+                    last_error = Some(e);
+                }
+            }
+        }
+        Err(last_error.expect("the pool has at least one endpoint"))
+    }
+}
+
+/// A JSON-RPC transport failing over across the endpoints of an `EndpointPool`.
+#[cfg(feature = "operator")]
+#[derive(Debug, Clone)]
+pub struct FailoverHttp {
+    pool: Arc<EndpointPool>,
+    clients: Vec<Http>,
+}
+
+#[cfg(feature = "operator")]
+impl FailoverHttp {
+    pub fn new(pool: Arc<EndpointPool>) -> anyhow::Result<Self> {
+        let clients = pool
+            .urls()
+            .iter()
+            .map(|url| {
+                url.parse::<Http>()
+                    .with_context(|| format!("invalid {} RPC url {}", pool.kind, host(url)))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        Ok(Self { pool, clients })
+    }
+
+    pub fn pool(&self) -> &Arc<EndpointPool> {
+        &self.pool
+    }
+}
+
+#[cfg(feature = "operator")]
+#[async_trait]
+impl JsonRpcClient for FailoverHttp {
+    type Error = HttpClientError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: fmt::Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        // The parameters are sent to every endpoint tried.
+        let params = serde_json::to_value(params).map_err(|err| HttpClientError::SerdeJson {
+            err,
+            text: String::new(),
+        })?;
+        self.pool
+            .call(
+                |index, _| self.clients[index].request(method, &params),
+                |e| !matches!(e, HttpClientError::JsonRpcError(_)),
+            )
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+
+    use anyhow::{anyhow, Result};
+
+    use super::*;
+
+    /// Calls failing on the endpoints with scripted failures left.
+    struct Script(RefCell<HashMap<usize, u32>>);
+
+    impl Script {
+        fn new(failures: &[(usize, u32)]) -> Self {
+            Self(RefCell::new(failures.iter().copied().collect()))
+        }
+
+        async fn call(&self, index: usize) -> Result<usize> {
+            let mut failures = self.0.borrow_mut();
+            match failures.get_mut(&index) {
+                Some(left) if *left > 0 => {
+                    *left -= 1;
+                    Err(anyhow!("endpoint {} is down", index))
+                }
+                _ => Ok(index),
+            }
+        }
+    }
+
+    async fn call(pool: &EndpointPool, script: &Script) -> Result<usize> {
+        pool.call(|index, _| script.call(index), |_| true).await
+    }
+
+    fn counts(pool: &EndpointPool) -> Vec<(u64, u64, bool)> {
+        pool.health()
+            .iter()
+            .map(|h| (h.requests, h.errors, h.demoted))
+            .collect()
+    }
+
+    #[test]
+    fn test_host() {
+        assert_eq!(
+            host("https://eth.example.com/v3/secret-key"),
+            "eth.example.com"
+        );
+        assert_eq!(host("http://user:pw@127.0.0.1:26657"), "127.0.0.1:26657");
+        assert_eq!(host("not a url"), "unknown");
+    }
+
+    #[tokio::test]
+    async fn test_failover_demotion() {
+        let pool = EndpointPool::new(
+            "tendermint",
+            vec![
+                "http://primary:26657".to_string(),
+                "http://secondary:26657".to_string(),
+            ],
+        )
+        .with_demote_after(2);
+        // The primary fails its first three calls.
+        let script = Script::new(&[(0, 3)]);
+
+        // Both calls fail over to the secondary, and the primary is demoted after the second.
+        assert_eq!(call(&pool, &script).await.unwrap(), 1);
+        assert_eq!(counts(&pool), [(1, 1, false), (1, 0, false)]);
+        assert_eq!(call(&pool, &script).await.unwrap(), 1);
+        assert_eq!(counts(&pool), [(2, 2, true), (2, 0, false)]);
+
+        // The demoted primary is no longer tried first.
+        assert_eq!(call(&pool, &script).await.unwrap(), 1);
+        assert_eq!(counts(&pool), [(2, 2, true), (3, 0, false)]);
+
+        // Once the secondary fails too, the primary is tried again. It fails once more, then
+        // recovers and is promoted, while the secondary is demoted in turn.
+        let script = Script::new(&[(0, 1), (1, 2)]);
+        assert!(call(&pool, &script).await.is_err());
+        assert_eq!(counts(&pool), [(3, 3, true), (4, 1, false)]);
+        assert_eq!(call(&pool, &script).await.unwrap(), 0);
+        assert_eq!(counts(&pool), [(4, 3, false), (5, 2, true)]);
+        assert_eq!(call(&pool, &script).await.unwrap(), 0);
+        assert_eq!(counts(&pool), [(5, 3, false), (5, 2, true)]);
+
+        let health = pool.health();
+        assert_eq!(health[0].host, "primary:26657");
+        assert!(health[0].p95.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_answers_are_not_failures() {
+        let pool = EndpointPool::new(
+            "ethereum",
+            vec!["http://a".to_string(), "http://b".to_string()],
+        );
+        let result = pool
+            .call(
+                |_, _| async { Err::<(), _>(anyhow!("execution reverted")) },
+                |_| false,
+            )
+            .await;
+        assert!(result.is_err());
+        // Only the first endpoint was asked, and it answered.
+        assert_eq!(counts(&pool), [(1, 0, false), (0, 0, false)]);
+    }
+}
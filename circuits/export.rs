@@ -0,0 +1,323 @@
+//! The artifacts submitted for a request, and their export for manual submission.
+//!
+//! `RequestInputs` builds the packed circuit input and the callback calldata of a request. The
+//! operator submits what it builds directly, and `export-input` writes the same bytes to a file
+//! (in the `FileBackend`'s JSON format) for `submit-input` to submit later.
+
+use std::str::FromStr;
+
+use alloy_primitives::{Address, Bytes, B256};
+use anyhow::{anyhow, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use tracing::{field, instrument, Span};
+
+use crate::backend::file::RequestFile;
+use crate::backend::{ProofRequest, RequestKind};
+use crate::encoding::{
+    encode_commit_header_range_calldata, encode_skip_calldata, encode_step_calldata,
+    HeaderRangeInput, SkipInput, StepInput,
+};
+use crate::target::RequestTarget;
+use crate::types::{HeaderHash, Height};
+
+/// The input and calldata of a request from `trusted_block` to `target_block`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestInputs {
+    #[serde(rename = "kind")]
+    pub kind: RequestKind,
+    #[serde(rename = "trusted_block")]
+    pub trusted_block: Height,
+    #[serde(rename = "trusted_header_hash")]
+    pub trusted_header_hash: HeaderHash,
+    #[serde(rename = "target_block")]
+    pub target_block: Height,
+    /// The calldata of the callback on the target contract.
+    #[serde(rename = "calldata")]
+    pub calldata: Bytes,
+    /// The packed circuit input.
+    #[serde(rename = "input")]
+    pub input: Bytes,
+}
+
+impl RequestInputs {
+    /// The inputs of the step or skip from `trusted_block` to `target_block`.
+    pub fn new(
+        trusted_block: Height,
+        trusted_header_hash: HeaderHash,
+        target_block: Height,
+    ) -> Result<Self> {
+        let kind = RequestKind::for_range(trusted_block.value(), target_block.value());
+        Self::of_kind(kind, trusted_block, trusted_header_hash, target_block)
+    }
+
+    /// The inputs of the data commitment over the headers from `trusted_block` to `target_block`.
+    pub fn data_commitment(
+        trusted_block: Height,
+        trusted_header_hash: HeaderHash,
+        target_block: Height,
+    ) -> Result<Self> {
+        ensure!(
+            trusted_block < target_block,
+            "no headers to commit to from {} to {}",
+            trusted_block,
+            target_block
+        );
+        let kind = RequestKind::DataCommitment;
+        Self::of_kind(kind, trusted_block, trusted_header_hash, target_block)
+    }
+
+    #[instrument(
+        name = "encode_inputs",
+        skip_all,
+        fields(
+            trusted_block = trusted_block.value(),
+            target_block = target_block.value(),
+            input_bytes = field::Empty,
+            calldata_bytes = field::Empty
+        )
+    )]
+    fn of_kind(
+        kind: RequestKind,
+        trusted_block: Height,
+        trusted_header_hash: HeaderHash,
+        target_block: Height,
+    ) -> Result<Self> {
+        let (input, calldata) = match kind {
+            RequestKind::Step => {
+                let input = StepInput {
+                    trusted_block,
+                    trusted_header_hash,
+                }
+                .encode_checked()?;
+                (input, encode_step_calldata(trusted_block))
+            }
+            RequestKind::Skip => {
+                let input = SkipInput {
+                    trusted_block,
+                    trusted_header_hash,
+                    target_block,
+                }
+                .encode_checked()?;
+                (input, encode_skip_calldata(trusted_block, target_block))
+            }
+            RequestKind::DataCommitment => {
+                let input = HeaderRangeInput {
+                    trusted_block,
+                    trusted_header_hash,
+                    target_block,
+                }
+                .encode_checked()?;
+                let calldata = encode_commit_header_range_calldata(trusted_block, target_block);
+                (input, calldata)
+            }
+        };
+        let span = Span::current();
+        span.record("input_bytes", input.len());
+        span.record("calldata_bytes", calldata.len());
+        Ok(Self {
+            kind,
+            trusted_block,
+            trusted_header_hash,
+            target_block,
+            calldata: calldata.into(),
+            input,
+        })
+    }
+
+    /// The request to submit for `target`.
+    pub fn proof_request<'a>(&self, target: &'a RequestTarget) -> ProofRequest<'a> {
+        ProofRequest {
+            target,
+            trusted_block: self.trusted_block.value(),
+            target_block: self.target_block.value(),
+            function_id: self.kind.function_id(target),
+            calldata: self.calldata.clone(),
+            input: self.input.clone(),
+            correlation_id: None,
+        }
+    }
+
+    /// The request for `target` in the `FileBackend`'s format.
+    pub fn export(&self, target: &RequestTarget) -> RequestFile {
+        RequestFile {
+            kind: self.kind.to_string(),
+            chain_id: target.chain_id,
+            address: target.address.to_string(),
+            trusted_block: self.trusted_block.value(),
+            target_block: self.target_block.value(),
+            function_id: self.kind.function_id(target).to_string(),
+            calldata: self.calldata.to_string(),
+            input: self.input.to_string(),
+            correlation_id: None,
+        }
+    }
+}
+
+/// The trusted header hash of the packed `kind` input `input`.
+pub fn trusted_header_hash(kind: RequestKind, input: &[u8]) -> Result<HeaderHash> {
+    Ok(match kind {
+        RequestKind::Step => StepInput::decode(input)?.trusted_header_hash,
+        RequestKind::Skip => SkipInput::decode(input)?.trusted_header_hash,
+        RequestKind::DataCommitment => HeaderRangeInput::decode(input)?.trusted_header_hash,
+    })
+}
+
+/// A request read back from an exported file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ImportedRequest {
+    pub chain_id: u32,
+    pub address: Address,
+    pub function_id: B256,
+    pub inputs: RequestInputs,
+}
+
+impl ImportedRequest {
+    /// Parse an exported request. The input and calldata must be exactly what `RequestInputs`
+    /// builds for the exported blocks and trusted header, so a hand-edited file is rejected.
+    pub fn from_file(file: &RequestFile) -> Result<Self> {
+        let kind = RequestKind::from_str(&file.kind)?;
+        let input = Bytes::from_str(&file.input).context("invalid input hex")?;
+        let calldata = Bytes::from_str(&file.calldata).context("invalid calldata hex")?;
+        let trusted_header_hash = trusted_header_hash(kind, &input)?;
+        let (trusted_block, target_block) = (Height(file.trusted_block), Height(file.target_block));
+        let inputs = match kind {
+            RequestKind::DataCommitment => {
+                RequestInputs::data_commitment(trusted_block, trusted_header_hash, target_block)?
+            }
+            _ => RequestInputs::new(trusted_block, trusted_header_hash, target_block)?,
+        };
+        ensure!(
+            inputs.kind == kind,
+            "{} request from {} to {} should be a {} request",
+            kind,
+            file.trusted_block,
+            file.target_block,
+            inputs.kind
+        );
+        ensure!(
+            inputs.input == input && inputs.calldata == calldata,
+            "the input or calldata does not match the request from {} to {}",
+            file.trusted_block,
+            file.target_block
+        );
+        Ok(Self {
+            chain_id: file.chain_id,
+            address: file.address.parse().context("invalid address")?,
+            function_id: file.function_id.parse().context("invalid function id")?,
+            inputs,
+        })
+    }
+
+    /// The request to submit for `target`, which must be the target it was exported for.
+    pub fn proof_request<'a>(&self, target: &'a RequestTarget) -> Result<ProofRequest<'a>> {
+        let function_id = self.inputs.kind.function_id(target);
+        if (target.chain_id, target.address, function_id)
+            != (self.chain_id, self.address, self.function_id)
+        {
+            return Err(anyhow!(
+                "the request was exported for {}:{} with function id {}, not {} with {}",
+                self.chain_id,
+                self.address,
+                self.function_id,
+                target,
+                function_id
+            ));
+        }
+        Ok(self.inputs.proof_request(target))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::mock::MockBackend;
+    use crate::backend::ProofBackend;
+    use crate::wire::assert_snapshot;
+
+    fn target() -> RequestTarget {
+        RequestTarget {
+            data_commitment_function_id: Some(B256::repeat_byte(0x55)),
+            ..RequestTarget::for_test(5)
+        }
+    }
+
+    #[test]
+    fn test_serde_snapshot() {
+        let inputs =
+            RequestInputs::new(Height(10000), HeaderHash([0x11; 32]), Height(10500)).unwrap();
+        assert_snapshot(&inputs, include_str!("fixtures/serde/request_inputs.json"));
+    }
+
+    #[tokio::test]
+    async fn test_export_round_trip() {
+        let target = target();
+        let hash = HeaderHash([0xab; 32]);
+        for inputs in [
+            RequestInputs::new(Height(100), hash, Height(101)),
+            RequestInputs::new(Height(100), hash, Height(500)),
+            RequestInputs::data_commitment(Height(100), hash, Height(500)),
+        ] {
+            let inputs = inputs.unwrap();
+            let json = serde_json::to_string_pretty(&inputs.export(&target)).unwrap();
+            let file: RequestFile = serde_json::from_str(&json).unwrap();
+            let imported = ImportedRequest::from_file(&file).unwrap();
+            assert_eq!(imported.inputs, inputs);
+
+            // Submitting the imported request sends the same bytes as the direct path.
+            let backend = MockBackend::new();
+            let direct = inputs.proof_request(&target);
+            let exported = imported.proof_request(&target).unwrap();
+            match inputs.kind {
+                RequestKind::Step => {
+                    backend.request_step(&direct).await.unwrap();
+                    backend.request_step(&exported).await.unwrap();
+                }
+                RequestKind::Skip => {
+                    backend.request_skip(&direct).await.unwrap();
+                    backend.request_skip(&exported).await.unwrap();
+                }
+                RequestKind::DataCommitment => {
+                    backend.request_data_commitment(&direct).await.unwrap();
+                    backend.request_data_commitment(&exported).await.unwrap();
+                }
+            }
+            let requests = backend.requests();
+            assert_eq!(requests[0].kind, inputs.kind);
+            assert_eq!(requests[0].function_id, requests[1].function_id);
+            assert_eq!(requests[0].calldata, requests[1].calldata);
+            assert_eq!(requests[0].input, requests[1].input);
+        }
+    }
+
+    #[test]
+    fn test_import_rejects_mismatches() {
+        let target = target();
+        let inputs = RequestInputs::new(Height(100), HeaderHash([0xab; 32]), Height(500)).unwrap();
+        let file = inputs.export(&target);
+
+        // The calldata must match the exported blocks.
+        let mut edited = file.clone();
+        edited.target_block = 600;
+        assert!(ImportedRequest::from_file(&edited).is_err());
+        let mut edited = file.clone();
+        edited.kind = "step".to_string();
+        assert!(ImportedRequest::from_file(&edited).is_err());
+        // So must the callback of the kind.
+        let mut edited = file.clone();
+        edited.kind = "data_commitment".to_string();
+        assert!(ImportedRequest::from_file(&edited).is_err());
+
+        // The request can only be submitted for the target it was exported for.
+        let imported = ImportedRequest::from_file(&file).unwrap();
+        let other = RequestTarget {
+            chain_id: 6,
+            ..target.clone()
+        };
+        assert!(imported.proof_request(&other).is_err());
+        let other = RequestTarget {
+            skip_function_id: B256::repeat_byte(0x44),
+            ..target
+        };
+        assert!(imported.proof_request(&other).is_err());
+    }
+}
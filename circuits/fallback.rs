@@ -0,0 +1,220 @@
+//! Falling back to steps when skips from a trusted block keep failing.
+//!
+//! A skip range that keeps failing in the circuit stalls the contract: every iteration requests
+//! the same range again. After `threshold` consecutive failures of skips from the same trusted
+//! block, the fallback engages, and requests single steps (or skips of at most `hop` blocks, and a
+//! step from any block such a skip failed from) until the contract is past the target of the
+//! failing skip, then normal operation resumes. Failures are counted per trusted block, so that
+//! unrelated failures never add up to a fallback.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use serde::{Deserialize, Serialize};
+
+/// The default number of consecutive skip failures from a trusted block that engage the fallback.
+pub const DEFAULT_THRESHOLD: u32 = 3;
+
+/// A change in the state of the fallback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FallbackTransition {
+    /// `failures` skips from `trusted_block` failed in a row: the fallback lasts until the
+    /// contract is at `until`.
+    Engaged {
+        trusted_block: u64,
+        until: u64,
+        failures: u32,
+    },
+    /// The contract reached `block`, past the region the fallback was engaged for.
+    Disengaged { block: u64 },
+}
+
+#[derive(Debug, Default)]
+struct State {
+    /// The consecutive skip failures, by trusted block.
+    failures: HashMap<u64, u32>,
+    /// The block the contract must reach for the fallback to disengage, while engaged.
+    until: Option<u64>,
+}
+
+/// Serializes its settings only, not whether it is engaged.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct StepFallback {
+    #[serde(rename = "threshold")]
+    pub threshold: u32,
+    /// The largest range requested while engaged: 1 for steps.
+    #[serde(rename = "hop_blocks")]
+    pub hop: u64,
+    #[serde(skip)]
+    state: Mutex<State>,
+}
+
+impl StepFallback {
+    pub fn new(threshold: u32, hop: u64) -> Self {
+        Self {
+            threshold,
+            hop: hop.max(1),
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    pub fn is_engaged(&self) -> bool {
+        self.state.lock().unwrap().until.is_some()
+    }
+
+    /// Record that the skip from `trusted_block` to `target_block` failed. Returns the transition
+    /// if this engages the fallback; a failure while engaged extends it to `target_block`.
+    pub fn record_failure(
+        &self,
+        trusted_block: u64,
+        target_block: u64,
+    ) -> Option<FallbackTransition> {
+        let mut state = self.state.lock().unwrap();
+        let failures = state.failures.entry(trusted_block).or_default();
+        *failures += 1;
+        let failures = *failures;
+        if failures < self.threshold {
+            return None;
+        }
+        match state.until {
+            Some(until) => {
+                state.until = Some(until.max(target_block));
+                None
+            }
+            None => {
+                state.until = Some(target_block);
+                Some(FallbackTransition::Engaged {
+                    trusted_block,
+                    until: target_block,
+                    failures,
+                })
+            }
+        }
+    }
+
+    /// Record that a request from `trusted_block` succeeded, which resets its failures.
+    pub fn record_success(&self, trusted_block: u64) {
+        self.state.lock().unwrap().failures.remove(&trusted_block);
+    }
+
+    /// The block to request from `current` instead of the selected `target`: while engaged, at
+    /// most `hop` blocks ahead, or the next block if a skip from `current` failed; `target`
+    /// otherwise. Returns the transition if the contract is past the failing region, which
+    /// disengages the fallback.
+    pub fn target(&self, current: u64, target: u64) -> (u64, Option<FallbackTransition>) {
+        let mut state = self.state.lock().unwrap();
+        let Some(until) = state.until else {
+            return (target, None);
+        };
+        if current >= until {
+            state.until = None;
+            state.failures.retain(|&block, _| block >= current);
+            return (
+                target,
+                Some(FallbackTransition::Disengaged { block: current }),
+            );
+        }
+        if state.failures.contains_key(&current) {
+            return (current + 1, None);
+        }
+        (target.min(until).min(current + self.hop), None)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A backend on which every skip from a trusted block in `bad` fails, and everything else
+    /// lands.
+    struct ScriptedBackend {
+        bad: std::ops::Range<u64>,
+    }
+
+    impl ScriptedBackend {
+        fn prove(&self, trusted_block: u64, target_block: u64) -> bool {
+            target_block == trusted_block + 1 || !self.bad.contains(&trusted_block)
+        }
+    }
+
+    /// Run `iterations` of a loop selecting skips of 100 blocks from `start`, returning the
+    /// transitions and the requested ranges.
+    fn run(
+        fallback: &StepFallback,
+        backend: &ScriptedBackend,
+        start: u64,
+        iterations: usize,
+    ) -> (Vec<FallbackTransition>, Vec<(u64, u64)>) {
+        let (mut transitions, mut requests) = (Vec::new(), Vec::new());
+        let mut current = start;
+        for _ in 0..iterations {
+            let (target, transition) = fallback.target(current, current + 100);
+            transitions.extend(transition);
+            requests.push((current, target));
+            if backend.prove(current, target) {
+                fallback.record_success(current);
+                current = target;
+            } else if target > current + 1 {
+                transitions.extend(fallback.record_failure(current, target));
+            }
+        }
+        (transitions, requests)
+    }
+
+    #[test]
+    fn test_engage_and_disengage() {
+        use FallbackTransition::*;
+
+        // Skips from 1000 fail: after 3 failures, the loop steps to 1100 and then skips again.
+        let fallback = StepFallback::new(3, 1);
+        let backend = ScriptedBackend { bad: 1000..1001 };
+        let (transitions, requests) = run(&fallback, &backend, 1000, 105);
+        assert_eq!(
+            transitions,
+            [
+                Engaged {
+                    trusted_block: 1000,
+                    until: 1100,
+                    failures: 3
+                },
+                Disengaged { block: 1100 }
+            ]
+        );
+        assert_eq!(
+            requests[..4],
+            [(1000, 1100), (1000, 1100), (1000, 1100), (1000, 1001)]
+        );
+        assert_eq!(requests[102], (1099, 1100));
+        assert_eq!(requests[103..], [(1100, 1200), (1200, 1300)]);
+        assert!(!fallback.is_engaged());
+
+        // Small skips instead of steps: a small skip that fails too is followed by a step.
+        let fallback = StepFallback::new(2, 10);
+        let backend = ScriptedBackend { bad: 1000..1030 };
+        let (transitions, requests) = run(&fallback, &backend, 1000, 70);
+        assert_eq!(transitions.len(), 2);
+        assert_eq!(requests[2..5], [(1000, 1001), (1001, 1011), (1001, 1002)]);
+        assert_eq!(requests[61..63], [(1030, 1040), (1040, 1050)]);
+        assert_eq!(requests[68..], [(1100, 1200), (1200, 1300)]);
+    }
+
+    #[test]
+    fn test_failures_by_trusted_block() {
+        // Failures from different trusted blocks don't add up.
+        let fallback = StepFallback::new(3, 1);
+        for trusted_block in [1000, 2000, 1000, 3000, 2000] {
+            assert_eq!(
+                fallback.record_failure(trusted_block, trusted_block + 100),
+                None
+            );
+        }
+        assert!(!fallback.is_engaged());
+
+        // A success resets the failures of its trusted block.
+        fallback.record_success(1000);
+        assert_eq!(fallback.record_failure(1000, 1100), None);
+        assert_eq!(fallback.record_failure(1000, 1100), None);
+        assert!(fallback.record_failure(1000, 1100).is_some());
+        assert_eq!(fallback.target(1000, 1100), (1001, None));
+    }
+}
@@ -0,0 +1,574 @@
+//! Fault injection, for tests of how the operator copes with flaky dependencies. Built in this
+//! crate's tests, and for downstream tests with the `testing` feature.
+//!
+//! `FaultInjecting` wraps a `HeaderFetcher` or a `ProofBackend` and, following a `FaultSchedule`,
+//! delays, fails or panics on chosen calls. The calls to each method are numbered from 1, so the
+//! same schedule injects the same faults on every run. A schedule has one rule per line: the
+//! method (or `*` for any), the calls, and the fault. The first rule matching a call applies.
+//!
+//! ```text
+//! # The first skip request fails to reach the backend, the second is slow.
+//! request_skip 1 transport
+//! request_skip 2 latency=50ms
+//! # Every third listing of the recent requests can't be parsed.
+//! recent_requests %3 malformed
+//! # From the 10th call on, anything panics.
+//! * 10.. panic
+//! ```
+//!
+//! Wrapping the `HeaderFetcher` the operator reads the chain through (see
+//! `TendermintXOperator::set_header_fetcher`) injects faults into the RPC reads of the run loop.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+#[cfg(feature = "operator")]
+use alloy_primitives::B256;
+use anyhow::{anyhow, ensure, Context, Result};
+use async_trait::async_trait;
+
+#[cfg(feature = "operator")]
+use crate::backend::{ProofBackend, ProofPayload, ProofRequest, RecentRequest};
+#[cfg(feature = "operator")]
+use crate::platform::FulfillmentStatus;
+use crate::selector::{HeaderFetcher, ValidatorPowers};
+use crate::types::HeaderHash;
+
+/// What goes wrong with a call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Fault {
+    /// The call is made after this delay.
+    Latency(Duration),
+    /// The call fails without being made, as if its request was lost.
+    Transport,
+    /// The call is made, but fails as if its response couldn't be parsed.
+    Malformed,
+    /// The call panics without being made.
+    Panic,
+}
+
+impl fmt::Display for Fault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Fault::Latency(delay) => write!(f, "latency={}ms", delay.as_millis()),
+            Fault::Transport => f.write_str("transport"),
+            Fault::Malformed => f.write_str("malformed"),
+            Fault::Panic => f.write_str("panic"),
+        }
+    }
+}
+
+impl FromStr for Fault {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "transport" => Ok(Fault::Transport),
+            "malformed" => Ok(Fault::Malformed),
+            "panic" => Ok(Fault::Panic),
+            _ => {
+                let delay = s
+                    .strip_prefix("latency=")
+                    .ok_or_else(|| anyhow!("unknown fault {:?}", s))?;
+                let (value, millis) = match delay.strip_suffix("ms") {
+                    Some(value) => (value, 1),
+                    None => (delay.strip_suffix('s').unwrap_or("-"), 1000),
+                };
+                let value: u64 = value
+                    .parse()
+                    .map_err(|_| anyhow!("invalid latency {:?}, expected e.g. 50ms", delay))?;
+                Ok(Fault::Latency(Duration::from_millis(value * millis)))
+            }
+        }
+    }
+}
+
+/// The calls to a method a fault applies to, numbered from 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Calls {
+    /// `3`
+    Nth(u64),
+    /// `3..=5`
+    Range(u64, u64),
+    /// `3..`
+    From(u64),
+    /// `%3`: every third call.
+    Every(u64),
+}
+
+impl Calls {
+    pub fn contains(&self, call: u64) -> bool {
+        match *self {
+            Calls::Nth(n) => call == n,
+            Calls::Range(first, last) => (first..=last).contains(&call),
+            Calls::From(first) => call >= first,
+            Calls::Every(n) => call % n == 0,
+        }
+    }
+}
+
+impl fmt::Display for Calls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Calls::Nth(n) => write!(f, "{}", n),
+            Calls::Range(first, last) => write!(f, "{}..={}", first, last),
+            Calls::From(first) => write!(f, "{}..", first),
+            Calls::Every(n) => write!(f, "%{}", n),
+        }
+    }
+}
+
+impl FromStr for Calls {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let number = |n: &str| -> Result<u64> {
+            let n: u64 = n
+                .parse()
+                .map_err(|_| anyhow!("invalid calls {:?}, expected e.g. 3, 3..=5, 3.. or %3", s))?;
+            ensure!(n > 0, "calls are numbered from 1, not {:?}", s);
+            Ok(n)
+        };
+        if let Some(n) = s.strip_prefix('%') {
+            return Ok(Calls::Every(number(n)?));
+        }
+        if let Some((first, last)) = s.split_once("..=") {
+            let (first, last) = (number(first)?, number(last)?);
+            ensure!(first <= last, "empty calls {:?}", s);
+            return Ok(Calls::Range(first, last));
+        }
+        if let Some(first) = s.strip_suffix("..") {
+            return Ok(Calls::From(number(first)?));
+        }
+        Ok(Calls::Nth(number(s)?))
+    }
+}
+
+/// A rule of a `FaultSchedule`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FaultRule {
+    /// The method the rule applies to, or `None` for any.
+    pub method: Option<String>,
+    pub calls: Calls,
+    pub fault: Fault,
+}
+
+/// The faults to inject, by method and call.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FaultSchedule {
+    pub rules: Vec<FaultRule>,
+}
+
+impl FaultSchedule {
+    /// No faults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Inject `fault` on the `calls` to `method` (or to any method for `*`).
+    pub fn inject(mut self, method: &str, calls: Calls, fault: Fault) -> Self {
+        let method = (method != "*").then(|| method.to_string());
+        self.rules.push(FaultRule {
+            method,
+            calls,
+            fault,
+        });
+        self
+    }
+
+    /// The fault to inject on `call` to `method`, if any.
+    pub fn fault(&self, method: &str, call: u64) -> Option<Fault> {
+        self.rules
+            .iter()
+            .find(|rule| {
+                rule.method.as_deref().map_or(true, |m| m == method) && rule.calls.contains(call)
+            })
+            .map(|rule| rule.fault)
+    }
+}
+
+impl fmt::Display for FaultSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for rule in &self.rules {
+            let method = rule.method.as_deref().unwrap_or("*");
+            writeln!(f, "{} {} {}", method, rule.calls, rule.fault)?;
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for FaultSchedule {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut schedule = Self::new();
+        for (i, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            let &[method, calls, fault] = fields.as_slice() else {
+                return Err(anyhow!(
+                    "line {}: expected a method, calls and a fault, got {:?}",
+                    i + 1,
+                    line
+                ));
+            };
+            let calls = calls.parse().with_context(|| format!("line {}", i + 1))?;
+            let fault = fault.parse().with_context(|| format!("line {}", i + 1))?;
+            schedule = schedule.inject(method, calls, fault);
+        }
+        Ok(schedule)
+    }
+}
+
+/// A fault injected by a `FaultInjecting`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InjectedFault {
+    pub method: String,
+    pub call: u64,
+    pub fault: Fault,
+}
+
+/// `inner`, with the faults of `schedule` injected into its calls.
+pub struct FaultInjecting<T> {
+    inner: T,
+    schedule: FaultSchedule,
+    /// The calls so far, by method.
+    calls: Mutex<HashMap<String, u64>>,
+    injected: Mutex<Vec<InjectedFault>>,
+}
+
+impl<T> FaultInjecting<T> {
+    pub fn new(inner: T, schedule: FaultSchedule) -> Self {
+        Self {
+            inner,
+            schedule,
+            calls: Mutex::new(HashMap::new()),
+            injected: Mutex::new(Vec::new()),
+        }
+    }
+
+    pub fn inner(&self) -> &T {
+        &self.inner
+    }
+
+    /// The number of calls to `method` so far, including those that failed.
+    pub fn calls(&self, method: &str) -> u64 {
+        self.calls.lock().unwrap().get(method).copied().unwrap_or(0)
+    }
+
+    /// The faults injected so far, in order.
+    pub fn injected(&self) -> Vec<InjectedFault> {
+        self.injected.lock().unwrap().clone()
+    }
+
+    /// Count a call to `method`, and return its number and the fault to inject, if any.
+    fn next_call(&self, method: &str) -> (u64, Option<Fault>) {
+        let call = {
+            let mut calls = self.calls.lock().unwrap();
+            let call = calls.entry(method.to_string()).or_default();
+            *call += 1;
+            *call
+        };
+        let fault = self.schedule.fault(method, call);
+        if let Some(fault) = fault {
+            self.injected.lock().unwrap().push(InjectedFault {
+                method: method.to_string(),
+                call,
+                fault,
+            });
+        }
+        (call, fault)
+    }
+
+    /// Make the call to `method`, `call`, with its fault.
+    async fn inject<R>(&self, method: &str, call: impl Future<Output = Result<R>>) -> Result<R> {
+        let (n, fault) = self.next_call(method);
+        match fault {
+            None => call.await,
+            Some(Fault::Latency(delay)) => {
+                tokio::time::sleep(delay).await;
+                call.await
+            }
+            Some(Fault::Transport) => Err(anyhow!(
+                "injected transport error on call {} to {}",
+                n,
+                method
+            )),
+            Some(Fault::Malformed) => {
+                call.await?;
+                Err(anyhow!(
+                    "injected malformed response to call {} to {}",
+                    n,
+                    method
+                ))
+            }
+            Some(Fault::Panic) => panic!("injected panic on call {} to {}", n, method),
+        }
+    }
+}
+
+#[async_trait]
+impl<T: HeaderFetcher> HeaderFetcher for FaultInjecting<T> {
+    async fn chain_head(&self) -> Result<u64> {
+        self.inject("chain_head", self.inner.chain_head()).await
+    }
+
+    async fn latest_header(&self) -> Result<(u64, i64)> {
+        self.inject("latest_header", self.inner.latest_header())
+            .await
+    }
+
+    async fn header_time(&self, block: u64) -> Result<i64> {
+        self.inject("header_time", self.inner.header_time(block))
+            .await
+    }
+
+    async fn header_hash_and_time(&self, block: u64) -> Result<(HeaderHash, i64)> {
+        let call = self.inner.header_hash_and_time(block);
+        self.inject("header_hash_and_time", call).await
+    }
+
+    async fn is_valid_skip(&self, trusted_block: u64, target_block: u64) -> Result<bool> {
+        let call = self.inner.is_valid_skip(trusted_block, target_block);
+        self.inject("is_valid_skip", call).await
+    }
+
+    async fn validator_powers(&self, block: u64) -> Result<ValidatorPowers> {
+        let call = self.inner.validator_powers(block);
+        self.inject("validator_powers", call).await
+    }
+}
+
+#[cfg(feature = "operator")]
+#[async_trait]
+impl<B: ProofBackend> ProofBackend for FaultInjecting<B> {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn served_by(&self, request_id: &str) -> Option<String> {
+        self.inner.served_by(request_id)
+    }
+
+    async fn request_step(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.inject("request_step", self.inner.request_step(request))
+            .await
+    }
+
+    async fn request_skip(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.inject("request_skip", self.inner.request_skip(request))
+            .await
+    }
+
+    async fn request_data_commitment(&self, request: &ProofRequest<'_>) -> Result<String> {
+        let call = self.inner.request_data_commitment(request);
+        self.inject("request_data_commitment", call).await
+    }
+
+    async fn status(&self, request_id: &str) -> Result<FulfillmentStatus> {
+        self.inject("status", self.inner.status(request_id)).await
+    }
+
+    async fn recent_requests(&self, function_id: B256) -> Result<Vec<RecentRequest>> {
+        let call = self.inner.recent_requests(function_id);
+        self.inject("recent_requests", call).await
+    }
+
+    async fn request_cost(&self, request_id: &str) -> Result<Option<f64>> {
+        self.inject("request_cost", self.inner.request_cost(request_id))
+            .await
+    }
+
+    async fn artifact_digest(&self, function_id: B256) -> Result<Option<B256>> {
+        let call = self.inner.artifact_digest(function_id);
+        self.inject("artifact_digest", call).await
+    }
+
+    async fn cancel(&self, request_id: &str) -> Result<bool> {
+        self.inject("cancel", self.inner.cancel(request_id)).await
+    }
+
+    async fn proof(&self, request_id: &str) -> Result<Option<ProofPayload>> {
+        self.inject("proof", self.inner.proof(request_id)).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Instant;
+
+    use super::*;
+    use crate::selector::{ChainHead, LargestSkip, TargetSelector};
+    use crate::testing::{InMemoryFetcher, SyntheticChain};
+
+    #[test]
+    fn test_schedule() {
+        let text = "
+            # Comments and blank lines are ignored.
+
+            request_skip 1 transport
+            request_skip 2..=3 latency=50ms
+            recent_requests %3 malformed # Every third listing.
+            * 10.. panic
+        ";
+        let schedule: FaultSchedule = text.parse().unwrap();
+        assert_eq!(
+            schedule,
+            FaultSchedule::new()
+                .inject("request_skip", Calls::Nth(1), Fault::Transport)
+                .inject(
+                    "request_skip",
+                    Calls::Range(2, 3),
+                    Fault::Latency(Duration::from_millis(50))
+                )
+                .inject("recent_requests", Calls::Every(3), Fault::Malformed)
+                .inject("*", Calls::From(10), Fault::Panic)
+        );
+        // Displayed as parsed.
+        assert_eq!(
+            schedule.to_string().parse::<FaultSchedule>().unwrap(),
+            schedule
+        );
+        assert_eq!(
+            schedule.to_string().lines().nth(1),
+            Some("request_skip 2..=3 latency=50ms")
+        );
+
+        assert_eq!(schedule.fault("request_skip", 1), Some(Fault::Transport));
+        assert_eq!(
+            schedule.fault("request_skip", 3),
+            Some(Fault::Latency(Duration::from_millis(50)))
+        );
+        assert_eq!(schedule.fault("request_skip", 4), None);
+        assert_eq!(schedule.fault("request_step", 1), None);
+        assert_eq!(schedule.fault("recent_requests", 6), Some(Fault::Malformed));
+        // The first matching rule applies.
+        assert_eq!(
+            schedule.fault("recent_requests", 12),
+            Some(Fault::Malformed)
+        );
+        assert_eq!(schedule.fault("status", 11), Some(Fault::Panic));
+        assert_eq!(
+            "latency=2s".parse::<Fault>().unwrap(),
+            Fault::Latency(Duration::from_secs(2))
+        );
+
+        for (text, error) in [
+            (
+                "request_skip 1",
+                "line 1: expected a method, calls and a fault",
+            ),
+            ("request_skip 0 panic", "calls are numbered from 1"),
+            ("request_skip 5..=3 panic", "empty calls"),
+            ("request_skip x panic", "invalid calls"),
+            ("request_skip 1 timeout", "unknown fault"),
+            ("request_skip 1 latency=fast", "invalid latency"),
+        ] {
+            let e = text.parse::<FaultSchedule>().unwrap_err();
+            assert!(format!("{:#}", e).contains(error), "{}: {:#}", text, e);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fault_injecting_fetcher() {
+        let schedule = "
+            chain_head 1 latency=50ms
+            is_valid_skip 2 malformed
+        ";
+        let fetcher = FaultInjecting::new(
+            InMemoryFetcher::new(SyntheticChain::new(7).with_churn(300, 4)),
+            schedule.parse().unwrap(),
+        );
+
+        let start = Instant::now();
+        assert_eq!(fetcher.chain_head().await.unwrap(), 1000);
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert_eq!(fetcher.chain_head().await.unwrap(), 1000);
+
+        // The first skip checked is invalid, and checking the second fails the selection.
+        let (block, time) = fetcher.latest_header().await.unwrap();
+        let head = ChainHead { block, time };
+        let error = LargestSkip
+            .select(100, 1000, head, &fetcher)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "injected malformed response to call 2 to is_valid_skip"
+        );
+        assert_eq!(fetcher.calls("is_valid_skip"), 2);
+        // The next selection goes on.
+        assert!(LargestSkip.select(100, 1000, head, &fetcher).await.is_ok());
+    }
+
+    #[cfg(feature = "operator")]
+    #[tokio::test]
+    async fn test_fault_injecting_backend() {
+        use alloy_primitives::Bytes;
+
+        use crate::backend::mock::MockBackend;
+        use crate::target::RequestTarget;
+
+        let target = RequestTarget::for_test(5);
+        let request = ProofRequest {
+            target: &target,
+            trusted_block: 10,
+            target_block: 20,
+            function_id: target.skip_function_id,
+            calldata: Bytes::from_static(&[1, 2]),
+            input: Bytes::from_static(&[3, 4]),
+            correlation_id: None,
+        };
+        let schedule = "
+            request_skip 1 transport
+            request_skip 2 malformed
+            status %2 transport
+        ";
+        let backend = FaultInjecting::new(MockBackend::new(), schedule.parse().unwrap());
+
+        // A transport error doesn't reach the backend, a malformed response does.
+        let error = backend.request_skip(&request).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "injected transport error on call 1 to request_skip"
+        );
+        assert_eq!(backend.inner().submission_attempts(), 0);
+        assert!(backend.request_skip(&request).await.is_err());
+        assert_eq!(backend.inner().requests().len(), 1);
+        assert_eq!(backend.request_skip(&request).await.unwrap(), "mock-2");
+        assert_eq!(backend.calls("request_skip"), 3);
+
+        // The default batch query goes through the faults of `status`.
+        let ids = ["mock-1".to_string(), "mock-2".to_string()];
+        let statuses = backend.statuses(&ids).await;
+        assert!(statuses[0].is_ok() && statuses[1].is_err());
+        assert_eq!(backend.name(), "mock");
+        assert_eq!(
+            backend.injected(),
+            [
+                InjectedFault {
+                    method: "request_skip".to_string(),
+                    call: 1,
+                    fault: Fault::Transport,
+                },
+                InjectedFault {
+                    method: "request_skip".to_string(),
+                    call: 2,
+                    fault: Fault::Malformed,
+                },
+                InjectedFault {
+                    method: "status".to_string(),
+                    call: 2,
+                    fault: Fault::Transport,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,359 @@
+//! Merkle proofs of the fields of a header against its header hash.
+//!
+//! A Tendermint header hash is the root of a Merkle tree (RFC 6962, as `tendermint::merkle`
+//! computes it) over the 14 protobuf encoded fields of the header, in the order of
+//! `header_fields_bytes`. A `FieldProof` holds the encoded field, its position and the hashes of
+//! its path to the root, which is enough for a consumer to check e.g. the `app_hash` of a height
+//! against the header hash a TendermintX contract stores for it, without the rest of the header.
+
+use std::fmt;
+use std::str::FromStr;
+
+use alloy_primitives::{hex, Bytes};
+use anyhow::{anyhow, ensure, Context, Result};
+use prost::Message;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use tendermint::block::Header;
+use tendermint::Time;
+use tendermint_proto::google::protobuf::Timestamp;
+use tendermint_proto::types::BlockId as RawBlockId;
+use tendermint_proto::version::Consensus as RawConsensusVersion;
+
+use crate::input::tendermint_utils::{
+    compute_hash_from_aunts, header_fields_bytes, leaf_hash, proofs_from_byte_slices,
+};
+use crate::input::{header_hash, InputDataFetcher};
+use crate::types::{HeaderHash, Height};
+
+/// A field of a header, named as in its protobuf definition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HeaderField {
+    Version,
+    ChainId,
+    Height,
+    Time,
+    LastBlockId,
+    LastCommitHash,
+    DataHash,
+    ValidatorsHash,
+    NextValidatorsHash,
+    ConsensusHash,
+    AppHash,
+    LastResultsHash,
+    EvidenceHash,
+    ProposerAddress,
+}
+
+impl HeaderField {
+    /// Every field, in the order of the leaves of the header's Merkle tree.
+    pub const ALL: [Self; 14] = [
+        Self::Version,
+        Self::ChainId,
+        Self::Height,
+        Self::Time,
+        Self::LastBlockId,
+        Self::LastCommitHash,
+        Self::DataHash,
+        Self::ValidatorsHash,
+        Self::NextValidatorsHash,
+        Self::ConsensusHash,
+        Self::AppHash,
+        Self::LastResultsHash,
+        Self::EvidenceHash,
+        Self::ProposerAddress,
+    ];
+
+    /// The index of the field's leaf.
+    pub fn index(self) -> usize {
+        Self::ALL
+            .iter()
+            .position(|&field| field == self)
+            .expect("every field is listed")
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Version => "version",
+            Self::ChainId => "chain_id",
+            Self::Height => "height",
+            Self::Time => "time",
+            Self::LastBlockId => "last_block_id",
+            Self::LastCommitHash => "last_commit_hash",
+            Self::DataHash => "data_hash",
+            Self::ValidatorsHash => "validators_hash",
+            Self::NextValidatorsHash => "next_validators_hash",
+            Self::ConsensusHash => "consensus_hash",
+            Self::AppHash => "app_hash",
+            Self::LastResultsHash => "last_results_hash",
+            Self::EvidenceHash => "evidence_hash",
+            Self::ProposerAddress => "proposer_address",
+        }
+    }
+
+    /// The value of the field from its protobuf encoded leaf: hashes and addresses as 0x-prefixed
+    /// lowercase hex, the time in RFC 3339, the version as `block/app` and the last block ID as
+    /// its hash and the total and hash of its part set header, joined by `:`.
+    pub fn decode(self, leaf: &[u8]) -> Result<String> {
+        let hex = |bytes: &[u8]| format!("0x{}", hex::encode(bytes));
+        let invalid = || format!("invalid {} leaf", self);
+        Ok(match self {
+            Self::Version => {
+                let version = RawConsensusVersion::decode(leaf).with_context(invalid)?;
+                format!("{}/{}", version.block, version.app)
+            }
+            Self::ChainId => String::decode(leaf).with_context(invalid)?,
+            Self::Height => i64::decode(leaf).with_context(invalid)?.to_string(),
+            Self::Time => {
+                let timestamp = Timestamp::decode(leaf).with_context(invalid)?;
+                Time::try_from(timestamp)
+                    .map_err(|e| anyhow!("{}: {}", invalid(), e))?
+                    .to_rfc3339()
+            }
+            Self::LastBlockId => {
+                let id = RawBlockId::decode(leaf).with_context(invalid)?;
+                let parts = id.part_set_header.unwrap_or_default();
+                format!("{}:{}:{}", hex(&id.hash), parts.total, hex(&parts.hash))
+            }
+            _ => hex(&Vec::<u8>::decode(leaf).with_context(invalid)?),
+        })
+    }
+}
+
+impl fmt::Display for HeaderField {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for HeaderField {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|field| field.as_str() == s)
+            .ok_or_else(|| {
+                let names = Self::ALL.map(Self::as_str).join(", ");
+                anyhow!("unknown header field {:?}, expected one of {}", s, names)
+            })
+    }
+}
+
+/// The proof of a field of the header of `height` against its header hash.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldProof {
+    #[serde(rename = "height")]
+    pub height: Height,
+    #[serde(rename = "field")]
+    pub field: HeaderField,
+    /// The value of the field, as `HeaderField::decode` renders it.
+    #[serde(rename = "value")]
+    pub value: String,
+    /// The protobuf encoded field, the leaf of the proof.
+    #[serde(rename = "leaf")]
+    pub leaf: Bytes,
+    /// The index of the leaf.
+    #[serde(rename = "index")]
+    pub index: u64,
+    /// The number of leaves.
+    #[serde(rename = "total")]
+    pub total: u64,
+    /// The hashes of the siblings on the path from the leaf to the root, from the leaf up.
+    #[serde(rename = "aunts")]
+    pub aunts: Vec<HeaderHash>,
+    /// The root the proof verifies against.
+    #[serde(rename = "header_hash")]
+    pub header_hash: HeaderHash,
+}
+
+impl FieldProof {
+    /// The proof of `field` of `header`.
+    pub fn new(header: &Header, field: HeaderField) -> Result<Self> {
+        let leaves = header_fields_bytes(header);
+        let (root, proofs) = proofs_from_byte_slices(leaves.clone());
+        let header_hash = header_hash(header);
+        // Both hash the same fields: a difference is a bug in the encodings here.
+        ensure!(
+            HeaderHash(root) == header_hash,
+            "the fields of header {} don't hash to its header hash",
+            header.height
+        );
+        let proof = &proofs[field.index()];
+        let leaf = Bytes::from(leaves[field.index()].clone());
+        Ok(Self {
+            height: Height(header.height.value()),
+            field,
+            value: field.decode(&leaf)?,
+            leaf,
+            index: proof.index,
+            total: proof.total,
+            aunts: proof.aunts.iter().copied().map(HeaderHash).collect(),
+            header_hash,
+        })
+    }
+
+    /// Fetch the header of `height` and prove its `field`.
+    pub async fn fetch(
+        fetcher: &InputDataFetcher,
+        height: Height,
+        field: HeaderField,
+    ) -> Result<Self> {
+        let header = fetcher.get_signed_header_from_number(height).await?.header;
+        Self::new(&header, field)
+    }
+
+    /// Check that the leaf is `field`'s and holds `value`, and that its path hashes to
+    /// `header_hash`. With `expected`, e.g. the header hash a contract stores for the height, the
+    /// header hash must also be that one.
+    pub fn verify(&self, expected: Option<HeaderHash>) -> Result<()> {
+        ensure!(
+            self.total == HeaderField::ALL.len() as u64,
+            "a header has {} fields, not {}",
+            HeaderField::ALL.len(),
+            self.total
+        );
+        ensure!(
+            self.index == self.field.index() as u64,
+            "the leaf {} is not the {} of a header, which is leaf {}",
+            self.index,
+            self.field,
+            self.field.index()
+        );
+        let value = self.field.decode(&self.leaf)?;
+        ensure!(
+            value == self.value,
+            "the leaf holds the {} {}, not {}",
+            self.field,
+            value,
+            self.value
+        );
+        let aunts = self.aunts.iter().map(|aunt| aunt.to_bytes()).collect();
+        let root = compute_hash_from_aunts(
+            self.index,
+            self.total,
+            leaf_hash::<Sha256>(&self.leaf),
+            aunts,
+        )
+        .map(HeaderHash)
+        .ok_or_else(|| anyhow!("the proof has {} aunts", self.aunts.len()))?;
+        ensure!(
+            root == self.header_hash,
+            "the proof hashes to {}, not to the header hash {}",
+            root,
+            self.header_hash
+        );
+        if let Some(expected) = expected {
+            ensure!(
+                expected == self.header_hash,
+                "the proof is against the header hash {}, not {}",
+                self.header_hash,
+                expected
+            );
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for FieldProof {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{} of block {}: {}", self.field, self.height, self.value)?;
+        writeln!(f, "  leaf {} of {}: {}", self.index, self.total, self.leaf)?;
+        writeln!(f, "  path:")?;
+        for aunt in self.aunts.iter() {
+            writeln!(f, "    {}", aunt)?;
+        }
+        write!(f, "  verifies against the header hash {}", self.header_hash)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::fixture_fetcher;
+
+    #[test]
+    fn test_field_names() {
+        for field in HeaderField::ALL {
+            assert_eq!(field.as_str().parse::<HeaderField>().unwrap(), field);
+            let json = serde_json::to_value(field).unwrap();
+            assert_eq!(json, field.as_str());
+        }
+        assert_eq!(HeaderField::AppHash.index(), 10);
+        assert_eq!(HeaderField::DataHash.index(), 6);
+        assert!("apphash".parse::<HeaderField>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_proofs_of_fixture_headers() {
+        let fetcher = fixture_fetcher();
+        for block in [3000, 10000, 10500, 157001] {
+            let header = fetcher
+                .get_signed_header_from_number(Height(block))
+                .await
+                .unwrap()
+                .header;
+            // tendermint-rs hashes the header the proofs are against.
+            let expected = HeaderHash::try_from(header.hash()).unwrap();
+            for field in HeaderField::ALL {
+                let proof = FieldProof::fetch(&fetcher, Height(block), field)
+                    .await
+                    .unwrap();
+                assert_eq!(proof.header_hash, expected);
+                assert_eq!(proof.height, Height(block));
+                proof.verify(Some(expected)).unwrap();
+            }
+
+            let hex = |bytes: &[u8]| format!("0x{}", hex::encode(bytes));
+            let value = |field| FieldProof::new(&header, field).unwrap().value;
+            assert_eq!(value(HeaderField::AppHash), hex(header.app_hash.as_bytes()));
+            assert_eq!(
+                value(HeaderField::DataHash),
+                hex(header.data_hash.unwrap_or_default().as_bytes())
+            );
+            assert_eq!(
+                value(HeaderField::ValidatorsHash),
+                hex(header.validators_hash.as_bytes())
+            );
+            assert_eq!(value(HeaderField::ChainId), "mocha-4");
+            assert_eq!(value(HeaderField::Height), block.to_string());
+            assert_eq!(value(HeaderField::Time), header.time.to_rfc3339());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_verify_rejects() {
+        let fetcher = fixture_fetcher();
+        let proof = FieldProof::fetch(&fetcher, Height(10000), HeaderField::AppHash)
+            .await
+            .unwrap();
+        // Through JSON and back.
+        let json = serde_json::to_string(&proof).unwrap();
+        assert_eq!(serde_json::from_str::<FieldProof>(&json).unwrap(), proof);
+
+        // Against another block's header hash.
+        let other = fetcher.compute_header_hash(Height(10001)).await.unwrap();
+        assert!(proof.verify(Some(other)).is_err());
+
+        // A tampered value, leaf, path or field.
+        let mut tampered = proof.clone();
+        tampered.value = format!("0x{}", "00".repeat(32));
+        assert!(tampered.verify(None).is_err());
+        let mut tampered = proof.clone();
+        let mut leaf = tampered.leaf.to_vec();
+        *leaf.last_mut().unwrap() ^= 1;
+        tampered.leaf = leaf.into();
+        tampered.value = HeaderField::AppHash.decode(&tampered.leaf).unwrap();
+        assert!(tampered.verify(None).is_err());
+        let mut tampered = proof.clone();
+        tampered.aunts[0] = HeaderHash([0; 32]);
+        assert!(tampered.verify(None).is_err());
+        let mut tampered = proof.clone();
+        tampered.aunts.pop();
+        assert!(tampered.verify(None).is_err());
+        let mut tampered = proof.clone();
+        tampered.field = HeaderField::DataHash;
+        assert!(tampered.verify(None).is_err());
+    }
+}
@@ -0,0 +1,170 @@
+//! Gating submissions on downstream demand.
+//!
+//! Proofs cost money even when nothing downstream (IBC packets, bridge messages) needs the updated
+//! consensus state. Before submitting, the run loop can ask a gate whether the proposed range is
+//! needed: a negative answer skips the request until the next, shorter iteration. A gate that fails
+//! or doesn't answer in time lets the request through by default (fail-open), so that a broken gate
+//! can't stall the light client forever; fail-closed skips it instead.
+
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use log::{info, warn};
+use serde_json::{json, Value};
+
+/// The default time a gate may take to answer.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Decides whether an update of the light client is currently needed.
+#[async_trait]
+pub trait Gate: Send + Sync {
+    /// Whether an update from `trusted_block` to `target_block` is needed.
+    async fn is_needed(&self, trusted_block: u64, target_block: u64) -> Result<bool>;
+}
+
+/// A gate behind an HTTP endpoint, POSTed `{"trusted_block": ..., "target_block": ...}` and
+/// answering `{"needed": true|false}`.
+pub struct HttpGate {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl HttpGate {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            client: reqwest::Client::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl Gate for HttpGate {
+    async fn is_needed(&self, trusted_block: u64, target_block: u64) -> Result<bool> {
+        let body = json!({ "trusted_block": trusted_block, "target_block": target_block });
+        let response = self
+            .client
+            .post(&self.url)
+            .header("content-type", "application/json")
+            .body(body.to_string())
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let answer: Value = serde_json::from_str(&response)?;
+        answer["needed"]
+            .as_bool()
+            .ok_or_else(|| anyhow!("invalid gate answer {}", response))
+    }
+}
+
+/// A gate, and what to do when it doesn't answer.
+pub struct Gating {
+    gate: Box<dyn Gate>,
+    pub timeout: Duration,
+    /// Whether a request proceeds when the gate fails or times out.
+    pub fail_open: bool,
+}
+
+impl Gating {
+    pub fn new(gate: Box<dyn Gate>) -> Self {
+        Self {
+            gate,
+            timeout: DEFAULT_TIMEOUT,
+            fail_open: true,
+        }
+    }
+
+    /// Whether a request from `trusted_block` to `target_block` may be submitted.
+    pub async fn allows(&self, trusted_block: u64, target_block: u64) -> bool {
+        let answer = tokio::time::timeout(
+            self.timeout,
+            self.gate.is_needed(trusted_block, target_block),
+        )
+        .await;
+        let error = match answer {
+            Ok(Ok(needed)) => {
+                if !needed {
+                    info!(
+                        "The gate found no demand for an update from {} to {}",
+                        trusted_block, target_block
+                    );
+                }
+                return needed;
+            }
+            Ok(Err(e)) => format!("{:#}", e),
+            Err(_) => format!("no answer within {:?}", self.timeout),
+        };
+        warn!(
+            "The gate failed for an update from {} to {} ({}), {}",
+            trusted_block,
+            target_block,
+            error,
+            if self.fail_open {
+                "proceeding"
+            } else {
+                "skipping"
+            }
+        );
+        self.fail_open
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A gate answering `answer` (or failing if `None`) after `delay`.
+    struct MockGate {
+        answer: Option<bool>,
+        delay: Duration,
+    }
+
+    impl MockGate {
+        fn new(answer: Option<bool>, delay: Duration) -> Self {
+            Self { answer, delay }
+        }
+    }
+
+    #[async_trait]
+    impl Gate for MockGate {
+        async fn is_needed(&self, _trusted_block: u64, _target_block: u64) -> Result<bool> {
+            tokio::time::sleep(self.delay).await;
+            self.answer.ok_or_else(|| anyhow!("gate unavailable"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_allow_and_deny() {
+        let gating = Gating::new(Box::new(MockGate::new(Some(true), Duration::ZERO)));
+        assert!(gating.allows(1000, 1500).await);
+
+        // A negative answer is followed whatever the failure policy.
+        let mut gating = Gating::new(Box::new(MockGate::new(Some(false), Duration::ZERO)));
+        assert!(!gating.allows(1000, 1500).await);
+        gating.fail_open = false;
+        assert!(!gating.allows(1000, 1500).await);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_and_errors() {
+        // A gate that answers too late is a failure, even if it would have allowed the request.
+        let mut gating = Gating::new(Box::new(MockGate::new(Some(true), Duration::from_secs(5))));
+        gating.timeout = Duration::from_millis(20);
+        gating.fail_open = false;
+        let start = std::time::Instant::now();
+        assert!(!gating.allows(1000, 1500).await);
+        assert!(start.elapsed() < Duration::from_secs(1));
+        // Fail-open by default.
+        let mut gating = Gating::new(Box::new(MockGate::new(Some(false), Duration::from_secs(5))));
+        gating.timeout = Duration::from_millis(20);
+        assert!(gating.allows(1000, 1500).await);
+
+        let mut gating = Gating::new(Box::new(MockGate::new(None, Duration::ZERO)));
+        assert!(gating.allows(1000, 1500).await);
+        gating.fail_open = false;
+        assert!(!gating.allows(1000, 1500).await);
+    }
+}
@@ -0,0 +1,230 @@
+//! Golden vectors of the step and skip encodings, shared with the contract's Foundry tests.
+//!
+//! The vectors file pins the packed circuit inputs and the callback calldata of the canonical
+//! (trusted block, trusted header hash, target block) tuples in `CASES`, as they were encoded when
+//! the file was last generated. `tests/golden_encodings.rs` checks that this crate still encodes
+//! them to the same bytes and `contracts/test/TendermintX.t.sol` that the contract does, so a
+//! change to the `sol!` tuples or the encoding helpers fails both until the file is deliberately
+//! regenerated with `cargo run --bin golden_encodings -- --regenerate`.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use alloy_primitives::{hex, Bytes};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::encoding::{
+    encode_skip_calldata, encode_skip_input, encode_step_calldata, encode_step_input,
+};
+use crate::types::{HeaderHash, Height};
+
+/// The vectors file, from the root of the crate. It sits with the Foundry tests, which can only
+/// read files under the contracts project.
+pub const VECTORS_PATH: &str = "contracts/test/fixtures/encodings.json";
+
+/// A canonical tuple: a step from `trusted_block`, and a skip from it to `target_block`.
+pub struct Case {
+    pub name: &'static str,
+    pub trusted_block: Height,
+    pub trusted_header_hash: HeaderHash,
+    pub target_block: Height,
+}
+
+/// The canonical tuples, covering the smallest and largest heights and a byte order check.
+pub const CASES: [Case; 4] = [
+    Case {
+        name: "first-block",
+        trusted_block: Height(1),
+        trusted_header_hash: HeaderHash([0; 32]),
+        target_block: Height(2),
+    },
+    Case {
+        name: "mocha-4",
+        trusted_block: Height(10000),
+        trusted_header_hash: HeaderHash(hex!(
+            "a0123d5e4b8b8888a61f931ee2252d83568b97c223e0eca9795b29b8bd8cba2d"
+        )),
+        target_block: Height(10004),
+    },
+    Case {
+        name: "byte-order",
+        trusted_block: Height(0x0102030405060708),
+        trusted_header_hash: HeaderHash(hex!(
+            "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"
+        )),
+        target_block: Height(0x1112131415161718),
+    },
+    Case {
+        name: "max-height",
+        trusted_block: Height(u64::MAX - 1),
+        trusted_header_hash: HeaderHash([0xff; 32]),
+        target_block: Height(u64::MAX),
+    },
+];
+
+/// The encodings of a step.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StepVector {
+    #[serde(rename = "name")]
+    pub name: String,
+    #[serde(rename = "trusted_block")]
+    pub trusted_block: Height,
+    #[serde(rename = "trusted_header_hash")]
+    pub trusted_header_hash: HeaderHash,
+    /// The packed input of the step circuit.
+    #[serde(rename = "input")]
+    pub input: Bytes,
+    /// The calldata of the `step(uint64)` callback.
+    #[serde(rename = "calldata")]
+    pub calldata: Bytes,
+}
+
+/// The encodings of a skip.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SkipVector {
+    #[serde(rename = "name")]
+    pub name: String,
+    #[serde(rename = "trusted_block")]
+    pub trusted_block: Height,
+    #[serde(rename = "trusted_header_hash")]
+    pub trusted_header_hash: HeaderHash,
+    #[serde(rename = "target_block")]
+    pub target_block: Height,
+    /// The packed input of the skip circuit.
+    #[serde(rename = "input")]
+    pub input: Bytes,
+    /// The calldata of the `skip(uint64,uint64)` callback.
+    #[serde(rename = "calldata")]
+    pub calldata: Bytes,
+}
+
+/// The contents of the vectors file.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncodingVectors {
+    #[serde(rename = "step")]
+    pub step: Vec<StepVector>,
+    #[serde(rename = "skip")]
+    pub skip: Vec<SkipVector>,
+}
+
+impl EncodingVectors {
+    /// The vectors of `CASES`, as this crate encodes them now.
+    pub fn generate() -> Self {
+        let step = CASES
+            .iter()
+            .map(|case| StepVector {
+                name: case.name.to_string(),
+                trusted_block: case.trusted_block,
+                trusted_header_hash: case.trusted_header_hash,
+                input: encode_step_input(case.trusted_block, case.trusted_header_hash).into(),
+                calldata: encode_step_calldata(case.trusted_block).into(),
+            })
+            .collect();
+        let skip = CASES
+            .iter()
+            .map(|case| SkipVector {
+                name: case.name.to_string(),
+                trusted_block: case.trusted_block,
+                trusted_header_hash: case.trusted_header_hash,
+                target_block: case.target_block,
+                input: encode_skip_input(
+                    case.trusted_block,
+                    case.trusted_header_hash,
+                    case.target_block,
+                )
+                .into(),
+                calldata: encode_skip_calldata(case.trusted_block, case.target_block).into(),
+            })
+            .collect();
+        Self { step, skip }
+    }
+
+    pub fn parse(json: &str) -> Result<Self> {
+        serde_json::from_str(json).context("invalid encoding vectors")
+    }
+
+    /// The vectors file at `path`.
+    pub fn read(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Self::parse(&json).with_context(|| format!("in {}", path.display()))
+    }
+
+    /// As written to the vectors file: pretty-printed, with a trailing newline.
+    pub fn to_json(&self) -> String {
+        let mut json = serde_json::to_string_pretty(self).unwrap();
+        json.push('\n');
+        json
+    }
+
+    /// The vectors that differ from `expected`, are missing from it or are only in it, one line
+    /// each. Empty if the two are the same.
+    pub fn diff(&self, expected: &Self) -> Vec<String> {
+        let mut differences = diff_by_name(
+            "step",
+            self.step.iter().map(|v| (v.name.as_str(), v)),
+            expected.step.iter().map(|v| (v.name.as_str(), v)),
+        );
+        differences.extend(diff_by_name(
+            "skip",
+            self.skip.iter().map(|v| (v.name.as_str(), v)),
+            expected.skip.iter().map(|v| (v.name.as_str(), v)),
+        ));
+        differences
+    }
+}
+
+/// The vectors file of this crate.
+pub fn vectors_path() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join(VECTORS_PATH)
+}
+
+fn diff_by_name<'a, T: PartialEq + std::fmt::Debug + 'a>(
+    kind: &str,
+    actual: impl Iterator<Item = (&'a str, &'a T)>,
+    expected: impl Iterator<Item = (&'a str, &'a T)>,
+) -> Vec<String> {
+    let actual: BTreeMap<_, _> = actual.collect();
+    let expected: BTreeMap<_, _> = expected.collect();
+    let mut differences = Vec::new();
+    for (name, vector) in &actual {
+        match expected.get(name) {
+            None => differences.push(format!("{} {}: not in the vectors", kind, name)),
+            Some(golden) if golden != vector => differences.push(format!(
+                "{} {}: encodes to {:?}, the vectors have {:?}",
+                kind, name, vector, golden
+            )),
+            Some(_) => {}
+        }
+    }
+    for name in expected.keys().filter(|name| !actual.contains_key(*name)) {
+        differences.push(format!("{} {}: no longer a case", kind, name));
+    }
+    differences
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff() {
+        let vectors = EncodingVectors::generate();
+        assert_eq!(vectors.step.len(), CASES.len());
+        assert!(vectors.diff(&vectors).is_empty());
+        assert_eq!(EncodingVectors::parse(&vectors.to_json()).unwrap(), vectors);
+
+        let mut changed = vectors.clone();
+        changed.skip[1].calldata = Bytes::from(vec![0xdb, 0xfa, 0xa3, 0x42]);
+        changed.step.pop();
+        let differences = vectors.diff(&changed);
+        assert_eq!(differences.len(), 2);
+        assert_eq!(differences[0], "step max-height: not in the vectors");
+        assert!(differences[1].starts_with("skip mocha-4: encodes to"));
+        assert_eq!(
+            changed.diff(&vectors)[0],
+            "step max-height: no longer a case"
+        );
+    }
+}
@@ -0,0 +1,397 @@
+//! Local verification of the proofs of the circuits, e.g. before relaying one by hand.
+//!
+//! The plonky2 proofs of the circuits are wrapped in a Groth16 proof over BN254, which the
+//! function verifier checks on-chain against two public inputs: the SHA-256 of the packed circuit
+//! input and the SHA-256 of the circuit output, each truncated to its low 253 bits.
+//! `verify_document` checks the proof of a `ProofDocument` the same way, with arkworks, and
+//! decodes its public values into the range it proves.
+//!
+//! Proofs and verifying keys are 32-byte big-endian words, in the order of the Solidity verifier
+//! (EIP-197): `(x, y)` for a G1 point and `(x.c1, x.c0, y.c1, y.c0)` for a G2 point. A proof is
+//! the 256 bytes of `A`, `B` and `C`, and a verifying key file the JSON of `VerifyingKeyFile`.
+
+use std::fmt;
+use std::path::Path;
+
+use alloy_primitives::B256;
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use ark_bn254::{Bn254, Fq, Fq2, Fr, G1Affine, G2Affine};
+use ark_ff::{BigInteger256, PrimeField};
+use ark_groth16::{prepare_verifying_key, Groth16, Proof, VerifyingKey};
+use serde::{Deserialize, Serialize};
+
+use crate::encoding::{InputDigest, SkipInput, StepInput};
+use crate::proof::{ProofDocument, PublicValues};
+use crate::types::{HeaderHash, Height};
+
+/// The number of public inputs of the circuits: the hashes of the input and of the output.
+pub const PUBLIC_INPUTS: usize = 2;
+
+/// The length of an encoded proof.
+pub const PROOF_LEN: usize = 8 * 32;
+
+/// A verifying key, as the words of the constants of the Solidity verifier.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VerifyingKeyFile {
+    #[serde(rename = "alpha_g1")]
+    pub alpha_g1: [B256; 2],
+    #[serde(rename = "beta_g2")]
+    pub beta_g2: [B256; 4],
+    #[serde(rename = "gamma_g2")]
+    pub gamma_g2: [B256; 4],
+    #[serde(rename = "delta_g2")]
+    pub delta_g2: [B256; 4],
+    /// The points the public inputs are weighted with, the constant one first.
+    #[serde(rename = "ic")]
+    pub ic: Vec<[B256; 2]>,
+}
+
+impl VerifyingKeyFile {
+    pub fn parse(json: &str) -> Result<Self> {
+        serde_json::from_str(json).context("invalid verifying key")
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        Self::parse(&json).with_context(|| format!("in {}", path.display()))
+    }
+
+    /// The key, once each of its points is checked to be on the curve and in its subgroup.
+    pub fn to_key(&self) -> Result<VerifyingKey<Bn254>> {
+        ensure!(
+            self.ic.len() == PUBLIC_INPUTS + 1,
+            "the verifying key takes {} public inputs, not the {} of the circuits",
+            self.ic.len().saturating_sub(1),
+            PUBLIC_INPUTS
+        );
+        Ok(VerifyingKey {
+            alpha_g1: g1(&self.alpha_g1).context("invalid alpha")?,
+            beta_g2: g2(&self.beta_g2).context("invalid beta")?,
+            gamma_g2: g2(&self.gamma_g2).context("invalid gamma")?,
+            delta_g2: g2(&self.delta_g2).context("invalid delta")?,
+            gamma_abc_g1: self
+                .ic
+                .iter()
+                .enumerate()
+                .map(|(i, point)| g1(point).with_context(|| format!("invalid ic[{}]", i)))
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+/// A base field element from a big-endian word, refusing words over the modulus.
+fn fq(word: &B256) -> Result<Fq> {
+    let mut limbs = [0u64; 4];
+    for (i, chunk) in word.0.rchunks(8).enumerate() {
+        limbs[i] = u64::from_be_bytes(chunk.try_into().unwrap());
+    }
+    Fq::from_bigint(BigInteger256::new(limbs))
+        .ok_or_else(|| anyhow!("{} is not below the field modulus", word))
+}
+
+fn g1(words: &[B256; 2]) -> Result<G1Affine> {
+    if words.iter().all(|word| *word == B256::ZERO) {
+        return Ok(G1Affine::identity());
+    }
+    let point = G1Affine::new_unchecked(fq(&words[0])?, fq(&words[1])?);
+    ensure!(point.is_on_curve(), "the G1 point is not on the curve");
+    ensure!(
+        point.is_in_correct_subgroup_assuming_on_curve(),
+        "the G1 point is not in the subgroup"
+    );
+    Ok(point)
+}
+
+fn g2(words: &[B256; 4]) -> Result<G2Affine> {
+    if words.iter().all(|word| *word == B256::ZERO) {
+        return Ok(G2Affine::identity());
+    }
+    let x = Fq2::new(fq(&words[1])?, fq(&words[0])?);
+    let y = Fq2::new(fq(&words[3])?, fq(&words[2])?);
+    let point = G2Affine::new_unchecked(x, y);
+    ensure!(point.is_on_curve(), "the G2 point is not on the curve");
+    ensure!(
+        point.is_in_correct_subgroup_assuming_on_curve(),
+        "the G2 point is not in the subgroup"
+    );
+    Ok(point)
+}
+
+/// Decode the 256 bytes of a proof.
+pub fn decode_proof(bytes: &[u8]) -> Result<Proof<Bn254>> {
+    ensure!(
+        bytes.len() == PROOF_LEN,
+        "a proof is {} bytes, not {}",
+        PROOF_LEN,
+        bytes.len()
+    );
+    let words: Vec<B256> = bytes.chunks(32).map(B256::from_slice).collect();
+    Ok(Proof {
+        a: g1(&[words[0], words[1]]).context("invalid A")?,
+        b: g2(&[words[2], words[3], words[4], words[5]]).context("invalid B")?,
+        c: g1(&[words[6], words[7]]).context("invalid C")?,
+    })
+}
+
+/// The SHA-256 of `bytes` as a public input: truncated to its low 253 bits, so that it is below
+/// the scalar field modulus.
+fn hash_input(bytes: &[u8]) -> Fr {
+    Fr::from_be_bytes_mod_order(InputDigest::of(bytes).public_input.as_slice())
+}
+
+/// The public inputs a proof of `values` is verified against.
+pub fn public_inputs(values: &PublicValues) -> [Fr; PUBLIC_INPUTS] {
+    [hash_input(&values.input), hash_input(&values.output)]
+}
+
+/// The range a proof is for, decoded from its public values.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProvedRange {
+    pub trusted_block: Height,
+    pub trusted_header_hash: HeaderHash,
+    pub target_block: Height,
+    pub target_header_hash: HeaderHash,
+}
+
+impl ProvedRange {
+    /// Decode the input of a step or a skip, and the header hash of the target block it outputs.
+    pub fn decode(values: &PublicValues) -> Result<Self> {
+        let (trusted_block, trusted_header_hash, target_block) = match values.input.len() {
+            StepInput::LEN => {
+                let input = StepInput::decode(&values.input)?;
+                let target_block = input.trusted_block.next();
+                (input.trusted_block, input.trusted_header_hash, target_block)
+            }
+            SkipInput::LEN => {
+                let input = SkipInput::decode(&values.input)?;
+                (
+                    input.trusted_block,
+                    input.trusted_header_hash,
+                    input.target_block,
+                )
+            }
+            len => bail!(
+                "an input of {} bytes is neither a step nor a skip input",
+                len
+            ),
+        };
+        let output: [u8; 32] = values.output[..].try_into().map_err(|_| {
+            anyhow!(
+                "an output of {} bytes is not a header hash",
+                values.output.len()
+            )
+        })?;
+        Ok(Self {
+            trusted_block,
+            trusted_header_hash,
+            target_block,
+            target_header_hash: HeaderHash(output),
+        })
+    }
+
+    /// Check the range against each field `expected` sets.
+    pub fn check(&self, expected: &ExpectedRange) -> Result<()> {
+        let mismatch = |field: &str, proved: &dyn fmt::Display, expected: &dyn fmt::Display| {
+            anyhow!(
+                "the proof is for the {} {}, not {}",
+                field,
+                proved,
+                expected
+            )
+        };
+        if let Some(block) = expected.trusted_block.filter(|b| *b != self.trusted_block) {
+            return Err(mismatch("trusted block", &self.trusted_block, &block));
+        }
+        if let Some(hash) = expected
+            .trusted_header_hash
+            .filter(|h| *h != self.trusted_header_hash)
+        {
+            return Err(mismatch("trusted hash", &self.trusted_header_hash, &hash));
+        }
+        if let Some(block) = expected.target_block.filter(|b| *b != self.target_block) {
+            return Err(mismatch("target block", &self.target_block, &block));
+        }
+        if let Some(hash) = expected
+            .target_header_hash
+            .filter(|h| *h != self.target_header_hash)
+        {
+            return Err(mismatch("target hash", &self.target_header_hash, &hash));
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for ProvedRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "trusted block: {}", self.trusted_block)?;
+        writeln!(f, "trusted hash:  {}", self.trusted_header_hash)?;
+        writeln!(f, "target block:  {}", self.target_block)?;
+        write!(f, "target hash:   {}", self.target_header_hash)
+    }
+}
+
+/// The fields of the range a proof is expected to be for. Unset fields aren't checked.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ExpectedRange {
+    pub trusted_block: Option<Height>,
+    pub trusted_header_hash: Option<HeaderHash>,
+    pub target_block: Option<Height>,
+    pub target_header_hash: Option<HeaderHash>,
+}
+
+/// Verify the proof of `document` against `key`, and that its public values are for the
+/// `expected` range. The error says which is at fault: the binding of the public inputs to the
+/// range, or the proof.
+pub fn verify_document(
+    document: &ProofDocument,
+    key: &VerifyingKey<Bn254>,
+    expected: &ExpectedRange,
+) -> Result<ProvedRange> {
+    let range = ProvedRange::decode(&document.public_values)
+        .context("public input binding: the public values are not those of a step or a skip")?;
+    range
+        .check(expected)
+        .context("public input binding: the public values are not those of the expected range")?;
+
+    let proof = decode_proof(&document.proof).context("proof: the proof is malformed")?;
+    let inputs = public_inputs(&document.public_values);
+    let verified = Groth16::<Bn254>::verify_proof(&prepare_verifying_key(key), &proof, &inputs)
+        .map_err(|e| anyhow!("proof: failed to verify: {}", e))?;
+    ensure!(
+        verified,
+        "proof: the proof does not verify against the verifying key for its public values"
+    );
+    Ok(range)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KEY: &str = include_str!("fixtures/groth16/verifying_key.json");
+    const PROOF: &str = include_str!("fixtures/groth16/proof.json");
+    const CORRUPTED: &str = include_str!("fixtures/groth16/corrupted_proof.json");
+
+    fn key() -> VerifyingKey<Bn254> {
+        VerifyingKeyFile::parse(KEY).unwrap().to_key().unwrap()
+    }
+
+    fn document(json: &str) -> ProofDocument {
+        serde_json::from_str(json).unwrap()
+    }
+
+    fn skip() -> ExpectedRange {
+        ExpectedRange {
+            trusted_block: Some(Height(10000)),
+            trusted_header_hash: Some(HeaderHash([0xab; 32])),
+            target_block: Some(Height(10500)),
+            target_header_hash: Some(HeaderHash([0xcd; 32])),
+        }
+    }
+
+    #[test]
+    fn test_verify_document() {
+        let range = verify_document(&document(PROOF), &key(), &skip()).unwrap();
+        assert_eq!(
+            range,
+            ProvedRange {
+                trusted_block: Height(10000),
+                trusted_header_hash: HeaderHash([0xab; 32]),
+                target_block: Height(10500),
+                target_header_hash: HeaderHash([0xcd; 32]),
+            }
+        );
+        assert_eq!(
+            verify_document(&document(PROOF), &key(), &ExpectedRange::default()).unwrap(),
+            range
+        );
+        assert!(range.to_string().starts_with("trusted block: 10000\n"));
+    }
+
+    #[test]
+    fn test_verify_corrupted_proof() {
+        let error = verify_document(&document(CORRUPTED), &key(), &skip()).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "proof: the proof does not verify against the verifying key for its public values"
+        );
+
+        // The valid proof, for other public values.
+        let mut document = document(PROOF);
+        document.public_values.output = vec![0xce; 32].into();
+        let expected = ExpectedRange {
+            target_header_hash: None,
+            ..skip()
+        };
+        let error = verify_document(&document, &key(), &expected).unwrap_err();
+        assert!(error.to_string().starts_with("proof: "), "{:#}", error);
+
+        let mut truncated = document.clone();
+        truncated.proof = truncated.proof[..PROOF_LEN - 1].to_vec().into();
+        let error = verify_document(&truncated, &key(), &expected).unwrap_err();
+        assert_eq!(
+            format!("{:#}", error),
+            "proof: the proof is malformed: a proof is 256 bytes, not 255"
+        );
+    }
+
+    #[test]
+    fn test_verify_binding() {
+        let document = document(PROOF);
+        let expected = ExpectedRange {
+            target_block: Some(Height(10501)),
+            ..skip()
+        };
+        let error = verify_document(&document, &key(), &expected).unwrap_err();
+        assert_eq!(
+            format!("{:#}", error),
+            "public input binding: the public values are not those of the expected range: the \
+             proof is for the target block 10500, not 10501"
+        );
+
+        let mut step = document.clone();
+        step.public_values.input = step.public_values.input[..StepInput::LEN].to_vec().into();
+        let error = verify_document(&step, &key(), &skip()).unwrap_err();
+        assert!(
+            error.to_string().starts_with("public input binding: "),
+            "{:#}",
+            error
+        );
+        // As a step, the public values decode, but the proof is for the skip.
+        let range = ProvedRange::decode(&step.public_values).unwrap();
+        assert_eq!(range.target_block, Height(10001));
+        let error = verify_document(&step, &key(), &ExpectedRange::default()).unwrap_err();
+        assert!(error.to_string().starts_with("proof: "), "{:#}", error);
+
+        let mut garbled = document;
+        garbled.public_values.input = vec![1; 3].into();
+        let error = verify_document(&garbled, &key(), &ExpectedRange::default()).unwrap_err();
+        assert_eq!(
+            format!("{:#}", error),
+            "public input binding: the public values are not those of a step or a skip: an input \
+             of 3 bytes is neither a step nor a skip input"
+        );
+    }
+
+    #[test]
+    fn test_verifying_key_file() {
+        let file = VerifyingKeyFile::parse(KEY).unwrap();
+        let mut short = file.clone();
+        short.ic.pop();
+        assert_eq!(
+            short.to_key().unwrap_err().to_string(),
+            "the verifying key takes 1 public inputs, not the 2 of the circuits"
+        );
+        let mut off_curve = file.clone();
+        off_curve.alpha_g1[1] = off_curve.alpha_g1[0];
+        assert_eq!(
+            format!("{:#}", off_curve.to_key().unwrap_err()),
+            "invalid alpha: the G1 point is not on the curve"
+        );
+        let mut over = file;
+        over.delta_g2[0] = B256::repeat_byte(0xff);
+        assert!(over.to_key().is_err());
+    }
+}
@@ -0,0 +1,579 @@
+//! The gRPC service of `tendermintx serve`, generated from `proto/tendermintx.proto`.
+//!
+//! It answers as the REST API of `api` does: `Prove` requests a proof through the same checks as
+//! `tendermintx prove`, from the latest block of the first target unless a trusted block is
+//! given, and `GetRequest`, `ListRequests` and `GetStatus` read the request store and the targets.
+//! `WatchUpdates` streams the `HeadUpdate` events the operator observes, from the request store's
+//! subscribers, so it requires a store. Every call must carry the token of the config as
+//! `authorization: Bearer <token>` metadata.
+
+use std::net::SocketAddr;
+use std::pin::Pin;
+
+use anyhow::{Context, Result};
+use ethers::providers::Middleware;
+use futures::Stream;
+use sha2::{Digest, Sha256};
+use tokio::sync::broadcast::error::RecvError;
+use tonic::service::interceptor::InterceptedService;
+use tonic::service::Interceptor;
+use tonic::{Code, Request, Response, Status};
+use tracing::{error, info, warn};
+
+use crate::correlation::CorrelationId;
+use crate::dashboard::StatusSnapshot;
+use crate::operator::{BeyondChainHead, RefusedRequest, SharedOperator, TendermintXOperator};
+use crate::sink::SinkEvent;
+use crate::store::{RequestRecord, RequestStore};
+use crate::types::{HeaderHash, Height};
+
+pub mod proto {
+    tonic::include_proto!("tendermintx.v1");
+}
+
+use proto::operator_server::{Operator, OperatorServer};
+
+/// The number of requests `ListRequests` lists by default.
+pub const DEFAULT_LIST_LIMIT: usize = 100;
+
+/// Refuses calls without the bearer token. The tokens are compared by digest, so that the time
+/// taken doesn't tell how much of one matched.
+#[derive(Clone)]
+pub struct TokenInterceptor {
+    digest: [u8; 32],
+}
+
+impl TokenInterceptor {
+    pub fn new(token: &str) -> Self {
+        Self {
+            digest: Sha256::digest(token.as_bytes()).into(),
+        }
+    }
+}
+
+impl Interceptor for TokenInterceptor {
+    fn call(&mut self, request: Request<()>) -> Result<Request<()>, Status> {
+        let bearer = request
+            .metadata()
+            .get("authorization")
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+        match bearer {
+            Some(bearer) if Sha256::digest(bearer.as_bytes())[..] == self.digest => Ok(request),
+            _ => Err(Status::unauthenticated("missing or wrong bearer token")),
+        }
+    }
+}
+
+/// The service, answering with `operator`.
+pub struct OperatorService<M> {
+    operator: SharedOperator<M>,
+}
+
+impl<M> OperatorService<M> {
+    pub fn new(operator: SharedOperator<M>) -> Self {
+        Self { operator }
+    }
+}
+
+/// The service with `operator`, taking `token`.
+pub fn server<M: Middleware + 'static>(
+    operator: SharedOperator<M>,
+    token: &str,
+) -> InterceptedService<OperatorServer<OperatorService<M>>, TokenInterceptor> {
+    OperatorServer::with_interceptor(OperatorService::new(operator), TokenInterceptor::new(token))
+}
+
+/// Serve the service with `operator` on `addr` until it fails.
+pub async fn serve<M: Middleware + 'static>(
+    operator: SharedOperator<M>,
+    token: &str,
+    addr: SocketAddr,
+) -> Result<()> {
+    info!("Serving gRPC on {}", addr);
+    tonic::transport::Server::builder()
+        .add_service(server(operator, token))
+        .serve(addr)
+        .await
+        .with_context(|| format!("the gRPC service on {} failed", addr))
+}
+
+/// The status of a proof request that failed with `error`: invalid past the chain head, a failed
+/// precondition if refused, and unavailable if the chain, a contract or the backend failed.
+fn prove_status(error: &anyhow::Error) -> Status {
+    let message = format!("{:#}", error);
+    if error.is::<BeyondChainHead>() {
+        Status::invalid_argument(message)
+    } else if RefusedRequest::is(error) {
+        Status::failed_precondition(message)
+    } else {
+        Status::unavailable(message)
+    }
+}
+
+fn internal(error: anyhow::Error) -> Status {
+    Status::internal(format!("{:#}", error))
+}
+
+fn store<M>(operator: &TendermintXOperator<M>) -> Result<&RequestStore, Status> {
+    operator
+        .store()
+        .map(|store| store.as_ref())
+        .ok_or_else(|| Status::unimplemented("this requires a request store"))
+}
+
+impl From<RequestRecord> for proto::Request {
+    fn from(record: RequestRecord) -> Self {
+        Self {
+            request_id: record.request_id,
+            chain_id: record.chain_id,
+            contract_address: record.contract_address.to_string(),
+            trusted_block: record.trusted_block,
+            trusted_hash: HeaderHash(record.trusted_hash).to_string(),
+            target_block: record.target_block,
+            function_id: record.function_id.to_string(),
+            status: record.status.as_str().to_string(),
+            correlation_id: record.correlation_id.map(|id| id.to_string()),
+            retry_of: record.retry_of,
+            attempt: record.attempt,
+            backend: record.backend,
+            created_at: record.created_at,
+            updated_at: record.updated_at,
+            finished_at: record.finished_at,
+        }
+    }
+}
+
+impl From<StatusSnapshot> for proto::Status {
+    fn from(snapshot: StatusSnapshot) -> Self {
+        let targets = snapshot
+            .targets
+            .into_iter()
+            .map(|target| proto::TargetStatus {
+                target: target.target,
+                latest_block: target.latest_block,
+                updated_at: target.updated_at,
+                lag_blocks: target.lag.blocks,
+                lag_secs: target.lag.seconds,
+                relayer_balance: target.balance.map(|report| report.balance.to_string()),
+                trusting_margin_secs: target.trusting_margin,
+                step_function_id: target.step_function_id.to_string(),
+                skip_function_id: target.skip_function_id.to_string(),
+                function_ids_from: target
+                    .function_ids_from
+                    .map_or_else(String::new, |source| source.to_string()),
+            });
+        let pending = snapshot
+            .pending
+            .into_iter()
+            .map(|pending| proto::PendingRequest {
+                request_id: pending.request_id,
+                target: pending.target,
+                target_block: pending.target_block,
+                status: pending.status.as_str().to_string(),
+                age_secs: pending.age.as_secs(),
+            });
+        Self {
+            head_block: snapshot.head_block,
+            head_time: snapshot.head_time,
+            targets: targets.collect(),
+            endpoints: snapshot.endpoints.iter().map(|e| e.to_string()).collect(),
+            pending: pending.collect(),
+            errors: snapshot.errors,
+        }
+    }
+}
+
+/// The head update of `event`, if it is one and of a target on `chain_id`, if set.
+fn head_update(event: SinkEvent, chain_id: Option<u32>) -> Option<proto::HeadUpdate> {
+    match event {
+        SinkEvent::HeadUpdate {
+            chain_id: event_chain_id,
+            contract_address,
+            block_number,
+            header_hash,
+            tx_hash,
+            eth_block_number,
+            timestamp,
+        } if chain_id.map_or(true, |id| id == event_chain_id) => Some(proto::HeadUpdate {
+            chain_id: event_chain_id,
+            contract_address: contract_address.to_string(),
+            block_number,
+            header_hash: header_hash.to_string(),
+            tx_hash: tx_hash.to_string(),
+            eth_block_number,
+            timestamp,
+        }),
+        _ => None,
+    }
+}
+
+type UpdateStream = Pin<Box<dyn Stream<Item = Result<proto::HeadUpdate, Status>> + Send>>;
+
+#[tonic::async_trait]
+impl<M: Middleware + 'static> Operator for OperatorService<M> {
+    async fn prove(
+        &self,
+        request: Request<proto::ProveRequest>,
+    ) -> Result<Response<proto::ProveResponse>, Status> {
+        let request = request.into_inner();
+        let target_block = Height(request.target_block);
+        let operator = self.operator.read().await;
+        let (trusted_block, trusted_hash) = operator
+            .stored_trusted_header(request.trusted_block.map(Height))
+            .await
+            .map_err(|e| prove_status(&e))?;
+        info!(%trusted_block, %target_block, "Proving for gRPC");
+        let submitted = operator
+            .prove(trusted_block..=target_block, trusted_hash)
+            .await
+            .map_err(|e| {
+                error!("The request over gRPC failed: {:#}", e);
+                prove_status(&e)
+            })?;
+        // Every target already has a request pending for the range.
+        let Some(first) = submitted.first() else {
+            return Err(Status::already_exists(format!(
+                "a request from {} to {} is already pending for every target",
+                trusted_block, target_block
+            )));
+        };
+        Ok(Response::new(proto::ProveResponse {
+            correlation_id: first.correlation_id.to_string(),
+            trusted_block: trusted_block.value(),
+            target_block: target_block.value(),
+            requests: submitted
+                .iter()
+                .map(|request| proto::SubmittedRequest {
+                    target: request.target.to_string(),
+                    request_id: request.request_id.clone(),
+                })
+                .collect(),
+        }))
+    }
+
+    async fn get_request(
+        &self,
+        request: Request<proto::GetRequestRequest>,
+    ) -> Result<Response<proto::Request>, Status> {
+        let request_id = request.into_inner().request_id;
+        let operator = self.operator.read().await;
+        match store(&operator)?.get(&request_id).map_err(internal)? {
+            Some(record) => Ok(Response::new(record.into())),
+            None => Err(Status::not_found(format!(
+                "no request stored for {}",
+                request_id
+            ))),
+        }
+    }
+
+    async fn list_requests(
+        &self,
+        request: Request<proto::ListRequestsRequest>,
+    ) -> Result<Response<proto::ListRequestsResponse>, Status> {
+        let request = request.into_inner();
+        let limit = match request.limit {
+            0 => DEFAULT_LIST_LIMIT,
+            limit => limit as usize,
+        };
+        let operator = self.operator.read().await;
+        let store = store(&operator)?;
+        let mut records = match request.correlation_id {
+            Some(id) => {
+                let correlation_id = id
+                    .parse::<CorrelationId>()
+                    .map_err(|e| Status::invalid_argument(format!("{:#}", e)))?;
+                store.by_correlation_id(correlation_id)
+            }
+            None if request.pending => store.pending(),
+            None => store.list(limit),
+        }
+        .map_err(internal)?;
+        if request.pending {
+            records.retain(|record| record.status.is_pending());
+        }
+        records.truncate(limit);
+        Ok(Response::new(proto::ListRequestsResponse {
+            requests: records.into_iter().map(Into::into).collect(),
+        }))
+    }
+
+    async fn get_status(
+        &self,
+        _request: Request<proto::GetStatusRequest>,
+    ) -> Result<Response<proto::Status>, Status> {
+        let snapshot = TendermintXOperator::shared_status(&self.operator)
+            .await
+            .map_err(|e| Status::new(Code::Unavailable, format!("{:#}", e)))?;
+        Ok(Response::new(snapshot.into()))
+    }
+
+    type WatchUpdatesStream = UpdateStream;
+
+    async fn watch_updates(
+        &self,
+        request: Request<proto::WatchUpdatesRequest>,
+    ) -> Result<Response<Self::WatchUpdatesStream>, Status> {
+        let chain_id = request.into_inner().chain_id;
+        let events = store(&*self.operator.read().await)?.subscribe();
+        let updates = futures::stream::unfold(events, move |mut events| async move {
+            loop {
+                match events.recv().await {
+                    Ok(event) => {
+                        if let Some(update) = head_update(event, chain_id) {
+                            return Some((Ok(update), events));
+                        }
+                    }
+                    Err(RecvError::Lagged(missed)) => {
+                        warn!("A gRPC watcher fell behind and missed {} events", missed);
+                    }
+                    Err(RecvError::Closed) => return None,
+                }
+            }
+        });
+        let updates: UpdateStream = Box::pin(updates);
+        Ok(Response::new(updates))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use alloy_primitives::{Address, B256};
+    use ethers::providers::{MockProvider, Provider};
+    use futures::StreamExt;
+    use tokio::sync::RwLock;
+    use tonic::metadata::MetadataValue;
+    use tonic::transport::{Channel, Endpoint, Server, Uri};
+
+    use super::proto::operator_client::OperatorClient;
+    use super::*;
+    use crate::backend::mock::MockBackend;
+    use crate::input::{header_hash, InputDataFetcher, InputDataMode};
+    use crate::operator::TendermintXConfig;
+    use crate::target::RequestTarget;
+    use crate::testing::{MockTendermintServer, SyntheticChain};
+    use crate::trusted::LocalTrustedState;
+
+    const TOKEN: &str = "grpc-token";
+
+    /// An operator of the chain of `server` with a request store in `dir`, trusting its block 100.
+    fn test_operator(
+        server: &MockTendermintServer,
+        dir: &std::path::Path,
+    ) -> SharedOperator<Provider<MockProvider>> {
+        let mut fetcher = InputDataFetcher::new(vec![server.url()], "");
+        fetcher.mode = InputDataMode::Rpc;
+        let mut config = TendermintXConfig::new(vec![RequestTarget::for_test(5)]);
+        config.store_path = Some(dir.join("requests.db"));
+        let (provider, _) = Provider::<MockProvider>::mocked();
+        let backend = Box::new(MockBackend::new());
+        let mut operator =
+            TendermintXOperator::new(config, fetcher, backend, vec![Arc::new(provider)]).unwrap();
+        let trusted = Arc::new(LocalTrustedState::new(1000));
+        let hash = header_hash(&server.chain().header(100));
+        trusted.insert(Height(100), hash).unwrap();
+        operator.set_trusted_state(0, trusted).unwrap();
+        Arc::new(RwLock::new(operator))
+    }
+
+    /// A channel to the service of `operator`, served in memory.
+    async fn in_memory_channel(operator: SharedOperator<Provider<MockProvider>>) -> Channel {
+        let (client, server_io) = tokio::io::duplex(64 * 1024);
+        tokio::spawn(async move {
+            Server::builder()
+                .add_service(server(operator, TOKEN))
+                .serve_with_incoming(futures::stream::iter([Ok::<_, std::io::Error>(server_io)]))
+                .await
+        });
+        let mut client = Some(client);
+        Endpoint::try_from("http://[::]:50051")
+            .unwrap()
+            .connect_with_connector(tower::service_fn(move |_: Uri| {
+                let client = client.take();
+                async move {
+                    client.ok_or_else(|| {
+                        std::io::Error::new(std::io::ErrorKind::Other, "already connected")
+                    })
+                }
+            }))
+            .await
+            .unwrap()
+    }
+
+    fn authorized<T>(message: T) -> Request<T> {
+        let mut request = Request::new(message);
+        let bearer: MetadataValue<_> = format!("Bearer {}", TOKEN).parse().unwrap();
+        request.metadata_mut().insert("authorization", bearer);
+        request
+    }
+
+    #[tokio::test]
+    async fn test_service() {
+        let server = MockTendermintServer::start(SyntheticChain::new(1))
+            .await
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let operator = test_operator(&server, dir.path());
+        let mut client = OperatorClient::new(in_memory_channel(operator.clone()).await);
+
+        let status = client
+            .get_status(proto::GetStatusRequest {})
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::Unauthenticated);
+
+        let proved = client
+            .prove(authorized(proto::ProveRequest {
+                trusted_block: None,
+                target_block: 200,
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!((proved.trusted_block, proved.target_block), (100, 200));
+        assert_eq!(
+            proved.requests,
+            [proto::SubmittedRequest {
+                target: RequestTarget::for_test(5).to_string(),
+                request_id: "mock-1".to_string(),
+            }]
+        );
+        let status = client
+            .prove(authorized(proto::ProveRequest {
+                trusted_block: Some(100),
+                target_block: 200,
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::AlreadyExists);
+        let status = client
+            .prove(authorized(proto::ProveRequest {
+                trusted_block: Some(150),
+                target_block: 200,
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::FailedPrecondition);
+        assert_eq!(status.message(), "no header stored for trusted block 150");
+        let status = client
+            .prove(authorized(proto::ProveRequest {
+                trusted_block: Some(100),
+                target_block: 1001,
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::InvalidArgument);
+        assert_eq!(
+            status.message(),
+            "target block 1001 is after the chain head 1000"
+        );
+
+        let request = client
+            .get_request(authorized(proto::GetRequestRequest {
+                request_id: "mock-1".to_string(),
+            }))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(request.status, "pending");
+        assert_eq!(request.correlation_id, Some(proved.correlation_id.clone()));
+        let status = client
+            .get_request(authorized(proto::GetRequestRequest {
+                request_id: "mock-2".to_string(),
+            }))
+            .await
+            .unwrap_err();
+        assert_eq!(status.code(), Code::NotFound);
+
+        for list in [
+            proto::ListRequestsRequest::default(),
+            proto::ListRequestsRequest {
+                correlation_id: Some(proved.correlation_id.clone()),
+                pending: true,
+                ..Default::default()
+            },
+        ] {
+            let listed = client
+                .list_requests(authorized(list))
+                .await
+                .unwrap()
+                .into_inner();
+            assert_eq!(listed.requests, [request.clone()]);
+        }
+
+        let status = client
+            .get_status(authorized(proto::GetStatusRequest {}))
+            .await
+            .unwrap()
+            .into_inner();
+        assert_eq!(status.head_block, 1000);
+        assert_eq!(status.targets[0].lag_blocks, 900);
+        assert_eq!(status.targets[0].function_ids_from, "config");
+        assert_eq!(status.pending[0].request_id, "mock-1");
+
+        // Once the function IDs are resolved, the status doesn't wait for a request being
+        // submitted.
+        let submitting = operator.read().await;
+        let status = client.get_status(authorized(proto::GetStatusRequest {}));
+        let status = tokio::time::timeout(Duration::from_secs(5), status)
+            .await
+            .expect("the status waited for the request")
+            .unwrap()
+            .into_inner();
+        assert_eq!(status.head_block, 1000);
+        drop(submitting);
+    }
+
+    #[tokio::test]
+    async fn test_watch_updates() {
+        let server = MockTendermintServer::start(SyntheticChain::new(1))
+            .await
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let operator = test_operator(&server, dir.path());
+        let mut client = OperatorClient::new(in_memory_channel(operator.clone()).await);
+
+        let mut updates = client
+            .watch_updates(authorized(proto::WatchUpdatesRequest { chain_id: Some(5) }))
+            .await
+            .unwrap()
+            .into_inner();
+        let head_update = |chain_id, block_number| SinkEvent::HeadUpdate {
+            chain_id,
+            contract_address: Address::repeat_byte(0x11),
+            block_number,
+            header_hash: B256::repeat_byte(0xbb),
+            tx_hash: B256::repeat_byte(0xcc),
+            eth_block_number: 18_000_000,
+            timestamp: 2000,
+        };
+        {
+            let operator = operator.read().await;
+            let store = operator.store().unwrap();
+            // Only the head updates of the targets on chain 5 are streamed.
+            store.publish(head_update(6, 150));
+            store.publish(SinkEvent::Transition {
+                request_id: "req_1".to_string(),
+                status: crate::store::RequestStatus::Relayed,
+                at: 1500,
+            });
+            store.publish(head_update(5, 200));
+        }
+        let update = updates.next().await.unwrap().unwrap();
+        assert_eq!(
+            update,
+            proto::HeadUpdate {
+                chain_id: 5,
+                contract_address: Address::repeat_byte(0x11).to_string(),
+                block_number: 200,
+                header_hash: B256::repeat_byte(0xbb).to_string(),
+                tx_hash: B256::repeat_byte(0xcc).to_string(),
+                eth_block_number: 18_000_000,
+                timestamp: 2000,
+            }
+        );
+    }
+}
@@ -0,0 +1,255 @@
+//! Liveness and readiness of the operator, served at `/healthz` and `/readyz` next to the
+//! metrics.
+//!
+//! The operator is live while its run loop keeps completing iterations: the last one must have
+//! completed within `max_iteration_age` (before the first iteration, the age counts from
+//! startup). It is ready when its configuration was validated, which holds once it is serving,
+//! and every `ReadinessCheck`, e.g. that the target contracts and the Tendermint RPC are
+//! reachable, passes within `check_timeout`.
+
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use ethers::providers::Middleware;
+use futures::future::join_all;
+use serde_json::{json, Map, Value};
+
+use crate::contract::TendermintXContract;
+use crate::target::RequestTarget;
+
+/// A dependency that must be reachable for the operator to be ready.
+#[async_trait]
+pub trait ReadinessCheck: Send + Sync {
+    /// The key of the check in the `/readyz` detail.
+    fn name(&self) -> String;
+
+    async fn check(&self) -> Result<()>;
+}
+
+/// Checks that the light client contract of a target can be read.
+pub struct ContractCheck<M> {
+    target: RequestTarget,
+    contract: TendermintXContract<M>,
+}
+
+impl<M> ContractCheck<M> {
+    pub fn new(target: RequestTarget, contract: TendermintXContract<M>) -> Self {
+        Self { target, contract }
+    }
+}
+
+#[async_trait]
+impl<M: Middleware + 'static> ReadinessCheck for ContractCheck<M> {
+    fn name(&self) -> String {
+        format!("contract {}", self.target)
+    }
+
+    async fn check(&self) -> Result<()> {
+        self.contract.latest_block().await.map(|_| ())
+    }
+}
+
+/// Checks that at least one of the Tendermint RPC endpoints answers a status query.
+pub struct TendermintRpcCheck {
+    urls: Vec<String>,
+}
+
+impl TendermintRpcCheck {
+    pub fn new(urls: Vec<String>) -> Self {
+        Self { urls }
+    }
+}
+
+#[async_trait]
+impl ReadinessCheck for TendermintRpcCheck {
+    fn name(&self) -> String {
+        "tendermint_rpc".to_string()
+    }
+
+    async fn check(&self) -> Result<()> {
+        let mut errors = Vec::new();
+        for url in self.urls.iter() {
+            match reqwest::get(format!("{}/status", url))
+                .await
+                .and_then(|res| res.error_for_status())
+            {
+                Ok(_) => return Ok(()),
+                Err(e) => errors.push(format!("{}: {}", url, e)),
+            }
+        }
+        Err(anyhow!("no endpoint is reachable: {}", errors.join("; ")))
+    }
+}
+
+pub struct Health {
+    started: Instant,
+    last_iteration: Mutex<Option<Instant>>,
+    max_iteration_age: Duration,
+    check_timeout: Duration,
+    checks: Vec<Box<dyn ReadinessCheck>>,
+}
+
+impl Health {
+    pub fn new(max_iteration_age: Duration, check_timeout: Duration) -> Self {
+        Self {
+            started: Instant::now(),
+            last_iteration: Mutex::new(None),
+            max_iteration_age,
+            check_timeout,
+            checks: Vec::new(),
+        }
+    }
+
+    pub fn with_check(mut self, check: impl ReadinessCheck + 'static) -> Self {
+        self.checks.push(Box::new(check));
+        self
+    }
+
+    /// Record that an iteration of the run loop completed.
+    pub fn record_iteration(&self) {
+        *self.last_iteration.lock().unwrap() = Some(Instant::now());
+    }
+
+    /// Whether the run loop is live, and the detail served at `/healthz`.
+    pub fn liveness(&self) -> (bool, Value) {
+        let now = Instant::now();
+        let last_iteration = *self.last_iteration.lock().unwrap();
+        let age = now.duration_since(last_iteration.unwrap_or(self.started));
+        let live = age <= self.max_iteration_age;
+        let detail = json!({
+            "status": if live { "ok" } else { "stalled" },
+            "uptime_seconds": now.duration_since(self.started).as_secs(),
+            "last_iteration_seconds_ago": last_iteration.map(|at| now.duration_since(at).as_secs()),
+            "max_iteration_age_seconds": self.max_iteration_age.as_secs(),
+        });
+        (live, detail)
+    }
+
+    /// Run the readiness checks concurrently. Returns whether all of them passed, and the detail
+    /// served at `/readyz`.
+    pub async fn readiness(&self) -> (bool, Value) {
+        let results = join_all(self.checks.iter().map(|check| async move {
+            match tokio::time::timeout(self.check_timeout, check.check()).await {
+                Ok(Ok(())) => Ok(()),
+                Ok(Err(e)) => Err(format!("{:#}", e)),
+                Err(_) => Err(format!("timed out after {:?}", self.check_timeout)),
+            }
+        }))
+        .await;
+
+        let mut checks = Map::new();
+        checks.insert("config".to_string(), json!("ok"));
+        let mut ready = true;
+        for (check, result) in self.checks.iter().zip(results) {
+            let value = match result {
+                Ok(()) => json!("ok"),
+                Err(e) => {
+                    ready = false;
+                    json!(e)
+                }
+            };
+            checks.insert(check.name(), value);
+        }
+        let detail = json!({
+            "status": if ready { "ready" } else { "not_ready" },
+            "checks": checks,
+        });
+        (ready, detail)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    use super::*;
+    use crate::metrics::{serve, OperatorMetrics};
+
+    struct StaticCheck(Option<&'static str>);
+
+    #[async_trait]
+    impl ReadinessCheck for StaticCheck {
+        fn name(&self) -> String {
+            "static".to_string()
+        }
+
+        async fn check(&self) -> Result<()> {
+            match self.0 {
+                Some(e) => Err(anyhow!(e)),
+                None => Ok(()),
+            }
+        }
+    }
+
+    fn spawn_server(health: Arc<Health>) -> SocketAddr {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(
+            Arc::new(OperatorMetrics::new()),
+            health,
+            None,
+            listener,
+        ));
+        addr
+    }
+
+    async fn get(addr: SocketAddr, path: &str) -> (u16, Value) {
+        let response = reqwest::get(format!("http://{}{}", addr, path))
+            .await
+            .unwrap();
+        let status = response.status().as_u16();
+        (
+            status,
+            serde_json::from_str(&response.text().await.unwrap()).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_healthz_stalled_loop() {
+        let health = Arc::new(Health::new(
+            Duration::from_millis(500),
+            Duration::from_secs(1),
+        ));
+        let addr = spawn_server(health.clone());
+
+        // Within the threshold of startup, before the first iteration.
+        let (status, detail) = get(addr, "/healthz").await;
+        assert_eq!(status, 200);
+        assert_eq!(detail["last_iteration_seconds_ago"], Value::Null);
+
+        // The loop stalls after an iteration.
+        health.record_iteration();
+        tokio::time::sleep(Duration::from_millis(600)).await;
+        let (status, detail) = get(addr, "/healthz").await;
+        assert_eq!(status, 503);
+        assert_eq!(detail["status"], "stalled");
+
+        // It recovers with the next iteration.
+        health.record_iteration();
+        let (status, detail) = get(addr, "/healthz").await;
+        assert_eq!(status, 200);
+        assert_eq!(detail["status"], "ok");
+    }
+
+    #[tokio::test]
+    async fn test_readyz() {
+        let health = Health::new(Duration::from_secs(60), Duration::from_secs(1))
+            .with_check(StaticCheck(None));
+        let addr = spawn_server(Arc::new(health));
+        let (status, detail) = get(addr, "/readyz").await;
+        assert_eq!(status, 200);
+        assert_eq!(detail["checks"]["config"], "ok");
+        assert_eq!(detail["checks"]["static"], "ok");
+
+        let health = Health::new(Duration::from_secs(60), Duration::from_secs(1))
+            .with_check(StaticCheck(Some("connection refused")));
+        let addr = spawn_server(Arc::new(health));
+        let (status, detail) = get(addr, "/readyz").await;
+        assert_eq!(status, 503);
+        assert_eq!(detail["status"], "not_ready");
+        assert_eq!(detail["checks"]["static"], "connection refused");
+    }
+}
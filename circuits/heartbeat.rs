@@ -0,0 +1,168 @@
+//! A heartbeat at the end of every iteration of the run loop, for monitoring that treats missing
+//! data as a failure.
+//!
+//! Each beat sets the `tendermintx_last_iteration_timestamp` gauge, and optionally touches a file
+//! (writing the timestamp to it) for filesystem-based liveness checks and pings a dead man's
+//! switch URL. The beat fires whatever the iteration did, including nothing at all.
+
+use std::future::Future;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use log::warn;
+
+use crate::metrics::OperatorMetrics;
+use crate::store::unix_timestamp;
+
+/// The maximum time the ping of the heartbeat URL may take.
+const PING_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub struct Heartbeat {
+    metrics: Arc<OperatorMetrics>,
+    /// The file touched on every beat, if any.
+    file: Option<PathBuf>,
+    /// The URL POSTed to on every beat, if any.
+    url: Option<String>,
+    client: reqwest::Client,
+}
+
+impl Heartbeat {
+    pub fn new(metrics: Arc<OperatorMetrics>) -> Self {
+        Self {
+            metrics,
+            file: None,
+            url: None,
+            client: reqwest::Client::new(),
+        }
+    }
+
+    pub fn with_file(mut self, file: impl Into<PathBuf>) -> Self {
+        self.file = Some(file.into());
+        self
+    }
+
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = Some(url.into());
+        self
+    }
+
+    /// Run `iteration`, then beat. Returns the output of the iteration.
+    pub async fn after<T>(&self, iteration: impl Future<Output = T>) -> T {
+        let output = iteration.await;
+        self.beat().await;
+        output
+    }
+
+    /// Beat now. Failures to touch the file or ping the URL are logged.
+    pub async fn beat(&self) {
+        let timestamp = unix_timestamp();
+        self.metrics.record_heartbeat(timestamp);
+        if let Some(file) = self.file.as_ref() {
+            if let Err(e) = std::fs::write(file, format!("{}\n", timestamp)) {
+                warn!("Failed to touch heartbeat file {}: {}", file.display(), e);
+            }
+        }
+        if let Some(url) = self.url.as_ref() {
+            if let Err(e) = self.ping(url).await {
+                warn!("Failed to ping heartbeat URL: {:#}", e);
+            }
+        }
+    }
+
+    async fn ping(&self, url: &str) -> Result<()> {
+        self.client
+            .post(url)
+            .timeout(PING_TIMEOUT)
+            .send()
+            .await
+            .context("heartbeat ping")?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Response, Server};
+
+    use super::*;
+    use crate::backend::mock::MockBackend;
+    use crate::backend::ProofBackend;
+    use crate::export::RequestInputs;
+    use crate::target::{submit_to_targets, RequestTarget};
+    use crate::types::{HeaderHash, Height};
+
+    /// A heartbeat URL counting its pings.
+    fn ping_endpoint() -> (String, Arc<AtomicUsize>) {
+        let pings = Arc::new(AtomicUsize::new(0));
+        let counter = pings.clone();
+        let make_service = make_service_fn(move |_| {
+            let counter = counter.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |_| {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    async { Ok::<_, Infallible>(Response::new(Body::empty())) }
+                }))
+            }
+        });
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        listener.set_nonblocking(true).unwrap();
+        tokio::spawn(Server::from_tcp(listener).unwrap().serve(make_service));
+        (format!("http://{}/ping", addr), pings)
+    }
+
+    #[tokio::test]
+    async fn test_heartbeat_on_every_iteration() {
+        let target = RequestTarget::for_test(5);
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("heartbeat");
+        let (url, pings) = ping_endpoint();
+        let metrics = Arc::new(OperatorMetrics::new());
+        let heartbeat = Heartbeat::new(metrics.clone())
+            .with_file(&file)
+            .with_url(url);
+        let backend = MockBackend::new();
+        let inputs = RequestInputs::new(Height(100), HeaderHash([0xab; 32]), Height(500)).unwrap();
+
+        // An iteration that submits a request.
+        let submitted = heartbeat
+            .after(async {
+                let submissions = submit_to_targets([&target], |target| {
+                    let request = inputs.proof_request(target);
+                    let backend = &backend;
+                    async move { backend.request_skip(&request).await }
+                })
+                .await;
+                !submissions.is_empty()
+            })
+            .await;
+        assert!(submitted);
+        assert_eq!(backend.requests().len(), 1);
+        assert_eq!(pings.load(Ordering::SeqCst), 1);
+        let touched: u64 = std::fs::read_to_string(&file)
+            .unwrap()
+            .trim()
+            .parse()
+            .unwrap();
+        assert!(touched > 0);
+        assert!(metrics.render(None).unwrap().contains(&format!(
+            "tendermintx_last_iteration_timestamp {}\n",
+            touched
+        )));
+
+        // An iteration with nothing to do still beats.
+        std::fs::remove_file(&file).unwrap();
+        let submitted = heartbeat.after(async { false }).await;
+        assert!(!submitted);
+        assert_eq!(backend.requests().len(), 1);
+        assert_eq!(pings.load(Ordering::SeqCst), 2);
+        assert!(file.exists());
+    }
+}
@@ -0,0 +1,297 @@
+//! IBC client updates built from the headers the operator fetches, for relayers based on ibc-rs.
+//!
+//! A `MsgUpdateClient` of an ICS-07 Tendermint client carries a `Header`: the signed header of the
+//! target block and its validator set, with the height the client already trusts and the
+//! validators it trusts there, i.e. those the trusted header commits to as its next validators.
+//! `HeaderBundle::fetch` reads these from the chain and checks that they hash to what the headers
+//! commit to, and `to_ibc_header` converts them the way Hermes does: the validator set of the
+//! target block with its proposer, the trusted validators without one, and both heights in the
+//! revision of the chain ID.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, ensure, Context, Result};
+use ibc_proto::google::protobuf::Any;
+use ibc_proto::ibc::core::client::v1::Height as IbcHeight;
+use ibc_proto::ibc::lightclients::tendermint::v1::Header;
+use prost::Message;
+use tendermint::block::signed_header::SignedHeader;
+use tendermint::validator::{Info, Set as TendermintValidatorSet};
+
+use crate::input::power::check_voting_powers;
+use crate::input::{header_hash, InputDataFetcher};
+use crate::types::{HeaderHash, Height};
+
+/// The type URL of a Tendermint client header in a `google.protobuf.Any`.
+pub const HEADER_TYPE_URL: &str = "/ibc.lightclients.tendermint.v1.Header";
+
+/// What a client update from the trusted block to the target block is built from.
+#[derive(Debug, Clone)]
+pub struct HeaderBundle {
+    /// The signed header of the trusted block.
+    pub trusted: SignedHeader,
+    /// The validators of the block after the trusted block.
+    pub trusted_next_validators: Vec<Info>,
+    /// The signed header of the target block.
+    pub target: SignedHeader,
+    /// The validators of the target block.
+    pub target_validators: Vec<Info>,
+}
+
+impl HeaderBundle {
+    /// Fetch the headers and validator sets of an update from `trusted` to `target`.
+    pub async fn fetch(
+        fetcher: &InputDataFetcher,
+        trusted: Height,
+        target: Height,
+    ) -> Result<Self> {
+        ensure!(
+            trusted < target,
+            "the target block {} is not after the trusted block {}",
+            target,
+            trusted
+        );
+        let bundle = Self {
+            trusted: fetcher.get_signed_header_from_number(trusted).await?,
+            trusted_next_validators: fetcher
+                .get_validator_set_from_number(trusted.next())
+                .await?,
+            target: fetcher.get_signed_header_from_number(target).await?,
+            target_validators: fetcher.get_validator_set_from_number(target).await?,
+        };
+        bundle.check()?;
+        Ok(bundle)
+    }
+
+    /// Check that both headers are those their commits sign, of the same chain, and that the
+    /// validator sets are those the headers commit to.
+    pub fn check(&self) -> Result<()> {
+        for signed_header in [&self.trusted, &self.target] {
+            let hash = header_hash(&signed_header.header);
+            let committed = HeaderHash::try_from(signed_header.commit.block_id.hash)?;
+            ensure!(
+                hash == committed,
+                "the header of block {} hashes to {}, but its commit signs {}",
+                signed_header.header.height,
+                hash,
+                committed
+            );
+        }
+        let (trusted, target) = (&self.trusted.header, &self.target.header);
+        ensure!(
+            trusted.chain_id == target.chain_id,
+            "the trusted block is of {}, the target block of {}",
+            trusted.chain_id,
+            target.chain_id
+        );
+        ensure!(
+            trusted.height < target.height,
+            "the target block {} is not after the trusted block {}",
+            target.height,
+            trusted.height
+        );
+        check_voting_powers(&self.trusted_next_validators)?;
+        check_voting_powers(&self.target_validators)?;
+        let next_validators =
+            TendermintValidatorSet::new(self.trusted_next_validators.clone(), None);
+        ensure!(
+            next_validators.hash() == trusted.next_validators_hash,
+            "the next validators of block {} do not hash to its next validators hash",
+            trusted.height
+        );
+        let validators = TendermintValidatorSet::new(self.target_validators.clone(), None);
+        ensure!(
+            validators.hash() == target.validators_hash,
+            "the validators of block {} do not hash to its validators hash",
+            target.height
+        );
+        Ok(())
+    }
+}
+
+/// The revision number of a chain ID, as IBC parses it: the number after the last dash of an ID
+/// of the form `{name}-{number}`, without leading zeros. 0 for other IDs.
+pub fn revision_number(chain_id: &str) -> u64 {
+    match chain_id.rsplit_once('-') {
+        Some((name, number))
+            if !name.is_empty() && !name.ends_with('-') && !number.starts_with('0') =>
+        {
+            number.parse().unwrap_or(0)
+        }
+        _ => 0,
+    }
+}
+
+/// The Tendermint client header of a client update from the bundle's trusted block to its target
+/// block.
+pub fn to_ibc_header(bundle: &HeaderBundle) -> Result<Header> {
+    bundle.check()?;
+    let target = &bundle.target.header;
+    let validators = TendermintValidatorSet::with_proposer(
+        bundle.target_validators.clone(),
+        target.proposer_address,
+    )
+    .map_err(|e| {
+        anyhow!(
+            "the proposer of block {} is not one of its validators: {}",
+            target.height,
+            e
+        )
+    })?;
+    let trusted_validators =
+        TendermintValidatorSet::new(bundle.trusted_next_validators.clone(), None);
+    Ok(Header {
+        signed_header: Some(bundle.target.clone().into()),
+        validator_set: Some(validators.into()),
+        trusted_height: Some(IbcHeight {
+            revision_number: revision_number(target.chain_id.as_str()),
+            revision_height: bundle.trusted.header.height.value(),
+        }),
+        trusted_validators: Some(trusted_validators.into()),
+    })
+}
+
+/// The header as the `Any` of a `MsgUpdateClient`.
+pub fn to_any(header: &Header) -> Any {
+    Any {
+        type_url: HEADER_TYPE_URL.to_string(),
+        value: header.encode_to_vec(),
+    }
+}
+
+/// Fetch the client update from `trusted` to `target` and encode its header as a protobuf `Any`.
+pub async fn encode_ibc_header(
+    fetcher: &InputDataFetcher,
+    trusted: Height,
+    target: Height,
+) -> Result<Vec<u8>> {
+    let bundle = HeaderBundle::fetch(fetcher, trusted, target).await?;
+    Ok(to_any(&to_ibc_header(&bundle)?).encode_to_vec())
+}
+
+/// Write the encoded header of the client update from `trusted` to `target` to `path`.
+pub async fn write_ibc_header(
+    fetcher: &InputDataFetcher,
+    trusted: Height,
+    target: Height,
+    path: &Path,
+) -> Result<()> {
+    let header = encode_ibc_header(fetcher, trusted, target).await?;
+    fs::write(path, header).with_context(|| format!("failed to write {}", path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use tendermint_proto::types::ValidatorSet as RawValidatorSet;
+
+    use super::*;
+    use crate::testing::{fixture_fetcher, MockTendermintServer, SyntheticChain};
+
+    fn validator_set(raw: Option<RawValidatorSet>) -> TendermintValidatorSet {
+        TendermintValidatorSet::try_from(raw.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_revision_number() {
+        assert_eq!(revision_number("mocha-4"), 4);
+        assert_eq!(revision_number("osmosis-1"), 1);
+        assert_eq!(revision_number("dydx-mainnet-12"), 12);
+        assert_eq!(revision_number("celestia"), 0);
+        assert_eq!(revision_number("cosmoshub-04"), 0);
+        assert_eq!(revision_number("-1"), 0);
+        assert_eq!(revision_number("a--1"), 0);
+        assert_eq!(revision_number("mocha-x"), 0);
+    }
+
+    #[tokio::test]
+    async fn test_to_ibc_header() {
+        // The skip of the fixtures, from 10000 to 10500. Hermes builds its header from the same
+        // responses: the commit and validators of the target block, and the validators of the
+        // block after the trusted block.
+        let fetcher = fixture_fetcher();
+        let bundle = HeaderBundle::fetch(&fetcher, Height(10000), Height(10500))
+            .await
+            .unwrap();
+        let header = to_ibc_header(&bundle).unwrap();
+
+        assert_eq!(
+            header.trusted_height,
+            Some(IbcHeight {
+                revision_number: 4,
+                revision_height: 10000,
+            })
+        );
+        let signed_header = SignedHeader::try_from(header.signed_header.clone().unwrap()).unwrap();
+        assert_eq!(signed_header, bundle.target);
+        assert_eq!(signed_header.header.height.value(), 10500);
+
+        let validators = validator_set(header.validator_set.clone());
+        assert_eq!(validators.hash(), bundle.target.header.validators_hash);
+        assert_eq!(
+            validators.proposer().as_ref().map(|p| p.address),
+            Some(bundle.target.header.proposer_address)
+        );
+        assert_eq!(
+            validators.validators().len(),
+            bundle.target_validators.len()
+        );
+        let trusted_validators = validator_set(header.trusted_validators.clone());
+        assert_eq!(
+            trusted_validators.hash(),
+            bundle.trusted.header.next_validators_hash
+        );
+        assert!(trusted_validators.proposer().is_none());
+
+        let any = Any::decode(&*to_any(&header).encode_to_vec()).unwrap();
+        assert_eq!(any.type_url, HEADER_TYPE_URL);
+        assert_eq!(Header::decode(&*any.value).unwrap(), header);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("header.pb");
+        write_ibc_header(&fetcher, Height(10000), Height(10500), &path)
+            .await
+            .unwrap();
+        assert_eq!(fs::read(&path).unwrap(), to_any(&header).encode_to_vec());
+    }
+
+    #[tokio::test]
+    async fn test_to_ibc_header_rejects() {
+        // 3 of the 4 validators are replaced at block 300.
+        let chain = SyntheticChain::new(7).with_churn(300, 3);
+        let server = MockTendermintServer::start(chain).await.unwrap();
+        let fetcher = server.fetcher();
+        let bundle = HeaderBundle::fetch(&fetcher, Height(200), Height(400))
+            .await
+            .unwrap();
+        assert_eq!(
+            to_ibc_header(&bundle)
+                .unwrap()
+                .trusted_height
+                .unwrap()
+                .revision_height,
+            200
+        );
+
+        let error = HeaderBundle::fetch(&fetcher, Height(400), Height(400))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("is not after"), "{:#}", error);
+
+        let mut swapped = bundle.clone();
+        swapped.target_validators = bundle.trusted_next_validators.clone();
+        let error = to_ibc_header(&swapped).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the validators of block 400 do not hash to its validators hash"
+        );
+
+        let mut forged = bundle;
+        forged.target.header.data_hash = None;
+        let error = to_ibc_header(&forged).unwrap_err();
+        assert!(
+            error.to_string().contains("but its commit signs"),
+            "{:#}",
+            error
+        );
+    }
+}
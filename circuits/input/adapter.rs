@@ -0,0 +1,341 @@
+//! The parsing of Tendermint RPC responses, per chain.
+//!
+//! Chains running a CometBFT variant can answer the `commit` and `validators` routes with extra
+//! fields and their own encodings of the same values. An `RpcAdapter` owns the step from the bytes
+//! of a response to the tendermint-rs types, reconstructing the canonical header where needed, so
+//! that everything after it is shared. `TendermintAdapter` parses the responses of CometBFT as is
+//! and is the default. `NamadaAdapter` parses those of Namada-style chains:
+//!
+//! - heights, versions, voting powers and page counts are JSON numbers instead of strings,
+//! - hashes and addresses are lowercase hex,
+//! - commit signatures name their block ID flag (`BLOCK_ID_FLAG_COMMIT`) instead of numbering it,
+//! - headers carry fields outside of the hashed header, which are dropped.
+//!
+//! The adapter of a chain is selected by its `ChainSpec`. The fixtures of the Namada adapter, in
+//! `circuits/fixtures/namada`, are the mocha-4 recordings rewritten in the variant's encoding.
+
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use anyhow::{anyhow, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use tendermint::block::signed_header::SignedHeader;
+
+use super::tendermint_utils::{BlockValidatorSet, CommitResponse, ValidatorSetResponse};
+
+/// Parses the responses of a chain's Tendermint RPC.
+pub trait RpcAdapter: Send + Sync {
+    /// The signed header of a `commit` response.
+    fn signed_header(&self, response: &str) -> Result<SignedHeader>;
+
+    /// The page of validators of a `validators` response.
+    fn validators(&self, response: &str) -> Result<BlockValidatorSet>;
+}
+
+/// The adapters, as a chain spec selects them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RpcAdapterKind {
+    #[default]
+    Tendermint,
+    Namada,
+}
+
+impl RpcAdapterKind {
+    pub fn adapter(self) -> Arc<dyn RpcAdapter> {
+        match self {
+            RpcAdapterKind::Tendermint => Arc::new(TendermintAdapter),
+            RpcAdapterKind::Namada => Arc::new(NamadaAdapter),
+        }
+    }
+}
+
+impl fmt::Display for RpcAdapterKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RpcAdapterKind::Tendermint => f.write_str("tendermint"),
+            RpcAdapterKind::Namada => f.write_str("namada"),
+        }
+    }
+}
+
+impl FromStr for RpcAdapterKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "tendermint" => Ok(RpcAdapterKind::Tendermint),
+            "namada" => Ok(RpcAdapterKind::Namada),
+            _ => Err(anyhow!(
+                "unknown RPC adapter {:?}, expected tendermint or namada",
+                s
+            )),
+        }
+    }
+}
+
+/// The responses of CometBFT.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TendermintAdapter;
+
+impl RpcAdapter for TendermintAdapter {
+    fn signed_header(&self, response: &str) -> Result<SignedHeader> {
+        let response: CommitResponse =
+            serde_json::from_str(response).context("invalid commit response")?;
+        Ok(response.result.signed_header)
+    }
+
+    fn validators(&self, response: &str) -> Result<BlockValidatorSet> {
+        let response: ValidatorSetResponse =
+            serde_json::from_str(response).context("invalid validators response")?;
+        Ok(response.result)
+    }
+}
+
+/// The fields of the hashed header, in the order they are hashed.
+const HEADER_FIELDS: [&str; 14] = [
+    "version",
+    "chain_id",
+    "height",
+    "time",
+    "last_block_id",
+    "last_commit_hash",
+    "data_hash",
+    "validators_hash",
+    "next_validators_hash",
+    "consensus_hash",
+    "app_hash",
+    "last_results_hash",
+    "evidence_hash",
+    "proposer_address",
+];
+
+/// The responses of Namada-style chains, rewritten into those of CometBFT before they are parsed.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct NamadaAdapter;
+
+impl RpcAdapter for NamadaAdapter {
+    fn signed_header(&self, response: &str) -> Result<SignedHeader> {
+        let mut response: Value =
+            serde_json::from_str(response).context("invalid commit response")?;
+        let signed_header = response
+            .pointer_mut("/result/signed_header")
+            .and_then(Value::as_object_mut)
+            .ok_or_else(|| anyhow!("the commit response has no signed header"))?;
+        let header = signed_header
+            .get_mut("header")
+            .and_then(Value::as_object_mut)
+            .ok_or_else(|| anyhow!("the signed header has no header"))?;
+        canonical_header(header);
+        let commit = signed_header
+            .get_mut("commit")
+            .and_then(Value::as_object_mut)
+            .ok_or_else(|| anyhow!("the signed header has no commit"))?;
+        canonical_commit(commit)?;
+
+        let response: CommitResponse =
+            serde_json::from_value(response).context("invalid commit response")?;
+        let signed_header = response.result.signed_header;
+        // The dropped fields must not have been hashed: the header is the one the commit signs.
+        let hash = signed_header.header.hash();
+        ensure!(
+            hash == signed_header.commit.block_id.hash,
+            "the reconstructed header of block {} hashes to {}, but its commit signs {}",
+            signed_header.header.height,
+            hash,
+            signed_header.commit.block_id.hash
+        );
+        Ok(signed_header)
+    }
+
+    fn validators(&self, response: &str) -> Result<BlockValidatorSet> {
+        let mut response: Value =
+            serde_json::from_str(response).context("invalid validators response")?;
+        let result = response
+            .get_mut("result")
+            .and_then(Value::as_object_mut)
+            .ok_or_else(|| anyhow!("the validators response has no result"))?;
+        for field in ["block_height", "count", "total"] {
+            stringify(result, field);
+        }
+        if let Some(validators) = result.get_mut("validators").and_then(Value::as_array_mut) {
+            for validator in validators.iter_mut().filter_map(Value::as_object_mut) {
+                uppercase(validator, "address");
+                stringify(validator, "voting_power");
+                stringify(validator, "proposer_priority");
+            }
+        }
+        let response: ValidatorSetResponse =
+            serde_json::from_value(response).context("invalid validators response")?;
+        Ok(response.result)
+    }
+}
+
+/// Rewrite `header` into the hashed header of CometBFT.
+fn canonical_header(header: &mut Map<String, Value>) {
+    header.retain(|field, _| HEADER_FIELDS.contains(&field.as_str()));
+    if let Some(version) = header.get_mut("version").and_then(Value::as_object_mut) {
+        stringify(version, "block");
+        stringify(version, "app");
+    }
+    stringify(header, "height");
+    if let Some(block_id) = header
+        .get_mut("last_block_id")
+        .and_then(Value::as_object_mut)
+    {
+        canonical_block_id(block_id);
+    }
+    for field in &HEADER_FIELDS[5..] {
+        uppercase(header, field);
+    }
+}
+
+fn canonical_commit(commit: &mut Map<String, Value>) -> Result<()> {
+    stringify(commit, "height");
+    if let Some(block_id) = commit.get_mut("block_id").and_then(Value::as_object_mut) {
+        canonical_block_id(block_id);
+    }
+    let signatures = commit.get_mut("signatures").and_then(Value::as_array_mut);
+    for signature in signatures.into_iter().flatten() {
+        let Some(signature) = signature.as_object_mut() else {
+            continue;
+        };
+        uppercase(signature, "validator_address");
+        if let Some(Value::String(flag)) = signature.get("block_id_flag") {
+            let flag = match flag.as_str() {
+                "BLOCK_ID_FLAG_ABSENT" => 1,
+                "BLOCK_ID_FLAG_COMMIT" => 2,
+                "BLOCK_ID_FLAG_NIL" => 3,
+                flag => return Err(anyhow!("unknown block ID flag {}", flag)),
+            };
+            signature.insert("block_id_flag".to_string(), Value::from(flag));
+        }
+    }
+    Ok(())
+}
+
+fn canonical_block_id(block_id: &mut Map<String, Value>) {
+    uppercase(block_id, "hash");
+    if let Some(parts) = block_id.get_mut("parts").and_then(Value::as_object_mut) {
+        uppercase(parts, "hash");
+    }
+}
+
+/// Replace the number at `field` of `object`, if any, with its decimal string.
+fn stringify(object: &mut Map<String, Value>, field: &str) {
+    if let Some(Value::Number(number)) = object.get(field) {
+        let number = number.to_string();
+        object.insert(field.to_string(), Value::String(number));
+    }
+}
+
+/// Uppercase the hex string at `field` of `object`, if any.
+fn uppercase(object: &mut Map<String, Value>, field: &str) {
+    if let Some(Value::String(hex)) = object.get_mut(field) {
+        hex.make_ascii_uppercase();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fixture(chain: &str, block: u64, file: &str) -> String {
+        std::fs::read_to_string(format!("./circuits/fixtures/{}/{}/{}", chain, block, file))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_tendermint_adapter() {
+        let adapter = TendermintAdapter;
+        let signed_header = adapter
+            .signed_header(&fixture("mocha-4", 10000, "commit.json"))
+            .unwrap();
+        assert_eq!(signed_header.header.height.value(), 10000);
+        assert_eq!(
+            signed_header.header.hash().to_string(),
+            "A0123D5E4B8B8888A61F931EE2252D83568B97C223E0ECA9795B29B8BD8CBA2D"
+        );
+        let validators = adapter
+            .validators(&fixture("mocha-4", 10000, "validators_1.json"))
+            .unwrap();
+        assert_eq!(validators.validators.len(), 2);
+        assert_eq!(validators.total, "2");
+
+        // The variant's responses don't parse as CometBFT's.
+        assert!(adapter
+            .signed_header(&fixture("namada", 10000, "commit.json"))
+            .is_err());
+        assert!(adapter
+            .validators(&fixture("namada", 10000, "validators_1.json"))
+            .is_err());
+    }
+
+    #[test]
+    fn test_namada_adapter() {
+        let adapter = NamadaAdapter;
+        for block in [10000, 10001] {
+            // The same header, commit and validators as the CometBFT responses they were
+            // rewritten from.
+            let expected = TendermintAdapter
+                .signed_header(&fixture("mocha-4", block, "commit.json"))
+                .unwrap();
+            let signed_header = adapter
+                .signed_header(&fixture("namada", block, "commit.json"))
+                .unwrap();
+            assert_eq!(signed_header, expected);
+            assert_eq!(
+                signed_header.header.hash(),
+                signed_header.commit.block_id.hash
+            );
+
+            let expected = TendermintAdapter
+                .validators(&fixture("mocha-4", block, "validators_1.json"))
+                .unwrap();
+            let validators = adapter
+                .validators(&fixture("namada", block, "validators_1.json"))
+                .unwrap();
+            assert_eq!(validators.validators, expected.validators);
+            assert_eq!(
+                (validators.count, validators.total),
+                (expected.count, expected.total)
+            );
+        }
+
+        // The CometBFT responses parse too.
+        adapter
+            .signed_header(&fixture("mocha-4", 10000, "commit.json"))
+            .unwrap();
+    }
+
+    #[test]
+    fn test_namada_adapter_checks_the_reconstructed_header() {
+        // A field the variant hashes can't be dropped.
+        let commit = fixture("namada", 10000, "commit.json")
+            .replace("\"chain_id\": \"mocha-4\"", "\"chain_id\": \"namada-1\"");
+        let error = NamadaAdapter.signed_header(&commit).unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("the reconstructed header of block 10000 hashes to"));
+
+        let commit = fixture("namada", 10000, "commit.json")
+            .replace("BLOCK_ID_FLAG_COMMIT", "BLOCK_ID_FLAG_UNKNOWN");
+        let error = NamadaAdapter.signed_header(&commit).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "unknown block ID flag BLOCK_ID_FLAG_UNKNOWN"
+        );
+    }
+
+    #[test]
+    fn test_parse_kind() {
+        assert_eq!(
+            " Namada ".parse::<RpcAdapterKind>().unwrap(),
+            RpcAdapterKind::Namada
+        );
+        assert_eq!(RpcAdapterKind::default().to_string(), "tendermint");
+        assert!("cosmos".parse::<RpcAdapterKind>().is_err());
+    }
+}
@@ -0,0 +1,483 @@
+//! Light blocks read from files, for input generation without a Tendermint RPC.
+//!
+//! A light block is what input generation reads of a block: the responses of the `commit` and
+//! `validators` routes, in the layout of the fetcher's fixtures (`<height>/commit.json` and
+//! `<height>/validators_<page>.json`). `LightBlocks` holds them from a directory in that layout,
+//! as the fetcher's save mode and snapshots write it, or from a bundle: a single JSON object from
+//! those paths to the responses, as `export-input --save-light-blocks` writes it. A fetcher in
+//! `InputDataMode::LightBlocks` reads nothing else, so that with the trusted header hash given
+//! explicitly, the inputs of a request are built without any network access.
+//!
+//! `LightBlocks::check` lists everything a request needs that is missing up front, instead of
+//! input generation failing on the first file it can't find.
+
+use std::collections::BTreeMap;
+use std::io::Read;
+use std::path::Path;
+use std::{fmt, fs};
+
+use anyhow::{anyhow, ensure, Context, Result};
+use serde_json::Value;
+
+use super::adapter::RpcAdapter;
+use super::{header_hash, InputDataFetcher};
+use crate::types::{HeaderHash, Height};
+
+/// The path that reads a bundle from stdin.
+pub const STDIN: &str = "-";
+
+/// What input generation reads of a block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LightBlockPart {
+    /// The signed header, in `commit.json`.
+    Commit,
+    /// Every page of the validator set, from `validators_1.json` on.
+    Validators,
+}
+
+/// A part of the light block at a height.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct LightBlockNeed {
+    pub height: Height,
+    pub part: LightBlockPart,
+}
+
+impl fmt::Display for LightBlockNeed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.part {
+            LightBlockPart::Commit => write!(f, "the commit of block {}", self.height),
+            LightBlockPart::Validators => write!(f, "the validators of block {}", self.height),
+        }
+    }
+}
+
+/// The parts of the light blocks a step or skip from `trusted_block` to `target_block` reads,
+/// for the circuits and the SP1 program alike: the signed headers and validator sets of both
+/// blocks, and the validator sets of the blocks after them.
+pub fn needed_for_range(trusted_block: Height, target_block: Height) -> Vec<LightBlockNeed> {
+    let mut needs = Vec::new();
+    for height in [trusted_block, target_block] {
+        needs.push(LightBlockNeed {
+            height,
+            part: LightBlockPart::Commit,
+        });
+        for height in [height, height.next()] {
+            needs.push(LightBlockNeed {
+                height,
+                part: LightBlockPart::Validators,
+            });
+        }
+    }
+    needs.sort();
+    needs.dedup();
+    needs
+}
+
+/// The parts of the light blocks a data commitment from `trusted_block` to `target_block` reads:
+/// the signed header of every block of the range.
+pub fn needed_for_header_range(trusted_block: Height, target_block: Height) -> Vec<LightBlockNeed> {
+    (trusted_block.value()..=target_block.value())
+        .map(|height| LightBlockNeed {
+            height: Height(height),
+            part: LightBlockPart::Commit,
+        })
+        .collect()
+}
+
+/// The file of the commit of block `height`.
+pub fn commit_file(height: Height) -> String {
+    format!("{}/commit.json", height)
+}
+
+/// The file of page `page` of the validators of block `height`.
+pub fn validators_file(height: Height, page: u64) -> String {
+    format!("{}/validators_{}.json", height, page)
+}
+
+/// Whether `file` is a path of the fixture layout.
+fn is_light_block_file(file: &str) -> bool {
+    let Some((height, name)) = file.split_once('/') else {
+        return false;
+    };
+    let page = name
+        .strip_prefix("validators_")
+        .and_then(|name| name.strip_suffix(".json"));
+    height.parse::<u64>().is_ok()
+        && (name == "commit.json" || page.is_some_and(|page| page.parse::<u64>().is_ok()))
+}
+
+/// The responses of the light blocks of a range, by their path in the fixture layout.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct LightBlocks {
+    files: BTreeMap<String, String>,
+}
+
+impl LightBlocks {
+    /// The light blocks at `path`: a directory in the fixture layout, a bundle, or a bundle read
+    /// from stdin for `-`.
+    pub fn load(path: &Path) -> Result<Self> {
+        if path == Path::new(STDIN) {
+            let mut bundle = String::new();
+            std::io::stdin()
+                .read_to_string(&mut bundle)
+                .context("failed to read the light blocks from stdin")?;
+            return Self::from_bundle(&bundle).context("invalid light blocks on stdin");
+        }
+        if path.is_dir() {
+            return Self::from_dir(path);
+        }
+        let bundle = fs::read_to_string(path)
+            .with_context(|| format!("failed to read the light blocks {}", path.display()))?;
+        Self::from_bundle(&bundle)
+            .with_context(|| format!("invalid light blocks {}", path.display()))
+    }
+
+    /// The light block files of the directory `dir`. Other files are ignored.
+    pub fn from_dir(dir: &Path) -> Result<Self> {
+        let mut files = BTreeMap::new();
+        let entries =
+            fs::read_dir(dir).with_context(|| format!("failed to read {}", dir.display()))?;
+        for entry in entries {
+            let entry = entry?;
+            let height = entry.file_name().to_string_lossy().to_string();
+            if height.parse::<u64>().is_err() || !entry.path().is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(entry.path())? {
+                let path = entry?.path();
+                let name = path.file_name().unwrap_or_default().to_string_lossy();
+                let file = format!("{}/{}", height, name);
+                if !is_light_block_file(&file) {
+                    continue;
+                }
+                let response = fs::read_to_string(&path)
+                    .with_context(|| format!("failed to read {}", path.display()))?;
+                files.insert(file, response);
+            }
+        }
+        Ok(Self { files })
+    }
+
+    /// The light blocks of a bundle.
+    pub fn from_bundle(bundle: &str) -> Result<Self> {
+        let bundle: BTreeMap<String, Value> =
+            serde_json::from_str(bundle).context("a bundle is a JSON object of responses")?;
+        let mut files = BTreeMap::new();
+        for (file, response) in bundle {
+            ensure!(
+                is_light_block_file(&file),
+                "{} is not the path of a commit or validators response",
+                file
+            );
+            files.insert(file, response.to_string());
+        }
+        Ok(Self { files })
+    }
+
+    /// The light blocks as a bundle.
+    pub fn to_bundle(&self) -> Result<String> {
+        let mut bundle = BTreeMap::new();
+        for (file, response) in &self.files {
+            let response: Value = serde_json::from_str(response)
+                .with_context(|| format!("the response {} is not JSON", file))?;
+            bundle.insert(file, response);
+        }
+        Ok(serde_json::to_string_pretty(&bundle)?)
+    }
+
+    /// Write the light blocks to `path` as a bundle.
+    pub fn write_bundle(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_bundle()?)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// The response at the path `file` of the fixture layout.
+    pub fn get(&self, file: &str) -> Option<&str> {
+        self.files.get(file).map(String::as_str)
+    }
+
+    pub fn len(&self) -> usize {
+        self.files.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    /// Read `needs` through `fetcher`, typically from its RPC, for `load` to read back elsewhere.
+    pub async fn fetch(fetcher: &InputDataFetcher, needs: &[LightBlockNeed]) -> Result<Self> {
+        let mut files = BTreeMap::new();
+        for need in needs {
+            match need.part {
+                LightBlockPart::Commit => {
+                    let response = fetcher.commit_response(need.height).await?;
+                    files.insert(commit_file(need.height), response);
+                }
+                LightBlockPart::Validators => {
+                    let mut page = 1;
+                    let mut fetched = 0;
+                    loop {
+                        let response = fetcher.validators_response(need.height, page).await?;
+                        let (count, total) = page_counts(fetcher.adapter.as_ref(), &response)
+                            .with_context(|| {
+                                format!("invalid validators of block {}", need.height)
+                            })?;
+                        files.insert(validators_file(need.height, page), response);
+                        fetched += count;
+                        if fetched >= total {
+                            break;
+                        }
+                        page += 1;
+                    }
+                }
+            }
+        }
+        Ok(Self { files })
+    }
+
+    /// Check that the light blocks hold all of `needs`, and that the header of `trusted_block`
+    /// hashes to `trusted_header_hash`, parsing the responses with `adapter`. A missing part fails
+    /// with all of those missing, and the files that would hold them.
+    pub fn check(
+        &self,
+        adapter: &dyn RpcAdapter,
+        needs: &[LightBlockNeed],
+        trusted_block: Height,
+        trusted_header_hash: HeaderHash,
+    ) -> Result<()> {
+        let mut missing = Vec::new();
+        for need in needs {
+            match need.part {
+                LightBlockPart::Commit => {
+                    let file = commit_file(need.height);
+                    if self.get(&file).is_none() {
+                        missing.push(format!("{} ({})", need, file));
+                    }
+                }
+                LightBlockPart::Validators => {
+                    let files = self.missing_validator_pages(adapter, need.height)?;
+                    if !files.is_empty() {
+                        missing.push(format!("{} ({})", need, files.join(", ")));
+                    }
+                }
+            }
+        }
+        ensure!(
+            missing.is_empty(),
+            "the light blocks lack {}",
+            missing.join("; ")
+        );
+
+        if let Some(response) = self.get(&commit_file(trusted_block)) {
+            let signed_header = adapter
+                .signed_header(response)
+                .with_context(|| format!("invalid commit of block {}", trusted_block))?;
+            let hash = header_hash(&signed_header.header);
+            ensure!(
+                hash == trusted_header_hash,
+                "the light block of the trusted block {} hashes to {}, not {}",
+                trusted_block,
+                hash,
+                trusted_header_hash
+            );
+        }
+        Ok(())
+    }
+
+    /// The files of the pages of the validators of block `height` that are missing. The number of
+    /// pages is read from the first.
+    fn missing_validator_pages(
+        &self,
+        adapter: &dyn RpcAdapter,
+        height: Height,
+    ) -> Result<Vec<String>> {
+        let mut missing = Vec::new();
+        let first = validators_file(height, 1);
+        let Some(response) = self.get(&first) else {
+            return Ok(vec![first]);
+        };
+        let (count, total) = page_counts(adapter, response)
+            .with_context(|| format!("invalid validators of block {}", height))?;
+        ensure!(
+            count > 0 || total == 0,
+            "the first page of the validators of block {} is empty",
+            height
+        );
+        let pages = if count == 0 { 1 } else { total.div_ceil(count) };
+        for page in 2..=pages {
+            let file = validators_file(height, page);
+            if self.get(&file).is_none() {
+                missing.push(file);
+            }
+        }
+        Ok(missing)
+    }
+}
+
+/// The validators of a page of a `validators` response, and those of the whole set.
+fn page_counts(adapter: &dyn RpcAdapter, response: &str) -> Result<(u64, u64)> {
+    let validators = adapter.validators(response)?;
+    let count = validators
+        .count
+        .parse()
+        .map_err(|_| anyhow!("invalid count {}", validators.count))?;
+    let total = validators
+        .total
+        .parse()
+        .map_err(|_| anyhow!("invalid total {}", validators.total))?;
+    Ok((count, total))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use plonky2x::prelude::GoldilocksField;
+
+    use super::*;
+    use crate::consts::VALIDATOR_SET_SIZE_MAX;
+    use crate::input::adapter::TendermintAdapter;
+    use crate::testing::{MockTendermintServer, SyntheticChain};
+
+    #[test]
+    fn test_needed() {
+        let needs = needed_for_range(Height(100), Height(101));
+        let needs: Vec<_> = needs.iter().map(|need| need.to_string()).collect();
+        assert_eq!(
+            needs,
+            [
+                "the commit of block 100",
+                "the validators of block 100",
+                "the commit of block 101",
+                "the validators of block 101",
+                "the validators of block 102",
+            ]
+        );
+        assert_eq!(needed_for_range(Height(100), Height(500)).len(), 6);
+        assert_eq!(needed_for_header_range(Height(100), Height(104)).len(), 5);
+    }
+
+    #[tokio::test]
+    async fn test_round_trip() {
+        let server = MockTendermintServer::start(SyntheticChain::new(7).with_validators(150))
+            .await
+            .unwrap();
+        let mut online = server.fetcher();
+        let trusted_hash = online.compute_header_hash(Height(100)).await.unwrap();
+        let needs = needed_for_range(Height(100), Height(400));
+        let light_blocks = LightBlocks::fetch(&online, &needs).await.unwrap();
+        // Both blocks and the blocks after them, their validators in two pages.
+        assert_eq!(light_blocks.len(), 2 + 4 * 2);
+        let target = online
+            .get_signed_header_from_number(Height(400))
+            .await
+            .unwrap();
+        let validators = online
+            .get_validator_set_from_number(Height(401))
+            .await
+            .unwrap();
+        let skip = online
+            .get_skip_inputs::<VALIDATOR_SET_SIZE_MAX, GoldilocksField>(
+                Height(100),
+                trusted_hash,
+                Height(400),
+            )
+            .await
+            .unwrap();
+        drop(server);
+
+        // Through a bundle or a directory, once the chain is no longer reachable.
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = dir.path().join("light_blocks.json");
+        light_blocks.write_bundle(&bundle).unwrap();
+        let from_bundle = LightBlocks::load(&bundle).unwrap();
+        let layout = dir.path().join("fixtures");
+        for file in light_blocks.files.keys() {
+            let path = layout.join(file);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, from_bundle.get(file).unwrap()).unwrap();
+        }
+        fs::write(layout.join("README"), "ignored").unwrap();
+        let from_dir = LightBlocks::load(&layout).unwrap();
+        assert_eq!(from_dir, from_bundle);
+
+        let adapter = TendermintAdapter;
+        from_bundle
+            .check(&adapter, &needs, Height(100), trusted_hash)
+            .unwrap();
+        let from_bundle = Arc::new(from_bundle);
+        let mut offline = InputDataFetcher::with_light_blocks(from_bundle, "light_blocks.json");
+        assert_eq!(
+            offline
+                .get_signed_header_from_number(Height(400))
+                .await
+                .unwrap(),
+            target
+        );
+        assert_eq!(
+            offline
+                .get_validator_set_from_number(Height(401))
+                .await
+                .unwrap(),
+            validators
+        );
+        let offline_skip = offline
+            .get_skip_inputs::<VALIDATOR_SET_SIZE_MAX, GoldilocksField>(
+                Height(100),
+                trusted_hash,
+                Height(400),
+            )
+            .await
+            .unwrap();
+        assert_eq!(offline_skip.trusted_header, skip.trusted_header);
+        assert_eq!(offline_skip.target_header, skip.target_header);
+        assert_eq!(offline_skip.round, skip.round);
+        assert_eq!(offline_skip.nb_target_validators, 150);
+    }
+
+    #[tokio::test]
+    async fn test_check_lists_missing() {
+        let server = MockTendermintServer::start(SyntheticChain::new(7).with_validators(150))
+            .await
+            .unwrap();
+        let fetcher = server.fetcher();
+        let trusted_hash = fetcher.compute_header_hash(Height(100)).await.unwrap();
+        let needs = needed_for_range(Height(100), Height(400));
+        let mut light_blocks = LightBlocks::fetch(&fetcher, &needs).await.unwrap();
+        light_blocks.files.remove("400/commit.json");
+        light_blocks.files.remove("401/validators_2.json");
+        light_blocks.files.remove("101/validators_1.json");
+
+        let adapter = TendermintAdapter;
+        let error = light_blocks
+            .check(&adapter, &needs, Height(100), trusted_hash)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the light blocks lack the validators of block 101 (101/validators_1.json); the \
+             commit of block 400 (400/commit.json); the validators of block 401 \
+             (401/validators_2.json)"
+        );
+
+        // Complete, but for another trusted header.
+        let light_blocks = LightBlocks::fetch(&fetcher, &needs).await.unwrap();
+        let error = light_blocks
+            .check(&adapter, &needs, Height(100), HeaderHash([0xab; 32]))
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("the light block of the trusted block 100 hashes to"));
+    }
+
+    #[test]
+    fn test_bundle_rejects_other_files() {
+        assert!(LightBlocks::from_bundle("{\"100/commit.json\": {}}").is_ok());
+        let error = LightBlocks::from_bundle("{\"100/block.json\": {}}").unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "100/block.json is not the path of a commit or validators response"
+        );
+        assert!(LightBlocks::from_bundle("[]").is_err());
+        assert!(LightBlocks::load(Path::new("./missing.json")).is_err());
+    }
+}
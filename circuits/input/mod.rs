@@ -1,45 +1,58 @@
+pub mod adapter;
 pub mod conversion;
+pub mod light_blocks;
+pub mod power;
 pub mod tendermint_utils;
 pub mod utils;
 
 use std::collections::HashMap;
 use std::path::Path;
+use std::sync::Arc;
 use std::{env, fs};
 
-use ethers::types::H256;
+use anyhow::{anyhow, ensure, Context, Result};
+use ethers_core::types::H256;
 use log::{debug, info};
 use plonky2x::frontend::merkle::tree::InclusionProof;
 use plonky2x::prelude::RichField;
 use tendermint::block::signed_header::SignedHeader;
-use tendermint::validator::{Info, Set as TendermintValidatorSet};
+use tendermint::validator::Info;
 use tendermint_proto::types::BlockId as RawBlockId;
 use tendermint_proto::Protobuf;
+use tracing::{field, instrument, Span};
 
-use self::tendermint_utils::{
-    generate_proofs_from_header, is_valid_skip, CommitResponse, Hash, Header, Proof,
-    ValidatorSetResponse,
-};
+use self::adapter::{RpcAdapter, TendermintAdapter};
+use self::light_blocks::LightBlocks;
+use self::power::check_voting_powers;
+use self::tendermint_utils::{generate_proofs_from_header, BlockValidatorSet, Hash, Header, Proof};
 use self::utils::convert_to_h256;
 use crate::consts::{
-    BLOCK_HEIGHT_INDEX, CHAIN_ID_INDEX, HEADER_PROOF_DEPTH, LAST_BLOCK_ID_INDEX,
+    BLOCK_HEIGHT_INDEX, CHAIN_ID_INDEX, DATA_HASH_INDEX, HEADER_PROOF_DEPTH, LAST_BLOCK_ID_INDEX,
     NEXT_VALIDATORS_HASH_INDEX, PROTOBUF_BLOCK_ID_SIZE_BYTES, PROTOBUF_CHAIN_ID_SIZE_BYTES,
     PROTOBUF_HASH_SIZE_BYTES, VALIDATORS_HASH_INDEX,
 };
+use crate::endpoint::EndpointPool;
 use crate::input::conversion::{get_validator_data_from_block, validator_hash_field_from_block};
+use crate::types::{HeaderHash, Height};
 use crate::variables::*;
+use crate::{reporting, selector};
 
 #[derive(Debug, PartialEq)]
 pub enum InputDataMode {
     Rpc,
     Fixture,
+    /// Read the responses from light blocks loaded up front, and never query the RPC.
+    LightBlocks(Arc<LightBlocks>),
 }
 
 pub struct InputDataFetcher {
     pub mode: InputDataMode,
-    pub urls: Vec<String>,
+    pub endpoints: Arc<EndpointPool>,
     pub fixture_path: String,
     pub proof_cache: HashMap<Hash, Vec<Proof>>,
     pub save: bool,
+    /// Parses the responses of the chain's RPC.
+    pub adapter: Arc<dyn RpcAdapter>,
 }
 
 pub struct StepInputs<F: RichField> {
@@ -73,6 +86,21 @@ pub struct SkipInputs<F: RichField> {
     pub nb_trusted_validators: usize,                                     // nb_trusted_validators
 }
 
+/// The inputs of a data commitment over the headers from a trusted block to a target block.
+pub struct DataCommitmentInputs<F: RichField> {
+    pub trusted_header: [u8; 32],
+    pub target_header: [u8; 32],
+    /// The data hashes of the blocks from the trusted block up to, but excluding, the target block:
+    /// the leaves of the commitment.
+    pub data_hashes: Vec<[u8; 32]>,
+    /// The inclusion of each of `data_hashes` in the header of its block.
+    pub data_hash_proofs: Vec<InclusionProof<HEADER_PROOF_DEPTH, PROTOBUF_HASH_SIZE_BYTES, F>>,
+    /// The inclusion of the ID of the previous block in each header after the trusted block, up to
+    /// the target block, chaining them to the trusted header.
+    pub last_block_id_proofs:
+        Vec<InclusionProof<HEADER_PROOF_DEPTH, PROTOBUF_BLOCK_ID_SIZE_BYTES, F>>,
+}
+
 impl Default for InputDataFetcher {
     fn default() -> Self {
         dotenv::dotenv().ok();
@@ -93,6 +121,12 @@ impl Default for InputDataFetcher {
 
 const MAX_NUM_RETRIES: usize = 3;
 
+/// The hash of `header`, as the circuits compute it: the merkle root of its protobuf encoded
+/// fields. Input generation and `compute_header_hash` both hash through here.
+pub fn header_hash(header: &Header) -> HeaderHash {
+    HeaderHash::try_from(header.hash()).expect("header hashes are SHA-256")
+}
+
 impl InputDataFetcher {
     pub fn new(urls: Vec<String>, fixture_path: &str) -> Self {
         #[allow(unused_mut)]
@@ -108,10 +142,26 @@ impl InputDataFetcher {
 
         Self {
             mode,
-            urls,
+            endpoints: Arc::new(EndpointPool::new("tendermint", urls)),
             fixture_path: fixture_path.to_string(),
             proof_cache: HashMap::new(),
             save: false,
+            adapter: Arc::new(TendermintAdapter),
+        }
+    }
+
+    /// A fetcher reading only `light_blocks`, loaded from `source`.
+    pub fn with_light_blocks(light_blocks: Arc<LightBlocks>, source: &str) -> Self {
+        let mut fetcher = Self::new(vec![format!("file://{}", source)], source);
+        fetcher.mode = InputDataMode::LightBlocks(light_blocks);
+        fetcher
+    }
+
+    /// The light blocks the fetcher reads instead of the RPC, if any.
+    pub fn light_blocks(&self) -> Option<&LightBlocks> {
+        match &self.mode {
+            InputDataMode::LightBlocks(light_blocks) => Some(light_blocks),
+            _ => None,
         }
     }
 
@@ -119,116 +169,242 @@ impl InputDataFetcher {
         self.save = save;
     }
 
-    // Request data from the Tendermint RPC with quadratic backoff & multiple RPC's.
-    pub async fn request_from_rpc(&self, route: &str, retries: usize) -> String {
-        for _ in 0..self.urls.len() {
-            let url = format!("{}/{}", self.urls[0], route);
-            info!("Querying url {:?}", url.clone());
-            let mut res = reqwest::get(url.clone()).await;
-            let mut num_retries = 0;
-            while res.is_err() && num_retries < retries {
-                info!("Querying url {:?}", url.clone());
-                res = reqwest::get(url.clone()).await;
-                // Quadratic backoff for requests.
-                tokio::time::sleep(std::time::Duration::from_secs(2u64.pow(num_retries as u32)))
-                    .await;
-                num_retries += 1;
-            }
+    pub fn set_adapter(&mut self, adapter: Arc<dyn RpcAdapter>) {
+        self.adapter = adapter;
+    }
 
-            if res.is_ok() {
-                return res.unwrap().text().await.unwrap();
+    /// Request data from the Tendermint RPC, failing over across the RPC's, with quadratic backoff
+    /// between rounds. Returns the failure once the retries are exhausted.
+    #[instrument(skip(self, retries), fields(response_bytes = field::Empty))]
+    pub async fn try_request_from_rpc(&self, route: &str, retries: usize) -> Result<String> {
+        let mut num_retries = 0;
+        loop {
+            let res = self
+                .endpoints
+                .call(
+                    |_, url| {
+                        let url = format!("{}/{}", url, route);
+                        async move {
+                            info!("Querying url {:?}", url);
+                            reqwest::get(url).await?.error_for_status()?.text().await
+                        }
+                    },
+                    |_| true,
+                )
+                .await;
+            match res {
+                Ok(text) => {
+                    Span::current().record("response_bytes", text.len());
+                    return Ok(text);
+                }
+                Err(_) if num_retries < retries => {
+                    reporting::breadcrumb("rpc", format!("failed to query {}, retrying", route));
+                    // Quadratic backoff for requests.
+                    tokio::time::sleep(std::time::Duration::from_secs(
+                        2u64.pow(num_retries as u32),
+                    ))
+                    .await;
+                    num_retries += 1;
+                }
+                Err(e) => return Err(e).with_context(|| format!("failed to query {}", route)),
             }
         }
-        panic!("Failed to fetch data from Tendermint RPC endpoint");
     }
 
-    // Get the latest signed header from the RPC endpoint.
-    // Note: Only used in script.
-    pub async fn get_latest_signed_header(&mut self) -> SignedHeader {
-        if self.mode == InputDataMode::Rpc {
-            let route = "commit";
-            let res = self.request_from_rpc(route, MAX_NUM_RETRIES).await;
-            let v: CommitResponse = serde_json::from_str(&res).expect("Failed to parse JSON");
-            v.result.signed_header
-        } else {
-            panic!("get_latest_signed_header is only supported in RPC mode")
-        }
+    /// The value of the ABCI query of `path` at the latest block, failing over across the RPC's.
+    /// Unlike the other requests, a failure is returned rather than retried.
+    pub async fn abci_query(&self, path: &str) -> Result<Vec<u8>> {
+        let route = format!("abci_query?path=\"{}\"", path);
+        let response = self
+            .endpoints
+            .call(
+                |_, url| {
+                    let url = format!("{}/{}", url, route);
+                    async move { reqwest::get(url).await?.error_for_status()?.text().await }
+                },
+                |_| true,
+            )
+            .await
+            .with_context(|| format!("failed to query {}", path))?;
+        let response: serde_json::Value =
+            serde_json::from_str(&response).context("invalid abci_query response")?;
+        let response = &response["result"]["response"];
+        let code = response["code"].as_u64().unwrap_or_default();
+        ensure!(
+            code == 0,
+            "the ABCI query {} failed with code {}: {}",
+            path,
+            code,
+            response["log"].as_str().unwrap_or_default()
+        );
+        let value = response["value"].as_str().unwrap_or_default();
+        subtle_encoding::base64::decode(value)
+            .map_err(|e| anyhow!("invalid value of the ABCI query {}: {}", path, e))
+    }
+
+    /// The latest signed header of the RPC endpoint, or the failure to fetch or parse it.
+    #[instrument(skip_all, fields(height = field::Empty))]
+    pub async fn latest_signed_header(&self) -> Result<SignedHeader> {
+        ensure!(
+            self.mode == InputDataMode::Rpc,
+            "latest_signed_header is only supported in RPC mode"
+        );
+        let route = "commit";
+        let res = self.try_request_from_rpc(route, MAX_NUM_RETRIES).await?;
+        let signed_header = self
+            .adapter
+            .signed_header(&res)
+            .context("failed to parse the latest commit")?;
+        Span::current().record("height", signed_header.header.height.value());
+        Ok(signed_header)
     }
 
     // Search to find the highest block number to call request_combined_skip on. If the search
     // returns start_block + 1, then we call request_combined_step instead.
-    pub async fn find_block_to_request(&mut self, start_block: u64, max_end_block: u64) -> u64 {
-        let mut curr_end_block = max_end_block;
-        loop {
-            if curr_end_block - start_block == 1 {
-                return curr_end_block;
-            }
-
-            let start_block_validators = self.get_validator_set_from_number(start_block).await;
-            let start_validator_set = TendermintValidatorSet::new(start_block_validators, None);
-
-            let target_block_validators = self.get_validator_set_from_number(curr_end_block).await;
-            let target_validator_set = TendermintValidatorSet::new(target_block_validators, None);
-
-            let target_block_commit = self.get_signed_header_from_number(curr_end_block).await;
-
-            if is_valid_skip(
-                start_validator_set,
-                target_validator_set,
-                target_block_commit.commit,
-            ) {
-                return curr_end_block;
-            }
-
-            let mid_block = (curr_end_block + start_block) / 2;
-            curr_end_block = mid_block;
-        }
+    #[instrument(
+        skip_all,
+        fields(
+            start_block = start_block.value(),
+            max_end_block = max_end_block.value(),
+            target_block = field::Empty
+        )
+    )]
+    pub async fn find_block_to_request(
+        &self,
+        start_block: Height,
+        max_end_block: Height,
+    ) -> Result<Height> {
+        let target_block =
+            selector::largest_skip(self, start_block.value(), max_end_block.value()).await?;
+        Span::current().record("target_block", target_block);
+        Ok(Height(target_block))
     }
 
-    pub async fn get_signed_header_from_number(&self, block_number: u64) -> SignedHeader {
-        let file_name = format!(
-            "{}/{}/commit.json",
-            self.fixture_path,
-            block_number.to_string().as_str()
-        );
-        let query_route = format!("commit?height={}", block_number.to_string().as_str());
-
-        let fetched_result = match &self.mode {
+    /// The response to the RPC request `query_route`, or the file `file` of the fixture layout
+    /// that holds it.
+    async fn fetch_response(&self, query_route: &str, file: &str) -> Result<String> {
+        let file_name = format!("{}/{}", self.fixture_path, file);
+        match &self.mode {
             InputDataMode::Rpc => {
-                let res = self.request_from_rpc(&query_route, MAX_NUM_RETRIES).await;
+                let res = self
+                    .try_request_from_rpc(query_route, MAX_NUM_RETRIES)
+                    .await?;
                 if self.save {
                     // Ensure the directory exists
                     if let Some(parent) = Path::new(&file_name).parent() {
-                        fs::create_dir_all(parent).unwrap();
+                        fs::create_dir_all(parent)
+                            .with_context(|| format!("could not create {}", parent.display()))?;
                     }
-                    fs::write(file_name.as_str(), res.as_bytes()).expect("Unable to write file");
+                    fs::write(file_name.as_str(), res.as_bytes())
+                        .with_context(|| format!("could not write {}", file_name))?;
                 }
-                res
+                Ok(res)
             }
             InputDataMode::Fixture => {
-                let file_content = fs::read_to_string(file_name.as_str());
                 info!("File name: {}", file_name.as_str());
-                file_content.unwrap()
+                fs::read_to_string(file_name.as_str())
+                    .with_context(|| format!("could not read the fixture {}", file_name))
             }
-        };
-        let v: CommitResponse =
-            serde_json::from_str(&fetched_result).expect("Failed to parse JSON");
-        v.result.signed_header
+            // `LightBlocks::check` finds what is missing before input generation starts.
+            InputDataMode::LightBlocks(light_blocks) => match light_blocks.get(file) {
+                Some(response) => Ok(response.to_string()),
+                None => Err(anyhow!(
+                    "{} is missing from the light blocks {}",
+                    file,
+                    self.fixture_path
+                )),
+            },
+        }
+    }
+
+    /// The raw `commit` response of block `block_number`.
+    pub async fn commit_response(&self, block_number: Height) -> Result<String> {
+        let query_route = format!("commit?height={}", block_number);
+        self.fetch_response(&query_route, &light_blocks::commit_file(block_number))
+            .await
+    }
+
+    /// The raw `validators` response of page `page_number` of the validators of block
+    /// `block_number`.
+    pub async fn validators_response(
+        &self,
+        block_number: Height,
+        page_number: u64,
+    ) -> Result<String> {
+        let query_route = format!(
+            "validators?height={}&per_page=100&page={}",
+            block_number, page_number
+        );
+        let file = light_blocks::validators_file(block_number, page_number);
+        self.fetch_response(&query_route, &file).await
+    }
+
+    #[instrument(skip_all, fields(height = block_number.value()))]
+    pub async fn get_signed_header_from_number(
+        &self,
+        block_number: Height,
+    ) -> Result<SignedHeader> {
+        let fetched_result = self.commit_response(block_number).await?;
+        self.adapter
+            .signed_header(&fetched_result)
+            .with_context(|| format!("failed to parse the commit of block {}", block_number))
+    }
+
+    /// The canonical hash of the header at `height`, checked against the block ID its commit
+    /// signs.
+    #[instrument(skip_all, fields(height = height.value()))]
+    pub async fn compute_header_hash(&self, height: Height) -> Result<HeaderHash> {
+        let signed_header = self.get_signed_header_from_number(height).await?;
+        let fetched = Height::from(signed_header.header.height);
+        ensure!(
+            fetched == height,
+            "fetched the header of block {} for block {}",
+            fetched,
+            height
+        );
+        let hash = header_hash(&signed_header.header);
+        let committed = HeaderHash::try_from(signed_header.commit.block_id.hash)?;
+        ensure!(
+            hash == committed,
+            "the header of block {} hashes to {}, but its commit signs {}",
+            height,
+            hash,
+            committed
+        );
+        Ok(hash)
     }
 
-    pub async fn get_validator_set_from_number(&mut self, block_number: u64) -> Vec<Info> {
+    #[instrument(skip_all, fields(height = block_number.value(), validators = field::Empty))]
+    pub async fn get_validator_set_from_number(&self, block_number: Height) -> Result<Vec<Info>> {
         let mut validators = Vec::new();
 
         let mut page_number = 1;
         let mut num_so_far = 0;
         loop {
-            let fetched_result = self.fetch_validator_result(block_number, page_number).await;
-
-            validators.extend(fetched_result.result.validators);
-            // Parse count to u32.
-            let parsed_count: u32 = fetched_result.result.count.parse().unwrap();
-            // Parse total to u32.
-            let parsed_total: u32 = fetched_result.result.total.parse().unwrap();
+            let fetched_result = self
+                .fetch_validator_result(block_number, page_number)
+                .await?;
+
+            validators.extend(fetched_result.validators);
+            let count = |value: &str| -> Result<u32> {
+                value.parse().with_context(|| {
+                    format!(
+                        "invalid count {:?} in the validators of block {}",
+                        value, block_number
+                    )
+                })
+            };
+            let parsed_count = count(&fetched_result.count)?;
+            let parsed_total = count(&fetched_result.total)?;
+            // An empty page would never reach the total.
+            ensure!(
+                parsed_count > 0 || num_so_far >= parsed_total,
+                "page {} of the validators of block {} is empty, with {} of {} listed",
+                page_number,
+                block_number,
+                num_so_far,
+                parsed_total
+            );
 
             num_so_far += parsed_count;
             if num_so_far >= parsed_total {
@@ -237,48 +413,19 @@ impl InputDataFetcher {
             page_number += 1;
         }
 
-        validators
+        Span::current().record("validators", validators.len());
+        Ok(validators)
     }
 
     async fn fetch_validator_result(
-        &mut self,
-        block_number: u64,
+        &self,
+        block_number: Height,
         page_number: u64,
-    ) -> ValidatorSetResponse {
-        // Check size of validator set.
-        let file_name = format!(
-            "{}/{}/validators_{}.json",
-            self.fixture_path,
-            block_number.to_string().as_str(),
-            page_number
-        );
-        let query_route = format!(
-            "validators?height={}&per_page=100&page={}",
-            block_number.to_string().as_str(),
-            page_number.to_string().as_str()
-        );
-
-        let fetched_result = match &self.mode {
-            InputDataMode::Rpc => {
-                let res = self.request_from_rpc(&query_route, MAX_NUM_RETRIES).await;
-                if self.save {
-                    // Ensure the directory exists
-                    if let Some(parent) = Path::new(&file_name).parent() {
-                        fs::create_dir_all(parent).unwrap();
-                    }
-                    fs::write(file_name.as_str(), res.as_bytes()).expect("Unable to write file");
-                }
-                res
-            }
-            InputDataMode::Fixture => {
-                let file_content = fs::read_to_string(file_name.as_str());
-                info!("File name: {}", file_name.as_str());
-                file_content.unwrap()
-            }
-        };
-        let v: ValidatorSetResponse =
-            serde_json::from_str(&fetched_result).expect("Failed to parse JSON");
-        v
+    ) -> Result<BlockValidatorSet> {
+        let fetched_result = self.validators_response(block_number, page_number).await?;
+        self.adapter
+            .validators(&fetched_result)
+            .with_context(|| format!("failed to parse the validators of block {}", block_number))
     }
 
     pub fn get_merkle_proof(
@@ -315,31 +462,37 @@ impl InputDataFetcher {
 
     pub async fn get_step_inputs<const VALIDATOR_SET_SIZE_MAX: usize, F: RichField>(
         &mut self,
-        prev_block_number: u64,
-        prev_header_hash: H256,
-    ) -> StepInputs<F> {
+        prev_block_number: Height,
+        prev_header_hash: HeaderHash,
+    ) -> Result<StepInputs<F>> {
         debug!("Getting step inputs");
-        let prev_block_signed_header = self.get_signed_header_from_number(prev_block_number).await;
+        let prev_block_signed_header = self
+            .get_signed_header_from_number(prev_block_number)
+            .await?;
         let prev_header = prev_block_signed_header.header;
-        assert_eq!(
-            prev_header.hash().as_bytes(),
-            prev_header_hash.as_bytes(),
-            "Prev header hash 
-        doesn't pass sanity check"
+        ensure!(
+            header_hash(&prev_header) == prev_header_hash,
+            "the header of block {} hashes to {}, not the trusted {}",
+            prev_block_number,
+            header_hash(&prev_header),
+            prev_header_hash
         );
 
         let next_block_signed_header = self
-            .get_signed_header_from_number(prev_block_number + 1)
-            .await;
+            .get_signed_header_from_number(prev_block_number.next())
+            .await?;
         let next_block_validators = self
-            .get_validator_set_from_number(prev_block_number + 1)
-            .await;
+            .get_validator_set_from_number(prev_block_number.next())
+            .await?;
         let nb_validators = next_block_validators.len();
-        assert!(
+        ensure!(
             nb_validators <= VALIDATOR_SET_SIZE_MAX,
-            "The validator set size of the next block is larger than the
-            VALIDATOR_SET_SIZE_MAX."
+            "the {} validators of the next block are more than the {} supported",
+            nb_validators,
+            VALIDATOR_SET_SIZE_MAX
         );
+        check_voting_powers(&next_block_validators)
+            .context("the next block's validators can't be proved")?;
 
         // Note: Extends the validator set with the absent validators.
         let next_block_validators = get_validator_data_from_block::<VALIDATOR_SET_SIZE_MAX, F>(
@@ -384,17 +537,14 @@ impl InputDataFetcher {
             next_block_signed_header.header.validators_hash.encode_vec(),
         );
 
-        let last_block_id_hash = next_block_signed_header.header.last_block_id.unwrap().hash;
-        let encoded_last_block_id = Protobuf::<RawBlockId>::encode_vec(
-            next_block_signed_header
-                .header
-                .last_block_id
-                .unwrap_or_default(),
-        );
-        assert_eq!(
-            last_block_id_hash.as_bytes(),
-            &encoded_last_block_id[2..34],
-            "prev header hash doesn't pass sanity check"
+        let last_block_id = next_block_signed_header
+            .header
+            .last_block_id
+            .ok_or_else(|| anyhow!("the next block has no last block ID"))?;
+        let encoded_last_block_id = Protobuf::<RawBlockId>::encode_vec(last_block_id);
+        ensure!(
+            encoded_last_block_id.get(2..34) == Some(last_block_id.hash.as_bytes()),
+            "the last block ID of the next block doesn't encode its hash"
         );
         let next_block_last_block_id_proof = self.get_inclusion_proof(
             &next_block_signed_header.header,
@@ -408,9 +558,9 @@ impl InputDataFetcher {
             prev_header.next_validators_hash.encode_vec(),
         );
         let round = next_block_signed_header.commit.round.value() as usize;
-        let next_block_header = next_block_signed_header.header.hash();
-        StepInputs {
-            next_header: next_block_header.as_bytes().try_into().unwrap(),
+        let next_block_header = header_hash(&next_block_signed_header.header);
+        Ok(StepInputs {
+            next_header: next_block_header.to_bytes(),
             round,
             next_block_validators,
             nb_validators,
@@ -419,44 +569,123 @@ impl InputDataFetcher {
             next_block_validators_hash_proof,
             next_block_last_block_id_proof,
             prev_block_next_validators_hash_proof,
+        })
+    }
+
+    pub async fn get_data_commitment_inputs<F: RichField>(
+        &mut self,
+        trusted_block_number: Height,
+        trusted_block_hash: HeaderHash,
+        target_block_number: Height,
+    ) -> Result<DataCommitmentInputs<F>> {
+        ensure!(
+            trusted_block_number < target_block_number,
+            "no headers to commit to from {} to {}",
+            trusted_block_number,
+            target_block_number
+        );
+        let mut headers = Vec::new();
+        let mut height = trusted_block_number;
+        while height <= target_block_number {
+            headers.push(self.get_signed_header_from_number(height).await?.header);
+            height = height.next();
+        }
+        ensure!(
+            header_hash(&headers[0]) == trusted_block_hash,
+            "the header of block {} hashes to {}, not the trusted {}",
+            trusted_block_number,
+            header_hash(&headers[0]),
+            trusted_block_hash
+        );
+
+        let mut data_hashes = Vec::new();
+        let mut data_hash_proofs = Vec::new();
+        for header in &headers[..headers.len() - 1] {
+            let data_hash = header.data_hash.unwrap_or_default();
+            data_hashes.push(
+                data_hash
+                    .as_bytes()
+                    .try_into()
+                    .expect("data hashes are SHA-256"),
+            );
+            data_hash_proofs.push(self.get_inclusion_proof(
+                header,
+                DATA_HASH_INDEX as u64,
+                data_hash.encode_vec(),
+            ));
         }
+
+        let mut last_block_id_proofs = Vec::new();
+        for (prev_header, header) in headers.iter().zip(&headers[1..]) {
+            let last_block_id = header.last_block_id.unwrap_or_default();
+            ensure!(
+                HeaderHash::try_from(last_block_id.hash).ok() == Some(header_hash(prev_header)),
+                "the header of block {} doesn't follow the header of block {}",
+                header.height,
+                prev_header.height
+            );
+            last_block_id_proofs.push(self.get_inclusion_proof(
+                header,
+                LAST_BLOCK_ID_INDEX as u64,
+                Protobuf::<RawBlockId>::encode_vec(last_block_id),
+            ));
+        }
+
+        Ok(DataCommitmentInputs {
+            trusted_header: trusted_block_hash.to_bytes(),
+            target_header: header_hash(&headers[headers.len() - 1]).to_bytes(),
+            data_hashes,
+            data_hash_proofs,
+            last_block_id_proofs,
+        })
     }
 
     pub async fn get_skip_inputs<const VALIDATOR_SET_SIZE_MAX: usize, F: RichField>(
         &mut self,
-        trusted_block_number: u64,
-        trusted_block_hash: H256,
-        target_block_number: u64,
-    ) -> SkipInputs<F> {
+        trusted_block_number: Height,
+        trusted_block_hash: HeaderHash,
+        target_block_number: Height,
+    ) -> Result<SkipInputs<F>> {
         let trusted_block_validator_set = self
             .get_validator_set_from_number(trusted_block_number)
-            .await;
+            .await?;
         let nb_trusted_validators = trusted_block_validator_set.len();
         let target_block_validator_set = self
             .get_validator_set_from_number(target_block_number)
-            .await;
+            .await?;
         let nb_target_validators = target_block_validator_set.len();
-        assert!(
+        ensure!(
             nb_trusted_validators <= VALIDATOR_SET_SIZE_MAX
                 && nb_target_validators <= VALIDATOR_SET_SIZE_MAX,
-            "The validator set size of the trusted or target block is larger than the 
-            VALIDATOR_SET_SIZE_MAX."
+            "the {} trusted or {} target validators are more than the {} supported",
+            nb_trusted_validators,
+            nb_target_validators,
+            VALIDATOR_SET_SIZE_MAX
         );
+        for (block, validators) in [
+            ("trusted", &trusted_block_validator_set),
+            ("target", &target_block_validator_set),
+        ] {
+            check_voting_powers(validators)
+                .with_context(|| format!("the {} block's validators can't be proved", block))?;
+        }
 
         let trusted_signed_header = self
             .get_signed_header_from_number(trusted_block_number)
-            .await;
-        let computed_trusted_header_hash = trusted_signed_header.header.hash();
-        assert_eq!(
-            computed_trusted_header_hash.as_bytes(),
-            trusted_block_hash.as_bytes(),
-            "Trusted header hash doesn't pass sanity check! An incorrect header was likely pushed 
-            to the contract, typically the genesis header."
+            .await?;
+        let computed_trusted_header_hash = header_hash(&trusted_signed_header.header);
+        // An incorrect header was likely pushed to the contract, typically the genesis header.
+        ensure!(
+            computed_trusted_header_hash == trusted_block_hash,
+            "the header of block {} hashes to {}, not the trusted {}",
+            trusted_block_number,
+            computed_trusted_header_hash,
+            trusted_block_hash
         );
         let target_signed_header = self
             .get_signed_header_from_number(target_block_number)
-            .await;
-        let target_block_header = target_signed_header.header.hash();
+            .await?;
+        let target_block_header = header_hash(&target_signed_header.header);
         let round = target_signed_header.commit.round.value() as usize;
 
         let target_block_validators = get_validator_data_from_block::<VALIDATOR_SET_SIZE_MAX, F>(
@@ -507,19 +736,19 @@ impl InputDataFetcher {
             trusted_signed_header.header.validators_hash.encode_vec(),
         );
 
-        SkipInputs {
+        Ok(SkipInputs {
             target_block_validators,
             nb_target_validators,
-            target_header: target_block_header.as_bytes().try_into().unwrap(),
+            target_header: target_block_header.to_bytes(),
             round,
             target_block_chain_id_proof,
             target_block_height_proof,
             target_block_validators_hash_proof,
-            trusted_header: trusted_block_hash.as_bytes().try_into().unwrap(),
+            trusted_header: trusted_block_hash.to_bytes(),
             trusted_block_validators_hash_proof,
             trusted_block_validators_hash_fields,
             nb_trusted_validators,
-        }
+        })
     }
 }
 
@@ -530,18 +759,199 @@ pub(crate) mod tests {
 
     use crate::consts::VALIDATOR_SET_SIZE_MAX;
     use crate::input::conversion::get_validator_data_from_block;
+    use crate::testing::{fixture_fetcher, MockTendermintServer, SyntheticChain};
+    use crate::types::{HeaderHash, Height};
 
     #[tokio::test]
     #[cfg_attr(feature = "ci", ignore)]
     async fn test_get_header() {
         let data_fetcher = super::InputDataFetcher::default();
-        let signed_header = data_fetcher.get_signed_header_from_number(3000).await;
+        let signed_header = data_fetcher
+            .get_signed_header_from_number(Height(3000))
+            .await
+            .unwrap();
         println!(
             "Header: {:?}",
             String::from_utf8(hex::encode(signed_header.header.hash()))
         );
     }
 
+    #[tokio::test]
+    async fn test_compute_header_hash() {
+        let data_fetcher = fixture_fetcher();
+        // The block IDs signed by the commits of the recorded blocks.
+        for (height, hash) in [
+            (
+                3000,
+                "A8512F18C34B70E1533CFD5AA04F251FCB0D7BE56EC570051FBAD9BDB9435E6A",
+            ),
+            (
+                10000,
+                "A0123D5E4B8B8888A61F931EE2252D83568B97C223E0ECA9795B29B8BD8CBA2D",
+            ),
+            (
+                10500,
+                "E2BA1B86926925A69C2FCC32E5178E7E6653D386C956BB975142FA73211A9444",
+            ),
+        ] {
+            let computed = data_fetcher
+                .compute_header_hash(Height(height))
+                .await
+                .unwrap();
+            assert_eq!(computed, hash.parse::<HeaderHash>().unwrap());
+            // The hash input generation checks the trusted header against.
+            let signed_header = data_fetcher
+                .get_signed_header_from_number(Height(height))
+                .await
+                .unwrap();
+            assert_eq!(super::header_hash(&signed_header.header), computed);
+        }
+
+        // A commit for another header.
+        let dir = tempfile::tempdir().unwrap();
+        let commit = std::fs::read_to_string("./circuits/fixtures/mocha-4/10000/commit.json")
+            .unwrap()
+            .replace(
+                "A0123D5E4B8B8888A61F931EE2252D83568B97C223E0ECA9795B29B8BD8CBA2D",
+                "F2A340CC2AEF6FE163254B326A52334B45793EB11417029F9548418F88B38E26",
+            );
+        std::fs::create_dir(dir.path().join("10000")).unwrap();
+        std::fs::write(dir.path().join("10000/commit.json"), commit).unwrap();
+        let data_fetcher = super::InputDataFetcher::new(
+            vec!["http://localhost:26657".to_string()],
+            dir.path().to_str().unwrap(),
+        );
+        let error = data_fetcher
+            .compute_header_hash(Height(10000))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("but its commit signs"));
+
+        // A commit that can't be fetched is an error too.
+        let error = data_fetcher
+            .compute_header_hash(Height(10001))
+            .await
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("could not read the fixture"),
+            "{:#}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fetch_failures() {
+        let dir = tempfile::tempdir().unwrap();
+        let data_fetcher = super::InputDataFetcher::new(
+            vec!["http://localhost:26657".to_string()],
+            dir.path().to_str().unwrap(),
+        );
+        // A missing fixture.
+        let error = data_fetcher
+            .get_signed_header_from_number(Height(10000))
+            .await
+            .unwrap_err();
+        assert!(
+            error.to_string().contains("could not read the fixture"),
+            "{:#}",
+            error
+        );
+
+        // Malformed validators.
+        std::fs::create_dir(dir.path().join("10000")).unwrap();
+        let validators = r#"{"result": {"validators": [], "count": "x", "total": "1"}}"#;
+        let file = super::light_blocks::validators_file(Height(10000), 1);
+        std::fs::write(dir.path().join(file), validators).unwrap();
+        assert!(data_fetcher
+            .get_validator_set_from_number(Height(10000))
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn test_get_data_commitment_inputs() {
+        let server = MockTendermintServer::start(SyntheticChain::new(1))
+            .await
+            .unwrap();
+        let hash = |height| super::header_hash(&server.chain().header(height));
+        let (trusted_hash, target_hash) = (hash(100), hash(104));
+        let inputs = server
+            .fetcher()
+            .get_data_commitment_inputs::<GoldilocksField>(Height(100), trusted_hash, Height(104))
+            .await
+            .unwrap();
+        assert_eq!(inputs.trusted_header, trusted_hash.to_bytes());
+        assert_eq!(inputs.target_header, target_hash.to_bytes());
+
+        // The data hashes of 100 to 103, each in its header.
+        assert_eq!(inputs.data_hashes.len(), 4);
+        for (i, (data_hash, proof)) in inputs
+            .data_hashes
+            .iter()
+            .zip(&inputs.data_hash_proofs)
+            .enumerate()
+        {
+            let header = server.chain().header(100 + i as u64);
+            assert_eq!(data_hash, header.data_hash.unwrap().as_bytes());
+            // The protobuf encoding: a tag and a length, then the hash.
+            assert_eq!(&proof.leaf[2..], data_hash);
+        }
+        assert_eq!(inputs.last_block_id_proofs.len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_find_block_to_request() {
+        // 3 of the 4 validators are replaced at block 300.
+        let chain = SyntheticChain::new(7).with_churn(300, 3);
+        let server = MockTendermintServer::start(chain).await.unwrap();
+        let fetcher = server.fetcher();
+
+        // The range is halved until it no longer crosses the rotation: 1000, 550 and 325 do.
+        assert_eq!(
+            fetcher
+                .find_block_to_request(Height(100), Height(1000))
+                .await
+                .unwrap(),
+            Height(212)
+        );
+        // After it, the furthest block.
+        assert_eq!(
+            fetcher
+                .find_block_to_request(Height(300), Height(1000))
+                .await
+                .unwrap(),
+            Height(1000)
+        );
+
+        // Half of the validators left at block 800 are still enough.
+        server.rotate_validators(800, 2);
+        assert_eq!(
+            fetcher
+                .find_block_to_request(Height(300), Height(1000))
+                .await
+                .unwrap(),
+            Height(1000)
+        );
+        // None of them are from block 900.
+        server.rotate_validators(900, 2);
+        assert_eq!(
+            fetcher
+                .find_block_to_request(Height(300), Height(1000))
+                .await
+                .unwrap(),
+            Height(650)
+        );
+
+        // Every skip from just before the rotation crosses it: a step.
+        assert_eq!(
+            fetcher
+                .find_block_to_request(Height(299), Height(1000))
+                .await
+                .unwrap(),
+            Height(300)
+        );
+    }
+
     type F = GoldilocksField;
 
     #[tokio::test]
@@ -552,13 +962,15 @@ pub(crate) mod tests {
             ..Default::default()
         };
 
-        let target_block_number = 600000;
+        let target_block_number = Height(600000);
         let target_block_validator_set = data_fetcher
             .get_validator_set_from_number(target_block_number)
-            .await;
+            .await
+            .unwrap();
         let target_signed_header = data_fetcher
             .get_signed_header_from_number(target_block_number)
-            .await;
+            .await
+            .unwrap();
 
         let _ = get_validator_data_from_block::<VALIDATOR_SET_SIZE_MAX, F>(
             &target_block_validator_set,
@@ -574,17 +986,18 @@ pub(crate) mod tests {
             ..Default::default()
         };
 
-        let mut target_block_number = 610000;
+        let mut target_block_number = Height(610000);
         loop {
             println!("Checking block number: {}", target_block_number);
             let target_signed_header = data_fetcher
                 .get_signed_header_from_number(target_block_number)
-                .await;
+                .await
+                .unwrap();
             if target_signed_header.commit.round.value() != 0 {
                 println!("Found header with non-zero round: {}", target_block_number);
                 break;
             }
-            target_block_number += 1;
+            target_block_number = target_block_number.next();
         }
     }
 }
@@ -0,0 +1,154 @@
+//! Voting power arithmetic that can't overflow.
+//!
+//! Tendermint bounds each voting power by `i64::MAX`, but not their sum over a validator set, and
+//! chains mapping 10^18-scale staking tokens to power come close to `u64::MAX` in total. Sums and
+//! threshold comparisons are done in `u128` here, where a validator set of any size can't
+//! overflow. The circuits do theirs in `u64`, scaling the signed power by the threshold
+//! denominator 3: `check_voting_powers` refuses the sets whose total they can't take.
+
+use anyhow::{ensure, Result};
+use tendermint::validator::Info;
+
+/// The largest total voting power the circuits take: three times it still fits the `u64` of
+/// their threshold checks.
+pub const MAX_TOTAL_VOTING_POWER: u64 = u64::MAX / 3;
+
+/// The sum of `powers`.
+pub fn total_voting_power(powers: impl IntoIterator<Item = u64>) -> u128 {
+    powers.into_iter().map(u128::from).sum()
+}
+
+/// Whether `power` is at least `numerator / denominator` of `total`.
+pub fn reaches_fraction(power: u128, total: u128, numerator: u128, denominator: u128) -> bool {
+    // Neither side overflows: both are below 2^64 times a small constant.
+    power * denominator >= total * numerator
+}
+
+/// The total voting power of `validators`, if the circuits can take it: every voting power, and
+/// their sum, at most `MAX_TOTAL_VOTING_POWER`.
+pub fn check_voting_powers(validators: &[Info]) -> Result<u64> {
+    for validator in validators {
+        ensure!(
+            validator.power.value() <= MAX_TOTAL_VOTING_POWER,
+            "validator {} has a voting power of {}, over the {} the circuits take",
+            validator.address,
+            validator.power.value(),
+            MAX_TOTAL_VOTING_POWER
+        );
+    }
+    let total = total_voting_power(validators.iter().map(|v| v.power.value()));
+    ensure!(
+        total <= MAX_TOTAL_VOTING_POWER as u128,
+        "the {} validators have a total voting power of {}, over the {} the circuits take",
+        validators.len(),
+        total,
+        MAX_TOTAL_VOTING_POWER
+    );
+    Ok(total as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use tendermint::block::CommitSig;
+    use tendermint::validator::Set as TendermintValidatorSet;
+    use tendermint::vote::Power;
+    use tendermint::{PublicKey, Time};
+
+    use super::*;
+    use crate::input::tendermint_utils::is_valid_skip;
+    use crate::testing::SyntheticChain;
+
+    /// A validator with a distinct key for each `seed`.
+    fn validator(seed: u8, power: u64) -> Info {
+        let key = ed25519_consensus::SigningKey::from([seed; 32]);
+        let pub_key = PublicKey::from_raw_ed25519(&key.verification_key().to_bytes()).unwrap();
+        Info::new(pub_key, Power::try_from(power).unwrap())
+    }
+
+    #[test]
+    fn test_total_voting_power() {
+        // Naively summed in u64, these wrap around.
+        let max = i64::MAX as u64;
+        let powers = [max, max, max];
+        assert!(powers
+            .iter()
+            .try_fold(0u64, |sum, p| sum.checked_add(*p))
+            .is_none());
+        assert_eq!(total_voting_power(powers), 3 * max as u128);
+
+        assert!(reaches_fraction(1, 3, 1, 3));
+        assert!(!reaches_fraction(0, 3, 1, 3));
+        // Over u64::MAX in total, a third of it is still a third.
+        let total = total_voting_power(powers);
+        assert!(reaches_fraction(max as u128, total, 1, 3));
+        assert!(!reaches_fraction(max as u128 - 1, total, 1, 3));
+        assert!(reaches_fraction(2 * max as u128, total, 2, 3));
+        assert!(!reaches_fraction(2 * max as u128 - 1, total, 2, 3));
+    }
+
+    #[test]
+    fn test_check_voting_powers() {
+        let validators: Vec<_> = (1..=4).map(|seed| validator(seed, 10)).collect();
+        assert_eq!(check_voting_powers(&validators).unwrap(), 40);
+        assert_eq!(check_voting_powers(&[]).unwrap(), 0);
+
+        // Up to the limit, tendermint-rs takes the set.
+        let third = MAX_TOTAL_VOTING_POWER / 3;
+        let large = [
+            validator(1, third),
+            validator(2, third),
+            validator(3, MAX_TOTAL_VOTING_POWER - 2 * third),
+        ];
+        assert_eq!(check_voting_powers(&large).unwrap(), MAX_TOTAL_VOTING_POWER);
+        let set = TendermintValidatorSet::new(large.to_vec(), None);
+        assert_eq!(set.total_voting_power().value(), MAX_TOTAL_VOTING_POWER);
+
+        // Once over u64::MAX in total.
+        let max = i64::MAX as u64;
+        let huge = [validator(1, max / 2), validator(2, max), validator(3, max)];
+        let error = check_voting_powers(&huge).unwrap_err().to_string();
+        assert!(
+            error.starts_with(&format!(
+                "validator {} has a voting power of",
+                huge[1].address
+            )),
+            "{}",
+            error
+        );
+
+        let over = [validator(1, MAX_TOTAL_VOTING_POWER), validator(2, 1)];
+        assert_eq!(
+            check_voting_powers(&over).unwrap_err().to_string(),
+            format!(
+                "the 2 validators have a total voting power of {}, over the {} the circuits take",
+                MAX_TOTAL_VOTING_POWER as u128 + 1,
+                MAX_TOTAL_VOTING_POWER
+            )
+        );
+    }
+
+    #[test]
+    fn test_is_valid_skip_with_huge_powers() {
+        // A third of the target's voting power, which overflows a u64 in total.
+        let max = i64::MAX as u64;
+        let target: Vec<_> = (1..=3).map(|seed| validator(seed, max)).collect();
+        let trusted = [target[0].clone(), validator(4, 10)];
+        let commit = |signers: &[Info]| {
+            let mut commit = SyntheticChain::new(1).signed_header(100).commit;
+            commit.signatures = signers
+                .iter()
+                .map(|signer| CommitSig::BlockIdFlagCommit {
+                    validator_address: signer.address,
+                    timestamp: Time::unix_epoch(),
+                    signature: None,
+                })
+                .collect();
+            commit
+        };
+        assert!(is_valid_skip(&trusted, &target, commit(&target)));
+        assert!(!is_valid_skip(&trusted, &target, commit(&target[1..])));
+        let mut lighter = target.clone();
+        lighter[0].power = Power::try_from(max - 1).unwrap();
+        assert!(!is_valid_skip(&lighter[..1], &lighter, commit(&lighter)));
+    }
+}
@@ -12,7 +12,6 @@ use subtle_encoding::hex;
 use tendermint::block::signed_header::SignedHeader;
 pub use tendermint::block::Header;
 pub use tendermint::merkle::Hash;
-use tendermint::validator::Set as TendermintValidatorSet;
 /// Source (tendermint-rs): https://github.com/informalsystems/tendermint-rs/blob/e930691a5639ef805c399743ac0ddbba0e9f53da/tendermint/src/merkle.rs#L32
 use tendermint::{
     block::{Commit, CommitSig},
@@ -25,6 +24,8 @@ use tendermint_proto::types::{BlockId as RawBlockId, Data as RawData};
 use tendermint_proto::version::Consensus as RawConsensusVersion;
 use tendermint_proto::Protobuf;
 
+use super::power::{reaches_fraction, total_voting_power};
+
 /// Compute leaf hashes for arbitrary byte vectors.
 /// The leaves of the tree are the bytes of the given byte vectors in
 /// the given order.
@@ -371,8 +372,9 @@ where
     hasher.inner_hash(left, right)
 }
 
-pub fn generate_proofs_from_header(h: &Header) -> (Hash, Vec<Proof>) {
-    let fields_bytes = vec![
+/// The protobuf encodings of the fields of `h`, in the order of the leaves of its Merkle tree.
+pub fn header_fields_bytes(h: &Header) -> Vec<Vec<u8>> {
+    vec![
         Protobuf::<RawConsensusVersion>::encode_vec(h.version),
         h.chain_id.clone().encode_vec(),
         h.height.encode_vec(),
@@ -387,9 +389,11 @@ pub fn generate_proofs_from_header(h: &Header) -> (Hash, Vec<Proof>) {
         h.last_results_hash.unwrap_or_default().encode_vec(),
         h.evidence_hash.unwrap_or_default().encode_vec(),
         h.proposer_address.encode_vec(),
-    ];
+    ]
+}
 
-    proofs_from_byte_slices(fields_bytes)
+pub fn generate_proofs_from_header(h: &Header) -> (Hash, Vec<Proof>) {
+    proofs_from_byte_slices(header_fields_bytes(h))
 }
 
 pub fn generate_proofs_from_block_id(
@@ -440,43 +444,41 @@ pub fn get_vote_from_commit_sig(
     })
 }
 
-/// Determines if a valid skip is possible between start_block and target_block.
+/// Determines if a valid skip is possible between start_block and target_block: whether the
+/// validators of the start block that signed the target block hold at least 1/3 of the voting
+/// power of the target block's validators. Voting powers are summed in `u128`, as the sums of
+/// chains with huge powers overflow a `u64`.
 pub fn is_valid_skip(
-    start_validator_set: TendermintValidatorSet,
-    target_validator_set: TendermintValidatorSet,
+    start_block_validators: &[Info],
+    target_block_validators: &[Info],
     target_block_commit: Commit,
 ) -> bool {
-    let threshold = 1_f64 / 3_f64;
+    let target_block_total_voting_power =
+        total_voting_power(target_block_validators.iter().map(|v| v.power.value()));
+    let reached = |shared_voting_power| {
+        reaches_fraction(shared_voting_power, target_block_total_voting_power, 1, 3)
+    };
 
     let mut shared_voting_power = 0;
-
-    let target_block_total_voting_power = target_validator_set.total_voting_power().value();
-
-    let start_block_validators = start_validator_set.validators();
-
-    let mut start_block_idx = 0;
-    let start_block_num_validators = start_block_validators.len();
-
     // Exit if we have already reached the threshold
     // TODO: Confirm this is resilient by testing many different cases.
-    while target_block_total_voting_power as f64 * threshold > shared_voting_power as f64
-        && start_block_idx < start_block_num_validators
-    {
-        if let Some(target_block_validator) =
-            target_validator_set.validator(start_block_validators[start_block_idx].address)
+    for start_block_validator in start_block_validators {
+        if reached(shared_voting_power) {
+            break;
+        }
+        if let Some(target_block_validator) = target_block_validators
+            .iter()
+            .find(|v| v.address == start_block_validator.address)
         {
             // Confirm that the validator has signed on target_block.
             for sig in target_block_commit.signatures.iter() {
-                if sig.validator_address().is_some()
-                    && sig.validator_address().unwrap() == target_block_validator.address
-                {
+                if sig.validator_address() == Some(target_block_validator.address) {
                     // Add the shared voting power to the validator
-                    shared_voting_power += target_block_validator.power();
+                    shared_voting_power += u128::from(target_block_validator.power.value());
                 }
             }
         }
-        start_block_idx += 1;
     }
 
-    target_block_total_voting_power as f64 * threshold <= shared_voting_power as f64
+    reached(shared_voting_power)
 }
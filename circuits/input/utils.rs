@@ -1,4 +1,4 @@
-use ethers::types::H256;
+use ethers_core::types::H256;
 
 pub fn convert_to_h256(aunts: Vec<[u8; 32]>) -> Vec<H256> {
     let mut aunts_h256 = Vec::new();
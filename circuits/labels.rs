@@ -0,0 +1,210 @@
+//! Labels identifying who a request was made by, e.g. `operator=ops,environment=prod`.
+//!
+//! The labels of a target are recorded with each of its requests in the store and included in
+//! the log lines about those requests, so that requests from several operators sharing one
+//! platform account can be told apart.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use anyhow::{anyhow, Context, Result};
+use serde::{Deserialize, Serialize};
+
+/// The maximum length of a label key.
+const MAX_KEY_LEN: usize = 63;
+
+/// The maximum length of a label value.
+const MAX_VALUE_LEN: usize = 255;
+
+/// A validated set of labels. Keys are lowercase alphanumeric with underscores, starting with a
+/// letter. Values are alphanumeric or one of `-_.:/`. Serializes as a JSON object, validated when
+/// deserialized.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(
+    try_from = "BTreeMap<String, String>",
+    into = "BTreeMap<String, String>"
+)]
+pub struct Labels(BTreeMap<String, String>);
+
+impl Labels {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Parse comma separated `key=value` pairs. An empty string has no labels.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut labels = Self::new();
+        for pair in spec.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let (key, value) = pair
+                .split_once('=')
+                .ok_or_else(|| anyhow!("invalid label {:?}, expected key=value", pair))?;
+            labels.insert(key.trim(), value.trim())?;
+        }
+        Ok(labels)
+    }
+
+    pub fn insert(&mut self, key: &str, value: &str) -> Result<()> {
+        validate_key(key)?;
+        validate_value(key, value)?;
+        self.0.insert(key.to_string(), value.to_string());
+        Ok(())
+    }
+
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.0.get(key).map(String::as_str)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.0.iter().map(|(k, v)| (k.as_str(), v.as_str()))
+    }
+
+    /// The labels as a JSON object, as recorded in the store.
+    pub fn to_json(&self) -> String {
+        serde_json::to_string(&self.0).expect("labels serialize to JSON")
+    }
+
+    pub fn from_json(json: &str) -> Result<Self> {
+        let map: BTreeMap<String, String> = serde_json::from_str(json).context("invalid labels")?;
+        map.try_into()
+    }
+}
+
+impl TryFrom<BTreeMap<String, String>> for Labels {
+    type Error = anyhow::Error;
+
+    fn try_from(map: BTreeMap<String, String>) -> Result<Self> {
+        let mut labels = Self::new();
+        for (key, value) in map.iter() {
+            labels.insert(key, value)?;
+        }
+        Ok(labels)
+    }
+}
+
+impl From<Labels> for BTreeMap<String, String> {
+    fn from(labels: Labels) -> Self {
+        labels.0
+    }
+}
+
+/// Formats as `key=value` pairs separated by spaces.
+impl fmt::Display for Labels {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let pairs = self
+            .iter()
+            .map(|(key, value)| format!("{}={}", key, value))
+            .collect::<Vec<_>>();
+        f.write_str(&pairs.join(" "))
+    }
+}
+
+fn validate_key(key: &str) -> Result<()> {
+    let valid = key.len() <= MAX_KEY_LEN
+        && key.starts_with(|c: char| c.is_ascii_lowercase())
+        && key
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_');
+    if !valid {
+        return Err(anyhow!(
+            "invalid label key {:?}: expected at most {} of a-z, 0-9 and _, starting with a letter",
+            key,
+            MAX_KEY_LEN
+        ));
+    }
+    Ok(())
+}
+
+fn validate_value(key: &str, value: &str) -> Result<()> {
+    let valid = !value.is_empty()
+        && value.len() <= MAX_VALUE_LEN
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "-_.:/".contains(c));
+    if !valid {
+        return Err(anyhow!(
+            "invalid value {:?} for label {}: expected 1 to {} of A-Z, a-z, 0-9 and -_.:/",
+            value,
+            key,
+            MAX_VALUE_LEN
+        ));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::Bytes;
+
+    use super::*;
+    use crate::backend::mock::MockBackend;
+    use crate::backend::{ProofBackend, ProofRequest};
+    use crate::store::tests::new_request;
+    use crate::store::{NewRequest, RequestStore};
+    use crate::target::RequestTarget;
+
+    #[test]
+    fn test_parse_labels() {
+        let labels = Labels::parse("operator=ops-team, environment=prod,").unwrap();
+        assert_eq!(labels.get("operator"), Some("ops-team"));
+        assert_eq!(labels.get("environment"), Some("prod"));
+        assert_eq!(labels.to_string(), "environment=prod operator=ops-team");
+        assert_eq!(Labels::from_json(&labels.to_json()).unwrap(), labels);
+        assert!(Labels::parse("").unwrap().is_empty());
+
+        assert!(Labels::parse("operator").is_err());
+        assert!(Labels::parse("Operator=ops").is_err());
+        assert!(Labels::parse("1operator=ops").is_err());
+        assert!(Labels::parse("operator=").is_err());
+        assert!(Labels::parse("operator=ops team").is_err());
+        assert!(Labels::parse(&format!("{}=ops", "k".repeat(64))).is_err());
+        assert!(Labels::parse(&format!("operator={}", "v".repeat(256))).is_err());
+        assert!(Labels::from_json(r#"{"Bad": "x"}"#).is_err());
+    }
+
+    #[test]
+    fn test_serde_labels() {
+        let labels = Labels::parse("operator=ops-team,environment=prod").unwrap();
+        let json = serde_json::to_string(&labels).unwrap();
+        assert_eq!(json, r#"{"environment":"prod","operator":"ops-team"}"#);
+        assert_eq!(serde_json::from_str::<Labels>(&json).unwrap(), labels);
+        // Deserializing validates, like parsing.
+        assert!(serde_json::from_str::<Labels>(r#"{"operator": "ops team"}"#).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_label_propagation() {
+        let mut labels = Labels::parse("operator=ops,environment=prod").unwrap();
+        labels.insert("chain", "5").unwrap();
+        let target = RequestTarget {
+            labels: labels.clone(),
+            ..RequestTarget::for_test(5)
+        };
+        let request = ProofRequest {
+            target: &target,
+            trusted_block: 100,
+            target_block: 101,
+            function_id: target.step_function_id,
+            calldata: Bytes::new(),
+            input: Bytes::new(),
+            correlation_id: None,
+        };
+
+        let backend = MockBackend::new();
+        let request_id = backend.request_step(&request).await.unwrap();
+        assert_eq!(backend.requests()[0].target.labels, labels);
+
+        let dir = tempfile::tempdir().unwrap();
+        let store = RequestStore::open(dir.path().join("requests.db")).unwrap();
+        store
+            .insert(&NewRequest {
+                labels: request.target.labels.clone(),
+                ..new_request(&request_id, 100, 101)
+            })
+            .unwrap();
+        assert_eq!(store.get(&request_id).unwrap().unwrap().labels, labels);
+    }
+}
@@ -0,0 +1,290 @@
+//! How far each target's light client is behind the chain, and when that is worth an alert.
+//!
+//! The lag is the number of blocks between the chain head and the contract's latest block, and
+//! the time between their headers (the lag in blocks at the chain's recent block time). Some lag
+//! is normal while a proof is in flight, so the alert only fires once the lag stayed above the
+//! threshold for the configured duration, and clears as soon as it drops back under it.
+//!
+//! Proofs cost money, so a target whose lag is under the minimum lag isn't worth a request yet.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DurationSeconds};
+
+use crate::chainspec::ChainSpec;
+
+/// The lag of a target behind the chain head.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Lag {
+    pub blocks: u64,
+    /// The time between the header of the contract's latest block and the chain head's.
+    pub seconds: u64,
+}
+
+impl Lag {
+    /// The lag of a contract at `block` (with header time `block_time`) behind the chain head
+    /// `head` (with header time `head_time`), times in unix seconds.
+    pub fn new(head: u64, head_time: i64, block: u64, block_time: i64) -> Self {
+        Self {
+            blocks: head.saturating_sub(block),
+            seconds: head_time.saturating_sub(block_time).max(0) as u64,
+        }
+    }
+}
+
+impl fmt::Display for Lag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} blocks ({:?})",
+            self.blocks,
+            Duration::from_secs(self.seconds)
+        )
+    }
+}
+
+/// The lag a target must reach before a request is submitted for it.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct MinLag {
+    #[serde(rename = "blocks")]
+    pub blocks: Option<u64>,
+    #[serde(rename = "seconds")]
+    pub seconds: Option<u64>,
+}
+
+impl MinLag {
+    /// Whether `lag` is worth a request: it reached either threshold, or neither is set.
+    pub fn reached(&self, lag: Lag) -> bool {
+        match (self.blocks, self.seconds) {
+            (None, None) => true,
+            (blocks, seconds) => {
+                blocks.map_or(false, |blocks| lag.blocks >= blocks)
+                    || seconds.map_or(false, |seconds| lag.seconds >= seconds)
+            }
+        }
+    }
+
+    /// The time until `lag` reaches either threshold at the block time of `spec`, if one is set.
+    pub fn eta(&self, lag: Lag, spec: &ChainSpec) -> Option<Duration> {
+        let blocks = self
+            .blocks
+            .map(|blocks| spec.duration_of(blocks.saturating_sub(lag.blocks)));
+        let seconds = self
+            .seconds
+            .map(|seconds| Duration::from_secs(seconds.saturating_sub(lag.seconds)));
+        match (blocks, seconds) {
+            (Some(blocks), Some(seconds)) => Some(blocks.min(seconds)),
+            (blocks, seconds) => blocks.or(seconds),
+        }
+    }
+}
+
+impl fmt::Display for MinLag {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.blocks, self.seconds) {
+            (Some(blocks), Some(seconds)) => {
+                write!(f, "{} blocks or {:?}", blocks, Duration::from_secs(seconds))
+            }
+            (Some(blocks), None) => write!(f, "{} blocks", blocks),
+            (None, Some(seconds)) => write!(f, "{:?}", Duration::from_secs(seconds)),
+            (None, None) => write!(f, "none"),
+        }
+    }
+}
+
+/// A change in the alert state of a target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LagTransition {
+    /// The lag has been above the threshold for the configured duration.
+    Fired,
+    /// The lag dropped back under the threshold after the alert fired.
+    Cleared,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct BreachState {
+    since: Instant,
+    fired: bool,
+}
+
+/// Tracks how long each target's lag has been above the threshold. Serializes its settings only.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LagMonitor {
+    /// The lag in blocks above which a target is behind.
+    #[serde(rename = "threshold_blocks")]
+    pub threshold_blocks: u64,
+    /// How long a target must stay behind before the alert fires.
+    #[serde(rename = "sustain_secs")]
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub sustain: Duration,
+    #[serde(skip)]
+    breaches: HashMap<String, BreachState>,
+}
+
+impl LagMonitor {
+    pub fn new(threshold_blocks: u64, sustain: Duration) -> Self {
+        Self {
+            threshold_blocks,
+            sustain,
+            breaches: HashMap::new(),
+        }
+    }
+
+    /// Record the lag of the target `key` observed at `now`. Returns the transition of its alert
+    /// state, if any.
+    pub fn observe(&mut self, key: &str, lag: Lag, now: Instant) -> Option<LagTransition> {
+        if lag.blocks <= self.threshold_blocks {
+            let breach = self.breaches.remove(key)?;
+            return breach.fired.then_some(LagTransition::Cleared);
+        }
+        let breach = self.breaches.entry(key.to_string()).or_insert(BreachState {
+            since: now,
+            fired: false,
+        });
+        if !breach.fired && now.saturating_duration_since(breach.since) >= self.sustain {
+            breach.fired = true;
+            return Some(LagTransition::Fired);
+        }
+        None
+    }
+
+    /// How long the target `key` has been behind at `now`, if it is.
+    pub fn behind_for(&self, key: &str, now: Instant) -> Option<Duration> {
+        self.breaches
+            .get(key)
+            .map(|breach| now.saturating_duration_since(breach.since))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn lag(blocks: u64) -> Lag {
+        Lag {
+            blocks,
+            seconds: blocks * 6,
+        }
+    }
+
+    #[test]
+    fn test_lag() {
+        let lag = Lag::new(1500, 1_700_003_000, 1000, 1_700_000_000);
+        assert_eq!(
+            lag,
+            Lag {
+                blocks: 500,
+                seconds: 3000
+            }
+        );
+        // A contract ahead of a stale head is not behind.
+        assert_eq!(
+            Lag::new(1000, 0, 1500, 10),
+            Lag {
+                blocks: 0,
+                seconds: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_min_lag() {
+        let lag = |blocks, seconds| Lag { blocks, seconds };
+        let min_lag = |blocks, seconds| MinLag { blocks, seconds };
+
+        // No threshold: every lag is worth a request, even none.
+        assert!(MinLag::default().reached(lag(0, 0)));
+        assert!(MinLag::default().reached(lag(3, 14400)));
+
+        let blocks = min_lag(Some(100), None);
+        assert!(!blocks.reached(lag(0, 0)));
+        assert!(!blocks.reached(lag(99, 86400)));
+        assert!(blocks.reached(lag(100, 0)));
+        assert!(blocks.reached(lag(101, 600)));
+
+        let seconds = min_lag(None, Some(3600));
+        assert!(!seconds.reached(lag(5000, 3599)));
+        assert!(seconds.reached(lag(1, 3600)));
+
+        // With both, either is enough.
+        let both = min_lag(Some(100), Some(3600));
+        assert!(!both.reached(lag(99, 3599)));
+        assert!(both.reached(lag(100, 3599)));
+        assert!(both.reached(lag(99, 3600)));
+        assert!(both.reached(lag(3, 14400)));
+
+        assert_eq!(both.to_string(), "100 blocks or 3600s");
+        assert_eq!(seconds.to_string(), "3600s");
+    }
+
+    #[test]
+    fn test_min_lag_eta() {
+        let lag = Lag {
+            blocks: 40,
+            seconds: 600,
+        };
+        // 60 more blocks of 5 seconds.
+        let spec = ChainSpec::preset("osmosis").unwrap();
+        let blocks = MinLag {
+            blocks: Some(100),
+            seconds: None,
+        };
+        assert_eq!(blocks.eta(lag, &spec), Some(Duration::from_secs(300)));
+        let both = MinLag {
+            blocks: Some(100),
+            seconds: Some(700),
+        };
+        assert_eq!(both.eta(lag, &spec), Some(Duration::from_secs(100)));
+        assert_eq!(MinLag::default().eta(lag, &spec), None);
+    }
+
+    #[test]
+    fn test_hysteresis() {
+        let mut monitor = LagMonitor::new(100, Duration::from_secs(600));
+        let start = Instant::now();
+        let at = |minutes: u64| start + Duration::from_secs(60 * minutes);
+
+        // (minute, lag in blocks, expected transition)
+        let series = [
+            (0, 150, None),
+            (5, 150, None),
+            // A dip under the threshold restarts the clock, without an alert to clear.
+            (9, 50, None),
+            (10, 150, None),
+            (19, 400, None),
+            (20, 150, Some(LagTransition::Fired)),
+            // The alert fires once per episode.
+            (25, 300, None),
+            (30, 100, Some(LagTransition::Cleared)),
+            (31, 20, None),
+            (32, 150, None),
+        ];
+        for (minute, blocks, expected) in series {
+            assert_eq!(
+                monitor.observe("5:0x11", lag(blocks), at(minute)),
+                expected,
+                "at minute {}",
+                minute
+            );
+        }
+        assert_eq!(
+            monitor.behind_for("5:0x11", at(34)),
+            Some(Duration::from_secs(120))
+        );
+
+        // Targets are tracked independently.
+        assert_eq!(monitor.observe("10:0x22", lag(150), at(40)), None);
+        assert_eq!(
+            monitor.observe("5:0x11", lag(150), at(42)),
+            Some(LagTransition::Fired)
+        );
+        assert_eq!(
+            monitor.behind_for("10:0x22", at(42)),
+            Some(Duration::from_secs(120))
+        );
+    }
+}
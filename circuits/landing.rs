@@ -0,0 +1,161 @@
+//! Waiting for a request to land on-chain.
+//!
+//! Rather than sleeping a fixed delay after submitting, the run loop waits for the contract's
+//! latest block to reach each request's target block (or for the request to fail), so that the
+//! next range is selected as soon as the previous one is stored and never from a trusted block
+//! that is about to change. A request that takes longer than the timeout falls back to the fixed
+//! delay.
+
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::Result;
+use log::{debug, warn};
+use tokio::time::Instant;
+
+use crate::platform::FulfillmentStatus;
+
+/// The default time to wait for a request to land on-chain.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(2 * 60 * 60);
+
+/// The default time between checks of a request while waiting for it.
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How the wait for a request ended.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Landing {
+    /// The contract stored the target block, or a later one.
+    Landed {
+        latest_block: u64,
+    },
+    /// The platform failed to fulfill the request.
+    Failed {
+        error: Option<String>,
+    },
+    TimedOut,
+}
+
+/// Wait until `latest_block` returns at least `target_block`, or `status` returns a failure,
+/// checking both every `interval` until `deadline`. Failed reads are logged and retried.
+pub async fn wait_for_landing<B, BF, S, SF>(
+    mut latest_block: B,
+    mut status: S,
+    target_block: u64,
+    interval: Duration,
+    deadline: Instant,
+) -> Landing
+where
+    B: FnMut() -> BF,
+    BF: Future<Output = Result<u64>>,
+    S: FnMut() -> SF,
+    SF: Future<Output = Result<FulfillmentStatus>>,
+{
+    loop {
+        match latest_block().await {
+            Ok(block) if block >= target_block => {
+                return Landing::Landed {
+                    latest_block: block,
+                }
+            }
+            Ok(block) => debug!("Waiting for block {}, at {}", target_block, block),
+            Err(e) => warn!("Failed to read the latest block: {:#}", e),
+        }
+        match status().await {
+            Ok(FulfillmentStatus::Failed { error }) => return Landing::Failed { error },
+            Ok(_) => {}
+            Err(e) => warn!("Failed to query the request status: {:#}", e),
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return Landing::TimedOut;
+        }
+        tokio::time::sleep(interval.min(deadline - now)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use anyhow::anyhow;
+
+    use super::*;
+
+    /// A contract whose latest block is set to `block` after `delay`.
+    fn advancing_contract(block: u64, delay: Duration) -> Arc<AtomicU64> {
+        let latest = Arc::new(AtomicU64::new(1000));
+        let relayed = latest.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(delay).await;
+            relayed.store(block, Ordering::SeqCst);
+        });
+        latest
+    }
+
+    #[tokio::test]
+    async fn test_wait_for_landing() {
+        let interval = Duration::from_millis(5);
+        let deadline = || Instant::now() + Duration::from_secs(5);
+        let proving = || async { Ok::<_, anyhow::Error>(FulfillmentStatus::Proving) };
+
+        // The request lands after a while: the wait ends as soon as it's stored.
+        let latest = advancing_contract(1500, Duration::from_millis(50));
+        let start = Instant::now();
+        let latest = latest.as_ref();
+        let read = move || async move { Ok::<_, anyhow::Error>(latest.load(Ordering::SeqCst)) };
+        let landing = wait_for_landing(read, proving, 1500, interval, deadline()).await;
+        assert_eq!(landing, Landing::Landed { latest_block: 1500 });
+        assert!(start.elapsed() >= Duration::from_millis(50));
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        // Another operator stored a later block: that counts too, even if the reads fail now and
+        // then.
+        let latest = advancing_contract(1600, Duration::from_millis(20));
+        let reads = AtomicU64::new(0);
+        let (reads, latest) = (&reads, latest.as_ref());
+        let read = move || async move {
+            if reads.fetch_add(1, Ordering::SeqCst) % 2 == 0 {
+                return Err(anyhow!("connection reset"));
+            }
+            Ok(latest.load(Ordering::SeqCst))
+        };
+        let landing = wait_for_landing(read, proving, 1500, interval, deadline()).await;
+        assert_eq!(landing, Landing::Landed { latest_block: 1600 });
+    }
+
+    #[tokio::test]
+    async fn test_failure_and_timeout() {
+        let interval = Duration::from_millis(5);
+        let stuck = || async { Ok::<_, anyhow::Error>(1000) };
+
+        // The proof fails after a few checks.
+        let statuses = Mutex::new(vec![
+            Ok(FulfillmentStatus::Failed {
+                error: Some("prover crashed".to_string()),
+            }),
+            Err(anyhow!("platform unavailable")),
+            Ok(FulfillmentStatus::Proving),
+        ]);
+        let statuses = &statuses;
+        let status = move || async move { statuses.lock().unwrap().pop().unwrap() };
+        let deadline = Instant::now() + Duration::from_secs(5);
+        assert_eq!(
+            wait_for_landing(stuck, status, 1500, interval, deadline).await,
+            Landing::Failed {
+                error: Some("prover crashed".to_string())
+            }
+        );
+
+        // The request never lands: the wait ends at the deadline.
+        let start = Instant::now();
+        let proved =
+            || async { Ok::<_, anyhow::Error>(FulfillmentStatus::Proved { proof_id: None }) };
+        let deadline = start + Duration::from_millis(50);
+        assert_eq!(
+            wait_for_landing(stuck, proved, 1500, interval, deadline).await,
+            Landing::TimedOut
+        );
+        assert!(start.elapsed() >= Duration::from_millis(50));
+    }
+}
@@ -0,0 +1,365 @@
+//! Leader election between redundant operators.
+//!
+//! Operators for the same chain hold a lease under a shared key: only the holder (the leader)
+//! submits requests, while the others monitor the targets and try to acquire the lease every third
+//! of its time to live. The leader renews it at the same interval, so a follower takes over within
+//! a lease timeout (plus a refresh interval) of the leader disappearing. A leader that fails to
+//! renew its lease steps down right away, since it can no longer tell whether another operator
+//! took over.
+
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use async_trait::async_trait;
+use log::{info, warn};
+
+use crate::metrics::OperatorMetrics;
+
+/// The default time to live of the lease.
+pub const DEFAULT_LEASE_TTL: Duration = Duration::from_secs(30);
+
+/// The key the lease is held under, unless configured.
+pub const DEFAULT_LEASE_KEY: &str = "tendermintx:leader";
+
+/// A lease held by at most one operator at a time.
+#[async_trait]
+pub trait Lease: Send + Sync {
+    /// Acquire the lease for `holder` for `ttl`, or renew it if `holder` already holds it.
+    /// Returns whether `holder` holds the lease.
+    async fn try_acquire(&self, holder: &str, ttl: Duration) -> Result<bool>;
+
+    /// Release the lease if `holder` holds it.
+    async fn release(&self, holder: &str) -> Result<()>;
+}
+
+pub trait Clock: Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// A lease shared by the operators of a single process.
+#[derive(Debug, Default)]
+pub struct MemoryLease<C = SystemClock> {
+    clock: C,
+    /// The holder of the lease and when it expires.
+    held: Mutex<Option<(String, Instant)>>,
+}
+
+impl<C: Clock> MemoryLease<C> {
+    pub fn new(clock: C) -> Self {
+        Self {
+            clock,
+            held: Mutex::new(None),
+        }
+    }
+}
+
+#[async_trait]
+impl<C: Clock> Lease for MemoryLease<C> {
+    async fn try_acquire(&self, holder: &str, ttl: Duration) -> Result<bool> {
+        let now = self.clock.now();
+        let mut held = self.held.lock().unwrap();
+        match held.as_ref() {
+            Some((current, expires_at)) if current != holder && *expires_at > now => Ok(false),
+            _ => {
+                *held = Some((holder.to_string(), now + ttl));
+                Ok(true)
+            }
+        }
+    }
+
+    async fn release(&self, holder: &str) -> Result<()> {
+        let mut held = self.held.lock().unwrap();
+        if held
+            .as_ref()
+            .map_or(false, |(current, _)| current == holder)
+        {
+            *held = None;
+        }
+        Ok(())
+    }
+}
+
+/// A lease held in Redis, under a key that expires with the lease.
+#[cfg(feature = "redis")]
+pub struct RedisLease {
+    client: redis::Client,
+    key: String,
+}
+
+#[cfg(feature = "redis")]
+impl RedisLease {
+    pub fn new(url: &str, key: impl Into<String>) -> Result<Self> {
+        Ok(Self {
+            client: redis::Client::open(url)?,
+            key: key.into(),
+        })
+    }
+}
+
+#[cfg(feature = "redis")]
+#[async_trait]
+impl Lease for RedisLease {
+    async fn try_acquire(&self, holder: &str, ttl: Duration) -> Result<bool> {
+        // Acquiring and renewing must be atomic, so that an expired lease isn't renewed by its
+        // previous holder after another operator acquired it.
+        let script = redis::Script::new(
+            r"
+            local current = redis.call('GET', KEYS[1])
+            if current == false then
+                redis.call('SET', KEYS[1], ARGV[1], 'PX', ARGV[2])
+                return 1
+            elseif current == ARGV[1] then
+                redis.call('PEXPIRE', KEYS[1], ARGV[2])
+                return 1
+            end
+            return 0
+            ",
+        );
+        let mut connection = self.client.get_async_connection().await?;
+        let acquired: i64 = script
+            .key(&self.key)
+            .arg(holder)
+            .arg(ttl.as_millis() as u64)
+            .invoke_async(&mut connection)
+            .await?;
+        Ok(acquired == 1)
+    }
+
+    async fn release(&self, holder: &str) -> Result<()> {
+        let script = redis::Script::new(
+            r"
+            if redis.call('GET', KEYS[1]) == ARGV[1] then
+                return redis.call('DEL', KEYS[1])
+            end
+            return 0
+            ",
+        );
+        let mut connection = self.client.get_async_connection().await?;
+        let _: i64 = script
+            .key(&self.key)
+            .arg(holder)
+            .invoke_async(&mut connection)
+            .await?;
+        Ok(())
+    }
+}
+
+/// A change in the leadership of an operator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LeadershipChange {
+    Elected,
+    SteppedDown,
+}
+
+/// Whether this operator is the leader, kept up to date by `run`.
+pub struct LeaderElection {
+    lease: Arc<dyn Lease>,
+    /// The identity of this operator in the lease.
+    pub holder: String,
+    pub ttl: Duration,
+    leader: AtomicBool,
+    metrics: Arc<OperatorMetrics>,
+}
+
+impl LeaderElection {
+    pub fn new(
+        lease: Arc<dyn Lease>,
+        holder: impl Into<String>,
+        ttl: Duration,
+        metrics: Arc<OperatorMetrics>,
+    ) -> Self {
+        Self {
+            lease,
+            holder: holder.into(),
+            ttl,
+            leader: AtomicBool::new(false),
+            metrics,
+        }
+    }
+
+    pub fn is_leader(&self) -> bool {
+        self.leader.load(Ordering::SeqCst)
+    }
+
+    /// The time between attempts to acquire or renew the lease.
+    pub fn refresh_interval(&self) -> Duration {
+        self.ttl / 3
+    }
+
+    /// Acquire or renew the lease. Returns the change in leadership, if any.
+    pub async fn refresh(&self) -> Option<LeadershipChange> {
+        let leader = match self.lease.try_acquire(&self.holder, self.ttl).await {
+            Ok(leader) => leader,
+            Err(e) => {
+                warn!("Failed to refresh the leader lease: {:#}", e);
+                false
+            }
+        };
+        let change = match (self.leader.swap(leader, Ordering::SeqCst), leader) {
+            (false, true) => {
+                info!("{} is now the leader", self.holder);
+                Some(LeadershipChange::Elected)
+            }
+            (true, false) => {
+                warn!("{} is no longer the leader", self.holder);
+                Some(LeadershipChange::SteppedDown)
+            }
+            _ => None,
+        };
+        self.metrics.record_leader(leader);
+        change
+    }
+
+    /// Refresh the lease every refresh interval, forever, after an initial `refresh`.
+    pub async fn run(&self) {
+        loop {
+            tokio::time::sleep(self.refresh_interval()).await;
+            self.refresh().await;
+        }
+    }
+
+    /// Release the lease, so that a follower takes over without waiting for it to expire.
+    pub async fn resign(&self) {
+        if !self.leader.swap(false, Ordering::SeqCst) {
+            return;
+        }
+        match self.lease.release(&self.holder).await {
+            Ok(()) => info!("{} resigned as the leader", self.holder),
+            Err(e) => warn!("Failed to release the leader lease: {:#}", e),
+        }
+        self.metrics.record_leader(false);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A clock that only moves when told to.
+    #[derive(Clone)]
+    struct ManualClock(Arc<Mutex<Instant>>);
+
+    impl ManualClock {
+        fn new() -> Self {
+            Self(Arc::new(Mutex::new(Instant::now())))
+        }
+
+        fn advance(&self, by: Duration) {
+            *self.0.lock().unwrap() += by;
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> Instant {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    /// A lease that fails while `down` is set, like an unreachable Redis.
+    struct FlakyLease {
+        inner: MemoryLease<ManualClock>,
+        down: AtomicBool,
+    }
+
+    #[async_trait]
+    impl Lease for FlakyLease {
+        async fn try_acquire(&self, holder: &str, ttl: Duration) -> Result<bool> {
+            if self.down.load(Ordering::SeqCst) {
+                return Err(anyhow::anyhow!("connection refused"));
+            }
+            self.inner.try_acquire(holder, ttl).await
+        }
+
+        async fn release(&self, holder: &str) -> Result<()> {
+            self.inner.release(holder).await
+        }
+    }
+
+    fn elections(lease: Arc<dyn Lease>) -> (LeaderElection, LeaderElection, Arc<OperatorMetrics>) {
+        let metrics = Arc::new(OperatorMetrics::new());
+        let ttl = Duration::from_secs(30);
+        (
+            LeaderElection::new(lease.clone(), "a", ttl, metrics.clone()),
+            LeaderElection::new(lease, "b", ttl, Arc::new(OperatorMetrics::new())),
+            metrics,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_takeover() {
+        use LeadershipChange::*;
+
+        let clock = ManualClock::new();
+        let (a, b, metrics) = elections(Arc::new(MemoryLease::new(clock.clone())));
+        assert_eq!(a.refresh().await, Some(Elected));
+        assert_eq!(b.refresh().await, None);
+        assert!(a.is_leader() && !b.is_leader());
+
+        // The leader renews its lease every refresh interval.
+        for _ in 0..5 {
+            clock.advance(a.refresh_interval());
+            assert_eq!(a.refresh().await, None);
+            assert_eq!(b.refresh().await, None);
+        }
+
+        // The leader disappears: the follower takes over once the lease expires.
+        clock.advance(Duration::from_secs(20));
+        assert_eq!(b.refresh().await, None);
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(b.refresh().await, Some(Elected));
+        // The previous leader comes back as a follower.
+        assert_eq!(a.refresh().await, Some(SteppedDown));
+        assert!(!a.is_leader() && b.is_leader());
+
+        // A resigning leader hands over right away.
+        b.resign().await;
+        assert!(!b.is_leader());
+        assert_eq!(a.refresh().await, Some(Elected));
+
+        let mut out = crate::metrics::MetricsWriter::new();
+        metrics.write(&mut out);
+        let out = out.finish();
+        assert!(out.contains("tendermintx_leader 1\n"), "{}", out);
+        assert!(
+            out.contains("tendermintx_leadership_changes_total 3\n"),
+            "{}",
+            out
+        );
+    }
+
+    #[tokio::test]
+    async fn test_lease_failure() {
+        let clock = ManualClock::new();
+        let lease = Arc::new(FlakyLease {
+            inner: MemoryLease::new(clock.clone()),
+            down: AtomicBool::new(false),
+        });
+        let (a, b, _) = elections(lease.clone());
+        a.refresh().await;
+        assert!(a.is_leader());
+
+        // A leader that can't renew its lease steps down, and nobody leads while the lease is
+        // unreachable.
+        lease.down.store(true, Ordering::SeqCst);
+        clock.advance(a.refresh_interval());
+        assert_eq!(a.refresh().await, Some(LeadershipChange::SteppedDown));
+        assert_eq!(b.refresh().await, None);
+        assert!(!a.is_leader() && !b.is_leader());
+
+        // Once reachable again, the previous leader renews its lease if it hasn't expired.
+        lease.down.store(false, Ordering::SeqCst);
+        assert_eq!(b.refresh().await, None);
+        assert_eq!(a.refresh().await, Some(LeadershipChange::Elected));
+    }
+}
@@ -1,10 +1,139 @@
+//! The TendermintX circuits and operator.
+//!
+//! Without default features, `--features input` builds only the Tendermint chain fetching and
+//! circuit input generation (`input::InputDataFetcher` and the modules it needs), without the
+//! Ethereum, Succinct platform and operator dependencies of the `operator` feature.
+
 #![allow(clippy::needless_range_loop)]
 #![allow(clippy::too_many_arguments)]
 
+#[cfg(feature = "operator")]
+pub mod abi;
+#[cfg(feature = "operator")]
+pub mod alert;
+#[cfg(feature = "api")]
+pub mod api;
+#[cfg(feature = "operator")]
+pub mod artifact;
+#[cfg(feature = "operator")]
+pub mod attestation;
+#[cfg(feature = "operator")]
+pub mod audit;
+#[cfg(feature = "operator")]
+pub mod backend;
+#[cfg(feature = "operator")]
+pub mod backfill;
+#[cfg(feature = "operator")]
+pub mod balance;
+#[cfg(feature = "operator")]
 pub mod builder;
+#[cfg(feature = "operator")]
+pub mod catchup;
+#[cfg(feature = "operator")]
+pub mod chainspec;
+#[cfg(feature = "operator")]
 pub mod config;
 pub mod consts;
+#[cfg(feature = "operator")]
+pub mod contract;
+#[cfg(feature = "operator")]
+pub mod control;
+#[cfg(feature = "operator")]
+pub mod correlation;
+#[cfg(feature = "operator")]
+pub mod dashboard;
+#[cfg(feature = "operator")]
+pub mod drain;
+#[cfg(feature = "operator")]
+pub mod encoding;
+pub mod endpoint;
+#[cfg(feature = "operator")]
+pub mod export;
+#[cfg(feature = "operator")]
+pub mod fallback;
+#[cfg(any(test, feature = "testing"))]
+pub mod fault;
+#[cfg(feature = "operator")]
+pub mod field_proof;
+#[cfg(feature = "operator")]
+pub mod gate;
+#[cfg(feature = "operator")]
+pub mod golden;
+#[cfg(feature = "operator")]
+pub mod groth16;
+#[cfg(feature = "grpc")]
+pub mod grpc;
+#[cfg(feature = "operator")]
+pub mod health;
+#[cfg(feature = "operator")]
+pub mod heartbeat;
+#[cfg(feature = "ibc")]
+pub mod ibc;
 pub mod input;
+#[cfg(feature = "operator")]
+pub mod labels;
+#[cfg(feature = "operator")]
+pub mod lag;
+#[cfg(feature = "operator")]
+pub mod landing;
+#[cfg(feature = "operator")]
+pub mod leader;
+#[cfg(feature = "operator")]
+pub mod logging;
+#[cfg(feature = "operator")]
+pub mod metrics;
+#[cfg(feature = "operator")]
+pub mod migrate;
+#[cfg(feature = "operator")]
+pub mod operator;
+#[cfg(feature = "otel")]
+pub mod otel;
+#[cfg(feature = "operator")]
+pub mod pagerduty;
+#[cfg(feature = "operator")]
+pub mod platform;
+#[cfg(feature = "operator")]
+pub mod poller;
+#[cfg(feature = "operator")]
+pub mod proof;
+#[cfg(feature = "operator")]
+pub mod registry;
+#[cfg(feature = "operator")]
+pub mod repair;
+#[cfg(feature = "operator")]
+pub mod replay;
+pub mod reporting;
+#[cfg(feature = "operator")]
+pub mod retry;
+#[cfg(feature = "operator")]
+pub mod schedule;
+pub mod selector;
+#[cfg(feature = "operator")]
+pub mod signer;
+#[cfg(feature = "operator")]
+pub mod sink;
+#[cfg(feature = "operator")]
 pub mod skip;
+pub mod snapshot;
+#[cfg(feature = "operator")]
+pub mod staleness;
+#[cfg(feature = "operator")]
 pub mod step;
+#[cfg(feature = "operator")]
+pub mod store;
+#[cfg(feature = "operator")]
+pub mod summary;
+#[cfg(feature = "operator")]
+pub mod target;
+#[cfg(any(test, feature = "testing"))]
+pub mod testing;
+#[cfg(feature = "operator")]
+pub mod trusted;
+pub mod types;
+#[cfg(feature = "operator")]
+pub mod upgrade;
 pub mod variables;
+#[cfg(feature = "operator")]
+pub mod webhook;
+#[cfg(feature = "operator")]
+pub mod wire;
@@ -0,0 +1,243 @@
+//! Logging for the operator.
+//!
+//! Events are emitted with `tracing`, and the records of the `log` macros used across the crate
+//! are forwarded to the same subscriber. `RUST_LOG` takes the env-filter syntax, e.g.
+//! `info,tendermintx::backend=debug`, and `LOG_FORMAT=json` writes one JSON object per line with
+//! the fields of the enclosing spans, so a request's events carry its chain, blocks and ID, and
+//! the correlation ID of the attempt it was made for. With
+//! the `otel` feature, the spans are also exported over OTLP (see `otel`).
+
+use std::future::Future;
+use std::str::FromStr;
+use std::{env, fmt};
+
+use anyhow::{anyhow, Result};
+use tracing::{field, info, info_span, warn, Instrument, Span, Subscriber};
+use tracing_subscriber::fmt::MakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Layer};
+
+use crate::backend::{ProofRequest, RequestKind};
+use crate::correlation::CorrelationId;
+
+/// The filter used when `RUST_LOG` is unset.
+pub const DEFAULT_FILTER: &str = "info";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable lines.
+    #[default]
+    Text,
+    /// One JSON object per line.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(anyhow!("invalid log format {:?}, expected text or json", s)),
+        }
+    }
+}
+
+impl fmt::Display for LogFormat {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LogFormat::Text => write!(f, "text"),
+            LogFormat::Json => write!(f, "json"),
+        }
+    }
+}
+
+/// The filter configured by `RUST_LOG`, or `DEFAULT_FILTER` if it is unset.
+pub fn env_filter() -> Result<EnvFilter> {
+    match env::var("RUST_LOG") {
+        Ok(directives) if !directives.is_empty() => EnvFilter::try_new(&directives)
+            .map_err(|e| anyhow!("invalid RUST_LOG {:?}: {}", directives, e)),
+        _ => Ok(EnvFilter::new(DEFAULT_FILTER)),
+    }
+}
+
+/// A layer writing JSON lines to `writer`.
+fn json_layer<S, W>(writer: W) -> impl Layer<S> + Send + Sync
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    tracing_subscriber::fmt::layer()
+        .json()
+        .with_current_span(true)
+        .with_span_list(true)
+        .with_writer(writer)
+}
+
+/// A subscriber writing JSON lines to `writer`.
+pub fn json_subscriber<W>(filter: EnvFilter, writer: W) -> impl Subscriber + Send + Sync
+where
+    W: for<'w> MakeWriter<'w> + Send + Sync + 'static,
+{
+    tracing_subscriber::registry()
+        .with(json_layer(writer))
+        .with(filter)
+}
+
+/// Install the global subscriber, writing to stdout in `format` and, with the `otel` feature,
+/// exporting spans to the configured OTLP endpoint.
+pub fn init(format: LogFormat) -> Result<()> {
+    let filter = env_filter()?;
+    let output = match format {
+        LogFormat::Text => tracing_subscriber::fmt::layer().boxed(),
+        LogFormat::Json => json_layer(std::io::stdout).boxed(),
+    };
+    let subscriber = tracing_subscriber::registry().with(output).with(filter);
+    #[cfg(feature = "otel")]
+    let subscriber = subscriber.with(crate::otel::layer()?);
+    subscriber
+        .try_init()
+        .map_err(|e| anyhow!("could not initialize logging: {}", e))?;
+    #[cfg(not(feature = "otel"))]
+    if env::var_os("OTEL_EXPORTER_OTLP_ENDPOINT").is_some_and(|endpoint| !endpoint.is_empty()) {
+        warn!("OTEL_EXPORTER_OTLP_ENDPOINT is set, but this build has no otel feature");
+    }
+    Ok(())
+}
+
+/// Flush the spans not exported yet. Does nothing without the `otel` feature.
+pub fn shutdown() {
+    #[cfg(feature = "otel")]
+    crate::otel::shutdown();
+}
+
+/// The span of a proof attempt, from planning its range until its requests land or are given up
+/// on. Its requests' spans are within it, so their events carry the attempt's correlation ID.
+pub fn attempt_span(correlation_id: CorrelationId) -> Span {
+    info_span!("attempt", %correlation_id)
+}
+
+/// The span of the submission of a `kind` request. The request ID is recorded once the backend
+/// accepts it.
+pub fn request_span(kind: RequestKind, request: &ProofRequest<'_>) -> Span {
+    let span = info_span!(
+        "proof_request",
+        %kind,
+        chain_id = request.target.chain_id,
+        address = %request.target.address,
+        trusted_block = request.trusted_block,
+        target_block = request.target_block,
+        labels = %request.target.labels,
+        correlation_id = field::Empty,
+        request_id = field::Empty,
+    );
+    if let Some(correlation_id) = request.correlation_id {
+        span.record("correlation_id", field::display(correlation_id));
+    }
+    span
+}
+
+/// Run the submission `submit` of `request` in its span, and log its outcome there.
+pub async fn submit_in_span<F>(
+    kind: RequestKind,
+    request: &ProofRequest<'_>,
+    submit: F,
+) -> Result<String>
+where
+    F: Future<Output = Result<String>>,
+{
+    let span = request_span(kind, request);
+    let result = submit.instrument(span.clone()).await;
+    let _entered = span.enter();
+    match &result {
+        Ok(request_id) => {
+            span.record("request_id", request_id.as_str());
+            info!("Request submitted");
+        }
+        Err(e) => warn!(error = %format!("{:#}", e), "Request failed"),
+    }
+    result
+}
+
+/// A writer keeping what is logged to it, to check the JSON lines in tests.
+#[cfg(test)]
+#[derive(Clone, Default)]
+pub(crate) struct Captured(std::sync::Arc<std::sync::Mutex<Vec<u8>>>);
+
+#[cfg(test)]
+impl Captured {
+    /// The JSON lines logged so far.
+    pub(crate) fn lines(&self) -> Vec<serde_json::Value> {
+        let out = String::from_utf8(self.0.lock().unwrap().clone()).unwrap();
+        out.lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+impl std::io::Write for Captured {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::backend::mock::MockBackend;
+    use crate::backend::ProofBackend;
+    use crate::export::RequestInputs;
+    use crate::labels::Labels;
+    use crate::target::RequestTarget;
+    use crate::types::{HeaderHash, Height};
+
+    #[tokio::test]
+    async fn test_json_request_event() {
+        let target = RequestTarget {
+            labels: Labels::parse("operator=ops").unwrap(),
+            ..RequestTarget::for_test(5)
+        };
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = json_subscriber(EnvFilter::new("info"), move || writer.clone());
+        let _default = tracing::subscriber::set_default(subscriber);
+
+        let backend = MockBackend::new();
+        let inputs = RequestInputs::new(Height(100), HeaderHash([0xab; 32]), Height(500)).unwrap();
+        let request = inputs.proof_request(&target);
+        let request_id = submit_in_span(inputs.kind, &request, backend.request_skip(&request))
+            .await
+            .unwrap();
+
+        let lines = captured.lines();
+        let line = lines.last().unwrap();
+        assert_eq!(line["level"], "INFO");
+        assert_eq!(line["fields"]["message"], "Request submitted");
+        assert!(line["timestamp"].is_string());
+        assert_eq!(
+            line["span"],
+            json!({
+                "name": "proof_request",
+                "kind": "skip",
+                "chain_id": 5,
+                "address": "0x1111111111111111111111111111111111111111",
+                "trusted_block": 100,
+                "target_block": 500,
+                "labels": "operator=ops",
+                "request_id": request_id,
+            })
+        );
+        assert_eq!(line["spans"][0]["name"], "proof_request");
+    }
+}
@@ -0,0 +1,1121 @@
+//! Operator metrics in the Prometheus text exposition format.
+//!
+//! `OperatorMetrics` is the registry of the operator's live metrics. It is owned by the operator
+//! rather than global, so the library can be embedded without the HTTP listener of `serve`.
+
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::fmt::Write;
+use std::net::TcpListener;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::{error, info};
+
+use crate::backend::ratelimit::RateLimiter;
+use crate::backend::RequestKind;
+use crate::endpoint::{EndpointHealth, EndpointPool};
+use crate::export::RequestInputs;
+use crate::health::Health;
+use crate::platform::fallback_key_uses;
+use crate::store::{unix_timestamp, ChainStats, RequestStore, Turnaround};
+use crate::target::{RequestTarget, TargetSubmission};
+
+/// The upper bounds in seconds of the turnaround histogram buckets, from a minute to a day.
+pub const TURNAROUND_BUCKETS: &[f64] = &[
+    60.0, 300.0, 600.0, 1200.0, 1800.0, 3600.0, 7200.0, 14400.0, 86400.0,
+];
+
+/// The upper bounds in seconds of the data fetcher latency histogram buckets.
+pub const FETCH_LATENCY_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0];
+
+/// The upper bounds of the request size (`target_block - trusted_block`) histogram buckets.
+pub const REQUEST_SIZE_BUCKETS: &[f64] = &[
+    1.0, 10.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0,
+];
+
+/// The upper bounds in seconds of the histogram buckets of the time submissions wait for the
+/// rate limit. Submissions within the limit wait for none.
+pub const RATE_LIMIT_WAIT_BUCKETS: &[f64] = &[0.0, 1.0, 10.0, 60.0, 300.0, 900.0, 1800.0, 3600.0];
+
+/// How far back the request statistics and turnarounds of the store are exported.
+const STORE_METRICS_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// A histogram with fixed bucket upper bounds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Histogram {
+    buckets: &'static [f64],
+    /// The number of observations in each bucket, not cumulative, with the `+Inf` bucket last.
+    counts: Vec<u64>,
+    sum: f64,
+}
+
+impl Histogram {
+    pub fn new(buckets: &'static [f64]) -> Self {
+        Self {
+            buckets,
+            counts: vec![0; buckets.len() + 1],
+            sum: 0.0,
+        }
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        let bucket = self
+            .buckets
+            .iter()
+            .position(|&bound| value <= bound)
+            .unwrap_or(self.buckets.len());
+        self.counts[bucket] += 1;
+        self.sum += value;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+}
+
+/// Writes metric families in the Prometheus text exposition format.
+#[derive(Debug, Default)]
+pub struct MetricsWriter {
+    /// The name and the output of each family, in the order they were started. A family started
+    /// again is appended to, so that its samples stay together.
+    families: Vec<(String, String)>,
+    current: usize,
+    /// The labels of every sample, before its own.
+    labels: Vec<(String, String)>,
+}
+
+impl MetricsWriter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Start a metric family, or continue it if it was already started. `kind` is the Prometheus
+    /// metric type, e.g. "counter" or "gauge".
+    pub fn family(&mut self, name: &str, kind: &str, help: &str) -> &mut Self {
+        match self.families.iter().position(|(family, _)| family == name) {
+            Some(index) => self.current = index,
+            None => {
+                let header = format!("# HELP {} {}\n# TYPE {} {}\n", name, help, name, kind);
+                self.families.push((name.to_string(), header));
+                self.current = self.families.len() - 1;
+            }
+        }
+        self
+    }
+
+    /// Add `labels` to every sample written from now on, before its own labels, e.g. to tell the
+    /// metrics of several operators apart. Replaces the labels set before.
+    pub fn set_labels(&mut self, labels: &[(&str, &str)]) -> &mut Self {
+        self.labels = labels
+            .iter()
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect();
+        self
+    }
+
+    /// Write a sample of the current family.
+    pub fn sample(&mut self, name: &str, labels: &[(&str, &str)], value: f64) -> &mut Self {
+        let labels = self
+            .labels
+            .iter()
+            .map(|(key, value)| (key.as_str(), value.as_str()))
+            .chain(labels.iter().copied())
+            .map(|(key, value)| format!("{}=\"{}\"", key, escape_label(value)))
+            .collect::<Vec<_>>();
+        if self.families.is_empty() {
+            self.families.push((String::new(), String::new()));
+        }
+        let out = &mut self.families[self.current].1;
+        out.push_str(name);
+        if !labels.is_empty() {
+            write!(out, "{{{}}}", labels.join(",")).unwrap();
+        }
+        writeln!(out, " {}", value).unwrap();
+        self
+    }
+
+    /// Write the samples of a histogram of `values` for the current family with the given bucket
+    /// upper bounds. The `+Inf` bucket is added.
+    pub fn histogram(
+        &mut self,
+        name: &str,
+        labels: &[(&str, &str)],
+        buckets: &'static [f64],
+        values: &[f64],
+    ) -> &mut Self {
+        let mut histogram = Histogram::new(buckets);
+        for &value in values {
+            histogram.observe(value);
+        }
+        self.histogram_samples(name, labels, &histogram)
+    }
+
+    /// Write the samples of `histogram` for the current family.
+    pub fn histogram_samples(
+        &mut self,
+        name: &str,
+        labels: &[(&str, &str)],
+        histogram: &Histogram,
+    ) -> &mut Self {
+        let bucket_name = format!("{}_bucket", name);
+        let bounds = histogram
+            .buckets
+            .iter()
+            .copied()
+            .chain(std::iter::once(f64::INFINITY));
+        let mut cumulative = 0;
+        for (bound, count) in bounds.zip(histogram.counts.iter()) {
+            cumulative += count;
+            let le = if bound.is_infinite() {
+                "+Inf".to_string()
+            } else {
+                bound.to_string()
+            };
+            let mut bucket_labels = labels.to_vec();
+            bucket_labels.push(("le", &le));
+            self.sample(&bucket_name, &bucket_labels, cumulative as f64);
+        }
+        self.sample(&format!("{}_sum", name), labels, histogram.sum);
+        self.sample(&format!("{}_count", name), labels, histogram.count() as f64);
+        self
+    }
+
+    pub fn finish(self) -> String {
+        self.families.into_iter().map(|(_, out)| out).collect()
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Write the per-chain request statistics.
+pub fn write_request_stats(writer: &mut MetricsWriter, stats: &[ChainStats]) {
+    let statuses: [(&str, fn(&ChainStats) -> u64); 6] = [
+        ("submitted", |s| s.submitted),
+        ("relayed", |s| s.relayed),
+        ("delivered", |s| s.delivered),
+        ("failed", |s| s.failed),
+        ("abandoned", |s| s.abandoned),
+        ("pending", |s| s.pending),
+    ];
+    writer.family(
+        "tendermintx_requests",
+        "gauge",
+        "The number of requests by chain and status.",
+    );
+    for chain in stats {
+        let chain_id = chain.chain_id.to_string();
+        for (status, count) in statuses.iter() {
+            writer.sample(
+                "tendermintx_requests",
+                &[("chain_id", &chain_id), ("status", status)],
+                count(chain) as f64,
+            );
+        }
+    }
+
+    writer.family(
+        "tendermintx_request_cost_total",
+        "gauge",
+        "The total cost of the requests with a known cost, by chain.",
+    );
+    for chain in stats {
+        if let Some(cost) = chain.total_cost {
+            writer.sample(
+                "tendermintx_request_cost_total",
+                &[("chain_id", &chain.chain_id.to_string())],
+                cost,
+            );
+        }
+    }
+
+    writer.family(
+        "tendermintx_request_duration_seconds_average",
+        "gauge",
+        "The average time from submission to relaying, by chain.",
+    );
+    for chain in stats {
+        if let Some(duration) = chain.average_duration {
+            writer.sample(
+                "tendermintx_request_duration_seconds_average",
+                &[("chain_id", &chain.chain_id.to_string())],
+                duration.as_secs_f64(),
+            );
+        }
+    }
+}
+
+/// Write histograms of the time from submission to fulfillment and to the header landing
+/// on-chain, per chain.
+pub fn write_turnarounds(writer: &mut MetricsWriter, turnarounds: &[Turnaround]) {
+    let families: [(&str, &str, fn(&Turnaround) -> Option<Duration>); 2] = [
+        (
+            "tendermintx_request_fulfilled_seconds",
+            "The time from submission until the backend reported the request relayed.",
+            |t| t.fulfilled,
+        ),
+        (
+            "tendermintx_request_onchain_seconds",
+            "The time from submission until the HeadUpdate event for the target block.",
+            |t| t.onchain,
+        ),
+    ];
+    for (name, help, latency) in families {
+        let mut by_chain: BTreeMap<u32, Vec<f64>> = BTreeMap::new();
+        for turnaround in turnarounds {
+            if let Some(latency) = latency(turnaround) {
+                by_chain
+                    .entry(turnaround.chain_id)
+                    .or_default()
+                    .push(latency.as_secs_f64());
+            }
+        }
+        writer.family(name, "histogram", help);
+        for (chain_id, values) in by_chain {
+            writer.histogram(
+                name,
+                &[("chain_id", &chain_id.to_string())],
+                TURNAROUND_BUCKETS,
+                &values,
+            );
+        }
+    }
+}
+
+/// The live state of a target.
+#[derive(Debug, Clone, Default)]
+struct TargetState {
+    latest_block: Option<u64>,
+    lag_blocks: Option<u64>,
+    lag_seconds: Option<f64>,
+    /// The time since the contract's latest block last increased.
+    head_age: Option<f64>,
+    consistent: Option<bool>,
+    /// The balance of the relayer account on the target's chain, in native tokens.
+    relayer_balance: Option<f64>,
+    relayer_transactions_remaining: Option<u64>,
+}
+
+#[derive(Debug, Default)]
+struct MetricsState {
+    chain_head: Option<u64>,
+    /// The time since the chain head last advanced.
+    chain_head_age: Option<f64>,
+    /// By (chain ID, contract address).
+    targets: BTreeMap<(String, String), TargetState>,
+    iterations: u64,
+    /// The unix time the last iteration of the run loop completed.
+    last_iteration: Option<u64>,
+    /// By (request kind, outcome).
+    submissions: BTreeMap<(String, &'static str), u64>,
+    retries: u64,
+    /// By request kind and chain ID.
+    request_sizes: BTreeMap<(String, String), Histogram>,
+    /// The step or skip decisions of the run loop, by request kind.
+    decisions: BTreeMap<String, u64>,
+    /// By data fetcher call.
+    fetches: BTreeMap<&'static str, Histogram>,
+    /// The RPC endpoint pools of the Tendermint and Ethereum providers.
+    endpoints: Vec<Arc<EndpointPool>>,
+    /// The limiter of the submissions, if any.
+    rate_limiter: Option<Arc<RateLimiter>>,
+    /// Whether this operator is the leader, if leader election is enabled.
+    leader: Option<bool>,
+    leadership_changes: u64,
+}
+
+/// The registry of the operator's live metrics.
+#[derive(Debug, Default)]
+pub struct OperatorMetrics {
+    state: Mutex<MetricsState>,
+}
+
+impl OperatorMetrics {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn update_target(&self, target: &RequestTarget, update: impl FnOnce(&mut TargetState)) {
+        let key = (target.chain_id.to_string(), target.address.to_string());
+        update(self.state.lock().unwrap().targets.entry(key).or_default());
+    }
+
+    /// Record the latest block of the Tendermint chain.
+    pub fn record_chain_head(&self, block: u64) {
+        self.state.lock().unwrap().chain_head = Some(block);
+    }
+
+    /// Record the latest block of a target's contract, and its lag behind `chain_head`.
+    pub fn record_latest_block(&self, target: &RequestTarget, block: u64, chain_head: u64) {
+        self.update_target(target, |state| {
+            state.latest_block = Some(block);
+            state.lag_blocks = Some(chain_head.saturating_sub(block));
+        });
+    }
+
+    /// Record the time between the header of a target's latest block and the chain head.
+    pub fn record_lag_seconds(&self, target: &RequestTarget, lag: f64) {
+        self.update_target(target, |state| state.lag_seconds = Some(lag));
+    }
+
+    /// Record the time since the chain head last advanced.
+    pub fn record_chain_head_age(&self, age: Duration) {
+        self.state.lock().unwrap().chain_head_age = Some(age.as_secs_f64());
+    }
+
+    /// Record the time since the latest block of a target's contract last increased.
+    pub fn record_head_age(&self, target: &RequestTarget, age: Duration) {
+        self.update_target(target, |state| state.head_age = Some(age.as_secs_f64()));
+    }
+
+    /// Record the outcome of the header consistency check of a target.
+    pub fn record_consistency(&self, target: &RequestTarget, consistent: bool) {
+        self.update_target(target, |state| state.consistent = Some(consistent));
+    }
+
+    /// Record the balance of the relayer account on a target's chain, in native tokens, and the
+    /// number of transactions it can pay for if known.
+    pub fn record_relayer_balance(
+        &self,
+        target: &RequestTarget,
+        balance: f64,
+        transactions_remaining: Option<u64>,
+    ) {
+        self.update_target(target, |state| {
+            state.relayer_balance = Some(balance);
+            state.relayer_transactions_remaining = transactions_remaining;
+        });
+    }
+
+    pub fn record_iteration(&self) {
+        self.state.lock().unwrap().iterations += 1;
+    }
+
+    /// Record that an iteration of the run loop completed at unix time `timestamp`.
+    pub fn record_heartbeat(&self, timestamp: u64) {
+        self.state.lock().unwrap().last_iteration = Some(timestamp);
+    }
+
+    /// Count the accepted and failed submissions of a request to several targets.
+    pub fn record_submissions(&self, kind: RequestKind, submissions: &[TargetSubmission]) {
+        let mut state = self.state.lock().unwrap();
+        for submission in submissions {
+            let outcome = if submission.result.is_ok() {
+                "accepted"
+            } else {
+                "failed"
+            };
+            *state
+                .submissions
+                .entry((kind.to_string(), outcome))
+                .or_default() += 1;
+        }
+    }
+
+    /// Record the distance between the trusted and the target block of each submission of
+    /// `inputs`.
+    pub fn record_request_sizes(&self, inputs: &RequestInputs, submissions: &[TargetSubmission]) {
+        let size = inputs.target_block.blocks_since(inputs.trusted_block) as f64;
+        let mut state = self.state.lock().unwrap();
+        for submission in submissions {
+            let key = (
+                inputs.kind.to_string(),
+                submission.target.chain_id.to_string(),
+            );
+            state
+                .request_sizes
+                .entry(key)
+                .or_insert_with(|| Histogram::new(REQUEST_SIZE_BUCKETS))
+                .observe(size);
+        }
+    }
+
+    /// Count a decision of the run loop to request a step or a skip.
+    pub fn record_decision(&self, kind: RequestKind) {
+        *self
+            .state
+            .lock()
+            .unwrap()
+            .decisions
+            .entry(kind.to_string())
+            .or_default() += 1;
+    }
+
+    /// Record whether this operator is the leader, counting the changes in leadership. Starting
+    /// out as a follower isn't a change.
+    pub fn record_leader(&self, leader: bool) {
+        let mut state = self.state.lock().unwrap();
+        if leader != state.leader.unwrap_or(false) {
+            state.leadership_changes += 1;
+        }
+        state.leader = Some(leader);
+    }
+
+    /// Count a resubmission of a failed request.
+    pub fn record_retry(&self) {
+        self.state.lock().unwrap().retries += 1;
+    }
+
+    /// Record the latency of a data fetcher call.
+    pub fn observe_fetch(&self, call: &'static str, elapsed: Duration) {
+        self.state
+            .lock()
+            .unwrap()
+            .fetches
+            .entry(call)
+            .or_insert_with(|| Histogram::new(FETCH_LATENCY_BUCKETS))
+            .observe(elapsed.as_secs_f64());
+    }
+
+    /// Export the health of the endpoints of `pool`.
+    pub fn register_endpoints(&self, pool: Arc<EndpointPool>) {
+        self.state.lock().unwrap().endpoints.push(pool);
+    }
+
+    /// Export the time submissions wait for `limiter`.
+    pub fn register_rate_limiter(&self, limiter: Arc<RateLimiter>) {
+        self.state.lock().unwrap().rate_limiter = Some(limiter);
+    }
+
+    /// The health of every registered endpoint.
+    pub fn endpoint_health(&self) -> Vec<EndpointHealth> {
+        let state = self.state.lock().unwrap();
+        state
+            .endpoints
+            .iter()
+            .flat_map(|pool| pool.health())
+            .collect()
+    }
+
+    pub fn write(&self, writer: &mut MetricsWriter) {
+        let state = self.state.lock().unwrap();
+
+        writer.family(
+            "tendermintx_chain_head_block",
+            "gauge",
+            "The latest block of the Tendermint chain.",
+        );
+        if let Some(block) = state.chain_head {
+            writer.sample("tendermintx_chain_head_block", &[], block as f64);
+        }
+
+        writer.family(
+            "tendermintx_chain_head_age_seconds",
+            "gauge",
+            "The time since the Tendermint chain head last advanced.",
+        );
+        if let Some(age) = state.chain_head_age {
+            writer.sample("tendermintx_chain_head_age_seconds", &[], age);
+        }
+
+        let gauges: [(&str, &str, fn(&TargetState) -> Option<f64>); 7] = [
+            (
+                "tendermintx_contract_latest_block",
+                "The latest block stored by the contract of each target.",
+                |t| t.latest_block.map(|b| b as f64),
+            ),
+            (
+                "tendermintx_lag_blocks",
+                "The number of blocks the contract of each target is behind the chain head.",
+                |t| t.lag_blocks.map(|b| b as f64),
+            ),
+            (
+                "tendermintx_lag_seconds",
+                "The time between the latest header stored by each target and the chain head.",
+                |t| t.lag_seconds,
+            ),
+            (
+                "tendermintx_contract_head_age_seconds",
+                "The time since the latest block stored by each target last increased.",
+                |t| t.head_age,
+            ),
+            (
+                "tendermintx_consistency_check",
+                "Whether the header stored by each target matches the chain (1) or not (0).",
+                |t| t.consistent.map(|c| if c { 1.0 } else { 0.0 }),
+            ),
+            (
+                "tendermintx_relayer_balance",
+                "The balance of the relayer account on the chain of each target, in native tokens.",
+                |t| t.relayer_balance,
+            ),
+            (
+                "tendermintx_relayer_transactions_remaining",
+                "The estimated number of transactions the relayer balance of each target pays for.",
+                |t| t.relayer_transactions_remaining.map(|n| n as f64),
+            ),
+        ];
+        for (name, help, value) in gauges {
+            writer.family(name, "gauge", help);
+            for ((chain_id, address), target) in state.targets.iter() {
+                if let Some(value) = value(target) {
+                    writer.sample(name, &[("chain_id", chain_id), ("address", address)], value);
+                }
+            }
+        }
+
+        writer
+            .family(
+                "tendermintx_iterations_total",
+                "counter",
+                "The number of iterations of the run loop.",
+            )
+            .sample("tendermintx_iterations_total", &[], state.iterations as f64);
+
+        writer.family(
+            "tendermintx_last_iteration_timestamp",
+            "gauge",
+            "The unix time the last iteration of the run loop completed.",
+        );
+        if let Some(timestamp) = state.last_iteration {
+            writer.sample(
+                "tendermintx_last_iteration_timestamp",
+                &[],
+                timestamp as f64,
+            );
+        }
+
+        writer.family(
+            "tendermintx_submissions_total",
+            "counter",
+            "The number of submissions by request kind and outcome.",
+        );
+        for ((kind, outcome), count) in state.submissions.iter() {
+            writer.sample(
+                "tendermintx_submissions_total",
+                &[("kind", kind), ("outcome", outcome)],
+                *count as f64,
+            );
+        }
+
+        writer
+            .family(
+                "tendermintx_retries_total",
+                "counter",
+                "The number of resubmissions of failed requests.",
+            )
+            .sample("tendermintx_retries_total", &[], state.retries as f64);
+
+        writer
+            .family(
+                "tendermintx_platform_fallback_key_uses_total",
+                "counter",
+                "The number of platform calls that only succeeded with the fallback API key, \
+                 each a sign that the primary key needs rotating.",
+            )
+            .sample(
+                "tendermintx_platform_fallback_key_uses_total",
+                &[],
+                fallback_key_uses() as f64,
+            );
+
+        writer.family(
+            "tendermintx_leader",
+            "gauge",
+            "Whether this operator is the leader (1) or a follower (0), with leader election.",
+        );
+        if let Some(leader) = state.leader {
+            writer.sample("tendermintx_leader", &[], if leader { 1.0 } else { 0.0 });
+        }
+        writer
+            .family(
+                "tendermintx_leadership_changes_total",
+                "counter",
+                "The number of times this operator was elected or stepped down as the leader.",
+            )
+            .sample(
+                "tendermintx_leadership_changes_total",
+                &[],
+                state.leadership_changes as f64,
+            );
+
+        writer.family(
+            "tendermintx_request_size_blocks",
+            "histogram",
+            "The distance between the trusted and the target block of the submitted requests.",
+        );
+        for ((kind, chain_id), histogram) in state.request_sizes.iter() {
+            writer.histogram_samples(
+                "tendermintx_request_size_blocks",
+                &[("kind", kind), ("chain_id", chain_id)],
+                histogram,
+            );
+        }
+
+        writer.family(
+            "tendermintx_request_decisions_total",
+            "counter",
+            "The number of times the run loop decided to request a step or a skip.",
+        );
+        for (kind, count) in state.decisions.iter() {
+            writer.sample(
+                "tendermintx_request_decisions_total",
+                &[("kind", kind)],
+                *count as f64,
+            );
+        }
+
+        writer.family(
+            "tendermintx_fetch_duration_seconds",
+            "histogram",
+            "The latency of the Tendermint data fetcher calls.",
+        );
+        for (call, histogram) in state.fetches.iter() {
+            writer.histogram_samples(
+                "tendermintx_fetch_duration_seconds",
+                &[("call", call)],
+                histogram,
+            );
+        }
+
+        if let Some(limiter) = state.rate_limiter.as_ref() {
+            write_rate_limit_waits(writer, limiter);
+        }
+
+        let endpoints = state
+            .endpoints
+            .iter()
+            .flat_map(|pool| pool.health())
+            .collect::<Vec<_>>();
+        let families: [(&str, &str, &str, fn(&EndpointHealth) -> Option<f64>); 4] = [
+            (
+                "tendermintx_rpc_requests_total",
+                "counter",
+                "The number of calls to each RPC endpoint.",
+                |e| Some(e.requests as f64),
+            ),
+            (
+                "tendermintx_rpc_errors_total",
+                "counter",
+                "The number of failed calls to each RPC endpoint.",
+                |e| Some(e.errors as f64),
+            ),
+            (
+                "tendermintx_rpc_latency_p95_seconds",
+                "gauge",
+                "The 95th percentile latency of the recent calls to each RPC endpoint.",
+                |e| e.p95.map(|p95| p95.as_secs_f64()),
+            ),
+            (
+                "tendermintx_rpc_endpoint_demoted",
+                "gauge",
+                "Whether each RPC endpoint is demoted behind the healthy ones (1) or not (0).",
+                |e| Some(if e.demoted { 1.0 } else { 0.0 }),
+            ),
+        ];
+        for (name, kind, help, value) in families {
+            writer.family(name, kind, help);
+            for endpoint in endpoints.iter() {
+                if let Some(value) = value(endpoint) {
+                    writer.sample(
+                        name,
+                        &[("kind", endpoint.kind), ("host", &endpoint.host)],
+                        value,
+                    );
+                }
+            }
+        }
+    }
+
+    /// The live metrics, and the request statistics and turnarounds of the last day in `store`.
+    pub fn render(&self, store: Option<&RequestStore>) -> Result<String> {
+        let mut writer = MetricsWriter::new();
+        self.write(&mut writer);
+        if let Some(store) = store {
+            write_store_metrics(&mut writer, store)?;
+        }
+        Ok(writer.finish())
+    }
+}
+
+/// Write the histogram of the time submissions waited for `limiter`.
+pub fn write_rate_limit_waits(writer: &mut MetricsWriter, limiter: &RateLimiter) {
+    writer
+        .family(
+            "tendermintx_rate_limit_wait_seconds",
+            "histogram",
+            "The time each submission waited for the submission rate limit.",
+        )
+        .histogram_samples("tendermintx_rate_limit_wait_seconds", &[], &limiter.waits());
+}
+
+/// Write the request statistics and turnarounds of the last day in `store`.
+pub fn write_store_metrics(writer: &mut MetricsWriter, store: &RequestStore) -> Result<()> {
+    let since = unix_timestamp().saturating_sub(STORE_METRICS_WINDOW.as_secs());
+    write_request_stats(writer, &store.stats(since)?);
+    write_turnarounds(writer, &store.turnarounds(since)?);
+    if let Some(sink) = store.sink() {
+        writer
+            .family(
+                "tendermintx_sink_dropped_writes_total",
+                "counter",
+                "The number of writes to the sink mirroring the request store that were dropped.",
+            )
+            .sample(
+                "tendermintx_sink_dropped_writes_total",
+                &[("sink", sink.name())],
+                sink.dropped() as f64,
+            );
+    }
+    Ok(())
+}
+
+/// What the metrics listener serves: the metrics of an operator, or those of every operator of a
+/// registry.
+pub trait MetricsSource: Send + Sync {
+    fn render(&self, store: Option<&RequestStore>) -> Result<String>;
+}
+
+impl MetricsSource for OperatorMetrics {
+    fn render(&self, store: Option<&RequestStore>) -> Result<String> {
+        OperatorMetrics::render(self, store)
+    }
+}
+
+async fn respond(
+    metrics: &dyn MetricsSource,
+    health: &Health,
+    store: Option<&RequestStore>,
+    request: Request<Body>,
+) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    if request.method() != Method::GET {
+        *response.status_mut() = StatusCode::NOT_FOUND;
+        return response;
+    }
+    match request.uri().path() {
+        "/metrics" => {}
+        "/healthz" => return health_response(health.liveness()),
+        "/readyz" => return health_response(health.readiness().await),
+        _ => {
+            *response.status_mut() = StatusCode::NOT_FOUND;
+            return response;
+        }
+    }
+    match metrics.render(store) {
+        Ok(body) => *response.body_mut() = Body::from(body),
+        Err(e) => {
+            error!("Failed to render metrics: {:#}", e);
+            *response.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+        }
+    }
+    response
+}
+
+/// A 200, or a 503 if the check failed, with its JSON detail.
+fn health_response((healthy, detail): (bool, serde_json::Value)) -> Response<Body> {
+    let mut response = Response::new(Body::from(detail.to_string()));
+    if !healthy {
+        *response.status_mut() = StatusCode::SERVICE_UNAVAILABLE;
+    }
+    response.headers_mut().insert(
+        hyper::header::CONTENT_TYPE,
+        hyper::header::HeaderValue::from_static("application/json"),
+    );
+    response
+}
+
+/// Serve the metrics at `/metrics`, and the liveness and readiness of the operator at `/healthz`
+/// and `/readyz`, on `listener` until it fails.
+pub async fn serve(
+    metrics: Arc<dyn MetricsSource>,
+    health: Arc<Health>,
+    store: Option<Arc<RequestStore>>,
+    listener: TcpListener,
+) -> Result<()> {
+    let addr = listener.local_addr()?;
+    listener.set_nonblocking(true)?;
+    let make_service = make_service_fn(move |_| {
+        let (metrics, health, store) = (metrics.clone(), health.clone(), store.clone());
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let (metrics, health, store) = (metrics.clone(), health.clone(), store.clone());
+                async move {
+                    let response =
+                        respond(metrics.as_ref(), &health, store.as_deref(), request).await;
+                    Ok::<_, Infallible>(response)
+                }
+            }))
+        }
+    });
+    let server = Server::from_tcp(listener)
+        .with_context(|| format!("failed to start the metrics listener on {}", addr))?;
+    info!("Serving metrics and health checks on http://{}", addr);
+    server.serve(make_service).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::backend::mock::MockBackend;
+    use crate::backend::ProofBackend;
+    use crate::store::tests::new_request;
+    use crate::target::submit_to_targets;
+    use crate::types::{HeaderHash, Height};
+
+    #[test]
+    fn test_write_request_stats() {
+        let stats = vec![ChainStats {
+            chain_id: 5,
+            submitted: 4,
+            relayed: 2,
+            delivered: 0,
+            failed: 1,
+            abandoned: 0,
+            pending: 1,
+            total_cost: Some(4.5),
+            average_cost: Some(2.25),
+            average_duration: Some(Duration::from_secs(200)),
+        }];
+        let mut writer = MetricsWriter::new();
+        write_request_stats(&mut writer, &stats);
+        let out = writer.finish();
+
+        assert!(out.contains("# TYPE tendermintx_requests gauge\n"));
+        assert!(out.contains("tendermintx_requests{chain_id=\"5\",status=\"submitted\"} 4\n"));
+        assert!(out.contains("tendermintx_requests{chain_id=\"5\",status=\"failed\"} 1\n"));
+        assert!(out.contains("tendermintx_request_cost_total{chain_id=\"5\"} 4.5\n"));
+        assert!(out.contains("tendermintx_request_duration_seconds_average{chain_id=\"5\"} 200\n"));
+    }
+
+    #[test]
+    fn test_write_turnarounds() {
+        let turnaround = |fulfilled: u64, onchain: Option<u64>| Turnaround {
+            chain_id: 5,
+            request_id: "req".to_string(),
+            target_block: 1,
+            submitted_at: 0,
+            fulfilled: Some(Duration::from_secs(fulfilled)),
+            onchain: onchain.map(Duration::from_secs),
+        };
+        let mut writer = MetricsWriter::new();
+        write_turnarounds(
+            &mut writer,
+            &[turnaround(30, Some(90)), turnaround(400, None)],
+        );
+        let out = writer.finish();
+
+        let fulfilled = "tendermintx_request_fulfilled_seconds";
+        assert!(out.contains(&format!("# TYPE {} histogram\n", fulfilled)));
+        assert!(out.contains(&format!(
+            "{}_bucket{{chain_id=\"5\",le=\"60\"}} 1\n",
+            fulfilled
+        )));
+        assert!(out.contains(&format!(
+            "{}_bucket{{chain_id=\"5\",le=\"600\"}} 2\n",
+            fulfilled
+        )));
+        assert!(out.contains(&format!(
+            "{}_bucket{{chain_id=\"5\",le=\"+Inf\"}} 2\n",
+            fulfilled
+        )));
+        assert!(out.contains(&format!("{}_sum{{chain_id=\"5\"}} 430\n", fulfilled)));
+        assert!(out.contains("tendermintx_request_onchain_seconds_count{chain_id=\"5\"} 1\n"));
+    }
+
+    #[tokio::test]
+    async fn test_serve_metrics() {
+        let target = RequestTarget::for_test(5);
+        let dir = tempfile::tempdir().unwrap();
+        let store = Arc::new(RequestStore::open(dir.path().join("requests.db")).unwrap());
+        let backend = MockBackend::new();
+        let metrics = Arc::new(OperatorMetrics::new());
+
+        // One iteration: read the heads, check consistency and submit a skip.
+        metrics.observe_fetch("get_latest_signed_header", Duration::from_millis(200));
+        metrics.record_chain_head(1500);
+        metrics.record_latest_block(&target, 1000, 1500);
+        metrics.record_lag_seconds(&target, 3000.0);
+        metrics.record_chain_head_age(Duration::from_secs(6));
+        metrics.record_head_age(&target, Duration::from_secs(1200));
+        metrics.record_consistency(&target, true);
+        metrics.record_relayer_balance(&target, 0.25, Some(100));
+        let inputs =
+            RequestInputs::new(Height(1000), HeaderHash([0xab; 32]), Height(1500)).unwrap();
+        let submissions = submit_to_targets([&target], |target| {
+            let request = inputs.proof_request(target);
+            let backend = &backend;
+            async move { backend.request_skip(&request).await }
+        })
+        .await;
+        metrics.record_submissions(inputs.kind, &submissions);
+        store
+            .insert(&new_request(
+                submissions[0].result.as_ref().unwrap(),
+                1000,
+                1500,
+            ))
+            .unwrap();
+        metrics.record_iteration();
+        let pool = Arc::new(EndpointPool::new(
+            "tendermint",
+            vec!["http://127.0.0.1:26657/key".to_string()],
+        ));
+        let failed = pool.call(|_, _| async { Err::<(), _>("down") }, |_| true);
+        failed.await.unwrap_err();
+        metrics.register_endpoints(pool);
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let health = Arc::new(Health::new(Duration::from_secs(60), Duration::from_secs(1)));
+        tokio::spawn(serve(metrics, health, Some(store), listener));
+        let body = reqwest::get(format!("http://{}/metrics", addr))
+            .await
+            .unwrap()
+            .text()
+            .await
+            .unwrap();
+
+        let series = "{chain_id=\"5\",address=\"0x1111111111111111111111111111111111111111\"}";
+        for expected in [
+            "tendermintx_chain_head_block 1500\n".to_string(),
+            format!("tendermintx_contract_latest_block{} 1000\n", series),
+            format!("tendermintx_lag_blocks{} 500\n", series),
+            format!("tendermintx_lag_seconds{} 3000\n", series),
+            "tendermintx_chain_head_age_seconds 6\n".to_string(),
+            format!("tendermintx_contract_head_age_seconds{} 1200\n", series),
+            format!("tendermintx_consistency_check{} 1\n", series),
+            format!("tendermintx_relayer_balance{} 0.25\n", series),
+            format!("tendermintx_relayer_transactions_remaining{} 100\n", series),
+            "tendermintx_iterations_total 1\n".to_string(),
+            "tendermintx_submissions_total{kind=\"skip\",outcome=\"accepted\"} 1\n".to_string(),
+            "tendermintx_retries_total 0\n".to_string(),
+            "# TYPE tendermintx_platform_fallback_key_uses_total counter\n".to_string(),
+            "tendermintx_fetch_duration_seconds_bucket{call=\"get_latest_signed_header\",le=\"0.25\"} 1\n"
+                .to_string(),
+            "tendermintx_requests{chain_id=\"5\",status=\"pending\"} 1\n".to_string(),
+            "tendermintx_rpc_requests_total{kind=\"tendermint\",host=\"127.0.0.1:26657\"} 1\n"
+                .to_string(),
+            "tendermintx_rpc_errors_total{kind=\"tendermint\",host=\"127.0.0.1:26657\"} 1\n"
+                .to_string(),
+            "tendermintx_rpc_endpoint_demoted{kind=\"tendermint\",host=\"127.0.0.1:26657\"} 0\n"
+                .to_string(),
+            "# TYPE tendermintx_request_fulfilled_seconds histogram\n".to_string(),
+        ] {
+            assert!(body.contains(&expected), "missing {:?} in:\n{}", expected, body);
+        }
+
+        let status = reqwest::get(format!("http://{}/other", addr))
+            .await
+            .unwrap()
+            .status();
+        assert_eq!(status, reqwest::StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_record_request_sizes() {
+        let target = RequestTarget::for_test;
+        let (chain_5, chain_10) = (target(5), target(10));
+        let metrics = OperatorMetrics::new();
+
+        // Steps and skips of various sizes, to one or both targets, accepted or not.
+        for (trusted_block, target_block, both) in [
+            (1000, 1001, true),
+            (1001, 1002, false),
+            (1002, 1050, true),
+            (1050, 1300, false),
+            (1300, 4000, true),
+            (4000, 20000, false),
+        ] {
+            let inputs = RequestInputs::new(
+                Height(trusted_block),
+                HeaderHash([0xab; 32]),
+                Height(target_block),
+            )
+            .unwrap();
+            metrics.record_decision(inputs.kind);
+            let mut submissions = vec![TargetSubmission {
+                target: &chain_5,
+                result: Ok("req".to_string()),
+            }];
+            if both {
+                submissions.push(TargetSubmission {
+                    target: &chain_10,
+                    result: Err(anyhow::anyhow!("rate limited")),
+                });
+            }
+            metrics.record_request_sizes(&inputs, &submissions);
+        }
+
+        let mut writer = MetricsWriter::new();
+        metrics.write(&mut writer);
+        let out = writer.finish();
+        let bucket = |kind: &str, chain_id: u32, le: &str, count: u64| {
+            format!(
+                "tendermintx_request_size_blocks_bucket{{kind=\"{}\",chain_id=\"{}\",le=\"{}\"}} \
+                 {}\n",
+                kind, chain_id, le, count
+            )
+        };
+        for expected in [
+            bucket("step", 5, "1", 2),
+            bucket("step", 10, "1", 1),
+            bucket("skip", 5, "50", 1),
+            bucket("skip", 5, "250", 2),
+            bucket("skip", 5, "2500", 2),
+            bucket("skip", 5, "5000", 3),
+            bucket("skip", 5, "10000", 3),
+            bucket("skip", 5, "+Inf", 4),
+            bucket("skip", 10, "100", 1),
+            bucket("skip", 10, "+Inf", 2),
+            "tendermintx_request_size_blocks_sum{kind=\"skip\",chain_id=\"5\"} 18998\n".to_string(),
+            "tendermintx_request_decisions_total{kind=\"skip\"} 4\n".to_string(),
+            "tendermintx_request_decisions_total{kind=\"step\"} 2\n".to_string(),
+        ] {
+            assert!(
+                out.contains(&expected),
+                "missing {:?} in:\n{}",
+                expected,
+                out
+            );
+        }
+    }
+
+    #[test]
+    fn test_labels_and_continued_families() {
+        let mut writer = MetricsWriter::new();
+        for (chain, value) in [("mocha-4", 1.0), ("osmosis-1", 2.0)] {
+            writer.set_labels(&[("source_chain", chain)]);
+            writer.family("a", "gauge", "A.").sample("a", &[], value);
+            writer
+                .family("b", "gauge", "B.")
+                .sample("b", &[("kind", "step")], value);
+        }
+        writer
+            .set_labels(&[])
+            .family("a", "gauge", "A.")
+            .sample("a", &[], 3.0);
+        assert_eq!(
+            writer.finish(),
+            "# HELP a A.\n# TYPE a gauge\n\
+             a{source_chain=\"mocha-4\"} 1\n\
+             a{source_chain=\"osmosis-1\"} 2\n\
+             a 3\n\
+             # HELP b B.\n# TYPE b gauge\n\
+             b{source_chain=\"mocha-4\",kind=\"step\"} 1\n\
+             b{source_chain=\"osmosis-1\",kind=\"step\"} 2\n"
+        );
+    }
+
+    #[test]
+    fn test_escape_label() {
+        let mut writer = MetricsWriter::new();
+        writer.sample("m", &[("path", "a\"b\\c")], 1.0);
+        assert_eq!(writer.finish(), "m{path=\"a\\\"b\\\\c\"} 1\n");
+    }
+}
@@ -0,0 +1,244 @@
+//! Seeding a new `TendermintX` deployment from the state of the one it replaces.
+//!
+//! When the contract of a target is rotated, the new deployment starts from a recent trusted
+//! header of the old one rather than from genesis. `plan` reads the latest block of the old
+//! contract and the header hash it stores for it, and refuses to go on unless that is the
+//! chain's header. `migrate` then stores it in the new contract with `setGenesisHeader`, through
+//! a client that signs, and `check_deployment` checks the new contract against the chain as the
+//! operator does every iteration. A deployment that already stores the state is not seeded again,
+//! so a migration interrupted before, or seeded by hand from `seed_calldata`, can be run again.
+
+use std::fmt;
+
+use alloy_primitives::{Address, Bytes};
+use alloy_sol_types::SolCall;
+use anyhow::{anyhow, ensure, Context, Result};
+use ethers::providers::Middleware;
+
+use crate::contract::bindings::setGenesisHeaderCall;
+use crate::contract::TendermintXContract;
+use crate::input::InputDataFetcher;
+use crate::types::{HeaderHash, Height};
+
+/// The latest block of a contract and the header hash it stores for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContractState {
+    pub block: Height,
+    pub header_hash: HeaderHash,
+}
+
+impl fmt::Display for ContractState {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "block {} with header {}", self.block, self.header_hash)
+    }
+}
+
+/// What a migration does: seed the new contract with the state of the old one, unless it
+/// already stores it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MigrationPlan {
+    pub old: Address,
+    pub new: Address,
+    /// The state of the old contract, verified against the chain.
+    pub state: ContractState,
+    /// Whether the new contract already stores `state` as its latest block.
+    pub seeded: bool,
+}
+
+impl MigrationPlan {
+    /// The calldata of the `setGenesisHeader` call seeding the new contract.
+    pub fn seed_calldata(&self) -> Bytes {
+        seed_calldata(&self.state)
+    }
+}
+
+/// The outcome of a migration.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migration {
+    pub plan: MigrationPlan,
+    /// The transaction that seeded the new contract, if it wasn't already.
+    pub tx_hash: Option<[u8; 32]>,
+}
+
+/// The calldata of the `setGenesisHeader` call storing `state`.
+pub fn seed_calldata(state: &ContractState) -> Bytes {
+    let call = setGenesisHeaderCall {
+        _height: state.block.value(),
+        _header: state.header_hash.to_bytes().into(),
+    };
+    call.abi_encode().into()
+}
+
+/// The latest block of `contract` and the header hash it stores for it, checked against the
+/// chain.
+pub async fn verified_state<M: Middleware + 'static>(
+    contract: &TendermintXContract<M>,
+    fetcher: &InputDataFetcher,
+) -> Result<ContractState> {
+    let block = contract.latest_block().await?;
+    ensure!(block != 0, "{} stores no block", contract.address());
+    let header_hash = contract
+        .header_hash(block)
+        .await?
+        .map(HeaderHash)
+        .ok_or_else(|| {
+            anyhow!(
+                "{} stores no header for its latest block {}",
+                contract.address(),
+                block
+            )
+        })?;
+    let block = Height(block);
+    let chain_hash = fetcher.compute_header_hash(block).await?;
+    ensure!(
+        header_hash == chain_hash,
+        "{} stores the header {} for block {}, but the chain's header is {}",
+        contract.address(),
+        header_hash,
+        block,
+        chain_hash
+    );
+    Ok(ContractState { block, header_hash })
+}
+
+/// Plan the migration from `old` to `new`. Fails if the state of `old` isn't the chain's, or if
+/// `new` was already initialized with another state.
+pub async fn plan<M: Middleware + 'static, N: Middleware + 'static>(
+    old: &TendermintXContract<M>,
+    new: &TendermintXContract<N>,
+    fetcher: &InputDataFetcher,
+) -> Result<MigrationPlan> {
+    let state = verified_state(old, fetcher)
+        .await
+        .with_context(|| format!("refusing to migrate from {}", old.address()))?;
+    let latest = new.latest_block().await?;
+    let stored = new.header_hash(state.block.value()).await?;
+    let seeded = latest == state.block.value() && stored == Some(state.header_hash.to_bytes());
+    ensure!(
+        seeded || latest == 0,
+        "{} is already initialized at block {}",
+        new.address(),
+        latest
+    );
+    Ok(MigrationPlan {
+        old: old.address(),
+        new: new.address(),
+        state,
+        seeded,
+    })
+}
+
+/// Check that `new` stores `state` and that its latest block is the chain's, as the operator
+/// checks its targets.
+pub async fn check_deployment<M: Middleware + 'static>(
+    new: &TendermintXContract<M>,
+    fetcher: &InputDataFetcher,
+    state: &ContractState,
+) -> Result<()> {
+    let stored = new.header_hash(state.block.value()).await?;
+    ensure!(
+        stored == Some(state.header_hash.to_bytes()),
+        "{} does not store the header {} for block {}",
+        new.address(),
+        state.header_hash,
+        state.block
+    );
+    verified_state(new, fetcher)
+        .await
+        .with_context(|| format!("the consistency check of {} failed", new.address()))?;
+    Ok(())
+}
+
+/// Migrate from `old` to `new`, sending the seeding transaction through the signing client of
+/// `new`, and check the new deployment.
+pub async fn migrate<M: Middleware + 'static, N: Middleware + 'static>(
+    old: &TendermintXContract<M>,
+    new: &TendermintXContract<N>,
+    fetcher: &InputDataFetcher,
+) -> Result<Migration> {
+    let plan = plan(old, new, fetcher).await?;
+    let tx_hash = match plan.seeded {
+        true => None,
+        false => Some(
+            new.set_genesis_header(plan.state.block.value(), plan.state.header_hash.to_bytes())
+                .await?,
+        ),
+    };
+    check_deployment(new, fetcher, &plan.state).await?;
+    Ok(Migration { plan, tx_hash })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use ethers::providers::Provider;
+    use ethers::types::Bytes as EthersBytes;
+
+    use super::*;
+    use crate::testing::fixture_fetcher;
+
+    fn word(value: u64) -> EthersBytes {
+        let mut word = [0u8; 32];
+        word[24..].copy_from_slice(&value.to_be_bytes());
+        EthersBytes::from(word.to_vec())
+    }
+
+    #[test]
+    fn test_seed_calldata() {
+        let state = ContractState {
+            block: Height(10000),
+            header_hash: HeaderHash([0xab; 32]),
+        };
+        let calldata = seed_calldata(&state);
+        assert_eq!(&calldata[..4], setGenesisHeaderCall::SELECTOR);
+        let call = setGenesisHeaderCall::abi_decode(&calldata, true).unwrap();
+        assert_eq!(call._height, 10000);
+        assert_eq!(call._header.0, [0xab; 32]);
+        assert_eq!(
+            state.to_string(),
+            format!("block 10000 with header {}", HeaderHash([0xab; 32]))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_verified_state() {
+        let fetcher = fixture_fetcher();
+        let hash = fetcher.compute_header_hash(Height(10000)).await.unwrap();
+        let (provider, mock) = Provider::mocked();
+        let contract = TendermintXContract::new(Address::ZERO, Arc::new(provider));
+
+        // Responses are popped in reverse order of pushing: the latest block is read first.
+        mock.push::<EthersBytes, _>(EthersBytes::from(hash.to_bytes().to_vec()))
+            .unwrap();
+        mock.push::<EthersBytes, _>(word(10000)).unwrap();
+        assert_eq!(
+            verified_state(&contract, &fetcher).await.unwrap(),
+            ContractState {
+                block: Height(10000),
+                header_hash: hash,
+            }
+        );
+
+        mock.push::<EthersBytes, _>(EthersBytes::from(vec![0xab; 32]))
+            .unwrap();
+        mock.push::<EthersBytes, _>(word(10000)).unwrap();
+        let error = verified_state(&contract, &fetcher).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "{} stores the header {} for block 10000, but the chain's header is {}",
+                Address::ZERO,
+                HeaderHash([0xab; 32]),
+                hash
+            )
+        );
+
+        mock.push::<EthersBytes, _>(word(0)).unwrap();
+        let error = verified_state(&contract, &fetcher).await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!("{} stores no block", Address::ZERO)
+        );
+    }
+}
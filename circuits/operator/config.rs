@@ -0,0 +1,346 @@
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use alloy_primitives::Address;
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DurationSeconds};
+
+use super::LOOP_DELAY;
+use crate::alert::AlertWebhook;
+use crate::artifact::ExpectedDigests;
+use crate::catchup::{self, CatchUp};
+use crate::chainspec::ChainSpec;
+use crate::fallback::{self, StepFallback};
+use crate::gate::Gating;
+use crate::lag::{LagMonitor, MinLag};
+use crate::pagerduty::PagerDuty;
+use crate::retry::RetryPolicy;
+use crate::schedule::Schedule;
+use crate::selector::{LargestSkip, TargetSelector};
+use crate::staleness::StalenessMonitor;
+use crate::target::RequestTarget;
+use crate::{drain, landing};
+
+/// The settings of an operator. `new` gives the defaults, which a service embedding the operator
+/// changes through the fields; the binary reads them from the environment with `from_env`.
+///
+/// Serializes without its secrets (the alert webhooks, PagerDuty, the callback secret and the API
+/// token) and
+/// without the settings that are objects rather than values (the selector, gating and schedule).
+/// Those, and any missing field, deserialize to the defaults of `new`.
+#[serde_as]
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct TendermintXConfig {
+    #[serde(rename = "targets")]
+    pub targets: Vec<RequestTarget>,
+    /// The relayer account to monitor the balance of on each target chain, if any.
+    #[serde(rename = "relayer")]
+    pub relayer: Option<RelayerConfig>,
+    /// The SQLite database submitted requests are recorded in, if any.
+    #[serde(rename = "store_path")]
+    pub store_path: Option<PathBuf>,
+    /// The Postgres database the request store and the head updates are mirrored to, if any.
+    /// Requires `store_path`. Not serialized, as it holds the password.
+    #[serde(skip)]
+    pub postgres_dsn: Option<String>,
+    /// The append-only record of submissions and outcomes, if any.
+    #[serde(rename = "audit")]
+    pub audit: Option<AuditConfig>,
+    /// The file holding the ed25519 key every submission is attested with, if any.
+    #[serde(rename = "attestation_key_path")]
+    pub attestation_key_path: Option<PathBuf>,
+    /// The listener for platform callbacks, if any. Requires `store_path`.
+    #[serde(rename = "webhook")]
+    pub webhook: Option<WebhookConfig>,
+    /// The REST API of `tendermintx serve`, if any.
+    #[serde(rename = "api")]
+    pub api: Option<ApiConfig>,
+    /// The gRPC service of `tendermintx serve`, if any.
+    #[serde(rename = "grpc")]
+    pub grpc: Option<ApiConfig>,
+    #[serde(rename = "retry_policy")]
+    pub retry_policy: RetryPolicy,
+    /// The age after which a pending request is abandoned, if any.
+    #[serde(rename = "max_request_age_secs")]
+    #[serde_as(as = "Option<DurationSeconds<u64>>")]
+    pub max_request_age: Option<Duration>,
+    /// The limit on submissions shared by all targets, if any.
+    #[serde(rename = "max_requests_per_hour")]
+    pub max_requests_per_hour: Option<usize>,
+    /// How often the status of the pending requests in the store is refreshed while idle.
+    #[serde(rename = "status_poll_interval_secs")]
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub status_poll_interval: Duration,
+    /// The maximum time to wait for the requests of an iteration to land on-chain, before falling
+    /// back to the loop delay.
+    #[serde(rename = "landing_timeout_secs")]
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub landing_timeout: Duration,
+    #[serde(rename = "landing_poll_interval_secs")]
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub landing_poll_interval: Duration,
+    /// The maximum time to drain the in-flight requests on shutdown.
+    #[serde(rename = "drain_timeout_secs")]
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub drain_timeout: Duration,
+    /// Where the metrics and health checks are served, if anywhere.
+    #[serde(rename = "metrics_addr")]
+    pub metrics_addr: Option<SocketAddr>,
+    /// The age of the last iteration after which the run loop counts as stalled.
+    #[serde(rename = "max_iteration_age_secs")]
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub max_iteration_age: Duration,
+    /// The timeout of each readiness check.
+    #[serde(rename = "readiness_timeout_secs")]
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub readiness_timeout: Duration,
+    #[serde(skip)]
+    pub alert_webhooks: Vec<AlertWebhook>,
+    /// How long an alert isn't repeated for.
+    #[serde(rename = "alert_dedup_window_secs")]
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub alert_dedup_window: Duration,
+    #[serde(skip)]
+    pub pagerduty: Option<PagerDuty>,
+    /// The consecutive iterations after which failing submissions are alerted on.
+    #[serde(rename = "failure_alert_threshold")]
+    pub failure_alert_threshold: u32,
+    /// Alerts on targets that stay behind, if set.
+    #[serde(rename = "lag_monitor")]
+    pub lag_monitor: Option<LagMonitor>,
+    #[serde(rename = "staleness")]
+    pub staleness: StalenessMonitor,
+    #[serde(rename = "catch_up")]
+    pub catch_up: CatchUp,
+    /// The lag a group of targets must reach before a request is submitted for it.
+    #[serde(rename = "min_lag")]
+    pub min_lag: MinLag,
+    /// Selects the block each request proves.
+    #[serde(skip)]
+    pub selector: Box<dyn TargetSelector>,
+    /// Asked whether each request is needed before submitting it, if set.
+    #[serde(skip)]
+    pub gating: Option<Gating>,
+    /// Requests steps past skips that keep failing, unless disabled.
+    #[serde(rename = "fallback")]
+    pub fallback: Option<StepFallback>,
+    /// Where `tendermintx ctl` commands are served, if anywhere.
+    #[serde(rename = "control_socket")]
+    pub control_socket: Option<PathBuf>,
+    #[serde(skip)]
+    pub schedule: Schedule,
+    /// The file touched after every iteration, if any.
+    #[serde(rename = "heartbeat_file")]
+    pub heartbeat_file: Option<PathBuf>,
+    /// The URL POSTed to after every iteration, if any.
+    #[serde(rename = "heartbeat_url")]
+    pub heartbeat_url: Option<String>,
+    /// The constants of the Tendermint chain, if known. They bound skips further than the
+    /// contract's `skip_max`, hold back unconfirmed blocks and pace the wait for the minimum lag.
+    #[serde(rename = "chain_spec")]
+    pub chain_spec: Option<ChainSpec>,
+    /// The heights of the chain's upgrades: no request proves past one before the contract is at
+    /// it.
+    #[serde(rename = "halt_heights")]
+    pub halt_heights: Vec<u64>,
+    /// Whether the chain's upgrade module is asked for the height of its next upgrade, through
+    /// the Tendermint RPC, every iteration.
+    #[serde(rename = "upgrade_discovery")]
+    pub upgrade_discovery: bool,
+    /// The circuit artifact digest expected for each function ID, checked at startup.
+    #[serde(rename = "circuit_digests")]
+    pub circuit_digests: ExpectedDigests,
+    /// The manifest of the artifacts the digests are compared with, instead of those the
+    /// platform registered, if set.
+    #[serde(rename = "artifact_manifest")]
+    pub artifact_manifest: Option<PathBuf>,
+    /// Whether to run, with a warning, when the artifacts don't match.
+    #[serde(rename = "allow_circuit_mismatch")]
+    pub allow_circuit_mismatch: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RelayerConfig {
+    #[serde(rename = "address")]
+    pub address: Address,
+    /// The balance threshold in native tokens.
+    #[serde(rename = "balance_threshold")]
+    pub balance_threshold: String,
+    #[serde(rename = "gas_per_transaction")]
+    pub gas_per_transaction: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AuditConfig {
+    #[serde(rename = "path")]
+    pub path: PathBuf,
+    /// The size at which the log is rotated.
+    #[serde(rename = "max_bytes")]
+    pub max_bytes: u64,
+    /// The number of rotated files kept.
+    #[serde(rename = "max_files")]
+    pub max_files: usize,
+}
+
+/// Serializes without its secret, which deserializes empty.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct WebhookConfig {
+    #[serde(rename = "addr")]
+    pub addr: SocketAddr,
+    /// The secret callbacks are signed with.
+    #[serde(skip)]
+    pub secret: String,
+}
+
+/// Where an API of `tendermintx serve` listens. Serializes without its token, which deserializes
+/// empty.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ApiConfig {
+    #[serde(rename = "addr")]
+    pub addr: SocketAddr,
+    /// The bearer token every request must carry.
+    #[serde(skip)]
+    pub token: String,
+}
+
+impl TendermintXConfig {
+    /// The default settings for `targets`: no request store, audit log, listeners or alerts, and
+    /// the furthest block that can be proved requested every loop delay.
+    pub fn new(targets: Vec<RequestTarget>) -> Self {
+        Self {
+            targets,
+            relayer: None,
+            store_path: None,
+            postgres_dsn: None,
+            audit: None,
+            attestation_key_path: None,
+            webhook: None,
+            api: None,
+            grpc: None,
+            retry_policy: RetryPolicy::default(),
+            max_request_age: None,
+            max_requests_per_hour: None,
+            status_poll_interval: Duration::from_secs(300),
+            landing_timeout: landing::DEFAULT_TIMEOUT,
+            landing_poll_interval: landing::DEFAULT_POLL_INTERVAL,
+            drain_timeout: drain::DEFAULT_TIMEOUT,
+            metrics_addr: None,
+            // The loop is stalled once an iteration takes twice the loop delay.
+            max_iteration_age: Duration::from_secs(60 * 2 * LOOP_DELAY),
+            readiness_timeout: Duration::from_secs(5),
+            alert_webhooks: Vec::new(),
+            alert_dedup_window: Duration::from_secs(30 * 60),
+            pagerduty: None,
+            failure_alert_threshold: 3,
+            lag_monitor: None,
+            staleness: StalenessMonitor::new(
+                Duration::from_secs(30 * 60),
+                Duration::from_secs(60 * 2 * LOOP_DELAY),
+            ),
+            catch_up: CatchUp::new(catchup::DEFAULT_EXIT_LAG),
+            min_lag: MinLag {
+                blocks: None,
+                seconds: None,
+            },
+            selector: Box::new(LargestSkip),
+            gating: None,
+            fallback: Some(StepFallback::new(fallback::DEFAULT_THRESHOLD, 1)),
+            control_socket: None,
+            schedule: Schedule::default(),
+            chain_spec: None,
+            halt_heights: Vec::new(),
+            upgrade_discovery: false,
+            heartbeat_file: None,
+            heartbeat_url: None,
+            circuit_digests: ExpectedDigests::default(),
+            artifact_manifest: None,
+            allow_circuit_mismatch: false,
+        }
+    }
+}
+
+impl Default for TendermintXConfig {
+    /// The defaults of `new`, without targets.
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::wire::{assert_json_snapshot, assert_snapshot};
+
+    #[test]
+    fn test_serde_snapshot() {
+        let mut config = TendermintXConfig::new(vec![RequestTarget::for_test(5)]);
+        config.store_path = Some(PathBuf::from("requests.db"));
+        config.webhook = Some(WebhookConfig {
+            addr: "127.0.0.1:8080".parse().unwrap(),
+            secret: "secret".to_string(),
+        });
+        config.api = Some(ApiConfig {
+            addr: "127.0.0.1:8000".parse().unwrap(),
+            token: "api-token".to_string(),
+        });
+        config.pagerduty = Some(PagerDuty::new("routing-key", Default::default()));
+        config.lag_monitor = Some(LagMonitor::new(500, Duration::from_secs(3600)));
+        config.chain_spec = ChainSpec::preset("celestia");
+        assert_json_snapshot(&config, include_str!("../fixtures/serde/config.json"));
+
+        // Secrets are never serialized.
+        let json = serde_json::to_string(&config).unwrap();
+        assert!(!json.contains("secret") && !json.contains("routing-key"));
+        assert!(!json.contains("api-token"));
+        let parsed: TendermintXConfig = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed.webhook.unwrap().secret, "");
+        assert_eq!(parsed.api.unwrap().token, "");
+        assert!(parsed.pagerduty.is_none());
+
+        // Missing fields take the defaults, and a null fallback disables it.
+        let parsed: TendermintXConfig =
+            serde_json::from_str(r#"{"max_requests_per_hour": 10, "fallback": null}"#).unwrap();
+        assert!(parsed.targets.is_empty());
+        assert_eq!(parsed.max_requests_per_hour, Some(10));
+        assert_eq!(parsed.retry_policy, RetryPolicy::default());
+        assert!(parsed.fallback.is_none());
+        assert!(TendermintXConfig::default().fallback.is_some());
+    }
+
+    #[test]
+    fn test_serde_round_trips() {
+        let relayer = RelayerConfig {
+            address: Address::repeat_byte(0x44),
+            balance_threshold: "0.5".to_string(),
+            gas_per_transaction: 500_000,
+        };
+        let json = serde_json::to_string(&relayer).unwrap();
+        assert_eq!(
+            serde_json::from_str::<RelayerConfig>(&json).unwrap(),
+            relayer
+        );
+
+        let audit = AuditConfig {
+            path: PathBuf::from("audit.jsonl"),
+            max_bytes: 1 << 20,
+            max_files: 3,
+        };
+        let json = serde_json::to_string(&audit).unwrap();
+        assert_eq!(serde_json::from_str::<AuditConfig>(&json).unwrap(), audit);
+
+        assert_snapshot(
+            &RetryPolicy::default(),
+            r#"{"max_attempts": 3, "initial_backoff_secs": 60, "max_backoff_secs": 900}"#,
+        );
+        assert_snapshot(
+            &MinLag {
+                blocks: Some(100),
+                seconds: None,
+            },
+            r#"{"blocks": 100, "seconds": null}"#,
+        );
+    }
+}
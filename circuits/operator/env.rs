@@ -0,0 +1,816 @@
+//! Reading the operator's settings from the environment, as documented in `.env.example`.
+//!
+//! Missing or invalid settings are errors, never panics, so that a service embedding the operator
+//! decides what to do about them.
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt::Display;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use alloy_primitives::{Address, B256};
+use anyhow::{anyhow, ensure, Context, Result};
+use ethers::providers::{Middleware, Provider};
+
+use super::{
+    ApiConfig, AuditConfig, RelayerConfig, TendermintXConfig, TendermintXOperator, WebhookConfig,
+};
+use crate::alert::{AlertWebhook, WebhookFormat};
+use crate::artifact::ExpectedDigests;
+use crate::audit::{self, AuditLog};
+use crate::backend::failover::FailoverBackend;
+use crate::backend::file::FileBackend;
+use crate::backend::http::{HttpBackend, HttpBackendConfig};
+use crate::backend::local::LocalBackend;
+use crate::backend::ratelimit::{RateLimitedBackend, RateLimiter};
+#[cfg(feature = "sp1")]
+use crate::backend::sp1::Sp1Backend;
+use crate::backend::ProofBackend;
+use crate::balance::DEFAULT_GAS_PER_TRANSACTION;
+use crate::chainspec::{ChainSpec, ChainSpecOverrides};
+use crate::endpoint::{EndpointPool, FailoverHttp};
+use crate::fallback::{self, StepFallback};
+use crate::gate::{Gating, HttpGate};
+use crate::health::Health;
+use crate::input::light_blocks::LightBlocks;
+use crate::input::InputDataFetcher;
+use crate::labels::Labels;
+use crate::lag::{LagMonitor, MinLag};
+use crate::leader::{self, LeaderElection, Lease};
+use crate::metrics::OperatorMetrics;
+use crate::pagerduty::{PagerDuty, SeverityMap};
+use crate::platform::PlatformClient;
+use crate::registry::{ChainEntry, ChainRegistry, OperatorBuilder};
+use crate::retry::RetryPolicy;
+use crate::schedule::{Schedule, Stagger};
+use crate::selector::{self, FixedCadence, LargestSkip, StableValidators, TargetSelector};
+use crate::signer::{self_test, signer_address, signer_client, SignerClient, SignerSource};
+use crate::staleness::StalenessMonitor;
+use crate::store::RequestStore;
+use crate::target::{parse_function_ids, RequestMode, RequestTarget};
+
+/// An optional environment variable. Empty values (e.g. `KEY=` in .env) count as unset.
+pub fn env_opt(key: &str) -> Option<String> {
+    env::var(key).ok().filter(|value| !value.is_empty())
+}
+
+/// A required environment variable.
+fn env_required(key: &str) -> Result<String> {
+    env::var(key).map_err(|_| anyhow!("{} must be set", key))
+}
+
+/// An optional environment variable, parsed.
+fn env_parse<T>(key: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    env_opt(key)
+        .map(|value| value.parse().map_err(|e| anyhow!("invalid {}: {}", key, e)))
+        .transpose()
+}
+
+/// An optional environment variable in seconds.
+fn env_secs(key: &str) -> Result<Option<Duration>> {
+    Ok(env_parse(key)?.map(Duration::from_secs))
+}
+
+/// An optional environment variable in minutes.
+fn env_minutes(key: &str) -> Result<Option<Duration>> {
+    Ok(env_parse::<u64>(key)?.map(|minutes| Duration::from_secs(60 * minutes)))
+}
+
+/// An optional environment variable in hours.
+fn env_hours(key: &str) -> Result<Option<Duration>> {
+    Ok(env_parse::<u64>(key)?.map(|hours| Duration::from_secs(60 * 60 * hours)))
+}
+
+/// Split a comma separated environment variable into its entries.
+fn env_list(key: &str) -> Result<Vec<String>> {
+    Ok(env_required(key)?
+        .split(',')
+        .map(|s| s.trim().to_string())
+        .collect())
+}
+
+/// The function IDs of `targets` targets, as hex: a single entry shared by all of them, or one
+/// entry per target, left empty for a target without one.
+fn env_function_ids(key: &str, targets: usize) -> Result<Vec<B256>> {
+    parse_function_ids(key, &env_required(key)?, targets)
+}
+
+/// Like `env_function_ids`, but every target is left without one if `key` isn't set.
+fn env_function_ids_opt(key: &str, targets: usize) -> Result<Vec<B256>> {
+    match env_opt(key) {
+        Some(list) => parse_function_ids(key, &list, targets),
+        None => Ok(vec![B256::ZERO; targets]),
+    }
+}
+
+/// One entry of a comma separated environment variable per target, or a single entry shared by
+/// all of them.
+fn env_per_target(key: &str, targets: usize) -> Result<Vec<String>> {
+    let mut entries = env_list(key)?;
+    if entries.len() == 1 {
+        entries = vec![entries[0].clone(); targets];
+    }
+    ensure!(
+        entries.len() == targets,
+        "{} must have one entry or one entry per target",
+        key
+    );
+    Ok(entries)
+}
+
+impl TendermintXConfig {
+    /// The settings in the environment, with the defaults of `new` for those that aren't set.
+    pub fn from_env() -> Result<Self> {
+        Self::from_env_targets(targets()?)
+    }
+
+    /// The settings in the environment for `targets`, instead of those of CHAIN_ID and
+    /// CONTRACT_ADDRESS.
+    pub fn from_env_targets(targets: Vec<RequestTarget>) -> Result<Self> {
+        let mut config = Self::new(targets);
+
+        // Optionally monitor the balance of the relayer account.
+        if let Some(address) = env_parse("RELAYER_ADDRESS")? {
+            config.relayer = Some(RelayerConfig {
+                address,
+                balance_threshold: env_opt("RELAYER_BALANCE_THRESHOLD")
+                    .unwrap_or_else(|| "0".to_string()),
+                gas_per_transaction: env_parse("RELAYER_GAS_PER_TX")?
+                    .unwrap_or(DEFAULT_GAS_PER_TRANSACTION),
+            });
+        }
+
+        config.store_path = env_parse("REQUEST_STORE_PATH")?;
+        config.postgres_dsn = env_opt("POSTGRES_DSN");
+        if let Some(path) = env_parse("AUDIT_LOG_PATH")? {
+            config.audit = Some(AuditConfig {
+                path,
+                max_bytes: env_parse::<u64>("AUDIT_LOG_MAX_MB")?
+                    .map_or(audit::DEFAULT_MAX_BYTES, |mb| 1024 * 1024 * mb),
+                max_files: env_parse("AUDIT_LOG_MAX_FILES")?.unwrap_or(audit::DEFAULT_MAX_FILES),
+            });
+        }
+        config.attestation_key_path = env_parse("ATTESTATION_KEY_PATH")?;
+        if let Some(addr) = env_parse("WEBHOOK_BIND_ADDR")? {
+            config.webhook = Some(WebhookConfig {
+                addr,
+                secret: env_opt("WEBHOOK_SECRET")
+                    .ok_or_else(|| anyhow!("WEBHOOK_SECRET must be set"))?,
+            });
+        }
+        if let Some(addr) = env_parse("API_BIND_ADDR")? {
+            config.api = Some(ApiConfig {
+                addr,
+                token: env_opt("API_TOKEN").ok_or_else(|| anyhow!("API_TOKEN must be set"))?,
+            });
+        }
+        if let Some(addr) = env_parse("GRPC_BIND_ADDR")? {
+            config.grpc = Some(ApiConfig {
+                addr,
+                token: env_opt("GRPC_TOKEN").ok_or_else(|| anyhow!("GRPC_TOKEN must be set"))?,
+            });
+        }
+
+        if let Some(max_attempts) = env_parse("MAX_REQUEST_ATTEMPTS")? {
+            config.retry_policy.max_attempts = max_attempts;
+        }
+        config.max_request_age = env_minutes("REQUEST_MAX_AGE_MINUTES")?;
+        config.max_requests_per_hour = env_parse("MAX_REQUESTS_PER_HOUR")?;
+        if let Some(interval) = env_secs("STATUS_POLL_INTERVAL_SECS")? {
+            config.status_poll_interval = interval;
+        }
+        if let Some(timeout) = env_minutes("LANDING_TIMEOUT_MINUTES")? {
+            config.landing_timeout = timeout;
+        }
+        if let Some(interval) = env_secs("LANDING_POLL_SECS")? {
+            config.landing_poll_interval = interval;
+        }
+        if let Some(timeout) = env_secs("DRAIN_TIMEOUT_SECS")? {
+            config.drain_timeout = timeout;
+        }
+
+        config.metrics_addr = env_parse("METRICS_BIND_ADDR")?;
+        if let Some(age) = env_minutes("HEALTH_MAX_ITERATION_AGE_MINUTES")? {
+            config.max_iteration_age = age;
+        }
+        if let Some(timeout) = env_secs("READINESS_TIMEOUT_SECS")? {
+            config.readiness_timeout = timeout;
+        }
+
+        for (key, format) in [
+            ("ALERT_WEBHOOK_URLS", WebhookFormat::Json),
+            ("ALERT_SLACK_WEBHOOK_URLS", WebhookFormat::Slack),
+        ] {
+            if env_opt(key).is_some() {
+                config.alert_webhooks.extend(
+                    env_list(key)?
+                        .into_iter()
+                        .map(|url| AlertWebhook { url, format }),
+                );
+            }
+        }
+        if let Some(window) = env_minutes("ALERT_DEDUP_MINUTES")? {
+            config.alert_dedup_window = window;
+        }
+        if let Some(routing_key) = env_opt("PAGERDUTY_ROUTING_KEY") {
+            let severities = match env_opt("PAGERDUTY_SEVERITIES") {
+                Some(s) => SeverityMap::parse(&s).context("invalid PAGERDUTY_SEVERITIES")?,
+                None => SeverityMap::default(),
+            };
+            let mut pagerduty = PagerDuty::new(routing_key, severities);
+            if let Some(url) = env_opt("PAGERDUTY_EVENTS_URL") {
+                pagerduty = pagerduty.with_url(url);
+            }
+            config.pagerduty = Some(pagerduty);
+        }
+        if let Some(threshold) = env_parse("ALERT_FAILURE_THRESHOLD")? {
+            config.failure_alert_threshold = threshold;
+        }
+        if let Some(blocks) = env_parse("LAG_ALERT_BLOCKS")? {
+            let sustain =
+                env_minutes("LAG_ALERT_AFTER_MINUTES")?.unwrap_or(Duration::from_secs(60 * 60));
+            config.lag_monitor = Some(LagMonitor::new(blocks, sustain));
+        }
+        config.staleness = StalenessMonitor::new(
+            env_minutes("CHAIN_HALT_ALERT_MINUTES")?.unwrap_or(Duration::from_secs(30 * 60)),
+            env_minutes("CLIENT_STALL_ALERT_MINUTES")?
+                .unwrap_or(Duration::from_secs(60 * 2 * super::LOOP_DELAY)),
+        );
+
+        if let Some(exit_lag) = env_parse("CATCH_UP_EXIT_LAG_BLOCKS")? {
+            config.catch_up.exit_lag = exit_lag;
+        }
+        if let Some(interval) = env_secs("CATCH_UP_POLL_SECS")? {
+            config.catch_up.poll_interval = interval;
+        }
+        if let Some(timeout) = env_minutes("CATCH_UP_TIMEOUT_MINUTES")? {
+            config.catch_up.timeout = timeout;
+        }
+        config.min_lag = MinLag {
+            blocks: env_parse("MIN_LAG_BLOCKS")?,
+            seconds: env_parse("MIN_LAG_SECONDS")?,
+        };
+        config.selector = target_selector()?;
+        config.gating = gating()?;
+        let fallback_failures =
+            env_parse("STEP_FALLBACK_FAILURES")?.unwrap_or(fallback::DEFAULT_THRESHOLD);
+        config.fallback = match fallback_failures {
+            0 => None,
+            failures => {
+                let hop = env_parse("STEP_FALLBACK_HOP_BLOCKS")?.unwrap_or(1);
+                Some(StepFallback::new(failures, hop))
+            }
+        };
+
+        config.control_socket = env_parse("CONTROL_SOCKET")?;
+        config.schedule = schedule()?;
+        config.chain_spec = chain_spec()?;
+        if let Some(heights) = env_opt("HALT_HEIGHTS") {
+            config.halt_heights = heights
+                .split(',')
+                .map(|height| height.trim().parse())
+                .collect::<Result<_, _>>()
+                .context("invalid HALT_HEIGHTS")?;
+        }
+        config.upgrade_discovery = env_parse("UPGRADE_DISCOVERY")?.unwrap_or(false);
+        config.heartbeat_file = env_parse("HEARTBEAT_FILE")?;
+        config.heartbeat_url = env_opt("HEARTBEAT_URL");
+        if let Some(digests) = env_opt("CIRCUIT_DIGESTS") {
+            config.circuit_digests =
+                ExpectedDigests::parse(&digests).context("invalid CIRCUIT_DIGESTS")?;
+        }
+        config.artifact_manifest = env_parse("CIRCUIT_MANIFEST")?;
+        config.allow_circuit_mismatch = env_parse("ALLOW_CIRCUIT_MISMATCH")?.unwrap_or(false);
+        Ok(config)
+    }
+}
+
+/// CHAIN_ID and CONTRACT_ADDRESS are comma separated lists with one entry per target. The
+/// optional REQUEST_MODE ("platform" by default, or "offchain") is either a single entry shared by
+/// all targets or one entry per target. The optional REQUEST_LABELS (e.g.
+/// "operator=ops,environment=prod") are attached to the requests of every target, together with a
+/// `chain` label for the target's chain ID. With DATA_COMMITMENTS=true, every target also takes
+/// data commitments, proved by the circuit DATA_COMMITMENT_FUNCTION_ID. STEP_FUNCTION_ID,
+/// SKIP_FUNCTION_ID and DATA_COMMITMENT_FUNCTION_ID are each a single entry shared by all
+/// targets, or one entry per target: every target must have an ID for each kind it is requested.
+/// STEP_FUNCTION_ID and SKIP_FUNCTION_ID may be left out for contracts exposing their IDs, which
+/// `resolve_function_ids` reads and checks the configured ones against. An entry of CHAIN_ID may
+/// list several `|` separated chain IDs (e.g. "1|10|42161") for a contract deployed at the same
+/// address on each of them behind the same gateway: the first is the chain the contract is read
+/// on, and every request is submitted for each of them.
+fn targets() -> Result<Vec<RequestTarget>> {
+    let chain_ids = env_list("CHAIN_ID")?;
+    let contract_addresses = env_list("CONTRACT_ADDRESS")?;
+    ensure!(
+        chain_ids.len() == contract_addresses.len(),
+        "CHAIN_ID and CONTRACT_ADDRESS must have the same number of entries"
+    );
+
+    let request_modes = match env_opt("REQUEST_MODE") {
+        Some(_) => env_per_target("REQUEST_MODE", chain_ids.len())?
+            .iter()
+            .map(|mode| mode.parse::<RequestMode>().context("invalid REQUEST_MODE"))
+            .collect::<Result<Vec<_>>>()?,
+        None => vec![RequestMode::default(); chain_ids.len()],
+    };
+
+    let labels = Labels::parse(&env_opt("REQUEST_LABELS").unwrap_or_default())
+        .context("invalid REQUEST_LABELS")?;
+    ensure!(
+        labels.get("chain").is_none(),
+        "REQUEST_LABELS must not set chain, it is set per target"
+    );
+
+    // Load the function IDs of every target.
+    let targets = chain_ids.len();
+    let step_function_ids = env_function_ids_opt("STEP_FUNCTION_ID", targets)?;
+    let skip_function_ids = env_function_ids_opt("SKIP_FUNCTION_ID", targets)?;
+    let data_commitment_function_ids = match env_parse("DATA_COMMITMENTS")?.unwrap_or(false) {
+        true => env_function_ids("DATA_COMMITMENT_FUNCTION_ID", targets)?
+            .into_iter()
+            .map(Some)
+            .collect(),
+        false => vec![None; targets],
+    };
+
+    chain_ids
+        .iter()
+        .zip(contract_addresses.iter())
+        .zip(request_modes)
+        .enumerate()
+        .map(|(i, ((chain_id, contract_address), request_mode))| {
+            let mut chain_ids = chain_id.split('|').map(|chain_id| {
+                chain_id
+                    .trim()
+                    .parse::<u32>()
+                    .map_err(|e| anyhow!("invalid chain id {:?}: {}", chain_id, e))
+            });
+            let chain_id = chain_ids.next().expect("split yields an entry")?;
+            let mirror_chain_ids = chain_ids.collect::<Result<Vec<_>>>()?;
+            ensure!(
+                !mirror_chain_ids.contains(&chain_id),
+                "chain id {} is listed twice for {}",
+                chain_id,
+                contract_address
+            );
+            let mut labels = labels.clone();
+            labels.insert("chain", &chain_id.to_string())?;
+            Ok(RequestTarget {
+                chain_id,
+                address: contract_address
+                    .parse::<Address>()
+                    .map_err(|e| anyhow!("invalid address {:?}: {}", contract_address, e))?,
+                step_function_id: step_function_ids[i],
+                skip_function_id: skip_function_ids[i],
+                data_commitment_function_id: data_commitment_function_ids[i],
+                request_mode,
+                labels,
+                mirror_chain_ids,
+            })
+        })
+        .collect()
+}
+
+/// TARGET_SELECTOR selects the block each request proves: "largest" (the default) the furthest
+/// block that can be proved, "cadence" the first block TARGET_CADENCE_MINUTES (6 hours by default)
+/// of chain time after the contract's latest block, once the chain reaches it, and "stable" the
+/// furthest block whose validator set still overlaps the trusted one by TARGET_MIN_OVERLAP.
+fn target_selector() -> Result<Box<dyn TargetSelector>> {
+    Ok(match env_opt("TARGET_SELECTOR").as_deref() {
+        None | Some("largest") => Box::new(LargestSkip),
+        Some("cadence") => {
+            let cadence =
+                env_minutes("TARGET_CADENCE_MINUTES")?.unwrap_or(Duration::from_secs(6 * 60 * 60));
+            Box::new(FixedCadence::new(cadence))
+        }
+        Some("stable") => {
+            let min_overlap =
+                env_parse("TARGET_MIN_OVERLAP")?.unwrap_or(selector::DEFAULT_MIN_OVERLAP);
+            ensure!(
+                min_overlap > 1.0 / 3.0 && min_overlap <= 1.0,
+                "TARGET_MIN_OVERLAP must be over 1/3 and at most 1"
+            );
+            let mut stable = StableValidators::new(min_overlap);
+            if let Some(samples) = env_parse("TARGET_OVERLAP_SAMPLES")? {
+                stable.samples = samples;
+            }
+            Box::new(stable)
+        }
+        Some(selector) => return Err(anyhow!("unknown TARGET_SELECTOR {:?}", selector)),
+    })
+}
+
+/// GATE_URL is asked whether each request is needed, within GATE_TIMEOUT_SECS. GATE_FAILURE is
+/// "open" (the default) to submit when the gate fails, or "closed" not to.
+fn gating() -> Result<Option<Gating>> {
+    let Some(url) = env_opt("GATE_URL") else {
+        return Ok(None);
+    };
+    let mut gating = Gating::new(Box::new(HttpGate::new(url)));
+    if let Some(timeout) = env_secs("GATE_TIMEOUT_SECS")? {
+        gating.timeout = timeout;
+    }
+    gating.fail_open = match env_opt("GATE_FAILURE").as_deref() {
+        None | Some("open") => true,
+        Some("closed") => false,
+        Some(failure) => return Err(anyhow!("invalid GATE_FAILURE {:?}", failure)),
+    };
+    Ok(Some(gating))
+}
+
+fn schedule() -> Result<Schedule> {
+    let mut schedule = match env_parse("JITTER_SEED")? {
+        Some(seed) => Schedule::with_seed(seed),
+        None => Schedule::default(),
+    };
+    if let Some(jitter) = env_secs("STARTUP_JITTER_SECS")? {
+        schedule.startup_jitter = jitter;
+    }
+    if let Some(percent) = env_parse("LOOP_JITTER_PERCENT")? {
+        schedule.jitter_percent = percent;
+    }
+    if let Some(index) = env_parse("OPERATOR_INDEX")? {
+        let count =
+            env_parse("OPERATOR_COUNT")?.ok_or_else(|| anyhow!("OPERATOR_COUNT must be set"))?;
+        let stagger = Stagger { index, count };
+        ensure!(
+            stagger.index < stagger.count,
+            "OPERATOR_INDEX must be less than OPERATOR_COUNT"
+        );
+        schedule.stagger = Some(stagger);
+    }
+    schedule.cron = env_parse("SCHEDULE_CRON")?;
+    Ok(schedule)
+}
+
+/// CHAIN_SPEC selects the constants of the Tendermint chain: a preset (cosmoshub, osmosis or
+/// celestia), or "custom" to set all of them. CHAIN_UNBONDING_PERIOD_HOURS,
+/// CHAIN_TRUSTING_PERIOD_HOURS, CHAIN_BLOCK_TIME_MS, CHAIN_MAX_VALIDATORS,
+/// CHAIN_CONFIRMATION_DEPTH and CHAIN_RPC_ADAPTER override those of the preset.
+fn chain_spec() -> Result<Option<ChainSpec>> {
+    let overrides = ChainSpecOverrides {
+        unbonding_period: env_hours("CHAIN_UNBONDING_PERIOD_HOURS")?,
+        trusting_period: env_hours("CHAIN_TRUSTING_PERIOD_HOURS")?,
+        block_time: env_parse("CHAIN_BLOCK_TIME_MS")?.map(Duration::from_millis),
+        max_validators: env_parse("CHAIN_MAX_VALIDATORS")?,
+        confirmation_depth: env_parse("CHAIN_CONFIRMATION_DEPTH")?,
+        rpc_adapter: env_parse("CHAIN_RPC_ADAPTER")?,
+    };
+    let Some(name) = env_opt("CHAIN_SPEC") else {
+        ensure!(
+            overrides == ChainSpecOverrides::default(),
+            "the CHAIN_* overrides require CHAIN_SPEC"
+        );
+        return Ok(None);
+    };
+    let base = match name.trim() {
+        "custom" => None,
+        name => Some(name.parse().context("invalid CHAIN_SPEC")?),
+    };
+    ChainSpec::with_overrides(base, overrides).map(Some)
+}
+
+/// PROOF_BACKEND selects where requests are proved: "platform" (the default) submits them to the
+/// Succinct platform, "file" writes their inputs to PROOF_BACKEND_DIR and "local" proves them with
+/// the LOCAL_STEP_PROVER and LOCAL_SKIP_PROVER binaries, writing the proofs to PROOF_BACKEND_DIR.
+/// With the `sp1` feature, "sp1" submits them to the SP1 prover at SP1_PROVER_URL, authenticated
+/// with SP1_API_KEY if set, encoding the light blocks read from TENDERMINT_RPC_URL. "http" submits
+/// them to the custom relayer service described by the JSON file at HTTP_BACKEND_CONFIG, with the
+/// bearer token HTTP_BACKEND_TOKEN if set.
+///
+/// With the platform backend, SECONDARY_SUCCINCT_RPC_URL optionally configures a secondary
+/// endpoint with the same API that submissions fail over to when the primary keeps failing.
+pub fn proof_backend() -> Result<Box<dyn ProofBackend>> {
+    proof_backend_with(InputDataFetcher::default)
+}
+
+/// Like `proof_backend`, but the backends that build their inputs from light blocks read them
+/// with the fetcher of `fetcher`.
+#[cfg_attr(not(feature = "sp1"), allow(unused_variables))]
+pub fn proof_backend_with(
+    fetcher: impl FnOnce() -> InputDataFetcher,
+) -> Result<Box<dyn ProofBackend>> {
+    match env_opt("PROOF_BACKEND").as_deref() {
+        None | Some("platform") => {
+            let succinct_rpc_url = env_required("SUCCINCT_RPC_URL")?;
+            let succinct_api_key = env_required("SUCCINCT_API_KEY")?;
+            let mut primary = PlatformClient::new(succinct_rpc_url, succinct_api_key);
+            if let Some(fallback_key) = env_opt("SUCCINCT_API_KEY_FALLBACK") {
+                primary = primary.with_fallback_key(fallback_key);
+            }
+            let primary = Box::new(primary);
+            let Some(secondary_rpc_url) = env_opt("SECONDARY_SUCCINCT_RPC_URL") else {
+                return Ok(primary);
+            };
+            let secondary_api_key = env_required("SECONDARY_SUCCINCT_API_KEY")?;
+            let secondary = Box::new(
+                PlatformClient::new(secondary_rpc_url, secondary_api_key).with_name("secondary"),
+            );
+            let policy = RetryPolicy {
+                max_attempts: env_parse("FAILOVER_ATTEMPTS")?.unwrap_or(3),
+                initial_backoff: Duration::from_secs(5),
+                max_backoff: Duration::from_secs(30),
+            };
+            Ok(Box::new(FailoverBackend::new(primary, secondary, policy)))
+        }
+        Some("file") => {
+            let dir = env_required("PROOF_BACKEND_DIR")?;
+            let backend = FileBackend::new(dir).context("could not create PROOF_BACKEND_DIR")?;
+            Ok(Box::new(backend))
+        }
+        Some("local") => {
+            let step_prover = env_required("LOCAL_STEP_PROVER")?;
+            let skip_prover = env_required("LOCAL_SKIP_PROVER")?;
+            let dir = env_required("PROOF_BACKEND_DIR")?;
+            let backend = LocalBackend::new(step_prover, skip_prover, dir)
+                .context("could not create PROOF_BACKEND_DIR")?;
+            Ok(Box::new(backend))
+        }
+        Some("http") => {
+            let config = HttpBackendConfig::load(Path::new(&env_required("HTTP_BACKEND_CONFIG")?))?;
+            let backend = HttpBackend::new(config, env_opt("HTTP_BACKEND_TOKEN"))?;
+            Ok(Box::new(backend))
+        }
+        #[cfg(feature = "sp1")]
+        Some("sp1") => {
+            let url = env_required("SP1_PROVER_URL")?;
+            let backend = Sp1Backend::new(url, env_opt("SP1_API_KEY"), fetcher());
+            Ok(Box::new(backend))
+        }
+        #[cfg(not(feature = "sp1"))]
+        Some("sp1") => Err(anyhow!("PROOF_BACKEND sp1 requires the sp1 feature")),
+        Some(backend) => Err(anyhow!("unknown PROOF_BACKEND {:?}", backend)),
+    }
+}
+
+/// The Ethereum provider of each of `targets` targets, and the endpoint pools behind them.
+/// ETHEREUM_RPC_URL is either a single entry shared by all targets or one entry per target, each
+/// one or more `|` separated URLs to fail over across. Targets with the same entry share their
+/// endpoints, and their health.
+pub fn ethereum_providers(
+    targets: usize,
+) -> Result<(Vec<Arc<Provider<FailoverHttp>>>, Vec<Arc<EndpointPool>>)> {
+    failover_providers(env_per_target("ETHEREUM_RPC_URL", targets)?)
+}
+
+/// A provider for each of `entries`, one or more `|` separated URLs to fail over across, and the
+/// endpoint pools behind them. Equal entries share their endpoints.
+pub fn failover_providers(
+    entries: Vec<String>,
+) -> Result<(Vec<Arc<Provider<FailoverHttp>>>, Vec<Arc<EndpointPool>>)> {
+    let mut pools = BTreeMap::new();
+    let mut providers = Vec::new();
+    for ethereum_rpc_url in entries {
+        let pool = pools
+            .entry(ethereum_rpc_url.clone())
+            .or_insert_with(|| {
+                let urls = ethereum_rpc_url
+                    .split('|')
+                    .map(|url| url.trim().to_string());
+                Arc::new(EndpointPool::new("ethereum", urls.collect()))
+            })
+            .clone();
+        let client = FailoverHttp::new(pool).context("invalid ETHEREUM_RPC_URL")?;
+        providers.push(Arc::new(Provider::new(client)));
+    }
+    Ok((providers, pools.into_values().collect()))
+}
+
+/// The key the operator signs transactions with, if any: RELAYER_PRIVATE_KEY, the keystore at
+/// RELAYER_KEYSTORE decrypted with RELAYER_KEYSTORE_PASSWORD or, with the `kms` feature, the AWS
+/// KMS key RELAYER_KMS_KEY_ID (an ID or ARN) in the region of AWS_REGION.
+pub fn signer_source() -> Result<Option<SignerSource>> {
+    let (key, keystore, kms) = (
+        env_opt("RELAYER_PRIVATE_KEY"),
+        env_opt("RELAYER_KEYSTORE"),
+        env_opt("RELAYER_KMS_KEY_ID"),
+    );
+    ensure!(
+        [key.is_some(), keystore.is_some(), kms.is_some()]
+            .iter()
+            .filter(|set| **set)
+            .count()
+            <= 1,
+        "only one of RELAYER_PRIVATE_KEY, RELAYER_KEYSTORE and RELAYER_KMS_KEY_ID can be set"
+    );
+    if let Some(key) = key {
+        return Ok(Some(SignerSource::PrivateKey(key)));
+    }
+    if let Some(path) = keystore {
+        return Ok(Some(SignerSource::Keystore {
+            path: path.into(),
+            password: env_required("RELAYER_KEYSTORE_PASSWORD")?,
+        }));
+    }
+    match kms {
+        #[cfg(feature = "kms")]
+        Some(key_id) => Ok(Some(SignerSource::Kms { key_id })),
+        #[cfg(not(feature = "kms"))]
+        Some(_) => Err(anyhow!("RELAYER_KMS_KEY_ID requires the kms feature")),
+        None => Ok(None),
+    }
+}
+
+/// LEADER_ELECTION_URL enables leader election among the operators holding LEADER_LEASE_KEY: only
+/// the leader submits, and a follower takes over within LEADER_LEASE_SECS of the leader
+/// disappearing. OPERATOR_ID identifies this operator (default: the hostname and process ID).
+pub fn leader_election(metrics: Arc<OperatorMetrics>) -> Result<Option<Arc<LeaderElection>>> {
+    let Some(url) = env_opt("LEADER_ELECTION_URL") else {
+        return Ok(None);
+    };
+    let key = env_opt("LEADER_LEASE_KEY").unwrap_or_else(|| leader::DEFAULT_LEASE_KEY.to_string());
+    let ttl = env_secs("LEADER_LEASE_SECS")?.unwrap_or(leader::DEFAULT_LEASE_TTL);
+    let holder = env_opt("OPERATOR_ID").unwrap_or_else(|| {
+        let host = env_opt("HOSTNAME").unwrap_or_else(|| "tendermintx".to_string());
+        format!("{}-{}", host, std::process::id())
+    });
+    let lease = leader_lease(&url, key)?;
+    Ok(Some(Arc::new(LeaderElection::new(
+        lease, holder, ttl, metrics,
+    ))))
+}
+
+#[cfg(feature = "redis")]
+fn leader_lease(url: &str, key: String) -> Result<Arc<dyn Lease>> {
+    let lease = leader::RedisLease::new(url, key).context("invalid LEADER_ELECTION_URL")?;
+    Ok(Arc::new(lease))
+}
+
+#[cfg(not(feature = "redis"))]
+fn leader_lease(_url: &str, _key: String) -> Result<Arc<dyn Lease>> {
+    Err(anyhow!(
+        "LEADER_ELECTION_URL is set, but this build has no redis feature"
+    ))
+}
+
+impl TendermintXOperator {
+    /// An operator with the settings, proving backend and providers in the environment.
+    pub fn from_env() -> Result<Self> {
+        Self::from_env_config(TendermintXConfig::from_env()?)
+    }
+
+    /// An operator with `config`, and the proving backend and providers in the environment.
+    pub fn from_env_config(config: TendermintXConfig) -> Result<Self> {
+        env_required("TENDERMINT_RPC_URL")?;
+        let (providers, pools) = ethereum_providers(config.targets.len())?;
+        Self::from_env_providers(config, providers, pools)
+    }
+
+    /// An operator with the settings and proving backend in the environment, reading the chain
+    /// from `light_blocks` (loaded from `source`) only: its inputs are built without any
+    /// Tendermint RPC. The Ethereum providers are configured but never queried by `prove` and
+    /// `export_input`, which take the trusted header hash instead of reading it from the contract.
+    pub fn from_env_light_blocks(light_blocks: LightBlocks, source: &str) -> Result<Self> {
+        let config = TendermintXConfig::from_env()?;
+        let light_blocks = Arc::new(light_blocks);
+        let fetcher = || InputDataFetcher::with_light_blocks(light_blocks.clone(), source);
+        let backend = proof_backend_with(fetcher)?;
+        let (providers, _) = ethereum_providers(config.targets.len())?;
+        Self::new(config, fetcher(), backend, providers)
+    }
+
+    /// The operator of the run loop with `config`, and one for the API of `serve` with the same
+    /// targets and settings. Both submit through the same backend within the same
+    /// MAX_REQUESTS_PER_HOUR, and record to the request store and audit log of the first, so that
+    /// the requests of the API are deduplicated and rate limited with those of the run loop. Only
+    /// the first starts the listeners.
+    pub fn with_api_from_env(mut config: TendermintXConfig) -> Result<(Self, Self)> {
+        env_required("TENDERMINT_RPC_URL")?;
+        let mut api_config = TendermintXConfig::from_env_targets(config.targets.clone())?;
+        api_config.max_requests_per_hour = None;
+        api_config.store_path = None;
+        api_config.postgres_dsn = None;
+        api_config.audit = None;
+        api_config.webhook = None;
+        api_config.metrics_addr = None;
+        api_config.control_socket = None;
+
+        let backend: Arc<dyn ProofBackend> = match config.max_requests_per_hour.take() {
+            Some(max) => {
+                let limiter = Arc::new(RateLimiter::per_hour(max));
+                Arc::new(RateLimitedBackend::new(proof_backend()?, limiter))
+            }
+            None => Arc::from(proof_backend()?),
+        };
+        let (providers, pools) = ethereum_providers(config.targets.len())?;
+        let fetcher = InputDataFetcher::default();
+        let operator = Self::new(
+            config,
+            fetcher,
+            Box::new(backend.clone()),
+            providers.clone(),
+        )?;
+        for pool in pools {
+            operator.metrics().register_endpoints(pool);
+        }
+        let fetcher = InputDataFetcher::default();
+        let mut api = Self::new(api_config, fetcher, Box::new(backend), providers)?;
+        api.share_records(&operator);
+        Ok((operator, api))
+    }
+}
+
+impl ChainRegistry {
+    /// The registry of the chains in the CHAIN_REGISTRY file, proving with the backend in the
+    /// environment within MAX_REQUESTS_PER_HOUR, shared by all chains. Each chain takes the other
+    /// settings of the environment, with its own targets, Ethereum RPC's and chain spec.
+    pub fn from_env() -> Result<Self> {
+        let path = env_parse::<PathBuf>("CHAIN_REGISTRY")?
+            .ok_or_else(|| anyhow!("CHAIN_REGISTRY must be set"))?;
+        let entries = ChainEntry::read_all(&path)?;
+        let shared = TendermintXConfig::from_env_targets(Vec::new())?;
+        let limiter = shared
+            .max_requests_per_hour
+            .map(|max| Arc::new(RateLimiter::per_hour(max)));
+        let health = Health::new(shared.max_iteration_age, shared.readiness_timeout);
+        let build: OperatorBuilder<Provider<FailoverHttp>> =
+            Box::new(|entry: &ChainEntry, backend| {
+                let config = TendermintXConfig::from_env_targets(entry.targets.clone())?;
+                let config = entry.operator_config(config);
+                let (providers, pools) = failover_providers(entry.ethereum_rpc_url_per_target())
+                    .with_context(|| format!("invalid Ethereum RPC of chain {}", entry.chain_id))?;
+                let fetcher =
+                    InputDataFetcher::new(entry.rpc_urls.clone(), "./circuits/fixtures/mocha-4");
+                let operator = TendermintXOperator::new(config, fetcher, backend, providers)?;
+                for pool in pools {
+                    operator.metrics().register_endpoints(pool);
+                }
+                Ok(operator)
+            });
+
+        let mut registry = Self::new(proof_backend()?, limiter, health, build);
+        if let Some(path) = shared.control_socket {
+            registry = registry.with_control_socket(path);
+        }
+        if let Some(addr) = shared.metrics_addr {
+            let store = match shared.store_path {
+                Some(path) => Some(Arc::new(
+                    RequestStore::open(path).context("could not open request store")?,
+                )),
+                None => None,
+            };
+            registry = registry.with_metrics(addr, store);
+        }
+        if let Some(audit) = shared.audit {
+            let audit = AuditLog::open(audit.path, audit.max_bytes, audit.max_files)
+                .context("could not open audit log")?;
+            registry = registry.with_audit(Arc::new(audit));
+        }
+        for entry in entries {
+            registry.add(entry)?;
+        }
+        Ok(registry)
+    }
+}
+
+impl TendermintXOperator<SignerClient> {
+    /// An operator with `config`, signing with the key of `source` through the providers in the
+    /// environment. The key must be that of RELAYER_ADDRESS, if set, and pass the signer's
+    /// self-test.
+    pub async fn from_env_with_signer(
+        config: TendermintXConfig,
+        source: SignerSource,
+    ) -> Result<Self> {
+        env_required("TENDERMINT_RPC_URL")?;
+        let signer = source.signer().await?;
+        if let Some(relayer) = config.relayer.as_ref() {
+            ensure!(
+                relayer.address == signer_address(&signer),
+                "the signing key is that of {}, not RELAYER_ADDRESS {}",
+                signer_address(&signer),
+                relayer.address
+            );
+        }
+        self_test(&signer).await?;
+        let (providers, pools) = ethereum_providers(config.targets.len())?;
+        let mut signers = Vec::new();
+        for provider in providers {
+            let client = signer_client(provider.as_ref().clone(), signer.clone()).await?;
+            signers.push(Arc::new(client));
+        }
+        Self::from_env_providers(config, signers, pools)
+    }
+}
+
+impl<M: Middleware + 'static> TendermintXOperator<M> {
+    fn from_env_providers(
+        config: TendermintXConfig,
+        providers: Vec<Arc<M>>,
+        pools: Vec<Arc<EndpointPool>>,
+    ) -> Result<Self> {
+        let data_fetcher = InputDataFetcher::default();
+        let backend = proof_backend()?;
+        let operator = Self::new(config, data_fetcher, backend, providers)?;
+        for pool in pools {
+            operator.metrics().register_endpoints(pool);
+        }
+        Ok(operator)
+    }
+}
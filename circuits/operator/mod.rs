@@ -0,0 +1,5057 @@
+//! The operator: keeps the light client contracts of its targets up to date with the Tendermint
+//! chain, as the `tendermintx` binary does.
+//!
+//! The operator is built from a `TendermintXConfig`, a data fetcher for the Tendermint chain, a
+//! proving backend and an Ethereum provider for each target, so that a service can embed it on its
+//! own runtime, with its own clients. `run` is the run loop, `run_once` a single iteration of it,
+//! and `prove` requests a single range. Failures are returned as errors: nothing here exits the
+//! process or panics on them.
+//!
+//! ```no_run
+//! use std::sync::Arc;
+//! use std::time::Duration;
+//!
+//! use alloy_primitives::B256;
+//! use ethers::providers::{Http, Provider};
+//! use tendermintx::backend::file::FileBackend;
+//! use tendermintx::input::InputDataFetcher;
+//! use tendermintx::labels::Labels;
+//! use tendermintx::operator::{TendermintXConfig, TendermintXOperator};
+//! use tendermintx::target::{RequestMode, RequestTarget};
+//!
+//! fn main() -> anyhow::Result<()> {
+//!     // A runtime of the service's own, shared with the rest of it.
+//!     let runtime = tokio::runtime::Builder::new_multi_thread()
+//!         .worker_threads(2)
+//!         .thread_name("light-client")
+//!         .enable_all()
+//!         .build()?;
+//!     runtime.block_on(async {
+//!         let target = RequestTarget {
+//!             chain_id: 5,
+//!             address: "0x1111111111111111111111111111111111111111".parse()?,
+//!             step_function_id: B256::repeat_byte(0x22),
+//!             skip_function_id: B256::repeat_byte(0x33),
+//!             data_commitment_function_id: None,
+//!             request_mode: RequestMode::Platform,
+//!             labels: Labels::new(),
+//!             mirror_chain_ids: Vec::new(),
+//!         };
+//!         let mut config = TendermintXConfig::new(vec![target]);
+//!         config.landing_timeout = Duration::from_secs(30 * 60);
+//!
+//!         let fetcher = InputDataFetcher::new(
+//!             vec!["http://localhost:26657".to_string()],
+//!             "./circuits/fixtures/mocha-4",
+//!         );
+//!         let backend = Box::new(FileBackend::new("proofs")?);
+//!         let provider = Arc::new(Provider::<Http>::try_from("http://localhost:8545")?);
+//!         let mut operator = TendermintXOperator::new(config, fetcher, backend, vec![provider])?;
+//!
+//!         // A single iteration, e.g. on the service's own schedule.
+//!         let outcome = operator.run_once().await?;
+//!         println!("{} ranges requested", outcome.chunks.len());
+//!         // Or the run loop, until it fails or Ctrl-C.
+//!         operator.run_until(async { tokio::signal::ctrl_c().await.unwrap_or(()) }).await
+//!     })
+//! }
+//! ```
+
+mod config;
+pub mod env;
+
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+use std::net::SocketAddr;
+use std::ops::RangeInclusive;
+use std::panic::AssertUnwindSafe;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use alloy_primitives::{Address, B256};
+use anyhow::{anyhow, ensure, Context, Result};
+use async_trait::async_trait;
+use chrono::{DateTime, SecondsFormat, Utc};
+use ethers::providers::{Middleware, Provider};
+use futures::future::try_join_all;
+use futures::FutureExt;
+use tokio::sync::watch;
+use tracing::{error, field, info, info_span, warn, Instrument, Span};
+
+pub use self::config::{ApiConfig, AuditConfig, RelayerConfig, TendermintXConfig, WebhookConfig};
+use crate::alert::{Alert, AlertKind, Alerter};
+use crate::artifact::{self, ArtifactManifest, ExpectedDigests, Verification};
+use crate::attestation::{self, Attestation, Attester};
+use crate::audit::{self, unix_timestamp_ms, AuditEvent, AuditLog};
+use crate::backend::ratelimit::{RateLimitedBackend, RateLimiter};
+use crate::backend::{find_unfulfilled, ProofBackend, ProofRequest, RecentRequest, RequestKind};
+use crate::backfill::{Backfill, BackfillSummary, Checkpoints};
+use crate::balance::BalanceMonitor;
+use crate::catchup::{self, CatchUp};
+use crate::chainspec::{ChainSpec, TrustedStateExpired};
+use crate::contract::TendermintXContract;
+use crate::control::{self, Control};
+use crate::correlation::CorrelationId;
+use crate::dashboard::{self, PendingStatus, StatusSnapshot, StatusSource, TargetStatus};
+use crate::drain::{self, InFlight};
+use crate::endpoint::FailoverHttp;
+use crate::export::{ImportedRequest, RequestInputs};
+use crate::fallback::{FallbackTransition, StepFallback};
+use crate::gate::Gating;
+use crate::health::{ContractCheck, Health, TendermintRpcCheck};
+use crate::heartbeat::Heartbeat;
+use crate::input::light_blocks::{self, LightBlocks};
+use crate::input::{header_hash, InputDataFetcher, InputDataMode};
+use crate::lag::{Lag, LagMonitor, LagTransition, MinLag};
+use crate::landing::{self, Landing};
+use crate::leader::LeaderElection;
+use crate::logging::{attempt_span, submit_in_span};
+use crate::metrics::{self, OperatorMetrics};
+use crate::platform::FulfillmentStatus;
+use crate::poller::refresh_pending;
+use crate::proof::ProofDocument;
+use crate::repair::{self, RepairPlan, ScanBounds};
+use crate::replay::replay;
+use crate::reporting;
+use crate::retry::{fulfill_with_retries, Attempt, RetryOutcome, RetryPolicy};
+use crate::schedule::Schedule;
+use crate::selector::{self, ChainHead, HeaderFetcher, TargetSelector};
+use crate::sink::SinkEvent;
+use crate::staleness::{Staleness, StalenessMonitor, StalenessTransition};
+use crate::store::{
+    unix_timestamp, NewRequest, RequestRecord, RequestStatus, RequestStore, Reservation,
+};
+use crate::summary::{Action, IterationSummary, Phases, RunSummary};
+use crate::target::{
+    submit_to_targets, FunctionIdSource, RequestMode, RequestTarget, TargetSubmission,
+};
+use crate::trusted::TrustedStateProvider;
+use crate::types::{HeaderHash, Height};
+use crate::upgrade::HaltHeights;
+use crate::webhook::{serve, WebhookHandler};
+
+/// A request target and the contract it reads the light client state from.
+struct Target<M> {
+    request: RequestTarget,
+    provider: Arc<M>,
+    contract: TendermintXContract<M>,
+    /// The trusted state of the light client, read from the contract unless replaced.
+    trusted: Arc<dyn TrustedStateProvider>,
+    /// Locked as a status check reads the operator while a request is being submitted.
+    balance_monitor: Option<tokio::sync::Mutex<BalanceMonitor>>,
+    /// The next Ethereum block to scan for `HeadUpdate` events.
+    head_updates_from: Option<u64>,
+    /// Where the step and skip function IDs come from, once `resolve_function_ids` read them.
+    function_ids_from: Option<FunctionIdSource>,
+    /// Whether the target is one of the mirror chains of the configured target before it. A
+    /// mirror reads the contract of that target, and shares its requests' input.
+    mirror: bool,
+}
+
+/// Keeps the light client contracts of its targets up to date, reading them through providers of
+/// type `M`.
+pub struct TendermintXOperator<M = Provider<FailoverHttp>> {
+    targets: Vec<Target<M>>,
+    backend: Box<dyn ProofBackend>,
+    data_fetcher: InputDataFetcher,
+    /// What the run loop reads the chain's headers through instead of `data_fetcher`, if set.
+    headers: Option<Arc<dyn HeaderFetcher>>,
+    /// The record of submitted requests, if any.
+    store: Option<Arc<RequestStore>>,
+    /// The append-only record of submissions and outcomes, if any.
+    audit: Option<Arc<AuditLog>>,
+    /// Signs an attestation of every submission, if set.
+    attester: Option<Attester>,
+    retry_policy: RetryPolicy,
+    /// The age after which a pending request is abandoned, if any.
+    max_request_age: Option<Duration>,
+    /// The limit on submissions shared by all targets, if any.
+    rate_limiter: Option<Arc<RateLimiter>>,
+    /// How often the status of the pending requests in the store is refreshed while idle.
+    status_poll_interval: Duration,
+    /// The maximum time to wait for the requests of an iteration to land on-chain, before falling
+    /// back to the loop delay.
+    landing_timeout: Duration,
+    landing_poll_interval: Duration,
+    /// The requests the run loop is waiting for, drained on shutdown.
+    in_flight: Mutex<Vec<InFlight>>,
+    /// The maximum time to drain the in-flight requests on shutdown.
+    drain_timeout: Duration,
+    /// The listener for platform callbacks, if any.
+    webhook: Option<Webhook>,
+    metrics: Arc<OperatorMetrics>,
+    /// Where the metrics and health checks are served, if anywhere.
+    metrics_addr: Option<SocketAddr>,
+    health: Arc<Health>,
+    heartbeat: Arc<Heartbeat>,
+    alerter: Alerter,
+    /// The consecutive iterations after which failing submissions are alerted on.
+    failure_alert_threshold: u32,
+    /// The consecutive iterations in which no target accepted a request.
+    consecutive_failures: u32,
+    /// Alerts on targets that stay behind, if set.
+    lag_monitor: Option<LagMonitor>,
+    /// Tells a halted chain from a stalled light client.
+    staleness: StalenessMonitor,
+    /// Submits the skips closing a large gap back to back.
+    catch_up: CatchUp,
+    /// The lag a group of targets must reach before a request is submitted for it.
+    min_lag: MinLag,
+    /// Selects the block each request proves.
+    selector: Box<dyn TargetSelector>,
+    /// Asked whether each request is needed before submitting it, if set.
+    gating: Option<Gating>,
+    /// Requests steps past skips that keep failing, unless disabled.
+    fallback: Option<StepFallback>,
+    /// Whether submissions are paused, toggled over the control socket.
+    control: Arc<Control>,
+    /// Where `tendermintx ctl` commands are served, if anywhere.
+    control_socket: Option<PathBuf>,
+    /// Jitters and staggers the run loop, so that replicas don't submit at the same time.
+    schedule: Schedule,
+    /// Elects the operator that submits among replicas, if set. Only the run loop takes part.
+    election: Option<Arc<LeaderElection>>,
+    /// The `skip_max` of every target, read before the first iteration.
+    skip_maxes: Vec<u64>,
+    /// The constants of the Tendermint chain, if known.
+    chain_spec: Option<ChainSpec>,
+    /// The heights of the chain's upgrades, which no request proves past before landing on them.
+    halt_heights: HaltHeights,
+    circuit_digests: ExpectedDigests,
+    artifact_manifest: Option<PathBuf>,
+    allow_circuit_mismatch: bool,
+    /// The circuit digests `verify_artifacts` found matching, recorded with each submission.
+    verified_digests: BTreeMap<B256, B256>,
+}
+
+/// An operator shared by the handlers of the API of `serve`. Requesting proofs, looking them up
+/// and collecting the status only read it (see `shared_status`), so none waits for another.
+pub type SharedOperator<M = Provider<FailoverHttp>> =
+    Arc<tokio::sync::RwLock<TendermintXOperator<M>>>;
+
+/// The trusted block a request starts from, and where its header hash comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum TrustedState {
+    /// Read from the contract of the targets, as `export_input` does.
+    FromContract { block: Height },
+    /// Given by the caller, as `prove` does, or read with the latest block, as the run loop does.
+    Explicit { block: Height, hash: HeaderHash },
+}
+
+impl TrustedState {
+    fn block(&self) -> Height {
+        match *self {
+            Self::FromContract { block } | Self::Explicit { block, .. } => block,
+        }
+    }
+}
+
+/// The state of the contract of a target, read once per iteration of the run loop: the
+/// consistency check and the request both take it from here.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct IterationState {
+    /// The latest block the contract stores.
+    latest_block: Height,
+    /// The header hash the contract stores for it, zero if none.
+    header_hash: HeaderHash,
+}
+
+impl IterationState {
+    async fn read(trusted: &dyn TrustedStateProvider) -> Result<Self> {
+        let latest_block = trusted.latest_block().await?;
+        let header_hash = trusted.hash_at(latest_block).await?.unwrap_or_default();
+        Ok(Self {
+            latest_block,
+            header_hash,
+        })
+    }
+
+    /// The trusted state of a request from the latest block. The header hash is the chain's once
+    /// `is_consistent` checked it.
+    fn trusted(&self) -> TrustedState {
+        TrustedState::Explicit {
+            block: self.latest_block,
+            hash: self.header_hash,
+        }
+    }
+}
+
+/// A contract storing a header hash that isn't the chain's, typically a wrong genesis header.
+/// Unlike the other failures of an iteration, retrying can't help: `run` returns it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderMismatch {
+    pub target: String,
+    pub block: u64,
+    pub tendermint_header: HeaderHash,
+    pub contract_header: HeaderHash,
+}
+
+impl std::fmt::Display for HeaderMismatch {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "Current header in the contract {} does not match chain's header hash for block {}: \
+             {} from Tendermint RPC, {} from the contract",
+            self.target, self.block, self.tendermint_header, self.contract_header
+        )
+    }
+}
+
+impl std::error::Error for HeaderMismatch {}
+
+struct Webhook {
+    addr: SocketAddr,
+    handler: Arc<WebhookHandler>,
+}
+
+/// A request accepted by some of the targets in an iteration of the run loop.
+#[derive(Debug, Clone)]
+pub struct Chunk {
+    /// The indices of the targets that accepted it.
+    pub targets: Vec<usize>,
+    /// The request ID of each of these targets, in order.
+    pub request_ids: Vec<String>,
+    pub trusted_block: u64,
+    pub target_block: u64,
+    pub skip_max: u64,
+    /// The lag of the targets behind the chain head before and after the request.
+    pub lag: u64,
+    pub remaining: u64,
+    pub correlation_id: CorrelationId,
+}
+
+/// What an iteration of the run loop submitted.
+#[derive(Debug, Clone)]
+pub struct IterationOutcome {
+    pub any_submitted: bool,
+    /// Whether a group of targets was skipped as its lag is under the minimum.
+    pub below_min_lag: bool,
+    /// The soonest time a skipped group reaches the minimum lag, if the chain spec is known.
+    pub min_lag_eta: Option<Duration>,
+    /// Whether a request was skipped as the gate found no demand for it.
+    pub gated: bool,
+    /// Whether submissions were paused, or left to the leader.
+    pub monitoring_only: bool,
+    pub chunks: Vec<Chunk>,
+    /// The summary of the iteration, as it was reported.
+    pub summary: IterationSummary,
+}
+
+/// A request accepted by the platform for a target.
+#[derive(Debug, Clone)]
+pub struct SubmittedRequest {
+    pub target: RequestTarget,
+    pub kind: RequestKind,
+    pub trusted_block: Height,
+    pub target_block: Height,
+    pub request_id: String,
+    /// The ID of the attempt the request was submitted for.
+    pub correlation_id: CorrelationId,
+}
+
+/// A proof requested up to a block the chain hasn't produced yet. The request is wrong, not the
+/// operator: retrying it fails the same way until the chain reaches the block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BeyondChainHead {
+    pub target_block: u64,
+    pub chain_head: u64,
+}
+
+impl std::fmt::Display for BeyondChainHead {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "target block {} is after the chain head {}",
+            self.target_block, self.chain_head
+        )
+    }
+}
+
+impl std::error::Error for BeyondChainHead {}
+
+/// A proof request the operator refuses as asked, e.g. a range crossing a halt height or a trusted
+/// hash that isn't the chain's, rather than one failing to read the chain or the contracts, or to
+/// submit. Retrying it fails the same way.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RefusedRequest(pub String);
+
+impl RefusedRequest {
+    /// Whether `error` refuses the request as asked: a `RefusedRequest`, `BeyondChainHead` or
+    /// `TrustedStateExpired`.
+    pub fn is(error: &anyhow::Error) -> bool {
+        error.is::<RefusedRequest>()
+            || error.is::<BeyondChainHead>()
+            || error.is::<TrustedStateExpired>()
+    }
+}
+
+impl std::fmt::Display for RefusedRequest {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for RefusedRequest {}
+
+/// The number of Ethereum blocks (about a day) scanned for `HeadUpdate` events on startup, so that
+/// requests submitted before a restart are correlated with their on-chain update.
+const HEAD_UPDATE_LOOKBACK: u64 = 7200;
+
+/// The delay between iterations of the run loop when a request doesn't land in time, in minutes.
+const LOOP_DELAY: u64 = 240;
+
+/// The delay before the next iteration when a target was skipped as its lag is under the
+/// minimum, in minutes. Shorter than the loop delay, so a chain that gets busy again isn't left
+/// behind for a whole loop.
+const MIN_LAG_DELAY: u64 = 30;
+
+/// The delay before the next iteration when the gate found no demand for a request, in minutes.
+const GATED_DELAY: u64 = 15;
+
+/// The delay before retrying an iteration that failed or whose requests all failed, in minutes.
+/// Short, as the failures are usually transient, but enough not to hammer the RPCs, the contracts
+/// and the backend.
+const RETRY_DELAY: u64 = 1;
+
+/// The delay between iterations of the run loop while submissions are paused or left to the
+/// leader, in minutes, so that resuming or taking over takes effect soon.
+const MONITORING_DELAY: u64 = 5;
+
+impl<M: Middleware + 'static> TendermintXOperator<M> {
+    /// An operator for the targets of `config`, reading the Tendermint chain with `data_fetcher`
+    /// (with the RPC adapter of the chain spec, if any) and proving with `backend`. `providers`
+    /// has the Ethereum provider of each target, in the same order as `config.targets`. Opens the
+    /// request store and audit log, if configured.
+    pub fn new(
+        config: TendermintXConfig,
+        mut data_fetcher: InputDataFetcher,
+        backend: Box<dyn ProofBackend>,
+        providers: Vec<Arc<M>>,
+    ) -> Result<Self> {
+        ensure!(!config.targets.is_empty(), "no targets to update");
+        ensure!(
+            providers.len() == config.targets.len(),
+            "{} providers for {} targets",
+            providers.len(),
+            config.targets.len()
+        );
+        if let Some(spec) = config.chain_spec.as_ref() {
+            data_fetcher.set_adapter(spec.rpc_adapter.adapter());
+        }
+        let mut health = Health::new(config.max_iteration_age, config.readiness_timeout)
+            .with_check(TendermintRpcCheck::new(data_fetcher.endpoints.urls()));
+        let mut targets = Vec::new();
+        for (request, provider) in config.targets.into_iter().zip(providers) {
+            let contract = TendermintXContract::new(request.address, provider.clone());
+            health = health.with_check(ContractCheck::new(
+                request.clone(),
+                TendermintXContract::new(request.address, provider.clone()),
+            ));
+            let balance_monitor = match config.relayer.as_ref() {
+                Some(relayer) => Some(tokio::sync::Mutex::new(
+                    BalanceMonitor::from_native_threshold(
+                        relayer.address,
+                        &relayer.balance_threshold,
+                        relayer.gas_per_transaction,
+                    )
+                    .context("invalid relayer balance threshold")?,
+                )),
+                None => None,
+            };
+            let trusted: Arc<dyn TrustedStateProvider> =
+                Arc::new(TendermintXContract::new(request.address, provider.clone()));
+            let mirrors = request
+                .mirror_chain_ids
+                .iter()
+                .map(|&chain_id| {
+                    Ok(Target {
+                        request: request.mirror(chain_id)?,
+                        provider: provider.clone(),
+                        contract: TendermintXContract::new(request.address, provider.clone()),
+                        trusted: trusted.clone(),
+                        balance_monitor: None,
+                        head_updates_from: None,
+                        function_ids_from: None,
+                        mirror: true,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            targets.push(Target {
+                request,
+                provider,
+                contract,
+                trusted,
+                balance_monitor,
+                head_updates_from: None,
+                function_ids_from: None,
+                mirror: false,
+            });
+            targets.extend(mirrors);
+        }
+
+        let mut backend = backend;
+        let rate_limiter = config
+            .max_requests_per_hour
+            .map(|max| Arc::new(RateLimiter::per_hour(max)));
+        if let Some(limiter) = rate_limiter.as_ref() {
+            backend = Box::new(RateLimitedBackend::new(backend, limiter.clone()));
+        }
+
+        let store = match config.store_path {
+            Some(path) => {
+                let store = RequestStore::open(path).context("could not open request store")?;
+                Some(Arc::new(with_postgres_sink(store, config.postgres_dsn)?))
+            }
+            None if config.postgres_dsn.is_some() => {
+                return Err(anyhow!("the Postgres sink requires a request store"))
+            }
+            None => None,
+        };
+        let audit = match config.audit {
+            Some(audit) => Some(Arc::new(
+                AuditLog::open(audit.path, audit.max_bytes, audit.max_files)
+                    .context("could not open audit log")?,
+            )),
+            None => None,
+        };
+        let attester = match config.attestation_key_path {
+            Some(path) => {
+                let attester = Attester::load(&path)?;
+                info!(
+                    "Signing submissions with the attestation key {}",
+                    attestation::public_key_hex(&attester.public_key())
+                );
+                Some(attester)
+            }
+            None => None,
+        };
+
+        // Callbacks update the request store, so the listener needs one.
+        let webhook = match config.webhook {
+            Some(webhook) => {
+                let store = store
+                    .clone()
+                    .ok_or_else(|| anyhow!("the callback listener requires a request store"))?;
+                let mut handler = WebhookHandler::new(store, webhook.secret);
+                if let Some(audit) = audit.as_ref() {
+                    handler = handler.with_audit(audit.clone());
+                }
+                Some(Webhook {
+                    addr: webhook.addr,
+                    handler: Arc::new(handler),
+                })
+            }
+            None => None,
+        };
+
+        let mut alerter = Alerter::new(config.alert_webhooks, config.alert_dedup_window);
+        if let Some(pagerduty) = config.pagerduty {
+            alerter = alerter.with_pagerduty(pagerduty);
+        }
+
+        let metrics = Arc::new(OperatorMetrics::new());
+        metrics.register_endpoints(data_fetcher.endpoints.clone());
+        if let Some(limiter) = rate_limiter.as_ref() {
+            metrics.register_rate_limiter(limiter.clone());
+        }
+        let mut heartbeat = Heartbeat::new(metrics.clone());
+        if let Some(file) = config.heartbeat_file {
+            heartbeat = heartbeat.with_file(file);
+        }
+        if let Some(url) = config.heartbeat_url {
+            heartbeat = heartbeat.with_url(url);
+        }
+
+        Ok(Self {
+            targets,
+            backend,
+            data_fetcher,
+            headers: None,
+            store,
+            audit,
+            attester,
+            retry_policy: config.retry_policy,
+            max_request_age: config.max_request_age,
+            rate_limiter,
+            status_poll_interval: config.status_poll_interval,
+            landing_timeout: config.landing_timeout,
+            landing_poll_interval: config.landing_poll_interval,
+            in_flight: Mutex::new(Vec::new()),
+            drain_timeout: config.drain_timeout,
+            webhook,
+            metrics,
+            metrics_addr: config.metrics_addr,
+            health: Arc::new(health),
+            heartbeat: Arc::new(heartbeat),
+            alerter,
+            failure_alert_threshold: config.failure_alert_threshold,
+            consecutive_failures: 0,
+            lag_monitor: config.lag_monitor,
+            staleness: config.staleness,
+            catch_up: config.catch_up,
+            min_lag: config.min_lag,
+            selector: config.selector,
+            gating: config.gating,
+            fallback: config.fallback,
+            control: Arc::new(Control::new()),
+            control_socket: config.control_socket,
+            schedule: config.schedule,
+            election: None,
+            skip_maxes: Vec::new(),
+            chain_spec: config.chain_spec,
+            halt_heights: HaltHeights::new(config.halt_heights, config.upgrade_discovery),
+            circuit_digests: config.circuit_digests,
+            artifact_manifest: config.artifact_manifest,
+            allow_circuit_mismatch: config.allow_circuit_mismatch,
+            verified_digests: BTreeMap::new(),
+        })
+    }
+
+    /// The metrics of the operator, e.g. to register more endpoint pools with.
+    pub fn metrics(&self) -> &Arc<OperatorMetrics> {
+        &self.metrics
+    }
+
+    /// Take the controls of `control` instead of its own, e.g. those shared by a chain registry.
+    pub fn set_control(&mut self, control: Arc<Control>) {
+        self.control = control;
+    }
+
+    /// Record iterations in `health` instead of its own, e.g. that shared by a chain registry.
+    pub fn set_health(&mut self, health: Arc<Health>) {
+        self.health = health;
+    }
+
+    /// Append to `audit` instead of its own audit log, e.g. that shared by a chain registry.
+    pub fn set_audit(&mut self, audit: Arc<AuditLog>) {
+        self.audit = Some(audit);
+    }
+
+    /// Read the chain's headers in the run loop through `headers` instead of the data fetcher: the
+    /// chain head, the headers the contracts are checked against and those the selector reads.
+    /// Input generation still reads the data fetcher.
+    pub fn set_header_fetcher(&mut self, headers: Arc<dyn HeaderFetcher>) {
+        self.headers = Some(headers);
+    }
+
+    /// What the chain's headers are read through.
+    fn headers(&self) -> &dyn HeaderFetcher {
+        match self.headers.as_ref() {
+            Some(headers) => headers.as_ref(),
+            None => &self.data_fetcher,
+        }
+    }
+
+    /// Take part in `election`: only the leader submits.
+    pub fn set_election(&mut self, election: Arc<LeaderElection>) {
+        self.election = Some(election);
+    }
+
+    /// The record of submitted requests, if any.
+    pub fn store(&self) -> Option<&Arc<RequestStore>> {
+        self.store.as_ref()
+    }
+
+    /// Record to the request store and audit log of `other` instead of its own, e.g. for the API
+    /// beside the run loop, so that the requests of each are deduplicated against the other's.
+    pub fn share_records(&mut self, other: &Self) {
+        self.store = other.store.clone();
+        self.audit = other.audit.clone();
+    }
+
+    /// The trusted block `block`, or the latest block of the first target if `None`, and the
+    /// header hash its contract stores for it.
+    pub async fn stored_trusted_header(
+        &self,
+        block: Option<Height>,
+    ) -> Result<(Height, HeaderHash)> {
+        let target = self
+            .targets
+            .first()
+            .ok_or_else(|| anyhow!("no targets to request"))?;
+        let block = match block {
+            Some(block) => block,
+            None => target.trusted.latest_block().await?,
+        };
+        self.trusted_header(&[target], TrustedState::FromContract { block })
+            .await
+    }
+
+    /// Read the latest block, header hashes, `skip_max` and function IDs of the target at `index`
+    /// (in the order of the config) from `trusted` instead of its contract, e.g. for a target that
+    /// isn't an EVM chain. `HeadUpdate` events are still scanned on the contract. The mirror chains
+    /// of the target read `trusted` as well.
+    pub fn set_trusted_state(
+        &mut self,
+        index: usize,
+        trusted: Arc<dyn TrustedStateProvider>,
+    ) -> Result<()> {
+        let start = self
+            .targets
+            .iter()
+            .enumerate()
+            .filter(|(_, t)| !t.mirror)
+            .nth(index)
+            .map(|(i, _)| i)
+            .ok_or_else(|| anyhow!("no target {}", index))?;
+        for target in self.targets[start..]
+            .iter_mut()
+            .enumerate()
+            .take_while(|(i, t)| *i == 0 || t.mirror)
+            .map(|(_, t)| t)
+        {
+            target.trusted = trusted.clone();
+        }
+        Ok(())
+    }
+
+    async fn request_step<'a>(
+        &self,
+        targets: &[&'a Target<M>],
+        trusted: TrustedState,
+        correlation_id: CorrelationId,
+    ) -> Result<Vec<TargetSubmission<'a>>> {
+        let inputs = self
+            .request_inputs(targets, trusted, trusted.block().next())
+            .await?;
+        self.submit_inputs(targets, &inputs, correlation_id).await
+    }
+
+    async fn request_skip<'a>(
+        &self,
+        targets: &[&'a Target<M>],
+        trusted: TrustedState,
+        target_block: Height,
+        correlation_id: CorrelationId,
+    ) -> Result<Vec<TargetSubmission<'a>>> {
+        let inputs = self.request_inputs(targets, trusted, target_block).await?;
+        self.submit_inputs(targets, &inputs, correlation_id).await
+    }
+
+    /// Like `request_skip`, but commits to the data hashes of the headers up to `target_block`.
+    /// Every target of `targets` must take data commitments.
+    async fn request_data_commitment<'a>(
+        &self,
+        targets: &[&'a Target<M>],
+        trusted: TrustedState,
+        target_block: Height,
+        correlation_id: CorrelationId,
+    ) -> Result<Vec<TargetSubmission<'a>>> {
+        for target in targets {
+            ensure!(
+                target.request.data_commitment_function_id.is_some(),
+                "data commitments are not enabled for {}",
+                target.request
+            );
+        }
+        let (trusted_block, trusted_header_hash) = self.trusted_header(targets, trusted).await?;
+        let inputs =
+            RequestInputs::data_commitment(trusted_block, trusted_header_hash, target_block)?;
+        self.submit_inputs(targets, &inputs, correlation_id).await
+    }
+
+    /// Submit the request of `inputs` to every target of `targets` without a pending request for
+    /// it, as the attempt `correlation_id`.
+    async fn submit_inputs<'a>(
+        &self,
+        targets: &[&'a Target<M>],
+        inputs: &RequestInputs,
+        correlation_id: CorrelationId,
+    ) -> Result<Vec<TargetSubmission<'a>>> {
+        let kind = inputs.kind;
+        let (targets, _reservations) = self
+            .without_pending_request(
+                targets.iter().map(|t| &t.request),
+                kind,
+                inputs.trusted_block.value(),
+                inputs.target_block.value(),
+            )
+            .await;
+        let submissions = submit_to_targets(targets, |target| {
+            let request = inputs
+                .proof_request(target)
+                .with_correlation_id(correlation_id);
+            async move { self.submit(kind, &request).await }
+        })
+        .await;
+        self.record_submissions(&submissions, inputs, correlation_id);
+        Ok(submissions)
+    }
+
+    /// The inputs of the request from `trusted` to `target_block`.
+    async fn request_inputs(
+        &self,
+        targets: &[&Target<M>],
+        trusted: TrustedState,
+        target_block: Height,
+    ) -> Result<RequestInputs> {
+        let (trusted_block, trusted_header_hash) = self.trusted_header(targets, trusted).await?;
+        RequestInputs::new(trusted_block, trusted_header_hash, target_block)
+    }
+
+    /// The trusted block and header hash of `trusted`. Targets are grouped by their latest block,
+    /// so the first of `targets` holds the trusted header if it isn't given.
+    async fn trusted_header(
+        &self,
+        targets: &[&Target<M>],
+        trusted: TrustedState,
+    ) -> Result<(Height, HeaderHash)> {
+        Ok(match trusted {
+            TrustedState::FromContract { block } => {
+                let target = targets
+                    .first()
+                    .ok_or_else(|| anyhow!("no targets to request"))?;
+                let hash = target.trusted.hash_at(block).await?.ok_or_else(|| {
+                    RefusedRequest(format!("no header stored for trusted block {}", block))
+                })?;
+                (block, hash)
+            }
+            TrustedState::Explicit { block, hash } => (block, hash),
+        })
+    }
+
+    /// The targets that have no pending `kind` request for the same range, either in the request
+    /// store or with the backend. The backend is checked as well so that requests submitted before
+    /// a restart are not resubmitted, even without a store. With a store, the range of each target
+    /// returned is reserved in it until the reservations are dropped, after recording the
+    /// requests: another submission for the range meanwhile skips the target.
+    async fn without_pending_request<'a>(
+        &self,
+        targets: impl IntoIterator<Item = &'a RequestTarget>,
+        kind: RequestKind,
+        trusted_block: u64,
+        target_block: u64,
+    ) -> (Vec<&'a RequestTarget>, Vec<Reservation<'_>>) {
+        let mut recent_requests: HashMap<B256, Vec<RecentRequest>> = HashMap::new();
+        let mut remaining = Vec::new();
+        let mut reservations = Vec::new();
+        for target in targets {
+            let function_id = kind.function_id(target);
+            if let Some(store) = self.store.as_ref() {
+                let reservation = store.reserve(
+                    target.chain_id,
+                    target.address,
+                    function_id,
+                    trusted_block,
+                    target_block,
+                );
+                let Some(reservation) = reservation else {
+                    info!(
+                        "A request for {} from {} to {} is being submitted, not resubmitting [{}]",
+                        target, trusted_block, target_block, target.labels
+                    );
+                    continue;
+                };
+                reservations.push(reservation);
+                match store.find_pending(
+                    target.chain_id,
+                    target.address,
+                    function_id,
+                    trusted_block,
+                    target_block,
+                ) {
+                    Ok(Some(record)) => {
+                        info!(
+                            "Request {} for {} from {} to {} is still pending, not resubmitting [{}]",
+                            record.request_id, target, trusted_block, target_block, target.labels
+                        );
+                        continue;
+                    }
+                    Ok(None) => {}
+                    Err(e) => error!("Failed to query the request store: {:#}", e),
+                }
+            }
+
+            if !recent_requests.contains_key(&function_id) {
+                let requests = self
+                    .backend
+                    .recent_requests(function_id)
+                    .await
+                    .unwrap_or_else(|e| {
+                        warn!(
+                            "Failed to list recent requests, not checking them for duplicates: {:#}",
+                            e
+                        );
+                        Vec::new()
+                    });
+                // Abandoned requests no longer block a new request for the same range.
+                let requests = requests
+                    .into_iter()
+                    .filter(|r| !self.is_abandoned(&r.request_id))
+                    .collect();
+                recent_requests.insert(function_id, requests);
+            }
+            if let Some(request) = find_unfulfilled(
+                &recent_requests[&function_id],
+                target,
+                kind,
+                trusted_block,
+                target_block,
+            ) {
+                info!(
+                    "Request {} for {} from {} to {} is still unfulfilled, not resubmitting [{}]",
+                    request.request_id, target, trusted_block, target_block, target.labels
+                );
+                continue;
+            }
+            remaining.push(target);
+        }
+        (remaining, reservations)
+    }
+
+    /// The name of the backend that served a request.
+    fn served_by(&self, request_id: &str) -> String {
+        self.backend
+            .served_by(request_id)
+            .unwrap_or_else(|| self.backend.name().to_string())
+    }
+
+    /// Whether the request store marks a request as abandoned.
+    fn is_abandoned(&self, request_id: &str) -> bool {
+        self.store.as_ref().map_or(false, |store| {
+            matches!(
+                store.get(request_id),
+                Ok(Some(record)) if record.status == RequestStatus::Abandoned
+            )
+        })
+    }
+
+    /// Abandon the pending requests older than the maximum request age and cancel them with the
+    /// backend, so that the loop requests a fresh target instead of waiting for them.
+    async fn abandon_stale_requests(&self) {
+        let (Some(store), Some(max_age)) = (self.store.as_ref(), self.max_request_age) else {
+            return;
+        };
+        let abandoned = match store.abandon_stale(unix_timestamp(), max_age) {
+            Ok(abandoned) => abandoned,
+            Err(e) => {
+                error!("Failed to abandon stale requests: {:#}", e);
+                return;
+            }
+        };
+        for record in abandoned {
+            warn!(
+                "Abandoned request {} for {}:{} from {} to {}, pending for more than {:?} [{}]",
+                record.request_id,
+                record.chain_id,
+                record.contract_address,
+                record.trusted_block,
+                record.target_block,
+                max_age,
+                record.labels
+            );
+            match self.backend.cancel(&record.request_id).await {
+                Ok(true) => info!(
+                    "Cancelled request {} [{}]",
+                    record.request_id, record.labels
+                ),
+                Ok(false) => {}
+                Err(e) => warn!(
+                    "Failed to cancel request {} [{}]: {:#}",
+                    record.request_id, record.labels, e
+                ),
+            }
+        }
+    }
+
+    /// Log the unfulfilled requests for each target, e.g. those submitted before a restart.
+    async fn log_unfulfilled_requests(&self) {
+        for target in self.targets.iter().map(|t| &t.request) {
+            for function_id in [target.step_function_id, target.skip_function_id] {
+                match self.backend.recent_requests(function_id).await {
+                    Ok(requests) => {
+                        for request in requests.iter().filter(|r| {
+                            r.is_unfulfilled()
+                                && r.chain_id == target.chain_id
+                                && r.address == target.address
+                        }) {
+                            info!(
+                                "Request {} for {} is unfulfilled (range {:?})",
+                                request.request_id,
+                                target,
+                                request.range()
+                            );
+                        }
+                    }
+                    Err(e) => warn!("Failed to list recent requests for {}: {:#}", target, e),
+                }
+            }
+        }
+    }
+
+    /// Record the sizes of the submissions of `inputs`, and the accepted ones in the request store.
+    fn record_submissions(
+        &self,
+        submissions: &[TargetSubmission],
+        inputs: &RequestInputs,
+        correlation_id: CorrelationId,
+    ) {
+        self.metrics.record_request_sizes(inputs, submissions);
+        let Some(store) = self.store.as_ref() else {
+            return;
+        };
+        for submission in submissions {
+            if let Ok(request_id) = &submission.result {
+                let request = NewRequest {
+                    chain_id: submission.target.chain_id,
+                    contract_address: submission.target.address,
+                    trusted_block: inputs.trusted_block.value(),
+                    trusted_hash: inputs.trusted_header_hash.to_bytes(),
+                    target_block: inputs.target_block.value(),
+                    function_id: inputs.kind.function_id(submission.target),
+                    request_id: request_id.clone(),
+                    retry_of: None,
+                    attempt: 1,
+                    backend: Some(self.served_by(request_id)),
+                    request_mode: submission.target.request_mode,
+                    labels: submission.target.labels.clone(),
+                    input: inputs.input.clone(),
+                    correlation_id: Some(correlation_id),
+                };
+                if let Err(e) = store.insert(&request) {
+                    error!(
+                        "Failed to record request {} [{}]: {:#}",
+                        request_id, submission.target.labels, e
+                    );
+                }
+            }
+        }
+    }
+
+    /// Correlate the `HeadUpdate` events emitted since the last scan with the stored requests for
+    /// their target block, recording when each request landed on-chain.
+    async fn record_head_updates(&mut self) {
+        let Some(store) = self.store.as_ref() else {
+            return;
+        };
+        let audit = self.audit.as_deref();
+        // The contract of a mirror chain isn't read: its requests land unrecorded.
+        for target in self.targets.iter_mut().filter(|t| !t.mirror) {
+            if let Err(e) = Self::scan_head_updates(store, audit, target).await {
+                warn!(
+                    "Failed to scan HeadUpdate events for {}: {:#}",
+                    target.request, e
+                );
+            }
+        }
+    }
+
+    async fn scan_head_updates(
+        store: &RequestStore,
+        audit: Option<&AuditLog>,
+        target: &mut Target<M>,
+    ) -> Result<()> {
+        let latest = target.provider.get_block_number().await?.as_u64();
+        let from = target
+            .head_updates_from
+            .unwrap_or_else(|| latest.saturating_sub(HEAD_UPDATE_LOOKBACK));
+        for update in target.contract.head_updates(from).await? {
+            let block = target
+                .provider
+                .get_block(update.eth_block_number)
+                .await?
+                .ok_or_else(|| anyhow!("block {} not found", update.eth_block_number))?;
+            let correlated = store.record_head_update(
+                target.request.chain_id,
+                target.request.address,
+                update.block_number,
+                block.timestamp.as_u64(),
+            )?;
+            store.publish(SinkEvent::HeadUpdate {
+                chain_id: target.request.chain_id,
+                contract_address: target.request.address,
+                block_number: update.block_number,
+                header_hash: B256::from(update.header_hash),
+                tx_hash: B256::from(update.tx_hash),
+                eth_block_number: update.eth_block_number,
+                timestamp: block.timestamp.as_u64(),
+            });
+            if let Some(audit) = audit {
+                let event = AuditEvent::HeadUpdate {
+                    chain_id: target.request.chain_id,
+                    address: target.request.address.to_string(),
+                    block_number: update.block_number,
+                    header_hash: B256::from(update.header_hash).to_string(),
+                    tx_hash: B256::from(update.tx_hash).to_string(),
+                    eth_block_number: update.eth_block_number,
+                    correlated,
+                };
+                if let Err(e) = audit.record(event) {
+                    error!("Failed to write audit log entry: {:#}", e);
+                }
+            }
+            if correlated > 0 {
+                info!(
+                    "Header {} landed on {} in tx {}",
+                    update.block_number,
+                    target.request,
+                    B256::from(update.tx_hash)
+                );
+            }
+        }
+        target.head_updates_from = Some(latest + 1);
+        Ok(())
+    }
+
+    /// For each target, the index of the first target reading the same trusted state, e.g. the
+    /// target a mirror is of: its own index if it is the first.
+    fn trusted_sources(&self) -> Vec<usize> {
+        self.targets
+            .iter()
+            .enumerate()
+            .map(|(i, target)| {
+                self.targets
+                    .iter()
+                    .position(|t| Arc::ptr_eq(&t.trusted, &target.trusted))
+                    .unwrap_or(i)
+            })
+            .collect()
+    }
+
+    /// Check that the contract of `target` stores the chain's header for its latest block, as read
+    /// into `state`, and return the unix timestamp of that header.
+    async fn is_consistent(&self, target: &Target<M>, state: &IterationState) -> Result<i64> {
+        let current_block = state.latest_block;
+        let start = Instant::now();
+        let expected_header = self
+            .headers()
+            .header_hash_and_time(current_block.value())
+            .await;
+        self.metrics
+            .observe_fetch("get_signed_header_from_number", start.elapsed());
+        let (expected_header, header_time) = expected_header?;
+        let contract_current_header = state.header_hash;
+        let consistent = expected_header == contract_current_header;
+        self.metrics.record_consistency(&target.request, consistent);
+        if consistent {
+            self.alerter.resolve(AlertKind::ConsistencyMismatch).await;
+        } else {
+            let alert = Alert::new(
+                AlertKind::ConsistencyMismatch,
+                format!("header mismatch on {}", target.request),
+            )
+            .with_detail("chain_id", target.request.chain_id)
+            .with_detail("address", target.request.address)
+            .with_detail("block", current_block)
+            .with_detail("tendermint_header", expected_header)
+            .with_detail("contract_header", contract_current_header);
+            self.alerter.send(&alert).await;
+            return Err(HeaderMismatch {
+                target: target.request.to_string(),
+                block: current_block.value(),
+                tendermint_header: expected_header,
+                contract_header: contract_current_header,
+            }
+            .into());
+        }
+        Ok(header_time)
+    }
+
+    /// Log the outcome of a request for each target. Returns true if at least one target accepted
+    /// the request, or if there was nothing to submit because every target already has a pending
+    /// request.
+    fn log_submissions(request_type: &str, submissions: &[TargetSubmission]) -> bool {
+        for submission in submissions {
+            match &submission.result {
+                Ok(request_id) => {
+                    info!(
+                        "{} request submitted for {}: {} [{}]",
+                        request_type, submission.target, request_id, submission.target.labels
+                    )
+                }
+                Err(e) => {
+                    error!(
+                        "{} request failed for {} [{}]: {}",
+                        request_type, submission.target, submission.target.labels, e
+                    );
+                    // Retried in the next iteration.
+                    let message =
+                        format!("{} request failed for {}", request_type, submission.target);
+                    reporting::breadcrumb("submission", message);
+                }
+            }
+        }
+        submissions.is_empty() || submissions.iter().any(|s| s.result.is_ok())
+    }
+
+    /// Run the loop: start the configured listeners, then repeatedly submit a request for each
+    /// group of targets and wait for them to land. A failed iteration is retried after a short
+    /// delay: only returns on a failure retrying can't fix, a contract storing a header that isn't
+    /// the chain's (`HeaderMismatch`). Dropping the future stops the loop, even in the middle of
+    /// a submission: use `run_until` to stop it cleanly.
+    pub async fn run(&mut self) -> Result<()> {
+        self.run_until(std::future::pending()).await
+    }
+
+    /// Run the loop like `run` until `shutdown` resolves. An iteration that started submitting
+    /// then completes, so that its requests are made and recorded, and the loop returns `Ok` at
+    /// its next wait. `drain` then waits for the requests it was waiting for.
+    pub async fn run_until(&mut self, shutdown: impl Future<Output = ()>) -> Result<()> {
+        let (stop, stopped) = watch::channel(false);
+        let run = self.run_loop(stopped);
+        tokio::pin!(run, shutdown);
+        tokio::select! {
+            result = &mut run => return result,
+            _ = &mut shutdown => {}
+        }
+        info!("Stopping the run loop once the current iteration is recorded");
+        stop.send_replace(true);
+        run.await
+    }
+
+    /// The loop of `run_until`, returning `Ok` at its first wait once `stopped` is set.
+    async fn run_loop(&mut self, mut stopped: watch::Receiver<bool>) -> Result<()> {
+        self.log_unfulfilled_requests().await;
+        self.resolve_function_ids().await?;
+        self.verify_artifacts().await?;
+
+        let targets = self.targets.iter().map(|t| t.request.to_string());
+        let alert = Alert::new(AlertKind::Startup, "operator started")
+            .with_detail("targets", targets.collect::<Vec<_>>().join(","))
+            .with_detail("backend", self.backend.name());
+        self.alerter.send(&alert).await;
+
+        if let Some(webhook) = self.webhook.as_ref() {
+            let (handler, addr) = (webhook.handler.clone(), webhook.addr);
+            tokio::spawn(async move {
+                if let Err(e) = serve(handler, addr).await {
+                    error!("Callback listener stopped: {:#}", e);
+                }
+            });
+        }
+
+        if let Some(addr) = self.metrics_addr {
+            let listener = std::net::TcpListener::bind(addr)
+                .with_context(|| format!("could not bind the metrics listener to {}", addr))?;
+            let (metrics, health, store) = (
+                self.metrics.clone(),
+                self.health.clone(),
+                self.store.clone(),
+            );
+            tokio::spawn(async move {
+                if let Err(e) = metrics::serve(metrics, health, store, listener).await {
+                    error!("Metrics listener stopped: {:#}", e);
+                }
+            });
+        }
+
+        if let Some(path) = self.control_socket.clone() {
+            let control = self.control.clone();
+            tokio::spawn(async move {
+                if let Err(e) = control::serve(control, path).await {
+                    error!("Control socket stopped: {:#}", e);
+                }
+            });
+        }
+
+        if let Some(election) = self.election.clone() {
+            election.refresh().await;
+            tokio::spawn(async move { election.run().await });
+        }
+
+        let startup_delay = self
+            .schedule
+            .startup_delay(Duration::from_secs(60 * LOOP_DELAY));
+        if !startup_delay.is_zero() {
+            info!("Delaying the first iteration by {:?}", startup_delay);
+            let delay = tokio::time::sleep(startup_delay);
+            if until_stopped(&mut stopped, delay).await.is_none() {
+                return Ok(());
+            }
+        }
+
+        self.read_skip_maxes().await?;
+        let mut leading = self.is_leader();
+        let mut iteration: u64 = 0;
+        loop {
+            if *stopped.borrow() {
+                return Ok(());
+            }
+            // A follower taking over refreshes what the previous leader may have changed.
+            if self.is_leader() && !leading {
+                info!("Took over as the leader, refreshing the contracts and pending requests");
+                self.refresh_skip_maxes().await;
+                self.log_unfulfilled_requests().await;
+            }
+            leading = self.is_leader();
+
+            iteration += 1;
+            let span = info_span!("iteration", iteration, chain_head = field::Empty);
+            let minutes = |delay| Duration::from_secs(60 * delay);
+            // A failed read or fetch doesn't stop the loop: it's retried like a failed request.
+            let outcome = match self.run_once().instrument(span).await {
+                Ok(outcome) => outcome,
+                Err(e) if e.is::<HeaderMismatch>() => return Err(e),
+                Err(e) => {
+                    error!("Iteration {} failed: {:#}", iteration, e);
+                    let retry = self.sleep_refreshing(minutes(RETRY_DELAY));
+                    if until_stopped(&mut stopped, retry).await.is_none() {
+                        return Ok(());
+                    }
+                    continue;
+                }
+            };
+            // The requests of the iteration were made and recorded: the drain waits for them.
+            if *stopped.borrow() {
+                self.track_in_flight(&outcome.chunks);
+                return Ok(());
+            }
+
+            // Retry soon if no target accepted a request.
+            if !outcome.any_submitted {
+                info!(
+                    "No request accepted, retrying in {:?}",
+                    minutes(RETRY_DELAY)
+                );
+                let retry = self.sleep_refreshing(minutes(RETRY_DELAY));
+                if until_stopped(&mut stopped, retry).await.is_none() {
+                    return Ok(());
+                }
+                continue;
+            }
+
+            // While catching up, the next chunks are submitted as soon as these landed.
+            let chunks = outcome.chunks.iter();
+            if self
+                .catch_up
+                .record_chunks(chunks.map(|c| (c.lag, c.skip_max, c.remaining)))
+            {
+                self.track_in_flight(&outcome.chunks);
+                let wait = self.wait_for_chunks(&outcome.chunks);
+                if until_stopped(&mut stopped, wait).await.is_none() {
+                    return Ok(());
+                }
+                self.in_flight.lock().unwrap().clear();
+                continue;
+            }
+
+            // On a cron schedule, iterations are aligned to its ticks rather than to the landing of
+            // the requests, unless only monitoring.
+            if let Some(tick) = self.schedule.next_tick(Utc::now()) {
+                if !outcome.monitoring_only {
+                    if until_stopped(&mut stopped, self.sleep_until(tick))
+                        .await
+                        .is_none()
+                    {
+                        return Ok(());
+                    }
+                    continue;
+                }
+            }
+
+            let delay = if outcome.monitoring_only {
+                minutes(MONITORING_DELAY)
+            } else if outcome.gated {
+                minutes(GATED_DELAY)
+            } else if outcome.below_min_lag {
+                // Sooner if the chain spec says the minimum lag is reached before.
+                let delay = minutes(MIN_LAG_DELAY);
+                outcome.min_lag_eta.map_or(delay, |eta| eta.min(delay))
+            } else {
+                minutes(LOOP_DELAY)
+            };
+            let delay = self.schedule.delay(delay);
+
+            // Otherwise the next range is selected as soon as these requests landed, or after the
+            // rest of the loop delay if one of them didn't in time.
+            let start = Instant::now();
+            if !outcome.chunks.is_empty() {
+                // Stopping while waiting leaves the requests in flight for the drain.
+                let landing = self.wait_for_landing(&outcome.chunks);
+                match until_stopped(&mut stopped, landing).await {
+                    Some(true) => continue,
+                    Some(false) => {}
+                    None => return Ok(()),
+                }
+            }
+            let delay = delay.saturating_sub(start.elapsed());
+            info!("Next iteration in {:?}", delay);
+            if until_stopped(&mut stopped, self.sleep_refreshing(delay))
+                .await
+                .is_none()
+            {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Run a single iteration of the loop: submit a request for each group of targets at the same
+    /// latest block, without waiting for the requests to land. Unlike `run`, starts no listeners.
+    pub async fn run_once(&mut self) -> Result<IterationOutcome> {
+        if !self.function_ids_resolved() {
+            self.resolve_function_ids().await?;
+        }
+        if self.skip_maxes.is_empty() {
+            self.read_skip_maxes().await?;
+        }
+        self.metrics.record_iteration();
+        // The heartbeat fires whether or not the iteration submitted anything.
+        let heartbeat = self.heartbeat.clone();
+        let outcome = match heartbeat.after(self.run_iteration()).await {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                // An iteration that couldn't read the chain or the contracts submitted nothing.
+                self.record_iteration_outcome(false).await;
+                return Err(e);
+            }
+        };
+        self.health.record_iteration();
+        self.record_iteration_outcome(outcome.any_submitted).await;
+        Ok(outcome)
+    }
+
+    /// Run a single iteration like `run_once`, then wait up to `flush_timeout` for the background
+    /// writes to complete, and summarize the run. For `run --once`, whose process exits next.
+    pub async fn run_oneshot(&mut self, flush_timeout: Duration) -> RunSummary {
+        let started_at_ms = unix_timestamp_ms();
+        let iteration = self.run_once().await.map(|outcome| outcome.summary);
+        if let Err(e) = iteration.as_ref() {
+            error!("The iteration failed: {:#}", e);
+        }
+        let mut summary = RunSummary::of(started_at_ms, iteration);
+        let start = Instant::now();
+        let flushed = self.flush(flush_timeout).await;
+        summary.flushed(start.elapsed(), flushed);
+        summary
+    }
+
+    /// Wait up to `timeout` for the sink of the request store to write the events recorded so far,
+    /// and sync the audit log to disk. The store itself and the audit log write synchronously.
+    pub async fn flush(&self, timeout: Duration) -> Result<()> {
+        if let Some(sink) = self.store.as_ref().and_then(|store| store.sink()) {
+            if !sink.flush(timeout).await {
+                return Err(anyhow!(
+                    "the {} sink has {} events left after {:?}",
+                    sink.name(),
+                    sink.pending(),
+                    timeout
+                ));
+            }
+        }
+        if let Some(audit) = self.audit.as_ref() {
+            audit.sync()?;
+        }
+        Ok(())
+    }
+
+    /// Sleep until `tick` like `sleep_refreshing`, but without returning early on a platform
+    /// callback.
+    async fn sleep_until(&self, tick: DateTime<Utc>) {
+        info!(
+            "Next iteration at {}",
+            tick.to_rfc3339_opts(SecondsFormat::Secs, true)
+        );
+        while let Ok(remaining) = (tick - Utc::now()).to_std() {
+            self.sleep_refreshing(remaining).await;
+        }
+    }
+
+    /// Count the consecutive iterations in which no target accepted a request, alerting once they
+    /// reach the threshold.
+    async fn record_iteration_outcome(&mut self, any_submitted: bool) {
+        if any_submitted {
+            self.alerter.resolve(AlertKind::CircuitBreakerOpen).await;
+            self.consecutive_failures = 0;
+            return;
+        }
+        self.consecutive_failures += 1;
+        if self.consecutive_failures >= self.failure_alert_threshold {
+            let alert = Alert::new(AlertKind::CircuitBreakerOpen, "submissions are failing")
+                .with_detail("consecutive_failures", self.consecutive_failures)
+                .with_detail("backend", self.backend.name());
+            self.alerter.send(&alert).await;
+        }
+    }
+
+    /// Wait for the contracts of the targets of `chunks` to store their target blocks, or for their
+    /// requests to fail. Returns whether every request did before the landing timeout.
+    async fn wait_for_landing(&self, chunks: &[Chunk]) -> bool {
+        let deadline = tokio::time::Instant::now() + self.landing_timeout;
+        self.track_in_flight(chunks);
+        let mut settled = true;
+        for chunk in chunks {
+            settled &= self
+                .wait_for_chunk(chunk, deadline)
+                .instrument(attempt_span(chunk.correlation_id))
+                .await;
+        }
+        self.in_flight.lock().unwrap().clear();
+        settled
+    }
+
+    /// Record the requests of `chunks` as the ones the run loop is waiting for, to be drained if
+    /// it stops before they settle. Off-chain proofs aren't relayed by the run loop.
+    fn track_in_flight(&self, chunks: &[Chunk]) {
+        *self.in_flight.lock().unwrap() = chunks
+            .iter()
+            .flat_map(|chunk| chunk.targets.iter().zip(chunk.request_ids.iter()))
+            .map(|(&index, request_id)| InFlight {
+                request_id: request_id.clone(),
+                mode: self.targets[index].request.request_mode,
+            })
+            .filter(|request| request.mode != RequestMode::Offchain)
+            .collect();
+    }
+
+    /// Wait for the targets of `chunk` until `deadline`, recording the outcome for the fallback.
+    /// Returns whether every request landed or failed. The requests for mirror chains aren't waited
+    /// for: only the contract of the first chain is read, so a mirror's landing can't be told.
+    async fn wait_for_chunk(&self, chunk: &Chunk, deadline: tokio::time::Instant) -> bool {
+        let (mut landed, mut failed, mut settled) = (false, false, true);
+        for (&index, request_id) in chunk.targets.iter().zip(chunk.request_ids.iter()) {
+            let target = &self.targets[index];
+            if target.mirror {
+                continue;
+            }
+            // Off-chain proofs aren't relayed by the run loop: the loop delay applies.
+            if target.request.request_mode == RequestMode::Offchain {
+                settled = false;
+                continue;
+            }
+            info!(
+                "Waiting for {} to store block {} [{}]",
+                target.request, chunk.target_block, request_id
+            );
+            let landing = landing::wait_for_landing(
+                || async move { target.trusted.latest_block().await.map(Height::value) },
+                || self.backend.status(request_id),
+                chunk.target_block,
+                self.landing_poll_interval,
+                deadline,
+            )
+            .await;
+            match landing {
+                Landing::Landed { latest_block } => {
+                    info!("{} stored block {}", target.request, latest_block);
+                    landed = true;
+                }
+                Landing::Failed { error } => {
+                    warn!(
+                        "Request {} for {} failed, selecting the next range: {}",
+                        request_id,
+                        target.request,
+                        error.as_deref().unwrap_or("unknown error")
+                    );
+                    failed = true;
+                }
+                Landing::TimedOut => {
+                    warn!(
+                        "Request {} for {} did not land within {:?}",
+                        request_id, target.request, self.landing_timeout
+                    );
+                    settled = false;
+                }
+            }
+        }
+        // The targets of a chunk share its range, so it counts once for the fallback.
+        self.record_fallback(chunk, landed, failed).await;
+        settled
+    }
+
+    /// Wait for the requests the run loop was waiting for when it stopped, until they settle, the
+    /// drain timeout elapses or `abort` resolves, e.g. on a second shutdown signal.
+    pub async fn drain(&self, abort: impl Future<Output = ()>) {
+        let requests = std::mem::take(&mut *self.in_flight.lock().unwrap());
+        if requests.is_empty() {
+            return;
+        }
+        info!(
+            "Draining {} in-flight requests for up to {:?}, signal again to abort",
+            requests.len(),
+            self.drain_timeout
+        );
+        let summary = drain::drain(
+            &requests,
+            self.backend.as_ref(),
+            self.store.as_deref(),
+            self.landing_poll_interval,
+            self.drain_timeout,
+            abort,
+        )
+        .await;
+        info!("Drained the in-flight requests: {}", summary);
+    }
+
+    /// Record the outcome of the request of `chunk` for the step fallback: whether it landed for
+    /// any of its targets, or failed.
+    async fn record_fallback(&self, chunk: &Chunk, landed: bool, failed: bool) {
+        let Some(fallback) = self.fallback.as_ref() else {
+            return;
+        };
+        let kind = RequestKind::for_range(chunk.trusted_block, chunk.target_block);
+        if landed {
+            fallback.record_success(chunk.trusted_block);
+        } else if failed && kind == RequestKind::Skip {
+            if let Some(transition) =
+                fallback.record_failure(chunk.trusted_block, chunk.target_block)
+            {
+                self.alert_fallback(transition, Some(chunk.correlation_id))
+                    .await;
+            }
+        }
+    }
+
+    /// Alert on a change in the state of the step fallback. `correlation_id` is the attempt whose
+    /// failure changed it, if any.
+    async fn alert_fallback(
+        &self,
+        transition: FallbackTransition,
+        correlation_id: Option<CorrelationId>,
+    ) {
+        match transition {
+            FallbackTransition::Engaged {
+                trusted_block,
+                until,
+                failures,
+            } => {
+                warn!(
+                    "{} skips from block {} failed, requesting steps up to block {}",
+                    failures, trusted_block, until
+                );
+                let alert = Alert::new(
+                    AlertKind::StepFallback,
+                    format!("skips from block {} keep failing", trusted_block),
+                )
+                .with_detail("trusted_block", trusted_block)
+                .with_detail("until_block", until)
+                .with_detail("failures", failures);
+                let alert = match correlation_id {
+                    Some(correlation_id) => alert.with_detail("correlation_id", correlation_id),
+                    None => alert,
+                };
+                self.alerter.send(&alert).await;
+            }
+            FallbackTransition::Disengaged { block } => {
+                info!(
+                    "At block {}, past the failing skips: resuming normal operation",
+                    block
+                );
+                self.alerter.resolve(AlertKind::StepFallback).await;
+            }
+        }
+    }
+
+    /// Wait for the contracts of the targets of `chunks` to store their target blocks. A chunk
+    /// that doesn't land in time (e.g. as its request failed) is logged, and the next iteration
+    /// resumes from wherever the contract is. Without chunks, waits for the poll interval.
+    async fn wait_for_chunks(&self, chunks: &[Chunk]) {
+        if chunks.is_empty() {
+            tokio::time::sleep(self.catch_up.poll_interval).await;
+            return;
+        }
+        for chunk in chunks {
+            let head = chunk.target_block + chunk.remaining;
+            let to_go = catchup::plan(chunk.target_block, head, chunk.skip_max).len();
+            // The mirror chains share the contract of the first.
+            for &index in chunk.targets.iter().filter(|&&i| !self.targets[i].mirror) {
+                let target = &self.targets[index];
+                info!(
+                    "Catching up: waiting for {} to store block {}, then {} skips to go",
+                    target.request, chunk.target_block, to_go
+                );
+                let stored = catchup::wait_for_block(
+                    || async move { target.trusted.latest_block().await.map(Height::value) },
+                    chunk.target_block,
+                    self.catch_up.poll_interval,
+                    self.catch_up.timeout,
+                )
+                .await;
+                if let Err(e) = stored {
+                    warn!("Catching up on {}: {:#}", target.request, e);
+                }
+            }
+        }
+    }
+
+    /// Submit a request for each group of targets at the same latest block.
+    async fn run_iteration(&mut self) -> Result<IterationOutcome> {
+        let mut summary = IterationSummary::start();
+        let mut phases = Phases::default();
+        let start = Instant::now();
+        self.abandon_stale_requests().await;
+        self.record_head_updates().await;
+
+        // Check the relayer balance on each target chain. Failures are logged and never block
+        // the iteration.
+        for target in self.targets.iter_mut() {
+            if let Some(monitor) = target.balance_monitor.as_mut().map(|m| m.get_mut()) {
+                let report = monitor.check(target.provider.as_ref()).await;
+                if let Ok(report) = report.as_ref() {
+                    self.metrics.record_relayer_balance(
+                        &target.request,
+                        report.balance_native(),
+                        report.transactions_remaining,
+                    );
+                }
+                match report {
+                    Ok(report) if report.below_threshold => {
+                        let alert = Alert::new(
+                            AlertKind::BalanceLow,
+                            format!("relayer balance low on {}", target.request),
+                        )
+                        .with_detail("address", monitor.address)
+                        .with_detail("balance_wei", report.balance)
+                        .with_detail("threshold_wei", monitor.threshold);
+                        self.alerter.send(&alert).await;
+                    }
+                    Ok(_) => self.alerter.resolve(AlertKind::BalanceLow).await,
+                    Err(e) => error!("Balance check failed for {}: {:#}", target.request, e),
+                }
+            }
+        }
+        phases.record("housekeeping", start.elapsed());
+
+        // Get the head of the chain and read the contracts of the targets, at once as neither
+        // depends on the other.
+        let chain_head = async {
+            let start = Instant::now();
+            let header = self
+                .headers()
+                .latest_header()
+                .await
+                .context("failed to fetch the chain head")?;
+            self.metrics
+                .observe_fetch("get_latest_signed_header", start.elapsed());
+            Ok::<_, anyhow::Error>((header, start.elapsed()))
+        };
+        // A contract is read once for the targets sharing it, e.g. a target and its mirrors.
+        let sources = self.trusted_sources();
+        let contracts = async {
+            let start = Instant::now();
+            let distinct = sources
+                .iter()
+                .enumerate()
+                .filter(|&(i, &source)| i == source)
+                .map(|(i, _)| i)
+                .collect::<Vec<_>>();
+            let read = try_join_all(
+                distinct
+                    .iter()
+                    .map(|&i| IterationState::read(self.targets[i].trusted.as_ref())),
+            )
+            .await
+            .context("failed to read the contracts of the targets")?;
+            let read = distinct.into_iter().zip(read).collect::<HashMap<_, _>>();
+            let states = sources
+                .iter()
+                .map(|source| read[source])
+                .collect::<Vec<_>>();
+            Ok::<_, anyhow::Error>((states, start.elapsed()))
+        };
+        let (((latest_block, latest_time), head_elapsed), (states, contracts_elapsed)) =
+            tokio::try_join!(chain_head, contracts)?;
+        phases.record("chain_head", head_elapsed);
+        phases.record("contracts", contracts_elapsed);
+        let head = ChainHead {
+            block: latest_block,
+            time: latest_time,
+        };
+        summary.chain_head = Some(latest_block);
+        self.metrics.record_chain_head(latest_block);
+        Span::current().record("chain_head", latest_block);
+        let now = unix_timestamp() as i64;
+        self.staleness.observe_chain_head(latest_time);
+        if let Some(age) = self.staleness.chain_age(now) {
+            self.metrics.record_chain_head_age(age);
+        }
+        self.halt_heights.discover(&self.data_fetcher).await;
+
+        // Group the targets by their latest block. Targets in the same group share the same
+        // trusted state, so their inputs are computed once.
+        let start = Instant::now();
+        let mut groups: BTreeMap<u64, Vec<usize>> = BTreeMap::new();
+        let mut transitions = Vec::new();
+        for (i, (target, state)) in self.targets.iter().zip(states.iter()).enumerate() {
+            // The run loop reasons about the blocks as numbers: the monitors, selectors and
+            // metrics take them as such.
+            let current_block = state.latest_block.value();
+            info!(
+                "Target {}: latest block {}, lag {} blocks",
+                target.request,
+                current_block,
+                latest_block.saturating_sub(current_block)
+            );
+            self.metrics
+                .record_latest_block(&target.request, current_block, latest_block);
+            let key = target.request.to_string();
+            self.staleness.observe_contract(&key, current_block, now);
+            if let Some(age) = self.staleness.contract_age(&key, now) {
+                self.metrics.record_head_age(&target.request, age);
+            }
+            if let Some(transition) = self.staleness.evaluate(&key, now) {
+                transitions.push((i, current_block, transition));
+            }
+            groups.entry(current_block).or_default().push(i);
+        }
+        for (i, current_block, transition) in transitions {
+            self.alert_staleness(i, current_block, latest_block, transition)
+                .await;
+        }
+        phases.record("contracts", start.elapsed());
+
+        // Read once, so that an iteration never submits for some of the groups only.
+        let paused = self.control.is_paused();
+        let following = !self.is_leader();
+        if paused {
+            info!("Submissions are paused, monitoring only");
+        } else if following {
+            info!("Not the leader, monitoring only");
+        }
+        let monitoring_only = paused || following;
+        let mut any_submitted = false;
+        let mut below_min_lag = false;
+        let mut min_lag_eta: Option<Duration> = None;
+        let mut gated = false;
+        let mut trusted_state_expired = false;
+        let mut chunks = Vec::new();
+        for (current_block, indices) in groups {
+            let targets = indices
+                .iter()
+                .map(|&i| &self.targets[i])
+                .collect::<Vec<_>>();
+            let names = targets.iter().map(|t| t.request.to_string()).collect();
+            let group = summary.group(current_block, names);
+
+            // Consistency check for the headers (this should only happen if an invalid header,
+            // typically the genesis header, is pushed to the contract). If this is triggered,
+            // double check the genesis header in the contract.
+            let start = Instant::now();
+            let mut group_lag = None;
+            let mut trusted_time = 0;
+            let mut checked = HashMap::new();
+            for (target, &i) in targets.iter().zip(indices.iter()) {
+                // The targets sharing a contract share its header, checked once.
+                let header_time = match checked.get(&sources[i]) {
+                    Some(&header_time) => header_time,
+                    None => self.is_consistent(target, &states[i]).await?,
+                };
+                checked.insert(sources[i], header_time);
+                trusted_time = header_time;
+                let lag = Lag::new(latest_block, latest_time, current_block, header_time);
+                group_lag = Some(lag);
+                info!("Target {}: lag {}", target.request, lag);
+                self.metrics
+                    .record_lag_seconds(&target.request, lag.seconds as f64);
+                let key = target.request.to_string();
+                let transition = self
+                    .lag_monitor
+                    .as_mut()
+                    .and_then(|monitor| monitor.observe(&key, lag, Instant::now()));
+                match transition {
+                    Some(LagTransition::Fired) => {
+                        let alert = Alert::new(
+                            AlertKind::LagExceeded,
+                            format!("{} is behind the chain head", target.request),
+                        )
+                        .with_detail("lag_blocks", lag.blocks)
+                        .with_detail("lag_seconds", lag.seconds)
+                        .with_detail("latest_block", current_block)
+                        .with_detail("chain_head", latest_block);
+                        self.alerter.send(&alert).await;
+                    }
+                    Some(LagTransition::Cleared) => {
+                        info!("Target {} caught up with the chain head", target.request);
+                        self.alerter.resolve(AlertKind::LagExceeded).await;
+                    }
+                    None => {}
+                }
+            }
+            phases.record("contracts", start.elapsed());
+
+            // The targets of a group are at the same block, so they have the same lag. A lag under
+            // the minimum isn't a failure: the group is checked again after a shorter delay.
+            if let Some(lag) = group_lag.filter(|&lag| !self.min_lag.reached(lag)) {
+                info!(
+                    "lag {} below threshold {}, skipping",
+                    lag.blocks, self.min_lag
+                );
+                let reason = format!("lag {} below the minimum {}", lag.blocks, self.min_lag);
+                group.skipped(Action::None, reason);
+                any_submitted = true;
+                below_min_lag = true;
+                if let Some(eta) = self
+                    .chain_spec
+                    .as_ref()
+                    .and_then(|s| self.min_lag.eta(lag, s))
+                {
+                    min_lag_eta = Some(min_lag_eta.map_or(eta, |soonest| soonest.min(eta)));
+                }
+                continue;
+            }
+            // Monitoring only isn't a failure either: nothing is alerted on and the circuit breaker
+            // stays closed.
+            if monitoring_only {
+                let reason = if paused {
+                    "submissions are paused"
+                } else {
+                    "not the leader"
+                };
+                group.skipped(Action::Paused, reason);
+                any_submitted = true;
+                continue;
+            }
+
+            // Get the maximum block height we can request: within the skip_max of every target,
+            // and confirmed if the chain spec says how deep.
+            let skip_max = indices.iter().map(|&i| self.skip_maxes[i]).min().unwrap();
+            let confirmation_depth = self.chain_spec.as_ref().map_or(0, |s| s.confirmation_depth);
+            let Some(mut max_end_block) =
+                selector::max_end_block(current_block, latest_block, skip_max, confirmation_depth)
+            else {
+                let reason = match self.chain_spec {
+                    Some(_) => "no confirmed block to request",
+                    None => "no block to request",
+                };
+                group.skipped(Action::None, reason);
+                any_submitted = true;
+                continue;
+            };
+            let halt_height = self.halt_heights.within(current_block, max_end_block);
+            if let Some(halt_height) = halt_height {
+                warn!(
+                    current_block,
+                    halt_height,
+                    "Bounding the request by the halt height {} instead of {}: the contract must \
+                     land on it before proving past the upgrade",
+                    halt_height,
+                    max_end_block
+                );
+                max_end_block = halt_height;
+            }
+
+            let start = Instant::now();
+            let selection = self
+                .selector
+                .select(current_block, max_end_block, head, self.headers())
+                .await;
+            let mut selection = match selection {
+                Ok(selection) => selection,
+                Err(e) => {
+                    // Retried in the next iteration, like a failed request.
+                    error!("Failed to select a block up to {}: {:#}", max_end_block, e);
+                    group.max_end_block = Some(max_end_block);
+                    group.error = Some(e.to_string());
+                    continue;
+                }
+            };
+            if let Some(halt_height) = halt_height {
+                selection.rationale = format!(
+                    "{}, bounded by the halt height {}",
+                    selection.rationale, halt_height
+                );
+            }
+            self.metrics.observe_fetch("select_target", start.elapsed());
+            phases.record("select", start.elapsed());
+            info!("{}", selection);
+            group.selected(max_end_block, &selection);
+            // No request due isn't a failure either.
+            let Some(mut target_block) = selection.target else {
+                group.skipped(Action::None, "no request due");
+                any_submitted = true;
+                continue;
+            };
+            if let Some(fallback) = self.fallback.as_ref() {
+                let (fallback_block, transition) = fallback.target(current_block, target_block);
+                if let Some(transition) = transition {
+                    self.alert_fallback(transition, None).await;
+                }
+                if fallback_block != target_block {
+                    info!(
+                        current_block,
+                        target_block = fallback_block,
+                        "Stepping past failing skips"
+                    );
+                    target_block = fallback_block;
+                    group.target_block = Some(target_block);
+                    group.rationale = Some(format!(
+                        "{}, stepping past failing skips",
+                        selection.rationale
+                    ));
+                }
+            }
+            if let Some(spec) = self.chain_spec.as_ref() {
+                let target_time = match self.header_time(Height(target_block)).await {
+                    Ok(time) => time,
+                    Err(e) => {
+                        // Retried in the next iteration, like a failed request.
+                        error!("Not requesting {}: {:#}", target_block, e);
+                        let kind = RequestKind::for_range(current_block, target_block);
+                        group.failed(kind, target_block, &e);
+                        continue;
+                    }
+                };
+                if let Err(e) =
+                    spec.validate_skip(current_block, trusted_time, target_block, target_time)
+                {
+                    if let Some(expired) = e.downcast_ref::<TrustedStateExpired>() {
+                        // Retrying can't help, so the group fails instead of waiting.
+                        error!("Not requesting {}: {:#}", target_block, e);
+                        let names = targets.iter().map(|t| t.request.to_string());
+                        self.alert_trusted_state_expired(names, expired).await;
+                        let kind = RequestKind::for_range(current_block, target_block);
+                        group.failed(kind, target_block, &e);
+                        trusted_state_expired = true;
+                    } else {
+                        warn!("Not requesting {}: {:#}", target_block, e);
+                        group.skipped(Action::None, format!("{:#}", e));
+                        any_submitted = true;
+                    }
+                    continue;
+                }
+            }
+            if let Some(gating) = self.gating.as_ref() {
+                let start = Instant::now();
+                let allowed = gating.allows(current_block, target_block).await;
+                phases.record("gate", start.elapsed());
+                if !allowed {
+                    group.skipped(Action::None, "not needed downstream");
+                    any_submitted = true;
+                    gated = true;
+                    continue;
+                }
+            }
+            let correlation_id = CorrelationId::generate();
+            let attempt = attempt_span(correlation_id);
+            attempt.in_scope(|| info!(current_block, target_block, "Requesting a proof"));
+
+            let kind = RequestKind::for_range(current_block, target_block);
+            self.metrics.record_decision(kind);
+            let start = Instant::now();
+            // Consistent, so every target of the group stores the same header hash.
+            let trusted = states[indices[0]].trusted();
+            let (request_type, submissions) = if kind == RequestKind::Step {
+                // Request the step if the target block is the next block.
+                let submissions = self
+                    .request_step(&targets, trusted, correlation_id)
+                    .instrument(attempt.clone())
+                    .await;
+                ("Step", submissions)
+            } else {
+                // Request a skip if the target block is not the next block.
+                let submissions = self
+                    .request_skip(&targets, trusted, Height(target_block), correlation_id)
+                    .instrument(attempt.clone())
+                    .await;
+                ("Skip", submissions)
+            };
+            phases.record("submit", start.elapsed());
+            // Nothing below awaits, so the span is only entered until the end of the iteration.
+            let _entered = attempt.enter();
+            match submissions {
+                Ok(submissions) => {
+                    group.submitted(kind, target_block, &submissions);
+                    self.metrics.record_submissions(kind, &submissions);
+                    any_submitted |= Self::log_submissions(request_type, &submissions);
+                    let (accepted, request_ids): (Vec<_>, Vec<_>) = indices
+                        .iter()
+                        .filter_map(|&i| {
+                            submissions.iter().find_map(|submission| {
+                                let request_id = submission.result.as_ref().ok()?;
+                                (*submission.target == self.targets[i].request)
+                                    .then(|| (i, request_id.clone()))
+                            })
+                        })
+                        .unzip();
+                    if !accepted.is_empty() {
+                        let lag = latest_block.saturating_sub(current_block);
+                        chunks.push(Chunk {
+                            targets: accepted,
+                            request_ids,
+                            trusted_block: current_block,
+                            target_block,
+                            skip_max,
+                            lag,
+                            remaining: latest_block.saturating_sub(target_block),
+                            correlation_id,
+                        });
+                    }
+                }
+                Err(e) => {
+                    error!("{} request failed: {}", request_type, e);
+                    group.failed(kind, target_block, &e);
+                }
+            }
+        }
+        if self.chain_spec.is_some() && !trusted_state_expired {
+            self.alerter.resolve(AlertKind::TrustedStateExpired).await;
+        }
+
+        if let Some(limiter) = self.rate_limiter.as_ref() {
+            info!(
+                "Time spent waiting for the submission rate limit: {:?}",
+                limiter.waited()
+            );
+        }
+        summary.phases_ms = phases;
+        summary.finish();
+        self.report_iteration(&summary);
+
+        Ok(IterationOutcome {
+            any_submitted,
+            below_min_lag,
+            min_lag_eta,
+            gated,
+            monitoring_only,
+            chunks,
+            summary,
+        })
+    }
+
+    /// Alert that the trusted state of `targets` is past the trusting period.
+    async fn alert_trusted_state_expired(
+        &self,
+        targets: impl Iterator<Item = String>,
+        expired: &TrustedStateExpired,
+    ) {
+        let targets = targets.collect::<Vec<_>>().join(",");
+        let alert = Alert::new(
+            AlertKind::TrustedStateExpired,
+            format!("the trusted state of {} expired", targets),
+        )
+        .with_detail("targets", &targets)
+        .with_detail("trusted_block", expired.trusted_block)
+        .with_detail("target_block", expired.target_block)
+        .with_detail("age_seconds", expired.age_secs)
+        .with_detail("trusting_period_seconds", expired.trusting_period_secs);
+        self.alerter.send(&alert).await;
+    }
+
+    /// Log `summary` as a single event, and append it to the audit log.
+    fn report_iteration(&self, summary: &IterationSummary) {
+        match serde_json::to_string(summary) {
+            Ok(json) => info!(summary = %json, "Finished the {}", summary),
+            Err(e) => error!("Failed to serialize the iteration summary: {:#}", e),
+        }
+        self.audit(AuditEvent::Iteration(summary.clone()));
+    }
+
+    /// Alert on a change in the staleness of the target at `index`, whose contract is at `block`
+    /// while the chain head is at `chain_head`.
+    async fn alert_staleness(
+        &self,
+        index: usize,
+        block: u64,
+        chain_head: u64,
+        transition: StalenessTransition,
+    ) {
+        let target = &self.targets[index].request;
+        let now = unix_timestamp() as i64;
+        let key = target.to_string();
+        let age = |age: Option<Duration>| age.unwrap_or_default().as_secs();
+        info!(
+            "Target {}: {} -> {}",
+            target, transition.from, transition.to
+        );
+        // As for lag, resolving clears the alerts of that kind for every target.
+        match transition.from {
+            Staleness::ChainHalted => self.alerter.resolve(AlertKind::ChainHalted).await,
+            Staleness::LightClientStalled => {
+                self.alerter.resolve(AlertKind::LightClientStalled).await
+            }
+            Staleness::Fresh => {}
+        }
+        let alert = match transition.to {
+            Staleness::ChainHalted => Alert::new(
+                AlertKind::ChainHalted,
+                "the Tendermint chain head stopped advancing",
+            )
+            .with_detail("chain_head", chain_head)
+            .with_detail("chain_head_age_seconds", age(self.staleness.chain_age(now))),
+            Staleness::LightClientStalled => Alert::new(
+                AlertKind::LightClientStalled,
+                format!("the light client of {} stopped advancing", target),
+            )
+            .with_detail("latest_block", block)
+            .with_detail("chain_head", chain_head)
+            .with_detail(
+                "head_age_seconds",
+                age(self.staleness.contract_age(&key, now)),
+            ),
+            Staleness::Fresh => return,
+        };
+        self.alerter.send(&alert).await;
+    }
+
+    /// Sleep for `duration`, refreshing the status of the pending requests in the store every
+    /// `status_poll_interval`. Returns early when a platform callback updates the store.
+    async fn sleep_refreshing(&self, duration: Duration) {
+        let deadline = tokio::time::Instant::now() + duration;
+        loop {
+            let now = tokio::time::Instant::now();
+            if now >= deadline {
+                return;
+            }
+            let callback = async {
+                match self.webhook.as_ref() {
+                    Some(webhook) => webhook.handler.updated().notified().await,
+                    None => std::future::pending().await,
+                }
+            };
+            let poll = std::cmp::min(self.status_poll_interval, deadline - now);
+            tokio::select! {
+                _ = tokio::time::sleep(poll) => {}
+                _ = callback => {
+                    info!("Woken by a platform callback");
+                    return;
+                }
+            }
+            let Some(store) = self.store.as_ref() else {
+                continue;
+            };
+            let audit = self.audit.as_deref();
+            match refresh_pending(store, self.backend.as_ref(), audit).await {
+                Ok(summary) if summary.pending == 0 => {}
+                Ok(summary) => info!("Status poll: {}", summary),
+                Err(e) => error!("Failed to refresh pending requests: {:#}", e),
+            }
+        }
+    }
+
+    /// Request a proof of `range`, from its start to its end, for every target without a pending
+    /// request for it. `trusted_hash` is the header hash of the trusted block at its start, and
+    /// must be the chain's. Returns the requests accepted; the targets that failed are logged, and
+    /// fail the request if none accepted it.
+    pub async fn prove(
+        &self,
+        range: RangeInclusive<Height>,
+        trusted_hash: HeaderHash,
+    ) -> Result<Vec<SubmittedRequest>> {
+        self.request_proof(range, trusted_hash, false).await
+    }
+
+    /// Like `prove`, but request a commitment to the data hashes of the headers of `range`. Every
+    /// target must take data commitments.
+    pub async fn prove_data_commitment(
+        &self,
+        range: RangeInclusive<Height>,
+        trusted_hash: HeaderHash,
+    ) -> Result<Vec<SubmittedRequest>> {
+        self.request_proof(range, trusted_hash, true).await
+    }
+
+    async fn request_proof(
+        &self,
+        range: RangeInclusive<Height>,
+        trusted_hash: HeaderHash,
+        data_commitment: bool,
+    ) -> Result<Vec<SubmittedRequest>> {
+        let (current_block, target_block) = range.into_inner();
+        if current_block >= target_block {
+            return Err(RefusedRequest(format!(
+                "invalid range: target block {} is not after trusted block {}",
+                target_block, current_block
+            ))
+            .into());
+        }
+        // Only the RPC knows the head: from light blocks, the blocks that aren't there are caught
+        // by their check below.
+        if self.data_fetcher.mode == InputDataMode::Rpc {
+            let chain_head = self.headers().chain_head().await?;
+            if target_block.value() > chain_head {
+                return Err(BeyondChainHead {
+                    target_block: target_block.value(),
+                    chain_head,
+                }
+                .into());
+            }
+        }
+        // A data commitment only hashes the headers, which an upgrade doesn't change.
+        let halt_height = self
+            .halt_heights
+            .within(current_block.value(), target_block.value())
+            .filter(|_| !data_commitment);
+        if let Some(halt_height) = halt_height {
+            return Err(RefusedRequest(format!(
+                "the range from {} to {} crosses the halt height {}: prove up to it first",
+                current_block.value(),
+                target_block.value(),
+                halt_height
+            ))
+            .into());
+        }
+        if let Some(blocks) = self.data_fetcher.light_blocks() {
+            let needs = match data_commitment {
+                true => light_blocks::needed_for_header_range(current_block, target_block),
+                false => light_blocks::needed_for_range(current_block, target_block),
+            };
+            let adapter = self.data_fetcher.adapter.as_ref();
+            blocks
+                .check(adapter, &needs, current_block, trusted_hash)
+                .map_err(|e| RefusedRequest(format!("{:#}", e)))?;
+        }
+
+        if let Some(spec) = self.chain_spec.as_ref() {
+            let trusted_time = self.header_time(current_block).await?;
+            let target_time = self.header_time(target_block).await?;
+            let valid = spec.validate_skip(
+                current_block.value(),
+                trusted_time,
+                target_block.value(),
+                target_time,
+            );
+            if let Some(expired) = valid
+                .as_ref()
+                .err()
+                .and_then(|e| e.downcast_ref::<TrustedStateExpired>())
+            {
+                let names = self.targets.iter().map(|t| t.request.to_string());
+                self.alert_trusted_state_expired(names, expired).await;
+            }
+            valid?;
+        }
+
+        // Otherwise only caught once the proof is paid for: the contract rejects a range longer
+        // than its skip_max, and the circuit a trusted header that isn't the chain's. From light
+        // blocks, the contracts aren't read.
+        let range = target_block.value() - current_block.value();
+        let targets = match self.data_fetcher.light_blocks() {
+            Some(_) => &[][..],
+            None => &self.targets[..],
+        };
+        for target in targets {
+            let skip_max = self.bound_skip(target.trusted.skip_max().await?);
+            if range > skip_max {
+                return Err(RefusedRequest(format!(
+                    "range of {} blocks is more than the skip_max {} of {}",
+                    range, skip_max, target.request
+                ))
+                .into());
+            }
+        }
+        let header = self
+            .data_fetcher
+            .get_signed_header_from_number(current_block)
+            .await?
+            .header;
+        let hash = header_hash(&header);
+        if hash != trusted_hash {
+            return Err(RefusedRequest(format!(
+                "trusted hash {} is not the header hash of block {} ({})",
+                trusted_hash, current_block, hash
+            ))
+            .into());
+        }
+
+        let correlation_id = CorrelationId::generate();
+        let attempt = attempt_span(correlation_id);
+        attempt.in_scope(|| info!(%current_block, %target_block, "Requesting a proof"));
+
+        let targets: Vec<_> = self.targets.iter().collect();
+        let trusted = TrustedState::Explicit {
+            block: current_block,
+            hash: trusted_hash,
+        };
+        let kind = if data_commitment {
+            RequestKind::DataCommitment
+        } else {
+            RequestKind::for_range(current_block.value(), target_block.value())
+        };
+        let (request_type, result) = if data_commitment {
+            let result =
+                self.request_data_commitment(&targets, trusted, target_block, correlation_id);
+            ("Data commitment", result.instrument(attempt.clone()).await)
+        } else if target_block == current_block.next() {
+            // Request the step if the target block is the next block.
+            let result = self.request_step(&targets, trusted, correlation_id);
+            ("Step", result.instrument(attempt.clone()).await)
+        } else {
+            // Request a skip if the target block is not the next block.
+            let result = self.request_skip(&targets, trusted, target_block, correlation_id);
+            ("Skip", result.instrument(attempt.clone()).await)
+        };
+        let submissions = result.with_context(|| format!("{} request failed", request_type))?;
+        for request_id in submissions.iter().filter_map(|s| s.result.as_ref().ok()) {
+            info!("request____start{}request____end", request_id);
+        }
+        let accepted = attempt.in_scope(|| Self::log_submissions(request_type, &submissions));
+        ensure!(accepted, "{} request failed for every target", request_type);
+        Ok(submissions
+            .into_iter()
+            .filter_map(|s| {
+                Some(SubmittedRequest {
+                    target: s.target.clone(),
+                    kind,
+                    trusted_block: current_block,
+                    target_block,
+                    request_id: s.result.ok()?,
+                    correlation_id,
+                })
+            })
+            .collect())
+    }
+
+    /// Wait for a request to be relayed on-chain, fail, or time out, and log the outcome.
+    async fn wait_for_request(
+        &self,
+        request_id: &str,
+        target: &RequestTarget,
+        timeout: Duration,
+    ) -> Result<FulfillmentStatus> {
+        let (mode, labels) = (target.request_mode, &target.labels);
+        let status = self
+            .backend
+            .wait_for_fulfillment(request_id, mode, timeout)
+            .await?;
+        self.audit(AuditEvent::fulfillment(request_id, "wait", &status, mode));
+        if let Some(store) = self.store.as_ref() {
+            let stored_status = RequestStatus::from_fulfillment(&status, mode);
+            if let Err(e) = store.update_status(request_id, stored_status) {
+                error!(
+                    "Failed to update request {} [{}]: {:#}",
+                    request_id, labels, e
+                );
+            }
+            if let (
+                RequestStatus::Delivered,
+                FulfillmentStatus::Proved {
+                    proof_id: Some(proof),
+                },
+            ) = (stored_status, &status)
+            {
+                if let Err(e) = store.record_proof_location(request_id, proof) {
+                    error!(
+                        "Failed to record proof of request {} [{}]: {:#}",
+                        request_id, labels, e
+                    );
+                }
+            }
+            if status.is_settled(mode) {
+                match self.backend.request_cost(request_id).await {
+                    Ok(Some(cost)) => {
+                        if let Err(e) = store.record_cost(request_id, cost) {
+                            error!(
+                                "Failed to record cost of request {} [{}]: {:#}",
+                                request_id, labels, e
+                            );
+                        }
+                    }
+                    Ok(None) => {}
+                    Err(e) => warn!(
+                        "Failed to get cost of request {} [{}]: {:#}",
+                        request_id, labels, e
+                    ),
+                }
+            }
+        }
+        match &status {
+            FulfillmentStatus::Proved { proof_id } if mode == RequestMode::Offchain => {
+                info!(
+                    "Request {} proved off-chain (proof: {:?}) [{}]",
+                    request_id, proof_id, labels
+                )
+            }
+            FulfillmentStatus::Relayed { proof_id, tx_hash } => {
+                info!(
+                    "Request {} relayed on-chain (proof: {:?}, tx: {:?}) [{}]",
+                    request_id, proof_id, tx_hash, labels
+                )
+            }
+            FulfillmentStatus::Failed { error } => {
+                error!(
+                    "Request {} failed: {} [{}]",
+                    request_id,
+                    error.as_deref().unwrap_or("no error message"),
+                    labels
+                )
+            }
+            status => {
+                error!(
+                    "Request {} not fulfilled after {:?}: {:?} [{}]",
+                    request_id, timeout, status, labels
+                )
+            }
+        }
+        Ok(status)
+    }
+
+    /// Resign from the leader election, if any, and alert that the operator stopped.
+    pub async fn stop(&self) {
+        if let Some(election) = self.election.as_ref() {
+            election.resign().await;
+        }
+        let alert = Alert::new(AlertKind::Shutdown, "operator stopped");
+        self.alerter.send(&alert).await;
+    }
+
+    /// Whether this operator submits requests: always, unless it follows another one.
+    fn is_leader(&self) -> bool {
+        self.election
+            .as_ref()
+            .map_or(true, |election| election.is_leader())
+    }
+
+    /// `skip_max`, bounded by the trusting period of the chain spec if known.
+    fn bound_skip(&self, skip_max: u64) -> u64 {
+        match self.chain_spec.as_ref() {
+            Some(spec) => skip_max.min(spec.max_skip()),
+            None => skip_max,
+        }
+    }
+
+    /// The header time of `block`, in unix seconds.
+    async fn header_time(&self, block: Height) -> Result<i64> {
+        let start = Instant::now();
+        let time = self.headers().header_time(block.value()).await;
+        self.metrics
+            .observe_fetch("get_signed_header_from_number", start.elapsed());
+        time
+    }
+
+    /// Take the step and skip function IDs of every target from its contract, if it exposes them,
+    /// falling back to the configured ones, and log them with their source. Fails if a configured
+    /// ID differs from the contract's, listing the differences, or if a target is left without the
+    /// ID of a kind of request it takes. Reading the chain from light blocks, the contracts aren't
+    /// read. Startup, `run_once` and `collect_status` call it; the other commands call it first.
+    pub async fn resolve_function_ids(&mut self) -> Result<()> {
+        let offline = self.data_fetcher.light_blocks().is_some();
+        for target in self.targets.iter_mut() {
+            let registered = match offline {
+                true => None,
+                false => target.trusted.function_ids().await?,
+            };
+            let source = target.request.resolve_function_ids(registered)?;
+            target.request.validate()?;
+            info!(
+                target = %target.request,
+                function_ids_from = %source,
+                "Step function ID {}, skip function ID {}, from the {}",
+                target.request.step_function_id,
+                target.request.skip_function_id,
+                source
+            );
+            target.function_ids_from = Some(source);
+        }
+        Ok(())
+    }
+
+    fn function_ids_resolved(&self) -> bool {
+        self.targets.iter().all(|t| t.function_ids_from.is_some())
+    }
+
+    /// Read the `skip_max` of every target: the upper limit of the largest skip that can be
+    /// requested. This is bounded by the unbonding period, which for most Tendermint chains is ~2
+    /// weeks, or ~100K blocks with a block time of 12s.
+    async fn read_skip_maxes(&mut self) -> Result<()> {
+        let sources = self.trusted_sources();
+        let mut skip_maxes = Vec::new();
+        for (target, &source) in self.targets.iter().zip(sources.iter()) {
+            let skip_max = match skip_maxes.get(source) {
+                Some(&skip_max) => skip_max,
+                None => self.bound_skip(target.trusted.skip_max().await?),
+            };
+            skip_maxes.push(skip_max);
+        }
+        self.skip_maxes = skip_maxes;
+        Ok(())
+    }
+
+    /// Read the `skip_max` of every target again, once per trusted state as `read_skip_maxes`
+    /// does, keeping the previous value on failure.
+    async fn refresh_skip_maxes(&mut self) {
+        let sources = self.trusted_sources();
+        for (i, &source) in sources.iter().enumerate() {
+            if source != i {
+                self.skip_maxes[i] = self.skip_maxes[source];
+                continue;
+            }
+            let target = &self.targets[i];
+            match target.trusted.skip_max().await {
+                Ok(skip_max) => self.skip_maxes[i] = self.bound_skip(skip_max),
+                Err(e) => warn!("Failed to read the skip_max of {}: {:#}", target.request, e),
+            }
+        }
+    }
+
+    async fn submit(&self, kind: RequestKind, request: &ProofRequest<'_>) -> Result<String> {
+        // Also covers the retries and resubmissions made outside of the run loop's groups.
+        if self.control.is_paused() {
+            return Err(anyhow!("submissions are paused"));
+        }
+        if !self.is_leader() {
+            return Err(anyhow!("not the leader"));
+        }
+        let attestation = match self.attester.as_ref() {
+            Some(attester) => {
+                Some(attester.sign(Attestation::of(kind, request, unix_timestamp())?))
+            }
+            None => None,
+        };
+        let submission = match kind {
+            RequestKind::Step => self.backend.request_step(request),
+            RequestKind::Skip => self.backend.request_skip(request),
+            RequestKind::DataCommitment => self.backend.request_data_commitment(request),
+        };
+        // A backend that panics fails the submission, rather than the loop.
+        let submission = AssertUnwindSafe(submission)
+            .catch_unwind()
+            .map(|result| result.unwrap_or_else(|panic| Err(backend_panic(panic))));
+        let result = submit_in_span(kind, request, submission).await;
+        let backend = match &result {
+            Ok(request_id) => self.served_by(request_id),
+            Err(_) => self.backend.name().to_string(),
+        };
+        let digest = self.verified_digests.get(&request.function_id).copied();
+        if let (Ok(request_id), Some(store), Some(attestation)) =
+            (&result, self.store.as_ref(), attestation.as_ref())
+        {
+            if let Err(e) = store.record_attestation(request_id, attestation) {
+                error!(
+                    "Failed to record the attestation of {}: {:#}",
+                    request_id, e
+                );
+            }
+        }
+        self.audit(AuditEvent::submission(
+            kind,
+            request,
+            &backend,
+            &result,
+            digest,
+            attestation,
+        ));
+        result
+    }
+
+    /// Compare the circuit digests expected for the function IDs with those of the artifact
+    /// manifest, if configured, or else those the backend registered. A mismatch is an error,
+    /// unless mismatches are allowed, when it is only logged. The digests that match are recorded
+    /// with every submission from then on.
+    pub async fn verify_artifacts(&mut self) -> Result<Verification> {
+        let verification = match self.artifact_manifest.as_ref() {
+            Some(path) => {
+                let manifest = ArtifactManifest::open(path)?;
+                let lookup = |function_id| {
+                    let digest = manifest.digest(&function_id);
+                    async move { Ok(digest) }
+                };
+                artifact::verify(&self.circuit_digests, lookup).await?
+            }
+            None => {
+                let backend = &self.backend;
+                let lookup = |function_id| backend.artifact_digest(function_id);
+                artifact::verify(&self.circuit_digests, lookup).await?
+            }
+        };
+        if let Err(e) = verification.ensure_matching() {
+            if !self.allow_circuit_mismatch {
+                return Err(e);
+            }
+            warn!("Running with mismatched circuits: {:#}", e);
+        }
+        for (function_id, digest) in verification.verified.iter() {
+            info!(
+                "Function {} has the expected circuit {}",
+                function_id, digest
+            );
+        }
+        self.verified_digests = verification.verified.clone();
+        Ok(verification)
+    }
+
+    /// Append `event` to the audit log, if there is one.
+    fn audit(&self, event: AuditEvent) {
+        if let Some(audit) = self.audit.as_ref() {
+            if let Err(e) = audit.record(event) {
+                error!("Failed to write audit log entry: {:#}", e);
+            }
+        }
+    }
+
+    /// Write the request from `trusted_block` to `target_block` for the target on `chain_id` (the
+    /// first target by default) to `out` without submitting it: the raw input, or with `json` the
+    /// whole request in the format read by `submit_input`. The trusted header is read from the
+    /// contract like the run loop does, unless `trusted_hash` gives it, which must then be the
+    /// chain's. With light blocks, it must be given: the contract isn't reachable.
+    pub async fn export_input(
+        &self,
+        chain_id: Option<u32>,
+        trusted_block: Height,
+        trusted_hash: Option<HeaderHash>,
+        target_block: Height,
+        out: &Path,
+        json: bool,
+    ) -> Result<()> {
+        let target = match chain_id {
+            Some(chain_id) => self
+                .targets
+                .iter()
+                .find(|t| t.request.chain_id == chain_id)
+                .ok_or_else(|| anyhow!("no target for chain {}", chain_id))?,
+            None => &self.targets[0],
+        };
+        let trusted = match trusted_hash {
+            Some(hash) => {
+                if let Some(blocks) = self.data_fetcher.light_blocks() {
+                    let needs = light_blocks::needed_for_range(trusted_block, target_block);
+                    let adapter = self.data_fetcher.adapter.as_ref();
+                    blocks.check(adapter, &needs, trusted_block, hash)?;
+                }
+                let chain_hash = self.data_fetcher.compute_header_hash(trusted_block).await?;
+                ensure!(
+                    chain_hash == hash,
+                    "trusted hash {} is not the header hash of block {} ({})",
+                    hash,
+                    trusted_block,
+                    chain_hash
+                );
+                TrustedState::Explicit {
+                    block: trusted_block,
+                    hash,
+                }
+            }
+            None => {
+                ensure!(
+                    self.data_fetcher.light_blocks().is_none(),
+                    "exporting from light blocks takes the trusted hash"
+                );
+                TrustedState::FromContract {
+                    block: trusted_block,
+                }
+            }
+        };
+        let inputs = self
+            .request_inputs(&[target], trusted, target_block)
+            .await?;
+        let contents = if json {
+            serde_json::to_vec_pretty(&inputs.export(&target.request))?
+        } else {
+            inputs.input.to_vec()
+        };
+        std::fs::write(out, contents)
+            .with_context(|| format!("failed to write {}", out.display()))?;
+        info!(
+            "Exported {} request for {} from {} to {} to {}",
+            inputs.kind,
+            target.request,
+            trusted_block,
+            target_block,
+            out.display()
+        );
+        Ok(())
+    }
+
+    /// Write the light blocks a request from `trusted_block` to `target_block` reads to `out`, as
+    /// a bundle for `export_input` or `prove` to read without network access.
+    pub async fn export_light_blocks(
+        &self,
+        trusted_block: Height,
+        target_block: Height,
+        out: &Path,
+    ) -> Result<()> {
+        let needs = light_blocks::needed_for_range(trusted_block, target_block);
+        let light_blocks = LightBlocks::fetch(&self.data_fetcher, &needs).await?;
+        light_blocks.write_bundle(out)?;
+        info!(
+            "Exported the {} light block responses from {} to {} to {}",
+            light_blocks.len(),
+            trusted_block,
+            target_block,
+            out.display()
+        );
+        Ok(())
+    }
+
+    /// The stored requests of the attempt `correlation_id`, oldest first, each with its status as
+    /// the backend reports it now.
+    pub async fn attempt_status(
+        &self,
+        correlation_id: CorrelationId,
+    ) -> Result<Vec<(RequestRecord, Result<FulfillmentStatus>)>> {
+        let store = self
+            .store
+            .as_ref()
+            .ok_or_else(|| anyhow!("looking up an attempt requires a request store"))?;
+        let records = store.by_correlation_id(correlation_id)?;
+        ensure!(
+            !records.is_empty(),
+            "no requests stored for correlation ID {}",
+            correlation_id
+        );
+        let mut statuses = Vec::with_capacity(records.len());
+        for record in records {
+            let status = self.backend.status(&record.request_id).await;
+            statuses.push((record, status));
+        }
+        Ok(statuses)
+    }
+
+    /// The chain head, the lag of every target behind it and the balance of its relayer, the
+    /// pending requests and the recent errors. Submits nothing.
+    pub async fn collect_status(&mut self) -> Result<StatusSnapshot> {
+        if !self.function_ids_resolved() {
+            self.resolve_function_ids().await?;
+        }
+        self.status_snapshot().await
+    }
+
+    /// `collect_status` of a shared operator. It is only written to resolve the function IDs if
+    /// they aren't yet, so the status doesn't wait for the requests being submitted.
+    pub async fn shared_status(operator: &SharedOperator<M>) -> Result<StatusSnapshot> {
+        if !operator.read().await.function_ids_resolved() {
+            let mut operator = operator.write().await;
+            if !operator.function_ids_resolved() {
+                operator.resolve_function_ids().await?;
+            }
+        }
+        operator.read().await.status_snapshot().await
+    }
+
+    async fn status_snapshot(&self) -> Result<StatusSnapshot> {
+        let head = self.data_fetcher.latest_signed_header().await?;
+        let head_block = head.header.height.value();
+        let head_time = head.header.time.unix_timestamp();
+        let mut targets = Vec::new();
+        let mut errors = Vec::new();
+        for target in self.targets.iter() {
+            let block = target.trusted.latest_block().await?;
+            let header = self
+                .data_fetcher
+                .get_signed_header_from_number(block)
+                .await
+                .with_context(|| {
+                    format!("failed to read the latest header of {}", target.request)
+                })?;
+            let block = block.value();
+            let updated_at = header.header.time.unix_timestamp();
+            let mut balance = None;
+            if let Some(monitor) = target.balance_monitor.as_ref() {
+                match monitor.lock().await.check(target.provider.as_ref()).await {
+                    Ok(report) => balance = Some(report),
+                    Err(e) => errors.push(format!(
+                        "balance check for {} failed: {:#}",
+                        target.request, e
+                    )),
+                }
+            }
+            targets.push(TargetStatus {
+                target: target.request.to_string(),
+                latest_block: block,
+                updated_at,
+                lag: Lag::new(head_block, head_time, block, updated_at),
+                balance,
+                trusting_margin: self
+                    .chain_spec
+                    .as_ref()
+                    .map(|spec| spec.trusting_margin(updated_at, head_time)),
+                step_function_id: target.request.step_function_id,
+                skip_function_id: target.request.skip_function_id,
+                function_ids_from: target.function_ids_from,
+            });
+        }
+
+        let now = unix_timestamp();
+        let mut pending = Vec::new();
+        if let Some(store) = self.store.as_ref() {
+            for record in store.pending()? {
+                pending.push(PendingStatus {
+                    target: format!("{}:{}", record.chain_id, record.contract_address),
+                    target_block: record.target_block,
+                    status: record.status,
+                    age: Duration::from_secs(now.saturating_sub(record.created_at)),
+                    request_id: record.request_id,
+                });
+            }
+        }
+
+        // The audit log has the error messages; the store only knows which requests failed.
+        let mut recent = Vec::new();
+        if let Some(audit) = self.audit.as_ref() {
+            for entry in audit::recent_errors(audit.path(), dashboard::RECENT_ERRORS)? {
+                recent.push(entry.event.to_string());
+            }
+        } else if let Some(store) = self.store.as_ref() {
+            let failed = store
+                .list(100)?
+                .into_iter()
+                .filter(|record| record.status == RequestStatus::Failed)
+                .take(dashboard::RECENT_ERRORS)
+                .map(|record| format!("request {}", record));
+            recent.extend(failed);
+            // The store lists the newest first.
+            recent.reverse();
+        }
+        recent.extend(errors);
+
+        Ok(StatusSnapshot {
+            head_block,
+            head_time,
+            targets,
+            endpoints: self.metrics.endpoint_health(),
+            pending,
+            errors: recent,
+        })
+    }
+
+    /// Submit a request exported with `export_input`, without checking for pending requests.
+    pub async fn submit_input(&self, path: &Path) -> Result<()> {
+        let contents = std::fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let imported = ImportedRequest::from_file(&serde_json::from_str(&contents)?)?;
+        let target = self
+            .targets
+            .iter()
+            .map(|t| &t.request)
+            .find(|t| t.chain_id == imported.chain_id && t.address == imported.address)
+            .ok_or_else(|| anyhow!("no target for {}:{}", imported.chain_id, imported.address))?;
+        let correlation_id = CorrelationId::generate();
+        let attempt = attempt_span(correlation_id);
+        let request = imported
+            .proof_request(target)?
+            .with_correlation_id(correlation_id);
+        let inputs = &imported.inputs;
+        let submission = TargetSubmission {
+            target,
+            result: self
+                .submit(inputs.kind, &request)
+                .instrument(attempt.clone())
+                .await,
+        };
+        if let Ok(request_id) = &submission.result {
+            info!("request____start{}request____end", request_id);
+        }
+        let submissions = [submission];
+        attempt.in_scope(|| Self::log_submissions(&format!("{:?}", inputs.kind), &submissions));
+        self.record_submissions(&submissions, inputs, correlation_id);
+        match &submissions[0].result {
+            Ok(_) => Ok(()),
+            Err(e) => Err(anyhow!("submission failed: {:#}", e)),
+        }
+    }
+
+    /// Prove the checkpoints of `backfill` into the contract of the target on `chain_id`, or the
+    /// first target.
+    pub async fn backfill(
+        &self,
+        chain_id: Option<u32>,
+        mut backfill: Backfill,
+    ) -> Result<BackfillSummary> {
+        let target = self.target_of(chain_id)?;
+        backfill.poll_interval = self.landing_poll_interval;
+        backfill.timeout = self.landing_timeout;
+        let mut contract = BackfillContract {
+            operator: self,
+            target,
+            data_fetcher: InputDataFetcher {
+                adapter: self.data_fetcher.adapter.clone(),
+                ..InputDataFetcher::new(
+                    self.data_fetcher.endpoints.urls(),
+                    &self.data_fetcher.fixture_path,
+                )
+            },
+            skip_max: self.bound_skip(target.trusted.skip_max().await?),
+        };
+        backfill.run(&mut contract).await
+    }
+
+    /// The target of `chain_id`, the first target if unset. Mirror chains have no contract the
+    /// operator can send to.
+    fn target_of(&self, chain_id: Option<u32>) -> Result<&Target<M>> {
+        match chain_id {
+            Some(chain_id) => self
+                .targets
+                .iter()
+                .find(|t| t.request.chain_id == chain_id && !t.mirror)
+                .ok_or_else(|| anyhow!("no target for chain {}", chain_id)),
+            None => Ok(&self.targets[0]),
+        }
+    }
+
+    /// The contract address of the target of `chain_id`, the first target if unset.
+    pub fn target_address(&self, chain_id: Option<u32>) -> Result<Address> {
+        Ok(self.target_of(chain_id)?.request.address)
+    }
+
+    /// The header hash the target of `chain_id` (the first target if unset) stores for `block`,
+    /// if any.
+    pub async fn stored_header_hash(
+        &self,
+        chain_id: Option<u32>,
+        block: Height,
+    ) -> Result<Option<HeaderHash>> {
+        self.target_of(chain_id)?.trusted.hash_at(block).await
+    }
+
+    /// Scan the stored headers of the target of `chain_id` (the first target if unset) backwards
+    /// for the newest that is the chain's, and plan the skips from it to the chain head. The
+    /// stored blocks are read from the `HeadUpdate` events since the Ethereum block `events_from`
+    /// if set, and probed otherwise. Reads only.
+    pub async fn repair_plan(
+        &self,
+        chain_id: Option<u32>,
+        events_from: Option<u64>,
+        bounds: ScanBounds,
+    ) -> Result<RepairPlan> {
+        let target = self.target_of(chain_id)?;
+        let stored = match events_from {
+            Some(from) => {
+                let updates = target.contract.head_updates(from).await?;
+                Some(updates.iter().map(|u| Height(u.block_number)).collect())
+            }
+            None => None,
+        };
+        let divergence =
+            repair::scan(target.trusted.as_ref(), &self.data_fetcher, stored, bounds).await?;
+        let head = self.data_fetcher.latest_signed_header().await?;
+        let skip_max = self.bound_skip(target.trusted.skip_max().await?);
+        RepairPlan::new(
+            target.request.to_string(),
+            divergence,
+            head.header.height.into(),
+            skip_max,
+        )
+    }
+
+    /// Set the consistent state of `plan`, planned for the target of `chain_id`, back as the
+    /// latest block of its contract, with a `setGenesisHeader` transaction the operator's client
+    /// must sign, and check it was. The run loop then proves the skips of the plan. Returns the
+    /// transaction hash, `None` if there was nothing to set back.
+    pub async fn repair(
+        &self,
+        chain_id: Option<u32>,
+        plan: &RepairPlan,
+    ) -> Result<Option<[u8; 32]>> {
+        if !plan.needs_rewind() {
+            return Ok(None);
+        }
+        let target = self.target_of(chain_id)?;
+        let state = plan.state;
+        let tx_hash = target
+            .contract
+            .set_genesis_header(state.block.value(), state.header_hash.to_bytes())
+            .await?;
+        let latest = target.contract.latest_block().await?;
+        let stored = target.contract.header_hash(state.block.value()).await?;
+        ensure!(
+            latest == state.block.value() && stored == Some(state.header_hash.to_bytes()),
+            "{} did not set {} back as its latest block",
+            target.request,
+            state
+        );
+        info!(
+            "Set {} back as the latest block of {} in tx {}",
+            state,
+            target.request,
+            B256::from(tx_hash)
+        );
+        Ok(Some(tx_hash))
+    }
+
+    /// Replay the stored request `request_id` for its target.
+    pub async fn replay(&self, request_id: &str, force: bool) -> Result<()> {
+        let store = self
+            .store
+            .as_ref()
+            .ok_or_else(|| anyhow!("replaying a request requires a request store"))?;
+        let record = store
+            .get(request_id)?
+            .ok_or_else(|| anyhow!("request {} is not in the store", request_id))?;
+        let target = self
+            .targets
+            .iter()
+            .find(|t| {
+                t.request.chain_id == record.chain_id
+                    && t.request.address == record.contract_address
+            })
+            .ok_or_else(|| {
+                anyhow!(
+                    "no target for {}:{}",
+                    record.chain_id,
+                    record.contract_address
+                )
+            })?;
+        let latest_block = target.trusted.latest_block().await?.value();
+        let replayed = replay(
+            store,
+            self.backend.as_ref(),
+            &target.request,
+            &record,
+            latest_block,
+            force,
+        )
+        .await?;
+        info!(
+            "Replayed request {} for {}: {} [{}]",
+            request_id, target.request, replayed, target.request.labels
+        );
+        info!("request____start{}request____end", replayed);
+        Ok(())
+    }
+
+    /// Submit a new attempt of a failed request. The trusted header is re-fetched from the
+    /// Tendermint chain rather than reused from the original request. Retries keep the correlation
+    /// ID of the request they retry.
+    async fn resubmit(&self, request: &SubmittedRequest, attempt: u32) -> Result<String> {
+        self.metrics.record_retry();
+        let trusted_hash = self
+            .data_fetcher
+            .compute_header_hash(request.trusted_block)
+            .await?;
+        let target = &request.target;
+        let inputs = RequestInputs::new(request.trusted_block, trusted_hash, request.target_block)?;
+        let proof_request = inputs
+            .proof_request(target)
+            .with_correlation_id(request.correlation_id);
+        let function_id = proof_request.function_id;
+        let request_id = self.submit(inputs.kind, &proof_request).await?;
+        info!(
+            "Resubmitted request {} for {} (attempt {}): {} [{}]",
+            request.request_id, target, attempt, request_id, target.labels
+        );
+        info!("request____start{}request____end", request_id);
+
+        if let Some(store) = self.store.as_ref() {
+            let record = NewRequest {
+                chain_id: target.chain_id,
+                contract_address: target.address,
+                trusted_block: request.trusted_block.value(),
+                trusted_hash: trusted_hash.to_bytes(),
+                target_block: request.target_block.value(),
+                function_id,
+                request_id: request_id.clone(),
+                retry_of: Some(request.request_id.clone()),
+                attempt,
+                backend: Some(self.served_by(&request_id)),
+                request_mode: target.request_mode,
+                labels: target.labels.clone(),
+                input: inputs.input.clone(),
+                correlation_id: Some(request.correlation_id),
+            };
+            if let Err(e) = store.insert(&record) {
+                error!(
+                    "Failed to record request {} [{}]: {:#}",
+                    request_id, target.labels, e
+                );
+            }
+        }
+        Ok(request_id)
+    }
+
+    /// Wait for each request to be fulfilled, retrying failed requests according to the retry
+    /// policy. Returns the outcome of each request, in order.
+    pub async fn wait_for_requests(
+        &self,
+        requests: &[SubmittedRequest],
+        timeout: Duration,
+    ) -> Vec<RetryOutcome> {
+        let mut outcomes = Vec::with_capacity(requests.len());
+        for request in requests {
+            let attempt_span = attempt_span(request.correlation_id);
+            let outcome = fulfill_with_retries(
+                &self.retry_policy,
+                request.request_id.clone(),
+                |request_id| async move {
+                    self.wait_for_request(&request_id, &request.target, timeout)
+                        .await
+                },
+                |attempt| self.resubmit(request, attempt),
+            )
+            .instrument(attempt_span.clone())
+            .await;
+            if outcome.exhausted {
+                attempt_span.in_scope(|| {
+                    error!(
+                        "ALERT: request {} for {} from {} to {} failed {} times, manual intervention required [{}]",
+                        request.request_id,
+                        request.target,
+                        request.trusted_block,
+                        request.target_block,
+                        outcome.attempts.len(),
+                        request.target.labels
+                    )
+                });
+                let alert = Alert::new(
+                    AlertKind::RetriesExhausted,
+                    format!("request {} failed on every attempt", request.request_id),
+                )
+                .with_detail("target", &request.target)
+                .with_detail("trusted_block", request.trusted_block)
+                .with_detail("target_block", request.target_block)
+                .with_detail("attempts", outcome.attempts.len())
+                .with_detail("correlation_id", request.correlation_id);
+                self.alerter.send(&alert).instrument(attempt_span).await;
+            }
+            outcomes.push(outcome);
+        }
+        outcomes
+    }
+
+    /// The proof document of an off-chain request that `outcome` followed to its proof.
+    pub async fn proof_document(
+        &self,
+        request: &SubmittedRequest,
+        outcome: &RetryOutcome,
+    ) -> Result<ProofDocument> {
+        ensure!(
+            request.target.request_mode == RequestMode::Offchain,
+            "request {} for {} is relayed on-chain, only off-chain requests have a proof document",
+            request.request_id,
+            request.target
+        );
+        let request_id = match outcome.attempts.last() {
+            Some(Attempt {
+                request_id: Some(request_id),
+                status: Some(FulfillmentStatus::Proved { .. }),
+                ..
+            }) => request_id,
+            _ => {
+                return Err(anyhow!(
+                    "request {} for {} was not proved: {:?}",
+                    request.request_id,
+                    request.target,
+                    outcome.status()
+                ))
+            }
+        };
+        let payload = self
+            .backend
+            .proof(request_id)
+            .await
+            .with_context(|| format!("failed to fetch the proof of request {}", request_id))?
+            .ok_or_else(|| {
+                anyhow!(
+                    "the {} backend returned no proof for request {}",
+                    self.backend.name(),
+                    request_id
+                )
+            })?;
+        let function_id = request.kind.function_id(&request.target);
+        let verification_key = self
+            .backend
+            .artifact_digest(function_id)
+            .await
+            .with_context(|| format!("failed to query the artifact of function {}", function_id))?;
+        Ok(ProofDocument::new(
+            request_id,
+            function_id,
+            verification_key,
+            payload,
+        ))
+    }
+
+    /// Write the proof document of the single off-chain request in `requests` to `path`, once
+    /// `outcomes` (those of `wait_for_requests`) saw it proved.
+    pub async fn emit_proof(
+        &self,
+        requests: &[SubmittedRequest],
+        outcomes: &[RetryOutcome],
+        path: &Path,
+    ) -> Result<()> {
+        let mut offchain = requests
+            .iter()
+            .zip(outcomes)
+            .filter(|(request, _)| request.target.request_mode == RequestMode::Offchain);
+        let (request, outcome) = offchain
+            .next()
+            .ok_or_else(|| anyhow!("no off-chain request to emit the proof of"))?;
+        ensure!(
+            offchain.next().is_none(),
+            "more than one off-chain request: emit the proof of one target at a time"
+        );
+        let document = self.proof_document(request, outcome).await?;
+        document.write(path)?;
+        info!(
+            "Wrote the proof of request {} for {} to {}",
+            document.request_id,
+            request.target,
+            path.display()
+        );
+        Ok(())
+    }
+}
+
+/// The contract of a target that `backfill` proves checkpoints into. Its data fetcher is its own,
+/// so that the backfill never touches the run loop's.
+struct BackfillContract<'a, M> {
+    operator: &'a TendermintXOperator<M>,
+    target: &'a Target<M>,
+    data_fetcher: InputDataFetcher,
+    skip_max: u64,
+}
+
+#[async_trait]
+impl<M: Middleware + 'static> Checkpoints for BackfillContract<'_, M> {
+    async fn stored_hash(&self, height: u64) -> Result<Option<[u8; 32]>> {
+        let hash = self.target.trusted.hash_at(Height(height)).await?;
+        Ok(hash.map(HeaderHash::to_bytes))
+    }
+
+    async fn latest_block(&self) -> Result<u64> {
+        Ok(self.target.trusted.latest_block().await?.value())
+    }
+
+    async fn next_target(&mut self, trusted_block: u64, max_block: u64) -> Result<u64> {
+        let max_block = max_block.min(trusted_block + self.skip_max);
+        // Landing on a halt height before proving past it, as the run loop does.
+        let max_block = self
+            .operator
+            .halt_heights
+            .within(trusted_block, max_block)
+            .unwrap_or(max_block);
+        let target_block = self
+            .data_fetcher
+            .find_block_to_request(Height(trusted_block), Height(max_block))
+            .await?;
+        Ok(target_block.value())
+    }
+
+    async fn request(
+        &self,
+        trusted_block: u64,
+        trusted_hash: [u8; 32],
+        target_block: u64,
+    ) -> Result<Option<String>> {
+        let operator = self.operator;
+        let target = &self.target.request;
+        let (targets, _reservations) = operator
+            .without_pending_request(
+                [target],
+                RequestKind::for_range(trusted_block, target_block),
+                trusted_block,
+                target_block,
+            )
+            .await;
+        if targets.is_empty() {
+            return Ok(None);
+        }
+        let inputs = RequestInputs::new(
+            Height(trusted_block),
+            HeaderHash(trusted_hash),
+            Height(target_block),
+        )?;
+        let correlation_id = CorrelationId::generate();
+        let attempt = attempt_span(correlation_id);
+        let request = inputs
+            .proof_request(target)
+            .with_correlation_id(correlation_id);
+        let submission = TargetSubmission {
+            target,
+            result: operator
+                .submit(inputs.kind, &request)
+                .instrument(attempt.clone())
+                .await,
+        };
+        let submissions = [submission];
+        attempt.in_scope(|| {
+            TendermintXOperator::<M>::log_submissions(&format!("{:?}", inputs.kind), &submissions)
+        });
+        operator.record_submissions(&submissions, &inputs, correlation_id);
+        let [submission] = submissions;
+        submission.result.map(Some)
+    }
+
+    async fn status(&self, request_id: &str) -> Result<FulfillmentStatus> {
+        self.operator.backend.status(request_id).await
+    }
+}
+
+#[async_trait]
+impl<M: Middleware + 'static> StatusSource for TendermintXOperator<M> {
+    async fn collect(&mut self) -> Result<StatusSnapshot> {
+        self.collect_status().await
+    }
+}
+
+/// `store`, mirrored to the Postgres database at `dsn` if set.
+#[cfg(feature = "postgres")]
+fn with_postgres_sink(store: RequestStore, dsn: Option<String>) -> Result<RequestStore> {
+    match dsn {
+        Some(dsn) => {
+            let sink = crate::sink::postgres::spawn(&dsn, crate::sink::DEFAULT_CAPACITY)?;
+            Ok(store.with_sink(sink))
+        }
+        None => Ok(store),
+    }
+}
+
+#[cfg(not(feature = "postgres"))]
+fn with_postgres_sink(store: RequestStore, dsn: Option<String>) -> Result<RequestStore> {
+    ensure!(dsn.is_none(), "POSTGRES_DSN requires the postgres feature");
+    Ok(store)
+}
+
+/// The error of a submission whose backend panicked, with the panic's message.
+fn backend_panic(panic: Box<dyn std::any::Any + Send>) -> anyhow::Error {
+    let message = panic
+        .downcast_ref::<String>()
+        .map(String::as_str)
+        .or_else(|| panic.downcast_ref::<&str>().copied())
+        .unwrap_or("no message");
+    anyhow!("the backend panicked: {}", message)
+}
+
+/// The output of `wait`, or None if `stopped` is set first.
+async fn until_stopped<F: Future>(
+    stopped: &mut watch::Receiver<bool>,
+    wait: F,
+) -> Option<F::Output> {
+    if *stopped.borrow() {
+        return None;
+    }
+    tokio::select! {
+        output = wait => Some(output),
+        // Only ever set, so any change stops.
+        _ = stopped.changed() => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::Address;
+    use ethers::providers::{Http, JsonRpcError, MockProvider, MockResponse};
+    use ethers::types::Bytes;
+    use tracing_subscriber::EnvFilter;
+
+    use super::*;
+    use crate::backend::mock::{MockBackend, MockRequest};
+    use crate::backend::ProofPayload;
+    use crate::control::ControlCommand;
+    use crate::encoding::{
+        encode_commit_header_range_calldata, encode_header_range_input, encode_skip_calldata,
+        encode_skip_input, encode_step_calldata, encode_step_input,
+    };
+    use crate::fault::{Fault, FaultInjecting};
+    use crate::input::adapter::RpcAdapterKind;
+    use crate::logging::{json_subscriber, Captured};
+    use crate::summary::RunOutcome;
+    use crate::testing::{fixture_fetcher, MockTendermintServer, SyntheticChain};
+    use crate::trusted::LocalTrustedState;
+
+    fn operator(
+        config: TendermintXConfig,
+        providers: usize,
+    ) -> Result<TendermintXOperator<Provider<Http>>> {
+        let fetcher = fixture_fetcher();
+        let provider = Arc::new(Provider::try_from("http://localhost:8545").unwrap());
+        let backend = Box::new(MockBackend::new());
+        TendermintXOperator::new(config, fetcher, backend, vec![provider; providers])
+    }
+
+    /// The requests submitted for the test target of chain 5 from block 100 to `target_block`,
+    /// with the trusted header either read from the contract or given.
+    async fn submitted_requests(
+        trusted_from_contract: bool,
+        target_block: u64,
+    ) -> Vec<MockRequest> {
+        let fetcher = fixture_fetcher();
+        let (provider, mock) = Provider::mocked();
+        mock.push::<Bytes, _>(Bytes::from(vec![0xab; 32])).unwrap();
+        let backend = Arc::new(MockBackend::new());
+        let config = TendermintXConfig::new(vec![RequestTarget::for_test(5)]);
+        let operator = TendermintXOperator::<Provider<MockProvider>>::new(
+            config,
+            fetcher,
+            Box::new(backend.clone()),
+            vec![Arc::new(provider)],
+        )
+        .unwrap();
+
+        let trusted = if trusted_from_contract {
+            TrustedState::FromContract { block: Height(100) }
+        } else {
+            TrustedState::Explicit {
+                block: Height(100),
+                hash: HeaderHash([0xab; 32]),
+            }
+        };
+        let targets: Vec<_> = operator.targets.iter().collect();
+        let correlation_id = CorrelationId::generate();
+        let submissions = if target_block == 101 {
+            operator
+                .request_step(&targets, trusted, correlation_id)
+                .await
+                .unwrap()
+        } else {
+            operator
+                .request_skip(&targets, trusted, Height(target_block), correlation_id)
+                .await
+                .unwrap()
+        };
+        assert!(submissions.iter().all(|s| s.result.is_ok()));
+        backend.requests()
+    }
+
+    #[tokio::test]
+    async fn test_trusted_state_variants_submit_the_same_request() {
+        for (target_block, kind) in [(101, RequestKind::Step), (500, RequestKind::Skip)] {
+            let from_contract = submitted_requests(true, target_block).await;
+            let explicit = submitted_requests(false, target_block).await;
+            assert_eq!(from_contract.len(), 1);
+            assert_eq!(from_contract[0].kind, kind);
+            assert_eq!(
+                from_contract[0].input.as_ref(),
+                &RequestInputs::new(Height(100), HeaderHash([0xab; 32]), Height(target_block))
+                    .unwrap()
+                    .input[..]
+            );
+            assert_eq!(from_contract, explicit);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_request_inputs_without_stored_header() {
+        let fetcher = fixture_fetcher();
+        let (provider, mock) = Provider::mocked();
+        mock.push::<Bytes, _>(Bytes::from(vec![0u8; 32])).unwrap();
+        let config = TendermintXConfig::new(vec![RequestTarget::for_test(5)]);
+        let operator = TendermintXOperator::new(
+            config,
+            fetcher,
+            Box::new(MockBackend::new()),
+            vec![Arc::new(provider)],
+        )
+        .unwrap();
+
+        let targets: Vec<_> = operator.targets.iter().collect();
+        let trusted = TrustedState::FromContract { block: Height(100) };
+        let error = operator
+            .request_inputs(&targets, trusted, Height(101))
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "no header stored for trusted block 100");
+    }
+
+    #[test]
+    fn test_new() {
+        // Every target needs a provider.
+        let config =
+            TendermintXConfig::new(vec![RequestTarget::for_test(5), RequestTarget::for_test(5)]);
+        let error = operator(config, 1).err().unwrap();
+        assert_eq!(error.to_string(), "1 providers for 2 targets");
+        let error = operator(TendermintXConfig::new(Vec::new()), 0)
+            .err()
+            .unwrap();
+        assert_eq!(error.to_string(), "no targets to update");
+
+        // Callbacks update the request store.
+        let mut config = TendermintXConfig::new(vec![RequestTarget::for_test(5)]);
+        config.webhook = Some(WebhookConfig {
+            addr: "127.0.0.1:0".parse().unwrap(),
+            secret: "secret".to_string(),
+        });
+        let error = operator(config, 1).err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "the callback listener requires a request store"
+        );
+
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = TendermintXConfig::new(vec![RequestTarget::for_test(5)]);
+        config.store_path = Some(dir.path().join("requests.db"));
+        config.max_requests_per_hour = Some(10);
+        let operator = operator(config, 1).unwrap();
+        assert!(operator.store.is_some());
+        assert!(operator.rate_limiter.is_some());
+        assert_eq!(operator.targets[0].request, RequestTarget::for_test(5));
+    }
+
+    #[tokio::test]
+    async fn test_consistency_with_local_trusted_state() {
+        let mut operator =
+            operator(TendermintXConfig::new(vec![RequestTarget::for_test(5)]), 1).unwrap();
+        let trusted = Arc::new(LocalTrustedState::new(1000));
+        let header = operator
+            .data_fetcher
+            .get_signed_header_from_number(Height(10000))
+            .await
+            .unwrap()
+            .header;
+        trusted.insert(Height(10000), header_hash(&header)).unwrap();
+        trusted
+            .insert(Height(10001), HeaderHash([0xab; 32]))
+            .unwrap();
+        operator.set_trusted_state(0, trusted).unwrap();
+        assert!(operator
+            .set_trusted_state(1, Arc::new(LocalTrustedState::new(1000)))
+            .is_err());
+
+        // Nothing is read from Ethereum.
+        operator.read_skip_maxes().await.unwrap();
+        assert_eq!(operator.skip_maxes, vec![1000]);
+        let target = &operator.targets[0];
+        let state = IterationState::read(target.trusted.as_ref()).await.unwrap();
+        let stored = IterationState {
+            latest_block: Height(10001),
+            header_hash: HeaderHash([0xab; 32]),
+        };
+        assert_eq!(state, stored);
+        let error = operator.is_consistent(target, &state).await.unwrap_err();
+        assert!(error.is::<HeaderMismatch>());
+        let consistent = IterationState {
+            latest_block: Height(10000),
+            header_hash: header_hash(&header),
+        };
+        assert_eq!(
+            operator.is_consistent(target, &consistent).await.unwrap(),
+            header.time.unix_timestamp()
+        );
+    }
+
+    /// An operator for the test target of chain 5 reading the mocha-4 fixtures and a local trusted
+    /// state with a skip_max of `skip_max`, so that nothing is read from Tendermint or Ethereum.
+    fn fixture_operator(
+        config: TendermintXConfig,
+        backend: Box<dyn ProofBackend>,
+        skip_max: u64,
+    ) -> TendermintXOperator<Provider<Http>> {
+        let fetcher = fixture_fetcher();
+        let provider = Arc::new(Provider::try_from("http://localhost:8545").unwrap());
+        let mut operator =
+            TendermintXOperator::new(config, fetcher, backend, vec![provider]).unwrap();
+        operator
+            .set_trusted_state(0, Arc::new(LocalTrustedState::new(skip_max)))
+            .unwrap();
+        operator
+    }
+
+    async fn fixture_hash<M>(operator: &TendermintXOperator<M>, block: u64) -> HeaderHash {
+        let header = operator
+            .data_fetcher
+            .get_signed_header_from_number(Height(block))
+            .await
+            .unwrap()
+            .header;
+        header_hash(&header)
+    }
+
+    #[tokio::test]
+    async fn test_prove() {
+        let backend = Arc::new(MockBackend::new());
+        let config = TendermintXConfig::new(vec![RequestTarget::for_test(5)]);
+        let operator = fixture_operator(config, Box::new(backend.clone()), 1000);
+        let trusted_hash = fixture_hash(&operator, 10000).await;
+
+        // A step, then a skip: exactly their encodings reach the backend.
+        for (target_block, request_id) in [(10001, "mock-1"), (10004, "mock-2")] {
+            let submitted = operator
+                .prove(Height(10000)..=Height(target_block), trusted_hash)
+                .await
+                .unwrap();
+            assert_eq!(submitted.len(), 1);
+            assert_eq!(submitted[0].request_id, request_id);
+            assert_eq!(submitted[0].target, RequestTarget::for_test(5));
+            assert_eq!(
+                (submitted[0].trusted_block, submitted[0].target_block),
+                (Height(10000), Height(target_block))
+            );
+        }
+        let step = MockRequest {
+            request_id: "mock-1".to_string(),
+            kind: RequestKind::Step,
+            target: RequestTarget::for_test(5),
+            trusted_block: 10000,
+            target_block: 10001,
+            function_id: RequestTarget::for_test(5).step_function_id,
+            calldata: encode_step_calldata(Height(10000)).into(),
+            input: encode_step_input(Height(10000), trusted_hash).into(),
+        };
+        let skip = MockRequest {
+            request_id: "mock-2".to_string(),
+            kind: RequestKind::Skip,
+            target: RequestTarget::for_test(5),
+            trusted_block: 10000,
+            target_block: 10004,
+            function_id: RequestTarget::for_test(5).skip_function_id,
+            calldata: encode_skip_calldata(Height(10000), Height(10004)).into(),
+            input: encode_skip_input(Height(10000), trusted_hash, Height(10004)).into(),
+        };
+        assert_eq!(backend.requests(), [step, skip]);
+    }
+
+    #[tokio::test]
+    async fn test_function_ids_per_target() {
+        let mut other = RequestTarget::for_test(5);
+        other.chain_id = 10;
+        other.step_function_id = B256::repeat_byte(0x44);
+        other.skip_function_id = B256::repeat_byte(0x55);
+        let backend = Arc::new(MockBackend::new());
+        let config = TendermintXConfig::new(vec![RequestTarget::for_test(5), other.clone()]);
+        let fetcher = fixture_fetcher();
+        let provider = Arc::new(Provider::try_from("http://localhost:8545").unwrap());
+        let mut fan_out = TendermintXOperator::new(
+            config,
+            fetcher,
+            Box::new(backend.clone()),
+            vec![provider; 2],
+        )
+        .unwrap();
+        for index in 0..2 {
+            fan_out
+                .set_trusted_state(index, Arc::new(LocalTrustedState::new(1000)))
+                .unwrap();
+        }
+        let trusted_hash = fixture_hash(&fan_out, 10000).await;
+
+        // The same request is submitted to both targets, each with its own function ID.
+        fan_out
+            .prove(Height(10000)..=Height(10004), trusted_hash)
+            .await
+            .unwrap();
+        let requests = backend.requests();
+        let submitted = requests
+            .iter()
+            .map(|r| (r.target.chain_id, r.function_id))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            submitted,
+            [
+                (5, RequestTarget::for_test(5).skip_function_id),
+                (10, other.skip_function_id)
+            ]
+        );
+        assert_eq!(requests[0].input, requests[1].input);
+
+        // A target missing a function ID is rejected at startup, by name.
+        other.skip_function_id = B256::ZERO;
+        let mut missing = operator(
+            TendermintXConfig::new(vec![RequestTarget::for_test(5), other]),
+            2,
+        )
+        .unwrap();
+        for index in 0..2 {
+            let trusted = Arc::new(LocalTrustedState::new(1000));
+            missing.set_trusted_state(index, trusted).unwrap();
+        }
+        let error = missing.resolve_function_ids().await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "target 10:{} has no skip function ID",
+                Address::repeat_byte(0x11)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_mirror_chain_ids() {
+        let dir = tempfile::tempdir().unwrap();
+        let mirrored = RequestTarget {
+            mirror_chain_ids: vec![10, 42161],
+            ..RequestTarget::for_test(5)
+        };
+        let mut config = TendermintXConfig::new(vec![mirrored]);
+        config.store_path = Some(dir.path().join("requests.db"));
+        let backend = Arc::new(MockBackend::new());
+        backend.fail_chain(10);
+        let operator = fixture_operator(config, Box::new(backend.clone()), 1000);
+        let trusted_hash = fixture_hash(&operator, 10000).await;
+
+        // A failure for one chain doesn't keep the request from the others.
+        let submitted = operator
+            .prove(Height(10000)..=Height(10004), trusted_hash)
+            .await
+            .unwrap();
+        let chain_ids = |submitted: &[SubmittedRequest]| {
+            submitted
+                .iter()
+                .map(|s| s.target.chain_id)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(chain_ids(&submitted), [5, 42161]);
+        assert_eq!(backend.submission_attempts(), 3);
+
+        // Retrying submits only for the chain without a pending request.
+        backend.recover_chain(10);
+        let retried = operator
+            .prove(Height(10000)..=Height(10004), trusted_hash)
+            .await
+            .unwrap();
+        assert_eq!(chain_ids(&retried), [10]);
+
+        // One input, submitted for each chain under its own request ID.
+        let requests = backend.requests();
+        let submitted = requests
+            .iter()
+            .map(|r| (r.target.chain_id, r.target.address, r.function_id))
+            .collect::<Vec<_>>();
+        let (address, skip) = (
+            RequestTarget::for_test(5).address,
+            RequestTarget::for_test(5).skip_function_id,
+        );
+        assert_eq!(
+            submitted,
+            [
+                (5, address, skip),
+                (42161, address, skip),
+                (10, address, skip)
+            ]
+        );
+        assert!(requests.iter().all(|r| r.input == requests[0].input));
+        let store = operator.store.as_ref().unwrap();
+        for request in requests.iter() {
+            let record = store.get(&request.request_id).unwrap().unwrap();
+            assert_eq!(record.chain_id, request.target.chain_id);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_mirrors_are_not_waited_for() {
+        let server = MockTendermintServer::start(SyntheticChain::new(7))
+            .await
+            .unwrap();
+        let mirrored = RequestTarget {
+            mirror_chain_ids: vec![10],
+            ..RequestTarget::for_test(5)
+        };
+        let mut config = TendermintXConfig::new(vec![mirrored]);
+        config.landing_timeout = Duration::from_secs(60);
+        config.landing_poll_interval = Duration::from_millis(10);
+        let (mut operator, backend, _) = faulty_operator(&server, config, "");
+        let outcome = operator.run_once().await.unwrap();
+        let chunk = &outcome.chunks[0];
+        assert_eq!(chunk.targets.len(), 2);
+
+        // The request for chain 5 fails while the mirror's is still proving: the chunk settles
+        // without polling the mirror's request, which the contract of chain 5 can't tell about.
+        let failed = FulfillmentStatus::Failed { error: None };
+        backend.inner().set_status(&chunk.request_ids[0], failed);
+        let status_calls = backend.inner().status_calls();
+        assert!(operator.wait_for_landing(&outcome.chunks).await);
+        assert_eq!(backend.inner().status_calls(), status_calls + 1);
+    }
+
+    #[tokio::test]
+    async fn test_resolve_function_ids() {
+        // Operators of `target` reading its contract through a mock provider.
+        let mocked = |target: RequestTarget| {
+            let fetcher = fixture_fetcher();
+            let (provider, mock) = Provider::mocked();
+            let config = TendermintXConfig::new(vec![target]);
+            let backend = Box::new(MockBackend::new());
+            let operator =
+                TendermintXOperator::new(config, fetcher, backend, vec![Arc::new(provider)]);
+            (operator.unwrap(), mock)
+        };
+        // The getters' answers, popped in reverse order of pushing.
+        let registered = |mock: &MockProvider| {
+            mock.push::<Bytes, _>(Bytes::from(vec![0x45; 32])).unwrap();
+            mock.push::<Bytes, _>(Bytes::from(vec![0x44; 32])).unwrap();
+        };
+        let mut unconfigured = RequestTarget::for_test(5);
+        unconfigured.step_function_id = B256::ZERO;
+        unconfigured.skip_function_id = B256::ZERO;
+
+        // A contract exposing the getters is the source of the IDs.
+        let (mut operator, mock) = mocked(unconfigured.clone());
+        registered(&mock);
+        operator.resolve_function_ids().await.unwrap();
+        assert!(operator.function_ids_resolved());
+        let resolved = &operator.targets[0];
+        assert_eq!(resolved.function_ids_from, Some(FunctionIdSource::Contract));
+        assert_eq!(
+            (
+                resolved.request.step_function_id,
+                resolved.request.skip_function_id
+            ),
+            (B256::repeat_byte(0x44), B256::repeat_byte(0x45))
+        );
+
+        // Configured IDs that differ from the contract's stop the operator.
+        let (mut operator, mock) = mocked(RequestTarget::for_test(5));
+        registered(&mock);
+        let error = operator.resolve_function_ids().await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "the function IDs configured for target 5:{} differ from its contract's:\n\
+                 - step function ID {}\n\
+                 + step function ID {}\n\
+                 - skip function ID {}\n\
+                 + skip function ID {}",
+                Address::repeat_byte(0x11),
+                B256::repeat_byte(0x22),
+                B256::repeat_byte(0x44),
+                B256::repeat_byte(0x33),
+                B256::repeat_byte(0x45)
+            )
+        );
+        assert!(!operator.function_ids_resolved());
+
+        // A contract without them: its calls revert, and the configured IDs are used.
+        let revert = || {
+            MockResponse::Error(JsonRpcError {
+                code: 3,
+                message: "execution reverted".to_string(),
+                data: None,
+            })
+        };
+        let (mut operator, mock) = mocked(RequestTarget::for_test(5));
+        mock.push_response(revert());
+        operator.resolve_function_ids().await.unwrap();
+        assert_eq!(operator.targets[0].request, RequestTarget::for_test(5));
+        assert_eq!(
+            operator.targets[0].function_ids_from,
+            Some(FunctionIdSource::Config)
+        );
+        // Without configured IDs either, the target has none.
+        let (mut operator, mock) = mocked(unconfigured);
+        mock.push_response(revert());
+        let error = operator.resolve_function_ids().await.unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "target 5:{} has no step function ID",
+                Address::repeat_byte(0x11)
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prove_rejects() {
+        let backend = Arc::new(MockBackend::new());
+        let config = TendermintXConfig::new(vec![RequestTarget::for_test(5)]);
+        let operator = fixture_operator(config, Box::new(backend.clone()), 3);
+        let trusted_hash = fixture_hash(&operator, 10000).await;
+        let prove = |range: RangeInclusive<u64>, hash: HeaderHash| {
+            let range = Height(*range.start())..=Height(*range.end());
+            let operator = &operator;
+            async move { operator.prove(range, hash).await.unwrap_err().to_string() }
+        };
+
+        // The blocks swapped.
+        assert_eq!(
+            prove(10001..=10000, trusted_hash).await,
+            "invalid range: target block 10000 is not after trusted block 10001"
+        );
+        // Another header hash than the chain's.
+        let error = prove(10000..=10001, HeaderHash([0xab; 32])).await;
+        assert!(
+            error.starts_with(&format!(
+                "trusted hash {} is not the header hash of block 10000",
+                HeaderHash([0xab; 32])
+            )),
+            "{}",
+            error
+        );
+        // A range over the skip_max.
+        assert_eq!(
+            prove(10000..=10004, trusted_hash).await,
+            format!(
+                "range of 4 blocks is more than the skip_max 3 of {}",
+                RequestTarget::for_test(5)
+            )
+        );
+        // None of them reached the backend.
+        assert_eq!(backend.submission_attempts(), 0);
+
+        // The backend failed the only target.
+        backend.fail_chain(RequestTarget::for_test(5).chain_id);
+        assert_eq!(
+            prove(10000..=10003, trusted_hash).await,
+            "Skip request failed for every target"
+        );
+        assert!(backend.submission_attempts() > 0);
+        assert!(backend.requests().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_export_input_from_light_blocks() {
+        let server = MockTendermintServer::start(SyntheticChain::new(7).with_validators(150))
+            .await
+            .unwrap();
+        let (online, _, _) = faulty_operator(
+            &server,
+            TendermintXConfig::new(vec![RequestTarget::for_test(5)]),
+            "",
+        );
+        let dir = tempfile::tempdir().unwrap();
+        let bundle = dir.path().join("light_blocks.json");
+        online
+            .export_light_blocks(Height(100), Height(180), &bundle)
+            .await
+            .unwrap();
+        let exported = dir.path().join("online.json");
+        online
+            .export_input(None, Height(100), None, Height(180), &exported, true)
+            .await
+            .unwrap();
+        let trusted_hash = header_hash(&server.chain().header(100));
+        drop(server);
+
+        // Neither the chain nor the contract is reachable.
+        let light_blocks = Arc::new(LightBlocks::load(&bundle).unwrap());
+        let fetcher = InputDataFetcher::with_light_blocks(light_blocks, "light_blocks.json");
+        let backend = Arc::new(MockBackend::new());
+        let (provider, _) = Provider::mocked();
+        let offline = TendermintXOperator::new(
+            TendermintXConfig::new(vec![RequestTarget::for_test(5)]),
+            fetcher,
+            Box::new(backend.clone()),
+            vec![Arc::new(provider)],
+        )
+        .unwrap();
+        let imported = dir.path().join("offline.json");
+        offline
+            .export_input(
+                None,
+                Height(100),
+                Some(trusted_hash),
+                Height(180),
+                &imported,
+                true,
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(&imported).unwrap(),
+            std::fs::read(&exported).unwrap()
+        );
+        let requests = offline
+            .prove(Height(100)..=Height(180), trusted_hash)
+            .await
+            .unwrap();
+        assert_eq!(requests[0].kind, RequestKind::Skip);
+        assert_eq!(backend.requests().len(), 1);
+
+        // The trusted hash must be given, and be that of the light blocks.
+        let error = offline
+            .export_input(None, Height(100), None, Height(180), &imported, true)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "exporting from light blocks takes the trusted hash"
+        );
+        let other_hash = HeaderHash([0xab; 32]);
+        let error = offline
+            .prove(Height(100)..=Height(180), other_hash)
+            .await
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .starts_with("the light block of the trusted block 100 hashes to"));
+
+        // Every missing part is listed, with the files that hold it.
+        let error = offline
+            .export_input(
+                None,
+                Height(100),
+                Some(trusted_hash),
+                Height(200),
+                &imported,
+                true,
+            )
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the light blocks lack the commit of block 200 (200/commit.json); the validators of \
+             block 200 (200/validators_1.json); the validators of block 201 \
+             (201/validators_1.json)"
+        );
+        let error = offline
+            .prove(Height(100)..=Height(101), trusted_hash)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the light blocks lack the commit of block 101 (101/commit.json); the validators of \
+             block 102 (102/validators_1.json)"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_emit_proof() {
+        let backend = Arc::new(MockBackend::new());
+        let offchain = RequestTarget {
+            request_mode: RequestMode::Offchain,
+            ..RequestTarget::for_test(5)
+        };
+        let config = TendermintXConfig::new(vec![offchain.clone()]);
+        let operator = fixture_operator(config, Box::new(backend.clone()), 1000);
+        let trusted_hash = fixture_hash(&operator, 10000).await;
+        let requests = operator
+            .prove(Height(10000)..=Height(10001), trusted_hash)
+            .await
+            .unwrap();
+        assert_eq!(requests[0].kind, RequestKind::Step);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("proof.json");
+
+        // Still proving when the wait times out.
+        let outcomes = operator.wait_for_requests(&requests, Duration::ZERO).await;
+        let error = operator
+            .emit_proof(&requests, &outcomes, &path)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "request mock-1 for {} was not proved: Some(TimedOut)",
+                offchain
+            )
+        );
+
+        let proved = FulfillmentStatus::Proved { proof_id: None };
+        backend.set_status("mock-1", proved);
+        let outcomes = operator.wait_for_requests(&requests, Duration::ZERO).await;
+        let error = operator
+            .emit_proof(&requests, &outcomes, &path)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the mock backend returned no proof for request mock-1"
+        );
+
+        let payload = ProofPayload {
+            proof: vec![0xaa; 4].into(),
+            input: encode_step_input(Height(10000), trusted_hash).into(),
+            output: vec![0xbb; 32].into(),
+        };
+        backend.set_proof("mock-1", payload.clone());
+        operator
+            .emit_proof(&requests, &outcomes, &path)
+            .await
+            .unwrap();
+        let document: ProofDocument =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            document,
+            ProofDocument::new("mock-1", offchain.step_function_id, None, payload)
+        );
+
+        // Relayed requests have no proof document.
+        let relayed = SubmittedRequest {
+            target: RequestTarget::for_test(5),
+            ..requests[0].clone()
+        };
+        let error = operator
+            .emit_proof(&[relayed], &outcomes, &path)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "no off-chain request to emit the proof of"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_prove_data_commitment() {
+        let dir = tempfile::tempdir().unwrap();
+        let backend = Arc::new(MockBackend::new());
+        let target = RequestTarget {
+            data_commitment_function_id: Some(B256::repeat_byte(0x44)),
+            ..RequestTarget::for_test(5)
+        };
+        let mut config = TendermintXConfig::new(vec![target.clone()]);
+        config.store_path = Some(dir.path().join("requests.db"));
+        let operator = fixture_operator(config, Box::new(backend.clone()), 1000);
+        let trusted_hash = fixture_hash(&operator, 10000).await;
+        let range = Height(10000)..=Height(10004);
+
+        let submitted = operator
+            .prove_data_commitment(range.clone(), trusted_hash)
+            .await
+            .unwrap();
+        assert_eq!(submitted.len(), 1);
+        let commitment = MockRequest {
+            request_id: "mock-1".to_string(),
+            kind: RequestKind::DataCommitment,
+            target: target.clone(),
+            trusted_block: 10000,
+            target_block: 10004,
+            function_id: B256::repeat_byte(0x44),
+            calldata: encode_commit_header_range_calldata(Height(10000), Height(10004)).into(),
+            input: encode_header_range_input(Height(10000), trusted_hash, Height(10004)).into(),
+        };
+        assert_eq!(backend.requests(), [commitment]);
+
+        // While it is pending it isn't resubmitted, but it doesn't hold up the skip of its range.
+        let submitted = operator
+            .prove_data_commitment(range.clone(), trusted_hash)
+            .await
+            .unwrap();
+        assert!(submitted.is_empty());
+        operator.prove(range.clone(), trusted_hash).await.unwrap();
+        let requests = backend.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[1].kind, RequestKind::Skip);
+
+        // Nor is anything submitted for a target without data commitments.
+        let config = TendermintXConfig::new(vec![RequestTarget::for_test(5)]);
+        let operator = fixture_operator(config, Box::new(backend.clone()), 1000);
+        let error = operator
+            .prove_data_commitment(range, trusted_hash)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            format!("{:#}", error),
+            format!(
+                "Data commitment request failed: data commitments are not enabled for {}",
+                RequestTarget::for_test(5)
+            )
+        );
+        assert_eq!(backend.submission_attempts(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_correlation_id_in_logs_and_store() {
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = TendermintXConfig::new(vec![RequestTarget::for_test(5)]);
+        config.store_path = Some(dir.path().join("requests.db"));
+        let operator = fixture_operator(config, Box::new(MockBackend::new()), 1000);
+        let trusted_hash = fixture_hash(&operator, 10000).await;
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = json_subscriber(EnvFilter::new("info"), move || writer.clone());
+        let _default = tracing::subscriber::set_default(subscriber);
+
+        let submitted = operator
+            .prove(Height(10000)..=Height(10004), trusted_hash)
+            .await
+            .unwrap();
+        assert_eq!(submitted.len(), 1);
+        let correlation_id = submitted[0].correlation_id;
+
+        // The lines logged for the attempt, from planning to submission, carry its ID.
+        let lines = captured.lines();
+        let logged = |prefix: &str| {
+            lines
+                .iter()
+                .find(|line| {
+                    line["fields"]["message"]
+                        .as_str()
+                        .is_some_and(|message| message.starts_with(prefix))
+                })
+                .unwrap_or_else(|| panic!("no {:?} line", prefix))
+        };
+        for prefix in [
+            "Requesting a proof",
+            "Request submitted",
+            "Skip request submitted",
+        ] {
+            let line = logged(prefix);
+            assert_eq!(line["spans"][0]["name"], "attempt");
+            assert_eq!(
+                line["spans"][0]["correlation_id"],
+                correlation_id.to_string()
+            );
+        }
+        assert_eq!(
+            logged("Request submitted")["span"]["correlation_id"],
+            correlation_id.to_string()
+        );
+
+        // So does the stored request.
+        let store = operator.store.as_ref().unwrap();
+        let records = store.by_correlation_id(correlation_id).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].request_id, submitted[0].request_id);
+        assert_eq!(records[0].correlation_id, Some(correlation_id));
+    }
+
+    #[tokio::test]
+    async fn test_attested_submissions() {
+        let dir = tempfile::tempdir().unwrap();
+        let seed = "07".repeat(32);
+        let key_path = dir.path().join("attestation.key");
+        std::fs::write(&key_path, &seed).unwrap();
+        let audit_path = dir.path().join("audit.jsonl");
+        let mut config = TendermintXConfig::new(vec![RequestTarget::for_test(5)]);
+        config.store_path = Some(dir.path().join("requests.db"));
+        config.audit = Some(AuditConfig {
+            path: audit_path.clone(),
+            max_bytes: audit::DEFAULT_MAX_BYTES,
+            max_files: audit::DEFAULT_MAX_FILES,
+        });
+        config.attestation_key_path = Some(key_path);
+        let captured = Captured::default();
+        let writer = captured.clone();
+        let subscriber = json_subscriber(EnvFilter::new("debug"), move || writer.clone());
+        let _default = tracing::subscriber::set_default(subscriber);
+        let operator = fixture_operator(config, Box::new(MockBackend::new()), 1000);
+        let trusted_hash = fixture_hash(&operator, 10000).await;
+
+        let submitted = operator
+            .prove(Height(10000)..=Height(10004), trusted_hash)
+            .await
+            .unwrap();
+        let public_key = Attester::from_seed([0x07; 32]).public_key();
+        let stored = operator
+            .store
+            .as_ref()
+            .unwrap()
+            .attestation(&submitted[0].request_id)
+            .unwrap()
+            .unwrap();
+        stored.verify(&public_key).unwrap();
+        assert_eq!(stored.attestation.trusted_block, 10000);
+        assert_eq!(
+            stored.attestation.trusted_hash,
+            B256::from(trusted_hash.to_bytes())
+        );
+        assert_eq!(stored.attestation.target_block, 10004);
+        assert_eq!(
+            stored.attestation.correlation_id,
+            Some(submitted[0].correlation_id)
+        );
+
+        let summary = audit::verify_signatures(&[audit_path], &public_key).unwrap();
+        assert_eq!((summary.signed, summary.unsigned), (1, 0));
+        // The public key is logged at startup, never the key itself.
+        let lines = captured.lines();
+        assert!(lines.iter().any(|line| line["fields"]["message"]
+            .as_str()
+            .is_some_and(|m| m.contains(&attestation::public_key_hex(&public_key)))));
+        assert!(lines.iter().all(|line| !line.to_string().contains(&seed)));
+    }
+
+    #[tokio::test]
+    async fn test_chain_spec_bounds_skips() {
+        // A block every 6 seconds.
+        let server = MockTendermintServer::start(SyntheticChain::new(7))
+            .await
+            .unwrap();
+        let spec = ChainSpec::preset("celestia").unwrap();
+        let mut config = TendermintXConfig::new(vec![RequestTarget::for_test(5)]);
+        config.chain_spec = Some(ChainSpec {
+            trusting_period: Duration::from_secs(60),
+            block_time: Duration::from_secs(1),
+            ..spec
+        });
+        let provider = Arc::new(Provider::try_from("http://localhost:8545").unwrap());
+        let backend = Box::new(MockBackend::new());
+        let mut operator =
+            TendermintXOperator::new(config, server.fetcher(), backend, vec![provider]).unwrap();
+        operator
+            .set_trusted_state(0, Arc::new(LocalTrustedState::new(1000)))
+            .unwrap();
+
+        // The contract's skip_max is bounded by the trusting period.
+        operator.read_skip_maxes().await.unwrap();
+        assert_eq!(operator.skip_maxes, vec![60]);
+
+        // 120 seconds of chain time.
+        let error = operator
+            .prove(Height(100)..=Height(120), HeaderHash([0xab; 32]))
+            .await
+            .unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("more than the trusting period of celestia"));
+    }
+
+    #[tokio::test]
+    async fn test_chain_spec_selects_rpc_adapter() {
+        let mut config = TendermintXConfig::new(vec![RequestTarget::for_test(5)]);
+        config.chain_spec = Some(ChainSpec {
+            rpc_adapter: RpcAdapterKind::Namada,
+            ..ChainSpec::preset("celestia").unwrap()
+        });
+        let fetcher = InputDataFetcher::new(
+            vec!["http://localhost:26657".to_string()],
+            "./circuits/fixtures/namada",
+        );
+        let provider = Arc::new(Provider::try_from("http://localhost:8545").unwrap());
+        let backend = Box::new(MockBackend::new());
+        let operator = TendermintXOperator::new(config, fetcher, backend, vec![provider]).unwrap();
+
+        // The variant's responses yield the header of the CometBFT ones.
+        assert_eq!(
+            operator
+                .data_fetcher
+                .compute_header_hash(Height(10000))
+                .await
+                .unwrap(),
+            "A0123D5E4B8B8888A61F931EE2252D83568B97C223E0ECA9795B29B8BD8CBA2D"
+                .parse::<HeaderHash>()
+                .unwrap()
+        );
+        let validators = operator
+            .data_fetcher
+            .get_validator_set_from_number(Height(10001))
+            .await
+            .unwrap();
+        assert_eq!(validators.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_verify_artifacts() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = dir.path().join("manifest.json");
+        let functions = format!(
+            r#"{{"functions": {{"{}": {{"digest": "{}"}}}}}}"#,
+            B256::repeat_byte(0x22),
+            B256::repeat_byte(0x44)
+        );
+        std::fs::write(&manifest, functions).unwrap();
+        let config = |digest: u8, manifest: Option<PathBuf>, allow_mismatch: bool| {
+            let mut config = TendermintXConfig::new(vec![RequestTarget::for_test(5)]);
+            config
+                .circuit_digests
+                .insert(B256::repeat_byte(0x22), B256::repeat_byte(digest));
+            config.artifact_manifest = manifest;
+            config.allow_circuit_mismatch = allow_mismatch;
+            config
+        };
+
+        let mut matching = operator(config(0x44, Some(manifest.clone()), false), 1).unwrap();
+        let verification = matching.verify_artifacts().await.unwrap();
+        assert!(verification.mismatches.is_empty());
+        assert_eq!(
+            matching.verified_digests,
+            BTreeMap::from([(B256::repeat_byte(0x22), B256::repeat_byte(0x44))])
+        );
+
+        // A digest the manifest doesn't match refuses to run, unless allowed.
+        let mut mismatched = operator(config(0x55, Some(manifest.clone()), false), 1).unwrap();
+        let error = mismatched.verify_artifacts().await.unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("don't match the expected digests"));
+        let mut allowed = operator(config(0x55, Some(manifest), true), 1).unwrap();
+        let verification = allowed.verify_artifacts().await.unwrap();
+        assert_eq!(verification.mismatches.len(), 1);
+        assert!(allowed.verified_digests.is_empty());
+
+        // Without a manifest the backend is asked, and the mock has no digests.
+        let mut unregistered = operator(config(0x44, None, false), 1).unwrap();
+        assert!(unregistered.verify_artifacts().await.is_err());
+    }
+
+    /// An operator for the test target of chain 5 on the chain of `server`, proving with the mock
+    /// backend behind the faults of `schedule`, from a local trusted state at block 100 with a
+    /// skip_max of 100.
+    fn faulty_operator(
+        server: &MockTendermintServer,
+        config: TendermintXConfig,
+        schedule: &str,
+    ) -> (
+        TendermintXOperator<Provider<MockProvider>>,
+        Arc<FaultInjecting<MockBackend>>,
+        Arc<LocalTrustedState>,
+    ) {
+        let backend = Arc::new(FaultInjecting::new(
+            MockBackend::new(),
+            schedule.parse().unwrap(),
+        ));
+        let (provider, _) = Provider::mocked();
+        let mut operator = TendermintXOperator::new(
+            config,
+            server.fetcher(),
+            Box::new(backend.clone()),
+            vec![Arc::new(provider)],
+        )
+        .unwrap();
+        let trusted = Arc::new(LocalTrustedState::new(100));
+        let hash = header_hash(&server.chain().header(100));
+        trusted.insert(Height(100), hash).unwrap();
+        operator.set_trusted_state(0, trusted.clone()).unwrap();
+        (operator, backend, trusted)
+    }
+
+    /// Store the target blocks of the requests of `outcome`, as if they had landed.
+    fn land(
+        server: &MockTendermintServer,
+        trusted: &LocalTrustedState,
+        outcome: &IterationOutcome,
+    ) {
+        for chunk in &outcome.chunks {
+            let hash = header_hash(&server.chain().header(chunk.target_block));
+            trusted.insert(Height(chunk.target_block), hash).unwrap();
+        }
+    }
+
+    #[tokio::test]
+    async fn test_loop_survives_backend_faults() {
+        let server = MockTendermintServer::start(SyntheticChain::new(7))
+            .await
+            .unwrap();
+        let store = tempfile::tempdir().unwrap();
+        let mut config = TendermintXConfig::new(vec![RequestTarget::for_test(5)]);
+        config.store_path = Some(store.path().join("requests.db"));
+        let schedule = "
+            request_skip 1 transport
+            request_skip 2 latency=20ms
+            request_skip 3 panic
+            request_skip 5..=6 transport
+            recent_requests %2 malformed
+        ";
+        let (mut operator, backend, trusted) = faulty_operator(&server, config, schedule);
+
+        // Every iteration completes, whether its submission failed or not, and each failed one is
+        // retried by the next until it is accepted.
+        let mut submitted = Vec::new();
+        for _ in 0..10 {
+            let outcome = operator.run_once().await.unwrap();
+            submitted.push(outcome.any_submitted);
+            land(&server, &trusted, &outcome);
+        }
+        assert_eq!(
+            submitted,
+            [false, true, false, true, false, false, true, true, true, true]
+        );
+        let faults = backend.injected();
+        assert_eq!(faults.len(), 5 + 5);
+        assert_eq!(faults[3].fault, Fault::Panic);
+        let ranges: Vec<_> = backend
+            .inner()
+            .requests()
+            .iter()
+            .map(|r| (r.trusted_block, r.target_block))
+            .collect();
+        assert_eq!(
+            ranges,
+            [
+                (100, 200),
+                (200, 300),
+                (300, 400),
+                (400, 500),
+                (500, 600),
+                (600, 700)
+            ]
+        );
+        // The failed submissions didn't reach the backend.
+        assert_eq!(backend.inner().submission_attempts(), 6);
+
+        // While a request is pending, the next iterations don't resubmit it: the request store
+        // has it, whatever the backend's list of recent requests.
+        let outcome = operator.run_once().await.unwrap();
+        assert_eq!(outcome.chunks[0].target_block, 800);
+        for _ in 0..3 {
+            operator.run_once().await.unwrap();
+        }
+        assert_eq!(backend.inner().requests().len(), 7);
+        assert_eq!(backend.calls("request_skip"), 11);
+
+        // Failed reads of the chain fail the iteration, or the selection of its group, and the
+        // next iteration retries them.
+        let hash = header_hash(&server.chain().header(800));
+        trusted.insert(Height(800), hash).unwrap();
+        let schedule = "
+            latest_header 1 transport
+            header_hash_and_time 1 transport
+            is_valid_skip 1 malformed
+        ";
+        let headers = Arc::new(FaultInjecting::new(
+            server.fetcher(),
+            schedule.parse().unwrap(),
+        ));
+        operator.set_header_fetcher(headers.clone());
+        let error = operator.run_once().await.unwrap_err();
+        assert!(format!("{:#}", error).contains("failed to fetch the chain head"));
+        let error = operator.run_once().await.unwrap_err();
+        assert!(format!("{:#}", error).contains("call 1 to header_hash_and_time"));
+        let outcome = operator.run_once().await.unwrap();
+        assert!(!outcome.any_submitted);
+        assert_eq!(
+            outcome.summary.groups[0].error.as_deref(),
+            Some("injected malformed response to call 1 to is_valid_skip")
+        );
+        assert_eq!(operator.consecutive_failures, 3);
+        let outcome = operator.run_once().await.unwrap();
+        assert_eq!(outcome.chunks[0].target_block, 900);
+        assert_eq!(operator.consecutive_failures, 0);
+        assert_eq!(headers.injected().len(), 3);
+        assert_eq!(backend.inner().requests().len(), 8);
+    }
+
+    #[tokio::test]
+    async fn test_circuit_breaker_under_faults() {
+        let server = MockTendermintServer::start(SyntheticChain::new(7))
+            .await
+            .unwrap();
+        let mut config = TendermintXConfig::new(vec![RequestTarget::for_test(5)]);
+        config.failure_alert_threshold = 3;
+        let (mut operator, backend, _) =
+            faulty_operator(&server, config, "request_skip 1..=2 transport");
+        let schedule = "
+            latest_header 2 transport
+            is_valid_skip 3 transport
+        ";
+        let headers = FaultInjecting::new(server.fetcher(), schedule.parse().unwrap());
+        operator.set_header_fetcher(Arc::new(headers));
+
+        // The alert fires on the third consecutive failure, whether the submission failed, the
+        // chain head couldn't be read (the second iteration), or the selection failed (the fourth).
+        for failures in 1..=4 {
+            match operator.run_once().await {
+                Ok(outcome) => assert!(!outcome.any_submitted),
+                Err(e) => {
+                    assert_eq!(failures, 2);
+                    assert!(format!("{:#}", e).contains("call 2 to latest_header"));
+                }
+            }
+            assert_eq!(operator.consecutive_failures, failures);
+            assert_eq!(
+                operator.alerter.is_sent(AlertKind::CircuitBreakerOpen),
+                failures >= 3
+            );
+        }
+
+        // And resolves once a submission is accepted.
+        assert!(operator.run_once().await.unwrap().any_submitted);
+        assert_eq!(operator.consecutive_failures, 0);
+        assert!(!operator.alerter.is_sent(AlertKind::CircuitBreakerOpen));
+        assert_eq!(backend.inner().requests().len(), 1);
+        assert_eq!(backend.calls("request_skip"), 3);
+    }
+
+    #[tokio::test]
+    async fn test_run_until_finishes_the_submission() {
+        let server = MockTendermintServer::start(SyntheticChain::new(7))
+            .await
+            .unwrap();
+        let store = tempfile::tempdir().unwrap();
+        let mut config = TendermintXConfig::new(vec![RequestTarget::for_test(5)]);
+        config.store_path = Some(store.path().join("requests.db"));
+        let (mut operator, backend, _) =
+            faulty_operator(&server, config, "request_skip 1 latency=200ms");
+
+        // The shutdown comes while the first request is being submitted.
+        let submitting = backend.clone();
+        let shutdown = async move {
+            while submitting.calls("request_skip") == 0 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        };
+        operator.run_until(shutdown).await.unwrap();
+
+        // The submission completed and was recorded, and is left for the drain.
+        let requests = backend.inner().requests();
+        assert_eq!(requests.len(), 1);
+        let store = operator.store.as_ref().unwrap();
+        assert!(store.get(&requests[0].request_id).unwrap().is_some());
+        assert_eq!(
+            *operator.in_flight.lock().unwrap(),
+            [InFlight {
+                request_id: requests[0].request_id.clone(),
+                mode: RequestMode::Platform,
+            }]
+        );
+    }
+
+    /// A trusted state counting the reads of each getter, and answering the latest block after
+    /// `latency`.
+    struct CountingTrustedState {
+        inner: Arc<LocalTrustedState>,
+        reads: Mutex<BTreeMap<&'static str, usize>>,
+        latency: Duration,
+        /// When each read of the latest block started and ended.
+        latest_block_reads: Mutex<Vec<(Instant, Instant)>>,
+    }
+
+    impl CountingTrustedState {
+        fn new(inner: Arc<LocalTrustedState>) -> Self {
+            Self {
+                inner,
+                reads: Mutex::new(BTreeMap::new()),
+                latency: Duration::ZERO,
+                latest_block_reads: Mutex::new(Vec::new()),
+            }
+        }
+
+        fn read(&self, getter: &'static str) {
+            *self.reads.lock().unwrap().entry(getter).or_default() += 1;
+        }
+
+        /// The reads since the last call.
+        fn take(&self) -> Vec<(&'static str, usize)> {
+            std::mem::take(&mut *self.reads.lock().unwrap())
+                .into_iter()
+                .collect()
+        }
+    }
+
+    #[async_trait]
+    impl TrustedStateProvider for CountingTrustedState {
+        async fn latest(&self) -> Result<(Height, HeaderHash)> {
+            self.read("latest");
+            self.inner.latest().await
+        }
+
+        async fn hash_at(&self, height: Height) -> Result<Option<HeaderHash>> {
+            self.read("hash_at");
+            self.inner.hash_at(height).await
+        }
+
+        async fn skip_max(&self) -> Result<u64> {
+            self.read("skip_max");
+            self.inner.skip_max().await
+        }
+
+        async fn latest_block(&self) -> Result<Height> {
+            self.read("latest_block");
+            let start = Instant::now();
+            tokio::time::sleep(self.latency).await;
+            let latest_block = self.inner.latest_block().await;
+            let read = (start, Instant::now());
+            self.latest_block_reads.lock().unwrap().push(read);
+            latest_block
+        }
+    }
+
+    /// The headers of `inner`, answering the chain head after `latency` and recording when each
+    /// read of it started and ended.
+    struct TimedHeaders<T> {
+        inner: T,
+        latency: Duration,
+        head_reads: Mutex<Vec<(Instant, Instant)>>,
+    }
+
+    #[async_trait]
+    impl<T: HeaderFetcher> HeaderFetcher for TimedHeaders<T> {
+        async fn chain_head(&self) -> Result<u64> {
+            Ok(self.latest_header().await?.0)
+        }
+
+        async fn latest_header(&self) -> Result<(u64, i64)> {
+            let start = Instant::now();
+            tokio::time::sleep(self.latency).await;
+            let header = self.inner.latest_header().await;
+            self.head_reads
+                .lock()
+                .unwrap()
+                .push((start, Instant::now()));
+            header
+        }
+
+        async fn header_time(&self, block: u64) -> Result<i64> {
+            self.inner.header_time(block).await
+        }
+
+        async fn header_hash_and_time(&self, block: u64) -> Result<(HeaderHash, i64)> {
+            self.inner.header_hash_and_time(block).await
+        }
+
+        async fn is_valid_skip(&self, trusted_block: u64, target_block: u64) -> Result<bool> {
+            self.inner.is_valid_skip(trusted_block, target_block).await
+        }
+
+        async fn validator_powers(&self, block: u64) -> Result<selector::ValidatorPowers> {
+            self.inner.validator_powers(block).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_contract_read_once_per_iteration() {
+        let server = MockTendermintServer::start(SyntheticChain::new(7))
+            .await
+            .unwrap();
+        let config = TendermintXConfig::new(vec![RequestTarget::for_test(5)]);
+        let (mut operator, backend, trusted) = faulty_operator(&server, config, "");
+        let counting = Arc::new(CountingTrustedState::new(trusted.clone()));
+        operator.set_trusted_state(0, counting.clone()).unwrap();
+
+        // The skip_max is read before the first iteration, then each iteration reads the latest
+        // block and its header hash once, for the consistency check and the request alike.
+        let mut outcome = operator.run_once().await.unwrap();
+        assert_eq!(outcome.chunks[0].target_block, 200);
+        assert_eq!(
+            counting.take(),
+            [("hash_at", 1), ("latest_block", 1), ("skip_max", 1)]
+        );
+        for target_block in [300, 400] {
+            land(&server, &trusted, &outcome);
+            outcome = operator.run_once().await.unwrap();
+            assert_eq!(outcome.chunks[0].target_block, target_block);
+            assert_eq!(counting.take(), [("hash_at", 1), ("latest_block", 1)]);
+        }
+        assert_eq!(backend.inner().requests().len(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_contract_read_once_for_mirrors() {
+        let server = MockTendermintServer::start(SyntheticChain::new(7))
+            .await
+            .unwrap();
+        let mirrored = RequestTarget {
+            mirror_chain_ids: vec![10, 42161],
+            ..RequestTarget::for_test(5)
+        };
+        let config = TendermintXConfig::new(vec![mirrored]);
+        let (mut operator, backend, trusted) = faulty_operator(&server, config, "");
+        let counting = Arc::new(CountingTrustedState::new(trusted.clone()));
+        operator.set_trusted_state(0, counting.clone()).unwrap();
+        let headers = Arc::new(FaultInjecting::new(server.fetcher(), "".parse().unwrap()));
+        operator.set_header_fetcher(headers.clone());
+
+        // The mirrors read the contract of chain 5: it is read, and its header checked, once for
+        // the three targets.
+        let outcome = operator.run_once().await.unwrap();
+        assert_eq!(outcome.chunks[0].targets.len(), 3);
+        assert_eq!(
+            counting.take(),
+            [("hash_at", 1), ("latest_block", 1), ("skip_max", 1)]
+        );
+        assert_eq!(headers.calls("header_hash_and_time"), 1);
+        land(&server, &trusted, &outcome);
+        let outcome = operator.run_once().await.unwrap();
+        assert_eq!(outcome.chunks[0].targets.len(), 3);
+        assert_eq!(counting.take(), [("hash_at", 1), ("latest_block", 1)]);
+        assert_eq!(headers.calls("header_hash_and_time"), 2);
+        assert_eq!(backend.inner().requests().len(), 6);
+
+        // Taking over as the leader, the skip_max is read again once too.
+        operator.refresh_skip_maxes().await;
+        assert_eq!(counting.take(), [("skip_max", 1)]);
+        assert!(operator
+            .skip_maxes
+            .iter()
+            .all(|max| *max == operator.skip_maxes[0]));
+    }
+
+    #[tokio::test]
+    async fn test_chain_head_and_contracts_read_concurrently() {
+        let latency = Duration::from_millis(400);
+        let server = MockTendermintServer::start(SyntheticChain::new(7))
+            .await
+            .unwrap();
+        let config = TendermintXConfig::new(vec![RequestTarget::for_test(5)]);
+        let (mut operator, backend, trusted) = faulty_operator(&server, config, "");
+        let mut slow = CountingTrustedState::new(trusted.clone());
+        slow.latency = latency;
+        let slow = Arc::new(slow);
+        operator.set_trusted_state(0, slow.clone()).unwrap();
+        let headers = Arc::new(TimedHeaders {
+            inner: server.fetcher(),
+            latency,
+            head_reads: Mutex::new(Vec::new()),
+        });
+        operator.set_header_fetcher(headers.clone());
+
+        // Each side takes the latency, and each starts before the other ends.
+        let outcome = operator.run_once().await.unwrap();
+        assert_eq!(outcome.chunks[0].target_block, 200);
+        let phases = &outcome.summary.phases_ms.0;
+        assert!(phases["chain_head"] >= latency.as_millis() as u64);
+        assert!(phases["contracts"] >= latency.as_millis() as u64);
+        let [(head_start, head_end)] = headers.head_reads.lock().unwrap()[..] else {
+            panic!("the chain head wasn't read once");
+        };
+        let [(contract_start, contract_end)] = slow.latest_block_reads.lock().unwrap()[..] else {
+            panic!("the contract wasn't read once");
+        };
+        assert!(head_start < contract_end && contract_start < head_end);
+        assert_eq!(backend.inner().requests().len(), 1);
+
+        // An error on either side fails the iteration, saying which.
+        operator
+            .set_trusted_state(0, Arc::new(LocalTrustedState::new(100)))
+            .unwrap();
+        let error = operator.run_once().await.unwrap_err();
+        assert!(
+            format!("{:#}", error).starts_with("failed to read the contracts of the targets: "),
+            "{:#}",
+            error
+        );
+        operator.set_trusted_state(0, trusted).unwrap();
+        let failing = FaultInjecting::new(
+            server.fetcher(),
+            "latest_header 1 transport".parse().unwrap(),
+        );
+        operator.set_header_fetcher(Arc::new(failing));
+        let error = operator.run_once().await.unwrap_err();
+        assert!(
+            format!("{:#}", error).starts_with("failed to fetch the chain head: "),
+            "{:#}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn test_halt_heights_bound_requests() {
+        let server = MockTendermintServer::start(SyntheticChain::new(7))
+            .await
+            .unwrap();
+        let mut config = TendermintXConfig::new(vec![RequestTarget::for_test(5)]);
+        config.halt_heights = vec![150];
+        config.upgrade_discovery = true;
+        let (mut operator, backend, trusted) = faulty_operator(&server, config, "");
+        server.plan_upgrade("v2", 300);
+
+        // The skip_max allows 200, but the request lands on the halt height first.
+        let outcome = operator.run_once().await.unwrap();
+        assert_eq!(outcome.chunks[0].target_block, 150);
+        let rationale = outcome.summary.groups[0].rationale.as_ref().unwrap();
+        assert!(rationale.ends_with("bounded by the halt height 150"));
+        // Nor can a manual request cross it.
+        let error = operator
+            .prove(Height(100)..=Height(200), HeaderHash([0xab; 32]))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "the range from 100 to 200 crosses the halt height 150: prove up to it first"
+        );
+
+        // Past it, the furthest block again, until the discovered upgrade height.
+        land(&server, &trusted, &outcome);
+        let outcome = operator.run_once().await.unwrap();
+        assert_eq!(outcome.chunks[0].target_block, 250);
+        land(&server, &trusted, &outcome);
+        let outcome = operator.run_once().await.unwrap();
+        assert_eq!(outcome.chunks[0].target_block, 300);
+        land(&server, &trusted, &outcome);
+        let outcome = operator.run_once().await.unwrap();
+        assert_eq!(outcome.chunks[0].target_block, 400);
+        assert_eq!(backend.inner().requests().len(), 4);
+    }
+
+    #[tokio::test]
+    async fn test_concurrent_proofs_of_a_range() {
+        let server = MockTendermintServer::start(SyntheticChain::new(7))
+            .await
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let mut config = TendermintXConfig::new(vec![RequestTarget::for_test(5)]);
+        config.store_path = Some(dir.path().join("requests.db"));
+        let (operator, backend, _) =
+            faulty_operator(&server, config, "request_skip 1 latency=100ms");
+        let hash = header_hash(&server.chain().header(100));
+
+        // The second request checks the store while the first is being submitted, before it is
+        // recorded: the reserved range is skipped all the same.
+        let range = Height(100)..=Height(180);
+        let (first, second) = tokio::join!(
+            operator.prove(range.clone(), hash),
+            operator.prove(range, hash)
+        );
+        let submitted = [first.unwrap(), second.unwrap()].concat();
+        assert_eq!(submitted.len(), 1);
+        assert_eq!(backend.inner().requests().len(), 1);
+
+        // Nor can a request go past the chain head.
+        let error = operator
+            .prove(Height(100)..=Height(1001), hash)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.downcast_ref::<BeyondChainHead>(),
+            Some(&BeyondChainHead {
+                target_block: 1001,
+                chain_head: 1000,
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn test_trusted_state_expired() {
+        // A block every 6 seconds, with the head at block 1000.
+        let server = MockTendermintServer::start(SyntheticChain::new(7))
+            .await
+            .unwrap();
+        let mut config = TendermintXConfig::new(vec![RequestTarget::for_test(5)]);
+        config.chain_spec = Some(ChainSpec {
+            trusting_period: Duration::from_secs(500),
+            block_time: Duration::from_secs(1),
+            ..ChainSpec::preset("celestia").unwrap()
+        });
+        let (mut operator, backend, trusted) = faulty_operator(&server, config, "");
+
+        // A skip of skip_max (100 blocks) from block 100 spans 600 seconds: the group fails
+        // without a request, and the alert fires.
+        let status = operator.collect_status().await.unwrap();
+        assert_eq!(status.targets[0].trusting_margin, Some(500 - 5400));
+        let outcome = operator.run_once().await.unwrap();
+        assert!(!outcome.any_submitted);
+        let error = outcome.summary.groups[0].error.as_ref().unwrap();
+        assert!(error.starts_with("trusted state expired: the header of block 100 is"));
+        assert!(operator.alerter.is_sent(AlertKind::TrustedStateExpired));
+        assert!(backend.inner().requests().is_empty());
+
+        // Once the contract is reset to a recent block, skips resume and the alert resolves.
+        let hash = header_hash(&server.chain().header(990));
+        trusted.insert(Height(990), hash).unwrap();
+        let status = operator.collect_status().await.unwrap();
+        assert_eq!(status.targets[0].trusting_margin, Some(500 - 60));
+        assert!(operator.run_once().await.unwrap().any_submitted);
+        assert!(!operator.alerter.is_sent(AlertKind::TrustedStateExpired));
+        assert_eq!(backend.inner().requests().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_repair_plan() {
+        let server = MockTendermintServer::start(SyntheticChain::new(7))
+            .await
+            .unwrap();
+        let config = TendermintXConfig::new(vec![RequestTarget::for_test(5)]);
+        let (operator, _, trusted) = faulty_operator(&server, config, "");
+        // Block 100 is the chain's; 150 and 180 those of a fork.
+        for block in [150, 180] {
+            trusted
+                .insert(Height(block), HeaderHash([0xf0; 32]))
+                .unwrap();
+        }
+
+        let plan = operator
+            .repair_plan(Some(5), None, ScanBounds::default())
+            .await
+            .unwrap();
+        assert_eq!(plan.target, RequestTarget::for_test(5).to_string());
+        assert_eq!(plan.divergence.diverged_at(), Some(Height(150)));
+        assert_eq!(plan.state.block, Height(100));
+        assert!(plan.needs_rewind());
+        // Skips of at most the skip_max of the trusted state, 100 blocks, to the chain head.
+        assert_eq!(plan.head, Height(1000));
+        assert_eq!(plan.skips.len(), 9);
+        assert_eq!(plan.skips.first(), Some(&(100, 200)));
+        assert_eq!(plan.skips.last(), Some(&(900, 1000)));
+
+        assert!(operator
+            .repair_plan(Some(10), None, ScanBounds::default())
+            .await
+            .is_err());
+        assert_eq!(
+            operator.target_address(None).unwrap(),
+            Address::repeat_byte(0x11)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_run_oneshot() {
+        let server = MockTendermintServer::start(SyntheticChain::new(7))
+            .await
+            .unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let audit_path = dir.path().join("audit.jsonl");
+        let mut config = TendermintXConfig::new(vec![RequestTarget::for_test(5)]);
+        config.store_path = Some(dir.path().join("requests.db"));
+        config.audit = Some(AuditConfig {
+            path: audit_path.clone(),
+            max_bytes: audit::DEFAULT_MAX_BYTES,
+            max_files: audit::DEFAULT_MAX_FILES,
+        });
+        let (mut operator, _, _) = faulty_operator(&server, config, "request_skip 1 transport");
+
+        // The submission fails.
+        let summary = operator.run_oneshot(Duration::from_secs(5)).await;
+        assert_eq!(summary.outcome, RunOutcome::Failed);
+        assert_eq!(summary.exit_code, 1);
+        assert!(summary.targets[0].error.is_some());
+        let error = summary.targets[0].error.as_ref().unwrap();
+        assert_eq!(
+            summary.error,
+            Some(format!("{}: {}", RequestTarget::for_test(5), error))
+        );
+
+        // The next run's is accepted.
+        let summary = operator.run_oneshot(Duration::from_secs(5)).await;
+        assert_eq!(summary.outcome, RunOutcome::Acted);
+        assert_eq!(summary.exit_code, 0);
+        let run = &summary.targets[0];
+        assert_eq!((run.action, run.target_block), (Action::Skip, Some(200)));
+        assert!(run.request_id.is_some() && run.error.is_none());
+        assert_eq!(run.lag_before - run.lag_after, 100);
+        assert_eq!(
+            summary.chain_head,
+            summary.iteration.as_ref().unwrap().chain_head
+        );
+
+        // Nothing is submitted while paused.
+        operator.control.apply(ControlCommand::Pause).unwrap();
+        let summary = operator.run_oneshot(Duration::from_secs(5)).await;
+        assert_eq!(summary.outcome, RunOutcome::NothingToDo);
+        assert_eq!(summary.exit_code, 3);
+        assert_eq!(summary.targets[0].action, Action::Paused);
+
+        // Each run is in the audit log by the time it returns.
+        let audit = std::fs::read_to_string(&audit_path).unwrap();
+        let iterations = audit
+            .lines()
+            .filter(|line| line.contains("\"event\":\"iteration\""))
+            .count();
+        assert_eq!(iterations, 3);
+    }
+}
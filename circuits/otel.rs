@@ -0,0 +1,56 @@
+//! Trace export over OTLP, for following a request from the contract reads through the RPC
+//! fetches and input encoding to its submission.
+//!
+//! Only built with the `otel` feature. The exporter is installed when
+//! `OTEL_EXPORTER_OTLP_ENDPOINT` is set, and the layer is a no-op otherwise.
+
+use std::env;
+
+use anyhow::{Context, Result};
+use opentelemetry::KeyValue;
+use opentelemetry_otlp::WithExportConfig;
+use opentelemetry_sdk::trace::{self, Tracer};
+use opentelemetry_sdk::{runtime, Resource};
+use tracing::Subscriber;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::registry::LookupSpan;
+
+/// The `service.name` of the exported spans.
+const SERVICE_NAME: &str = "tendermintx";
+
+/// The OTLP endpoint configured by `OTEL_EXPORTER_OTLP_ENDPOINT`, if any.
+pub fn endpoint() -> Option<String> {
+    env::var("OTEL_EXPORTER_OTLP_ENDPOINT")
+        .ok()
+        .filter(|endpoint| !endpoint.is_empty())
+}
+
+/// The layer exporting spans to the configured endpoint, or `None` if no endpoint is set. Must be
+/// called from within the Tokio runtime, which runs the batch exporter.
+pub fn layer<S>() -> Result<Option<OpenTelemetryLayer<S, Tracer>>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let Some(endpoint) = endpoint() else {
+        return Ok(None);
+    };
+    let exporter = opentelemetry_otlp::new_exporter()
+        .tonic()
+        .with_endpoint(&endpoint);
+    let config = trace::config().with_resource(Resource::new(vec![KeyValue::new(
+        "service.name",
+        SERVICE_NAME,
+    )]));
+    let tracer = opentelemetry_otlp::new_pipeline()
+        .tracing()
+        .with_exporter(exporter)
+        .with_trace_config(config)
+        .install_batch(runtime::Tokio)
+        .with_context(|| format!("could not install the OTLP exporter for {}", endpoint))?;
+    Ok(Some(tracing_opentelemetry::layer().with_tracer(tracer)))
+}
+
+/// Flush the spans not exported yet.
+pub fn shutdown() {
+    opentelemetry::global::shutdown_tracer_provider();
+}
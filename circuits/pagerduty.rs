@@ -0,0 +1,293 @@
+//! Paging on-call through the PagerDuty Events API v2.
+//!
+//! Each alert class has a stable dedup key, so repeated alerts of a class update one incident
+//! rather than opening new ones, and the incident is resolved once the condition clears. Only the
+//! classes in the severity map page; the rest are left to the webhooks.
+
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use log::info;
+use serde_json::{json, Value};
+
+use crate::alert::{Alert, AlertKind};
+
+/// The Events API v2 endpoint.
+pub const EVENTS_URL: &str = "https://events.pagerduty.com/v2/enqueue";
+
+/// The maximum time a single event may take to deliver.
+const EVENT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The `source` of every event.
+const SOURCE: &str = "tendermintx";
+
+/// The severity of a PagerDuty event.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PdSeverity {
+    Critical,
+    Error,
+    Warning,
+    Info,
+}
+
+impl FromStr for PdSeverity {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "critical" => Ok(PdSeverity::Critical),
+            "error" => Ok(PdSeverity::Error),
+            "warning" => Ok(PdSeverity::Warning),
+            "info" => Ok(PdSeverity::Info),
+            _ => Err(anyhow!("invalid PagerDuty severity {:?}", s)),
+        }
+    }
+}
+
+impl fmt::Display for PdSeverity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PdSeverity::Critical => f.write_str("critical"),
+            PdSeverity::Error => f.write_str("error"),
+            PdSeverity::Warning => f.write_str("warning"),
+            PdSeverity::Info => f.write_str("info"),
+        }
+    }
+}
+
+/// The PagerDuty severity of each alert class that pages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeverityMap(HashMap<AlertKind, PdSeverity>);
+
+impl Default for SeverityMap {
+    /// Consistency mismatches and failing submissions page as critical.
+    fn default() -> Self {
+        Self(HashMap::from([
+            (AlertKind::ConsistencyMismatch, PdSeverity::Critical),
+            (AlertKind::CircuitBreakerOpen, PdSeverity::Critical),
+        ]))
+    }
+}
+
+impl SeverityMap {
+    /// Parse a comma separated list of `class=severity` entries, e.g.
+    /// `consistency_mismatch=critical,balance_low=warning`.
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut map = HashMap::new();
+        for entry in s.split(',').map(str::trim).filter(|e| !e.is_empty()) {
+            let (kind, severity) = entry.split_once('=').ok_or_else(|| {
+                anyhow!(
+                    "invalid severity mapping {:?}, expected class=severity",
+                    entry
+                )
+            })?;
+            map.insert(kind.trim().parse()?, severity.trim().parse()?);
+        }
+        Ok(Self(map))
+    }
+
+    pub fn get(&self, kind: AlertKind) -> Option<PdSeverity> {
+        self.0.get(&kind).copied()
+    }
+}
+
+/// The dedup key of the incident for alerts of `kind`.
+pub fn dedup_key(kind: AlertKind) -> String {
+    format!("{}/{}", SOURCE, kind)
+}
+
+/// The event triggering the incident for `alert`.
+pub fn trigger_event(routing_key: &str, alert: &Alert, severity: PdSeverity) -> Value {
+    json!({
+        "routing_key": routing_key,
+        "event_action": "trigger",
+        "dedup_key": dedup_key(alert.kind),
+        "payload": {
+            "summary": alert.summary,
+            "source": SOURCE,
+            "severity": severity.to_string(),
+            "component": alert.kind.to_string(),
+            "custom_details": alert.details,
+        },
+    })
+}
+
+/// The event resolving the incident for alerts of `kind`.
+pub fn resolve_event(routing_key: &str, kind: AlertKind) -> Value {
+    json!({
+        "routing_key": routing_key,
+        "event_action": "resolve",
+        "dedup_key": dedup_key(kind),
+    })
+}
+
+pub struct PagerDuty {
+    routing_key: String,
+    url: String,
+    severities: SeverityMap,
+    client: reqwest::Client,
+    /// The classes that may have an open incident. Nothing is known of the incidents triggered
+    /// before a restart, so every paging class starts out as possibly open and is resolved once
+    /// its condition is first seen cleared.
+    open: Mutex<HashSet<AlertKind>>,
+}
+
+impl PagerDuty {
+    pub fn new(routing_key: impl Into<String>, severities: SeverityMap) -> Self {
+        let open = AlertKind::ALL
+            .into_iter()
+            .filter(|kind| severities.get(*kind).is_some())
+            .collect();
+        Self {
+            routing_key: routing_key.into(),
+            url: EVENTS_URL.to_string(),
+            severities,
+            client: reqwest::Client::new(),
+            open: Mutex::new(open),
+        }
+    }
+
+    /// Send the events to `url` instead of the public endpoint.
+    pub fn with_url(mut self, url: impl Into<String>) -> Self {
+        self.url = url.into();
+        self
+    }
+
+    /// Trigger (or update) the incident for `alert`, if its class pages.
+    pub async fn trigger(&self, alert: &Alert) -> Result<()> {
+        let Some(severity) = self.severities.get(alert.kind) else {
+            return Ok(());
+        };
+        self.post(&trigger_event(&self.routing_key, alert, severity))
+            .await?;
+        self.open.lock().unwrap().insert(alert.kind);
+        info!("Triggered PagerDuty incident {}", dedup_key(alert.kind));
+        Ok(())
+    }
+
+    /// Resolve the incident for alerts of `kind`, if one may be open.
+    pub async fn resolve(&self, kind: AlertKind) -> Result<()> {
+        if !self.open.lock().unwrap().contains(&kind) {
+            return Ok(());
+        }
+        self.post(&resolve_event(&self.routing_key, kind)).await?;
+        self.open.lock().unwrap().remove(&kind);
+        info!("Resolved PagerDuty incident {}", dedup_key(kind));
+        Ok(())
+    }
+
+    async fn post(&self, event: &Value) -> Result<()> {
+        self.client
+            .post(&self.url)
+            .timeout(EVENT_TIMEOUT)
+            .header("content-type", "application/json")
+            .body(event.to_string())
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::Infallible;
+    use std::sync::Arc;
+
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::{Body, Request, Response, Server, StatusCode};
+
+    use super::*;
+
+    /// A mocked Events API recording the events it receives.
+    fn mock_endpoint() -> (String, Arc<Mutex<Vec<Value>>>) {
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let recorded = events.clone();
+        let make_service = make_service_fn(move |_| {
+            let recorded = recorded.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let recorded = recorded.clone();
+                    async move {
+                        let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                        recorded
+                            .lock()
+                            .unwrap()
+                            .push(serde_json::from_slice(&body).unwrap());
+                        let mut response = Response::new(Body::from(r#"{"status":"success"}"#));
+                        *response.status_mut() = StatusCode::ACCEPTED;
+                        Ok::<_, Infallible>(response)
+                    }
+                }))
+            }
+        });
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        listener.set_nonblocking(true).unwrap();
+        tokio::spawn(Server::from_tcp(listener).unwrap().serve(make_service));
+        (format!("http://{}/v2/enqueue", addr), events)
+    }
+
+    #[test]
+    fn test_parse_severities() {
+        let map = SeverityMap::parse("consistency_mismatch=critical, balance_low=warning").unwrap();
+        assert_eq!(
+            map.get(AlertKind::ConsistencyMismatch),
+            Some(PdSeverity::Critical)
+        );
+        assert_eq!(map.get(AlertKind::BalanceLow), Some(PdSeverity::Warning));
+        assert_eq!(map.get(AlertKind::CircuitBreakerOpen), None);
+        assert!(SeverityMap::parse("consistency_mismatch=loud").is_err());
+        assert!(SeverityMap::parse("unknown=critical").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_trigger_resolve() {
+        let (url, events) = mock_endpoint();
+        let pagerduty = PagerDuty::new("routing-key", SeverityMap::default()).with_url(url);
+
+        // Consistency was restored before any incident of this process: the possibly open
+        // incident from before a restart is resolved, once.
+        pagerduty
+            .resolve(AlertKind::ConsistencyMismatch)
+            .await
+            .unwrap();
+        pagerduty
+            .resolve(AlertKind::ConsistencyMismatch)
+            .await
+            .unwrap();
+        assert_eq!(events.lock().unwrap().len(), 1);
+
+        let alert = Alert::new(AlertKind::ConsistencyMismatch, "header mismatch on 5:0x11")
+            .with_detail("block", 1000);
+        pagerduty.trigger(&alert).await.unwrap();
+        pagerduty.trigger(&alert).await.unwrap();
+        // Classes outside the severity map don't page.
+        pagerduty
+            .trigger(&Alert::new(AlertKind::BalanceLow, "relayer balance low"))
+            .await
+            .unwrap();
+        pagerduty
+            .resolve(AlertKind::ConsistencyMismatch)
+            .await
+            .unwrap();
+
+        let events = events.lock().unwrap();
+        let actions = events
+            .iter()
+            .map(|e| e["event_action"].as_str().unwrap())
+            .collect::<Vec<_>>();
+        assert_eq!(actions, ["resolve", "trigger", "trigger", "resolve"]);
+        for event in events.iter() {
+            assert_eq!(event["routing_key"], "routing-key");
+            assert_eq!(event["dedup_key"], "tendermintx/consistency_mismatch");
+        }
+        assert_eq!(events[1]["payload"]["severity"], "critical");
+        assert_eq!(events[1]["payload"]["summary"], "header mismatch on 5:0x11");
+        assert_eq!(events[1]["payload"]["custom_details"]["block"], "1000");
+    }
+}
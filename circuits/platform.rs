@@ -0,0 +1,808 @@
+//! A client for the Succinct platform that can submit requests and follow them to fulfillment.
+
+use std::fmt;
+use std::future::Future;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use alloy_primitives::{Address, Bytes, B256};
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use log::warn;
+use reqwest::StatusCode;
+use serde::Deserialize;
+use succinct_client::request::SuccinctClient;
+use tracing::instrument;
+
+use crate::backend::{ProofBackend, ProofPayload, ProofRequest, RecentRequest};
+use crate::target::RequestMode;
+
+/// The status of a platform request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FulfillmentStatus {
+    /// The request is queued or its proof is still being generated.
+    Proving,
+    /// The proof was generated and is waiting to be relayed on-chain.
+    Proved { proof_id: Option<String> },
+    /// The proof was relayed on-chain.
+    Relayed {
+        proof_id: Option<String>,
+        tx_hash: Option<String>,
+    },
+    /// Proof generation or relaying failed. The error is the platform's message, verbatim.
+    Failed { error: Option<String> },
+    /// The request was not fulfilled before the timeout.
+    TimedOut,
+}
+
+impl FulfillmentStatus {
+    /// Whether the request can no longer change status.
+    pub fn is_terminal(&self) -> bool {
+        matches!(
+            self,
+            FulfillmentStatus::Relayed { .. } | FulfillmentStatus::Failed { .. }
+        )
+    }
+
+    /// Whether there is nothing more to wait for in `mode`. Off-chain requests are never relayed
+    /// by the platform, so they are settled once proved.
+    pub fn is_settled(&self, mode: RequestMode) -> bool {
+        match mode {
+            RequestMode::Platform => self.is_terminal(),
+            RequestMode::Offchain => {
+                self.is_terminal() || matches!(self, FulfillmentStatus::Proved { .. })
+            }
+        }
+    }
+}
+
+/// The platform's response to a request status query.
+#[derive(Debug, Deserialize)]
+struct RequestResponse {
+    status: String,
+    #[serde(default)]
+    proof_id: Option<String>,
+    #[serde(default, alias = "transaction_hash")]
+    tx_hash: Option<String>,
+    #[serde(default, alias = "error_message")]
+    error: Option<String>,
+    #[serde(default, alias = "credits")]
+    cost: Option<f64>,
+}
+
+impl From<RequestResponse> for FulfillmentStatus {
+    fn from(response: RequestResponse) -> Self {
+        match response.status.to_ascii_uppercase().as_str() {
+            "PENDING" | "QUEUED" | "RUNNING" | "PROVING" => FulfillmentStatus::Proving,
+            "SUCCESS" | "PROVED" if response.tx_hash.is_none() => FulfillmentStatus::Proved {
+                proof_id: response.proof_id,
+            },
+            "SUCCESS" | "PROVED" | "RELAYED" | "FULFILLED" => FulfillmentStatus::Relayed {
+                proof_id: response.proof_id,
+                tx_hash: response.tx_hash,
+            },
+            "FAILURE" | "FAILED" | "ERROR" => FulfillmentStatus::Failed {
+                error: response.error,
+            },
+            status => {
+                warn!("Unknown platform request status {:?}", status);
+                FulfillmentStatus::Proving
+            }
+        }
+    }
+}
+
+/// Parse the platform's response to a request status query.
+pub fn parse_request_status(body: &str) -> Result<FulfillmentStatus> {
+    let response: RequestResponse =
+        serde_json::from_str(body).context("failed to parse request status")?;
+    Ok(response.into())
+}
+
+/// A fulfillment callback posted by the platform.
+#[derive(Debug, Deserialize)]
+struct Callback {
+    #[serde(alias = "request_id")]
+    id: String,
+    #[serde(flatten)]
+    response: RequestResponse,
+}
+
+/// Parse a fulfillment callback into the request ID and its new status.
+pub fn parse_callback(body: &str) -> Result<(String, FulfillmentStatus)> {
+    let callback: Callback = serde_json::from_str(body).context("failed to parse callback")?;
+    Ok((callback.id, callback.response.into()))
+}
+
+/// A request as listed by the platform.
+#[derive(Debug, Deserialize)]
+struct ListedRequest {
+    id: String,
+    chain_id: u32,
+    to: String,
+    function_id: String,
+    input: String,
+    #[serde(flatten)]
+    response: RequestResponse,
+}
+
+impl TryFrom<ListedRequest> for RecentRequest {
+    type Error = anyhow::Error;
+
+    fn try_from(request: ListedRequest) -> Result<Self> {
+        Ok(RecentRequest {
+            address: request
+                .to
+                .parse()
+                .with_context(|| format!("invalid address {:?}", request.to))?,
+            function_id: request
+                .function_id
+                .parse()
+                .with_context(|| format!("invalid function ID {:?}", request.function_id))?,
+            input: request
+                .input
+                .parse()
+                .with_context(|| format!("invalid input {:?}", request.input))?,
+            request_id: request.id,
+            chain_id: request.chain_id,
+            status: request.response.into(),
+        })
+    }
+}
+
+/// Parse the platform's list of recent requests.
+pub fn parse_recent_requests(body: &str) -> Result<Vec<RecentRequest>> {
+    let requests: Vec<ListedRequest> =
+        serde_json::from_str(body).context("failed to parse request list")?;
+    requests.into_iter().map(RecentRequest::try_from).collect()
+}
+
+/// The platform's metadata of a function.
+#[derive(Debug, Deserialize)]
+struct FunctionResponse {
+    #[serde(default, alias = "digest")]
+    artifact_digest: Option<String>,
+}
+
+/// Parse the digest of the circuit artifact from the platform's metadata of a function, if it
+/// registered one.
+pub fn parse_function_digest(body: &str) -> Result<Option<B256>> {
+    let function: FunctionResponse =
+        serde_json::from_str(body).context("failed to parse function metadata")?;
+    function
+        .artifact_digest
+        .map(|digest| {
+            digest
+                .parse()
+                .with_context(|| format!("invalid artifact digest {:?}", digest))
+        })
+        .transpose()
+}
+
+/// The platform's response to a proof query: the proof of a fulfilled request and its public
+/// values, as hex.
+#[derive(Debug, Deserialize)]
+struct ProofResponse {
+    proof: String,
+    input: String,
+    #[serde(alias = "public_values")]
+    output: String,
+}
+
+/// Parse the platform's response to a proof query.
+pub fn parse_proof(body: &str) -> Result<ProofPayload> {
+    let response: ProofResponse = serde_json::from_str(body).context("failed to parse proof")?;
+    let hex = |name: &str, value: &str| {
+        value
+            .parse::<Bytes>()
+            .with_context(|| format!("invalid {} {:?}", name, value))
+    };
+    Ok(ProofPayload {
+        proof: hex("proof", &response.proof)?,
+        input: hex("input", &response.input)?,
+        output: hex("output", &response.output)?,
+    })
+}
+
+/// A platform API key. Only its fingerprint is ever formatted, so it can't leak into logs.
+#[derive(Clone, PartialEq, Eq)]
+pub struct ApiKey(String);
+
+impl ApiKey {
+    pub fn new(key: impl Into<String>) -> Self {
+        Self(key.into())
+    }
+
+    /// A non-secret identifier of the key: its last four characters.
+    pub fn fingerprint(&self) -> String {
+        let chars = self.0.chars().collect::<Vec<_>>();
+        let last = chars[chars.len().saturating_sub(4)..]
+            .iter()
+            .collect::<String>();
+        format!("...{}", last)
+    }
+
+    pub(crate) fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for ApiKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "ApiKey({})", self.fingerprint())
+    }
+}
+
+impl fmt::Display for ApiKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.fingerprint())
+    }
+}
+
+/// The number of platform calls in this process that only succeeded with the fallback API key,
+/// each a sign that the primary key needs rotating.
+static FALLBACK_KEY_USES: AtomicU64 = AtomicU64::new(0);
+
+/// The number of platform calls in this process that only succeeded with the fallback API key.
+pub fn fallback_key_uses() -> u64 {
+    FALLBACK_KEY_USES.load(Ordering::Relaxed)
+}
+
+/// The HTTP status of a platform response, for the errors that don't carry one otherwise.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct HttpStatus(pub StatusCode);
+
+impl fmt::Display for HttpStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "HTTP {}", self.0)
+    }
+}
+
+impl std::error::Error for HttpStatus {}
+
+/// The HTTP status of the platform response that caused an error, if any.
+fn http_status(error: &anyhow::Error) -> Option<StatusCode> {
+    // A status added as context is only found by downcasting the error itself.
+    if let Some(status) = error.downcast_ref::<HttpStatus>() {
+        return Some(status.0);
+    }
+    error.chain().find_map(|cause| {
+        if let Some(e) = cause.downcast_ref::<reqwest::Error>() {
+            return e.status();
+        }
+        cause.downcast_ref::<HttpStatus>().map(|status| status.0)
+    })
+}
+
+fn is_auth_status(status: StatusCode) -> bool {
+    status == StatusCode::UNAUTHORIZED || status == StatusCode::FORBIDDEN
+}
+
+/// Whether an error was caused by the platform rejecting the API key: a 401 or 403 response.
+/// Only the status of the response counts, never the text of the error.
+fn is_auth_failure(error: &anyhow::Error) -> bool {
+    http_status(error).is_some_and(is_auth_status)
+}
+
+/// The primary API key and an optional fallback that is tried once when the primary is rejected.
+#[derive(Debug, Clone)]
+pub struct ApiKeys {
+    primary: ApiKey,
+    fallback: Option<ApiKey>,
+}
+
+impl ApiKeys {
+    pub fn new(primary: ApiKey, fallback: Option<ApiKey>) -> Self {
+        Self { primary, fallback }
+    }
+
+    /// Run `call` with the primary key, and once more with the fallback key if the primary is
+    /// rejected. Both keys are redacted from the returned error, which keeps its causes. A call
+    /// that only succeeds with the fallback is counted in `fallback_key_uses`.
+    pub async fn with_fallback<T, F, Fut>(&self, mut call: F) -> Result<T>
+    where
+        F: FnMut(ApiKey) -> Fut,
+        Fut: Future<Output = Result<T>>,
+    {
+        let result = match call(self.primary.clone()).await {
+            Err(e) if is_auth_failure(&e) => match self.fallback.as_ref() {
+                Some(fallback) => {
+                    let result = call(fallback.clone()).await;
+                    if result.is_ok() {
+                        FALLBACK_KEY_USES.fetch_add(1, Ordering::Relaxed);
+                        warn!(
+                            "Platform API key {} was rejected but the fallback key {} succeeded, \
+                             rotate SUCCINCT_API_KEY",
+                            self.primary, fallback
+                        );
+                    }
+                    result
+                }
+                None => Err(e),
+            },
+            result => result,
+        };
+        result.map_err(|e| self.redact_error(e))
+    }
+
+    /// `error`, or if any of its causes mentions a key, the same chain of causes with the keys
+    /// replaced by their fingerprint.
+    fn redact_error(&self, error: anyhow::Error) -> anyhow::Error {
+        let mut causes = error
+            .chain()
+            .map(|cause| cause.to_string())
+            .collect::<Vec<_>>();
+        if causes.iter().all(|cause| self.redact(cause) == *cause) {
+            return error;
+        }
+        // Rebuild the chain from its root.
+        let root = causes.pop().unwrap_or_default();
+        let mut redacted = anyhow!(self.redact(&root));
+        while let Some(cause) = causes.pop() {
+            redacted = redacted.context(self.redact(&cause));
+        }
+        redacted
+    }
+
+    /// Replace every occurrence of the keys in `message` with their fingerprint.
+    fn redact(&self, message: &str) -> String {
+        let mut message = message.to_string();
+        for key in std::iter::once(&self.primary).chain(self.fallback.as_ref()) {
+            if !key.expose().is_empty() {
+                message =
+                    message.replace(key.expose(), &format!("<redacted {}>", key.fingerprint()));
+            }
+        }
+        message
+    }
+}
+
+pub struct PlatformClient {
+    /// The backend name recorded with each request, "platform" by default.
+    name: String,
+    http: reqwest::Client,
+    rpc_url: String,
+    api_keys: ApiKeys,
+}
+
+impl PlatformClient {
+    pub fn new(rpc_url: String, api_key: String) -> Self {
+        Self {
+            name: "platform".to_string(),
+            http: reqwest::Client::new(),
+            rpc_url,
+            api_keys: ApiKeys::new(ApiKey::new(api_key), None),
+        }
+    }
+
+    /// Use a different backend name, e.g. to tell a secondary endpoint apart.
+    pub fn with_name(mut self, name: &str) -> Self {
+        self.name = name.to_string();
+        self
+    }
+
+    /// Retry requests rejected with the API key once with `api_key`, e.g. while the primary key
+    /// is rotated.
+    pub fn with_fallback_key(mut self, api_key: String) -> Self {
+        self.api_keys.fallback = Some(ApiKey::new(api_key));
+        self
+    }
+
+    fn client(&self, api_key: &ApiKey) -> SuccinctClient {
+        SuccinctClient::new(
+            self.rpc_url.clone(),
+            api_key.expose().to_string(),
+            false,
+            false,
+        )
+    }
+
+    /// Submit a request to the platform, which proves it and relays the result to `to`. Returns
+    /// the request ID.
+    pub async fn submit_platform_request(
+        &self,
+        chain_id: u32,
+        to: Address,
+        calldata: Bytes,
+        function_id: B256,
+        input: Bytes,
+    ) -> Result<String> {
+        self.api_keys
+            .with_fallback(|api_key| {
+                let (calldata, input) = (calldata.clone(), input.clone());
+                async move {
+                    let result = self
+                        .client(&api_key)
+                        .submit_platform_request(chain_id, to, calldata, function_id, input)
+                        .await;
+                    self.with_key_status(result, &api_key, function_id).await
+                }
+            })
+            .await
+    }
+
+    /// Submit a request to the platform that is proved but not relayed. The proof is fetched and
+    /// relayed by the caller. Returns the request ID.
+    pub async fn submit_offchain_request(
+        &self,
+        chain_id: u32,
+        to: Address,
+        calldata: Bytes,
+        function_id: B256,
+        input: Bytes,
+    ) -> Result<String> {
+        self.api_keys
+            .with_fallback(|api_key| {
+                let (calldata, input) = (calldata.clone(), input.clone());
+                async move {
+                    let result = self
+                        .client(&api_key)
+                        .submit_request(chain_id, to, calldata, function_id, input)
+                        .await;
+                    self.with_key_status(result, &api_key, function_id).await
+                }
+            })
+            .await
+    }
+
+    /// `result`, with the status of the platform's answer to `api_key` if it failed without one.
+    /// The Succinct client doesn't report the status of a rejected submission, so the platform
+    /// is asked whether it accepts the key, listing the requests of `function_id`: a submission
+    /// can't have been accepted with a key the platform rejects.
+    async fn with_key_status<T>(
+        &self,
+        result: Result<T>,
+        api_key: &ApiKey,
+        function_id: B256,
+    ) -> Result<T> {
+        let error = match result {
+            Ok(value) => return Ok(value),
+            Err(e) if http_status(&e).is_some() => return Err(e),
+            Err(e) => e,
+        };
+        let url = format!("{}/requests?function_id={}", self.rpc_url, function_id);
+        match self
+            .http
+            .get(&url)
+            .bearer_auth(api_key.expose())
+            .send()
+            .await
+        {
+            Ok(response) if is_auth_status(response.status()) => {
+                Err(error.context(HttpStatus(response.status())))
+            }
+            _ => Err(error),
+        }
+    }
+
+    /// Submit a request in the request mode of its target.
+    #[instrument(
+        name = "platform_submit",
+        skip_all,
+        fields(
+            chain_id = request.target.chain_id,
+            address = %request.target.address,
+            mode = ?request.target.request_mode,
+            input_bytes = request.input.len(),
+            calldata_bytes = request.calldata.len(),
+        )
+    )]
+    async fn submit(&self, request: &ProofRequest<'_>) -> Result<String> {
+        let target = request.target;
+        match target.request_mode {
+            RequestMode::Platform => {
+                self.submit_platform_request(
+                    target.chain_id,
+                    target.address,
+                    request.calldata.clone(),
+                    request.function_id,
+                    request.input.clone(),
+                )
+                .await
+            }
+            RequestMode::Offchain => {
+                self.submit_offchain_request(
+                    target.chain_id,
+                    target.address,
+                    request.calldata.clone(),
+                    request.function_id,
+                    request.input.clone(),
+                )
+                .await
+            }
+        }
+    }
+
+    /// Query the current status of a request.
+    pub async fn request_status(&self, request_id: &str) -> Result<FulfillmentStatus> {
+        Ok(self.fetch_request(request_id).await?.into())
+    }
+
+    /// Query the cost of a request, if the platform reports it.
+    pub async fn request_cost(&self, request_id: &str) -> Result<Option<f64>> {
+        Ok(self.fetch_request(request_id).await?.cost)
+    }
+
+    /// Fetch the proof of a request, once the platform proved it.
+    pub async fn request_proof(&self, request_id: &str) -> Result<Option<ProofPayload>> {
+        let response = self.fetch_request(request_id).await?;
+        let proof_id = match FulfillmentStatus::from(response) {
+            FulfillmentStatus::Proved {
+                proof_id: Some(proof_id),
+            }
+            | FulfillmentStatus::Relayed {
+                proof_id: Some(proof_id),
+                ..
+            } => proof_id,
+            _ => return Ok(None),
+        };
+        let url = format!("{}/proof/{}", self.rpc_url, proof_id);
+        let body = self.get(&url).await.with_context(|| {
+            format!(
+                "failed to query proof {} of request {}",
+                proof_id, request_id
+            )
+        })?;
+        parse_proof(&body).map(Some)
+    }
+
+    /// GET `url` with the API key and return the response body.
+    async fn get(&self, url: &str) -> Result<String> {
+        self.api_keys
+            .with_fallback(|api_key| async move {
+                Ok::<_, anyhow::Error>(
+                    self.http
+                        .get(url)
+                        .bearer_auth(api_key.expose())
+                        .send()
+                        .await
+                        .and_then(|res| res.error_for_status())?
+                        .text()
+                        .await?,
+                )
+            })
+            .await
+    }
+
+    async fn fetch_request(&self, request_id: &str) -> Result<RequestResponse> {
+        let url = format!("{}/request/{}", self.rpc_url, request_id);
+        let body = self
+            .get(&url)
+            .await
+            .with_context(|| format!("failed to query status of request {}", request_id))?;
+        serde_json::from_str(&body).context("failed to parse request status")
+    }
+}
+
+#[async_trait]
+impl ProofBackend for PlatformClient {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn request_step(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.submit(request).await
+    }
+
+    async fn request_skip(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.submit(request).await
+    }
+
+    async fn request_data_commitment(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.submit(request).await
+    }
+
+    async fn status(&self, request_id: &str) -> Result<FulfillmentStatus> {
+        self.request_status(request_id).await
+    }
+
+    async fn request_cost(&self, request_id: &str) -> Result<Option<f64>> {
+        PlatformClient::request_cost(self, request_id).await
+    }
+
+    async fn recent_requests(&self, function_id: B256) -> Result<Vec<RecentRequest>> {
+        let url = format!("{}/requests?function_id={}", self.rpc_url, function_id);
+        let body = self
+            .get(&url)
+            .await
+            .with_context(|| format!("failed to list requests for function {}", function_id))?;
+        parse_recent_requests(&body)
+    }
+
+    async fn artifact_digest(&self, function_id: B256) -> Result<Option<B256>> {
+        let url = format!("{}/function/{}", self.rpc_url, function_id);
+        let body = self
+            .get(&url)
+            .await
+            .with_context(|| format!("failed to query function {}", function_id))?;
+        parse_function_digest(&body)
+    }
+
+    async fn proof(&self, request_id: &str) -> Result<Option<ProofPayload>> {
+        self.request_proof(request_id).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_request_status() {
+        assert_eq!(
+            parse_request_status(r#"{"status": "PENDING"}"#).unwrap(),
+            FulfillmentStatus::Proving
+        );
+        assert_eq!(
+            parse_request_status(r#"{"status": "RUNNING", "proof_id": "p1"}"#).unwrap(),
+            FulfillmentStatus::Proving
+        );
+        assert_eq!(
+            parse_request_status(r#"{"status": "SUCCESS", "proof_id": "p1"}"#).unwrap(),
+            FulfillmentStatus::Proved {
+                proof_id: Some("p1".to_string())
+            }
+        );
+        assert_eq!(
+            parse_request_status(
+                r#"{"status": "SUCCESS", "proof_id": "p1", "transaction_hash": "0xab"}"#
+            )
+            .unwrap(),
+            FulfillmentStatus::Relayed {
+                proof_id: Some("p1".to_string()),
+                tx_hash: Some("0xab".to_string())
+            }
+        );
+
+        // The platform's error message is surfaced verbatim.
+        let failed = parse_request_status(
+            r#"{"status": "FAILURE", "error_message": "Trusted header hash doesn't pass sanity check!"}"#,
+        )
+        .unwrap();
+        assert_eq!(
+            failed,
+            FulfillmentStatus::Failed {
+                error: Some("Trusted header hash doesn't pass sanity check!".to_string())
+            }
+        );
+        assert!(failed.is_terminal());
+        assert!(failed.is_settled(RequestMode::Offchain));
+    }
+
+    #[test]
+    fn test_is_settled() {
+        let proved = FulfillmentStatus::Proved {
+            proof_id: Some("p1".to_string()),
+        };
+        assert!(!proved.is_settled(RequestMode::Platform));
+        assert!(proved.is_settled(RequestMode::Offchain));
+        let relayed = FulfillmentStatus::Relayed {
+            proof_id: None,
+            tx_hash: None,
+        };
+        assert!(relayed.is_settled(RequestMode::Platform));
+        assert!(relayed.is_settled(RequestMode::Offchain));
+        assert!(!FulfillmentStatus::Proving.is_settled(RequestMode::Offchain));
+
+        assert!(parse_request_status("not json").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_api_key_fallback() {
+        let keys = ApiKeys::new(
+            ApiKey::new("primary-secret-aaaa"),
+            Some(ApiKey::new("fallback-secret-bbbb")),
+        );
+        let calls = std::sync::Mutex::new(Vec::new());
+        let call = |api_key: ApiKey| {
+            calls.lock().unwrap().push(api_key.fingerprint());
+            async move {
+                match api_key.expose() {
+                    "fallback-secret-bbbb" => Ok("req_1".to_string()),
+                    key => Err(anyhow::Error::new(HttpStatus(StatusCode::UNAUTHORIZED))
+                        .context(format!("invalid key {}", key))),
+                }
+            }
+        };
+
+        // The primary key is rejected, and the fallback succeeds.
+        let uses = fallback_key_uses();
+        assert_eq!(keys.with_fallback(call).await.unwrap(), "req_1");
+        assert_eq!(*calls.lock().unwrap(), vec!["...aaaa", "...bbbb"]);
+        assert!(fallback_key_uses() > uses);
+
+        // Other errors are not retried with the fallback, even if they mention 401 or 403.
+        for error in [
+            "connection refused",
+            "request 401 of block 4030 failed: unauthorized",
+        ] {
+            calls.lock().unwrap().clear();
+            let result = keys
+                .with_fallback(|api_key: ApiKey| {
+                    calls.lock().unwrap().push(api_key.fingerprint());
+                    async move { Err::<String, _>(anyhow!(error)) }
+                })
+                .await;
+            assert!(result.is_err());
+            assert_eq!(calls.lock().unwrap().len(), 1);
+        }
+        let forbidden = anyhow::Error::new(HttpStatus(StatusCode::FORBIDDEN)).context("submission");
+        assert!(is_auth_failure(&forbidden));
+
+        // Without a fallback the error is returned with its causes, the key redacted.
+        let keys = ApiKeys::new(ApiKey::new("primary-secret-aaaa"), None);
+        let error = keys.with_fallback(call).await.unwrap_err();
+        let message = format!("{:#}", error);
+        assert!(!message.contains("primary-secret"), "{}", message);
+        assert_eq!(
+            message,
+            "invalid key <redacted ...aaaa>: HTTP 401 Unauthorized"
+        );
+        assert_eq!(error.chain().count(), 2);
+        // An error that mentions no key is returned as is.
+        let error = keys
+            .with_fallback(|_| async {
+                Err::<String, _>(anyhow::Error::new(HttpStatus(StatusCode::BAD_GATEWAY)))
+            })
+            .await
+            .unwrap_err();
+        assert_eq!(
+            error.downcast_ref(),
+            Some(&HttpStatus(StatusCode::BAD_GATEWAY))
+        );
+        assert_eq!(
+            format!("{:?}", ApiKey::new("primary-secret-aaaa")),
+            "ApiKey(...aaaa)"
+        );
+    }
+
+    #[test]
+    fn test_parse_recent_requests() {
+        let body = r#"[
+            {
+                "id": "req_1",
+                "chain_id": 5,
+                "to": "0x0101010101010101010101010101010101010101",
+                "function_id": "0x0202020202020202020202020202020202020202020202020202020202020202",
+                "input": "0x0000000000000064abababababababababababababababababababababababababababababababab",
+                "status": "RUNNING"
+            }
+        ]"#;
+        let requests = parse_recent_requests(body).unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].request_id, "req_1");
+        assert_eq!(requests[0].address, Address::repeat_byte(1));
+        assert_eq!(requests[0].function_id, B256::repeat_byte(2));
+        assert_eq!(requests[0].range(), Some((100, 101)));
+        assert!(requests[0].is_unfulfilled());
+
+        assert!(parse_recent_requests(r#"[{"id": "req_1"}]"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_proof() {
+        let proof = parse_proof(include_str!("fixtures/platform/proof.json")).unwrap();
+        assert_eq!(proof.proof.len(), 256);
+        assert_eq!(&proof.input[..8], &10000u64.to_be_bytes());
+        assert_eq!(proof.output, Bytes::from([0xcd; 32]));
+
+        let body = r#"{"proof": "0x01", "input": "0x02", "public_values": "0x03"}"#;
+        assert_eq!(parse_proof(body).unwrap().output, Bytes::from_static(&[3]));
+        let error = parse_proof(r#"{"proof": "0xzz", "input": "0x", "output": "0x"}"#).unwrap_err();
+        assert_eq!(error.to_string(), "invalid proof \"0xzz\"");
+        assert!(parse_proof(r#"{"proof": "0x01"}"#).is_err());
+    }
+
+    #[test]
+    fn test_parse_function_digest() {
+        let body = r#"{
+            "id": "0x0202020202020202020202020202020202020202020202020202020202020202",
+            "artifact_digest": "0x0303030303030303030303030303030303030303030303030303030303030303"
+        }"#;
+        assert_eq!(
+            parse_function_digest(body).unwrap(),
+            Some(B256::repeat_byte(3))
+        );
+        assert_eq!(parse_function_digest(r#"{"id": "f"}"#).unwrap(), None);
+        assert!(parse_function_digest(r#"{"digest": "0x03"}"#).is_err());
+    }
+}
@@ -0,0 +1,184 @@
+//! Refreshes the status of every pending request in the store in one pass.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use anyhow::Result;
+use log::{error, warn};
+
+use crate::audit::{AuditEvent, AuditLog};
+use crate::backend::ProofBackend;
+use crate::platform::FulfillmentStatus;
+use crate::store::{RequestStatus, RequestStore, StatusUpdate};
+
+/// The outcome of a refresh of the pending requests.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RefreshSummary {
+    /// The number of pending requests queried.
+    pub pending: usize,
+    /// The number of requests whose status changed, by new status.
+    pub changed: BTreeMap<&'static str, usize>,
+    /// The number of requests whose status could not be queried.
+    pub errors: usize,
+}
+
+impl fmt::Display for RefreshSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let changed = self.changed.values().sum::<usize>();
+        write!(
+            f,
+            "refreshed {} pending requests: {} changed",
+            self.pending, changed
+        )?;
+        if changed > 0 {
+            let by_status = self
+                .changed
+                .iter()
+                .map(|(status, count)| format!("{} {}", count, status))
+                .collect::<Vec<_>>();
+            write!(f, " ({})", by_status.join(", "))?;
+        }
+        write!(f, ", {} errors", self.errors)
+    }
+}
+
+/// Query the status of every pending request in `store` with a single batched query and apply the
+/// changes in one transaction, recording each change in `audit` if set. The backend is not
+/// queried when nothing is pending.
+pub async fn refresh_pending(
+    store: &RequestStore,
+    backend: &dyn ProofBackend,
+    audit: Option<&AuditLog>,
+) -> Result<RefreshSummary> {
+    let pending = store.pending()?;
+    let mut summary = RefreshSummary {
+        pending: pending.len(),
+        ..Default::default()
+    };
+    if pending.is_empty() {
+        return Ok(summary);
+    }
+
+    let request_ids = pending
+        .iter()
+        .map(|record| record.request_id.clone())
+        .collect::<Vec<_>>();
+    let statuses = backend.statuses(&request_ids).await;
+
+    let mut updates = Vec::new();
+    let mut observed = Vec::new();
+    for (record, status) in pending.iter().zip(statuses) {
+        let status = match status {
+            Ok(status) => status,
+            Err(e) => {
+                warn!("Failed to query request {}: {:#}", record.request_id, e);
+                summary.errors += 1;
+                continue;
+            }
+        };
+        let new_status = RequestStatus::from_fulfillment(&status, record.request_mode);
+        if new_status == record.status {
+            continue;
+        }
+        observed.push(AuditEvent::fulfillment(
+            &record.request_id,
+            "poll",
+            &status,
+            record.request_mode,
+        ));
+        let proof_location = match (&new_status, status) {
+            (RequestStatus::Delivered, FulfillmentStatus::Proved { proof_id }) => proof_id,
+            _ => None,
+        };
+        *summary.changed.entry(new_status.as_str()).or_default() += 1;
+        updates.push(StatusUpdate {
+            request_id: record.request_id.clone(),
+            status: new_status,
+            proof_location,
+        });
+    }
+    store.update_statuses(&updates)?;
+    if let Some(audit) = audit {
+        for event in observed {
+            if let Err(e) = audit.record(event) {
+                error!("Failed to write audit log entry: {:#}", e);
+            }
+        }
+    }
+    Ok(summary)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::Bytes;
+
+    use super::*;
+    use crate::backend::mock::MockBackend;
+    use crate::backend::ProofRequest;
+    use crate::store::tests::new_request;
+    use crate::target::RequestTarget;
+
+    #[tokio::test]
+    async fn test_refresh_pending() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RequestStore::open(dir.path().join("requests.db")).unwrap();
+        let backend = MockBackend::new();
+
+        let summary = refresh_pending(&store, &backend, None).await.unwrap();
+        assert_eq!(summary.pending, 0);
+        assert_eq!(backend.status_calls(), 0);
+
+        let target = RequestTarget::for_test(5);
+        for block in 0..50 {
+            let request = ProofRequest {
+                target: &target,
+                trusted_block: block,
+                target_block: block + 1,
+                function_id: target.step_function_id,
+                calldata: Bytes::new(),
+                input: Bytes::new(),
+                correlation_id: None,
+            };
+            let request_id = backend.request_step(&request).await.unwrap();
+            store
+                .insert(&new_request(&request_id, block, block + 1))
+                .unwrap();
+        }
+        // 10 relayed, 5 failed and one unknown to the backend; the rest are still proving.
+        for i in 1..=10 {
+            let relayed = FulfillmentStatus::Relayed {
+                proof_id: None,
+                tx_hash: None,
+            };
+            backend.set_status(&format!("mock-{}", i), relayed);
+        }
+        for i in 11..=15 {
+            backend.set_status(
+                &format!("mock-{}", i),
+                FulfillmentStatus::Failed { error: None },
+            );
+        }
+        store.insert(&new_request("unknown", 100, 101)).unwrap();
+
+        let summary = refresh_pending(&store, &backend, None).await.unwrap();
+        assert_eq!(backend.status_calls(), 51);
+        assert_eq!(summary.pending, 51);
+        assert_eq!(summary.changed.get("relayed"), Some(&10));
+        assert_eq!(summary.changed.get("failed"), Some(&5));
+        assert_eq!(summary.errors, 1);
+        assert_eq!(
+            summary.to_string(),
+            "refreshed 51 pending requests: 15 changed (5 failed, 10 relayed), 1 errors"
+        );
+        assert_eq!(store.pending().unwrap().len(), 36);
+        assert_eq!(
+            store.get("mock-1").unwrap().unwrap().status,
+            RequestStatus::Relayed
+        );
+
+        // Only the requests that are still pending are queried again.
+        let summary = refresh_pending(&store, &backend, None).await.unwrap();
+        assert_eq!(backend.status_calls(), 51 + 36);
+        assert!(summary.changed.is_empty());
+    }
+}
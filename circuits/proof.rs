@@ -0,0 +1,127 @@
+//! The proofs of off-chain requests as JSON documents, for verifiers outside the EVM.
+//!
+//! Off-chain requests are proved but never relayed, so their proof has to be carried to the
+//! verifier some other way. A `ProofDocument` holds what a verifier (e.g. one compiled to Wasm)
+//! needs: the proof, the public input and output of the circuit, the function ID of the circuit,
+//! and the digest of its artifact, which identifies the verification key. Byte fields are
+//! 0x-prefixed hex, as in the other formats of `wire`, and `schema_version` changes whenever the
+//! document does.
+
+use std::fs;
+use std::path::Path;
+
+use alloy_primitives::{Bytes, B256};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::backend::ProofPayload;
+
+/// The version of the document format.
+pub const SCHEMA_VERSION: u32 = 1;
+
+/// The values the proof is verified against.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PublicValues {
+    /// The packed circuit input.
+    #[serde(rename = "input")]
+    pub input: Bytes,
+    /// The circuit output, e.g. the header hash of the target block.
+    #[serde(rename = "output")]
+    pub output: Bytes,
+}
+
+/// The proof of a request and what it is verified against.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofDocument {
+    #[serde(rename = "schema_version")]
+    pub schema_version: u32,
+    #[serde(rename = "request_id")]
+    pub request_id: String,
+    #[serde(rename = "function_id")]
+    pub function_id: B256,
+    /// The digest of the circuit artifact, if the backend registered one.
+    #[serde(rename = "verification_key")]
+    pub verification_key: Option<B256>,
+    #[serde(rename = "proof")]
+    pub proof: Bytes,
+    #[serde(rename = "public_values")]
+    pub public_values: PublicValues,
+}
+
+impl ProofDocument {
+    /// The document of the proof of `request_id`, by the circuit of `function_id`.
+    pub fn new(
+        request_id: &str,
+        function_id: B256,
+        verification_key: Option<B256>,
+        payload: ProofPayload,
+    ) -> Self {
+        Self {
+            schema_version: SCHEMA_VERSION,
+            request_id: request_id.to_string(),
+            function_id,
+            verification_key,
+            proof: payload.proof,
+            public_values: PublicValues {
+                input: payload.input,
+                output: payload.output,
+            },
+        }
+    }
+
+    /// The document as pretty-printed JSON, with a trailing newline.
+    pub fn render(&self) -> String {
+        let mut json = serde_json::to_string_pretty(self).expect("a proof document serializes");
+        json.push('\n');
+        json
+    }
+
+    /// Write the document to `path`.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.render())
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::platform::parse_proof;
+    use crate::wire::assert_snapshot;
+
+    const RECORDED: &str = include_str!("fixtures/platform/proof.json");
+    const DOCUMENT: &str = include_str!("fixtures/serde/proof_document.json");
+
+    fn document() -> ProofDocument {
+        ProofDocument::new(
+            "req_01hw3t2kq8",
+            B256::repeat_byte(0x22),
+            Some(B256::repeat_byte(0x03)),
+            parse_proof(RECORDED).unwrap(),
+        )
+    }
+
+    #[test]
+    fn test_render() {
+        let document = document();
+        assert_eq!(document.render(), DOCUMENT);
+        assert_snapshot(&document, DOCUMENT);
+        assert_eq!(document.public_values.input.len(), 40);
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("proof.json");
+        document.write(&path).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), DOCUMENT);
+    }
+
+    #[test]
+    fn test_render_without_verification_key() {
+        let document = ProofDocument {
+            verification_key: None,
+            ..document()
+        };
+        let json: serde_json::Value = serde_json::from_str(&document.render()).unwrap();
+        assert_eq!(json["schema_version"], SCHEMA_VERSION);
+        assert!(json["verification_key"].is_null());
+    }
+}
@@ -0,0 +1,1380 @@
+//! Running operators for many Tendermint chains in one process.
+//!
+//! A `ChainRegistry` maps the chain ID of each source chain to an operator of its own, with its
+//! fetcher, its targets and their function IDs, and its chain spec, all described by a
+//! `ChainEntry`. Chains are added and removed at runtime over the control socket. The operators
+//! submit through a single backend and share the global submission rate limit: a `FairScheduler`
+//! hands out the submissions the limit allows to the chains waiting for one, each in proportion to
+//! its priority, so that a busy chain can't starve the others. Each chain's loop runs in a task
+//! of its own, restarted with a backoff if it fails or panics.
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use alloy_primitives::B256;
+use anyhow::{anyhow, ensure, Context, Result};
+use async_trait::async_trait;
+use ethers::providers::{Middleware, Provider};
+use futures::future::join_all;
+use futures::stream::{FuturesUnordered, StreamExt};
+use log::{error, info, warn};
+use serde::{Deserialize, Serialize};
+use tokio::sync::Notify;
+use tokio::task::{AbortHandle, JoinError};
+
+use crate::audit::AuditLog;
+use crate::backend::ratelimit::RateLimiter;
+use crate::backend::{ProofBackend, ProofPayload, ProofRequest, RecentRequest};
+use crate::chainspec::ChainSpec;
+use crate::control::{self, Control};
+use crate::dashboard::StatusSnapshot;
+use crate::endpoint::FailoverHttp;
+use crate::health::Health;
+use crate::metrics::{self, write_store_metrics, MetricsSource, MetricsWriter, OperatorMetrics};
+use crate::operator::{IterationOutcome, TendermintXConfig, TendermintXOperator};
+use crate::platform::FulfillmentStatus;
+use crate::retry::RetryPolicy;
+use crate::store::RequestStore;
+use crate::target::{RequestMode, RequestTarget};
+
+/// How the loop of a chain that failed is restarted: after 10 seconds, doubling up to 10 minutes,
+/// as long as it keeps failing.
+pub fn default_restart_policy() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: u32::MAX,
+        initial_backoff: Duration::from_secs(10),
+        max_backoff: Duration::from_secs(10 * 60),
+    }
+}
+
+/// The highest priority of a chain.
+pub const MAX_PRIORITY: u32 = 16;
+
+/// The pass a chain of priority 1 advances by per submission. Divisible by every priority up to
+/// `MAX_PRIORITY`, so that their shares are exact.
+const STRIDE: u64 = 720_720;
+
+/// A chain of the registry, as configured.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChainEntry {
+    #[serde(rename = "chain_id")]
+    pub chain_id: String,
+    /// The Tendermint RPC's of the chain, failed over in order.
+    #[serde(rename = "rpc_urls")]
+    pub rpc_urls: Vec<String>,
+    /// The Ethereum RPC of each target, or a single one shared by all of them, each one or more
+    /// `|` separated URLs to fail over across.
+    #[serde(rename = "ethereum_rpc_urls")]
+    pub ethereum_rpc_urls: Vec<String>,
+    #[serde(rename = "targets")]
+    pub targets: Vec<RequestTarget>,
+    /// The constants of the chain, instead of the CHAIN_SPEC of the environment.
+    #[serde(
+        rename = "chain_spec",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub chain_spec: Option<ChainSpec>,
+    /// The heights of the chain's upgrades, instead of the HALT_HEIGHTS of the environment.
+    #[serde(
+        rename = "halt_heights",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub halt_heights: Vec<u64>,
+    /// The weight of the chain in the shared rate limit.
+    #[serde(rename = "priority", default = "default_priority")]
+    pub priority: u32,
+}
+
+fn default_priority() -> u32 {
+    1
+}
+
+impl ChainEntry {
+    /// The entries of the JSON array in the file at `path`.
+    pub fn read_all(path: &Path) -> Result<Vec<Self>> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        serde_json::from_str(&json).with_context(|| format!("invalid chains in {}", path.display()))
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        ensure!(!self.chain_id.is_empty(), "a chain must have an ID");
+        ensure!(
+            !self.rpc_urls.is_empty(),
+            "chain {} has no Tendermint RPC",
+            self.chain_id
+        );
+        ensure!(
+            !self.targets.is_empty(),
+            "chain {} has no targets",
+            self.chain_id
+        );
+        ensure!(
+            self.ethereum_rpc_urls.len() == 1 || self.ethereum_rpc_urls.len() == self.targets.len(),
+            "chain {} must have one Ethereum RPC or one per target",
+            self.chain_id
+        );
+        ensure!(
+            (1..=MAX_PRIORITY).contains(&self.priority),
+            "the priority of chain {} must be between 1 and {}",
+            self.chain_id,
+            MAX_PRIORITY
+        );
+        for target in self.targets.iter() {
+            target
+                .validate()
+                .with_context(|| format!("invalid target of chain {}", self.chain_id))?;
+        }
+        Ok(())
+    }
+
+    /// The Ethereum RPC of each target.
+    pub fn ethereum_rpc_url_per_target(&self) -> Vec<String> {
+        match self.ethereum_rpc_urls.as_slice() {
+            [url] => vec![url.clone(); self.targets.len()],
+            urls => urls.to_vec(),
+        }
+    }
+
+    /// `config`, the settings shared by the operators of a registry, for this chain: its targets,
+    /// chain spec and halt heights, without the listeners, the rate limit and the audit log, which
+    /// are the registry's.
+    pub fn operator_config(&self, mut config: TendermintXConfig) -> TendermintXConfig {
+        config.targets = self.targets.clone();
+        if let Some(spec) = self.chain_spec.clone() {
+            config.chain_spec = Some(spec);
+        }
+        if !self.halt_heights.is_empty() {
+            config.halt_heights = self.halt_heights.clone();
+        }
+        config.max_requests_per_hour = None;
+        config.metrics_addr = None;
+        config.control_socket = None;
+        config.webhook = None;
+        config.heartbeat_file = None;
+        config.heartbeat_url = None;
+        config.audit = None;
+        config
+    }
+}
+
+#[derive(Debug)]
+struct Share {
+    priority: u32,
+    /// Advances by `STRIDE / priority` with every submission: the chain waiting with the lowest
+    /// pass submits next.
+    pass: u64,
+    /// The submissions of the chain waiting for the limit.
+    waiting: usize,
+    submitted: u64,
+}
+
+#[derive(Debug, Default)]
+struct SchedulerState {
+    shares: BTreeMap<String, Share>,
+    /// The pass of the last submission. A chain that starts waiting after being idle starts from
+    /// it, rather than spending the submissions it didn't make while idle all at once.
+    virtual_time: u64,
+}
+
+impl SchedulerState {
+    /// The chain waiting with the lowest pass, the one of highest priority on a tie.
+    fn next(&self) -> Option<&str> {
+        self.shares
+            .iter()
+            .filter(|(_, share)| share.waiting > 0)
+            .min_by_key(|(_, share)| (share.pass, u32::MAX - share.priority))
+            .map(|(chain_id, _)| chain_id.as_str())
+    }
+}
+
+/// Shares a rate limit among the chains of a registry by stride scheduling: while several chains
+/// wait for the limit, each gets submissions in proportion to its priority. Without a limit,
+/// submissions never wait.
+#[derive(Debug)]
+pub struct FairScheduler {
+    limiter: Option<Arc<RateLimiter>>,
+    state: Mutex<SchedulerState>,
+    /// Notified when a chain submits or stops waiting, so that the next one tries.
+    released: Notify,
+}
+
+impl FairScheduler {
+    pub fn new(limiter: Option<Arc<RateLimiter>>) -> Self {
+        Self {
+            limiter,
+            state: Mutex::new(SchedulerState::default()),
+            released: Notify::new(),
+        }
+    }
+
+    /// Share the limit with `chain_id`, its priority clamped to `1..=MAX_PRIORITY`.
+    pub fn register(&self, chain_id: &str, priority: u32) {
+        let mut state = self.state.lock().unwrap();
+        let pass = state.virtual_time;
+        state.shares.insert(
+            chain_id.to_string(),
+            Share {
+                priority: priority.clamp(1, MAX_PRIORITY),
+                pass,
+                waiting: 0,
+                submitted: 0,
+            },
+        );
+    }
+
+    pub fn unregister(&self, chain_id: &str) {
+        self.state.lock().unwrap().shares.remove(chain_id);
+        self.released.notify_waiters();
+    }
+
+    /// The number of submissions of each chain.
+    pub fn submitted(&self) -> BTreeMap<String, u64> {
+        let state = self.state.lock().unwrap();
+        let shares = state.shares.iter();
+        shares
+            .map(|(id, share)| (id.clone(), share.submitted))
+            .collect()
+    }
+
+    /// The chain that submits next among those waiting, if any.
+    pub fn next_chain(&self) -> Option<String> {
+        self.state.lock().unwrap().next().map(str::to_string)
+    }
+
+    /// Count a submission of `chain_id` as waiting, until the returned guard is dropped.
+    pub fn wait(&self, chain_id: &str) -> Waiting<'_> {
+        let mut state = self.state.lock().unwrap();
+        let virtual_time = state.virtual_time;
+        if let Some(share) = state.shares.get_mut(chain_id) {
+            if share.waiting == 0 {
+                share.pass = share.pass.max(virtual_time);
+            }
+            share.waiting += 1;
+        }
+        Waiting {
+            scheduler: self,
+            chain_id: chain_id.to_string(),
+        }
+    }
+
+    /// Count a submission of the waiting `chain_id` at `now`, if it is its turn and within the
+    /// limit. Otherwise returns how long to wait for the limit if it is its turn, or `None` if
+    /// another chain goes first. A chain that isn't registered only waits for the limit.
+    pub fn try_reserve_at(&self, chain_id: &str, now: Instant) -> Result<(), Option<Duration>> {
+        let mut state = self.state.lock().unwrap();
+        let registered = state.shares.contains_key(chain_id);
+        if registered && state.next() != Some(chain_id) {
+            return Err(None);
+        }
+        if let Some(limiter) = self.limiter.as_ref() {
+            limiter.try_reserve_at(now).map_err(Some)?;
+        }
+        if let Some(share) = state.shares.get_mut(chain_id) {
+            let pass = share.pass;
+            share.pass += STRIDE / share.priority as u64;
+            share.submitted += 1;
+            state.virtual_time = state.virtual_time.max(pass);
+        }
+        Ok(())
+    }
+
+    /// Wait until it is the turn of `chain_id` and the submission is within the limit, and count
+    /// it.
+    pub async fn reserve(&self, chain_id: &str) {
+        let _waiting = self.wait(chain_id);
+        let start = Instant::now();
+        let mut waited = Duration::ZERO;
+        loop {
+            let released = self.released.notified();
+            tokio::pin!(released);
+            released.as_mut().enable();
+            match self.try_reserve_at(chain_id, Instant::now()) {
+                Ok(()) => break,
+                Err(Some(wait)) => tokio::time::sleep(wait).await,
+                Err(None) => released.await,
+            }
+            waited = start.elapsed();
+        }
+        if let Some(limiter) = self.limiter.as_ref() {
+            limiter.observe_wait(waited);
+        }
+    }
+}
+
+/// A submission of a chain waiting for its turn, counted until dropped.
+pub struct Waiting<'a> {
+    scheduler: &'a FairScheduler,
+    chain_id: String,
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        let mut state = self.scheduler.state.lock().unwrap();
+        if let Some(share) = state.shares.get_mut(&self.chain_id) {
+            share.waiting -= 1;
+        }
+        drop(state);
+        self.scheduler.released.notify_waiters();
+    }
+}
+
+/// The backend of the operator of a chain in a registry: submissions wait for the turn of the
+/// chain in the scheduler shared by the registry.
+pub struct ScheduledBackend {
+    inner: Arc<dyn ProofBackend>,
+    scheduler: Arc<FairScheduler>,
+    chain_id: String,
+}
+
+impl ScheduledBackend {
+    pub fn new(
+        inner: Arc<dyn ProofBackend>,
+        scheduler: Arc<FairScheduler>,
+        chain_id: &str,
+    ) -> Self {
+        Self {
+            inner,
+            scheduler,
+            chain_id: chain_id.to_string(),
+        }
+    }
+}
+
+#[async_trait]
+impl ProofBackend for ScheduledBackend {
+    fn name(&self) -> &str {
+        self.inner.name()
+    }
+
+    fn served_by(&self, request_id: &str) -> Option<String> {
+        self.inner.served_by(request_id)
+    }
+
+    async fn request_step(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.scheduler.reserve(&self.chain_id).await;
+        self.inner.request_step(request).await
+    }
+
+    async fn request_skip(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.scheduler.reserve(&self.chain_id).await;
+        self.inner.request_skip(request).await
+    }
+
+    async fn request_data_commitment(&self, request: &ProofRequest<'_>) -> Result<String> {
+        self.scheduler.reserve(&self.chain_id).await;
+        self.inner.request_data_commitment(request).await
+    }
+
+    async fn status(&self, request_id: &str) -> Result<FulfillmentStatus> {
+        self.inner.status(request_id).await
+    }
+
+    async fn statuses(&self, request_ids: &[String]) -> Vec<Result<FulfillmentStatus>> {
+        self.inner.statuses(request_ids).await
+    }
+
+    async fn recent_requests(&self, function_id: B256) -> Result<Vec<RecentRequest>> {
+        self.inner.recent_requests(function_id).await
+    }
+
+    async fn request_cost(&self, request_id: &str) -> Result<Option<f64>> {
+        self.inner.request_cost(request_id).await
+    }
+
+    async fn artifact_digest(&self, function_id: B256) -> Result<Option<B256>> {
+        self.inner.artifact_digest(function_id).await
+    }
+
+    async fn cancel(&self, request_id: &str) -> Result<bool> {
+        self.inner.cancel(request_id).await
+    }
+
+    async fn proof(&self, request_id: &str) -> Result<Option<ProofPayload>> {
+        self.inner.proof(request_id).await
+    }
+
+    async fn wait_for_fulfillment(
+        &self,
+        request_id: &str,
+        mode: RequestMode,
+        timeout: Duration,
+    ) -> Result<FulfillmentStatus> {
+        self.inner
+            .wait_for_fulfillment(request_id, mode, timeout)
+            .await
+    }
+}
+
+/// A change to the chains of a registry, queued over the control socket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RegistryChange {
+    Add(ChainEntry),
+    Remove(String),
+}
+
+#[derive(Debug, Default)]
+struct ChangesState {
+    /// The priority of each registered chain, including the queued additions.
+    chains: BTreeMap<String, u32>,
+    queued: Vec<RegistryChange>,
+}
+
+/// The chains of a registry as the control socket sees them, and the changes to them it queued
+/// for the registry to apply.
+#[derive(Debug, Default)]
+pub struct RegistryChanges {
+    state: Mutex<ChangesState>,
+    queued: Notify,
+}
+
+impl RegistryChanges {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Queue the addition of `entry`, unless its chain is already registered.
+    pub fn add(&self, entry: ChainEntry) -> Result<String> {
+        entry.validate()?;
+        let mut state = self.state.lock().unwrap();
+        ensure!(
+            !state.chains.contains_key(&entry.chain_id),
+            "chain {} is already registered",
+            entry.chain_id
+        );
+        let reply = format!("added {}", entry.chain_id);
+        state.chains.insert(entry.chain_id.clone(), entry.priority);
+        state.queued.push(RegistryChange::Add(entry));
+        self.queued.notify_one();
+        Ok(reply)
+    }
+
+    /// Queue the removal of the chain `chain_id`.
+    pub fn remove(&self, chain_id: &str) -> Result<String> {
+        let mut state = self.state.lock().unwrap();
+        ensure!(
+            state.chains.remove(chain_id).is_some(),
+            "chain {} is not registered",
+            chain_id
+        );
+        state
+            .queued
+            .push(RegistryChange::Remove(chain_id.to_string()));
+        self.queued.notify_one();
+        Ok(format!("removed {}", chain_id))
+    }
+
+    /// The registered chains and their priorities, e.g. "mocha-4 (priority 2), osmosis-1
+    /// (priority 1)".
+    pub fn describe(&self) -> String {
+        let state = self.state.lock().unwrap();
+        if state.chains.is_empty() {
+            return "no chains".to_string();
+        }
+        let chains = state.chains.iter();
+        let chains =
+            chains.map(|(chain_id, priority)| format!("{} (priority {})", chain_id, priority));
+        chains.collect::<Vec<_>>().join(", ")
+    }
+
+    /// The queued changes, oldest first.
+    pub fn take(&self) -> Vec<RegistryChange> {
+        std::mem::take(&mut self.state.lock().unwrap().queued)
+    }
+
+    /// Resolves once a change is queued.
+    pub async fn queued(&self) {
+        self.queued.notified().await
+    }
+
+    fn record(&self, chain_id: &str, priority: u32) {
+        let mut state = self.state.lock().unwrap();
+        state.chains.insert(chain_id.to_string(), priority);
+    }
+
+    fn forget(&self, chain_id: &str) {
+        self.state.lock().unwrap().chains.remove(chain_id);
+    }
+
+    fn contains(&self, chain_id: &str) -> bool {
+        self.state.lock().unwrap().chains.contains_key(chain_id)
+    }
+}
+
+/// The metrics of the operators of a registry, each labeled with the `source_chain` it runs for.
+#[derive(Debug, Default)]
+pub struct RegistryMetrics {
+    chains: Mutex<BTreeMap<String, Arc<OperatorMetrics>>>,
+    /// The limiter shared by the chains, if any.
+    limiter: Option<Arc<RateLimiter>>,
+}
+
+impl RegistryMetrics {
+    pub fn write(&self, writer: &mut MetricsWriter) {
+        for (chain_id, metrics) in self.chains.lock().unwrap().iter() {
+            writer.set_labels(&[("source_chain", chain_id)]);
+            metrics.write(writer);
+        }
+        writer.set_labels(&[]);
+        if let Some(limiter) = self.limiter.as_ref() {
+            metrics::write_rate_limit_waits(writer, limiter);
+        }
+    }
+}
+
+impl MetricsSource for RegistryMetrics {
+    fn render(&self, store: Option<&RequestStore>) -> Result<String> {
+        let mut writer = MetricsWriter::new();
+        self.write(&mut writer);
+        if let Some(store) = store {
+            write_store_metrics(&mut writer, store)?;
+        }
+        Ok(writer.finish())
+    }
+}
+
+/// Builds the operator of a chain, submitting through the given backend.
+pub type OperatorBuilder<M> =
+    Box<dyn Fn(&ChainEntry, Box<dyn ProofBackend>) -> Result<TendermintXOperator<M>> + Send + Sync>;
+
+pub struct RegisteredChain<M> {
+    pub entry: ChainEntry,
+    pub operator: TendermintXOperator<M>,
+}
+
+/// What a task of `ChainRegistry::run` ended with.
+enum Stopped {
+    /// The loop of a chain, started at the instant, returned, failed, panicked or was aborted.
+    Loop(String, Instant, Result<Result<()>, JoinError>),
+    /// The backoff before restarting the loop of a chain elapsed, or was aborted.
+    Backoff(String, Result<(), JoinError>),
+}
+
+type ChainTask = Pin<Box<dyn Future<Output = Stopped>>>;
+
+/// A chain whose loop runs, or is about to be restarted.
+struct Running {
+    entry: ChainEntry,
+    /// Aborts the loop, or the backoff before restarting it.
+    abort: AbortHandle,
+    /// The consecutive failures of the loop.
+    failures: u32,
+}
+
+/// The operators of the chains of a registry, sharing a backend, its rate limit and the controls.
+pub struct ChainRegistry<M = Provider<FailoverHttp>> {
+    /// The chains, until `run` starts their loops.
+    chains: BTreeMap<String, RegisteredChain<M>>,
+    backend: Arc<dyn ProofBackend>,
+    scheduler: Arc<FairScheduler>,
+    build: OperatorBuilder<M>,
+    control: Arc<Control>,
+    changes: Arc<RegistryChanges>,
+    metrics: Arc<RegistryMetrics>,
+    /// Shared by the operators of the chains: live while any of them iterates.
+    health: Arc<Health>,
+    control_socket: Option<PathBuf>,
+    metrics_addr: Option<SocketAddr>,
+    store: Option<Arc<RequestStore>>,
+    /// Shared by the operators of the chains, so that a single writer appends to the log.
+    audit: Option<Arc<AuditLog>>,
+    /// How failed loops are restarted.
+    restart_policy: RetryPolicy,
+}
+
+impl<M: Middleware + 'static> ChainRegistry<M> {
+    /// An empty registry proving with `backend` within `limiter`, if any, building the operator of
+    /// each chain with `build`.
+    pub fn new(
+        backend: Box<dyn ProofBackend>,
+        limiter: Option<Arc<RateLimiter>>,
+        health: Health,
+        build: OperatorBuilder<M>,
+    ) -> Self {
+        let changes = Arc::new(RegistryChanges::new());
+        Self {
+            chains: BTreeMap::new(),
+            backend: Arc::from(backend),
+            scheduler: Arc::new(FairScheduler::new(limiter.clone())),
+            build,
+            control: Arc::new(Control::with_registry(changes.clone())),
+            changes,
+            metrics: Arc::new(RegistryMetrics {
+                limiter,
+                ..Default::default()
+            }),
+            health: Arc::new(health),
+            control_socket: None,
+            metrics_addr: None,
+            store: None,
+            audit: None,
+            restart_policy: default_restart_policy(),
+        }
+    }
+
+    /// Restart the loop of a chain that failed or panicked after the backoff of `policy`, and stop
+    /// it once it failed `policy.max_attempts` times in a row. A loop that ran for the maximum
+    /// backoff or longer before failing starts over from the initial backoff.
+    pub fn with_restart_policy(mut self, policy: RetryPolicy) -> Self {
+        self.restart_policy = policy;
+        self
+    }
+
+    /// Serve the controls of the registry on `path` while it runs.
+    pub fn with_control_socket(mut self, path: PathBuf) -> Self {
+        self.control_socket = Some(path);
+        self
+    }
+
+    /// Serve the metrics of every chain, and the statistics of `store`, on `addr` while it runs.
+    pub fn with_metrics(mut self, addr: SocketAddr, store: Option<Arc<RequestStore>>) -> Self {
+        self.metrics_addr = Some(addr);
+        self.store = store;
+        self
+    }
+
+    /// Append the events of every chain to `audit`.
+    pub fn with_audit(mut self, audit: Arc<AuditLog>) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    pub fn control(&self) -> &Arc<Control> {
+        &self.control
+    }
+
+    pub fn scheduler(&self) -> &Arc<FairScheduler> {
+        &self.scheduler
+    }
+
+    pub fn metrics(&self) -> &Arc<RegistryMetrics> {
+        &self.metrics
+    }
+
+    pub fn chain_ids(&self) -> Vec<&str> {
+        self.chains.keys().map(String::as_str).collect()
+    }
+
+    /// Register `entry`, building the operator of its chain.
+    pub fn add(&mut self, entry: ChainEntry) -> Result<()> {
+        ensure!(
+            !self.changes.contains(&entry.chain_id),
+            "chain {} is already registered",
+            entry.chain_id
+        );
+        let chain = self.register(entry)?;
+        self.chains.insert(chain.entry.chain_id.clone(), chain);
+        Ok(())
+    }
+
+    /// Stop running the chain `chain_id`.
+    pub fn remove(&mut self, chain_id: &str) -> Result<ChainEntry> {
+        let chain = self
+            .chains
+            .remove(chain_id)
+            .ok_or_else(|| anyhow!("chain {} is not registered", chain_id))?;
+        self.unregister(chain_id);
+        Ok(chain.entry)
+    }
+
+    fn register(&self, entry: ChainEntry) -> Result<RegisteredChain<M>> {
+        entry.validate()?;
+        let chain_id = entry.chain_id.as_str();
+        let backend = ScheduledBackend::new(self.backend.clone(), self.scheduler.clone(), chain_id);
+        let mut operator = (self.build)(&entry, Box::new(backend))
+            .with_context(|| format!("could not build the operator of chain {}", chain_id))?;
+        operator.set_control(self.control.clone());
+        operator.set_health(self.health.clone());
+        if let Some(audit) = self.audit.as_ref() {
+            operator.set_audit(audit.clone());
+        }
+        self.scheduler.register(chain_id, entry.priority);
+        let metrics = operator.metrics().clone();
+        self.metrics
+            .chains
+            .lock()
+            .unwrap()
+            .insert(chain_id.to_string(), metrics);
+        self.changes.record(chain_id, entry.priority);
+        info!(
+            "Registered chain {} with {} targets, priority {}",
+            chain_id,
+            entry.targets.len(),
+            entry.priority
+        );
+        Ok(RegisteredChain { entry, operator })
+    }
+
+    fn unregister(&self, chain_id: &str) {
+        self.scheduler.unregister(chain_id);
+        self.metrics.chains.lock().unwrap().remove(chain_id);
+        self.changes.forget(chain_id);
+        info!("Removed chain {}", chain_id);
+    }
+
+    /// Apply the changes queued over the control socket to the chains not yet running.
+    pub fn apply_changes(&mut self) {
+        for change in self.changes.take() {
+            self.apply_change(change);
+        }
+    }
+
+    fn apply_change(&mut self, change: RegistryChange) {
+        match change {
+            RegistryChange::Add(entry) => {
+                let chain_id = entry.chain_id.clone();
+                match self.register(entry) {
+                    Ok(chain) => {
+                        self.chains.insert(chain_id, chain);
+                    }
+                    Err(e) => {
+                        error!("{:#}", e);
+                        self.changes.forget(&chain_id);
+                    }
+                }
+            }
+            RegistryChange::Remove(chain_id) => {
+                if self.chains.remove(&chain_id).is_some() {
+                    self.unregister(&chain_id);
+                }
+            }
+        }
+    }
+
+    /// Apply the queued changes, then run a single iteration of every chain, concurrently.
+    pub async fn run_once(&mut self) -> Vec<(String, Result<IterationOutcome>)> {
+        self.apply_changes();
+        let iterations = self.chains.iter_mut().map(|(chain_id, chain)| async move {
+            (chain_id.clone(), chain.operator.run_once().await)
+        });
+        join_all(iterations).await
+    }
+
+    /// The status of every chain.
+    pub async fn collect_status(&mut self) -> Vec<(String, Result<StatusSnapshot>)> {
+        let statuses = self.chains.iter_mut().map(|(chain_id, chain)| async move {
+            (chain_id.clone(), chain.operator.collect_status().await)
+        });
+        join_all(statuses).await
+    }
+
+    /// Run the loop of every chain, and those of the chains added over the control socket, until
+    /// they are removed, each in a task of its own. A chain whose loop fails or panics is rebuilt
+    /// and restarted after a backoff (see `with_restart_policy`), without stopping the others.
+    pub async fn run(mut self) -> Result<()> {
+        if let Some(path) = self.control_socket.clone() {
+            let control = self.control.clone();
+            tokio::spawn(async move {
+                if let Err(e) = control::serve(control, path).await {
+                    error!("Control socket stopped: {:#}", e);
+                }
+            });
+        }
+        if let Some(addr) = self.metrics_addr {
+            let listener = std::net::TcpListener::bind(addr)
+                .with_context(|| format!("could not bind the metrics listener to {}", addr))?;
+            let (metrics, health, store) = (
+                self.metrics.clone(),
+                self.health.clone(),
+                self.store.clone(),
+            );
+            tokio::spawn(async move {
+                if let Err(e) = metrics::serve(metrics, health, store, listener).await {
+                    error!("Metrics listener stopped: {:#}", e);
+                }
+            });
+        }
+
+        let mut tasks = FuturesUnordered::new();
+        let mut running = BTreeMap::new();
+        for (_, chain) in std::mem::take(&mut self.chains) {
+            start(&mut tasks, &mut running, chain, 0);
+        }
+
+        let changes = self.changes.clone();
+        loop {
+            let stopped = tokio::select! {
+                _ = changes.queued() => None,
+                Some(stopped) = tasks.next(), if !tasks.is_empty() => Some(stopped),
+            };
+            match stopped {
+                // Removed chains are unregistered as they are aborted, and a backoff only ends
+                // early when its chain is removed.
+                Some(Stopped::Loop(_, _, Err(e))) if e.is_cancelled() => {}
+                Some(Stopped::Backoff(_, Err(_))) => {}
+                Some(Stopped::Loop(chain_id, started, result)) => {
+                    let error = match result {
+                        Ok(Ok(())) => {
+                            running.remove(&chain_id);
+                            self.unregister(&chain_id);
+                            continue;
+                        }
+                        Ok(Err(e)) => format!("{:#}", e),
+                        Err(e) => format!("it panicked: {}", panic_message(e.into_panic())),
+                    };
+                    let chain = running.get_mut(&chain_id).unwrap();
+                    if started.elapsed() >= self.restart_policy.max_backoff {
+                        chain.failures = 0;
+                    }
+                    chain.failures += 1;
+                    self.restart_later(&mut tasks, &mut running, &chain_id, &error);
+                }
+                Some(Stopped::Backoff(chain_id, Ok(()))) => {
+                    let chain = &running[&chain_id];
+                    let failures = chain.failures;
+                    match self.register(chain.entry.clone()) {
+                        Ok(registered) => {
+                            info!("Restarting the loop of chain {}", chain_id);
+                            start(&mut tasks, &mut running, registered, failures);
+                        }
+                        Err(e) => {
+                            running.get_mut(&chain_id).unwrap().failures += 1;
+                            let error = format!("{:#}", e);
+                            self.restart_later(&mut tasks, &mut running, &chain_id, &error);
+                        }
+                    }
+                }
+                None => {
+                    for change in changes.take() {
+                        match change {
+                            RegistryChange::Remove(chain_id) if running.contains_key(&chain_id) => {
+                                running.remove(&chain_id).unwrap().abort.abort();
+                                self.unregister(&chain_id);
+                            }
+                            change => self.apply_change(change),
+                        }
+                        for (_, chain) in std::mem::take(&mut self.chains) {
+                            start(&mut tasks, &mut running, chain, 0);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// After the loop of `chain_id` failed with `error`, restart it after the backoff of its
+    /// consecutive failures, or stop it once they reach the maximum attempts.
+    fn restart_later(
+        &self,
+        tasks: &mut FuturesUnordered<ChainTask>,
+        running: &mut BTreeMap<String, Running>,
+        chain_id: &str,
+        error: &str,
+    ) {
+        let chain = running.get_mut(chain_id).unwrap();
+        if chain.failures >= self.restart_policy.max_attempts {
+            error!(
+                "The loop of chain {} failed {} times in a row, stopping it: {}",
+                chain_id, chain.failures, error
+            );
+            running.remove(chain_id);
+            self.unregister(chain_id);
+            return;
+        }
+        let backoff = self.restart_policy.backoff(chain.failures);
+        warn!(
+            "The loop of chain {} failed, restarting it in {:?}: {}",
+            chain_id, backoff, error
+        );
+        let task = tokio::spawn(tokio::time::sleep(backoff));
+        chain.abort = task.abort_handle();
+        let chain_id = chain_id.to_string();
+        tasks.push(Box::pin(
+            async move { Stopped::Backoff(chain_id, task.await) },
+        ));
+    }
+}
+
+/// The message of the panic of the loop of a chain.
+fn panic_message(panic: Box<dyn std::any::Any + Send>) -> String {
+    let message = panic
+        .downcast_ref::<String>()
+        .map(String::as_str)
+        .or_else(|| panic.downcast_ref::<&str>().copied());
+    message.unwrap_or("no message").to_string()
+}
+
+/// Start the loop of `chain` in a task of its own, awaited in `tasks` and aborted through its
+/// entry in `running`.
+fn start<M: Middleware + 'static>(
+    tasks: &mut FuturesUnordered<ChainTask>,
+    running: &mut BTreeMap<String, Running>,
+    chain: RegisteredChain<M>,
+    failures: u32,
+) {
+    let chain_id = chain.entry.chain_id.clone();
+    let mut operator = chain.operator;
+    let task = tokio::spawn(async move { operator.run().await });
+    running.insert(
+        chain_id.clone(),
+        Running {
+            entry: chain.entry,
+            abort: task.abort_handle(),
+            failures,
+        },
+    );
+    let started = Instant::now();
+    tasks.push(Box::pin(async move {
+        Stopped::Loop(chain_id, started, task.await)
+    }));
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use alloy_primitives::Address;
+    use ethers::providers::MockProvider;
+
+    use super::*;
+    use crate::audit::{self, DEFAULT_MAX_BYTES, DEFAULT_MAX_FILES};
+    use crate::backend::mock::MockBackend;
+    use crate::control::ControlCommand;
+    use crate::fault::FaultInjecting;
+    use crate::input::{header_hash, InputDataFetcher, InputDataMode};
+    use crate::operator::AuditConfig;
+    use crate::testing::{MockTendermintServer, SyntheticChain};
+    use crate::trusted::LocalTrustedState;
+    use crate::types::{HeaderHash, Height};
+
+    fn entry(chain_id: &str, rpc_url: String, target_chain_id: u32) -> ChainEntry {
+        ChainEntry {
+            chain_id: chain_id.to_string(),
+            rpc_urls: vec![rpc_url],
+            ethereum_rpc_urls: vec!["http://localhost:8545".to_string()],
+            targets: vec![RequestTarget::for_test(target_chain_id)],
+            chain_spec: None,
+            halt_heights: Vec::new(),
+            priority: 1,
+        }
+    }
+
+    /// Grant `grants` submissions in turn to the waiting chains, checking that only the next one
+    /// can submit.
+    fn grants(scheduler: &FairScheduler, grants: usize) -> Vec<String> {
+        let now = Instant::now();
+        let mut granted = Vec::new();
+        for _ in 0..grants {
+            let chain_id = scheduler.next_chain().unwrap();
+            for other in scheduler.submitted().keys().filter(|id| **id != chain_id) {
+                assert_eq!(scheduler.try_reserve_at(other, now), Err(None));
+            }
+            scheduler.try_reserve_at(&chain_id, now).unwrap();
+            granted.push(chain_id);
+        }
+        granted
+    }
+
+    #[test]
+    fn test_shares_by_priority() {
+        let scheduler = FairScheduler::new(None);
+        let mut waiting = Vec::new();
+        for (chain_id, priority) in [("a", 1), ("b", 2), ("c", 3)] {
+            scheduler.register(chain_id, priority);
+            waiting.push(scheduler.wait(chain_id));
+        }
+        let granted = grants(&scheduler, 60);
+        assert_eq!(granted[..6].join(""), "cbacbc");
+        let submitted = scheduler.submitted();
+        let shares: Vec<_> = submitted.values().copied().collect();
+        assert_eq!(shares, [10, 20, 30]);
+
+        // A chain that stops waiting doesn't hold the others up.
+        waiting.remove(2);
+        assert_eq!(grants(&scheduler, 3).join(""), "bab");
+        drop(waiting);
+        assert_eq!(scheduler.next_chain(), None);
+    }
+
+    #[test]
+    fn test_priority_is_bounded() {
+        // A priority past `STRIDE` would never advance the chain's pass, and starve the others.
+        let scheduler = FairScheduler::new(None);
+        scheduler.register("a", 1);
+        scheduler.register("b", u32::MAX);
+        let _waiting = [scheduler.wait("a"), scheduler.wait("b")];
+        let granted = grants(&scheduler, 34);
+        assert_eq!(granted.iter().filter(|id| *id == "a").count(), 2);
+    }
+
+    #[test]
+    fn test_late_chain_starts_even() {
+        // A chain joining, or waiting again after being idle, starts from the current pass: it
+        // gets its share from then on, not the submissions it missed.
+        let scheduler = FairScheduler::new(None);
+        scheduler.register("a", 1);
+        let _a = scheduler.wait("a");
+        assert_eq!(grants(&scheduler, 5).join(""), "aaaaa");
+        scheduler.register("b", 1);
+        let b = scheduler.wait("b");
+        assert_eq!(grants(&scheduler, 5).join(""), "babab");
+
+        drop(b);
+        assert_eq!(grants(&scheduler, 10).join(""), "aaaaaaaaaa");
+        let _b = scheduler.wait("b");
+        assert_eq!(grants(&scheduler, 4).join(""), "baba");
+
+        // Without the chain, the others go on; an unregistered chain only waits for the limit.
+        scheduler.unregister("b");
+        assert_eq!(grants(&scheduler, 2).join(""), "aa");
+        assert_eq!(scheduler.try_reserve_at("b", Instant::now()), Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_reserve_shares_the_limit() {
+        let limiter = Arc::new(RateLimiter::new(4, Duration::from_millis(20)));
+        let scheduler = Arc::new(FairScheduler::new(Some(limiter)));
+        scheduler.register("low", 1);
+        scheduler.register("high", 3);
+        let submit = |chain_id: &'static str| {
+            let scheduler = scheduler.clone();
+            tokio::spawn(async move {
+                loop {
+                    scheduler.reserve(chain_id).await;
+                }
+            })
+        };
+        let start = Instant::now();
+        let (low, high) = (submit("low"), submit("high"));
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        low.abort();
+        high.abort();
+        let windows = start.elapsed().as_millis() as u64 / 20 + 1;
+
+        let submitted = scheduler.submitted();
+        let (low, high) = (submitted["low"], submitted["high"]);
+        // At most 4 per 20ms, the high priority chain getting about 3 of each 4.
+        assert!(low + high <= 4 * windows, "{} + {}", low, high);
+        assert!(low > 0 && high >= 2 * low, "{} and {}", low, high);
+    }
+
+    #[test]
+    fn test_registry_changes() {
+        let changes = Arc::new(RegistryChanges::new());
+        let control = Control::with_registry(changes.clone());
+        let a = entry("chain-a", "http://localhost:26657".to_string(), 5);
+        assert_eq!(control.apply(ControlCommand::Chains).unwrap(), "no chains");
+        let add = format!("add-chain {}", serde_json::to_string(&a).unwrap());
+        let command: ControlCommand = add.parse().unwrap();
+        assert_eq!(command, ControlCommand::AddChain(Box::new(a.clone())));
+        assert_eq!(control.apply(command.clone()).unwrap(), "added chain-a");
+        assert_eq!(
+            control.apply(command).unwrap_err().to_string(),
+            "chain chain-a is already registered"
+        );
+
+        let mut b = entry("chain-b", "http://localhost:26658".to_string(), 6);
+        b.priority = 0;
+        assert!(changes.add(b.clone()).is_err());
+        b.priority = 2;
+        changes.add(b.clone()).unwrap();
+        assert_eq!(
+            control.apply(ControlCommand::Chains).unwrap(),
+            "chain-a (priority 1), chain-b (priority 2)"
+        );
+        let remove = ControlCommand::RemoveChain("chain-a".to_string());
+        assert_eq!(control.apply(remove.clone()).unwrap(), "removed chain-a");
+        assert_eq!(
+            control.apply(remove).unwrap_err().to_string(),
+            "chain chain-a is not registered"
+        );
+        assert_eq!(
+            changes.take(),
+            [
+                RegistryChange::Add(a),
+                RegistryChange::Add(b),
+                RegistryChange::Remove("chain-a".to_string())
+            ]
+        );
+        assert!(changes.take().is_empty());
+    }
+
+    #[test]
+    fn test_chain_entry() {
+        // Without a priority or a chain spec.
+        let targets = [5, 6].map(|chain_id| {
+            serde_json::json!({
+                "chain_id": chain_id,
+                "address": Address::repeat_byte(0x11),
+                "step_function_id": B256::repeat_byte(0x22),
+                "skip_function_id": B256::repeat_byte(0x33),
+            })
+        });
+        let json = serde_json::json!([{
+            "chain_id": "mocha-4",
+            "rpc_urls": ["http://localhost:26657"],
+            "ethereum_rpc_urls": ["http://localhost:8545"],
+            "targets": targets,
+        }]);
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("chains.json");
+        fs::write(&path, json.to_string()).unwrap();
+        let entries = ChainEntry::read_all(&path).unwrap();
+        assert_eq!(entries.len(), 1);
+        let entry = &entries[0];
+        assert_eq!(entry.priority, 1);
+        assert_eq!(
+            entry.targets,
+            [RequestTarget::for_test(5), RequestTarget::for_test(6)]
+        );
+        entry.validate().unwrap();
+        assert_eq!(entry.ethereum_rpc_url_per_target().len(), 2);
+
+        let mut shared = TendermintXConfig::new(Vec::new());
+        shared.halt_heights = vec![1000];
+        shared.audit = Some(AuditConfig {
+            path: dir.path().join("audit.jsonl"),
+            max_bytes: DEFAULT_MAX_BYTES,
+            max_files: DEFAULT_MAX_FILES,
+        });
+        let config = entry.operator_config(shared);
+        assert_eq!(config.targets, entry.targets);
+        assert_eq!(config.max_requests_per_hour, None);
+        assert!(config.audit.is_none());
+        assert_eq!(config.halt_heights, [1000]);
+        // The chain's halt heights replace those of the environment.
+        let mut upgraded = entry.clone();
+        upgraded.halt_heights = vec![2000, 3000];
+        let config = upgraded.operator_config(TendermintXConfig::new(Vec::new()));
+        assert_eq!(config.halt_heights, [2000, 3000]);
+
+        let mut invalid = entry.clone();
+        invalid.ethereum_rpc_urls.push(String::new());
+        invalid.ethereum_rpc_urls.push(String::new());
+        assert!(invalid.validate().is_err());
+
+        // A priority above the bound would make the chain's stride zero.
+        let mut priority = entry.clone();
+        priority.priority = MAX_PRIORITY;
+        priority.validate().unwrap();
+        priority.priority = MAX_PRIORITY + 1;
+        assert_eq!(
+            format!("{:#}", priority.validate().unwrap_err()),
+            "the priority of chain mocha-4 must be between 1 and 16"
+        );
+    }
+
+    /// A registry of the operators of the chains of `servers`, each trusting its block 100.
+    fn registry(
+        servers: &[(&str, &MockTendermintServer)],
+        backend: Arc<MockBackend>,
+    ) -> ChainRegistry<Provider<MockProvider>> {
+        registry_with(servers, backend, |_, _| {})
+    }
+
+    /// `registry`, passing each operator built to `built` first.
+    fn registry_with(
+        servers: &[(&str, &MockTendermintServer)],
+        backend: Arc<MockBackend>,
+        built: impl Fn(&ChainEntry, &mut TendermintXOperator<Provider<MockProvider>>)
+            + Send
+            + Sync
+            + 'static,
+    ) -> ChainRegistry<Provider<MockProvider>> {
+        let hashes: BTreeMap<String, HeaderHash> = servers
+            .iter()
+            .map(|(id, server)| (id.to_string(), header_hash(&server.chain().header(100))))
+            .collect();
+        let build: OperatorBuilder<Provider<MockProvider>> =
+            Box::new(move |entry: &ChainEntry, backend| {
+                let mut fetcher = InputDataFetcher::new(entry.rpc_urls.clone(), "");
+                fetcher.mode = InputDataMode::Rpc;
+                let config = entry.operator_config(TendermintXConfig::new(Vec::new()));
+                let (provider, _) = Provider::mocked();
+                let mut operator =
+                    TendermintXOperator::new(config, fetcher, backend, vec![Arc::new(provider)])?;
+                let trusted = Arc::new(LocalTrustedState::new(100));
+                trusted.insert(Height(100), hashes[&entry.chain_id])?;
+                operator.set_trusted_state(0, trusted)?;
+                built(entry, &mut operator);
+                Ok(operator)
+            });
+        let health = Health::new(Duration::from_secs(60), Duration::from_secs(5));
+        ChainRegistry::new(Box::new(backend), None, health, build)
+    }
+
+    #[tokio::test]
+    async fn test_chain_registry() {
+        let a = MockTendermintServer::start(SyntheticChain::new(1))
+            .await
+            .unwrap();
+        let b = MockTendermintServer::start(SyntheticChain::new(2))
+            .await
+            .unwrap();
+        let backend = Arc::new(MockBackend::new());
+        let dir = tempfile::tempdir().unwrap();
+        let audit_path = dir.path().join("audit.jsonl");
+        let audit = AuditLog::open(&audit_path, DEFAULT_MAX_BYTES, DEFAULT_MAX_FILES).unwrap();
+        let mut registry = registry(&[("chain-a", &a), ("chain-b", &b)], backend.clone())
+            .with_audit(Arc::new(audit));
+        registry.add(entry("chain-a", a.url(), 5)).unwrap();
+        assert!(registry.add(entry("chain-a", a.url(), 5)).is_err());
+
+        // A chain added over the control socket runs from the next iteration.
+        let add = ControlCommand::AddChain(Box::new(entry("chain-b", b.url(), 6)));
+        registry.control().apply(add).unwrap();
+        assert_eq!(registry.chain_ids(), ["chain-a"]);
+        let outcomes = registry.run_once().await;
+        assert_eq!(registry.chain_ids(), ["chain-a", "chain-b"]);
+        for (chain_id, outcome) in &outcomes {
+            assert!(outcome.as_ref().unwrap().any_submitted, "{}", chain_id);
+        }
+        let mut chains: Vec<_> = backend
+            .requests()
+            .iter()
+            .map(|r| (r.target.chain_id, r.trusted_block))
+            .collect();
+        chains.sort();
+        assert_eq!(chains, [(5, 100), (6, 100)]);
+        assert_eq!(registry.scheduler().submitted().values().sum::<u64>(), 2);
+        // Both chains append to the one audit log, in order.
+        let summary = audit::verify(&audit::log_files(&audit_path)).unwrap();
+        assert_eq!(summary.submissions, 2);
+
+        let metrics = registry.metrics().render(None).unwrap();
+        for chain_id in ["chain-a", "chain-b"] {
+            let sample = format!(
+                "tendermintx_chain_head_block{{source_chain=\"{}\"}}",
+                chain_id
+            );
+            assert!(
+                metrics.contains(&sample),
+                "missing {} in:\n{}",
+                sample,
+                metrics
+            );
+        }
+        let statuses = registry.collect_status().await;
+        assert_eq!(statuses.len(), 2);
+
+        // Removed, a chain stops running, and its metrics go.
+        let remove = ControlCommand::RemoveChain("chain-b".to_string());
+        registry.control().apply(remove).unwrap();
+        let outcomes = registry.run_once().await;
+        assert_eq!(outcomes.len(), 1);
+        assert_eq!(outcomes[0].0, "chain-a");
+        let metrics = registry.metrics().render(None).unwrap();
+        assert!(!metrics.contains("chain-b"), "{}", metrics);
+        assert_eq!(
+            registry.remove("chain-b").unwrap_err().to_string(),
+            "chain chain-b is not registered"
+        );
+        assert_eq!(registry.remove("chain-a").unwrap().chain_id, "chain-a");
+        assert!(registry.chain_ids().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_failed_chain_restarts() {
+        let a = MockTendermintServer::start(SyntheticChain::new(1))
+            .await
+            .unwrap();
+        let b = MockTendermintServer::start(SyntheticChain::new(2))
+            .await
+            .unwrap();
+        let backend = Arc::new(MockBackend::new());
+        // The first loop of chain-b panics reading the chain head, the second fails on a header
+        // mismatch, the third runs.
+        let builds = Arc::new(AtomicU32::new(0));
+        let built = {
+            let builds = builds.clone();
+            move |entry: &ChainEntry, operator: &mut TendermintXOperator<Provider<MockProvider>>| {
+                if entry.chain_id != "chain-b" {
+                    return;
+                }
+                let mut fetcher = InputDataFetcher::new(entry.rpc_urls.clone(), "");
+                fetcher.mode = InputDataMode::Rpc;
+                let schedule = match builds.fetch_add(1, Ordering::SeqCst) {
+                    0 => "latest_header 1 panic",
+                    _ => "",
+                };
+                let headers = FaultInjecting::new(fetcher, schedule.parse().unwrap());
+                operator.set_header_fetcher(Arc::new(headers));
+                if builds.load(Ordering::SeqCst) == 2 {
+                    let trusted = Arc::new(LocalTrustedState::new(100));
+                    trusted.insert(Height(100), HeaderHash::default()).unwrap();
+                    operator.set_trusted_state(0, trusted).unwrap();
+                }
+            }
+        };
+        let mut registry =
+            registry_with(&[("chain-a", &a), ("chain-b", &b)], backend.clone(), built)
+                .with_restart_policy(RetryPolicy {
+                    max_attempts: u32::MAX,
+                    initial_backoff: Duration::from_millis(10),
+                    max_backoff: Duration::from_secs(60),
+                });
+        registry.add(entry("chain-a", a.url(), 5)).unwrap();
+        registry.add(entry("chain-b", b.url(), 6)).unwrap();
+
+        // Chain-a submits however chain-b fails, and chain-b submits once restarted.
+        let submitted = async {
+            loop {
+                let mut chains: Vec<_> = backend
+                    .requests()
+                    .iter()
+                    .map(|r| r.target.chain_id)
+                    .collect();
+                chains.sort();
+                chains.dedup();
+                if chains == [5, 6] {
+                    return;
+                }
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::select! {
+            result = registry.run() => panic!("the registry stopped: {:?}", result),
+            submitted = tokio::time::timeout(Duration::from_secs(10), submitted) => {
+                submitted.unwrap()
+            }
+        }
+        assert_eq!(builds.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_restarts_are_bounded() {
+        let a = MockTendermintServer::start(SyntheticChain::new(1))
+            .await
+            .unwrap();
+        let backend = Arc::new(MockBackend::new());
+        // Every loop of chain-a fails on a header mismatch.
+        let builds = Arc::new(AtomicU32::new(0));
+        let built = {
+            let builds = builds.clone();
+            move |_: &ChainEntry, operator: &mut TendermintXOperator<Provider<MockProvider>>| {
+                builds.fetch_add(1, Ordering::SeqCst);
+                let trusted = Arc::new(LocalTrustedState::new(100));
+                trusted.insert(Height(100), HeaderHash::default()).unwrap();
+                operator.set_trusted_state(0, trusted).unwrap();
+            }
+        };
+        let mut registry =
+            registry_with(&[("chain-a", &a)], backend, built).with_restart_policy(RetryPolicy {
+                max_attempts: 3,
+                initial_backoff: Duration::from_millis(10),
+                max_backoff: Duration::from_secs(60),
+            });
+        registry.add(entry("chain-a", a.url(), 5)).unwrap();
+        let changes = registry.changes.clone();
+
+        // After the third failure in a row, the chain is stopped and unregistered.
+        let stopped = async {
+            while changes.contains("chain-a") {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        };
+        tokio::select! {
+            result = registry.run() => panic!("the registry stopped: {:?}", result),
+            stopped = tokio::time::timeout(Duration::from_secs(10), stopped) => stopped.unwrap(),
+        }
+        assert_eq!(builds.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,410 @@
+//! Finding where the state of a light client left the chain, and the way back.
+//!
+//! Once the consistency check trips, the contract stores a header that isn't the chain's. `scan`
+//! walks its stored blocks backwards from the latest one and compares each stored header with the
+//! chain's: the newest match is the consistent state, and the stored headers after it are the
+//! divergence. The stored blocks are those of the contract's `HeadUpdate` events if they are
+//! given, and are otherwise found by probing `blockHeightToHeaderHash` block by block. Either way
+//! the blocks are read `batch` at a time, concurrently, and no deeper than `max_blocks` below the
+//! latest block. A `RepairPlan` then lists the skips from the consistent state to the chain head:
+//! once that state is the contract's latest block again (with `setGenesisHeader`), the run loop
+//! proves them as it would after any outage.
+
+use std::fmt;
+
+use alloy_primitives::Bytes;
+use anyhow::{anyhow, Result};
+use futures::future::try_join_all;
+
+use crate::catchup;
+use crate::input::InputDataFetcher;
+use crate::migrate::{seed_calldata, ContractState};
+use crate::trusted::TrustedStateProvider;
+use crate::types::{HeaderHash, Height};
+
+/// The default number of blocks read at once.
+pub const DEFAULT_BATCH: u64 = 100;
+
+/// The default depth of a scan below the latest block, in blocks: about a week of 6 second blocks.
+pub const DEFAULT_MAX_BLOCKS: u64 = 100_000;
+
+/// How far a scan goes, and how many blocks it reads at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanBounds {
+    pub batch: u64,
+    pub max_blocks: u64,
+}
+
+impl Default for ScanBounds {
+    fn default() -> Self {
+        Self {
+            batch: DEFAULT_BATCH,
+            max_blocks: DEFAULT_MAX_BLOCKS,
+        }
+    }
+}
+
+/// A stored header that isn't the chain's.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Mismatch {
+    pub block: Height,
+    pub stored: HeaderHash,
+    pub chain: HeaderHash,
+}
+
+/// What a scan found.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Divergence {
+    /// The latest block of the light client, where the scan started.
+    pub latest: Height,
+    /// The newest stored block whose header is the chain's, `None` if there is none within the
+    /// bounds of the scan.
+    pub consistent: Option<ContractState>,
+    /// The stored headers newer than `consistent` that aren't the chain's, newest first.
+    pub mismatches: Vec<Mismatch>,
+    /// The number of blocks read.
+    pub scanned: u64,
+}
+
+impl Divergence {
+    /// The oldest stored block whose header isn't the chain's, if any.
+    pub fn diverged_at(&self) -> Option<Height> {
+        self.mismatches.last().map(|mismatch| mismatch.block)
+    }
+}
+
+/// Scan the stored headers of `trusted` backwards from its latest block for the newest that is
+/// the chain's, as `fetcher` reads it. `stored` lists the blocks stored by the light client, e.g.
+/// from its events, and every block within the bounds is probed without it. The latest block is
+/// read either way, as seeding a contract emits no event.
+pub async fn scan(
+    trusted: &dyn TrustedStateProvider,
+    fetcher: &InputDataFetcher,
+    stored: Option<Vec<Height>>,
+    bounds: ScanBounds,
+) -> Result<Divergence> {
+    let latest = trusted.latest_block().await?;
+    let deepest = latest
+        .value()
+        .saturating_sub(bounds.max_blocks.saturating_sub(1))
+        .max(1);
+    // The blocks to read, newest first.
+    let blocks: Vec<u64> = match stored {
+        Some(stored) => {
+            let mut blocks: Vec<u64> = stored
+                .into_iter()
+                .map(|block| block.value())
+                .filter(|block| (deepest..latest.value()).contains(block))
+                .collect();
+            blocks.push(latest.value());
+            blocks.sort_unstable_by(|a, b| b.cmp(a));
+            blocks.dedup();
+            blocks
+        }
+        None => (deepest..=latest.value()).rev().collect(),
+    };
+
+    let mut divergence = Divergence {
+        latest,
+        consistent: None,
+        mismatches: Vec::new(),
+        scanned: 0,
+    };
+    for batch in blocks.chunks(bounds.batch.max(1) as usize) {
+        let hashes =
+            try_join_all(batch.iter().map(|&block| trusted.hash_at(Height(block)))).await?;
+        divergence.scanned += batch.len() as u64;
+        let stored: Vec<(Height, HeaderHash)> = batch
+            .iter()
+            .zip(hashes)
+            .filter_map(|(&block, hash)| hash.map(|hash| (Height(block), hash)))
+            .collect();
+        let chain = try_join_all(
+            stored
+                .iter()
+                .map(|&(block, _)| fetcher.compute_header_hash(block)),
+        )
+        .await?;
+        for ((block, stored), chain) in stored.into_iter().zip(chain) {
+            if stored == chain {
+                divergence.consistent = Some(ContractState {
+                    block,
+                    header_hash: stored,
+                });
+                return Ok(divergence);
+            }
+            divergence.mismatches.push(Mismatch {
+                block,
+                stored,
+                chain,
+            });
+        }
+    }
+    Ok(divergence)
+}
+
+/// What a repair does: make the consistent state the latest block of the target again, and prove
+/// from there to the chain head.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RepairPlan {
+    /// The target, as it is displayed.
+    pub target: String,
+    pub divergence: Divergence,
+    /// The consistent state the repair starts from.
+    pub state: ContractState,
+    pub head: Height,
+    /// The skips from the consistent state to `head`, chunked by the target's `skip_max`: the
+    /// run loop may prove them in shorter ones, where the validator set requires it.
+    pub skips: Vec<(u64, u64)>,
+}
+
+impl RepairPlan {
+    /// The plan from what `divergence` found to `head`. Fails if the scan found no consistent
+    /// state.
+    pub fn new(
+        target: String,
+        divergence: Divergence,
+        head: Height,
+        skip_max: u64,
+    ) -> Result<Self> {
+        let state = divergence.consistent.ok_or_else(|| {
+            anyhow!(
+                "none of the {} blocks read below the latest block {} of {} stores the chain's \
+                 header: scan deeper, or seed the contract with a recent header of the chain",
+                divergence.scanned,
+                divergence.latest,
+                target
+            )
+        })?;
+        let skips = catchup::plan(state.block.value(), head.value(), skip_max);
+        Ok(Self {
+            target,
+            divergence,
+            state,
+            head,
+            skips,
+        })
+    }
+
+    /// Whether the latest block of the target must be set back to the consistent state.
+    pub fn needs_rewind(&self) -> bool {
+        self.divergence.latest != self.state.block
+    }
+
+    /// The calldata of the `setGenesisHeader` call setting the consistent state back.
+    pub fn rewind_calldata(&self) -> Bytes {
+        seed_calldata(&self.state)
+    }
+}
+
+impl fmt::Display for RepairPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let divergence = &self.divergence;
+        let Some(diverged_at) = divergence.diverged_at() else {
+            return writeln!(
+                f,
+                "{}: the latest block {} stores the chain's header, nothing to repair",
+                self.target, divergence.latest
+            );
+        };
+        writeln!(
+            f,
+            "{}: the stored headers diverge from the chain at block {}, {} of them up to the \
+             latest block {}:",
+            self.target,
+            diverged_at,
+            divergence.mismatches.len(),
+            divergence.latest
+        )?;
+        for mismatch in divergence.mismatches.iter() {
+            writeln!(
+                f,
+                "  block {}: stored {}, chain {}",
+                mismatch.block, mismatch.stored, mismatch.chain
+            )?;
+        }
+        writeln!(f, "Consistent state: {}", self.state)?;
+        writeln!(
+            f,
+            "Plan: set block {} back as the latest block, then prove {} skips to the chain head \
+             {}:",
+            self.state.block,
+            self.skips.len(),
+            self.head
+        )?;
+        for (trusted, target) in self.skips.iter() {
+            writeln!(f, "  {} -> {}", trusted, target)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use async_trait::async_trait;
+
+    use super::*;
+    use crate::input::header_hash;
+    use crate::testing::{MockTendermintServer, SyntheticChain};
+    use crate::trusted::LocalTrustedState;
+
+    /// A contract storing the chain's headers for blocks 100, 400 and 700, then a fork's for
+    /// blocks 800 and 900, and counting the headers read.
+    struct DivergedContract {
+        state: LocalTrustedState,
+        reads: AtomicU64,
+    }
+
+    impl DivergedContract {
+        fn new(server: &MockTendermintServer) -> Self {
+            let state = LocalTrustedState::new(300);
+            for block in [100, 400, 700] {
+                let hash = header_hash(&server.chain().header(block));
+                state.insert(Height(block), hash).unwrap();
+            }
+            for block in [800, 900] {
+                state.insert(Height(block), HeaderHash([0xf0; 32])).unwrap();
+            }
+            Self {
+                state,
+                reads: AtomicU64::new(0),
+            }
+        }
+
+        fn reads(&self) -> u64 {
+            self.reads.swap(0, Ordering::SeqCst)
+        }
+    }
+
+    #[async_trait]
+    impl TrustedStateProvider for DivergedContract {
+        async fn latest(&self) -> Result<(Height, HeaderHash)> {
+            self.state.latest().await
+        }
+
+        async fn hash_at(&self, height: Height) -> Result<Option<HeaderHash>> {
+            self.reads.fetch_add(1, Ordering::SeqCst);
+            self.state.hash_at(height).await
+        }
+
+        async fn skip_max(&self) -> Result<u64> {
+            self.state.skip_max().await
+        }
+    }
+
+    fn fork_mismatch(server: &MockTendermintServer, block: u64) -> Mismatch {
+        Mismatch {
+            block: Height(block),
+            stored: HeaderHash([0xf0; 32]),
+            chain: header_hash(&server.chain().header(block)),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_scan_finds_the_divergence() {
+        let server = MockTendermintServer::start(SyntheticChain::new(7))
+            .await
+            .unwrap();
+        let fetcher = server.fetcher();
+        let contract = DivergedContract::new(&server);
+
+        // Probing block by block, 50 at a time, down to block 700.
+        let bounds = ScanBounds {
+            batch: 50,
+            max_blocks: 1000,
+        };
+        let divergence = scan(&contract, &fetcher, None, bounds).await.unwrap();
+        assert_eq!(divergence.latest, Height(900));
+        assert_eq!(
+            divergence.consistent,
+            Some(ContractState {
+                block: Height(700),
+                header_hash: header_hash(&server.chain().header(700)),
+            })
+        );
+        assert_eq!(
+            divergence.mismatches,
+            [fork_mismatch(&server, 900), fork_mismatch(&server, 800)]
+        );
+        assert_eq!(divergence.diverged_at(), Some(Height(800)));
+        // The batch holding block 700 is read whole.
+        assert_eq!(divergence.scanned, 250);
+        assert_eq!(contract.reads(), 250);
+
+        // From the events, only the stored blocks and the latest one are read.
+        let stored = [100, 400, 700, 800].map(Height).to_vec();
+        let from_events = scan(&contract, &fetcher, Some(stored), bounds)
+            .await
+            .unwrap();
+        assert_eq!(from_events.consistent, divergence.consistent);
+        assert_eq!(from_events.mismatches, divergence.mismatches);
+        assert_eq!((from_events.scanned, contract.reads()), (5, 5));
+
+        // No deeper than the bounds.
+        let shallow = ScanBounds {
+            batch: 50,
+            max_blocks: 150,
+        };
+        let divergence = scan(&contract, &fetcher, None, shallow).await.unwrap();
+        assert_eq!(divergence.consistent, None);
+        assert_eq!(divergence.mismatches.len(), 2);
+        assert_eq!((divergence.scanned, contract.reads()), (150, 150));
+        let error =
+            RepairPlan::new("5:0x11".to_string(), divergence, Height(1000), 300).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "none of the 150 blocks read below the latest block 900 of 5:0x11 stores the \
+             chain's header: scan deeper, or seed the contract with a recent header of the chain"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_plan() {
+        let server = MockTendermintServer::start(SyntheticChain::new(7))
+            .await
+            .unwrap();
+        let fetcher = server.fetcher();
+        let contract = DivergedContract::new(&server);
+        let divergence = scan(&contract, &fetcher, None, ScanBounds::default())
+            .await
+            .unwrap();
+        let plan = RepairPlan::new("5:0x11".to_string(), divergence, Height(1000), 200).unwrap();
+        assert_eq!(plan.skips, [(700, 900), (900, 1000)]);
+        assert!(plan.needs_rewind());
+        assert_eq!(plan.rewind_calldata(), seed_calldata(&plan.state));
+
+        let (block_900, block_800) = (fork_mismatch(&server, 900), fork_mismatch(&server, 800));
+        assert_eq!(
+            plan.to_string(),
+            format!(
+                "5:0x11: the stored headers diverge from the chain at block 800, 2 of them up to \
+                 the latest block 900:\n  \
+                 block 900: stored {}, chain {}\n  \
+                 block 800: stored {}, chain {}\n\
+                 Consistent state: {}\n\
+                 Plan: set block 700 back as the latest block, then prove 2 skips to the chain \
+                 head 1000:\n  \
+                 700 -> 900\n  \
+                 900 -> 1000\n",
+                block_900.stored, block_900.chain, block_800.stored, block_800.chain, plan.state
+            )
+        );
+
+        // A consistent light client has nothing to repair.
+        let consistent = LocalTrustedState::new(200);
+        consistent
+            .insert(Height(700), header_hash(&server.chain().header(700)))
+            .unwrap();
+        let divergence = scan(&consistent, &fetcher, None, ScanBounds::default())
+            .await
+            .unwrap();
+        let plan = RepairPlan::new("5:0x11".to_string(), divergence, Height(1000), 200).unwrap();
+        assert!(!plan.needs_rewind());
+        assert_eq!(
+            plan.to_string(),
+            "5:0x11: the latest block 700 stores the chain's header, nothing to repair\n"
+        );
+    }
+}
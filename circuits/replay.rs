@@ -0,0 +1,221 @@
+//! Replaying a stored request: resubmitting exactly what it contained, e.g. after fixing a
+//! chain-side issue that made it fail.
+
+use anyhow::{anyhow, Result};
+use log::warn;
+
+use crate::backend::{ProofBackend, RequestKind};
+use crate::correlation::CorrelationId;
+use crate::export::RequestInputs;
+use crate::store::{NewRequest, RequestRecord, RequestStore};
+use crate::target::RequestTarget;
+use crate::types::{HeaderHash, Height};
+
+/// Resubmit the trusted header, blocks, function ID and input of `record` for `target`, and record
+/// the new request as a retry of the original one. The contract of `target` is at `latest_block`;
+/// if it already reached the target block the replay is pointless and only done with `force`.
+/// Returns the new request ID.
+pub async fn replay(
+    store: &RequestStore,
+    backend: &dyn ProofBackend,
+    target: &RequestTarget,
+    record: &RequestRecord,
+    latest_block: u64,
+    force: bool,
+) -> Result<String> {
+    if (target.chain_id, target.address) != (record.chain_id, record.contract_address) {
+        return Err(anyhow!(
+            "request {} is for {}:{}, not {}",
+            record.request_id,
+            record.chain_id,
+            record.contract_address,
+            target
+        ));
+    }
+    if latest_block >= record.target_block {
+        warn!(
+            "{} is already at block {}, past the target block {} of request {}",
+            target, latest_block, record.target_block, record.request_id
+        );
+        if !force {
+            return Err(anyhow!(
+                "replaying request {} is pointless, use --force to replay it anyway",
+                record.request_id
+            ));
+        }
+    }
+
+    let (trusted_block, trusted_hash, target_block) = (
+        Height(record.trusted_block),
+        HeaderHash(record.trusted_hash),
+        Height(record.target_block),
+    );
+    // The range of a data commitment would be replayed as a skip.
+    let mut inputs = if target.data_commitment_function_id == Some(record.function_id) {
+        RequestInputs::data_commitment(trusted_block, trusted_hash, target_block)?
+    } else {
+        RequestInputs::new(trusted_block, trusted_hash, target_block)?
+    };
+    match record.input.as_ref() {
+        Some(input) if *input != inputs.input => {
+            warn!(
+                "The stored input of request {} differs from the current encoding, replaying the \
+                 stored input",
+                record.request_id
+            );
+            inputs.input = input.clone();
+        }
+        Some(_) => {}
+        None => warn!(
+            "No input stored for request {}, replaying the current encoding",
+            record.request_id
+        ),
+    }
+    // A replay is an attempt of its own.
+    let correlation_id = CorrelationId::generate();
+    let mut request = inputs
+        .proof_request(target)
+        .with_correlation_id(correlation_id);
+    request.function_id = record.function_id;
+    let request_id = match inputs.kind {
+        RequestKind::Step => backend.request_step(&request).await?,
+        RequestKind::Skip => backend.request_skip(&request).await?,
+        RequestKind::DataCommitment => backend.request_data_commitment(&request).await?,
+    };
+
+    // Retries always link to the original request.
+    let original = record.retry_of.as_deref().unwrap_or(&record.request_id);
+    let attempt = store
+        .retry_history(original)?
+        .iter()
+        .map(|r| r.attempt)
+        .max()
+        .unwrap_or(record.attempt)
+        + 1;
+    store.insert(&NewRequest {
+        chain_id: record.chain_id,
+        contract_address: record.contract_address,
+        trusted_block: record.trusted_block,
+        trusted_hash: record.trusted_hash,
+        target_block: record.target_block,
+        function_id: record.function_id,
+        request_id: request_id.clone(),
+        retry_of: Some(original.to_string()),
+        attempt,
+        backend: Some(
+            backend
+                .served_by(&request_id)
+                .unwrap_or_else(|| backend.name().to_string()),
+        ),
+        request_mode: target.request_mode,
+        labels: target.labels.clone(),
+        input: inputs.input,
+        correlation_id: Some(correlation_id),
+    })?;
+    Ok(request_id)
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::{Address, B256};
+
+    use super::*;
+    use crate::backend::mock::MockBackend;
+    use crate::labels::Labels;
+    use crate::target::RequestMode;
+
+    fn target() -> RequestTarget {
+        RequestTarget {
+            skip_function_id: B256::repeat_byte(0x22),
+            ..RequestTarget::for_test(5)
+        }
+    }
+
+    fn seeded_store(dir: &tempfile::TempDir) -> RequestStore {
+        let store = RequestStore::open(dir.path().join("requests.db")).unwrap();
+        let inputs = RequestInputs::new(Height(100), HeaderHash([0xab; 32]), Height(500)).unwrap();
+        store
+            .insert(&NewRequest {
+                chain_id: 5,
+                contract_address: Address::repeat_byte(0x11),
+                trusted_block: 100,
+                trusted_hash: [0xab; 32],
+                target_block: 500,
+                function_id: B256::repeat_byte(0x22),
+                request_id: "req_abc123".to_string(),
+                retry_of: None,
+                attempt: 1,
+                backend: Some("mock".to_string()),
+                request_mode: RequestMode::Platform,
+                labels: Labels::new(),
+                input: inputs.input,
+                correlation_id: None,
+            })
+            .unwrap();
+        store
+    }
+
+    #[tokio::test]
+    async fn test_replay_fresh_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = seeded_store(&dir);
+        let backend = MockBackend::new();
+        let record = store.get("req_abc123").unwrap().unwrap();
+
+        let request_id = replay(&store, &backend, &target(), &record, 400, false)
+            .await
+            .unwrap();
+        let submitted = &backend.requests()[0];
+        assert_eq!(submitted.kind, RequestKind::Skip);
+        assert_eq!(
+            (submitted.trusted_block, submitted.target_block),
+            (100, 500)
+        );
+        assert_eq!(submitted.function_id, record.function_id);
+        assert_eq!(Some(&submitted.input), record.input.as_ref());
+
+        let replayed = store.get(&request_id).unwrap().unwrap();
+        assert_eq!(replayed.retry_of.as_deref(), Some("req_abc123"));
+        assert_eq!(replayed.attempt, 2);
+        assert_eq!(replayed.input, record.input);
+        // Under a correlation ID of its own.
+        assert!(replayed.correlation_id.is_some());
+        assert_ne!(replayed.correlation_id, record.correlation_id);
+
+        // A replay of the replay still links to the original request.
+        let request_id = replay(&store, &backend, &target(), &replayed, 400, false)
+            .await
+            .unwrap();
+        let replayed = store.get(&request_id).unwrap().unwrap();
+        assert_eq!(replayed.retry_of.as_deref(), Some("req_abc123"));
+        assert_eq!(replayed.attempt, 3);
+    }
+
+    #[tokio::test]
+    async fn test_replay_stale_target() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = seeded_store(&dir);
+        let backend = MockBackend::new();
+        let record = store.get("req_abc123").unwrap().unwrap();
+
+        assert!(replay(&store, &backend, &target(), &record, 500, false)
+            .await
+            .is_err());
+        assert!(backend.requests().is_empty());
+        assert_eq!(store.retry_history("req_abc123").unwrap().len(), 1);
+
+        replay(&store, &backend, &target(), &record, 500, true)
+            .await
+            .unwrap();
+        assert_eq!(backend.requests().len(), 1);
+        assert_eq!(store.retry_history("req_abc123").unwrap().len(), 2);
+
+        let other = RequestTarget {
+            chain_id: 6,
+            ..target()
+        };
+        assert!(replay(&store, &backend, &other, &record, 400, false)
+            .await
+            .is_err());
+    }
+}
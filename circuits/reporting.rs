@@ -0,0 +1,181 @@
+//! Error reporting to Sentry, so errors are aggregated rather than lost with the log files.
+//!
+//! Only reported with the `sentry` feature and `SENTRY_DSN` set; reporting does nothing
+//! otherwise. The alerts for errors (consistency mismatches, failing submissions and exhausted
+//! retries) are captured as events, tagged with the chain, contract and block heights from their
+//! details, and panics are captured by the panic hook. Transient errors that are retried (a failed
+//! RPC call, submission or proof attempt) are only recorded as breadcrumbs of the next event.
+
+use std::fmt;
+
+#[cfg(feature = "operator")]
+use crate::alert::{Alert, AlertKind};
+
+/// The alert details attached to events as tags, the rest being attached as extra data.
+pub const TAG_KEYS: [&str; 8] = [
+    "chain_id",
+    "address",
+    "target",
+    "block",
+    "latest_block",
+    "chain_head",
+    "trusted_block",
+    "target_block",
+];
+
+/// Whether alerts of `kind` are reported as Sentry events.
+#[cfg(feature = "operator")]
+pub fn is_reported(kind: AlertKind) -> bool {
+    matches!(
+        kind,
+        AlertKind::ConsistencyMismatch
+            | AlertKind::CircuitBreakerOpen
+            | AlertKind::RetriesExhausted
+    )
+}
+
+/// Keeps the Sentry client alive. Pending events are flushed when it is dropped.
+pub struct ReportGuard {
+    #[cfg(feature = "sentry")]
+    _guard: Option<sentry::ClientInitGuard>,
+}
+
+/// Initialize the Sentry client if `SENTRY_DSN` is set, installing its panic hook. Must be called
+/// after logging is initialized, and the guard held until the process exits.
+pub fn init() -> ReportGuard {
+    let dsn = std::env::var("SENTRY_DSN")
+        .ok()
+        .filter(|dsn| !dsn.is_empty());
+    #[cfg(feature = "sentry")]
+    {
+        let guard = dsn.map(|dsn| {
+            sentry::init((
+                dsn,
+                sentry::ClientOptions {
+                    release: sentry::release_name!(),
+                    environment: std::env::var("SENTRY_ENVIRONMENT").ok().map(Into::into),
+                    ..Default::default()
+                },
+            ))
+        });
+        ReportGuard { _guard: guard }
+    }
+    #[cfg(not(feature = "sentry"))]
+    {
+        if dsn.is_some() {
+            log::warn!("SENTRY_DSN is set, but this build has no sentry feature");
+        }
+        ReportGuard {}
+    }
+}
+
+/// The Sentry event for `alert`. Events of the same alert kind are grouped together.
+#[cfg(all(feature = "operator", feature = "sentry"))]
+pub fn alert_event(alert: &Alert) -> sentry::protocol::Event<'static> {
+    use std::borrow::Cow;
+
+    use sentry::protocol::{Event, Level, Value};
+
+    use crate::alert::Severity;
+
+    let level = match alert.kind.severity() {
+        Severity::Critical => Level::Fatal,
+        Severity::Warning => Level::Error,
+        Severity::Info => Level::Info,
+    };
+    let mut event = Event {
+        level,
+        message: Some(alert.summary.clone()),
+        logger: Some("tendermintx".to_string()),
+        fingerprint: Cow::Owned(vec![Cow::Owned(alert.kind.to_string())]),
+        ..Default::default()
+    };
+    event
+        .tags
+        .insert("alert".to_string(), alert.kind.to_string());
+    for (key, value) in alert.details.iter() {
+        if TAG_KEYS.contains(&key.as_str()) {
+            event.tags.insert(key.clone(), value.clone());
+        } else {
+            event.extra.insert(key.clone(), Value::from(value.clone()));
+        }
+    }
+    event
+}
+
+/// Capture `alert` as an event, if its kind is reported.
+#[cfg(feature = "operator")]
+pub fn capture_alert(alert: &Alert) {
+    #[cfg(feature = "sentry")]
+    if is_reported(alert.kind) {
+        sentry::capture_event(alert_event(alert));
+    }
+    #[cfg(not(feature = "sentry"))]
+    let _ = alert;
+}
+
+/// Record a transient error in `category` (e.g. `rpc`) as a breadcrumb.
+pub fn breadcrumb(category: &str, message: impl fmt::Display) {
+    #[cfg(feature = "sentry")]
+    sentry::add_breadcrumb(sentry::Breadcrumb {
+        category: Some(category.to_string()),
+        message: Some(message.to_string()),
+        level: sentry::Level::Warning,
+        ..Default::default()
+    });
+    #[cfg(not(feature = "sentry"))]
+    let _ = (category, message);
+}
+
+#[cfg(all(test, feature = "operator", feature = "sentry"))]
+mod tests {
+    use sentry::protocol::Level;
+
+    use super::*;
+
+    #[test]
+    fn test_alert_events() {
+        let mismatch = Alert::new(
+            AlertKind::ConsistencyMismatch,
+            "header mismatch on 5:0x1111111111111111111111111111111111111111",
+        )
+        .with_detail("chain_id", 5)
+        .with_detail("address", "0x1111111111111111111111111111111111111111")
+        .with_detail("block", 1000)
+        .with_detail("contract_header", "0xab");
+
+        let events = sentry::test::with_captured_events(|| {
+            breadcrumb("rpc", "Failed to query commit?height=1000, retrying");
+            // Startups are not errors.
+            capture_alert(&Alert::new(AlertKind::Startup, "operator started"));
+            capture_alert(&mismatch);
+        });
+        assert_eq!(events.len(), 1);
+        let event = &events[0];
+        assert_eq!(event.level, Level::Fatal);
+        assert_eq!(
+            event.message.as_deref(),
+            Some("header mismatch on 5:0x1111111111111111111111111111111111111111")
+        );
+        assert_eq!(event.fingerprint.as_ref(), ["consistency_mismatch"]);
+        let tags = event
+            .tags
+            .iter()
+            .map(|(k, v)| (k.as_str(), v.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            tags,
+            [
+                ("address", "0x1111111111111111111111111111111111111111"),
+                ("alert", "consistency_mismatch"),
+                ("block", "1000"),
+                ("chain_id", "5"),
+            ]
+        );
+        assert_eq!(event.extra["contract_header"], "0xab");
+        // The retried error before it is a breadcrumb of the event, not an event of its own.
+        let breadcrumbs = &event.breadcrumbs.values;
+        assert_eq!(breadcrumbs.len(), 1);
+        assert_eq!(breadcrumbs[0].category.as_deref(), Some("rpc"));
+    }
+}
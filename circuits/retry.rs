@@ -0,0 +1,291 @@
+//! Automatic retries of failed proof requests.
+//!
+//! A request the platform marks as failed (e.g. a flaky prover) is resubmitted with backoff until
+//! it is fulfilled or the attempts are exhausted. Every resubmission is a new platform request, so
+//! each attempt is returned to the caller for auditing.
+
+use std::future::Future;
+use std::time::Duration;
+
+use anyhow::Result;
+use log::{error, warn};
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DurationSeconds};
+
+use crate::platform::FulfillmentStatus;
+use crate::reporting;
+
+/// The default number of attempts for a request, including the original submission.
+pub const DEFAULT_MAX_ATTEMPTS: u32 = 3;
+
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetryPolicy {
+    /// The maximum number of attempts, including the original submission.
+    #[serde(rename = "max_attempts")]
+    pub max_attempts: u32,
+    /// The delay before the first retry. The delay doubles after every retry.
+    #[serde(rename = "initial_backoff_secs")]
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub initial_backoff: Duration,
+    #[serde(rename = "max_backoff_secs")]
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            initial_backoff: Duration::from_secs(60),
+            max_backoff: Duration::from_secs(15 * 60),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// The delay after the failure of `attempt` (starting at 1) before the next attempt.
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        std::cmp::min(
+            self.initial_backoff.saturating_mul(factor),
+            self.max_backoff,
+        )
+    }
+}
+
+/// A single attempt of a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Attempt {
+    /// The attempt number, starting at 1 for the original request.
+    pub attempt: u32,
+    /// The platform request ID, or `None` if the resubmission itself failed.
+    pub request_id: Option<String>,
+    /// The final status of the attempt, or `None` if it could not be determined.
+    pub status: Option<FulfillmentStatus>,
+}
+
+/// The outcome of a request and its retries.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RetryOutcome {
+    pub attempts: Vec<Attempt>,
+    /// Whether every attempt failed.
+    pub exhausted: bool,
+}
+
+impl RetryOutcome {
+    /// The status of the last attempt.
+    pub fn status(&self) -> Option<&FulfillmentStatus> {
+        self.attempts.last().and_then(|a| a.status.as_ref())
+    }
+}
+
+/// Wait for `request_id` to be fulfilled, resubmitting it with `resubmit` each time it fails.
+///
+/// `wait` follows a request to its final status and `resubmit` submits a new attempt (with fresh
+/// inputs) given its attempt number, returning the new request ID. Only failed requests are
+/// retried: a time out or an error while waiting ends the retries, as the request may still be
+/// fulfilled.
+pub async fn fulfill_with_retries<W, WFut, R, RFut>(
+    policy: &RetryPolicy,
+    request_id: String,
+    mut wait: W,
+    mut resubmit: R,
+) -> RetryOutcome
+where
+    W: FnMut(String) -> WFut,
+    WFut: Future<Output = Result<FulfillmentStatus>>,
+    R: FnMut(u32) -> RFut,
+    RFut: Future<Output = Result<String>>,
+{
+    let original_request_id = request_id.clone();
+    let mut attempts = Vec::new();
+    let mut attempt = 1;
+    let mut request_id = Some(request_id);
+    loop {
+        if let Some(id) = request_id.take() {
+            let status = match wait(id.clone()).await {
+                Ok(status) => status,
+                Err(e) => {
+                    error!("Failed to wait for request {}: {:#}", id, e);
+                    attempts.push(Attempt {
+                        attempt,
+                        request_id: Some(id),
+                        status: None,
+                    });
+                    return RetryOutcome {
+                        attempts,
+                        exhausted: false,
+                    };
+                }
+            };
+            let failed = matches!(status, FulfillmentStatus::Failed { .. });
+            attempts.push(Attempt {
+                attempt,
+                request_id: Some(id),
+                status: Some(status),
+            });
+            if !failed {
+                return RetryOutcome {
+                    attempts,
+                    exhausted: false,
+                };
+            }
+        }
+
+        if attempt >= policy.max_attempts {
+            error!(
+                "Request {} failed after {} attempts, giving up",
+                original_request_id, attempt
+            );
+            return RetryOutcome {
+                attempts,
+                exhausted: true,
+            };
+        }
+
+        let backoff = policy.backoff(attempt);
+        warn!(
+            "Attempt {} of request {} failed, retrying in {:?}",
+            attempt, original_request_id, backoff
+        );
+        let message = format!("attempt {} of {} failed", attempt, original_request_id);
+        reporting::breadcrumb("retry", message);
+        tokio::time::sleep(backoff).await;
+        attempt += 1;
+        match resubmit(attempt).await {
+            Ok(id) => request_id = Some(id),
+            Err(e) => {
+                error!(
+                    "Failed to resubmit request {} (attempt {}): {:#}",
+                    original_request_id, attempt, e
+                );
+                attempts.push(Attempt {
+                    attempt,
+                    request_id: None,
+                    status: None,
+                });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+
+    use anyhow::anyhow;
+
+    use super::*;
+
+    fn policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            initial_backoff: Duration::ZERO,
+            max_backoff: Duration::ZERO,
+        }
+    }
+
+    fn failed() -> FulfillmentStatus {
+        FulfillmentStatus::Failed {
+            error: Some("prover crashed".to_string()),
+        }
+    }
+
+    fn relayed() -> FulfillmentStatus {
+        FulfillmentStatus::Relayed {
+            proof_id: None,
+            tx_hash: Some("0xab".to_string()),
+        }
+    }
+
+    /// A mocked platform that returns the queued statuses in order and numbers resubmissions.
+    struct MockPlatform {
+        statuses: RefCell<VecDeque<FulfillmentStatus>>,
+        waited: RefCell<Vec<String>>,
+        resubmissions: RefCell<Vec<u32>>,
+    }
+
+    impl MockPlatform {
+        fn new(statuses: Vec<FulfillmentStatus>) -> Self {
+            Self {
+                statuses: RefCell::new(statuses.into()),
+                waited: RefCell::new(Vec::new()),
+                resubmissions: RefCell::new(Vec::new()),
+            }
+        }
+
+        async fn run(&self, policy: &RetryPolicy) -> RetryOutcome {
+            fulfill_with_retries(
+                policy,
+                "req".to_string(),
+                |id| {
+                    self.waited.borrow_mut().push(id);
+                    let status = self.statuses.borrow_mut().pop_front();
+                    async move { status.ok_or_else(|| anyhow!("no status")) }
+                },
+                |attempt| {
+                    self.resubmissions.borrow_mut().push(attempt);
+                    async move { Ok(format!("req_{}", attempt)) }
+                },
+            )
+            .await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_fail_then_succeed() {
+        let platform = MockPlatform::new(vec![failed(), failed(), relayed()]);
+        let outcome = platform.run(&policy(3)).await;
+
+        assert!(!outcome.exhausted);
+        assert_eq!(outcome.status(), Some(&relayed()));
+        assert_eq!(*platform.waited.borrow(), vec!["req", "req_2", "req_3"]);
+        assert_eq!(*platform.resubmissions.borrow(), vec![2, 3]);
+        assert_eq!(
+            outcome
+                .attempts
+                .iter()
+                .map(|a| (a.attempt, a.request_id.as_deref()))
+                .collect::<Vec<_>>(),
+            vec![(1, Some("req")), (2, Some("req_2")), (3, Some("req_3"))]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fail_exhausted() {
+        let platform = MockPlatform::new(vec![failed(), failed(), failed(), relayed()]);
+        let outcome = platform.run(&policy(3)).await;
+
+        assert!(outcome.exhausted);
+        assert_eq!(outcome.attempts.len(), 3);
+        assert_eq!(outcome.status(), Some(&failed()));
+        // No resubmission after the last attempt.
+        assert_eq!(*platform.resubmissions.borrow(), vec![2, 3]);
+    }
+
+    #[tokio::test]
+    async fn test_timeout_is_not_retried() {
+        let platform = MockPlatform::new(vec![FulfillmentStatus::TimedOut]);
+        let outcome = platform.run(&policy(3)).await;
+
+        assert!(!outcome.exhausted);
+        assert_eq!(outcome.status(), Some(&FulfillmentStatus::TimedOut));
+        assert!(platform.resubmissions.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            max_attempts: 10,
+            initial_backoff: Duration::from_secs(60),
+            max_backoff: Duration::from_secs(300),
+        };
+        assert_eq!(policy.backoff(1), Duration::from_secs(60));
+        assert_eq!(policy.backoff(2), Duration::from_secs(120));
+        assert_eq!(policy.backoff(3), Duration::from_secs(240));
+        assert_eq!(policy.backoff(4), Duration::from_secs(300));
+        assert_eq!(policy.backoff(40), Duration::from_secs(300));
+    }
+}
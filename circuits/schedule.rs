@@ -0,0 +1,262 @@
+//! When the run loop wakes up.
+//!
+//! Redundant operators for the same chain would otherwise wake up at the same time and race to
+//! submit the same requests. Each operator can delay its first iteration by a random startup
+//! jitter, vary every delay of the run loop by a percentage of it, and stagger its start by its
+//! index among the replicas, so that replicas `0..count` start `loop_delay / count` apart and
+//! interleave. The randomness is seeded, so a schedule can be replayed.
+//!
+//! Alternatively, iterations can be aligned to wall-clock times with a cron expression, in UTC.
+
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, Utc};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// A cron expression, with the fields of the cron crate (`sec min hour day-of-month month
+/// day-of-week [year]`) or the five usual ones (`min hour day-of-month month day-of-week`), which
+/// fire at second 0. Times are in UTC, so there are no DST transitions. Days of the week are best
+/// given by name (e.g. `MON`), as the cron crate numbers them from 1 for Sunday.
+#[derive(Debug, Clone)]
+pub struct Cron(cron::Schedule);
+
+impl FromStr for Cron {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let expression = match s.split_whitespace().count() {
+            5 => format!("0 {}", s.trim()),
+            _ => s.trim().to_string(),
+        };
+        let schedule = cron::Schedule::from_str(&expression)
+            .map_err(|e| anyhow!("invalid cron expression {:?}: {}", s, e))?;
+        Ok(Self(schedule))
+    }
+}
+
+impl Cron {
+    /// The first tick strictly after `now`, if there is one.
+    pub fn next_tick(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.0.after(&now).next()
+    }
+}
+
+/// The position of an operator among its replicas.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stagger {
+    pub index: u32,
+    pub count: u32,
+}
+
+impl Stagger {
+    /// The offset of this replica's first iteration: its share of the loop delay.
+    pub fn offset(&self, loop_delay: Duration) -> Duration {
+        if self.count == 0 {
+            return Duration::ZERO;
+        }
+        loop_delay.mul_f64(f64::from(self.index % self.count) / f64::from(self.count))
+    }
+}
+
+/// The largest jitter, in percent of the delay: a jittered delay is never under a tenth of it, so
+/// that the loop always waits.
+pub const MAX_JITTER_PERCENT: u32 = 90;
+
+/// `base` varied uniformly by up to `percent` of it, up or down. Percents over
+/// `MAX_JITTER_PERCENT` count as `MAX_JITTER_PERCENT`.
+pub fn jittered(base: Duration, percent: u32, rng: &mut impl Rng) -> Duration {
+    let spread = f64::from(percent.min(MAX_JITTER_PERCENT)) / 100.0;
+    if spread == 0.0 {
+        return base;
+    }
+    base.mul_f64(1.0 + rng.gen_range(-spread..=spread))
+}
+
+#[derive(Debug, Clone)]
+pub struct Schedule {
+    /// The maximum random delay before the first iteration.
+    pub startup_jitter: Duration,
+    /// The percentage of each delay of the run loop by which it varies, up or down.
+    pub jitter_percent: u32,
+    pub stagger: Option<Stagger>,
+    /// Aligns the iterations to its ticks instead of the loop delay, if set. Ticks aren't
+    /// jittered.
+    pub cron: Option<Cron>,
+    rng: StdRng,
+}
+
+impl Default for Schedule {
+    fn default() -> Self {
+        Self::new(StdRng::from_entropy())
+    }
+}
+
+impl Schedule {
+    fn new(rng: StdRng) -> Self {
+        Self {
+            startup_jitter: Duration::ZERO,
+            jitter_percent: 0,
+            stagger: None,
+            cron: None,
+            rng,
+        }
+    }
+
+    /// A schedule whose jitter is drawn from a generator seeded with `seed`.
+    pub fn with_seed(seed: u64) -> Self {
+        Self::new(StdRng::seed_from_u64(seed))
+    }
+
+    /// The delay before the first iteration: the stagger offset plus up to the startup jitter.
+    pub fn startup_delay(&mut self, loop_delay: Duration) -> Duration {
+        let offset = self
+            .stagger
+            .map_or(Duration::ZERO, |stagger| stagger.offset(loop_delay));
+        let jitter = if self.startup_jitter.is_zero() {
+            Duration::ZERO
+        } else {
+            self.startup_jitter.mul_f64(self.rng.gen_range(0.0..=1.0))
+        };
+        offset + jitter
+    }
+
+    /// The next cron tick after `now`, if iterations follow a cron schedule.
+    pub fn next_tick(&self, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.cron.as_ref()?.next_tick(now)
+    }
+
+    /// The jittered `delay` before the next iteration.
+    pub fn delay(&mut self, delay: Duration) -> Duration {
+        jittered(delay, self.jitter_percent, &mut self.rng)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_jittered() {
+        let base = Duration::from_secs(240 * 60);
+        let mut rng = StdRng::seed_from_u64(7);
+        let (mut below, mut above) = (false, false);
+        for _ in 0..1000 {
+            let delay = jittered(base, 10, &mut rng);
+            assert!(delay >= Duration::from_secs(216 * 60), "{:?}", delay);
+            assert!(delay <= Duration::from_secs(264 * 60), "{:?}", delay);
+            below |= delay < base;
+            above |= delay > base;
+        }
+        assert!(below && above);
+
+        assert_eq!(jittered(base, 0, &mut rng), base);
+        // At and past the cap, the delay never drops to nothing.
+        for percent in [MAX_JITTER_PERCENT, 100, 250] {
+            for _ in 0..1000 {
+                let delay = jittered(base, percent, &mut rng);
+                assert!(delay >= base.mul_f64(0.099), "{:?}", delay);
+                assert!(delay <= base.mul_f64(1.9), "{:?}", delay);
+            }
+        }
+    }
+
+    #[test]
+    fn test_seeded() {
+        let loop_delay = Duration::from_secs(3600);
+        let mut schedules = [Schedule::with_seed(42), Schedule::with_seed(42)];
+        for schedule in schedules.iter_mut() {
+            schedule.startup_jitter = Duration::from_secs(300);
+            schedule.jitter_percent = 20;
+        }
+        let [a, b] = &mut schedules;
+        let startup = a.startup_delay(loop_delay);
+        assert_eq!(startup, b.startup_delay(loop_delay));
+        assert!(startup <= Duration::from_secs(300));
+        for _ in 0..10 {
+            assert_eq!(a.delay(loop_delay), b.delay(loop_delay));
+        }
+        assert_ne!(
+            Schedule::with_seed(1).delay(loop_delay),
+            Schedule::with_seed(2).delay(loop_delay)
+        );
+    }
+
+    #[test]
+    fn test_cron() {
+        let at = |time: &str| time.parse::<DateTime<Utc>>().unwrap();
+        let next = |expression: &str, now: &str| {
+            let cron = expression.parse::<Cron>().unwrap();
+            cron.next_tick(at(now)).unwrap()
+        };
+        let now = "2026-10-14T13:27:10Z";
+        // Minute 0 of every 4th hour.
+        assert_eq!(next("0 */4 * * *", now), at("2026-10-14T16:00:00Z"));
+        // Strictly after a tick.
+        assert_eq!(
+            next("0 */4 * * *", "2026-10-14T16:00:00Z"),
+            at("2026-10-14T20:00:00Z")
+        );
+        assert_eq!(
+            next("0 */4 * * *", "2026-10-14T23:59:59Z"),
+            at("2026-10-15T00:00:00Z")
+        );
+        assert_eq!(next("30 0 1 * *", now), at("2026-11-01T00:30:00Z"));
+        assert_eq!(next("0 0 1 1 *", now), at("2027-01-01T00:00:00Z"));
+        // 2026-10-14 is a Wednesday.
+        assert_eq!(next("0 6 * * MON", now), at("2026-10-19T06:00:00Z"));
+        // No DST: 2026-03-29 and 2026-10-25 are like any other day in UTC.
+        assert_eq!(
+            next("0 2 * * *", "2026-03-29T01:00:00Z"),
+            at("2026-03-29T02:00:00Z")
+        );
+        assert_eq!(
+            next("0 2 * * *", "2026-10-25T02:00:00Z"),
+            at("2026-10-26T02:00:00Z")
+        );
+        // Six fields start with the seconds.
+        assert_eq!(next("*/30 * * * * *", now), at("2026-10-14T13:27:30Z"));
+
+        assert!("61 * * * *".parse::<Cron>().is_err());
+        assert!("every 4 hours".parse::<Cron>().is_err());
+
+        let mut schedule = Schedule::with_seed(1);
+        assert_eq!(schedule.next_tick(at(now)), None);
+        schedule.cron = Some("0 */4 * * *".parse().unwrap());
+        assert_eq!(
+            schedule.next_tick(at(now)),
+            Some(at("2026-10-14T16:00:00Z"))
+        );
+    }
+
+    #[test]
+    fn test_stagger() {
+        let loop_delay = Duration::from_secs(240 * 60);
+        let offsets = (0..4)
+            .map(|index| Stagger { index, count: 4 }.offset(loop_delay))
+            .collect::<Vec<_>>();
+        let minutes = |m: u64| Duration::from_secs(60 * m);
+        assert_eq!(
+            offsets,
+            [minutes(0), minutes(60), minutes(120), minutes(180)]
+        );
+        assert_eq!(
+            Stagger { index: 5, count: 4 }.offset(loop_delay),
+            minutes(60)
+        );
+        assert_eq!(
+            Stagger { index: 1, count: 0 }.offset(loop_delay),
+            Duration::ZERO
+        );
+
+        // Without startup jitter, a replica starts exactly at its offset.
+        let mut schedule = Schedule::with_seed(3);
+        schedule.stagger = Some(Stagger { index: 2, count: 4 });
+        assert_eq!(schedule.startup_delay(loop_delay), minutes(120));
+        schedule.startup_jitter = minutes(5);
+        let delay = schedule.startup_delay(loop_delay);
+        assert!(delay >= minutes(120) && delay <= minutes(125));
+    }
+}
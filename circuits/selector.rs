@@ -0,0 +1,1128 @@
+//! Selecting the block each request proves.
+//!
+//! From the contract's latest block, a request can prove any block up to the chain head or
+//! `skip_max` blocks ahead, whichever is closer, as long as enough of the trusted validator set
+//! signed it. Which of those blocks is worth a proof depends on the chain:
+//! - `LargestSkip` (the default) proves the furthest block it can, in as few requests as possible.
+//! - `FixedCadence` proves a block every fixed amount of chain time (e.g. once per 6 hours of
+//!   header time), whatever the block time, and waits in between.
+//! - `StableValidators` proves the furthest block whose validator set still shares a comfortable
+//!   margin of voting power with the trusted one, as skips across large validator rotations are
+//!   slower to prove and more likely to fail.
+//!
+//! Whatever the selector, a request never proves past a halt height the contract hasn't reached
+//! (see `halt_height_within`), as skips across a chain upgrade fail.
+
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use tendermint::account::Id as AccountId;
+
+use crate::input::power::total_voting_power;
+use crate::input::tendermint_utils::is_valid_skip;
+use crate::input::{header_hash, InputDataFetcher};
+use crate::types::{HeaderHash, Height};
+
+/// The voting power of each validator of a block, by address.
+pub type ValidatorPowers = HashMap<AccountId, u64>;
+
+/// The headers of the chain, as far as selecting a target and checking the contracts against it
+/// is concerned. The run loop reads the chain through one, so that its failures can be injected
+/// (see `FaultInjecting`).
+#[async_trait]
+pub trait HeaderFetcher: Send + Sync {
+    /// The latest block of the chain.
+    async fn chain_head(&self) -> Result<u64>;
+
+    /// The latest block of the chain and the time of its header, in unix seconds.
+    async fn latest_header(&self) -> Result<(u64, i64)> {
+        let head = self.chain_head().await?;
+        Ok((head, self.header_time(head).await?))
+    }
+
+    /// The time of the header of `block`, in unix seconds.
+    async fn header_time(&self, block: u64) -> Result<i64>;
+
+    /// The hash of the header of `block`, and its time in unix seconds.
+    async fn header_hash_and_time(&self, block: u64) -> Result<(HeaderHash, i64)>;
+
+    /// Whether `target_block` can be proved from `trusted_block` in a single skip.
+    async fn is_valid_skip(&self, trusted_block: u64, target_block: u64) -> Result<bool>;
+
+    /// The validators of `block` and their voting power.
+    async fn validator_powers(&self, block: u64) -> Result<ValidatorPowers>;
+}
+
+#[async_trait]
+impl HeaderFetcher for InputDataFetcher {
+    async fn chain_head(&self) -> Result<u64> {
+        Ok(self.latest_signed_header().await?.header.height.value())
+    }
+
+    async fn latest_header(&self) -> Result<(u64, i64)> {
+        let header = self.latest_signed_header().await?.header;
+        Ok((header.height.value(), header.time.unix_timestamp()))
+    }
+
+    async fn header_time(&self, block: u64) -> Result<i64> {
+        let header = self.get_signed_header_from_number(Height(block)).await?;
+        Ok(header.header.time.unix_timestamp())
+    }
+
+    async fn header_hash_and_time(&self, block: u64) -> Result<(HeaderHash, i64)> {
+        let header = self
+            .get_signed_header_from_number(Height(block))
+            .await?
+            .header;
+        Ok((header_hash(&header), header.time.unix_timestamp()))
+    }
+
+    async fn is_valid_skip(&self, trusted_block: u64, target_block: u64) -> Result<bool> {
+        let (trusted_block, target_block) = (Height(trusted_block), Height(target_block));
+        let trusted_validators = self.get_validator_set_from_number(trusted_block).await?;
+        let target_validators = self.get_validator_set_from_number(target_block).await?;
+        let target_commit = self.get_signed_header_from_number(target_block).await?;
+        Ok(is_valid_skip(
+            &trusted_validators,
+            &target_validators,
+            target_commit.commit,
+        ))
+    }
+
+    async fn validator_powers(&self, block: u64) -> Result<ValidatorPowers> {
+        let validators = self.get_validator_set_from_number(Height(block)).await?;
+        Ok(validators
+            .into_iter()
+            .map(|validator| (validator.address, validator.power.value()))
+            .collect())
+    }
+}
+
+/// The share of the voting power of the `target` validator set held by validators of the
+/// `trusted` one. A skip needs signatures from over 1/3 of it.
+pub fn validator_overlap(trusted: &ValidatorPowers, target: &ValidatorPowers) -> f64 {
+    let total = total_voting_power(target.values().copied());
+    if total == 0 {
+        return 0.0;
+    }
+    let shared = target
+        .iter()
+        .filter(|(address, _)| trusted.contains_key(address))
+        .map(|(_, power)| *power);
+    total_voting_power(shared) as f64 / total as f64
+}
+
+/// The block selected for a request, and why.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Selection {
+    /// The block to prove, or `None` if no request is due yet.
+    pub target: Option<u64>,
+    pub rationale: String,
+}
+
+impl Selection {
+    pub fn block(target: u64, rationale: impl Into<String>) -> Self {
+        Self {
+            target: Some(target),
+            rationale: rationale.into(),
+        }
+    }
+
+    pub fn none(rationale: impl Into<String>) -> Self {
+        Self {
+            target: None,
+            rationale: rationale.into(),
+        }
+    }
+}
+
+impl fmt::Display for Selection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.target {
+            Some(target) => write!(f, "Selected block {}: {}", target, self.rationale),
+            None => write!(f, "No block selected: {}", self.rationale),
+        }
+    }
+}
+
+/// The chain head as an iteration read it: its block and the time of its header, in unix seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChainHead {
+    pub block: u64,
+    pub time: i64,
+}
+
+/// How the block a request proves is selected.
+#[async_trait]
+pub trait TargetSelector: Send + Sync {
+    /// The block to prove from `current`, at most `max_end`, with the rationale of the decision.
+    /// The block is always after `current`, and `None` if `max_end` isn't. `head` is the chain
+    /// head the rest of the iteration works from, so that it isn't read again. Fails if the chain
+    /// can't be read.
+    async fn select(
+        &self,
+        current: u64,
+        max_end: u64,
+        head: ChainHead,
+        fetcher: &dyn HeaderFetcher,
+    ) -> Result<Selection>;
+}
+
+/// The furthest block a request from `current` can prove: the chain head less
+/// `confirmation_depth` blocks, and at most `skip_max` blocks ahead. `None` if that isn't after
+/// `current`, e.g. as the contract is at the chain head, or the chain head read is behind it.
+pub fn max_end_block(
+    current: u64,
+    chain_head: u64,
+    skip_max: u64,
+    confirmation_depth: u64,
+) -> Option<u64> {
+    let max_end = chain_head
+        .saturating_sub(confirmation_depth)
+        .min(current.saturating_add(skip_max));
+    (max_end > current).then_some(max_end)
+}
+
+/// The first of `halt_heights` after `current` and before `max_end`, if any: the furthest block a
+/// request from `current` may prove, so that the contract lands exactly on the halt height before
+/// proving past it. A halt height the contract is at is passed.
+pub fn halt_height_within(current: u64, max_end: u64, halt_heights: &BTreeSet<u64>) -> Option<u64> {
+    if max_end <= current {
+        return None;
+    }
+    halt_heights.range(current + 1..max_end).next().copied()
+}
+
+/// No block, if there's none after `current` up to `max_end`.
+fn no_block_after(current: u64, max_end: u64) -> Option<Selection> {
+    (max_end <= current).then(|| {
+        Selection::none(format!(
+            "no block after {} up to {} to prove",
+            current, max_end
+        ))
+    })
+}
+
+/// The furthest block up to `max_end` that can be proved from `current` in a single skip, halving
+/// the range until one can (down to the next block, which a step always proves).
+pub async fn largest_skip(fetcher: &dyn HeaderFetcher, current: u64, max_end: u64) -> Result<u64> {
+    let mut end = max_end;
+    while end > current + 1 && !fetcher.is_valid_skip(current, end).await? {
+        end = (current + end) / 2;
+    }
+    Ok(end)
+}
+
+/// Proves the furthest block it can.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LargestSkip;
+
+#[async_trait]
+impl TargetSelector for LargestSkip {
+    async fn select(
+        &self,
+        current: u64,
+        max_end: u64,
+        _head: ChainHead,
+        fetcher: &dyn HeaderFetcher,
+    ) -> Result<Selection> {
+        if let Some(selection) = no_block_after(current, max_end) {
+            return Ok(selection);
+        }
+        let target = largest_skip(fetcher, current, max_end).await?;
+        let rationale = if target == max_end {
+            format!("the furthest block from {}", current)
+        } else {
+            format!(
+                "the validator set changed too much from {} to prove {}",
+                current, max_end
+            )
+        };
+        Ok(Selection::block(target, rationale))
+    }
+}
+
+/// Proves at least every `cadence` of chain time: once the chain head's header is `cadence` or
+/// more after the header of the contract's latest block, the earliest block at least `cadence`
+/// after it, and nothing until then. Unlike a cadence in blocks, this holds on chains whose block
+/// time varies, or that produce no blocks while idle. When the block past the threshold is further
+/// than `skip_max` (or the validator set changed too much to reach it at once), the furthest block
+/// before it is proved instead, and the cadence starts again from there.
+#[derive(Debug, Clone, Copy)]
+pub struct FixedCadence {
+    pub cadence: Duration,
+}
+
+impl FixedCadence {
+    pub fn new(cadence: Duration) -> Self {
+        Self { cadence }
+    }
+}
+
+#[async_trait]
+impl TargetSelector for FixedCadence {
+    async fn select(
+        &self,
+        current: u64,
+        max_end: u64,
+        head: ChainHead,
+        fetcher: &dyn HeaderFetcher,
+    ) -> Result<Selection> {
+        if let Some(selection) = no_block_after(current, max_end) {
+            return Ok(selection);
+        }
+        let current_time = fetcher.header_time(current).await?;
+        let elapsed = head.time - current_time;
+        if elapsed < self.cadence.as_secs() as i64 {
+            return Ok(Selection::none(format!(
+                "the chain head is {}s of chain time after {}, under the cadence of {:?}",
+                elapsed, current, self.cadence
+            )));
+        }
+
+        let due = current_time + self.cadence.as_secs() as i64;
+        if fetcher.header_time(max_end).await? < due {
+            let target = largest_skip(fetcher, current, max_end).await?;
+            return Ok(Selection::block(
+                target,
+                format!(
+                    "the first block {:?} of chain time after {} is further than skip_max",
+                    self.cadence, current
+                ),
+            ));
+        }
+
+        // The earliest block whose header is at least `due`, in (current, max_end].
+        let (mut low, mut high) = (current + 1, max_end);
+        while low < high {
+            let mid = low + (high - low) / 2;
+            if fetcher.header_time(mid).await? >= due {
+                high = mid;
+            } else {
+                low = mid + 1;
+            }
+        }
+        let target = largest_skip(fetcher, current, high).await?;
+        let rationale = if target == high {
+            format!(
+                "the first block {:?} of chain time after {}, the chain head being {}s after it",
+                self.cadence, current, elapsed
+            )
+        } else {
+            format!(
+                "the validator set changed too much from {} to prove {}, the first \
+                 block {:?} of chain time after it",
+                current, high, self.cadence
+            )
+        };
+        Ok(Selection::block(target, rationale))
+    }
+}
+
+/// The default share of the voting power the validator sets must keep in common.
+pub const DEFAULT_MIN_OVERLAP: f64 = 2.0 / 3.0;
+
+/// The default number of heights sampled in the allowed range.
+pub const DEFAULT_SAMPLES: u64 = 8;
+
+/// Proves the furthest block whose validator set keeps at least `min_overlap` of its voting power
+/// in common with the validator set of the contract's latest block, trading a slightly shorter
+/// skip for one that is faster to prove and less likely to fail. `samples` evenly spaced heights
+/// of the allowed range are checked from the furthest, and the range between the furthest one
+/// over the margin and the next one is then bisected for the last block before the rotation.
+/// Validator sets are cached until the contract is past them, so that consecutive iterations
+/// mostly fetch the new blocks.
+#[derive(Debug)]
+pub struct StableValidators {
+    pub min_overlap: f64,
+    pub samples: u64,
+    cache: Mutex<HashMap<u64, Arc<ValidatorPowers>>>,
+}
+
+impl StableValidators {
+    pub fn new(min_overlap: f64) -> Self {
+        Self {
+            min_overlap,
+            samples: DEFAULT_SAMPLES,
+            cache: Mutex::new(HashMap::new()),
+        }
+    }
+
+    async fn powers(
+        &self,
+        fetcher: &dyn HeaderFetcher,
+        block: u64,
+    ) -> Result<Arc<ValidatorPowers>> {
+        if let Some(powers) = self.cache.lock().unwrap().get(&block) {
+            return Ok(powers.clone());
+        }
+        let powers = Arc::new(fetcher.validator_powers(block).await?);
+        self.cache.lock().unwrap().insert(block, powers.clone());
+        Ok(powers)
+    }
+
+    /// The overlap of the validator set of `block` with the `trusted` one.
+    async fn overlap(
+        &self,
+        fetcher: &dyn HeaderFetcher,
+        trusted: &ValidatorPowers,
+        block: u64,
+    ) -> Result<f64> {
+        Ok(validator_overlap(
+            trusted,
+            &self.powers(fetcher, block).await?,
+        ))
+    }
+}
+
+#[async_trait]
+impl TargetSelector for StableValidators {
+    async fn select(
+        &self,
+        current: u64,
+        max_end: u64,
+        _head: ChainHead,
+        fetcher: &dyn HeaderFetcher,
+    ) -> Result<Selection> {
+        if let Some(selection) = no_block_after(current, max_end) {
+            return Ok(selection);
+        }
+        self.cache
+            .lock()
+            .unwrap()
+            .retain(|&block, _| block >= current);
+        let trusted = self.powers(fetcher, current).await?;
+
+        // The furthest sample over the margin (or the current block), and the sample after it.
+        let range = max_end - current;
+        let samples = self.samples.clamp(1, range);
+        let (mut stable, mut share, mut rotated) = (current, 1.0, None);
+        for i in (1..=samples).rev() {
+            let block = current + range * i / samples;
+            let overlap = self.overlap(fetcher, &trusted, block).await?;
+            if overlap >= self.min_overlap {
+                (stable, share) = (block, overlap);
+                break;
+            }
+            rotated = Some(block);
+        }
+        // The last block over the margin before that sample.
+        if let Some(mut rotated) = rotated {
+            while rotated > stable + 1 {
+                let mid = stable + (rotated - stable) / 2;
+                let overlap = self.overlap(fetcher, &trusted, mid).await?;
+                if overlap >= self.min_overlap {
+                    (stable, share) = (mid, overlap);
+                } else {
+                    rotated = mid;
+                }
+            }
+        }
+
+        // Not even the next block is over the margin: a step always proves it.
+        let end = stable.max(current + 1);
+        let target = largest_skip(fetcher, current, end).await?;
+        let rationale = if target != end {
+            format!(
+                "the validator set changed too much from {} to prove {}",
+                current, end
+            )
+        } else if stable == current {
+            format!(
+                "the next validator set shares less than {:.0}% of the voting power with {}",
+                100.0 * self.min_overlap,
+                current
+            )
+        } else if target == max_end {
+            format!(
+                "the furthest block from {}, sharing {:.0}% of the voting power",
+                current,
+                100.0 * share
+            )
+        } else {
+            format!(
+                "the furthest block from {} sharing at least {:.0}% of the voting power ({:.0}%)",
+                current,
+                100.0 * self.min_overlap,
+                100.0 * share
+            )
+        };
+        Ok(Selection::block(target, rationale))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+
+    use super::*;
+    #[cfg(feature = "operator")]
+    use crate::backend::RequestKind;
+    use crate::testing::{InMemoryFetcher, MockTendermintServer, Scenario, SyntheticChain};
+
+    /// A chain at `head`, with the header times of `time` and the validator sets of `validators`,
+    /// which allows skips of at most `max_skip` blocks.
+    struct MockFetcher {
+        head: u64,
+        time: fn(u64) -> i64,
+        validators: fn(u64) -> ValidatorPowers,
+        max_skip: u64,
+        validity_checks: AtomicU64,
+        validator_fetches: AtomicU64,
+    }
+
+    /// 10 validators with 10% of the voting power each, from validator `first`.
+    /// The chain head of `fetcher`, as an iteration reads it.
+    async fn read_head(fetcher: &dyn HeaderFetcher) -> ChainHead {
+        let (block, time) = fetcher.latest_header().await.unwrap();
+        ChainHead { block, time }
+    }
+
+    fn validators(first: u8) -> ValidatorPowers {
+        (first..first + 10)
+            .map(|i| (AccountId::new([i; 20]), 10))
+            .collect()
+    }
+
+    impl MockFetcher {
+        /// A chain with a block every 6 seconds.
+        fn new(head: u64, max_skip: u64) -> Self {
+            Self::with_times(head, |block| 6 * block as i64, max_skip)
+        }
+
+        fn with_times(head: u64, time: fn(u64) -> i64, max_skip: u64) -> Self {
+            Self {
+                head,
+                time,
+                validators: |_| validators(0),
+                max_skip,
+                validity_checks: AtomicU64::new(0),
+                validator_fetches: AtomicU64::new(0),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl HeaderFetcher for MockFetcher {
+        async fn chain_head(&self) -> Result<u64> {
+            Ok(self.head)
+        }
+
+        async fn header_time(&self, block: u64) -> Result<i64> {
+            assert!(block <= self.head, "block {} is in the future", block);
+            Ok(1_700_000_000 + (self.time)(block))
+        }
+
+        async fn header_hash_and_time(&self, block: u64) -> Result<(HeaderHash, i64)> {
+            let mut hash = [0; 32];
+            hash[24..].copy_from_slice(&block.to_be_bytes());
+            Ok((HeaderHash(hash), self.header_time(block).await?))
+        }
+
+        async fn is_valid_skip(&self, trusted_block: u64, target_block: u64) -> Result<bool> {
+            self.validity_checks.fetch_add(1, Ordering::SeqCst);
+            Ok(target_block - trusted_block <= self.max_skip)
+        }
+
+        async fn validator_powers(&self, block: u64) -> Result<ValidatorPowers> {
+            assert!(block <= self.head, "block {} is in the future", block);
+            self.validator_fetches.fetch_add(1, Ordering::SeqCst);
+            Ok((self.validators)(block))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_largest_skip() {
+        let fetcher = MockFetcher::new(10_000, 10_000);
+        assert_eq!(
+            LargestSkip
+                .select(1000, 5000, read_head(&fetcher).await, &fetcher)
+                .await
+                .unwrap()
+                .target,
+            Some(5000)
+        );
+        assert_eq!(fetcher.validity_checks.load(Ordering::SeqCst), 1);
+
+        // The range is halved until the skip is valid.
+        let fetcher = MockFetcher::new(10_000, 1000);
+        assert_eq!(
+            LargestSkip
+                .select(1000, 5000, read_head(&fetcher).await, &fetcher)
+                .await
+                .unwrap()
+                .to_string(),
+            "Selected block 2000: the validator set changed too much from 1000 to prove 5000"
+        );
+        // A step is always valid.
+        let fetcher = MockFetcher::new(10_000, 0);
+        assert_eq!(
+            LargestSkip
+                .select(1000, 5000, read_head(&fetcher).await, &fetcher)
+                .await
+                .unwrap()
+                .target,
+            Some(1001)
+        );
+        assert_eq!(
+            LargestSkip
+                .select(1000, 1001, read_head(&fetcher).await, &fetcher)
+                .await
+                .unwrap()
+                .target,
+            Some(1001)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fixed_cadence() {
+        // 6 hours of chain time are 3600 blocks.
+        let cadence = FixedCadence::new(Duration::from_secs(6 * 60 * 60));
+
+        // Not due yet: the chain head is only 2 hours ahead.
+        let fetcher = MockFetcher::new(2200, 100_000);
+        let not_due = read_head(&fetcher).await;
+        assert_eq!(
+            cadence
+                .select(1000, 2200, not_due, &fetcher)
+                .await
+                .unwrap()
+                .target,
+            None
+        );
+
+        // Due: the first block 6 hours after the current one, not the chain head.
+        let fetcher = MockFetcher::new(10_000, 100_000);
+        assert_eq!(
+            cadence
+                .select(1000, 10_000, read_head(&fetcher).await, &fetcher)
+                .await
+                .unwrap()
+                .target,
+            Some(4600)
+        );
+        // The chain head the iteration read decides, even if the chain advanced since.
+        assert_eq!(
+            cadence
+                .select(1000, 2200, not_due, &fetcher)
+                .await
+                .unwrap()
+                .target,
+            None
+        );
+        // The block at the cadence is as good as a later one.
+        assert_eq!(
+            cadence
+                .select(1000, 4600, read_head(&fetcher).await, &fetcher)
+                .await
+                .unwrap()
+                .target,
+            Some(4600)
+        );
+
+        // skip_max is shorter than the cadence: the furthest block is proved.
+        assert_eq!(
+            cadence
+                .select(1000, 3000, read_head(&fetcher).await, &fetcher)
+                .await
+                .unwrap()
+                .target,
+            Some(3000)
+        );
+
+        // The validator set changed too much to reach the block at the cadence at once.
+        let fetcher = MockFetcher::new(10_000, 2000);
+        assert_eq!(
+            cadence
+                .select(1000, 10_000, read_head(&fetcher).await, &fetcher)
+                .await
+                .unwrap()
+                .target,
+            Some(2800)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chain_time() {
+        let cadence = FixedCadence::new(Duration::from_secs(6 * 60 * 60));
+
+        // 2 second blocks for 3 hours, then 12 second blocks: 6 hours after block 0 is block 6300,
+        // not the 3600th block.
+        let time = |block: u64| match block {
+            0..=5400 => 2 * block as i64,
+            _ => 10_800 + 12 * (block - 5400) as i64,
+        };
+        let fetcher = MockFetcher::with_times(10_000, time, 100_000);
+        assert_eq!(
+            cadence
+                .select(0, 10_000, read_head(&fetcher).await, &fetcher)
+                .await
+                .unwrap()
+                .target,
+            Some(6300)
+        );
+        let fetcher = MockFetcher::with_times(6299, time, 100_000);
+        assert_eq!(
+            cadence
+                .select(0, 6299, read_head(&fetcher).await, &fetcher)
+                .await
+                .unwrap()
+                .target,
+            None
+        );
+
+        // An idle chain only produces a block an hour after block 1000: 5 blocks later isn't
+        // enough, 10 blocks later the earliest block past 6 hours is proved.
+        let idle = |block: u64| match block {
+            0..=1000 => 6 * block as i64,
+            _ => 6000 + 3600 * (block - 1000) as i64,
+        };
+        let fetcher = MockFetcher::with_times(1005, idle, 100_000);
+        assert_eq!(
+            cadence
+                .select(1000, 1005, read_head(&fetcher).await, &fetcher)
+                .await
+                .unwrap()
+                .target,
+            None
+        );
+        let fetcher = MockFetcher::with_times(1010, idle, 100_000);
+        assert_eq!(
+            cadence
+                .select(1000, 1010, read_head(&fetcher).await, &fetcher)
+                .await
+                .unwrap()
+                .target,
+            Some(1006)
+        );
+        // A validator set that changed too much settles for a step.
+        let fetcher = MockFetcher::with_times(1010, idle, 0);
+        assert_eq!(
+            cadence
+                .select(1000, 1010, read_head(&fetcher).await, &fetcher)
+                .await
+                .unwrap()
+                .target,
+            Some(1001)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_skip_validity_on_mock_chain() {
+        // Half of the validators are replaced at block 300, and the other half at block 600.
+        let chain = SyntheticChain::new(7).with_churn(300, 2).with_churn(600, 2);
+        let server = MockTendermintServer::start(chain).await.unwrap();
+        let fetcher = server.fetcher();
+        assert_eq!(fetcher.chain_head().await.unwrap(), 1000);
+        assert_eq!(
+            fetcher.header_time(11).await.unwrap() - fetcher.header_time(1).await.unwrap(),
+            60
+        );
+
+        // A skip needs a third of the target's voting power from the trusted validators.
+        assert!(fetcher.is_valid_skip(100, 599).await.unwrap());
+        assert!(!fetcher.is_valid_skip(100, 600).await.unwrap());
+        assert!(fetcher.is_valid_skip(300, 1000).await.unwrap());
+        assert_eq!(
+            validator_overlap(
+                &fetcher.validator_powers(100).await.unwrap(),
+                &fetcher.validator_powers(300).await.unwrap()
+            ),
+            0.5
+        );
+
+        // The selectors read the same.
+        assert_eq!(
+            LargestSkip
+                .select(100, 1000, read_head(&fetcher).await, &fetcher)
+                .await
+                .unwrap()
+                .target,
+            Some(550)
+        );
+        assert_eq!(
+            StableValidators::new(0.9)
+                .select(100, 1000, read_head(&fetcher).await, &fetcher)
+                .await
+                .unwrap()
+                .target,
+            Some(299)
+        );
+
+        // Every validator is replaced at block 900 while the chain runs.
+        server.rotate_validators(900, 4);
+        assert!(!fetcher.is_valid_skip(600, 900).await.unwrap());
+        assert!(fetcher.is_valid_skip(600, 899).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_overlap_on_scenarios() {
+        // The share of the power kept from the trusted block, and whether a skip is valid.
+        let cases = [
+            (
+                Scenario::rotations(),
+                vec![
+                    (4999, 5000, 0.6, true),
+                    (4999, 7999, 0.6, true),
+                    (4999, 8000, 0.2, false),
+                    (5000, 10_000, 0.6, true),
+                ],
+            ),
+            (
+                Scenario::growth(),
+                vec![(4999, 7999, 1.0, true), (4999, 8000, 0.5, true)],
+            ),
+            (
+                Scenario::concentration(),
+                vec![
+                    (4999, 8999, 0.4, true),
+                    (4999, 9000, 40.0 / 130.0, false),
+                    (5000, 9000, 1.0, true),
+                ],
+            ),
+        ];
+        for (scenario, skips) in cases {
+            let fetcher = InMemoryFetcher::new(scenario.chain(7));
+            for (trusted, target, overlap, valid) in skips {
+                let trusted_powers = fetcher.validator_powers(trusted).await.unwrap();
+                let target_powers = fetcher.validator_powers(target).await.unwrap();
+                assert_eq!(
+                    validator_overlap(&trusted_powers, &target_powers),
+                    overlap,
+                    "{} to {}",
+                    trusted,
+                    target
+                );
+                assert_eq!(
+                    fetcher.is_valid_skip(trusted, target).await.unwrap(),
+                    valid,
+                    "{} to {}",
+                    trusted,
+                    target
+                );
+            }
+        }
+    }
+
+    /// The blocks `selector` proves one after the other from `current` to the chain head, with
+    /// skips of at most `skip_max` blocks.
+    async fn hops(
+        selector: &dyn TargetSelector,
+        fetcher: &InMemoryFetcher,
+        mut current: u64,
+        skip_max: u64,
+    ) -> Vec<u64> {
+        let head = read_head(fetcher).await;
+        let mut hops = Vec::new();
+        while let Some(max_end) = max_end_block(current, head.block, skip_max, 0) {
+            current = selector
+                .select(current, max_end, head, fetcher)
+                .await
+                .unwrap()
+                .target
+                .unwrap();
+            hops.push(current);
+        }
+        hops
+    }
+
+    #[tokio::test]
+    async fn test_hops_on_scenarios() {
+        let stable = StableValidators::new(DEFAULT_MIN_OVERLAP);
+
+        // Over both rotations at once, only 20% of the power is kept.
+        let fetcher = InMemoryFetcher::new(Scenario::rotations().chain(7));
+        assert_eq!(
+            hops(&LargestSkip, &fetcher, 1000, 10_000).await,
+            [5500, 10_000]
+        );
+        assert_eq!(
+            hops(&LargestSkip, &fetcher, 1000, 3000).await,
+            [4000, 7000, 10_000]
+        );
+        // Around each rotation, a skip to the block before it and a step.
+        assert_eq!(
+            hops(&stable, &fetcher, 1000, 10_000).await,
+            [4999, 5000, 7999, 8000, 10_000]
+        );
+
+        // Half of the power is kept across the growth: a valid skip, but under the margin.
+        let fetcher = InMemoryFetcher::new(Scenario::growth().chain(7));
+        assert_eq!(hops(&LargestSkip, &fetcher, 1000, 10_000).await, [10_000]);
+        assert_eq!(
+            hops(&stable, &fetcher, 1000, 10_000).await,
+            [7999, 8000, 10_000]
+        );
+
+        // The power gained at 9000 is enough to invalidate a skip from before the rotation.
+        let fetcher = InMemoryFetcher::new(Scenario::concentration().chain(7));
+        assert_eq!(
+            hops(&LargestSkip, &fetcher, 1000, 10_000).await,
+            [5500, 10_000]
+        );
+        assert_eq!(
+            hops(&stable, &fetcher, 1000, 10_000).await,
+            [4999, 5000, 10_000]
+        );
+    }
+
+    #[test]
+    fn test_max_end_block() {
+        assert_eq!(max_end_block(100, 1000, 500, 0), Some(600));
+        assert_eq!(max_end_block(100, 1000, 5000, 10), Some(990));
+        assert_eq!(max_end_block(100, 101, 1, 0), Some(101));
+        // At the chain head, behind it or not confirmed yet: nothing to request.
+        assert_eq!(max_end_block(1000, 1000, 500, 0), None);
+        assert_eq!(max_end_block(1000, 900, 500, 0), None);
+        assert_eq!(max_end_block(100, 102, 500, 2), None);
+        assert_eq!(max_end_block(100, 1000, 0, 0), None);
+        assert_eq!(
+            max_end_block(u64::MAX - 1, u64::MAX, u64::MAX, 0),
+            Some(u64::MAX)
+        );
+
+        for selector in selectors() {
+            let fetcher = MockFetcher::new(1000, 1000);
+            let runtime = tokio::runtime::Runtime::new().unwrap();
+            let head = runtime.block_on(read_head(&fetcher));
+            let selection = runtime
+                .block_on(selector.0.select(1000, 1000, head, &fetcher))
+                .unwrap();
+            assert_eq!(
+                selection.to_string(),
+                "No block selected: no block after 1000 up to 1000 to prove"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_halt_heights() {
+        let halt_heights = BTreeSet::from([3000, 8000]);
+        // Inside the window, the halt height bounds it.
+        assert_eq!(halt_height_within(1000, 5000, &halt_heights), Some(3000));
+        assert_eq!(halt_height_within(2999, 9000, &halt_heights), Some(3000));
+        // Outside of it: at its end, past it, or passed.
+        assert_eq!(halt_height_within(1000, 3000, &halt_heights), None);
+        assert_eq!(halt_height_within(1000, 2999, &halt_heights), None);
+        assert_eq!(halt_height_within(3000, 7000, &halt_heights), None);
+        assert_eq!(halt_height_within(5000, 5000, &halt_heights), None);
+        assert_eq!(halt_height_within(1000, 5000, &BTreeSet::new()), None);
+
+        // The contract lands exactly on each halt height, then skips on from it.
+        let hops = |fetcher: MockFetcher| async move {
+            let (mut current, mut hops) = (1000, Vec::new());
+            while let Some(max_end) = max_end_block(current, fetcher.head, 4000, 0) {
+                let max_end =
+                    halt_height_within(current, max_end, &halt_heights).unwrap_or(max_end);
+                let selection = LargestSkip
+                    .select(current, max_end, read_head(&fetcher).await, &fetcher)
+                    .await
+                    .unwrap();
+                current = selection.target.unwrap();
+                hops.push(current);
+            }
+            hops
+        };
+        assert_eq!(
+            hops(MockFetcher::new(10_000, 100_000)).await,
+            [3000, 7000, 8000, 10_000]
+        );
+        // A skip to the halt height that is too large gets closer to it first.
+        assert_eq!(
+            hops(MockFetcher::new(10_000, 1500)).await,
+            [2000, 3000, 4000, 5000, 6500, 8000, 9000, 10_000]
+        );
+        // A cadence isn't kept across a halt height.
+        let cadence = FixedCadence::new(Duration::from_secs(6 * 60 * 60));
+        let fetcher = MockFetcher::new(10_000, 100_000);
+        let max_end = halt_height_within(1000, 10_000, &halt_heights).unwrap();
+        assert_eq!(
+            cadence
+                .select(1000, max_end, read_head(&fetcher).await, &fetcher)
+                .await
+                .unwrap()
+                .target,
+            Some(3000)
+        );
+    }
+
+    /// The selectors checked against generated chains, and whether they always select a block.
+    fn selectors() -> Vec<(Box<dyn TargetSelector>, bool)> {
+        vec![
+            (Box::new(LargestSkip), true),
+            // 100 blocks.
+            (Box::new(FixedCadence::new(Duration::from_secs(600))), false),
+            (Box::new(StableValidators::new(DEFAULT_MIN_OVERLAP)), true),
+        ]
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(64))]
+
+        /// Whatever the chain, its validator churn and the contract's latest block, the selected
+        /// block is one a single request can prove, within skip_max and the chain head.
+        #[test]
+        fn test_selection_invariants(
+            seed in any::<u64>(),
+            validators in 1..=7usize,
+            churn in vec((1..=300u64, 0..=7usize), 0..4),
+            head in 1..=300u64,
+            current in 1..=320u64,
+            skip_max in 1..=400u64,
+            confirmation_depth in 0..=3u64,
+        ) {
+            let mut chain = SyntheticChain::new(seed)
+                .with_validators(validators)
+                .with_head(head);
+            for (height, replaced) in churn {
+                chain = chain.with_churn(height, replaced);
+            }
+            let fetcher = InMemoryFetcher::new(chain);
+            let runtime = tokio::runtime::Builder::new_current_thread().build().unwrap();
+
+            let bound = head.saturating_sub(confirmation_depth).min(current + skip_max);
+            let max_end = max_end_block(current, head, skip_max, confirmation_depth);
+            prop_assert_eq!(max_end, Some(bound).filter(|&bound| bound > current));
+
+            let chain_head = runtime.block_on(read_head(&fetcher));
+            for (selector, always_selects) in selectors() {
+                // Past the bound, the selectors are asked for the empty range.
+                let asked = max_end.unwrap_or(bound);
+                let selection = runtime
+                    .block_on(selector.select(current, asked, chain_head, &fetcher))
+                    .unwrap();
+                let Some(target) = selection.target else {
+                    prop_assert!(max_end.is_none() || !always_selects, "{}", selection);
+                    continue;
+                };
+                let max_end = max_end.unwrap();
+                prop_assert!(current < target && target <= max_end, "{}", selection);
+                prop_assert!(target - current <= skip_max);
+                prop_assert!(target <= head);
+                #[cfg(feature = "operator")]
+                prop_assert_eq!(
+                    RequestKind::for_range(current, target) == RequestKind::Step,
+                    target == current + 1
+                );
+                if target == current + 1 {
+                    continue;
+                }
+                // A skip needs signatures from a third of the target's voting power.
+                prop_assert!(runtime.block_on(fetcher.is_valid_skip(current, target)).unwrap());
+                let trusted = runtime.block_on(fetcher.validator_powers(current)).unwrap();
+                let signers = runtime.block_on(fetcher.validator_powers(target)).unwrap();
+                let shared: u64 = signers
+                    .iter()
+                    .filter(|(address, _)| trusted.contains_key(address))
+                    .map(|(_, power)| power)
+                    .sum();
+                prop_assert!(3 * shared >= signers.values().sum::<u64>(), "{}", selection);
+            }
+        }
+    }
+
+    #[test]
+    fn test_validator_overlap() {
+        assert_eq!(validator_overlap(&validators(0), &validators(0)), 1.0);
+        assert_eq!(validator_overlap(&validators(0), &validators(2)), 0.8);
+        assert_eq!(validator_overlap(&validators(0), &validators(10)), 0.0);
+        assert_eq!(
+            validator_overlap(&validators(0), &ValidatorPowers::new()),
+            0.0
+        );
+
+        // The share is of the target's voting power.
+        let mut target = validators(0);
+        target.insert(AccountId::new([20; 20]), 100);
+        assert_eq!(validator_overlap(&validators(0), &target), 0.5);
+
+        // The total of these overflows a u64.
+        let huge: ValidatorPowers = (0..3)
+            .map(|i| (AccountId::new([i; 20]), i64::MAX as u64))
+            .collect();
+        let mut trusted = huge.clone();
+        trusted.remove(&AccountId::new([2; 20]));
+        assert!((validator_overlap(&trusted, &huge) - 2.0 / 3.0).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_stable_validators() {
+        // 20% of the voting power rotates at block 3000.
+        let mut fetcher = MockFetcher::new(10_000, 100_000);
+        fetcher.validators = |block| validators(if block < 3000 { 0 } else { 2 });
+
+        // With a margin of 90%, the last block before the rotation.
+        let selector = StableValidators::new(0.9);
+        assert_eq!(
+            selector
+                .select(1000, 5000, read_head(&fetcher).await, &fetcher)
+                .await
+                .unwrap()
+                .target,
+            Some(2999)
+        );
+        let fetches = fetcher.validator_fetches.load(Ordering::SeqCst);
+        assert!(fetches <= 20, "{} validator sets fetched", fetches);
+        // The validator sets are cached.
+        assert_eq!(
+            selector
+                .select(1000, 5000, read_head(&fetcher).await, &fetcher)
+                .await
+                .unwrap()
+                .target,
+            Some(2999)
+        );
+        assert_eq!(fetcher.validator_fetches.load(Ordering::SeqCst), fetches);
+        // Past the rotation, the furthest block.
+        assert_eq!(
+            selector
+                .select(3000, 7000, read_head(&fetcher).await, &fetcher)
+                .await
+                .unwrap()
+                .target,
+            Some(7000)
+        );
+        assert_eq!(selector.cache.lock().unwrap().keys().min(), Some(&3000));
+
+        // 80% in common is over the default margin.
+        let selector = StableValidators::new(DEFAULT_MIN_OVERLAP);
+        assert_eq!(
+            selector
+                .select(1000, 5000, read_head(&fetcher).await, &fetcher)
+                .await
+                .unwrap()
+                .target,
+            Some(5000)
+        );
+
+        // The rotation is right after the trusted block: a step.
+        let selector = StableValidators::new(0.9);
+        assert_eq!(
+            selector
+                .select(2999, 5000, read_head(&fetcher).await, &fetcher)
+                .await
+                .unwrap()
+                .target,
+            Some(3000)
+        );
+
+        // The skip to the last block before the rotation is still too large.
+        let mut fetcher = MockFetcher::new(10_000, 1000);
+        fetcher.validators = |block| validators(if block < 3000 { 0 } else { 2 });
+        assert_eq!(
+            selector
+                .select(1000, 5000, read_head(&fetcher).await, &fetcher)
+                .await
+                .unwrap()
+                .target,
+            Some(1999)
+        );
+    }
+}
@@ -0,0 +1,457 @@
+//! The key the operator signs Ethereum transactions with.
+//!
+//! The operator is generic over its providers' [`Middleware`]: without a key it reads through the
+//! plain providers, and with one through a [`SignerMiddleware`] stacked on each of them, which
+//! reads exactly as the provider below it does.
+//!
+//! The key is either local (a private key or a keystore) or, with the `kms` feature, an AWS KMS
+//! key that never leaves KMS. Both sign through [`OperatorSigner`], so the relaying code is the
+//! same for either. The chain ID of the provider is set on the signer when the client is built,
+//! and transactions are signed for it as EIP-155 requires. `self_test` signs a message and
+//! recovers the signer's address from the signature, so that a key that can't sign for its
+//! address fails at startup instead of on the first transaction.
+
+use std::fmt;
+use std::path::PathBuf;
+
+use alloy_primitives::Address;
+use anyhow::{anyhow, ensure, Context, Result};
+use async_trait::async_trait;
+use ethers::middleware::SignerMiddleware;
+use ethers::providers::{Middleware, Provider};
+#[cfg(feature = "kms")]
+use ethers::signers::{AwsSigner, AwsSignerError};
+use ethers::signers::{LocalWallet, Signer, WalletError};
+use ethers::types::transaction::eip2718::TypedTransaction;
+use ethers::types::transaction::eip712::Eip712;
+use ethers::types::Signature;
+
+use crate::endpoint::FailoverHttp;
+
+/// A provider signing with the operator's key.
+pub type SignerClient<M = Provider<FailoverHttp>> = SignerMiddleware<M, OperatorSigner>;
+
+/// The message `self_test` signs.
+const SELF_TEST_MESSAGE: &[u8] = b"tendermintx signer self-test";
+
+/// Where the signing key comes from.
+#[derive(Clone, PartialEq, Eq)]
+pub enum SignerSource {
+    /// A private key, as hex.
+    PrivateKey(String),
+    /// An encrypted JSON keystore, and its password.
+    Keystore { path: PathBuf, password: String },
+    /// The ID or ARN of an AWS KMS key, in the region of the AWS environment.
+    #[cfg(feature = "kms")]
+    Kms { key_id: String },
+}
+
+impl fmt::Debug for SignerSource {
+    /// Without the key or password.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PrivateKey(_) => write!(f, "PrivateKey(..)"),
+            Self::Keystore { path, .. } => write!(f, "Keystore({})", path.display()),
+            #[cfg(feature = "kms")]
+            Self::Kms { key_id } => write!(f, "Kms({})", key_id),
+        }
+    }
+}
+
+impl SignerSource {
+    /// The signer of the key, for chain ID 1 until the client sets the provider's. Errors don't
+    /// include the key.
+    pub async fn signer(&self) -> Result<OperatorSigner> {
+        match self {
+            Self::PrivateKey(key) => {
+                let key = key.trim();
+                let wallet = key
+                    .strip_prefix("0x")
+                    .unwrap_or(key)
+                    .parse()
+                    .map_err(|_| anyhow!("invalid private key"))?;
+                Ok(OperatorSigner::Local(wallet))
+            }
+            Self::Keystore { path, password } => LocalWallet::decrypt_keystore(path, password)
+                .map(OperatorSigner::Local)
+                .with_context(|| format!("failed to decrypt the keystore {}", path.display())),
+            #[cfg(feature = "kms")]
+            Self::Kms { key_id } => {
+                let client = rusoto_kms::KmsClient::new(rusoto_core::Region::default());
+                kms_signer(client, key_id).await
+            }
+        }
+    }
+}
+
+/// The signer of the KMS key `key_id`, read with `client`.
+#[cfg(feature = "kms")]
+pub async fn kms_signer(client: rusoto_kms::KmsClient, key_id: &str) -> Result<OperatorSigner> {
+    AwsSigner::new(client, key_id, 1)
+        .await
+        .map(OperatorSigner::Kms)
+        .with_context(|| format!("failed to read the public key of the KMS key {}", key_id))
+}
+
+/// A local or KMS signer.
+#[derive(Debug, Clone)]
+pub enum OperatorSigner {
+    Local(LocalWallet),
+    #[cfg(feature = "kms")]
+    Kms(AwsSigner),
+}
+
+/// The error of the signer of an `OperatorSigner`.
+#[derive(Debug)]
+pub enum OperatorSignerError {
+    Local(WalletError),
+    #[cfg(feature = "kms")]
+    Kms(AwsSignerError),
+}
+
+impl fmt::Display for OperatorSignerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Local(e) => write!(f, "{}", e),
+            #[cfg(feature = "kms")]
+            Self::Kms(e) => write!(f, "KMS: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for OperatorSignerError {}
+
+#[async_trait]
+impl Signer for OperatorSigner {
+    type Error = OperatorSignerError;
+
+    async fn sign_message<S: Send + Sync + AsRef<[u8]>>(
+        &self,
+        message: S,
+    ) -> Result<Signature, Self::Error> {
+        match self {
+            Self::Local(wallet) => wallet
+                .sign_message(message)
+                .await
+                .map_err(OperatorSignerError::Local),
+            #[cfg(feature = "kms")]
+            Self::Kms(signer) => signer
+                .sign_message(message)
+                .await
+                .map_err(OperatorSignerError::Kms),
+        }
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature, Self::Error> {
+        match self {
+            Self::Local(wallet) => wallet
+                .sign_transaction(tx)
+                .await
+                .map_err(OperatorSignerError::Local),
+            #[cfg(feature = "kms")]
+            Self::Kms(signer) => signer
+                .sign_transaction(tx)
+                .await
+                .map_err(OperatorSignerError::Kms),
+        }
+    }
+
+    async fn sign_typed_data<T: Eip712 + Send + Sync>(
+        &self,
+        payload: &T,
+    ) -> Result<Signature, Self::Error> {
+        match self {
+            Self::Local(wallet) => wallet
+                .sign_typed_data(payload)
+                .await
+                .map_err(OperatorSignerError::Local),
+            #[cfg(feature = "kms")]
+            Self::Kms(signer) => signer
+                .sign_typed_data(payload)
+                .await
+                .map_err(OperatorSignerError::Kms),
+        }
+    }
+
+    fn address(&self) -> ethers::types::Address {
+        match self {
+            Self::Local(wallet) => wallet.address(),
+            #[cfg(feature = "kms")]
+            Self::Kms(signer) => signer.address(),
+        }
+    }
+
+    fn chain_id(&self) -> u64 {
+        match self {
+            Self::Local(wallet) => wallet.chain_id(),
+            #[cfg(feature = "kms")]
+            Self::Kms(signer) => signer.chain_id(),
+        }
+    }
+
+    fn with_chain_id<T: Into<u64>>(self, chain_id: T) -> Self {
+        match self {
+            Self::Local(wallet) => Self::Local(wallet.with_chain_id(chain_id)),
+            #[cfg(feature = "kms")]
+            Self::Kms(signer) => Self::Kms(signer.with_chain_id(chain_id)),
+        }
+    }
+}
+
+/// The address `signer` signs as.
+pub fn signer_address<S: Signer>(signer: &S) -> Address {
+    Address::from(signer.address().0)
+}
+
+/// Sign a fixed message with `signer` and check that the signature recovers to its address.
+pub async fn self_test<S: Signer>(signer: &S) -> Result<()> {
+    let signature = signer
+        .sign_message(SELF_TEST_MESSAGE)
+        .await
+        .map_err(|e| anyhow!("the signer failed to sign: {}", e))?;
+    let recovered = signature
+        .recover(SELF_TEST_MESSAGE)
+        .map_err(|e| anyhow!("the signature of the signer doesn't recover: {}", e))?;
+    ensure!(
+        recovered == signer.address(),
+        "the signature of the signer recovers to {}, not its address {}",
+        Address::from(recovered.0),
+        signer_address(signer)
+    );
+    Ok(())
+}
+
+/// `provider` signing with `signer`, for the chain `provider` is connected to.
+pub async fn signer_client<M: Middleware + 'static>(
+    provider: M,
+    signer: OperatorSigner,
+) -> Result<SignerClient<M>> {
+    SignerMiddleware::new_with_provider_chain(provider, signer)
+        .await
+        .map_err(|e| anyhow!("failed to read the chain ID of the signer: {}", e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use ethers::types::{Bytes, TransactionRequest, U256};
+
+    use super::*;
+    use crate::contract::TendermintXContract;
+
+    /// The first development account of anvil.
+    const KEY: &str = "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+    const ADDRESS: &str = "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266";
+
+    /// A transaction without a chain ID, signed for that of the signer.
+    fn transaction() -> TypedTransaction {
+        TransactionRequest::new()
+            .to(ethers::types::Address::repeat_byte(0x11))
+            .value(1)
+            .nonce(0)
+            .gas(21000)
+            .gas_price(1)
+            .into()
+    }
+
+    #[tokio::test]
+    async fn test_private_key() {
+        let signer = SignerSource::PrivateKey(KEY.to_string())
+            .signer()
+            .await
+            .unwrap();
+        assert_eq!(signer_address(&signer), ADDRESS.parse::<Address>().unwrap());
+        let unprefixed = SignerSource::PrivateKey(format!(" {} ", &KEY[2..]));
+        assert_eq!(
+            unprefixed.signer().await.unwrap().address(),
+            signer.address()
+        );
+
+        let error = SignerSource::PrivateKey("0x1234".to_string())
+            .signer()
+            .await
+            .unwrap_err();
+        assert_eq!(error.to_string(), "invalid private key");
+        assert_eq!(
+            format!("{:?}", SignerSource::PrivateKey(KEY.to_string())),
+            "PrivateKey(..)"
+        );
+        let keystore = SignerSource::Keystore {
+            path: PathBuf::from("/nonexistent/keystore.json"),
+            password: "hunter2".to_string(),
+        };
+        assert!(keystore.signer().await.is_err());
+        assert!(!format!("{:?}", keystore).contains("hunter2"));
+    }
+
+    #[tokio::test]
+    async fn test_self_test_and_chain_id() {
+        let signer = SignerSource::PrivateKey(KEY.to_string())
+            .signer()
+            .await
+            .unwrap()
+            .with_chain_id(5u64);
+        assert_eq!(signer.chain_id(), 5);
+        self_test(&signer).await.unwrap();
+
+        // EIP-155: the signature commits to the chain ID, v being 2 * 5 + 35 or 36.
+        let tx = transaction();
+        let signature = signer.sign_transaction(&tx).await.unwrap();
+        assert!(
+            signature.v == 45 || signature.v == 46,
+            "v = {}",
+            signature.v
+        );
+        let mut for_chain = tx.clone();
+        for_chain.set_chain_id(5);
+        assert_eq!(
+            signature.recover(for_chain.sighash()).unwrap(),
+            signer.address()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_signer_client_reads_through() {
+        let signer = SignerSource::PrivateKey(KEY.to_string())
+            .signer()
+            .await
+            .unwrap();
+        let (provider, mock) = Provider::mocked();
+
+        // Responses are popped in reverse order of pushing: the chain ID is read first.
+        let mut word = [0u8; 32];
+        word[24..].copy_from_slice(&10500u64.to_be_bytes());
+        mock.push::<Bytes, _>(Bytes::from(word.to_vec())).unwrap();
+        mock.push(U256::from(5)).unwrap();
+
+        let client = signer_client(provider, signer).await.unwrap();
+        assert_eq!(client.signer().chain_id(), 5);
+        assert_eq!(
+            signer_address(client.signer()),
+            ADDRESS.parse::<Address>().unwrap()
+        );
+
+        let contract = TendermintXContract::new(Address::ZERO, Arc::new(client));
+        assert_eq!(contract.latest_block().await.unwrap(), 10500);
+    }
+
+    #[cfg(feature = "kms")]
+    mod kms {
+        use alloy_primitives::hex;
+        use ethers::utils::hash_message;
+        use ethers_core::k256::ecdsa::signature::hazmat::PrehashSigner;
+        use ethers_core::k256::ecdsa::{Signature as KmsSignature, SigningKey};
+        use rusoto_core::Region;
+        use rusoto_kms::KmsClient;
+        use rusoto_mock::{
+            MockCredentialsProvider, MockRequestDispatcher, MultipleMockRequestDispatcher,
+        };
+        use subtle_encoding::base64;
+
+        use super::*;
+
+        const KEY_ID: &str =
+            "arn:aws:kms:us-east-1:111122223333:key/1234abcd-12ab-34cd-56ef-1234567890ab";
+
+        /// The key KMS holds: that of `super::KEY`.
+        fn kms_key() -> SigningKey {
+            SigningKey::from_slice(&hex::decode(KEY).unwrap()).unwrap()
+        }
+
+        fn base64(bytes: &[u8]) -> String {
+            String::from_utf8(base64::encode(bytes)).unwrap()
+        }
+
+        /// The answer of GetPublicKey: the DER SubjectPublicKeyInfo of a secp256k1 key.
+        fn public_key_response(key: &SigningKey) -> MockRequestDispatcher {
+            let mut der = hex::decode("3056301006072a8648ce3d020106052b8104000a034200").unwrap();
+            der.extend_from_slice(key.verifying_key().to_encoded_point(false).as_bytes());
+            let body = serde_json::json!({
+                "KeyId": KEY_ID,
+                "KeyUsage": "SIGN_VERIFY",
+                "PublicKey": base64(&der),
+                "SigningAlgorithms": ["ECDSA_SHA_256"],
+            });
+            MockRequestDispatcher::with_status(200).with_body(&body.to_string())
+        }
+
+        /// The answer of Sign for `digest`: the DER signature, without a recovery ID.
+        fn sign_response(key: &SigningKey, digest: [u8; 32]) -> MockRequestDispatcher {
+            let signature: KmsSignature = key.sign_prehash(&digest).unwrap();
+            let body = serde_json::json!({
+                "KeyId": KEY_ID,
+                "Signature": base64(signature.to_der().as_bytes()),
+                "SigningAlgorithm": "ECDSA_SHA_256",
+            });
+            MockRequestDispatcher::with_status(200).with_body(&body.to_string())
+        }
+
+        fn client(responses: Vec<MockRequestDispatcher>) -> KmsClient {
+            KmsClient::new_with(
+                MultipleMockRequestDispatcher::new(responses),
+                MockCredentialsProvider,
+                Region::UsEast1,
+            )
+        }
+
+        #[tokio::test]
+        async fn test_kms_signer() {
+            let key = kms_key();
+            let tx = transaction();
+            let mut for_chain = tx.clone();
+            for_chain.set_chain_id(5);
+            let client = client(vec![
+                public_key_response(&key),
+                sign_response(&key, hash_message(SELF_TEST_MESSAGE).0),
+                sign_response(&key, for_chain.sighash().0),
+            ]);
+
+            let signer = kms_signer(client, KEY_ID)
+                .await
+                .unwrap()
+                .with_chain_id(5u64);
+            assert_eq!(signer_address(&signer), ADDRESS.parse::<Address>().unwrap());
+            assert_eq!(signer.chain_id(), 5);
+            self_test(&signer).await.unwrap();
+
+            // The recovery ID KMS leaves out is recovered, and v commits to the chain ID.
+            let signature = signer.sign_transaction(&tx).await.unwrap();
+            assert!(
+                signature.v == 45 || signature.v == 46,
+                "v = {}",
+                signature.v
+            );
+            assert_eq!(
+                signature.recover(for_chain.sighash()).unwrap(),
+                signer.address()
+            );
+        }
+
+        #[tokio::test]
+        async fn test_kms_self_test_fails_without_sign_permission() {
+            let denied = serde_json::json!({
+                "__type": "AccessDeniedException",
+                "message": "not authorized to perform kms:Sign",
+            });
+            let client = client(vec![
+                public_key_response(&kms_key()),
+                MockRequestDispatcher::with_status(400).with_body(&denied.to_string()),
+            ]);
+            let signer = kms_signer(client, KEY_ID).await.unwrap();
+            let error = self_test(&signer).await.unwrap_err();
+            assert!(error
+                .to_string()
+                .starts_with("the signer failed to sign: KMS:"));
+            assert_eq!(
+                format!(
+                    "{:?}",
+                    SignerSource::Kms {
+                        key_id: KEY_ID.to_string()
+                    }
+                ),
+                format!("Kms({})", KEY_ID)
+            );
+        }
+    }
+}
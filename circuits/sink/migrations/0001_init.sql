@@ -0,0 +1,39 @@
+-- The requests of the request store, with their latest status.
+CREATE TABLE requests (
+    request_id TEXT PRIMARY KEY,
+    chain_id BIGINT NOT NULL,
+    contract_address TEXT NOT NULL,
+    trusted_block BIGINT NOT NULL,
+    trusted_hash TEXT NOT NULL,
+    target_block BIGINT NOT NULL,
+    function_id TEXT NOT NULL,
+    retry_of TEXT,
+    attempt INTEGER NOT NULL,
+    backend TEXT,
+    request_mode TEXT NOT NULL,
+    correlation_id TEXT,
+    status TEXT NOT NULL,
+    created_at BIGINT NOT NULL,
+    updated_at BIGINT NOT NULL
+);
+CREATE INDEX requests_target ON requests (chain_id, contract_address, target_block);
+
+-- When each request reached each of its statuses.
+CREATE TABLE request_transitions (
+    request_id TEXT NOT NULL,
+    status TEXT NOT NULL,
+    at BIGINT NOT NULL,
+    PRIMARY KEY (request_id, status)
+);
+
+-- The HeadUpdate events of the target contracts.
+CREATE TABLE head_updates (
+    chain_id BIGINT NOT NULL,
+    contract_address TEXT NOT NULL,
+    block_number BIGINT NOT NULL,
+    header_hash TEXT NOT NULL,
+    tx_hash TEXT NOT NULL,
+    eth_block_number BIGINT NOT NULL,
+    timestamp BIGINT NOT NULL,
+    PRIMARY KEY (chain_id, contract_address, block_number)
+);
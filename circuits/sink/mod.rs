@@ -0,0 +1,220 @@
+//! Mirroring the request store and the observed head updates to an external database.
+//!
+//! The request store hands every request it records, every status transition and every head
+//! update the operator observes to its `EventSink`, if it has one. `record` never blocks: events
+//! are queued on a bounded channel and written by a background task, e.g. `postgres::spawn`'s,
+//! which retries failing writes. Events that don't fit in the channel, or that the writer gives
+//! up on, are dropped and counted in `tendermintx_sink_dropped_writes_total`: the store remains
+//! the source of truth. `flush` waits for the writer to be done with the events queued so far,
+//! e.g. before a one-shot run exits.
+
+#[cfg(feature = "postgres")]
+pub mod postgres;
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use alloy_primitives::{Address, B256};
+use tokio::sync::mpsc;
+use tracing::warn;
+
+use crate::store::{NewRequest, RequestStatus};
+
+/// The default number of events queued for the writer.
+pub const DEFAULT_CAPACITY: usize = 1024;
+
+/// What is mirrored.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SinkEvent {
+    /// A request recorded as pending at unix timestamp `at`.
+    Request { request: Box<NewRequest>, at: u64 },
+    /// A request reaching `status` at unix timestamp `at`.
+    Transition {
+        request_id: String,
+        status: RequestStatus,
+        at: u64,
+    },
+    /// A `HeadUpdate` event of a target contract, emitted in an Ethereum block of unix timestamp
+    /// `timestamp`.
+    HeadUpdate {
+        chain_id: u32,
+        contract_address: Address,
+        block_number: u64,
+        header_hash: B256,
+        tx_hash: B256,
+        eth_block_number: u64,
+        timestamp: u64,
+    },
+}
+
+/// How often `flush` checks whether the writer is done.
+const FLUSH_POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The sending end of a bounded channel of events, and the counts of the events dropped and of
+/// those the writer isn't done with.
+#[derive(Debug, Clone)]
+pub struct EventSink {
+    name: &'static str,
+    sender: mpsc::Sender<SinkEvent>,
+    dropped: Arc<AtomicU64>,
+    pending: Arc<AtomicU64>,
+}
+
+impl EventSink {
+    /// A sink named `name` queuing up to `capacity` events, and the receiving end for its writer.
+    pub fn channel(name: &'static str, capacity: usize) -> (Self, EventReceiver) {
+        let (sender, receiver) = mpsc::channel(capacity);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let pending = Arc::new(AtomicU64::new(0));
+        let sink = Self {
+            name,
+            sender,
+            dropped: dropped.clone(),
+            pending: pending.clone(),
+        };
+        let receiver = EventReceiver {
+            receiver,
+            dropped,
+            pending,
+        };
+        (sink, receiver)
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Queue `event` for the writer, or drop it if the channel is full or the writer stopped.
+    pub fn record(&self, event: SinkEvent) {
+        // Counted before it is sent, so the writer is never done with more than was counted.
+        self.pending.fetch_add(1, Ordering::SeqCst);
+        if let Err(e) = self.sender.try_send(event) {
+            warn!("Dropped a write to the {} sink: {}", self.name, e);
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// The number of events dropped so far.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+
+    /// The number of events queued or being written.
+    pub fn pending(&self) -> u64 {
+        self.pending.load(Ordering::SeqCst)
+    }
+
+    /// Wait until the writer is done with every event recorded so far, written or dropped, for up
+    /// to `timeout`. Returns whether it was.
+    pub async fn flush(&self, timeout: Duration) -> bool {
+        let deadline = tokio::time::Instant::now() + timeout;
+        while self.pending() > 0 {
+            if tokio::time::Instant::now() >= deadline {
+                return false;
+            }
+            tokio::time::sleep(FLUSH_POLL_INTERVAL).await;
+        }
+        true
+    }
+}
+
+/// The receiving end of an `EventSink`, for its writer.
+#[derive(Debug)]
+pub struct EventReceiver {
+    receiver: mpsc::Receiver<SinkEvent>,
+    dropped: Arc<AtomicU64>,
+    pending: Arc<AtomicU64>,
+}
+
+impl EventReceiver {
+    /// The next event, once there is one. `None` once every `EventSink` is dropped.
+    pub async fn recv(&mut self) -> Option<SinkEvent> {
+        self.receiver.recv().await
+    }
+
+    /// The next event, if one is queued.
+    pub fn try_recv(&mut self) -> Option<SinkEvent> {
+        self.receiver.try_recv().ok()
+    }
+
+    /// Count an event the writer gave up on.
+    pub fn count_dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Mark the writer as done with an event it received, whether it wrote it or gave up on it.
+    pub fn done(&self) {
+        self.pending.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transition(request_id: &str) -> SinkEvent {
+        SinkEvent::Transition {
+            request_id: request_id.to_string(),
+            status: RequestStatus::Relayed,
+            at: 1000,
+        }
+    }
+
+    #[test]
+    fn test_record_drops_when_full() {
+        let (sink, mut receiver) = EventSink::channel("test", 2);
+        sink.record(transition("req_1"));
+        sink.record(transition("req_2"));
+        assert_eq!(sink.dropped(), 0);
+
+        // The writer is behind: nothing waits for it.
+        sink.record(transition("req_3"));
+        assert_eq!(sink.dropped(), 1);
+        assert_eq!(receiver.try_recv(), Some(transition("req_1")));
+        sink.record(transition("req_4"));
+        assert_eq!(receiver.try_recv(), Some(transition("req_2")));
+        assert_eq!(receiver.try_recv(), Some(transition("req_4")));
+        assert_eq!(receiver.try_recv(), None);
+
+        // Clones and the writer share the count.
+        let clone = sink.clone();
+        receiver.count_dropped();
+        assert_eq!(clone.dropped(), 2);
+
+        drop(receiver);
+        sink.record(transition("req_5"));
+        assert_eq!(clone.dropped(), 3);
+        // The dropped events aren't waited for.
+        assert_eq!(clone.pending(), 3);
+    }
+
+    #[tokio::test]
+    async fn test_flush() {
+        let (sink, mut receiver) = EventSink::channel("test", 16);
+        assert!(sink.flush(Duration::ZERO).await);
+        sink.record(transition("req_1"));
+        sink.record(transition("req_2"));
+        assert_eq!(sink.pending(), 2);
+
+        // The writer received an event, but isn't done with it.
+        let event = receiver.recv().await.unwrap();
+        assert_eq!(event, transition("req_1"));
+        assert!(!sink.flush(Duration::from_millis(30)).await);
+        receiver.done();
+        assert_eq!(sink.pending(), 1);
+
+        // A writer done with the rest while `flush` waits.
+        let writer = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(30)).await;
+            while let Some(_event) = receiver.recv().await {
+                receiver.done();
+            }
+        });
+        assert!(sink.flush(Duration::from_secs(5)).await);
+        assert_eq!(sink.pending(), 0);
+        drop(sink);
+        writer.await.unwrap();
+    }
+}
@@ -0,0 +1,328 @@
+//! The Postgres writer of an `EventSink`, for indexers that read the light client updates from
+//! their own database.
+//!
+//! Rows are upserted, so an event written twice, e.g. after a write that failed on its reply, is
+//! harmless: `requests` has a row per request with its latest status, `request_transitions` a row
+//! per request and status it reached, and `head_updates` a row per header stored by a target
+//! contract. The schema is created by the migrations embedded from `circuits/sink/migrations`,
+//! applied before the first write.
+
+use std::time::Duration;
+
+use alloy_primitives::B256;
+use anyhow::{Context, Result};
+use sqlx::migrate::Migrator;
+use sqlx::postgres::{PgPool, PgPoolOptions};
+use tracing::{error, warn};
+
+use super::{EventReceiver, EventSink, SinkEvent};
+use crate::retry::RetryPolicy;
+
+static MIGRATOR: Migrator = sqlx::migrate!("circuits/sink/migrations");
+
+/// How failing writes are retried before the event is dropped.
+pub fn default_retry_policy() -> RetryPolicy {
+    RetryPolicy {
+        max_attempts: 5,
+        initial_backoff: Duration::from_millis(500),
+        max_backoff: Duration::from_secs(10),
+    }
+}
+
+/// A sink writing to the database at `dsn` from a background task, which connects on the first
+/// write. Only the DSN is checked here. Must be called within a Tokio runtime.
+pub fn spawn(dsn: &str, capacity: usize) -> Result<EventSink> {
+    let pool = PgPoolOptions::new()
+        .max_connections(2)
+        .acquire_timeout(Duration::from_secs(5))
+        .connect_lazy(dsn)
+        .context("invalid POSTGRES_DSN")?;
+    Ok(spawn_with_pool(pool, capacity, default_retry_policy()))
+}
+
+/// A sink writing to `pool` from a background task.
+pub fn spawn_with_pool(pool: PgPool, capacity: usize, policy: RetryPolicy) -> EventSink {
+    let (sink, receiver) = EventSink::channel("postgres", capacity);
+    tokio::spawn(run(pool, receiver, policy));
+    sink
+}
+
+/// Write the events of `receiver` to `pool` in order, each with up to `policy.max_attempts`
+/// attempts, marking each done once written or dropped.
+async fn run(pool: PgPool, mut receiver: EventReceiver, policy: RetryPolicy) {
+    let mut migrated = false;
+    while let Some(event) = receiver.recv().await {
+        let mut attempt = 1;
+        loop {
+            let result = match migrated {
+                true => write(&pool, &event).await,
+                false => match MIGRATOR.run(&pool).await {
+                    Ok(()) => {
+                        migrated = true;
+                        write(&pool, &event).await
+                    }
+                    Err(e) => Err(e).context("failed to apply the migrations"),
+                },
+            };
+            match result {
+                Ok(()) => break,
+                Err(e) if attempt < policy.max_attempts => {
+                    warn!("Failed to write to Postgres (attempt {}): {:#}", attempt, e);
+                    tokio::time::sleep(policy.backoff(attempt)).await;
+                    attempt += 1;
+                }
+                Err(e) => {
+                    error!(
+                        "Dropped a write to Postgres after {} attempts: {:#}",
+                        attempt, e
+                    );
+                    receiver.count_dropped();
+                    break;
+                }
+            }
+        }
+        receiver.done();
+    }
+}
+
+/// Upsert the rows of `event`.
+pub async fn write(pool: &PgPool, event: &SinkEvent) -> Result<()> {
+    match event {
+        SinkEvent::Request { request, at } => {
+            let mut tx = pool.begin().await?;
+            sqlx::query(
+                "INSERT INTO requests (request_id, chain_id, contract_address, trusted_block, \
+                 trusted_hash, target_block, function_id, retry_of, attempt, backend, \
+                 request_mode, correlation_id, status, created_at, updated_at) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, 'pending', $13, $13) \
+                 ON CONFLICT (request_id) DO NOTHING",
+            )
+            .bind(&request.request_id)
+            .bind(request.chain_id as i64)
+            .bind(request.contract_address.to_string())
+            .bind(request.trusted_block as i64)
+            .bind(B256::from(request.trusted_hash).to_string())
+            .bind(request.target_block as i64)
+            .bind(request.function_id.to_string())
+            .bind(&request.retry_of)
+            .bind(request.attempt as i32)
+            .bind(&request.backend)
+            .bind(request.request_mode.as_str())
+            .bind(request.correlation_id.map(|id| id.to_string()))
+            .bind(*at as i64)
+            .execute(&mut *tx)
+            .await?;
+            insert_transition(&mut tx, &request.request_id, "pending", *at).await?;
+            tx.commit().await?;
+        }
+        SinkEvent::Transition {
+            request_id,
+            status,
+            at,
+        } => {
+            let mut tx = pool.begin().await?;
+            sqlx::query("UPDATE requests SET status = $2, updated_at = $3 WHERE request_id = $1")
+                .bind(request_id)
+                .bind(status.as_str())
+                .bind(*at as i64)
+                .execute(&mut *tx)
+                .await?;
+            insert_transition(&mut tx, request_id, status.as_str(), *at).await?;
+            tx.commit().await?;
+        }
+        SinkEvent::HeadUpdate {
+            chain_id,
+            contract_address,
+            block_number,
+            header_hash,
+            tx_hash,
+            eth_block_number,
+            timestamp,
+        } => {
+            sqlx::query(
+                "INSERT INTO head_updates (chain_id, contract_address, block_number, \
+                 header_hash, tx_hash, eth_block_number, timestamp) \
+                 VALUES ($1, $2, $3, $4, $5, $6, $7) \
+                 ON CONFLICT (chain_id, contract_address, block_number) DO UPDATE SET \
+                 header_hash = EXCLUDED.header_hash, tx_hash = EXCLUDED.tx_hash, \
+                 eth_block_number = EXCLUDED.eth_block_number, timestamp = EXCLUDED.timestamp",
+            )
+            .bind(*chain_id as i64)
+            .bind(contract_address.to_string())
+            .bind(*block_number as i64)
+            .bind(header_hash.to_string())
+            .bind(tx_hash.to_string())
+            .bind(*eth_block_number as i64)
+            .bind(*timestamp as i64)
+            .execute(pool)
+            .await?;
+        }
+    }
+    Ok(())
+}
+
+async fn insert_transition(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    request_id: &str,
+    status: &str,
+    at: u64,
+) -> Result<()> {
+    sqlx::query(
+        "INSERT INTO request_transitions (request_id, status, at) VALUES ($1, $2, $3) \
+         ON CONFLICT (request_id, status) DO UPDATE SET at = EXCLUDED.at",
+    )
+    .bind(request_id)
+    .bind(status)
+    .bind(at as i64)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::{Address, Bytes};
+    use sqlx::Row;
+
+    use super::*;
+    use crate::labels::Labels;
+    use crate::store::{NewRequest, RequestStatus};
+    use crate::target::RequestMode;
+
+    fn request() -> NewRequest {
+        NewRequest {
+            chain_id: 5,
+            contract_address: Address::repeat_byte(0x11),
+            trusted_block: 100,
+            trusted_hash: [0xaa; 32],
+            target_block: 200,
+            function_id: B256::repeat_byte(0x33),
+            request_id: "req_1".to_string(),
+            retry_of: None,
+            attempt: 1,
+            backend: Some("platform".to_string()),
+            request_mode: RequestMode::Platform,
+            labels: Labels::new(),
+            input: Bytes::new(),
+            correlation_id: None,
+        }
+    }
+
+    fn head_update(tx_hash: u8) -> SinkEvent {
+        SinkEvent::HeadUpdate {
+            chain_id: 5,
+            contract_address: Address::repeat_byte(0x11),
+            block_number: 200,
+            header_hash: B256::repeat_byte(0xbb),
+            tx_hash: B256::repeat_byte(tx_hash),
+            eth_block_number: 18_000_000,
+            timestamp: 2000,
+        }
+    }
+
+    #[sqlx::test(migrations = "circuits/sink/migrations")]
+    #[ignore = "needs Postgres at DATABASE_URL"]
+    async fn test_write(pool: PgPool) {
+        let request = SinkEvent::Request {
+            request: Box::new(request()),
+            at: 1000,
+        };
+        write(&pool, &request).await.unwrap();
+        // Written twice, e.g. after a retry, an event upserts the same rows.
+        write(&pool, &request).await.unwrap();
+        let relayed = SinkEvent::Transition {
+            request_id: "req_1".to_string(),
+            status: RequestStatus::Relayed,
+            at: 1500,
+        };
+        write(&pool, &relayed).await.unwrap();
+        write(&pool, &head_update(0xcc)).await.unwrap();
+        write(&pool, &head_update(0xdd)).await.unwrap();
+
+        let row = sqlx::query("SELECT status, created_at, updated_at FROM requests")
+            .fetch_one(&pool)
+            .await
+            .unwrap();
+        assert_eq!(row.get::<String, _>("status"), "relayed");
+        assert_eq!(row.get::<i64, _>("created_at"), 1000);
+        assert_eq!(row.get::<i64, _>("updated_at"), 1500);
+        let transitions: Vec<(String, i64)> =
+            sqlx::query_as("SELECT status, at FROM request_transitions ORDER BY at")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(
+            transitions,
+            [("pending".to_string(), 1000), ("relayed".to_string(), 1500)]
+        );
+        let heads: Vec<(i64, String)> =
+            sqlx::query_as("SELECT block_number, tx_hash FROM head_updates")
+                .fetch_all(&pool)
+                .await
+                .unwrap();
+        assert_eq!(heads, [(200, B256::repeat_byte(0xdd).to_string())]);
+    }
+
+    #[sqlx::test(migrations = "circuits/sink/migrations")]
+    #[ignore = "needs Postgres at DATABASE_URL"]
+    async fn test_background_writer(pool: PgPool) {
+        let sink = spawn_with_pool(pool.clone(), 16, default_retry_policy());
+        sink.record(SinkEvent::Request {
+            request: Box::new(request()),
+            at: 1000,
+        });
+        sink.record(head_update(0xcc));
+
+        let mut count = 0;
+        for _ in 0..50 {
+            count = sqlx::query_scalar::<_, i64>("SELECT COUNT(*) FROM head_updates")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+            if count == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(count, 1);
+        assert_eq!(sink.dropped(), 0);
+        assert!(sink.flush(Duration::from_secs(5)).await);
+
+        // A write that keeps failing is dropped and counted, and the writer goes on.
+        sqlx::query("DROP TABLE head_updates")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let policy = RetryPolicy {
+            max_attempts: 2,
+            initial_backoff: Duration::from_millis(10),
+            max_backoff: Duration::from_millis(10),
+        };
+        let sink = spawn_with_pool(pool.clone(), 16, policy);
+        sink.record(head_update(0xdd));
+        sink.record(SinkEvent::Transition {
+            request_id: "req_1".to_string(),
+            status: RequestStatus::Failed,
+            at: 1500,
+        });
+        for _ in 0..50 {
+            if sink.dropped() == 1 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(sink.dropped(), 1);
+        let mut status = String::new();
+        for _ in 0..50 {
+            status = sqlx::query_scalar("SELECT status FROM requests")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+            if status == "failed" {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(100)).await;
+        }
+        assert_eq!(status, "failed");
+    }
+}
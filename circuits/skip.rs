@@ -12,6 +12,7 @@ use serde::{Deserialize, Serialize};
 use crate::builder::verify::TendermintVerify;
 use crate::config::TendermintConfig;
 use crate::input::InputDataFetcher;
+use crate::types::Height;
 use crate::variables::*;
 
 pub trait TendermintSkipCircuit<L: PlonkParameters<D>, const D: usize> {
@@ -76,11 +77,13 @@ impl<const MAX_VALIDATOR_SET_SIZE: usize, L: PlonkParameters<D>, const D: usize>
         let mut data_fetcher = InputDataFetcher::default();
         let result = data_fetcher
             .get_skip_inputs::<MAX_VALIDATOR_SET_SIZE, L::Field>(
-                trusted_block,
-                trusted_header_hash,
-                target_block,
+                Height(trusted_block),
+                trusted_header_hash.into(),
+                Height(target_block),
             )
-            .await;
+            .await
+            // A hint can't fail: the proof can't be generated without its inputs.
+            .unwrap_or_else(|e| panic!("failed to fetch the skip inputs: {:#}", e));
 
         let verify_skip_struct = VerifySkipStruct::<MAX_VALIDATOR_SET_SIZE, L::Field> {
             target_header: result.target_header.into(),
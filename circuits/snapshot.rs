@@ -0,0 +1,370 @@
+//! Snapshots of a range of a real chain, recorded from a Tendermint RPC for the tests that can't
+//! query one.
+//!
+//! A snapshot holds every response input generation reads for a step from its trusted block and a
+//! skip from there to its target block: the commits and validator sets of the trusted block, the
+//! block after it and the target block. They are recorded through the fetcher's save mode, in the
+//! layout of `fixtures/mocha-4`, so that a fetcher in fixture mode reads a snapshot directory as
+//! it is. Each response is then normalized (its JSON-RPC ID reset and its keys sorted), so that
+//! recording the same range twice gives the same files.
+//!
+//! `manifest.json` describes the snapshot. Its `version` changes with the layout or the contents
+//! of the files, and `SnapshotManifest::load` migrates the snapshots of older versions.
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tendermint::block::CommitSig;
+
+use crate::input::{header_hash, InputDataFetcher, InputDataMode};
+use crate::types::{HeaderHash, Height};
+
+/// The version of the snapshots recorded by this crate.
+pub const SNAPSHOT_VERSION: u64 = 1;
+
+/// The manifest of a snapshot, in its directory.
+pub const MANIFEST_FILE: &str = "manifest.json";
+
+/// The validators per page of a `/validators` response, as the fetcher queries them.
+const VALIDATORS_PER_PAGE: usize = 100;
+
+/// What a snapshot holds, and where.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotManifest {
+    #[serde(rename = "version")]
+    pub version: u64,
+    #[serde(rename = "chain_id")]
+    pub chain_id: String,
+    #[serde(rename = "trusted_block")]
+    pub trusted_block: Height,
+    #[serde(rename = "target_block")]
+    pub target_block: Height,
+    #[serde(rename = "blocks")]
+    pub blocks: Vec<SnapshotBlock>,
+}
+
+/// A recorded block, with what tests look for in the data: large validator sets, commits in a
+/// later round, absent validators.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SnapshotBlock {
+    #[serde(rename = "height")]
+    pub height: Height,
+    #[serde(rename = "header_hash")]
+    pub header_hash: HeaderHash,
+    /// The round of its commit.
+    #[serde(rename = "round")]
+    pub round: u32,
+    #[serde(rename = "validators")]
+    pub validators: usize,
+    /// The validators that didn't sign its commit.
+    #[serde(rename = "absent")]
+    pub absent: usize,
+    /// Its responses, relative to the snapshot directory.
+    #[serde(rename = "files")]
+    pub files: Vec<String>,
+}
+
+impl SnapshotManifest {
+    /// The blocks a snapshot from `trusted_block` to `target_block` records.
+    pub fn heights(trusted_block: Height, target_block: Height) -> Vec<Height> {
+        let mut heights = vec![trusted_block, trusted_block.next(), target_block];
+        heights.dedup();
+        heights
+    }
+
+    /// The manifest of the snapshot in `dir`, migrated to the current version.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let path = dir.join(MANIFEST_FILE);
+        let json = fs::read_to_string(&path)
+            .with_context(|| format!("failed to read {}", path.display()))?;
+        let manifest: Self = serde_json::from_str::<Value>(&json)
+            .map_err(anyhow::Error::from)
+            .and_then(migrate)
+            .and_then(|manifest| Ok(serde_json::from_value(manifest)?))
+            .with_context(|| format!("invalid snapshot manifest {}", path.display()))?;
+        Ok(manifest)
+    }
+
+    pub fn write(&self, dir: &Path) -> Result<()> {
+        let path = dir.join(MANIFEST_FILE);
+        let mut json = serde_json::to_string_pretty(self)?;
+        json.push('\n');
+        fs::write(&path, json).with_context(|| format!("failed to write {}", path.display()))
+    }
+
+    /// Check that every file of the manifest is in `dir`, normalized.
+    pub fn check_files(&self, dir: &Path) -> Result<()> {
+        for file in self.blocks.iter().flat_map(|block| &block.files) {
+            let path = dir.join(file);
+            let response = fs::read_to_string(&path)
+                .with_context(|| format!("snapshot file {} is missing", path.display()))?;
+            ensure!(
+                normalize_response(&response)? == response,
+                "snapshot file {} isn't normalized",
+                path.display()
+            );
+        }
+        Ok(())
+    }
+}
+
+/// `manifest` as of `SNAPSHOT_VERSION`. A change to the snapshots bumps the version and adds the
+/// migration from the previous one here, which rewrites the manifest (and the files, if need be)
+/// and falls through to the next.
+fn migrate(manifest: Value) -> Result<Value> {
+    let version = manifest["version"]
+        .as_u64()
+        .ok_or_else(|| anyhow!("the manifest has no version"))?;
+    match version {
+        SNAPSHOT_VERSION => Ok(manifest),
+        version if version > SNAPSHOT_VERSION => bail!(
+            "snapshot version {} is newer than the supported version {}",
+            version,
+            SNAPSHOT_VERSION
+        ),
+        version => bail!("no migration from snapshot version {}", version),
+    }
+}
+
+/// `response` with its JSON-RPC ID reset and its keys sorted, pretty-printed.
+pub fn normalize_response(response: &str) -> Result<String> {
+    let mut value: Value = serde_json::from_str(response).context("invalid JSON response")?;
+    if let Some(id) = value.get_mut("id") {
+        *id = json!(-1);
+    }
+    let mut normalized = serde_json::to_string_pretty(&value)?;
+    normalized.push('\n');
+    Ok(normalized)
+}
+
+/// Record the snapshot from `trusted_block` to `target_block` from the Tendermint RPCs `urls`
+/// into `dir`, and write its manifest.
+pub async fn record_snapshot(
+    urls: Vec<String>,
+    trusted_block: Height,
+    target_block: Height,
+    dir: &Path,
+) -> Result<SnapshotManifest> {
+    ensure!(
+        trusted_block < target_block,
+        "target block {} is not after trusted block {}",
+        target_block,
+        trusted_block
+    );
+    let fixture_path = dir
+        .to_str()
+        .ok_or_else(|| anyhow!("invalid snapshot directory {}", dir.display()))?;
+    let mut fetcher = InputDataFetcher::new(urls, fixture_path);
+    fetcher.mode = InputDataMode::Rpc;
+    fetcher.set_save(true);
+
+    let mut chain_id = None;
+    let mut blocks = Vec::new();
+    for height in SnapshotManifest::heights(trusted_block, target_block) {
+        let signed_header = fetcher.get_signed_header_from_number(height).await?;
+        let validators = fetcher.get_validator_set_from_number(height).await?;
+        let hash = header_hash(&signed_header.header);
+        ensure!(
+            HeaderHash::try_from(signed_header.commit.block_id.hash)? == hash,
+            "the commit of block {} doesn't sign its header",
+            height
+        );
+        chain_id.get_or_insert_with(|| signed_header.header.chain_id.to_string());
+
+        let pages = validators.len().div_ceil(VALIDATORS_PER_PAGE).max(1);
+        let files = std::iter::once("commit.json".to_string())
+            .chain((1..=pages).map(|page| format!("validators_{}.json", page)))
+            .map(|file| format!("{}/{}", height, file))
+            .collect::<Vec<_>>();
+        for file in &files {
+            let path = dir.join(file);
+            let response = fs::read_to_string(&path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            fs::write(&path, normalize_response(&response)?)
+                .with_context(|| format!("failed to write {}", path.display()))?;
+        }
+        blocks.push(SnapshotBlock {
+            height,
+            header_hash: hash,
+            round: signed_header.commit.round.value(),
+            validators: validators.len(),
+            absent: signed_header
+                .commit
+                .signatures
+                .iter()
+                .filter(|signature| matches!(signature, CommitSig::BlockIdFlagAbsent))
+                .count(),
+            files,
+        });
+    }
+
+    let manifest = SnapshotManifest {
+        version: SNAPSHOT_VERSION,
+        chain_id: chain_id.unwrap_or_default(),
+        trusted_block,
+        target_block,
+        blocks,
+    };
+    manifest.write(dir)?;
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2x::prelude::GoldilocksField;
+
+    use super::*;
+    use crate::consts::VALIDATOR_SET_SIZE_MAX;
+    use crate::testing::{MockTendermintServer, SyntheticChain};
+
+    /// The snapshot checked in, recorded from mocha-4.
+    const SNAPSHOT: &str = "./circuits/fixtures/snapshots/mocha-4-10000-10500";
+
+    #[tokio::test]
+    async fn test_checked_in_snapshot() {
+        let dir = Path::new(SNAPSHOT);
+        let manifest = SnapshotManifest::load(dir).unwrap();
+        assert_eq!(manifest.version, SNAPSHOT_VERSION);
+        assert_eq!(manifest.chain_id, "mocha-4");
+        assert_eq!(
+            manifest.blocks.iter().map(|b| b.height).collect::<Vec<_>>(),
+            SnapshotManifest::heights(Height(10000), Height(10500))
+        );
+        manifest.check_files(dir).unwrap();
+
+        // The inputs built from the snapshot are those of the recorded chain.
+        let mut fetcher = InputDataFetcher::new(vec![], SNAPSHOT);
+        let trusted = &manifest.blocks[0];
+        let step = fetcher
+            .get_step_inputs::<VALIDATOR_SET_SIZE_MAX, GoldilocksField>(
+                trusted.height,
+                trusted.header_hash,
+            )
+            .await
+            .unwrap();
+        assert_eq!(step.next_header, manifest.blocks[1].header_hash.to_bytes());
+        assert_eq!(step.nb_validators, manifest.blocks[1].validators);
+        assert_eq!(step.round, manifest.blocks[1].round as usize);
+        let skip = fetcher
+            .get_skip_inputs::<VALIDATOR_SET_SIZE_MAX, GoldilocksField>(
+                trusted.height,
+                trusted.header_hash,
+                manifest.target_block,
+            )
+            .await
+            .unwrap();
+        let target = &manifest.blocks[2];
+        assert_eq!(skip.target_header, target.header_hash.to_bytes());
+        assert_eq!(skip.nb_target_validators, target.validators);
+        assert_eq!(skip.nb_trusted_validators, trusted.validators);
+        assert_eq!(
+            target.header_hash,
+            "0xe2ba1b86926925a69c2fcc32e5178e7e6653d386c956bb975142fa73211a9444"
+                .parse::<HeaderHash>()
+                .unwrap()
+        );
+    }
+
+    #[cfg(feature = "operator")]
+    #[test]
+    fn test_snapshot_encodings_golden() {
+        use crate::encoding::{encode_skip_input, encode_step_input};
+        use crate::golden::{EncodingVectors, CASES};
+
+        // The snapshot starts from the trusted block of the mocha-4 case of the golden vectors.
+        let manifest = SnapshotManifest::load(Path::new(SNAPSHOT)).unwrap();
+        let case = CASES.iter().find(|case| case.name == "mocha-4").unwrap();
+        let trusted = &manifest.blocks[0];
+        assert_eq!(trusted.height, case.trusted_block);
+        assert_eq!(trusted.header_hash, case.trusted_header_hash);
+
+        let vectors = EncodingVectors::generate();
+        let step = vectors.step.iter().find(|v| v.name == case.name).unwrap();
+        assert_eq!(
+            encode_step_input(trusted.height, trusted.header_hash),
+            step.input.as_ref()
+        );
+        let skip = vectors.skip.iter().find(|v| v.name == case.name).unwrap();
+        assert_eq!(
+            encode_skip_input(trusted.height, trusted.header_hash, case.target_block),
+            skip.input.as_ref()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_record_snapshot() {
+        // 3 pages of validators.
+        let chain = SyntheticChain::new(3).with_validators(250).with_head(20);
+        let server = MockTendermintServer::start(chain).await.unwrap();
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = record_snapshot(vec![server.url()], Height(5), Height(15), dir.path())
+            .await
+            .unwrap();
+        assert_eq!(manifest.chain_id, "mock-1");
+        assert_eq!(manifest.blocks.len(), 3);
+        let target = &manifest.blocks[2];
+        assert_eq!(target.height, Height(15));
+        assert_eq!(
+            (target.validators, target.absent, target.round),
+            (250, 0, 0)
+        );
+        assert_eq!(target.header_hash, header_hash(&server.chain().header(15)));
+        assert_eq!(
+            target.files,
+            [
+                "15/commit.json",
+                "15/validators_1.json",
+                "15/validators_2.json",
+                "15/validators_3.json"
+            ]
+        );
+        assert_eq!(SnapshotManifest::load(dir.path()).unwrap(), manifest);
+        manifest.check_files(dir.path()).unwrap();
+
+        // Recording the range again gives the same files.
+        let commit = fs::read_to_string(dir.path().join("15/commit.json")).unwrap();
+        record_snapshot(vec![server.url()], Height(5), Height(15), dir.path())
+            .await
+            .unwrap();
+        assert_eq!(
+            fs::read_to_string(dir.path().join("15/commit.json")).unwrap(),
+            commit
+        );
+
+        assert!(
+            record_snapshot(vec![server.url()], Height(15), Height(5), dir.path())
+                .await
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_manifest_versions() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = SnapshotManifest::load(Path::new(SNAPSHOT)).unwrap();
+        let mut json = serde_json::to_value(&manifest).unwrap();
+
+        json["version"] = json!(SNAPSHOT_VERSION + 1);
+        fs::write(dir.path().join(MANIFEST_FILE), json.to_string()).unwrap();
+        let error = SnapshotManifest::load(dir.path()).unwrap_err();
+        assert!(format!("{:#}", error).contains("newer than the supported version"));
+
+        json["version"] = json!(0);
+        fs::write(dir.path().join(MANIFEST_FILE), json.to_string()).unwrap();
+        let error = SnapshotManifest::load(dir.path()).unwrap_err();
+        assert!(format!("{:#}", error).contains("no migration from snapshot version 0"));
+    }
+
+    #[test]
+    fn test_normalize_response() {
+        let normalized = normalize_response(r#"{"result":{"b":1,"a":"x"},"id":42}"#).unwrap();
+        assert_eq!(
+            normalized,
+            "{\n  \"id\": -1,\n  \"result\": {\n    \"a\": \"x\",\n    \"b\": 1\n  }\n}\n"
+        );
+        assert_eq!(normalize_response(&normalized).unwrap(), normalized);
+    }
+}
@@ -0,0 +1,228 @@
+//! Whether the chain or a target's light client stopped making progress.
+//!
+//! The lag in blocks can't tell a halted chain from a healthy one: if the chain stops producing
+//! blocks, the contract catches up and the lag drops to zero. Instead this tracks the time since
+//! the chain head last advanced (the time of its header) and since each target's latest block
+//! last increased (when the operator first saw it), and classifies a target as `ChainHalted` when
+//! the chain head is too old, or `LightClientStalled` when the chain advances but the contract
+//! doesn't. A target's age starts when the operator first reads it, since earlier updates are
+//! unknown.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use serde_with::{serde_as, DurationSeconds};
+
+/// Whether a target is making progress.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Staleness {
+    Fresh,
+    /// The chain head hasn't advanced for the configured duration.
+    ChainHalted,
+    /// The chain advances, but the contract's latest block hasn't for the configured duration.
+    LightClientStalled,
+}
+
+impl fmt::Display for Staleness {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Staleness::Fresh => f.write_str("fresh"),
+            Staleness::ChainHalted => f.write_str("chain halted"),
+            Staleness::LightClientStalled => f.write_str("light client stalled"),
+        }
+    }
+}
+
+/// The staleness of a target, given the time since the chain head last advanced and since the
+/// target's contract last did. A halted chain takes precedence: the contract can't advance either.
+pub fn classify(
+    chain_age: Duration,
+    contract_age: Duration,
+    chain_halt_after: Duration,
+    client_stall_after: Duration,
+) -> Staleness {
+    if chain_age >= chain_halt_after {
+        Staleness::ChainHalted
+    } else if contract_age >= client_stall_after {
+        Staleness::LightClientStalled
+    } else {
+        Staleness::Fresh
+    }
+}
+
+/// A change in the staleness of a target.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StalenessTransition {
+    pub from: Staleness,
+    pub to: Staleness,
+}
+
+/// The latest block of a contract and the unix time it was first seen.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct ContractHead {
+    block: u64,
+    seen_at: i64,
+}
+
+/// Tracks when the chain head and each target's contract last advanced. Times are unix seconds.
+/// Serializes its thresholds only.
+#[serde_as]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StalenessMonitor {
+    #[serde(rename = "chain_halt_after_secs")]
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub chain_halt_after: Duration,
+    #[serde(rename = "client_stall_after_secs")]
+    #[serde_as(as = "DurationSeconds<u64>")]
+    pub client_stall_after: Duration,
+    /// The header time of the latest chain head seen.
+    #[serde(skip)]
+    chain_head_time: Option<i64>,
+    #[serde(skip)]
+    contracts: HashMap<String, ContractHead>,
+    #[serde(skip)]
+    states: HashMap<String, Staleness>,
+}
+
+fn age(since: i64, now: i64) -> Duration {
+    Duration::from_secs(now.saturating_sub(since).max(0) as u64)
+}
+
+impl StalenessMonitor {
+    pub fn new(chain_halt_after: Duration, client_stall_after: Duration) -> Self {
+        Self {
+            chain_halt_after,
+            client_stall_after,
+            chain_head_time: None,
+            contracts: HashMap::new(),
+            states: HashMap::new(),
+        }
+    }
+
+    /// Record the header time of the chain head.
+    pub fn observe_chain_head(&mut self, head_time: i64) {
+        self.chain_head_time = Some(self.chain_head_time.map_or(head_time, |t| t.max(head_time)));
+    }
+
+    /// Record that the contract of the target `key` is at `block` at `now`.
+    pub fn observe_contract(&mut self, key: &str, block: u64, now: i64) {
+        let head = self
+            .contracts
+            .entry(key.to_string())
+            .or_insert(ContractHead {
+                block,
+                seen_at: now,
+            });
+        if block > head.block {
+            *head = ContractHead {
+                block,
+                seen_at: now,
+            };
+        }
+    }
+
+    /// The time since the chain head last advanced, if it was observed.
+    pub fn chain_age(&self, now: i64) -> Option<Duration> {
+        self.chain_head_time.map(|time| age(time, now))
+    }
+
+    /// The time since the contract of the target `key` last advanced, if it was observed.
+    pub fn contract_age(&self, key: &str, now: i64) -> Option<Duration> {
+        self.contracts.get(key).map(|head| age(head.seen_at, now))
+    }
+
+    /// Classify the target `key` at `now`. Returns the transition of its staleness, if any.
+    /// Targets start out fresh.
+    pub fn evaluate(&mut self, key: &str, now: i64) -> Option<StalenessTransition> {
+        let (Some(chain_age), Some(contract_age)) =
+            (self.chain_age(now), self.contract_age(key, now))
+        else {
+            return None;
+        };
+        let to = classify(
+            chain_age,
+            contract_age,
+            self.chain_halt_after,
+            self.client_stall_after,
+        );
+        let from = self
+            .states
+            .insert(key.to_string(), to)
+            .unwrap_or(Staleness::Fresh);
+        (from != to).then_some(StalenessTransition { from, to })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        let minutes = |m: u64| Duration::from_secs(60 * m);
+        let classify = |chain: u64, contract: u64| {
+            classify(minutes(chain), minutes(contract), minutes(30), minutes(480))
+        };
+        assert_eq!(classify(1, 10), Staleness::Fresh);
+        assert_eq!(classify(1, 480), Staleness::LightClientStalled);
+        assert_eq!(classify(30, 10), Staleness::ChainHalted);
+        assert_eq!(classify(45, 600), Staleness::ChainHalted);
+    }
+
+    #[test]
+    fn test_timelines() {
+        let start = 1_700_000_000;
+        let at = |minutes: i64| start + 60 * minutes;
+        let fired = |from, to| Some(StalenessTransition { from, to });
+        use Staleness::*;
+
+        // (minute, chain head header minute, contract block, expected transition)
+        let series = [
+            (0, 0, 1000, None),
+            (60, 59, 1000, None),
+            (240, 239, 1200, None),
+            // The chain advances, but the contract doesn't for more than 8 hours.
+            (720, 719, 1200, fired(Fresh, LightClientStalled)),
+            (900, 899, 1200, None),
+            (960, 959, 1300, fired(LightClientStalled, Fresh)),
+            // The chain halts: the contract catches up with its last block and stops too.
+            (1000, 980, 1330, None),
+            (1030, 980, 1330, fired(Fresh, ChainHalted)),
+            // Still halted after the contract's own age passes the stall threshold.
+            (1500, 980, 1330, None),
+            // The chain resumes, but the contract is still at the block before the halt.
+            (1510, 1509, 1330, fired(ChainHalted, LightClientStalled)),
+            (1520, 1519, 1400, fired(LightClientStalled, Fresh)),
+        ];
+        let mut monitor =
+            StalenessMonitor::new(Duration::from_secs(1800), Duration::from_secs(28800));
+        for (minute, head_minute, block, expected) in series {
+            monitor.observe_chain_head(at(head_minute));
+            monitor.observe_contract("5:0x11", block, at(minute));
+            assert_eq!(
+                monitor.evaluate("5:0x11", at(minute)),
+                expected,
+                "at minute {}",
+                minute
+            );
+        }
+        assert_eq!(monitor.chain_age(at(1530)), Some(Duration::from_secs(660)));
+        assert_eq!(
+            monitor.contract_age("5:0x11", at(1530)),
+            Some(Duration::from_secs(600))
+        );
+
+        // Targets are tracked independently, and start out fresh.
+        monitor.observe_contract("10:0x22", 500, at(1530));
+        assert_eq!(monitor.evaluate("10:0x22", at(1530)), None);
+        monitor.observe_chain_head(at(2009));
+        assert_eq!(
+            monitor.evaluate("10:0x22", at(2010)),
+            fired(Fresh, LightClientStalled)
+        );
+        assert_eq!(monitor.contract_age("20:0x33", at(2010)), None);
+        assert_eq!(monitor.evaluate("20:0x33", at(2010)), None);
+    }
+}
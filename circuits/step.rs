@@ -11,6 +11,7 @@ use serde::{Deserialize, Serialize};
 use crate::builder::verify::TendermintVerify;
 use crate::config::TendermintConfig;
 use crate::input::InputDataFetcher;
+use crate::types::Height;
 use crate::variables::*;
 
 pub trait TendermintStepCircuit<L: PlonkParameters<D>, const D: usize> {
@@ -67,10 +68,12 @@ impl<const MAX_VALIDATOR_SET_SIZE: usize, L: PlonkParameters<D>, const D: usize>
         let mut data_fetcher = InputDataFetcher::default();
         let result = data_fetcher
             .get_step_inputs::<MAX_VALIDATOR_SET_SIZE, L::Field>(
-                prev_block_number,
-                prev_header_hash,
+                Height(prev_block_number),
+                prev_header_hash.into(),
             )
-            .await;
+            .await
+            // A hint can't fail: the proof can't be generated without its inputs.
+            .unwrap_or_else(|e| panic!("failed to fetch the step inputs: {:#}", e));
 
         let verify_step_struct = VerifyStepStruct::<MAX_VALIDATOR_SET_SIZE, L::Field> {
             next_header: result.next_header.into(),
@@ -0,0 +1,1610 @@
+//! A persistent record of the requests submitted by the operator.
+//!
+//! The store survives restarts, so it is used to avoid resubmitting a request for a range that
+//! already has a pending request, and to audit what was requested.
+
+use std::collections::HashSet;
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use alloy_primitives::{Address, Bytes, B256};
+use anyhow::{anyhow, Context, Result};
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::{Deserialize, Serialize};
+use serde_with::serde_as;
+use tokio::sync::broadcast;
+
+use crate::attestation::SignedAttestation;
+use crate::backend::RequestKind;
+use crate::correlation::CorrelationId;
+use crate::labels::Labels;
+use crate::platform::FulfillmentStatus;
+use crate::sink::{EventSink, SinkEvent};
+use crate::target::RequestMode;
+use crate::wire::PrefixedHex;
+
+/// The schema migrations, applied in order. The index of the last applied migration is tracked
+/// with `PRAGMA user_version`. Never edit a migration that has been released; add a new one.
+const MIGRATIONS: &[&str] = &[
+    r#"
+CREATE TABLE requests (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    created_at INTEGER NOT NULL,
+    updated_at INTEGER NOT NULL,
+    chain_id INTEGER NOT NULL,
+    contract_address TEXT NOT NULL,
+    trusted_block INTEGER NOT NULL,
+    trusted_hash TEXT NOT NULL,
+    target_block INTEGER NOT NULL,
+    function_id TEXT NOT NULL,
+    request_id TEXT NOT NULL UNIQUE,
+    status TEXT NOT NULL
+);
+CREATE INDEX requests_range ON requests (chain_id, contract_address, trusted_block, target_block);
+"#,
+    r#"
+ALTER TABLE requests ADD COLUMN retry_of TEXT;
+ALTER TABLE requests ADD COLUMN attempt INTEGER NOT NULL DEFAULT 1;
+CREATE INDEX requests_retry_of ON requests (retry_of);
+"#,
+    r#"
+ALTER TABLE requests ADD COLUMN finished_at INTEGER;
+ALTER TABLE requests ADD COLUMN cost REAL;
+"#,
+    r#"
+ALTER TABLE requests ADD COLUMN backend TEXT;
+"#,
+    r#"
+ALTER TABLE requests ADD COLUMN onchain_at INTEGER;
+"#,
+    r#"
+ALTER TABLE requests ADD COLUMN request_mode TEXT NOT NULL DEFAULT 'platform';
+ALTER TABLE requests ADD COLUMN proof_location TEXT;
+"#,
+    r#"
+ALTER TABLE requests ADD COLUMN labels TEXT NOT NULL DEFAULT '{}';
+"#,
+    r#"
+ALTER TABLE requests ADD COLUMN input TEXT;
+"#,
+    r#"
+ALTER TABLE requests ADD COLUMN correlation_id TEXT;
+CREATE INDEX requests_correlation_id ON requests (correlation_id);
+"#,
+    // Attestations are signed before the request is recorded, so they have their own table.
+    r#"
+CREATE TABLE attestations (
+    request_id TEXT PRIMARY KEY,
+    attestation TEXT NOT NULL
+);
+"#,
+];
+
+/// The status of a stored request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RequestStatus {
+    /// Submitted, and not known to be proved yet.
+    Pending,
+    /// Proved, but not relayed on-chain yet.
+    Proved,
+    Relayed,
+    /// Proved off-chain, with the proof at the request's `proof_location`. Relaying the proof is
+    /// up to the operator.
+    Delivered,
+    Failed,
+    /// Given up on by the operator because it was not fulfilled in time. The platform may still
+    /// fulfill it.
+    Abandoned,
+}
+
+impl RequestStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RequestStatus::Pending => "pending",
+            RequestStatus::Proved => "proved",
+            RequestStatus::Relayed => "relayed",
+            RequestStatus::Delivered => "delivered",
+            RequestStatus::Failed => "failed",
+            RequestStatus::Abandoned => "abandoned",
+        }
+    }
+
+    /// Whether the request may still be fulfilled.
+    pub fn is_pending(&self) -> bool {
+        matches!(self, RequestStatus::Pending | RequestStatus::Proved)
+    }
+
+    /// Whether the request has reached its final status.
+    pub fn is_finished(&self) -> bool {
+        !self.is_pending()
+    }
+
+    /// The status of a request in `mode` with the given fulfillment status. Off-chain requests are
+    /// delivered once proved.
+    pub fn from_fulfillment(status: &FulfillmentStatus, mode: RequestMode) -> Self {
+        match (status, mode) {
+            (FulfillmentStatus::Proved { .. }, RequestMode::Offchain) => RequestStatus::Delivered,
+            (status, _) => status.into(),
+        }
+    }
+}
+
+impl fmt::Display for RequestStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for RequestStatus {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "pending" => Ok(RequestStatus::Pending),
+            "proved" => Ok(RequestStatus::Proved),
+            "relayed" => Ok(RequestStatus::Relayed),
+            "delivered" => Ok(RequestStatus::Delivered),
+            "failed" => Ok(RequestStatus::Failed),
+            "abandoned" => Ok(RequestStatus::Abandoned),
+            _ => Err(anyhow!("unknown request status {:?}", s)),
+        }
+    }
+}
+
+impl From<&FulfillmentStatus> for RequestStatus {
+    fn from(status: &FulfillmentStatus) -> Self {
+        match status {
+            FulfillmentStatus::Proving | FulfillmentStatus::TimedOut => RequestStatus::Pending,
+            FulfillmentStatus::Proved { .. } => RequestStatus::Proved,
+            FulfillmentStatus::Relayed { .. } => RequestStatus::Relayed,
+            FulfillmentStatus::Failed { .. } => RequestStatus::Failed,
+        }
+    }
+}
+
+/// A request to record in the store.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NewRequest {
+    #[serde(rename = "chain_id")]
+    pub chain_id: u32,
+    #[serde(rename = "contract_address")]
+    pub contract_address: Address,
+    #[serde(rename = "trusted_block")]
+    pub trusted_block: u64,
+    #[serde(rename = "trusted_hash")]
+    #[serde_as(as = "PrefixedHex")]
+    pub trusted_hash: [u8; 32],
+    #[serde(rename = "target_block")]
+    pub target_block: u64,
+    #[serde(rename = "function_id")]
+    pub function_id: B256,
+    #[serde(rename = "request_id")]
+    pub request_id: String,
+    /// The request this is a retry of, if it is a retry. Always the original request, never an
+    /// intermediate retry.
+    #[serde(rename = "retry_of")]
+    pub retry_of: Option<String>,
+    /// The attempt number, starting at 1 for the original request.
+    #[serde(rename = "attempt")]
+    pub attempt: u32,
+    /// The name of the backend that served the request.
+    #[serde(rename = "backend")]
+    pub backend: Option<String>,
+    #[serde(rename = "request_mode")]
+    pub request_mode: RequestMode,
+    #[serde(rename = "labels")]
+    pub labels: Labels,
+    /// The packed circuit input that was submitted.
+    #[serde(rename = "input")]
+    pub input: Bytes,
+    /// The attempt the request is part of, if the operator planned it.
+    #[serde(rename = "correlation_id")]
+    pub correlation_id: Option<CorrelationId>,
+}
+
+/// A request read from the store.
+#[serde_as]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RequestRecord {
+    #[serde(rename = "id")]
+    pub id: i64,
+    /// Unix timestamp (seconds) of the submission.
+    #[serde(rename = "created_at")]
+    pub created_at: u64,
+    /// Unix timestamp (seconds) of the last status update.
+    #[serde(rename = "updated_at")]
+    pub updated_at: u64,
+    #[serde(rename = "chain_id")]
+    pub chain_id: u32,
+    #[serde(rename = "contract_address")]
+    pub contract_address: Address,
+    #[serde(rename = "trusted_block")]
+    pub trusted_block: u64,
+    #[serde(rename = "trusted_hash")]
+    #[serde_as(as = "PrefixedHex")]
+    pub trusted_hash: [u8; 32],
+    #[serde(rename = "target_block")]
+    pub target_block: u64,
+    #[serde(rename = "function_id")]
+    pub function_id: B256,
+    #[serde(rename = "request_id")]
+    pub request_id: String,
+    #[serde(rename = "status")]
+    pub status: RequestStatus,
+    #[serde(rename = "retry_of")]
+    pub retry_of: Option<String>,
+    #[serde(rename = "attempt")]
+    pub attempt: u32,
+    /// Unix timestamp (seconds) at which the request was relayed, failed or was abandoned.
+    #[serde(rename = "finished_at")]
+    pub finished_at: Option<u64>,
+    /// The cost of the request reported by the backend, if any.
+    #[serde(rename = "cost")]
+    pub cost: Option<f64>,
+    #[serde(rename = "backend")]
+    pub backend: Option<String>,
+    /// Unix timestamp (seconds) of the Ethereum block holding the `HeadUpdate` event for the
+    /// target block, once it has been seen.
+    #[serde(rename = "onchain_at")]
+    pub onchain_at: Option<u64>,
+    #[serde(rename = "request_mode")]
+    pub request_mode: RequestMode,
+    /// Where the proof of a delivered off-chain request can be fetched from: a path for the local
+    /// backends, the platform proof ID otherwise.
+    #[serde(rename = "proof_location")]
+    pub proof_location: Option<String>,
+    /// The labels of the target at the time of the submission.
+    #[serde(rename = "labels")]
+    pub labels: Labels,
+    /// The packed circuit input that was submitted. Not recorded for requests submitted before
+    /// the input was stored.
+    #[serde(rename = "input")]
+    pub input: Option<Bytes>,
+    /// The attempt the request is part of. Not recorded for requests submitted before
+    /// correlation IDs were.
+    #[serde(rename = "correlation_id")]
+    pub correlation_id: Option<CorrelationId>,
+}
+
+impl fmt::Display for RequestRecord {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {} {}:{} {} -> {} ({})",
+            self.request_id,
+            self.status,
+            self.chain_id,
+            self.contract_address,
+            self.trusted_block,
+            self.target_block,
+            B256::from(self.trusted_hash)
+        )
+    }
+}
+
+const RECORD_COLUMNS: &str = "id, created_at, updated_at, chain_id, contract_address, \
+     trusted_block, trusted_hash, target_block, function_id, request_id, status, retry_of, attempt, \
+     finished_at, cost, backend, onchain_at, request_mode, proof_location, labels, input, \
+     correlation_id";
+
+impl RequestRecord {
+    /// Whether the request is still pending more than `max_age` after it was submitted, at unix
+    /// timestamp `now`.
+    pub fn is_stale(&self, now: u64, max_age: Duration) -> bool {
+        self.status.is_pending() && now.saturating_sub(self.created_at) > max_age.as_secs()
+    }
+}
+
+/// The requests submitted for a chain over a period.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChainStats {
+    pub chain_id: u32,
+    pub submitted: u64,
+    pub relayed: u64,
+    pub delivered: u64,
+    pub failed: u64,
+    pub abandoned: u64,
+    pub pending: u64,
+    /// The total and average cost of the requests with a known cost.
+    pub total_cost: Option<f64>,
+    pub average_cost: Option<f64>,
+    /// The average time from submission to relaying of the relayed requests.
+    pub average_duration: Option<Duration>,
+}
+
+/// The distribution of the distances between the trusted and the target block of the requests of
+/// a kind on a chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RequestSizes {
+    pub chain_id: u32,
+    pub kind: RequestKind,
+    pub count: u64,
+    pub min: u64,
+    pub median: u64,
+    pub max: u64,
+}
+
+/// A new status for a stored request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatusUpdate {
+    pub request_id: String,
+    pub status: RequestStatus,
+    /// The proof location of a delivered off-chain request.
+    pub proof_location: Option<String>,
+}
+
+/// The time from submission to fulfillment and to the header landing on-chain of a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Turnaround {
+    pub chain_id: u32,
+    pub request_id: String,
+    pub target_block: u64,
+    /// Unix timestamp (seconds) of the submission.
+    pub submitted_at: u64,
+    /// The time until the backend reported the request as relayed.
+    pub fulfilled: Option<Duration>,
+    /// The time until the `HeadUpdate` event for the target block.
+    pub onchain: Option<Duration>,
+}
+
+fn parse_column<T: FromStr>(row: &Row, idx: usize) -> rusqlite::Result<T>
+where
+    T::Err: fmt::Display,
+{
+    let value: String = row.get(idx)?;
+    value.parse::<T>().map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(
+            idx,
+            rusqlite::types::Type::Text,
+            anyhow!("invalid value {:?}: {}", value, e).into(),
+        )
+    })
+}
+
+fn labels_column(row: &Row, idx: usize) -> rusqlite::Result<Labels> {
+    let value: String = row.get(idx)?;
+    Labels::from_json(&value).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(idx, rusqlite::types::Type::Text, e.into())
+    })
+}
+
+fn record_from_row(row: &Row) -> rusqlite::Result<RequestRecord> {
+    Ok(RequestRecord {
+        id: row.get(0)?,
+        created_at: row.get::<_, i64>(1)? as u64,
+        updated_at: row.get::<_, i64>(2)? as u64,
+        chain_id: row.get(3)?,
+        contract_address: parse_column(row, 4)?,
+        trusted_block: row.get::<_, i64>(5)? as u64,
+        trusted_hash: parse_column::<B256>(row, 6)?.0,
+        target_block: row.get::<_, i64>(7)? as u64,
+        function_id: parse_column(row, 8)?,
+        request_id: row.get(9)?,
+        status: parse_column(row, 10)?,
+        retry_of: row.get(11)?,
+        attempt: row.get(12)?,
+        finished_at: row.get::<_, Option<i64>>(13)?.map(|t| t as u64),
+        cost: row.get(14)?,
+        backend: row.get(15)?,
+        onchain_at: row.get::<_, Option<i64>>(16)?.map(|t| t as u64),
+        request_mode: parse_column(row, 17)?,
+        proof_location: row.get(18)?,
+        labels: labels_column(row, 19)?,
+        input: row
+            .get::<_, Option<String>>(20)?
+            .map(|_| parse_column(row, 20))
+            .transpose()?,
+        correlation_id: row
+            .get::<_, Option<String>>(21)?
+            .map(|_| parse_column(row, 21))
+            .transpose()?,
+    })
+}
+
+/// Parse an age such as "30d", "12h", "45m" or "90s".
+pub fn parse_age(age: &str) -> Result<Duration> {
+    let (value, unit) = age.split_at(age.len().saturating_sub(1));
+    let seconds = match unit {
+        "d" => 24 * 60 * 60,
+        "h" => 60 * 60,
+        "m" => 60,
+        "s" => 1,
+        _ => return Err(anyhow!("invalid age {:?}, expected e.g. 30d", age)),
+    };
+    let value = value
+        .parse::<u64>()
+        .with_context(|| format!("invalid age {:?}, expected e.g. 30d", age))?;
+    Ok(Duration::from_secs(value * seconds))
+}
+
+/// The current unix timestamp in seconds.
+pub fn unix_timestamp() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .expect("system time is before the unix epoch")
+        .as_secs()
+}
+
+pub struct RequestStore {
+    conn: Mutex<Connection>,
+    /// The ranges being submitted, reserved with `reserve` until their request is recorded.
+    reserved: Mutex<HashSet<RangeKey>>,
+    /// Where the requests and their transitions are mirrored, if anywhere.
+    sink: Option<EventSink>,
+    /// The same events, for the subscribers that watch them live.
+    events: broadcast::Sender<SinkEvent>,
+}
+
+/// The number of events a subscriber can fall behind before it misses some.
+const SUBSCRIBER_CAPACITY: usize = 256;
+
+impl RequestStore {
+    /// Open (or create) the store at `path` and apply any pending migrations.
+    pub fn open(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let conn = Connection::open(path)
+            .with_context(|| format!("failed to open request store at {}", path.display()))?;
+        Self::from_connection(conn)
+    }
+
+    fn from_connection(mut conn: Connection) -> Result<Self> {
+        migrate(&mut conn)?;
+        Ok(Self {
+            conn: Mutex::new(conn),
+            reserved: Mutex::new(HashSet::new()),
+            sink: None,
+            events: broadcast::channel(SUBSCRIBER_CAPACITY).0,
+        })
+    }
+
+    /// Mirror every request recorded from now on, and its status transitions, to `sink`.
+    pub fn with_sink(mut self, sink: EventSink) -> Self {
+        self.sink = Some(sink);
+        self
+    }
+
+    /// The sink the store mirrors to, if any.
+    pub fn sink(&self) -> Option<&EventSink> {
+        self.sink.as_ref()
+    }
+
+    /// The events published from now on: the requests recorded, their status transitions and
+    /// the head updates. A subscriber that falls behind misses the oldest.
+    pub fn subscribe(&self) -> broadcast::Receiver<SinkEvent> {
+        self.events.subscribe()
+    }
+
+    /// Hand `event` to the sink, if there is one, and to the subscribers.
+    pub fn publish(&self, event: SinkEvent) {
+        // Without subscribers, the event is dropped.
+        if self.events.receiver_count() > 0 {
+            let _ = self.events.send(event.clone());
+        }
+        if let Some(sink) = self.sink.as_ref() {
+            sink.record(event);
+        }
+    }
+
+    /// Record a newly submitted request as pending. Returns the row ID.
+    pub fn insert(&self, request: &NewRequest) -> Result<i64> {
+        self.insert_at(request, unix_timestamp())
+    }
+
+    fn insert_at(&self, request: &NewRequest, now: u64) -> Result<i64> {
+        let now = now as i64;
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT INTO requests (created_at, updated_at, chain_id, contract_address, \
+             trusted_block, trusted_hash, target_block, function_id, request_id, status, \
+             retry_of, attempt, backend, request_mode, labels, input, correlation_id) \
+             VALUES (?1, ?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+            params![
+                now,
+                request.chain_id,
+                request.contract_address.to_string(),
+                request.trusted_block as i64,
+                B256::from(request.trusted_hash).to_string(),
+                request.target_block as i64,
+                request.function_id.to_string(),
+                request.request_id,
+                RequestStatus::Pending.as_str(),
+                request.retry_of,
+                request.attempt,
+                request.backend,
+                request.request_mode.as_str(),
+                request.labels.to_json(),
+                request.input.to_string(),
+                request.correlation_id.map(|id| id.to_string()),
+            ],
+        )
+        .with_context(|| format!("failed to record request {}", request.request_id))?;
+        let id = conn.last_insert_rowid();
+        drop(conn);
+        self.publish(SinkEvent::Request {
+            request: Box::new(request.clone()),
+            at: now as u64,
+        });
+        Ok(id)
+    }
+
+    /// Update the status of a request. Errors if the request is not in the store.
+    pub fn update_status(&self, request_id: &str, status: RequestStatus) -> Result<()> {
+        self.update_status_at(request_id, status, unix_timestamp())
+    }
+
+    fn update_status_at(&self, request_id: &str, status: RequestStatus, now: u64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        set_status(&conn, request_id, status, now)?;
+        drop(conn);
+        self.publish(SinkEvent::Transition {
+            request_id: request_id.to_string(),
+            status,
+            at: now,
+        });
+        Ok(())
+    }
+
+    /// Apply several status updates in a single transaction. Either all of them are applied, or
+    /// none if any request is not in the store.
+    pub fn update_statuses(&self, updates: &[StatusUpdate]) -> Result<()> {
+        let now = unix_timestamp();
+        let mut conn = self.conn.lock().unwrap();
+        let tx = conn.transaction()?;
+        for update in updates {
+            set_status(&tx, &update.request_id, update.status, now)?;
+            if let Some(location) = update.proof_location.as_ref() {
+                tx.execute(
+                    "UPDATE requests SET proof_location = ?1 WHERE request_id = ?2",
+                    params![location, update.request_id],
+                )?;
+            }
+        }
+        tx.commit()?;
+        drop(conn);
+        for update in updates {
+            self.publish(SinkEvent::Transition {
+                request_id: update.request_id.clone(),
+                status: update.status,
+                at: now,
+            });
+        }
+        Ok(())
+    }
+
+    /// Record the cost of a request reported by the backend.
+    pub fn record_cost(&self, request_id: &str, cost: f64) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let updated = conn.execute(
+            "UPDATE requests SET cost = ?1 WHERE request_id = ?2",
+            params![cost, request_id],
+        )?;
+        if updated == 0 {
+            return Err(anyhow!("request {} is not in the store", request_id));
+        }
+        Ok(())
+    }
+
+    /// Record where the proof of an off-chain request can be fetched from.
+    pub fn record_proof_location(&self, request_id: &str, location: &str) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        let updated = conn.execute(
+            "UPDATE requests SET proof_location = ?1 WHERE request_id = ?2",
+            params![location, request_id],
+        )?;
+        if updated == 0 {
+            return Err(anyhow!("request {} is not in the store", request_id));
+        }
+        Ok(())
+    }
+
+    /// Record the operator's signed attestation of the submission of a request.
+    pub fn record_attestation(
+        &self,
+        request_id: &str,
+        attestation: &SignedAttestation,
+    ) -> Result<()> {
+        let conn = self.conn.lock().unwrap();
+        conn.execute(
+            "INSERT OR REPLACE INTO attestations (request_id, attestation) VALUES (?1, ?2)",
+            params![request_id, serde_json::to_string(attestation)?],
+        )?;
+        Ok(())
+    }
+
+    /// The signed attestation of the submission of a request, if the operator signed it.
+    pub fn attestation(&self, request_id: &str) -> Result<Option<SignedAttestation>> {
+        let conn = self.conn.lock().unwrap();
+        let attestation: Option<String> = conn
+            .query_row(
+                "SELECT attestation FROM attestations WHERE request_id = ?1",
+                params![request_id],
+                |row| row.get(0),
+            )
+            .optional()?;
+        attestation
+            .map(|json| serde_json::from_str(&json).context("invalid stored attestation"))
+            .transpose()
+    }
+
+    /// Aggregate the requests submitted at or after unix timestamp `since`, per chain.
+    pub fn stats(&self, since: u64) -> Result<Vec<ChainStats>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT chain_id, COUNT(*), \
+             SUM(status = 'relayed'), SUM(status = 'delivered'), SUM(status = 'failed'), \
+             SUM(status = 'abandoned'), SUM(status IN ('pending', 'proved')), SUM(cost), \
+             AVG(cost), AVG(CASE WHEN status = 'relayed' THEN finished_at - created_at END) \
+             FROM requests WHERE created_at >= ?1 GROUP BY chain_id ORDER BY chain_id",
+        )?;
+        let stats = stmt
+            .query_map(params![since as i64], |row| {
+                Ok(ChainStats {
+                    chain_id: row.get(0)?,
+                    submitted: row.get::<_, i64>(1)? as u64,
+                    relayed: row.get::<_, i64>(2)? as u64,
+                    delivered: row.get::<_, i64>(3)? as u64,
+                    failed: row.get::<_, i64>(4)? as u64,
+                    abandoned: row.get::<_, i64>(5)? as u64,
+                    pending: row.get::<_, i64>(6)? as u64,
+                    total_cost: row.get(7)?,
+                    average_cost: row.get(8)?,
+                    average_duration: row
+                        .get::<_, Option<f64>>(9)?
+                        .map(|secs| Duration::from_secs_f64(secs.max(0.0))),
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(stats)
+    }
+
+    /// The distribution of the request sizes per chain and kind of the requests submitted since
+    /// unix time `since`, steps first.
+    pub fn request_sizes(&self, since: u64) -> Result<Vec<RequestSizes>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT chain_id, target_block - trusted_block FROM requests WHERE created_at >= ?1 \
+             ORDER BY chain_id, target_block - trusted_block",
+        )?;
+        let rows = stmt
+            .query_map(params![since as i64], |row| {
+                Ok((row.get::<_, u32>(0)?, row.get::<_, i64>(1)?.max(0) as u64))
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+
+        let mut sizes = Vec::new();
+        let mut chains = rows
+            .iter()
+            .map(|(chain_id, _)| *chain_id)
+            .collect::<Vec<_>>();
+        chains.dedup();
+        for chain_id in chains {
+            for kind in [RequestKind::Step, RequestKind::Skip] {
+                // The rows are sorted by size.
+                let of_kind = rows
+                    .iter()
+                    .filter(|(chain, size)| {
+                        *chain == chain_id && RequestKind::for_range(0, *size) == kind
+                    })
+                    .map(|(_, size)| *size)
+                    .collect::<Vec<_>>();
+                if of_kind.is_empty() {
+                    continue;
+                }
+                sizes.push(RequestSizes {
+                    chain_id,
+                    kind,
+                    count: of_kind.len() as u64,
+                    min: of_kind[0],
+                    median: of_kind[(of_kind.len() - 1) / 2],
+                    max: of_kind[of_kind.len() - 1],
+                });
+            }
+        }
+        Ok(sizes)
+    }
+
+    /// Record that the header at `target_block` was stored on the contract by an Ethereum block
+    /// with unix timestamp `timestamp`. Every request for the target block that did not fail is
+    /// correlated with the update, as whichever of them was relayed is not known. Returns the
+    /// number of requests updated.
+    pub fn record_head_update(
+        &self,
+        chain_id: u32,
+        contract_address: Address,
+        target_block: u64,
+        timestamp: u64,
+    ) -> Result<usize> {
+        let conn = self.conn.lock().unwrap();
+        let updated = conn.execute(
+            "UPDATE requests SET onchain_at = ?4 WHERE chain_id = ?1 AND contract_address = ?2 \
+             AND target_block = ?3 AND status != 'failed' AND onchain_at IS NULL",
+            params![
+                chain_id,
+                contract_address.to_string(),
+                target_block as i64,
+                timestamp as i64
+            ],
+        )?;
+        Ok(updated)
+    }
+
+    /// The turnaround of the requests submitted at or after unix timestamp `since` that were
+    /// fulfilled or seen on-chain, oldest first.
+    pub fn turnarounds(&self, since: u64) -> Result<Vec<Turnaround>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT chain_id, request_id, target_block, created_at, \
+             CASE WHEN status = 'relayed' THEN finished_at END, onchain_at \
+             FROM requests WHERE created_at >= ?1 \
+             AND (status = 'relayed' OR onchain_at IS NOT NULL) ORDER BY created_at, id",
+        )?;
+        let since_submission = |submitted_at: u64, at: Option<i64>| {
+            at.map(|at| Duration::from_secs((at as u64).saturating_sub(submitted_at)))
+        };
+        let turnarounds = stmt
+            .query_map(params![since as i64], |row| {
+                let submitted_at = row.get::<_, i64>(3)? as u64;
+                Ok(Turnaround {
+                    chain_id: row.get(0)?,
+                    request_id: row.get(1)?,
+                    target_block: row.get::<_, i64>(2)? as u64,
+                    submitted_at,
+                    fulfilled: since_submission(submitted_at, row.get(4)?),
+                    onchain: since_submission(submitted_at, row.get(5)?),
+                })
+            })?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(turnarounds)
+    }
+
+    /// Get a request by its platform request ID.
+    pub fn get(&self, request_id: &str) -> Result<Option<RequestRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let record = conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM requests WHERE request_id = ?1",
+                    RECORD_COLUMNS
+                ),
+                params![request_id],
+                record_from_row,
+            )
+            .optional()?;
+        Ok(record)
+    }
+
+    /// The requests of the attempt `correlation_id`, oldest first.
+    pub fn by_correlation_id(&self, correlation_id: CorrelationId) -> Result<Vec<RequestRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM requests WHERE correlation_id = ?1 ORDER BY id",
+            RECORD_COLUMNS
+        ))?;
+        let records = stmt
+            .query_map(params![correlation_id.to_string()], record_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(records)
+    }
+
+    /// The most recent `limit` requests, newest first.
+    pub fn list(&self, limit: usize) -> Result<Vec<RequestRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM requests ORDER BY id DESC LIMIT ?1",
+            RECORD_COLUMNS
+        ))?;
+        let records = stmt
+            .query_map(params![limit as i64], record_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(records)
+    }
+
+    /// All requests that may still be fulfilled, oldest first.
+    pub fn pending(&self) -> Result<Vec<RequestRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM requests WHERE status IN ('pending', 'proved') ORDER BY id",
+            RECORD_COLUMNS
+        ))?;
+        let records = stmt
+            .query_map([], record_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(records)
+    }
+
+    /// Mark the requests that are stale at unix timestamp `now` as abandoned, so that the range
+    /// can be requested again. Returns the abandoned requests.
+    pub fn abandon_stale(&self, now: u64, max_age: Duration) -> Result<Vec<RequestRecord>> {
+        let stale = self
+            .pending()?
+            .into_iter()
+            .filter(|record| record.is_stale(now, max_age))
+            .collect::<Vec<_>>();
+        for record in stale.iter() {
+            self.update_status(&record.request_id, RequestStatus::Abandoned)?;
+        }
+        Ok(stale)
+    }
+
+    /// The original request and all of its retries, in attempt order.
+    pub fn retry_history(&self, original_request_id: &str) -> Result<Vec<RequestRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT {} FROM requests WHERE request_id = ?1 OR retry_of = ?1 ORDER BY attempt, id",
+            RECORD_COLUMNS
+        ))?;
+        let records = stmt
+            .query_map(params![original_request_id], record_from_row)?
+            .collect::<rusqlite::Result<Vec<_>>>()?;
+        Ok(records)
+    }
+
+    /// Reserve the range of `function_id` from `trusted_block` to `target_block` on a contract
+    /// for a submission, until the reservation is dropped. Returns `None` if it is already
+    /// reserved: checking for a pending request and submitting one aren't atomic, so whoever
+    /// submits for a range reserves it first, and holds it until the request is recorded.
+    pub fn reserve(
+        &self,
+        chain_id: u32,
+        contract_address: Address,
+        function_id: B256,
+        trusted_block: u64,
+        target_block: u64,
+    ) -> Option<Reservation<'_>> {
+        let key = (
+            chain_id,
+            contract_address,
+            function_id,
+            trusted_block,
+            target_block,
+        );
+        let reserved = self.reserved.lock().unwrap().insert(key);
+        reserved.then_some(Reservation { store: self, key })
+    }
+
+    /// The pending request for the same range and function on the same contract, if any.
+    pub fn find_pending(
+        &self,
+        chain_id: u32,
+        contract_address: Address,
+        function_id: B256,
+        trusted_block: u64,
+        target_block: u64,
+    ) -> Result<Option<RequestRecord>> {
+        let conn = self.conn.lock().unwrap();
+        let record = conn
+            .query_row(
+                &format!(
+                    "SELECT {} FROM requests WHERE chain_id = ?1 AND contract_address = ?2 \
+                     AND trusted_block = ?3 AND target_block = ?4 AND function_id = ?5 \
+                     AND status IN ('pending', 'proved') ORDER BY id DESC LIMIT 1",
+                    RECORD_COLUMNS
+                ),
+                params![
+                    chain_id,
+                    contract_address.to_string(),
+                    trusted_block as i64,
+                    target_block as i64,
+                    function_id.to_string()
+                ],
+                record_from_row,
+            )
+            .optional()?;
+        Ok(record)
+    }
+}
+
+/// The chain ID, contract address, function ID, trusted block and target block of a request.
+type RangeKey = (u32, Address, B256, u64, u64);
+
+/// A range reserved with `RequestStore::reserve`, released when dropped.
+#[must_use]
+pub struct Reservation<'a> {
+    store: &'a RequestStore,
+    key: RangeKey,
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        self.store.reserved.lock().unwrap().remove(&self.key);
+    }
+}
+
+fn set_status(conn: &Connection, request_id: &str, status: RequestStatus, now: u64) -> Result<()> {
+    let updated = conn.execute(
+        "UPDATE requests SET status = ?1, updated_at = ?2, \
+         finished_at = CASE WHEN ?3 THEN COALESCE(finished_at, ?2) ELSE NULL END \
+         WHERE request_id = ?4",
+        params![
+            status.as_str(),
+            now as i64,
+            status.is_finished(),
+            request_id
+        ],
+    )?;
+    if updated == 0 {
+        return Err(anyhow!("request {} is not in the store", request_id));
+    }
+    Ok(())
+}
+
+/// Apply the migrations that have not been applied to `conn` yet.
+fn migrate(conn: &mut Connection) -> Result<()> {
+    let version = conn.query_row("PRAGMA user_version", [], |row| row.get::<_, i64>(0))? as usize;
+    if version > MIGRATIONS.len() {
+        return Err(anyhow!(
+            "request store schema version {} is newer than this binary supports ({})",
+            version,
+            MIGRATIONS.len()
+        ));
+    }
+    for (i, migration) in MIGRATIONS.iter().enumerate().skip(version) {
+        let tx = conn.transaction()?;
+        tx.execute_batch(migration)
+            .with_context(|| format!("failed to apply request store migration {}", i + 1))?;
+        tx.pragma_update(None, "user_version", (i + 1) as i64)?;
+        tx.commit()?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::wire::assert_snapshot;
+
+    pub(crate) fn new_request(
+        request_id: &str,
+        trusted_block: u64,
+        target_block: u64,
+    ) -> NewRequest {
+        NewRequest {
+            chain_id: 5,
+            contract_address: Address::repeat_byte(0x11),
+            trusted_block,
+            trusted_hash: [0xab; 32],
+            target_block,
+            function_id: B256::repeat_byte(0x22),
+            request_id: request_id.to_string(),
+            retry_of: None,
+            attempt: 1,
+            backend: Some("platform".to_string()),
+            request_mode: RequestMode::Platform,
+            labels: Labels::new(),
+            input: Bytes::from_static(&[0xcd; 48]),
+            correlation_id: None,
+        }
+    }
+
+    #[test]
+    fn test_serde_snapshots() {
+        let mut request = new_request("req_1", 100, 200);
+        request.labels = Labels::parse("operator=ops").unwrap();
+        request.correlation_id = Some("01HF3B8Y5ZQK4V9C2T7N6M1R0S".parse().unwrap());
+        assert_snapshot(&request, include_str!("fixtures/serde/new_request.json"));
+
+        let record = RequestRecord {
+            id: 7,
+            created_at: 1_700_000_000,
+            updated_at: 1_700_000_600,
+            chain_id: 5,
+            contract_address: Address::repeat_byte(0x11),
+            trusted_block: 100,
+            trusted_hash: [0xab; 32],
+            target_block: 200,
+            function_id: B256::repeat_byte(0x22),
+            request_id: "req_2".to_string(),
+            status: RequestStatus::Relayed,
+            retry_of: Some("req_1".to_string()),
+            attempt: 2,
+            finished_at: Some(1_700_000_600),
+            cost: Some(0.25),
+            backend: Some("platform".to_string()),
+            onchain_at: None,
+            request_mode: RequestMode::Offchain,
+            proof_location: None,
+            labels: Labels::new(),
+            input: None,
+            correlation_id: None,
+        };
+        assert_snapshot(&record, include_str!("fixtures/serde/request_record.json"));
+
+        // Every status round-trips as its name.
+        for status in [
+            "pending",
+            "proved",
+            "relayed",
+            "delivered",
+            "failed",
+            "abandoned",
+        ] {
+            let parsed: RequestStatus = status.parse().unwrap();
+            let json = serde_json::to_string(&parsed).unwrap();
+            assert_eq!(json, format!("{:?}", status));
+            assert_eq!(
+                serde_json::from_str::<RequestStatus>(&json).unwrap(),
+                parsed
+            );
+        }
+    }
+
+    #[test]
+    fn test_insert_and_get() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RequestStore::open(dir.path().join("requests.db")).unwrap();
+
+        let request = new_request("req_1", 100, 200);
+        store.insert(&request).unwrap();
+
+        let record = store.get("req_1").unwrap().unwrap();
+        assert_eq!(record.chain_id, request.chain_id);
+        assert_eq!(record.contract_address, request.contract_address);
+        assert_eq!(record.trusted_block, 100);
+        assert_eq!(record.trusted_hash, request.trusted_hash);
+        assert_eq!(record.target_block, 200);
+        assert_eq!(record.function_id, request.function_id);
+        assert_eq!(record.status, RequestStatus::Pending);
+        assert_eq!(record.backend.as_deref(), Some("platform"));
+        assert_eq!(record.request_mode, RequestMode::Platform);
+        assert_eq!(record.proof_location, None);
+        assert!(record.labels.is_empty());
+        assert_eq!(record.input.as_ref(), Some(&request.input));
+
+        store.record_proof_location("req_1", "proof_1").unwrap();
+        assert_eq!(
+            store
+                .get("req_1")
+                .unwrap()
+                .unwrap()
+                .proof_location
+                .as_deref(),
+            Some("proof_1")
+        );
+
+        assert!(store.get("req_2").unwrap().is_none());
+        // Request IDs are unique.
+        assert!(store.insert(&request).is_err());
+    }
+
+    #[test]
+    fn test_status_and_dedup() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("requests.db");
+        {
+            let store = RequestStore::open(&path).unwrap();
+            store.insert(&new_request("req_1", 100, 200)).unwrap();
+            store.insert(&new_request("req_2", 200, 300)).unwrap();
+            store.update_status("req_1", RequestStatus::Failed).unwrap();
+            assert!(store
+                .update_status("missing", RequestStatus::Failed)
+                .is_err());
+        }
+
+        // Reopening the store (as after a restart) keeps the records and re-applies no migrations.
+        let store = RequestStore::open(&path).unwrap();
+        let (contract, function) = (Address::repeat_byte(0x11), B256::repeat_byte(0x22));
+        assert!(store
+            .find_pending(5, contract, function, 100, 200)
+            .unwrap()
+            .is_none());
+        assert_eq!(
+            store
+                .find_pending(5, contract, function, 200, 300)
+                .unwrap()
+                .unwrap()
+                .request_id,
+            "req_2"
+        );
+        assert!(store
+            .find_pending(1, contract, function, 200, 300)
+            .unwrap()
+            .is_none());
+        // Nor is the request for another function over the same range, e.g. a data commitment.
+        let other = B256::repeat_byte(0x44);
+        assert!(store
+            .find_pending(5, contract, other, 200, 300)
+            .unwrap()
+            .is_none());
+
+        let pending = store.pending().unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].request_id, "req_2");
+
+        // A range is reserved once at a time, for a function on a contract.
+        let reservation = store.reserve(5, contract, function, 300, 400).unwrap();
+        assert!(store.reserve(5, contract, function, 300, 400).is_none());
+        let other_reservation = store.reserve(5, contract, other, 300, 400).unwrap();
+        drop(reservation);
+        assert!(store.reserve(5, contract, function, 300, 400).is_some());
+        drop(other_reservation);
+
+        let list = store.list(10).unwrap();
+        assert_eq!(
+            list.iter()
+                .map(|r| r.request_id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["req_2", "req_1"]
+        );
+    }
+
+    #[test]
+    fn test_update_statuses() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RequestStore::open(dir.path().join("requests.db")).unwrap();
+        store.insert(&new_request("req_1", 100, 200)).unwrap();
+        store.insert(&new_request("req_2", 200, 300)).unwrap();
+
+        let update = |request_id: &str, status| StatusUpdate {
+            request_id: request_id.to_string(),
+            status,
+            proof_location: None,
+        };
+        // A missing request rolls back the whole batch.
+        assert!(store
+            .update_statuses(&[
+                update("req_1", RequestStatus::Relayed),
+                update("missing", RequestStatus::Relayed),
+            ])
+            .is_err());
+        assert_eq!(store.pending().unwrap().len(), 2);
+
+        store
+            .update_statuses(&[
+                update("req_1", RequestStatus::Relayed),
+                StatusUpdate {
+                    proof_location: Some("proof_2".to_string()),
+                    ..update("req_2", RequestStatus::Delivered)
+                },
+            ])
+            .unwrap();
+        assert!(store.pending().unwrap().is_empty());
+        let record = store.get("req_2").unwrap().unwrap();
+        assert_eq!(record.status, RequestStatus::Delivered);
+        assert_eq!(record.proof_location.as_deref(), Some("proof_2"));
+        assert!(record.finished_at.is_some());
+    }
+
+    #[test]
+    fn test_sink_mirrors_transitions() {
+        let dir = tempfile::tempdir().unwrap();
+        let (sink, mut receiver) = EventSink::channel("test", 16);
+        let store = RequestStore::open(dir.path().join("requests.db"))
+            .unwrap()
+            .with_sink(sink);
+        let request = new_request("req_1", 100, 200);
+        store.insert_at(&request, 1000).unwrap();
+        store
+            .update_status_at("req_1", RequestStatus::Proved, 1100)
+            .unwrap();
+        // Failed updates aren't mirrored.
+        assert!(store
+            .update_status("missing", RequestStatus::Failed)
+            .is_err());
+        assert!(store
+            .update_statuses(&[StatusUpdate {
+                request_id: "missing".to_string(),
+                status: RequestStatus::Relayed,
+                proof_location: None,
+            }])
+            .is_err());
+        store
+            .abandon_stale(u64::MAX, Duration::from_secs(60))
+            .unwrap();
+
+        assert_eq!(
+            receiver.try_recv(),
+            Some(SinkEvent::Request {
+                request: Box::new(request),
+                at: 1000,
+            })
+        );
+        assert_eq!(
+            receiver.try_recv(),
+            Some(SinkEvent::Transition {
+                request_id: "req_1".to_string(),
+                status: RequestStatus::Proved,
+                at: 1100,
+            })
+        );
+        match receiver.try_recv() {
+            Some(SinkEvent::Transition {
+                request_id, status, ..
+            }) => assert_eq!(
+                (request_id.as_str(), status),
+                ("req_1", RequestStatus::Abandoned)
+            ),
+            event => panic!("unexpected event {:?}", event),
+        }
+        assert_eq!(receiver.try_recv(), None);
+        assert_eq!(store.sink().unwrap().dropped(), 0);
+    }
+
+    #[test]
+    fn test_subscribe() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RequestStore::open(dir.path().join("requests.db")).unwrap();
+        // Nothing is kept for subscribers to come.
+        store
+            .insert_at(&new_request("req_1", 100, 200), 1000)
+            .unwrap();
+        let mut first = store.subscribe();
+        let mut second = store.subscribe();
+        store
+            .update_status_at("req_1", RequestStatus::Relayed, 1100)
+            .unwrap();
+        let relayed = SinkEvent::Transition {
+            request_id: "req_1".to_string(),
+            status: RequestStatus::Relayed,
+            at: 1100,
+        };
+        assert_eq!(first.try_recv().unwrap(), relayed);
+        assert_eq!(second.try_recv().unwrap(), relayed);
+        assert!(first.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_attestations() {
+        use crate::attestation::{Attestation, Attester};
+
+        let dir = tempfile::tempdir().unwrap();
+        let store = RequestStore::open(dir.path().join("requests.db")).unwrap();
+        let attester = Attester::from_seed([0x07; 32]);
+        let signed = attester.sign(Attestation {
+            chain_id: 5,
+            contract: Address::repeat_byte(0x11),
+            trusted_block: 100,
+            trusted_hash: B256::repeat_byte(0xab),
+            target_block: 200,
+            function_id: B256::repeat_byte(0x22),
+            timestamp: 1000,
+            correlation_id: None,
+        });
+        // Recorded before the request itself.
+        store.record_attestation("req_1", &signed).unwrap();
+        store.insert(&new_request("req_1", 100, 200)).unwrap();
+        let stored = store.attestation("req_1").unwrap().unwrap();
+        assert_eq!(stored, signed);
+        stored.verify(&attester.public_key()).unwrap();
+        assert_eq!(store.attestation("req_2").unwrap(), None);
+    }
+
+    #[test]
+    fn test_retry_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RequestStore::open(dir.path().join("requests.db")).unwrap();
+
+        store.insert(&new_request("req_1", 100, 200)).unwrap();
+        store.insert(&new_request("other", 100, 150)).unwrap();
+        for (attempt, request_id) in [(2, "req_1_retry_1"), (3, "req_1_retry_2")] {
+            store
+                .insert(&NewRequest {
+                    retry_of: Some("req_1".to_string()),
+                    attempt,
+                    ..new_request(request_id, 100, 200)
+                })
+                .unwrap();
+        }
+
+        let history = store.retry_history("req_1").unwrap();
+        assert_eq!(
+            history
+                .iter()
+                .map(|r| (r.request_id.as_str(), r.attempt))
+                .collect::<Vec<_>>(),
+            vec![("req_1", 1), ("req_1_retry_1", 2), ("req_1_retry_2", 3)]
+        );
+        assert_eq!(history[2].retry_of.as_deref(), Some("req_1"));
+        assert_eq!(store.retry_history("other").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_by_correlation_id() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RequestStore::open(dir.path().join("requests.db")).unwrap();
+
+        // An attempt submitted to two contracts, and one before correlation IDs.
+        let correlation_id = CorrelationId::generate();
+        for (request_id, address) in [("req_1", 0x11), ("req_2", 0x12)] {
+            store
+                .insert(&NewRequest {
+                    contract_address: Address::repeat_byte(address),
+                    correlation_id: Some(correlation_id),
+                    ..new_request(request_id, 100, 200)
+                })
+                .unwrap();
+        }
+        store.insert(&new_request("req_3", 100, 200)).unwrap();
+
+        let records = store.by_correlation_id(correlation_id).unwrap();
+        assert_eq!(
+            records
+                .iter()
+                .map(|r| r.request_id.as_str())
+                .collect::<Vec<_>>(),
+            vec!["req_1", "req_2"]
+        );
+        assert_eq!(records[0].correlation_id, Some(correlation_id));
+        assert_eq!(store.get("req_3").unwrap().unwrap().correlation_id, None);
+        assert!(store
+            .by_correlation_id(CorrelationId::generate())
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_abandon_stale() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RequestStore::open(dir.path().join("requests.db")).unwrap();
+        store.insert(&new_request("req_1", 100, 200)).unwrap();
+        store.insert(&new_request("req_2", 200, 300)).unwrap();
+        store
+            .update_status("req_2", RequestStatus::Relayed)
+            .unwrap();
+
+        let record = store.get("req_1").unwrap().unwrap();
+        let max_age = Duration::from_secs(3600);
+        assert!(!record.is_stale(record.created_at + 3600, max_age));
+        assert!(record.is_stale(record.created_at + 3601, max_age));
+
+        // Nothing is stale yet.
+        assert!(store
+            .abandon_stale(record.created_at + 60, max_age)
+            .unwrap()
+            .is_empty());
+
+        // Only the pending request is abandoned, and it no longer blocks a new request for the
+        // same range.
+        let (contract, function) = (Address::repeat_byte(0x11), B256::repeat_byte(0x22));
+        assert!(store
+            .find_pending(5, contract, function, 100, 200)
+            .unwrap()
+            .is_some());
+        let abandoned = store
+            .abandon_stale(record.created_at + 7200, max_age)
+            .unwrap();
+        assert_eq!(abandoned.len(), 1);
+        assert_eq!(abandoned[0].request_id, "req_1");
+        assert_eq!(
+            store.get("req_1").unwrap().unwrap().status,
+            RequestStatus::Abandoned
+        );
+        assert_eq!(
+            store.get("req_2").unwrap().unwrap().status,
+            RequestStatus::Relayed
+        );
+        assert!(store
+            .find_pending(5, contract, function, 100, 200)
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_stats() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RequestStore::open(dir.path().join("requests.db")).unwrap();
+
+        // Chain 5: two relayed in 100s and 300s, one failed, one pending, and one before the
+        // period. Chain 10: one pending.
+        let seed = [
+            (
+                "req_1",
+                5,
+                1000,
+                Some((RequestStatus::Relayed, 1100)),
+                Some(1.5),
+            ),
+            (
+                "req_2",
+                5,
+                2000,
+                Some((RequestStatus::Relayed, 2300)),
+                Some(2.5),
+            ),
+            ("req_3", 5, 3000, Some((RequestStatus::Failed, 3010)), None),
+            ("req_4", 5, 4000, None, None),
+            (
+                "old",
+                5,
+                10,
+                Some((RequestStatus::Relayed, 20)),
+                Some(100.0),
+            ),
+            ("req_5", 10, 1000, None, None),
+        ];
+        for (request_id, chain_id, created_at, update, cost) in seed {
+            let request = NewRequest {
+                chain_id,
+                ..new_request(request_id, created_at, created_at + 1)
+            };
+            store.insert_at(&request, created_at).unwrap();
+            if let Some((status, at)) = update {
+                store.update_status_at(request_id, status, at).unwrap();
+            }
+            if let Some(cost) = cost {
+                store.record_cost(request_id, cost).unwrap();
+            }
+        }
+
+        let stats = store.stats(1000).unwrap();
+        assert_eq!(
+            stats,
+            vec![
+                ChainStats {
+                    chain_id: 5,
+                    submitted: 4,
+                    relayed: 2,
+                    delivered: 0,
+                    failed: 1,
+                    abandoned: 0,
+                    pending: 1,
+                    total_cost: Some(4.0),
+                    average_cost: Some(2.0),
+                    average_duration: Some(Duration::from_secs(200)),
+                },
+                ChainStats {
+                    chain_id: 10,
+                    submitted: 1,
+                    relayed: 0,
+                    delivered: 0,
+                    failed: 0,
+                    abandoned: 0,
+                    pending: 1,
+                    total_cost: None,
+                    average_cost: None,
+                    average_duration: None,
+                },
+            ]
+        );
+        assert_eq!(store.get("req_1").unwrap().unwrap().finished_at, Some(1100));
+        assert!(store.stats(5000).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_request_sizes() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RequestStore::open(dir.path().join("requests.db")).unwrap();
+
+        // (request ID, chain ID, trusted block, target block, submitted at)
+        let seed = [
+            ("step_1", 5, 100, 101, 1000),
+            ("skip_1", 5, 101, 400, 1000),
+            ("skip_2", 5, 400, 450, 1100),
+            ("step_2", 5, 450, 451, 1200),
+            ("skip_3", 5, 451, 1451, 1300),
+            ("old", 5, 10, 5000, 10),
+            ("skip_4", 10, 100, 200, 1000),
+        ];
+        for (request_id, chain_id, trusted_block, target_block, created_at) in seed {
+            let request = NewRequest {
+                chain_id,
+                ..new_request(request_id, trusted_block, target_block)
+            };
+            store.insert_at(&request, created_at).unwrap();
+        }
+
+        let sizes = |chain_id, kind, count, min, median, max| RequestSizes {
+            chain_id,
+            kind,
+            count,
+            min,
+            median,
+            max,
+        };
+        assert_eq!(
+            store.request_sizes(1000).unwrap(),
+            vec![
+                sizes(5, RequestKind::Step, 2, 1, 1, 1),
+                sizes(5, RequestKind::Skip, 3, 50, 299, 1000),
+                sizes(10, RequestKind::Skip, 1, 100, 100, 100),
+            ]
+        );
+        assert!(store.request_sizes(5000).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_turnarounds() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RequestStore::open(dir.path().join("requests.db")).unwrap();
+        let address = Address::repeat_byte(0x11);
+
+        // req_1 is relayed after 200s and lands on-chain after 350s. req_2 failed and was retried
+        // as req_3, which lands on-chain before the backend reports it relayed.
+        store
+            .insert_at(&new_request("req_1", 100, 200), 1000)
+            .unwrap();
+        store
+            .insert_at(&new_request("req_2", 200, 300), 2000)
+            .unwrap();
+        store
+            .insert_at(&new_request("req_3", 200, 300), 2100)
+            .unwrap();
+        store
+            .insert_at(&new_request("req_4", 300, 400), 3000)
+            .unwrap();
+        store
+            .update_status_at("req_1", RequestStatus::Relayed, 1200)
+            .unwrap();
+        store
+            .update_status_at("req_2", RequestStatus::Failed, 2050)
+            .unwrap();
+        assert_eq!(store.record_head_update(5, address, 200, 1350).unwrap(), 1);
+        assert_eq!(store.record_head_update(5, address, 300, 2400).unwrap(), 1);
+        store
+            .update_status_at("req_3", RequestStatus::Relayed, 2500)
+            .unwrap();
+        // Updates for other contracts, and repeated updates, are ignored.
+        assert_eq!(
+            store
+                .record_head_update(5, Address::repeat_byte(0x33), 400, 3100)
+                .unwrap(),
+            0
+        );
+        assert_eq!(store.record_head_update(5, address, 200, 9999).unwrap(), 0);
+
+        let turnaround =
+            |request_id: &str, target_block, submitted_at, fulfilled, onchain| Turnaround {
+                chain_id: 5,
+                request_id: request_id.to_string(),
+                target_block,
+                submitted_at,
+                fulfilled: Some(Duration::from_secs(fulfilled)),
+                onchain: Some(Duration::from_secs(onchain)),
+            };
+        assert_eq!(
+            store.turnarounds(0).unwrap(),
+            vec![
+                turnaround("req_1", 200, 1000, 200, 350),
+                turnaround("req_3", 300, 2100, 400, 300),
+            ]
+        );
+        assert_eq!(store.turnarounds(2000).unwrap().len(), 1);
+        assert_eq!(store.get("req_1").unwrap().unwrap().onchain_at, Some(1350));
+    }
+
+    #[test]
+    fn test_parse_age() {
+        assert_eq!(parse_age("30d").unwrap(), Duration::from_secs(30 * 86400));
+        assert_eq!(parse_age("12h").unwrap(), Duration::from_secs(12 * 3600));
+        assert_eq!(parse_age("90s").unwrap(), Duration::from_secs(90));
+        assert!(parse_age("30").is_err());
+        assert!(parse_age("d").is_err());
+        assert!(parse_age("").is_err());
+    }
+
+    #[test]
+    fn test_status_mapping() {
+        assert_eq!(
+            RequestStatus::from(&FulfillmentStatus::TimedOut),
+            RequestStatus::Pending
+        );
+        assert_eq!(
+            RequestStatus::from(&FulfillmentStatus::Failed { error: None }),
+            RequestStatus::Failed
+        );
+        let proved = FulfillmentStatus::Proved { proof_id: None };
+        assert_eq!(
+            RequestStatus::from_fulfillment(&proved, RequestMode::Platform),
+            RequestStatus::Proved
+        );
+        assert_eq!(
+            RequestStatus::from_fulfillment(&proved, RequestMode::Offchain),
+            RequestStatus::Delivered
+        );
+        assert!(RequestStatus::Delivered.is_finished());
+        for status in [
+            RequestStatus::Pending,
+            RequestStatus::Proved,
+            RequestStatus::Relayed,
+            RequestStatus::Delivered,
+            RequestStatus::Failed,
+            RequestStatus::Abandoned,
+        ] {
+            assert_eq!(status.as_str().parse::<RequestStatus>().unwrap(), status);
+        }
+    }
+}
@@ -0,0 +1,722 @@
+//! A summary of each iteration of the run loop.
+//!
+//! Reconstructing an iteration from its log lines is tedious, more so with several targets. The run
+//! loop builds a summary as it goes: the chain head, then for each group of targets at the same
+//! block, the range it could request, the selected target and why, the action taken and its
+//! result, and the time spent in each phase. At the end of the iteration the summary is logged as a
+//! single event and written to the audit log.
+//!
+//! A one-shot run (`run --once`) reduces its single iteration to a `RunSummary`: its outcome, what
+//! it did for each target and how far behind the target is before and after, for a CronJob to
+//! read from the file `--summary-file` names. The outcome is also the exit code.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::fs::File;
+use std::io::Write;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Error, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::audit::unix_timestamp_ms;
+use crate::backend::RequestKind;
+use crate::selector::Selection;
+use crate::target::TargetSubmission;
+
+/// What an iteration did for a group of targets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Action {
+    Step,
+    Skip,
+    DataCommitment,
+    /// No request was due, or the request wasn't needed.
+    None,
+    /// Submissions are paused, or this operator isn't the leader.
+    Paused,
+}
+
+impl From<RequestKind> for Action {
+    fn from(kind: RequestKind) -> Self {
+        match kind {
+            RequestKind::Step => Action::Step,
+            RequestKind::Skip => Action::Skip,
+            RequestKind::DataCommitment => Action::DataCommitment,
+        }
+    }
+}
+
+impl fmt::Display for Action {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Action::Step => "step",
+            Action::Skip => "skip",
+            Action::DataCommitment => "data_commitment",
+            Action::None => "none",
+            Action::Paused => "paused",
+        })
+    }
+}
+
+/// The submission of a request for a target.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SubmissionSummary {
+    pub target: String,
+    pub request_id: Option<String>,
+    /// The top-level message of the submission error, without its causes, if it failed.
+    pub error: Option<String>,
+}
+
+/// What an iteration did for a group of targets at the same block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct GroupSummary {
+    /// The latest block of the group's contracts.
+    pub latest_block: u64,
+    pub targets: Vec<String>,
+    pub max_end_block: Option<u64>,
+    pub target_block: Option<u64>,
+    /// Why the selector chose the target block, or none.
+    pub rationale: Option<String>,
+    pub action: Action,
+    /// Why no request was made, if none was.
+    pub reason: Option<String>,
+    pub submissions: Vec<SubmissionSummary>,
+    /// The top-level message of the error that prevented any submission, if there was one.
+    pub error: Option<String>,
+}
+
+impl GroupSummary {
+    /// No request is made, because of `reason`.
+    pub fn skipped(&mut self, action: Action, reason: impl Into<String>) {
+        self.action = action;
+        self.reason = Some(reason.into());
+    }
+
+    /// `selection` was made from the range up to `max_end_block`.
+    pub fn selected(&mut self, max_end_block: u64, selection: &Selection) {
+        self.max_end_block = Some(max_end_block);
+        self.target_block = selection.target;
+        self.rationale = Some(selection.rationale.clone());
+    }
+
+    /// A `kind` request for `target_block` was submitted to each target in `submissions`.
+    pub fn submitted(
+        &mut self,
+        kind: RequestKind,
+        target_block: u64,
+        submissions: &[TargetSubmission<'_>],
+    ) {
+        self.action = kind.into();
+        self.target_block = Some(target_block);
+        self.submissions = submissions
+            .iter()
+            .map(|submission| SubmissionSummary {
+                target: submission.target.to_string(),
+                request_id: submission.result.as_ref().ok().cloned(),
+                error: submission.result.as_ref().err().map(|e| e.to_string()),
+            })
+            .collect();
+    }
+
+    /// A `kind` request for `target_block` failed before any submission, with `error`.
+    pub fn failed(&mut self, kind: RequestKind, target_block: u64, error: &Error) {
+        self.action = kind.into();
+        self.target_block = Some(target_block);
+        self.error = Some(error.to_string());
+    }
+}
+
+impl fmt::Display for GroupSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} from {}", self.action, self.latest_block)?;
+        if let Some(target_block) = self.target_block {
+            write!(f, " to {}", target_block)?;
+        }
+        if let Some(reason) = &self.reason {
+            write!(f, " ({})", reason)?;
+        }
+        if let Some(error) = &self.error {
+            write!(f, " failed: {}", error)?;
+        } else if !self.submissions.is_empty() {
+            let accepted = self
+                .submissions
+                .iter()
+                .filter(|submission| submission.request_id.is_some())
+                .count();
+            write!(f, ": {}/{} submitted", accepted, self.submissions.len())?;
+        }
+        Ok(())
+    }
+}
+
+/// The total time spent in each phase of an iteration, in milliseconds.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Phases(pub BTreeMap<String, u64>);
+
+impl Phases {
+    /// Add `duration` to the time spent in `phase`.
+    pub fn record(&mut self, phase: &str, duration: Duration) {
+        *self.0.entry(phase.to_string()).or_default() += duration.as_millis() as u64;
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IterationSummary {
+    /// Unix time in milliseconds.
+    pub started_at_ms: u64,
+    /// Unix time in milliseconds, 0 until the iteration is finished.
+    pub finished_at_ms: u64,
+    pub chain_head: Option<u64>,
+    pub groups: Vec<GroupSummary>,
+    pub phases_ms: Phases,
+}
+
+impl IterationSummary {
+    /// The summary of an iteration starting now.
+    pub fn start() -> Self {
+        Self {
+            started_at_ms: unix_timestamp_ms(),
+            finished_at_ms: 0,
+            chain_head: None,
+            groups: Vec::new(),
+            phases_ms: Phases::default(),
+        }
+    }
+
+    /// The summary of a new group of `targets` at `latest_block`, which makes no request until
+    /// told otherwise.
+    pub fn group(&mut self, latest_block: u64, targets: Vec<String>) -> &mut GroupSummary {
+        self.groups.push(GroupSummary {
+            latest_block,
+            targets,
+            max_end_block: None,
+            target_block: None,
+            rationale: None,
+            action: Action::None,
+            reason: None,
+            submissions: Vec::new(),
+            error: None,
+        });
+        self.groups.last_mut().unwrap()
+    }
+
+    pub fn finish(&mut self) {
+        self.finished_at_ms = unix_timestamp_ms().max(self.started_at_ms);
+    }
+
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.finished_at_ms.saturating_sub(self.started_at_ms))
+    }
+}
+
+impl fmt::Display for IterationSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "iteration of {:?}", self.duration())?;
+        if let Some(chain_head) = self.chain_head {
+            write!(f, " at chain head {}", chain_head)?;
+        }
+        for (i, group) in self.groups.iter().enumerate() {
+            write!(f, "{} {}", if i == 0 { ":" } else { ";" }, group)?;
+        }
+        Ok(())
+    }
+}
+
+/// The exit code of a one-shot run that submitted a request.
+pub const EXIT_ACTED: i32 = 0;
+/// The exit code of a one-shot run that failed.
+pub const EXIT_FAILED: i32 = 1;
+/// The exit code of a one-shot run that had nothing to do.
+pub const EXIT_NOTHING_TO_DO: i32 = 3;
+
+/// The outcome of a one-shot run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunOutcome {
+    /// A request was submitted, and none failed.
+    Acted,
+    /// No request was due, or submissions are paused.
+    NothingToDo,
+    /// The iteration or a submission failed, or the background writes didn't complete.
+    Failed,
+}
+
+impl RunOutcome {
+    pub fn exit_code(self) -> i32 {
+        match self {
+            RunOutcome::Acted => EXIT_ACTED,
+            RunOutcome::NothingToDo => EXIT_NOTHING_TO_DO,
+            RunOutcome::Failed => EXIT_FAILED,
+        }
+    }
+}
+
+impl fmt::Display for RunOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            RunOutcome::Acted => "acted",
+            RunOutcome::NothingToDo => "nothing_to_do",
+            RunOutcome::Failed => "failed",
+        })
+    }
+}
+
+/// What a one-shot run did for a target.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TargetRun {
+    pub target: String,
+    pub action: Action,
+    /// The ID of the request submitted for the target, if one was accepted.
+    pub request_id: Option<String>,
+    pub target_block: Option<u64>,
+    /// How many blocks the target was behind the chain head before the run.
+    pub lag_before: u64,
+    /// How many blocks the target is behind the chain head once its request lands, or as before if
+    /// none was accepted.
+    pub lag_after: u64,
+    /// The top-level message of the error of the target's request, if it failed.
+    pub error: Option<String>,
+}
+
+/// The machine-readable result of a one-shot run.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RunSummary {
+    pub outcome: RunOutcome,
+    pub exit_code: i32,
+    /// Unix time in milliseconds.
+    pub started_at_ms: u64,
+    /// Unix time in milliseconds, once the background writes completed.
+    pub finished_at_ms: u64,
+    /// The time spent in the iteration, in milliseconds.
+    pub iteration_ms: u64,
+    /// The time spent waiting for the background writes, in milliseconds.
+    pub flush_ms: u64,
+    pub chain_head: Option<u64>,
+    pub targets: Vec<TargetRun>,
+    /// The error that failed the run, with its causes, if it failed.
+    pub error: Option<String>,
+    /// The summary of the iteration, unless it failed before the end.
+    pub iteration: Option<IterationSummary>,
+}
+
+impl RunSummary {
+    /// The summary of a one-shot run started at `started_at_ms`, whose iteration just ended with
+    /// `iteration`.
+    pub fn of(started_at_ms: u64, iteration: Result<IterationSummary>) -> Self {
+        let now = unix_timestamp_ms().max(started_at_ms);
+        let mut summary = Self {
+            outcome: RunOutcome::NothingToDo,
+            exit_code: EXIT_NOTHING_TO_DO,
+            started_at_ms,
+            finished_at_ms: now,
+            iteration_ms: now - started_at_ms,
+            flush_ms: 0,
+            chain_head: None,
+            targets: Vec::new(),
+            error: None,
+            iteration: None,
+        };
+        let iteration = match iteration {
+            Ok(iteration) => iteration,
+            Err(e) => {
+                summary.fail(format!("{:#}", e));
+                return summary;
+            }
+        };
+        let chain_head = iteration.chain_head.unwrap_or_default();
+        for group in &iteration.groups {
+            let lag_before = chain_head.saturating_sub(group.latest_block);
+            for target in &group.targets {
+                let submission = group
+                    .submissions
+                    .iter()
+                    .find(|submission| &submission.target == target);
+                let request_id = submission.and_then(|submission| submission.request_id.clone());
+                let error = submission
+                    .and_then(|submission| submission.error.clone())
+                    .or_else(|| group.error.clone());
+                let lag_after = match (&request_id, group.target_block) {
+                    (Some(_), Some(target_block)) => chain_head.saturating_sub(target_block),
+                    _ => lag_before,
+                };
+                summary.targets.push(TargetRun {
+                    target: target.clone(),
+                    action: group.action,
+                    request_id,
+                    target_block: group.target_block,
+                    lag_before,
+                    lag_after,
+                    error,
+                });
+            }
+        }
+        summary.chain_head = iteration.chain_head;
+        summary.iteration = Some(iteration);
+
+        if let Some(failed) = summary.targets.iter().find(|run| run.error.is_some()) {
+            let error = format!("{}: {}", failed.target, failed.error.as_deref().unwrap());
+            summary.fail(error);
+        } else if summary.targets.iter().any(|run| run.request_id.is_some()) {
+            summary.outcome = RunOutcome::Acted;
+            summary.exit_code = EXIT_ACTED;
+        }
+        summary
+    }
+
+    /// The background writes completed in `duration`, or failed to with `flushed`'s error. Either
+    /// way, the run is finished.
+    pub fn flushed(&mut self, duration: Duration, flushed: Result<()>) {
+        self.flush_ms = duration.as_millis() as u64;
+        self.finished_at_ms = unix_timestamp_ms().max(self.started_at_ms + self.iteration_ms);
+        if let Err(e) = flushed {
+            self.fail(format!("{:#}", e));
+        }
+    }
+
+    /// Fail the run, keeping its first error.
+    fn fail(&mut self, error: String) {
+        self.outcome = RunOutcome::Failed;
+        self.exit_code = EXIT_FAILED;
+        self.error.get_or_insert(error);
+    }
+
+    /// Write the summary to `path` as JSON, replacing it at once: a reader never sees a partial
+    /// summary.
+    pub fn write(&self, path: &Path) -> Result<()> {
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        let tmp = Path::new(&tmp);
+        let json = serde_json::to_string_pretty(self)?;
+        let mut file =
+            File::create(tmp).with_context(|| format!("could not create {}", tmp.display()))?;
+        file.write_all(json.as_bytes())?;
+        file.write_all(b"\n")?;
+        file.sync_all()?;
+        std::fs::rename(tmp, path)
+            .with_context(|| format!("could not write the run summary to {}", path.display()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use anyhow::anyhow;
+    use serde_json::Value;
+
+    use super::*;
+    use crate::audit::{AuditEntry, AuditEvent};
+    use crate::target::RequestTarget;
+
+    /// The fields of `value` that are null or empty, sorted.
+    fn missing(value: &Value) -> Vec<String> {
+        let mut missing = value
+            .as_object()
+            .unwrap()
+            .iter()
+            .filter(|(_, field)| match field {
+                Value::Null => true,
+                Value::Array(items) => items.is_empty(),
+                Value::Object(fields) => fields.is_empty(),
+                _ => false,
+            })
+            .map(|(name, _)| name.clone())
+            .collect::<Vec<_>>();
+        missing.sort();
+        missing
+    }
+
+    #[test]
+    fn test_success() {
+        let (a, b) = (RequestTarget::for_test(5), RequestTarget::for_test(10));
+        let mut summary = IterationSummary::start();
+        summary.chain_head = Some(2000);
+        summary
+            .phases_ms
+            .record("chain_head", Duration::from_millis(30));
+        let group = summary.group(1000, vec![a.to_string(), b.to_string()]);
+        group.selected(
+            1500,
+            &Selection::block(1500, "the furthest block from 1000"),
+        );
+        let submissions = [
+            TargetSubmission {
+                target: &a,
+                result: Ok("req_1".to_string()),
+            },
+            TargetSubmission {
+                target: &b,
+                result: Err(anyhow!("rate limited").context("submission failed")),
+            },
+        ];
+        group.submitted(RequestKind::Skip, 1500, &submissions);
+        summary
+            .phases_ms
+            .record("submit", Duration::from_millis(200));
+        summary
+            .phases_ms
+            .record("submit", Duration::from_millis(100));
+        summary.finish();
+
+        let json = serde_json::to_value(&summary).unwrap();
+        assert_eq!(missing(&json), Vec::<String>::new());
+        // Only the reason and error of a group that submitted are missing.
+        assert_eq!(missing(&json["groups"][0]), ["error", "reason"]);
+        assert_eq!(json["groups"][0]["action"], "skip");
+        assert_eq!(json["groups"][0]["max_end_block"], 1500);
+        assert_eq!(
+            json["groups"][0]["submissions"][1]["error"],
+            "submission failed"
+        );
+        assert_eq!(json["phases_ms"]["submit"], 300);
+        assert!(summary.finished_at_ms >= summary.started_at_ms);
+        assert_eq!(
+            summary.groups[0].to_string(),
+            "skip from 1000 to 1500: 1/2 submitted"
+        );
+
+        let parsed: IterationSummary = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed, summary);
+        // As an audit log entry.
+        let entry = AuditEntry {
+            timestamp_ms: summary.finished_at_ms,
+            event: AuditEvent::Iteration(summary.clone()),
+        };
+        let line = serde_json::to_string(&entry).unwrap();
+        let value: Value = serde_json::from_str(&line).unwrap();
+        assert_eq!(value["event"], "iteration");
+        assert_eq!(value["chain_head"], 2000);
+        assert_eq!(serde_json::from_str::<AuditEntry>(&line).unwrap(), entry);
+    }
+
+    #[test]
+    fn test_failure_and_no_action() {
+        let mut summary = IterationSummary::start();
+        summary.chain_head = Some(2000);
+
+        // The inputs of the request couldn't be computed.
+        let group = summary.group(1000, vec![RequestTarget::for_test(5).to_string()]);
+        group.selected(1001, &Selection::block(1001, "stepping"));
+        let error = anyhow!("header not found").context("no header stored for trusted block 1000");
+        group.failed(RequestKind::Step, 1001, &error);
+        // Nothing is due for the other groups.
+        let group = summary.group(1900, vec![RequestTarget::for_test(10).to_string()]);
+        group.selected(2000, &Selection::none("the cadence isn't reached"));
+        group.skipped(Action::None, "no request due");
+        let group = summary.group(1950, vec![RequestTarget::for_test(15).to_string()]);
+        group.skipped(Action::Paused, "submissions are paused");
+        summary
+            .phases_ms
+            .record("select", Duration::from_millis(10));
+        summary.finish();
+
+        let json = serde_json::to_value(&summary).unwrap();
+        assert_eq!(missing(&json), Vec::<String>::new());
+        assert_eq!(missing(&json["groups"][0]), ["reason", "submissions"]);
+        assert_eq!(
+            json["groups"][0]["error"],
+            "no header stored for trusted block 1000"
+        );
+        assert_eq!(
+            missing(&json["groups"][1]),
+            ["error", "submissions", "target_block"]
+        );
+        assert_eq!(json["groups"][2]["action"], "paused");
+
+        let display = summary.to_string();
+        assert!(display.starts_with("iteration of "), "{}", display);
+        assert!(
+            display.ends_with(
+                " at chain head 2000: step from 1000 to 1001 failed: no header stored for \
+                 trusted block 1000; none from 1900 (no request due); paused from 1950 \
+                 (submissions are paused)"
+            ),
+            "{}",
+            display
+        );
+    }
+
+    /// The fields of a run summary, and of each of its targets.
+    const RUN_FIELDS: [&str; 10] = [
+        "chain_head",
+        "error",
+        "exit_code",
+        "finished_at_ms",
+        "flush_ms",
+        "iteration",
+        "iteration_ms",
+        "outcome",
+        "started_at_ms",
+        "targets",
+    ];
+    const TARGET_FIELDS: [&str; 7] = [
+        "action",
+        "error",
+        "lag_after",
+        "lag_before",
+        "request_id",
+        "target",
+        "target_block",
+    ];
+
+    /// Check that `json` has exactly the fields of a run summary, of the right types, and return
+    /// it parsed.
+    fn check_schema(json: &Value) -> RunSummary {
+        let fields = |value: &Value| {
+            let mut fields: Vec<_> = value.as_object().unwrap().keys().cloned().collect();
+            fields.sort();
+            fields
+        };
+        assert_eq!(fields(json), RUN_FIELDS);
+        for field in [
+            "exit_code",
+            "started_at_ms",
+            "finished_at_ms",
+            "iteration_ms",
+            "flush_ms",
+        ] {
+            assert!(json[field].is_u64(), "{}", field);
+        }
+        assert!(json["outcome"].is_string());
+        assert!(json["error"].is_null() || json["error"].is_string());
+        for target in json["targets"].as_array().unwrap() {
+            assert_eq!(fields(target), TARGET_FIELDS);
+            assert!(target["lag_before"].is_u64() && target["lag_after"].is_u64());
+        }
+        let summary: RunSummary = serde_json::from_value(json.clone()).unwrap();
+        assert_eq!(summary.exit_code, summary.outcome.exit_code());
+        assert!(summary.finished_at_ms >= summary.started_at_ms);
+        summary
+    }
+
+    #[test]
+    fn test_run_acted() {
+        let (a, b) = (RequestTarget::for_test(5), RequestTarget::for_test(10));
+        let mut iteration = IterationSummary::start();
+        iteration.chain_head = Some(2000);
+        let group = iteration.group(1000, vec![a.to_string()]);
+        let submissions = [TargetSubmission {
+            target: &a,
+            result: Ok("req_1".to_string()),
+        }];
+        group.submitted(RequestKind::Skip, 1900, &submissions);
+        let group = iteration.group(1990, vec![b.to_string()]);
+        group.skipped(Action::None, "no request due");
+        iteration.finish();
+
+        let mut summary = RunSummary::of(iteration.started_at_ms, Ok(iteration));
+        summary.flushed(Duration::from_millis(12), Ok(()));
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("summary.json");
+        summary.write(&path).unwrap();
+        let json: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(check_schema(&json), summary);
+
+        assert_eq!(json["outcome"], "acted");
+        assert_eq!(json["exit_code"], EXIT_ACTED);
+        assert_eq!(json["flush_ms"], 12);
+        assert_eq!(json["chain_head"], 2000);
+        assert!(json["error"].is_null());
+        assert_eq!(json["targets"][0]["action"], "skip");
+        assert_eq!(json["targets"][0]["request_id"], "req_1");
+        assert_eq!(json["targets"][0]["lag_before"], 1000);
+        assert_eq!(json["targets"][0]["lag_after"], 100);
+        // A target with nothing due keeps its lag.
+        assert_eq!(json["targets"][1]["action"], "none");
+        assert_eq!(json["targets"][1]["lag_after"], 10);
+        assert_eq!(json["iteration"]["groups"][1]["reason"], "no request due");
+        // Only the summary is left.
+        let files: Vec<_> = std::fs::read_dir(dir.path()).unwrap().collect();
+        assert_eq!(files.len(), 1);
+    }
+
+    #[test]
+    fn test_run_nothing_to_do() {
+        let mut iteration = IterationSummary::start();
+        iteration.chain_head = Some(2000);
+        let group = iteration.group(1990, vec![RequestTarget::for_test(5).to_string()]);
+        group.selected(2000, &Selection::none("the cadence isn't reached"));
+        group.skipped(Action::None, "no request due");
+        let group = iteration.group(1950, vec![RequestTarget::for_test(10).to_string()]);
+        group.skipped(Action::Paused, "submissions are paused");
+        iteration.finish();
+
+        let mut summary = RunSummary::of(iteration.started_at_ms, Ok(iteration));
+        summary.flushed(Duration::ZERO, Ok(()));
+        let json = serde_json::to_value(&summary).unwrap();
+        check_schema(&json);
+        assert_eq!(json["outcome"], "nothing_to_do");
+        assert_eq!(json["exit_code"], EXIT_NOTHING_TO_DO);
+        assert_eq!(json["targets"][1]["action"], "paused");
+        assert_eq!(
+            (
+                &json["targets"][1]["lag_before"],
+                &json["targets"][1]["lag_after"]
+            ),
+            (&Value::from(50), &Value::from(50))
+        );
+        assert!(json["targets"][0]["request_id"].is_null());
+    }
+
+    #[test]
+    fn test_run_failed() {
+        // The iteration failed before the end.
+        let error = anyhow!("connection refused").context("could not read the skip max");
+        let mut summary = RunSummary::of(unix_timestamp_ms(), Err(error));
+        summary.flushed(Duration::from_millis(3), Ok(()));
+        let json = serde_json::to_value(&summary).unwrap();
+        check_schema(&json);
+        assert_eq!(json["outcome"], "failed");
+        assert_eq!(json["exit_code"], EXIT_FAILED);
+        assert_eq!(
+            json["error"],
+            "could not read the skip max: connection refused"
+        );
+        assert!(json["iteration"].is_null() && json["chain_head"].is_null());
+
+        // A submission failed, while another was accepted.
+        let (a, b) = (RequestTarget::for_test(5), RequestTarget::for_test(10));
+        let mut iteration = IterationSummary::start();
+        iteration.chain_head = Some(2000);
+        let group = iteration.group(1000, vec![a.to_string(), b.to_string()]);
+        let submissions = [
+            TargetSubmission {
+                target: &a,
+                result: Ok("req_1".to_string()),
+            },
+            TargetSubmission {
+                target: &b,
+                result: Err(anyhow!("rate limited")),
+            },
+        ];
+        group.submitted(RequestKind::Skip, 1500, &submissions);
+        iteration.finish();
+        let mut summary = RunSummary::of(iteration.started_at_ms, Ok(iteration));
+        summary.flushed(Duration::ZERO, Ok(()));
+        let json = serde_json::to_value(&summary).unwrap();
+        check_schema(&json);
+        assert_eq!(json["outcome"], "failed");
+        assert_eq!(json["error"], format!("{}: rate limited", b));
+        assert_eq!(json["targets"][0]["lag_after"], 500);
+        assert_eq!(json["targets"][1]["lag_after"], 1000);
+
+        // The iteration succeeded, but the background writes didn't complete.
+        let mut iteration = IterationSummary::start();
+        iteration.chain_head = Some(2000);
+        iteration.group(1990, vec![a.to_string()]);
+        iteration.finish();
+        let mut summary = RunSummary::of(iteration.started_at_ms, Ok(iteration));
+        assert_eq!(summary.outcome, RunOutcome::NothingToDo);
+        let error = anyhow!("the postgres sink has 2 events left after 30s");
+        summary.flushed(Duration::from_secs(30), Err(error));
+        let json = serde_json::to_value(&summary).unwrap();
+        check_schema(&json);
+        assert_eq!(json["outcome"], "failed");
+        assert_eq!(json["flush_ms"], 30_000);
+        assert_eq!(
+            json["error"],
+            "the postgres sink has 2 events left after 30s"
+        );
+    }
+}
@@ -0,0 +1,499 @@
+//! Request targets: the (chain, contract, function IDs) a proof request is submitted for.
+//!
+//! The same Tendermint chain can be tracked by several `TendermintX` deployments (e.g. on mainnet
+//! and on an L2). The inputs for a request only depend on the trusted state, so they are computed
+//! once and submitted to every target that shares that state. Each target has its own function
+//! IDs, as deployments on different chains register their own circuits: the request built for a
+//! target always carries the IDs of that target. Contracts that expose their step and skip
+//! function IDs are the source of those: the configured IDs are then only checked against them.
+
+use std::fmt;
+use std::future::Future;
+use std::str::FromStr;
+
+use alloy_primitives::{Address, B256};
+use anyhow::{anyhow, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::labels::Labels;
+
+/// How requests for a target are fulfilled.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum RequestMode {
+    /// The platform proves the request and relays the proof to the contract.
+    #[default]
+    Platform,
+    /// The platform only proves the request. The proof is fetched and relayed by the operator.
+    Offchain,
+}
+
+impl RequestMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RequestMode::Platform => "platform",
+            RequestMode::Offchain => "offchain",
+        }
+    }
+}
+
+impl fmt::Display for RequestMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for RequestMode {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s {
+            "platform" => Ok(RequestMode::Platform),
+            "offchain" => Ok(RequestMode::Offchain),
+            _ => Err(anyhow!(
+                "unknown request mode {:?}, expected platform or offchain",
+                s
+            )),
+        }
+    }
+}
+
+/// The step and skip function IDs a contract requests proofs with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FunctionIds {
+    pub step: B256,
+    pub skip: B256,
+}
+
+/// Where the step and skip function IDs of a target come from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum FunctionIdSource {
+    /// The getters of the contract.
+    Contract,
+    /// The configuration: STEP_FUNCTION_ID and SKIP_FUNCTION_ID, or a registry entry.
+    Config,
+}
+
+impl FunctionIdSource {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            FunctionIdSource::Contract => "contract",
+            FunctionIdSource::Config => "config",
+        }
+    }
+}
+
+impl fmt::Display for FunctionIdSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A `TendermintX` deployment that proof requests are submitted for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestTarget {
+    /// The chain ID of the chain the contract is deployed on.
+    #[serde(rename = "chain_id")]
+    pub chain_id: u32,
+    /// The address of the `TendermintX` contract.
+    #[serde(rename = "address")]
+    pub address: Address,
+    /// The function ID of the step circuit. Zero if missing, which `validate` rejects.
+    #[serde(rename = "step_function_id", default)]
+    pub step_function_id: B256,
+    /// The function ID of the skip circuit. Zero if missing, which `validate` rejects.
+    #[serde(rename = "skip_function_id", default)]
+    pub skip_function_id: B256,
+    /// The function ID of the data commitment circuit, if the contract takes data commitments.
+    #[serde(
+        rename = "data_commitment_function_id",
+        default,
+        skip_serializing_if = "Option::is_none"
+    )]
+    pub data_commitment_function_id: Option<B256>,
+    #[serde(rename = "request_mode", default)]
+    pub request_mode: RequestMode,
+    /// Labels recorded with and logged for every request to this target.
+    #[serde(rename = "labels", default)]
+    pub labels: Labels,
+    /// The other chains the contract is deployed on at the same address, behind the same gateway.
+    /// Every request is also submitted for each of them, with the same input.
+    #[serde(
+        rename = "mirror_chain_ids",
+        default,
+        skip_serializing_if = "Vec::is_empty"
+    )]
+    pub mirror_chain_ids: Vec<u32>,
+}
+
+impl fmt::Display for RequestTarget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.chain_id, self.address)
+    }
+}
+
+impl RequestTarget {
+    /// The target on the mirror chain `chain_id`: the same deployment, with the `chain` label of
+    /// that chain if the target has one, and no mirrors of its own.
+    pub fn mirror(&self, chain_id: u32) -> Result<Self> {
+        let mut labels = self.labels.clone();
+        if labels.get("chain").is_some() {
+            labels.insert("chain", &chain_id.to_string())?;
+        }
+        Ok(Self {
+            chain_id,
+            labels,
+            mirror_chain_ids: Vec::new(),
+            ..self.clone()
+        })
+    }
+
+    /// Check that the target has a function ID for steps, skips and, if it takes data
+    /// commitments, data commitments. The error names the target and the missing ID.
+    pub fn validate(&self) -> Result<()> {
+        let function_ids = [
+            ("step", Some(self.step_function_id)),
+            ("skip", Some(self.skip_function_id)),
+            ("data commitment", self.data_commitment_function_id),
+        ];
+        for (kind, function_id) in function_ids {
+            ensure!(
+                function_id != Some(B256::ZERO),
+                "target {} has no {} function ID",
+                self,
+                kind
+            );
+        }
+        Ok(())
+    }
+
+    /// Take the step and skip function IDs `registered` with the contract, if it exposes them,
+    /// and return where the IDs come from. A configured ID must be the contract's: the error lists
+    /// those that differ, `-` for the configured ID followed by `+` for the contract's.
+    pub fn resolve_function_ids(
+        &mut self,
+        registered: Option<FunctionIds>,
+    ) -> Result<FunctionIdSource> {
+        let Some(registered) = registered else {
+            return Ok(FunctionIdSource::Config);
+        };
+        let mut differences = Vec::new();
+        for (kind, configured, registered) in [
+            ("step", self.step_function_id, registered.step),
+            ("skip", self.skip_function_id, registered.skip),
+        ] {
+            if configured != B256::ZERO && configured != registered {
+                differences.push(format!("- {} function ID {}", kind, configured));
+                differences.push(format!("+ {} function ID {}", kind, registered));
+            }
+        }
+        ensure!(
+            differences.is_empty(),
+            "the function IDs configured for target {} differ from its contract's:\n{}",
+            self,
+            differences.join("\n")
+        );
+        self.step_function_id = registered.step;
+        self.skip_function_id = registered.skip;
+        Ok(FunctionIdSource::Contract)
+    }
+
+    /// The target of the tests on chain `chain_id`: the contract `0x1111…` with the step and skip
+    /// function IDs `0x2222…` and `0x3333…`, fulfilled by the platform, without labels, data
+    /// commitments or mirrors. Tests override the fields they are about.
+    #[cfg(test)]
+    pub fn for_test(chain_id: u32) -> Self {
+        Self {
+            chain_id,
+            address: Address::repeat_byte(0x11),
+            step_function_id: B256::repeat_byte(0x22),
+            skip_function_id: B256::repeat_byte(0x33),
+            data_commitment_function_id: None,
+            request_mode: RequestMode::Platform,
+            labels: Labels::new(),
+            mirror_chain_ids: Vec::new(),
+        }
+    }
+}
+
+/// The function IDs of the comma separated `list` of the setting `key` for `targets` targets:
+/// a single entry shared by all of them, or one entry per target, empty for a target without
+/// one, which is zero.
+pub fn parse_function_ids(key: &str, list: &str, targets: usize) -> Result<Vec<B256>> {
+    let mut entries = list.split(',').map(str::trim).collect::<Vec<_>>();
+    if entries.len() == 1 {
+        entries = vec![entries[0]; targets];
+    }
+    ensure!(
+        entries.len() == targets,
+        "{} must have one entry or one entry per target",
+        key
+    );
+    entries
+        .into_iter()
+        .enumerate()
+        .map(|(i, entry)| match entry {
+            "" => Ok(B256::ZERO),
+            entry => parse_function_id(entry)
+                .with_context(|| format!("invalid entry {} of {}", i + 1, key)),
+        })
+        .collect()
+}
+
+fn parse_function_id(id: &str) -> Result<B256> {
+    let bytes = alloy_primitives::hex::decode(id.strip_prefix("0x").unwrap_or(id))
+        .map_err(|e| anyhow!("invalid hex, expected 0x prefix: {}", e))?;
+    ensure!(bytes.len() == 32, "expected 32 bytes");
+    Ok(B256::from_slice(&bytes))
+}
+
+/// The outcome of submitting a request to a single target.
+#[derive(Debug)]
+pub struct TargetSubmission<'a> {
+    pub target: &'a RequestTarget,
+    /// The request ID returned by the platform, or the submission error.
+    pub result: Result<String>,
+}
+
+/// Submit a request to each target in order. A failure for one target does not prevent the
+/// submission to the remaining targets.
+pub async fn submit_to_targets<'a, I, F, Fut>(
+    targets: I,
+    mut submit: F,
+) -> Vec<TargetSubmission<'a>>
+where
+    I: IntoIterator<Item = &'a RequestTarget>,
+    F: FnMut(&'a RequestTarget) -> Fut,
+    Fut: Future<Output = Result<String>>,
+{
+    let mut submissions = Vec::new();
+    for target in targets {
+        let result = submit(target).await;
+        submissions.push(TargetSubmission { target, result });
+    }
+    submissions
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::RefCell;
+
+    use super::*;
+    use crate::wire::assert_snapshot;
+
+    fn target(chain_id: u32) -> RequestTarget {
+        RequestTarget {
+            address: Address::repeat_byte(chain_id as u8),
+            step_function_id: B256::repeat_byte(1),
+            skip_function_id: B256::repeat_byte(2),
+            ..RequestTarget::for_test(chain_id)
+        }
+    }
+
+    #[test]
+    fn test_serde_snapshot() {
+        let mut target = target(5);
+        target.request_mode = RequestMode::Offchain;
+        target.labels = Labels::parse("operator=ops").unwrap();
+        assert_snapshot(&target, include_str!("fixtures/serde/request_target.json"));
+
+        // The mode and labels are optional.
+        let json = serde_json::json!({
+            "chain_id": 5,
+            "address": target.address,
+            "step_function_id": target.step_function_id,
+            "skip_function_id": target.skip_function_id,
+        });
+        let parsed: RequestTarget = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.request_mode, RequestMode::Platform);
+        assert!(parsed.labels.is_empty());
+        assert!(parsed.mirror_chain_ids.is_empty());
+    }
+
+    #[test]
+    fn test_mirror() {
+        let mut primary = target(5);
+        primary.labels = Labels::parse("operator=ops,chain=5").unwrap();
+        primary.mirror_chain_ids = vec![10, 42161];
+        let mirror = primary.mirror(10).unwrap();
+        assert_eq!(mirror.chain_id, 10);
+        assert_eq!(mirror.address, primary.address);
+        assert_eq!(mirror.step_function_id, primary.step_function_id);
+        assert_eq!(
+            mirror.labels,
+            Labels::parse("operator=ops,chain=10").unwrap()
+        );
+        assert!(mirror.mirror_chain_ids.is_empty());
+
+        // Without a chain label, the labels are kept as they are.
+        primary.labels = Labels::parse("operator=ops").unwrap();
+        assert_eq!(primary.mirror(10).unwrap().labels, primary.labels);
+    }
+
+    #[test]
+    fn test_parse_function_ids() {
+        let (step, skip) = (B256::repeat_byte(1), B256::repeat_byte(2));
+        // A single entry is shared by every target.
+        assert_eq!(
+            parse_function_ids("STEP_FUNCTION_ID", &step.to_string(), 3).unwrap(),
+            [step; 3]
+        );
+        // One entry per target, without the prefix too, and empty for a target without one.
+        let list = format!("{}, {} ,", step, skip.to_string().trim_start_matches("0x"));
+        assert_eq!(
+            parse_function_ids("STEP_FUNCTION_ID", &list, 3).unwrap(),
+            [step, skip, B256::ZERO]
+        );
+
+        let error = parse_function_ids("STEP_FUNCTION_ID", &list, 2).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "STEP_FUNCTION_ID must have one entry or one entry per target"
+        );
+        let list = format!("{},0x1234", step);
+        let error = parse_function_ids("SKIP_FUNCTION_ID", &list, 2).unwrap_err();
+        assert_eq!(
+            format!("{:#}", error),
+            "invalid entry 2 of SKIP_FUNCTION_ID: expected 32 bytes"
+        );
+        assert!(parse_function_ids("SKIP_FUNCTION_ID", "0xzz", 1).is_err());
+    }
+
+    #[test]
+    fn test_validate_function_ids() {
+        assert!(target(5).validate().is_ok());
+        let mut with_commitments = target(5);
+        with_commitments.data_commitment_function_id = Some(B256::repeat_byte(3));
+        assert!(with_commitments.validate().is_ok());
+
+        // A target configured without its skip function ID parses, but is rejected by name.
+        let partial = serde_json::json!({
+            "chain_id": 10,
+            "address": Address::repeat_byte(10),
+            "step_function_id": B256::repeat_byte(1),
+        });
+        let partial: RequestTarget = serde_json::from_value(partial).unwrap();
+        assert_eq!(partial.skip_function_id, B256::ZERO);
+        assert_eq!(
+            partial.validate().unwrap_err().to_string(),
+            format!(
+                "target 10:{} has no skip function ID",
+                Address::repeat_byte(10)
+            )
+        );
+        let mut partial = target(5);
+        partial.data_commitment_function_id = Some(B256::ZERO);
+        assert_eq!(
+            partial.validate().unwrap_err().to_string(),
+            format!(
+                "target 5:{} has no data commitment function ID",
+                Address::repeat_byte(5)
+            )
+        );
+    }
+
+    #[test]
+    fn test_function_ids_per_target() {
+        use crate::backend::RequestKind;
+
+        let optimism = target(10);
+        let mut arbitrum = target(42161);
+        arbitrum.step_function_id = B256::repeat_byte(0x11);
+        arbitrum.skip_function_id = B256::repeat_byte(0x12);
+        arbitrum.data_commitment_function_id = Some(B256::repeat_byte(0x13));
+        for (kind, ids) in [
+            (
+                RequestKind::Step,
+                [B256::repeat_byte(1), B256::repeat_byte(0x11)],
+            ),
+            (
+                RequestKind::Skip,
+                [B256::repeat_byte(2), B256::repeat_byte(0x12)],
+            ),
+            (
+                RequestKind::DataCommitment,
+                [B256::ZERO, B256::repeat_byte(0x13)],
+            ),
+        ] {
+            assert_eq!(
+                [kind.function_id(&optimism), kind.function_id(&arbitrum)],
+                ids
+            );
+        }
+    }
+
+    #[test]
+    fn test_resolve_function_ids() {
+        let registered = FunctionIds {
+            step: B256::repeat_byte(0x11),
+            skip: B256::repeat_byte(0x12),
+        };
+
+        // Without getters, the configured IDs.
+        let mut configured = target(5);
+        assert_eq!(
+            configured.resolve_function_ids(None).unwrap(),
+            FunctionIdSource::Config
+        );
+        assert_eq!(configured, target(5));
+
+        // The contract's, for a target configured without them or with the same.
+        let mut unconfigured = target(5);
+        unconfigured.step_function_id = B256::ZERO;
+        unconfigured.skip_function_id = B256::ZERO;
+        assert_eq!(
+            unconfigured.resolve_function_ids(Some(registered)).unwrap(),
+            FunctionIdSource::Contract
+        );
+        assert_eq!(
+            (unconfigured.step_function_id, unconfigured.skip_function_id),
+            (registered.step, registered.skip)
+        );
+        unconfigured.resolve_function_ids(Some(registered)).unwrap();
+
+        // Configured IDs that differ are listed.
+        let mut partial = target(5);
+        partial.step_function_id = registered.step;
+        let error = partial.resolve_function_ids(Some(registered)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            format!(
+                "the function IDs configured for target 5:{} differ from its contract's:\n\
+                 - skip function ID {}\n\
+                 + skip function ID {}",
+                Address::repeat_byte(5),
+                B256::repeat_byte(2),
+                registered.skip
+            )
+        );
+        assert_eq!(partial.skip_function_id, B256::repeat_byte(2));
+        assert!(configured.resolve_function_ids(Some(registered)).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_submit_to_targets_with_failing_target() {
+        let targets = vec![target(1), target(10), target(42161)];
+        let submitted = RefCell::new(Vec::new());
+
+        let submissions = submit_to_targets(&targets, |target| {
+            submitted.borrow_mut().push(target.chain_id);
+            async move {
+                if target.chain_id == 10 {
+                    Err(anyhow!("platform unavailable"))
+                } else {
+                    Ok(format!("request-{}", target.chain_id))
+                }
+            }
+        })
+        .await;
+
+        // Every target is attempted, even after a failure.
+        assert_eq!(*submitted.borrow(), vec![1, 10, 42161]);
+        assert_eq!(submissions.len(), 3);
+        assert_eq!(submissions[0].target, &targets[0]);
+        assert_eq!(submissions[0].result.as_ref().unwrap(), "request-1");
+        assert!(submissions[1].result.is_err());
+        assert_eq!(submissions[2].result.as_ref().unwrap(), "request-42161");
+    }
+}
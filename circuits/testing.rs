@@ -0,0 +1,1065 @@
+//! A mock Tendermint RPC for tests, serving a synthetic chain. Built in this crate's tests, and for
+//! downstream tests with the `testing` feature.
+//!
+//! The chain is generated from a seed: the same seed always gives the same validator keys,
+//! headers and commits. Its headers link to the previous ones, commit to the validator sets that
+//! sign them, and their commits carry valid signatures from every validator, so they pass the same
+//! checks as a real chain's, from `is_valid_skip` to input generation. The server answers the
+//! routes `InputDataFetcher` queries, `/commit`, `/validators`, `/block`, `/status` and the
+//! upgrade plan of `/abci_query`, and the chain can be changed while it serves: advanced, halted,
+//! its validators rotated, an upgrade planned, or made to fail.
+//! A `Scenario` scripts the rotations of its validator set in advance, by share of voting power.
+//!
+//! ```no_run
+//! use tendermintx::testing::{MockTendermintServer, SyntheticChain};
+//! use tendermintx::types::Height;
+//!
+//! # async fn example() -> anyhow::Result<()> {
+//! // 3 of the 4 validators are replaced at block 300: a skip across it is invalid.
+//! let chain = SyntheticChain::new(7).with_head(1000).with_churn(300, 3);
+//! let server = MockTendermintServer::start(chain).await?;
+//! let fetcher = server.fetcher();
+//! let target = fetcher
+//!     .find_block_to_request(Height(100), Height(1000))
+//!     .await?;
+//! assert!(target < Height(300));
+//! # Ok(())
+//! # }
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::ops::RangeInclusive;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use ed25519_consensus::SigningKey;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use subtle_encoding::base64;
+use tendermint::block::header::Version;
+use tendermint::block::signed_header::SignedHeader;
+use tendermint::block::{self, Commit, CommitSig, Header, Round};
+use tendermint::validator::{Info, Set as TendermintValidatorSet};
+use tendermint::vote::{Power, SignedVote, ValidatorIndex, Vote};
+use tendermint::{account, chain, AppHash, Hash, PublicKey, Signature, Time};
+use tokio::sync::oneshot;
+
+use crate::input::tendermint_utils::is_valid_skip;
+use crate::input::{header_hash, InputDataFetcher, InputDataMode};
+use crate::selector::{HeaderFetcher, ValidatorPowers};
+use crate::types::HeaderHash;
+
+/// The default number of validators.
+pub const DEFAULT_VALIDATORS: usize = 4;
+
+/// The voting power of each validator, unless a `Scenario` changes it.
+pub const VOTING_POWER: u64 = 10;
+
+/// A change of the validator set of a `SyntheticChain`, from a block on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Rotation {
+    /// Replace this many validators, the longest serving first, by new ones of the same power.
+    Replace(usize),
+    /// Replace the longest serving validators holding at least this percentage of the voting
+    /// power, by new ones of the same power.
+    ReplacePower(u64),
+    /// Add as many new validators as there are, of `VOTING_POWER` each.
+    DoubleSet,
+    /// The newest validator gains this percentage of the total voting power.
+    GainPower(u64),
+}
+
+/// A validator rotation pattern: the size of the initial validator set, the changes to it by
+/// block, and the chain head.
+///
+/// ```
+/// use tendermintx::testing::Scenario;
+///
+/// let scenario = Scenario::new(10)
+///     .rotate_power(5000, 40)
+///     .double_set(8000)
+///     .gain_power(9000, 20);
+/// let mut chain = scenario.chain(7);
+/// assert_eq!(chain.validators(7999).len(), 10);
+/// assert_eq!(chain.validators(8000).len(), 20);
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scenario {
+    pub validators: usize,
+    pub head: u64,
+    pub changes: Vec<(u64, Rotation)>,
+}
+
+impl Scenario {
+    /// `validators` validators of `VOTING_POWER` each, that don't change, up to block 10000.
+    pub fn new(validators: usize) -> Self {
+        Self {
+            validators,
+            head: 10_000,
+            changes: Vec::new(),
+        }
+    }
+
+    pub fn with_head(mut self, head: u64) -> Self {
+        self.head = head;
+        self
+    }
+
+    /// At `height`, rotate `percent` of the voting power.
+    pub fn rotate_power(mut self, height: u64, percent: u64) -> Self {
+        self.changes.push((height, Rotation::ReplacePower(percent)));
+        self
+    }
+
+    /// At `height`, double the size of the validator set.
+    pub fn double_set(mut self, height: u64) -> Self {
+        self.changes.push((height, Rotation::DoubleSet));
+        self
+    }
+
+    /// At `height`, one validator gains `percent` of the voting power.
+    pub fn gain_power(mut self, height: u64, percent: u64) -> Self {
+        self.changes.push((height, Rotation::GainPower(percent)));
+        self
+    }
+
+    /// The chain of `seed` following this scenario.
+    pub fn chain(&self, seed: u64) -> SyntheticChain {
+        SyntheticChain::new(seed).with_scenario(self)
+    }
+
+    /// 10 validators, 40% of the power rotating at blocks 5000 and 8000: a skip from before 5000
+    /// to 8000 or after keeps 20% of the power, and is invalid.
+    pub fn rotations() -> Self {
+        Self::new(10).rotate_power(5000, 40).rotate_power(8000, 40)
+    }
+
+    /// 10 validators, doubling to 20 at block 8000: more than `VALIDATOR_SET_SIZE_MAX`, while a
+    /// skip across the growth keeps half of the power.
+    pub fn growth() -> Self {
+        Self::new(10).double_set(8000)
+    }
+
+    /// 10 validators, 60% of the power rotating at block 5000 and one of the new validators
+    /// gaining 30% of the power at block 9000: a skip from before 5000 keeps 40% of the power up
+    /// to 8999, and 31% from 9000.
+    pub fn concentration() -> Self {
+        Self::new(10).rotate_power(5000, 60).gain_power(9000, 30)
+    }
+}
+
+/// A synthetic Tendermint chain, generated block by block from a seed.
+#[derive(Debug)]
+pub struct SyntheticChain {
+    seed: u64,
+    chain_id: chain::Id,
+    validators: usize,
+    block_time: Duration,
+    genesis_time: i64,
+    head: u64,
+    halted: bool,
+    /// The changes of the validator set, by the block they apply from.
+    changes: BTreeMap<u64, Vec<Rotation>>,
+    /// The blocks whose queries fail with a 500.
+    failing: Vec<RangeInclusive<u64>>,
+    /// The name and height of the planned upgrade, if any.
+    upgrade_plan: Option<(String, u64)>,
+    keys: HashMap<usize, SigningKey>,
+    /// The header hashes computed so far. Each header links to the previous one.
+    hashes: BTreeMap<u64, Hash>,
+}
+
+impl SyntheticChain {
+    /// A chain of 4 validators, with a block every 6 seconds, at block 1000.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            seed,
+            chain_id: chain::Id::try_from("mock-1".to_string()).expect("valid chain ID"),
+            validators: DEFAULT_VALIDATORS,
+            block_time: Duration::from_secs(6),
+            genesis_time: 1_700_000_000,
+            head: 1000,
+            halted: false,
+            changes: BTreeMap::new(),
+            failing: Vec::new(),
+            upgrade_plan: None,
+            keys: HashMap::new(),
+            hashes: BTreeMap::new(),
+        }
+    }
+
+    pub fn with_chain_id(mut self, chain_id: &str) -> Self {
+        self.chain_id = chain::Id::try_from(chain_id.to_string()).expect("valid chain ID");
+        self
+    }
+
+    pub fn with_validators(mut self, validators: usize) -> Self {
+        assert!(validators > 0, "a chain needs a validator");
+        self.validators = validators;
+        self
+    }
+
+    pub fn with_block_time(mut self, block_time: Duration) -> Self {
+        self.block_time = block_time;
+        self
+    }
+
+    /// The time of block 1, in unix seconds.
+    pub fn with_genesis_time(mut self, genesis_time: i64) -> Self {
+        self.genesis_time = genesis_time;
+        self
+    }
+
+    pub fn with_head(mut self, head: u64) -> Self {
+        assert!(head > 0, "the chain starts at block 1");
+        self.head = head;
+        self
+    }
+
+    /// Replace `replaced` validators, the longest serving first, from block `height` on.
+    pub fn with_churn(mut self, height: u64, replaced: usize) -> Self {
+        self.rotate_validators(height, replaced);
+        self
+    }
+
+    /// The validators, changes and head of `scenario`, in addition to any earlier changes.
+    pub fn with_scenario(mut self, scenario: &Scenario) -> Self {
+        self = self
+            .with_validators(scenario.validators)
+            .with_head(scenario.head);
+        for &(height, rotation) in &scenario.changes {
+            self.change_validators(height, rotation);
+        }
+        self
+    }
+
+    pub fn chain_id(&self) -> &chain::Id {
+        &self.chain_id
+    }
+
+    pub fn head(&self) -> u64 {
+        self.head
+    }
+
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Produce `blocks` new blocks, unless the chain is halted.
+    pub fn advance(&mut self, blocks: u64) {
+        if !self.halted {
+            self.head += blocks;
+        }
+    }
+
+    /// Stop producing blocks: the head stays where it is until `resume`.
+    pub fn halt(&mut self) {
+        self.halted = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.halted = false;
+    }
+
+    /// Replace `replaced` validators from block `height` on, in addition to any earlier churn.
+    pub fn rotate_validators(&mut self, height: u64, replaced: usize) {
+        self.change_validators(height, Rotation::Replace(replaced));
+    }
+
+    /// Apply `rotation` to the validator set from block `height` on, after any earlier change.
+    pub fn change_validators(&mut self, height: u64, rotation: Rotation) {
+        self.changes.entry(height).or_default().push(rotation);
+        // The header before `height` commits to the validators of `height` as its next ones.
+        let first_changed = height.saturating_sub(1);
+        self.hashes.retain(|&block, _| block < first_changed);
+    }
+
+    /// Make the queries for the blocks of `heights` fail with a 500, until `clear_failures`.
+    pub fn fail_heights(&mut self, heights: RangeInclusive<u64>) {
+        self.failing.push(heights);
+    }
+
+    pub fn clear_failures(&mut self) {
+        self.failing.clear();
+    }
+
+    /// Plan the upgrade `name` at block `height`, answered by the upgrade module's `CurrentPlan`
+    /// query until another one is planned.
+    pub fn plan_upgrade(&mut self, name: &str, height: u64) {
+        self.upgrade_plan = Some((name.to_string(), height));
+    }
+
+    fn is_failing(&self, height: u64) -> bool {
+        self.failing.iter().any(|heights| heights.contains(&height))
+    }
+
+    /// A deterministic 32 byte value for `tag` at `height`.
+    fn digest(&self, tag: &str, height: u64) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.seed.to_le_bytes());
+        hasher.update(tag.as_bytes());
+        hasher.update(height.to_le_bytes());
+        hasher.finalize().into()
+    }
+
+    fn key(&mut self, index: usize) -> &SigningKey {
+        let seed = self.digest("validator", index as u64);
+        self.keys
+            .entry(index)
+            .or_insert_with(|| SigningKey::from(seed))
+    }
+
+    /// The indices of the validators of `height` and their voting power, in the order they
+    /// joined.
+    fn members(&self, height: u64) -> Vec<(usize, u64)> {
+        let mut members: Vec<(usize, u64)> = (0..self.validators)
+            .map(|index| (index, VOTING_POWER))
+            .collect();
+        let mut next = self.validators;
+        let rotations = self
+            .changes
+            .range(..=height)
+            .flat_map(|(_, changes)| changes);
+        for &rotation in rotations {
+            let total = members.iter().map(|(_, power)| power).sum::<u64>();
+            let replaced = match rotation {
+                Rotation::Replace(replaced) => replaced.min(members.len()),
+                Rotation::ReplacePower(percent) => {
+                    let mut power = 0;
+                    members
+                        .iter()
+                        .take_while(|(_, p)| {
+                            let short = power * 100 < percent * total;
+                            power += p;
+                            short
+                        })
+                        .count()
+                }
+                Rotation::DoubleSet => {
+                    let added = members.len();
+                    members.extend((next..next + added).map(|index| (index, VOTING_POWER)));
+                    next += added;
+                    0
+                }
+                Rotation::GainPower(percent) => {
+                    if let Some((_, power)) = members.last_mut() {
+                        *power += total * percent / 100;
+                    }
+                    0
+                }
+            };
+            let powers: Vec<u64> = members.drain(..replaced).map(|(_, power)| power).collect();
+            members.extend(powers.into_iter().zip(next..).map(|(power, i)| (i, power)));
+            next += replaced;
+        }
+        members
+    }
+
+    /// The validators of `height` and their keys.
+    fn signers(&mut self, height: u64) -> Vec<(Info, SigningKey)> {
+        self.members(height)
+            .into_iter()
+            .map(|(index, power)| {
+                let key = self.key(index).clone();
+                let pub_key = PublicKey::from_raw_ed25519(&key.verification_key().to_bytes())
+                    .expect("valid ed25519 key");
+                let power = Power::try_from(power).expect("valid power");
+                (Info::new(pub_key, power), key)
+            })
+            .collect()
+    }
+
+    /// The validator set of `height`.
+    pub fn validators(&mut self, height: u64) -> Vec<Info> {
+        self.signers(height)
+            .into_iter()
+            .map(|(info, _)| info)
+            .collect()
+    }
+
+    /// The validators of `height` as a set, in the order they sign.
+    fn validator_set(&mut self, height: u64) -> TendermintValidatorSet {
+        TendermintValidatorSet::new(self.validators(height), None)
+    }
+
+    fn time(&self, height: u64) -> Time {
+        let nanos = self.block_time.as_nanos() as i64 * (height as i64 - 1);
+        Time::from_unix_timestamp(
+            self.genesis_time + nanos / 1_000_000_000,
+            (nanos % 1_000_000_000) as u32,
+        )
+        .expect("valid block time")
+    }
+
+    fn sha256(&self, tag: &str, height: u64) -> Hash {
+        Hash::Sha256(self.digest(tag, height))
+    }
+
+    /// The header of `height`, whose last block ID is that of `previous`.
+    fn build_header(&mut self, height: u64, previous: Option<block::Id>) -> Header {
+        let validators = self.validator_set(height);
+        let next_validators = self.validator_set(height + 1);
+        let proposers = validators.validators();
+        let proposer_address = proposers[height as usize % proposers.len()].address;
+        Header {
+            version: Version { block: 11, app: 1 },
+            chain_id: self.chain_id.clone(),
+            height: block::Height::try_from(height).expect("valid height"),
+            time: self.time(height),
+            last_block_id: previous,
+            last_commit_hash: Some(self.sha256("last_commit", height)),
+            data_hash: Some(self.sha256("data", height)),
+            validators_hash: validators.hash(),
+            next_validators_hash: next_validators.hash(),
+            consensus_hash: self.sha256("consensus", 0),
+            app_hash: AppHash::try_from(self.digest("app", height).to_vec())
+                .expect("valid app hash"),
+            last_results_hash: Some(self.sha256("last_results", height)),
+            evidence_hash: Some(self.sha256("evidence", height)),
+            proposer_address,
+        }
+    }
+
+    fn block_id(&self, height: u64, hash: Hash) -> block::Id {
+        block::Id {
+            hash,
+            part_set_header: block::parts::Header::new(1, self.sha256("parts", height))
+                .expect("valid part set header"),
+        }
+    }
+
+    /// The header of `height`, computing the hashes of the headers before it that aren't yet.
+    pub fn header(&mut self, height: u64) -> Header {
+        assert!(height > 0, "the chain starts at block 1");
+        let (mut block, mut previous) = match self.hashes.range(..height).next_back() {
+            Some((&block, &hash)) => (block + 1, Some(self.block_id(block, hash))),
+            None => (1, None),
+        };
+        loop {
+            let header = self.build_header(block, previous);
+            self.hashes.insert(block, header.hash());
+            if block == height {
+                return header;
+            }
+            previous = Some(self.block_id(block, header.hash()));
+            block += 1;
+        }
+    }
+
+    /// The header of `height` and the commit of every validator of `height` to it.
+    pub fn signed_header(&mut self, height: u64) -> SignedHeader {
+        let header = self.header(height);
+        let block_id = self.block_id(height, header.hash());
+        let timestamp = self.time(height + 1);
+        let validators = self.validator_set(height);
+        let keys: HashMap<account::Id, SigningKey> = self
+            .signers(height)
+            .into_iter()
+            .map(|(info, key)| (info.address, key))
+            .collect();
+        let signatures = validators
+            .validators()
+            .iter()
+            .enumerate()
+            .map(|(i, validator)| {
+                let key = &keys[&validator.address];
+                let vote = Vote {
+                    vote_type: tendermint::vote::Type::Precommit,
+                    height: header.height,
+                    round: Round::default(),
+                    block_id: Some(block_id),
+                    timestamp: Some(timestamp),
+                    validator_address: validator.address,
+                    validator_index: ValidatorIndex::try_from(i).expect("valid index"),
+                    // The sign bytes don't include the signature.
+                    signature: Signature::try_from([0u8; 64].as_slice()).ok(),
+                    extension: Default::default(),
+                    extension_signature: None,
+                };
+                let sign_bytes = SignedVote::from_vote(vote, self.chain_id.clone())
+                    .expect("a signature")
+                    .sign_bytes();
+                let signature = key.sign(&sign_bytes).to_bytes();
+                CommitSig::BlockIdFlagCommit {
+                    validator_address: validator.address,
+                    timestamp,
+                    signature: Signature::try_from(signature.as_slice()).ok(),
+                }
+            })
+            .collect();
+        let commit = Commit {
+            height: header.height,
+            round: Round::default(),
+            block_id,
+            signatures,
+        };
+        SignedHeader::new(header, commit).expect("a commit of the header")
+    }
+
+    /// The JSON-RPC result of the request to `route` with `query`, or the status and message of
+    /// the error to answer with.
+    fn respond(&mut self, route: &str, query: &HashMap<String, String>) -> Result<Value, Error> {
+        let height = match query.get("height") {
+            Some(height) => height
+                .trim_matches('"')
+                .parse::<u64>()
+                .map_err(|_| Error::new(format!("invalid height {:?}", height)))?,
+            None => self.head,
+        };
+        if height == 0 || height > self.head {
+            return Err(Error::new(format!(
+                "height {} must be less than or equal to the current blockchain height {}",
+                height, self.head
+            )));
+        }
+        if self.is_failing(height) {
+            return Err(Error::new(format!("failing block {}", height)));
+        }
+        match route {
+            "/commit" => Ok(json!({
+                "signed_header": self.signed_header(height),
+                "canonical": true,
+            })),
+            "/validators" => {
+                let page = query_number(query, "page", 1)?;
+                let per_page = query_number(query, "per_page", 30)?.clamp(1, 100);
+                let validators = self.validator_set(height).validators().clone();
+                let total = validators.len();
+                if page == 0 || (page - 1) * per_page >= total {
+                    return Err(Error::new(format!("page {} out of range", page)));
+                }
+                let start = (page - 1) * per_page;
+                let page: Vec<_> = validators.into_iter().skip(start).take(per_page).collect();
+                Ok(json!({
+                    "block_height": height.to_string(),
+                    "count": page.len().to_string(),
+                    "total": total.to_string(),
+                    "validators": page,
+                }))
+            }
+            "/block" => {
+                let signed_header = self.signed_header(height);
+                let last_commit = match height {
+                    1 => Value::Null,
+                    _ => serde_json::to_value(self.signed_header(height - 1).commit)
+                        .map_err(|e| Error::new(e.to_string()))?,
+                };
+                Ok(json!({
+                    "block_id": signed_header.commit.block_id,
+                    "block": {
+                        "header": signed_header.header,
+                        "data": {"txs": []},
+                        "evidence": {"evidence": []},
+                        "last_commit": last_commit,
+                    },
+                }))
+            }
+            "/status" => {
+                let header = self.header(self.head);
+                Ok(json!({
+                    "node_info": {"network": self.chain_id},
+                    "sync_info": {
+                        "latest_block_hash": header.hash(),
+                        "latest_block_height": self.head.to_string(),
+                        "latest_block_time": header.time,
+                        "catching_up": false,
+                    },
+                }))
+            }
+            "/abci_query" => {
+                let path = query.get("path").map(|path| path.replace("%22", ""));
+                let path = path.as_deref().map(|path| path.trim_matches('"'));
+                let response = match path {
+                    Some(CURRENT_PLAN_PATH) => {
+                        let value = self
+                            .upgrade_plan
+                            .as_ref()
+                            .map_or(Vec::new(), |plan| current_plan_response(&plan.0, plan.1));
+                        let value = String::from_utf8(base64::encode(value)).unwrap();
+                        json!({"code": 0, "log": "", "value": value, "height": height.to_string()})
+                    }
+                    path => json!({
+                        "code": 6,
+                        "log": format!("no query path {:?}", path.unwrap_or_default()),
+                        "value": null,
+                        "height": height.to_string(),
+                    }),
+                };
+                Ok(json!({ "response": response }))
+            }
+            _ => Err(Error {
+                status: StatusCode::NOT_FOUND,
+                message: format!("unknown route {}", route),
+            }),
+        }
+    }
+}
+
+/// The query path of the upgrade module's current plan.
+const CURRENT_PLAN_PATH: &str = "/cosmos.upgrade.v1beta1.Query/CurrentPlan";
+
+/// The protobuf encoded `QueryCurrentPlanResponse` of the upgrade `name` at `height`: a plan with
+/// its name (field 1) and height (field 3).
+fn current_plan_response(name: &str, height: u64) -> Vec<u8> {
+    let mut plan = vec![0x0a, name.len() as u8];
+    plan.extend_from_slice(name.as_bytes());
+    plan.push(0x18);
+    let mut height = height;
+    while height >= 0x80 {
+        plan.push(height as u8 | 0x80);
+        height >>= 7;
+    }
+    plan.push(height as u8);
+    let mut response = vec![0x0a, plan.len() as u8];
+    response.extend(plan);
+    response
+}
+
+/// An error response of the mock RPC.
+struct Error {
+    status: StatusCode,
+    message: String,
+}
+
+impl Error {
+    /// A 500, as the RPC answers the requests it can't serve, e.g. for a block past the head.
+    fn new(message: String) -> Self {
+        Self {
+            status: StatusCode::INTERNAL_SERVER_ERROR,
+            message,
+        }
+    }
+}
+
+fn query_number(
+    query: &HashMap<String, String>,
+    key: &str,
+    default: usize,
+) -> Result<usize, Error> {
+    query.get(key).map_or(Ok(default), |value| {
+        value
+            .trim_matches('"')
+            .parse()
+            .map_err(|_| Error::new(format!("invalid {} {:?}", key, value)))
+    })
+}
+
+fn handle(chain: &Mutex<SyntheticChain>, request: &Request<Body>) -> Response<Body> {
+    let query: HashMap<String, String> = request
+        .uri()
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter_map(|pair| pair.split_once('='))
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect();
+    let result = chain.lock().unwrap().respond(request.uri().path(), &query);
+    let (status, body) = match result {
+        Ok(result) => (
+            StatusCode::OK,
+            json!({"jsonrpc": "2.0", "id": -1, "result": result}),
+        ),
+        Err(error) => (
+            error.status,
+            json!({
+                "jsonrpc": "2.0",
+                "id": -1,
+                "error": {"code": -32603, "message": "Internal error", "data": error.message},
+            }),
+        ),
+    };
+    Response::builder()
+        .status(status)
+        .header("content-type", "application/json")
+        .body(Body::from(body.to_string()))
+        .expect("a valid response")
+}
+
+/// A Tendermint RPC serving a `SyntheticChain` on a local port, until dropped.
+pub struct MockTendermintServer {
+    addr: SocketAddr,
+    chain: Arc<Mutex<SyntheticChain>>,
+    _shutdown: oneshot::Sender<()>,
+}
+
+impl MockTendermintServer {
+    /// Serve `chain` on a free local port.
+    pub async fn start(chain: SyntheticChain) -> Result<Self> {
+        let chain = Arc::new(Mutex::new(chain));
+        let served = chain.clone();
+        let make_service = make_service_fn(move |_| {
+            let chain = served.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let response = handle(&chain, &request);
+                    async move { Ok::<_, Infallible>(response) }
+                }))
+            }
+        });
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")
+            .context("failed to bind the mock Tendermint RPC")?;
+        let addr = listener.local_addr()?;
+        listener.set_nonblocking(true)?;
+        let (shutdown, stopped) = oneshot::channel::<()>();
+        let server = Server::from_tcp(listener)?
+            .serve(make_service)
+            .with_graceful_shutdown(async {
+                stopped.await.ok();
+            });
+        tokio::spawn(server);
+        Ok(Self {
+            addr,
+            chain,
+            _shutdown: shutdown,
+        })
+    }
+
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// A data fetcher querying this server.
+    pub fn fetcher(&self) -> InputDataFetcher {
+        let mut fetcher = InputDataFetcher::new(vec![self.url()], "");
+        fetcher.mode = InputDataMode::Rpc;
+        fetcher
+    }
+
+    /// The chain served, e.g. to compare what was fetched with.
+    pub fn chain(&self) -> MutexGuard<'_, SyntheticChain> {
+        self.chain.lock().unwrap()
+    }
+
+    pub fn advance(&self, blocks: u64) {
+        self.chain().advance(blocks);
+    }
+
+    pub fn halt(&self) {
+        self.chain().halt();
+    }
+
+    pub fn resume(&self) {
+        self.chain().resume();
+    }
+
+    pub fn rotate_validators(&self, height: u64, replaced: usize) {
+        self.chain().rotate_validators(height, replaced);
+    }
+
+    pub fn fail_heights(&self, heights: RangeInclusive<u64>) {
+        self.chain().fail_heights(heights);
+    }
+
+    pub fn clear_failures(&self) {
+        self.chain().clear_failures();
+    }
+
+    pub fn plan_upgrade(&self, name: &str, height: u64) {
+        self.chain().plan_upgrade(name, height);
+    }
+}
+
+/// A fetcher of the recorded mocha-4 blocks in `circuits/fixtures/mocha-4`, reading fixtures only:
+/// its RPC is never reached.
+pub fn fixture_fetcher() -> InputDataFetcher {
+    InputDataFetcher::new(
+        vec!["http://localhost:26657".to_string()],
+        "./circuits/fixtures/mocha-4",
+    )
+}
+
+/// A `SyntheticChain` read directly rather than over RPC, for what only needs a `HeaderFetcher`
+/// (like the selectors): the answers of `MockTendermintServer::fetcher` without the HTTP round
+/// trips, so that many generated chains can be checked quickly. The chain's failures don't apply.
+pub struct InMemoryFetcher {
+    chain: Mutex<SyntheticChain>,
+}
+
+impl InMemoryFetcher {
+    pub fn new(chain: SyntheticChain) -> Self {
+        Self {
+            chain: Mutex::new(chain),
+        }
+    }
+
+    pub fn chain(&self) -> MutexGuard<'_, SyntheticChain> {
+        self.chain.lock().unwrap()
+    }
+}
+
+#[async_trait]
+impl HeaderFetcher for InMemoryFetcher {
+    async fn chain_head(&self) -> Result<u64> {
+        Ok(self.chain().head())
+    }
+
+    async fn header_time(&self, block: u64) -> Result<i64> {
+        Ok(self.chain().header(block).time.unix_timestamp())
+    }
+
+    async fn header_hash_and_time(&self, block: u64) -> Result<(HeaderHash, i64)> {
+        let header = self.chain().header(block);
+        Ok((header_hash(&header), header.time.unix_timestamp()))
+    }
+
+    async fn is_valid_skip(&self, trusted_block: u64, target_block: u64) -> Result<bool> {
+        let mut chain = self.chain();
+        Ok(is_valid_skip(
+            &chain.validators(trusted_block),
+            &chain.validators(target_block),
+            chain.signed_header(target_block).commit,
+        ))
+    }
+
+    async fn validator_powers(&self, block: u64) -> Result<ValidatorPowers> {
+        Ok(self
+            .chain()
+            .validators(block)
+            .into_iter()
+            .map(|validator| (validator.address, validator.power.value()))
+            .collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use plonky2x::prelude::GoldilocksField;
+
+    use super::*;
+    use crate::consts::VALIDATOR_SET_SIZE_MAX;
+    use crate::input::conversion::get_validator_data_from_block;
+    use crate::types::Height;
+
+    #[tokio::test]
+    async fn test_synthetic_chain() {
+        let chain = || SyntheticChain::new(7).with_churn(300, 2);
+        let server = MockTendermintServer::start(chain()).await.unwrap();
+        let fetcher = server.fetcher();
+
+        // The same seed gives the same chain, another seed another one.
+        let signed_header = fetcher
+            .get_signed_header_from_number(Height(500))
+            .await
+            .unwrap();
+        assert_eq!(signed_header, chain().signed_header(500));
+        assert_ne!(signed_header.header, SyntheticChain::new(8).header(500));
+
+        // Each header links to the previous one, and commits to its validators, who all signed
+        // it: input generation verifies the signatures.
+        let previous = fetcher.compute_header_hash(Height(499)).await.unwrap();
+        let last_block_id = signed_header.header.last_block_id.unwrap();
+        assert_eq!(HeaderHash::try_from(last_block_id.hash).unwrap(), previous);
+        let validators = fetcher
+            .get_validator_set_from_number(Height(500))
+            .await
+            .unwrap();
+        assert_eq!(
+            TendermintValidatorSet::new(validators.clone(), None).hash(),
+            signed_header.header.validators_hash
+        );
+        let signed = get_validator_data_from_block::<VALIDATOR_SET_SIZE_MAX, GoldilocksField>(
+            &validators,
+            &signed_header,
+        );
+        assert_eq!(signed.iter().filter(|v| v.signed).count(), 4);
+
+        // Half of the validators were replaced at block 300.
+        let addresses = |validators: Vec<Info>| {
+            validators
+                .into_iter()
+                .map(|v| v.address)
+                .collect::<Vec<_>>()
+        };
+        let before = addresses(
+            fetcher
+                .get_validator_set_from_number(Height(299))
+                .await
+                .unwrap(),
+        );
+        let after = addresses(
+            fetcher
+                .get_validator_set_from_number(Height(300))
+                .await
+                .unwrap(),
+        );
+        assert_eq!(before.iter().filter(|a| after.contains(a)).count(), 2);
+
+        // The header times follow the block time.
+        assert_eq!(
+            signed_header.header.time.unix_timestamp(),
+            1_700_000_000 + 6 * 499
+        );
+    }
+
+    #[test]
+    fn test_scenarios() {
+        for scenario in [
+            Scenario::rotations(),
+            Scenario::growth(),
+            Scenario::concentration(),
+        ] {
+            let mut chain = scenario.chain(7);
+            assert_eq!(chain.head(), 10_000);
+            // Around each change, the headers commit to the validator sets that sign them and
+            // sign the next ones.
+            for &(height, _) in &scenario.changes {
+                for block in height - 1..=height + 1 {
+                    let signed_header = chain.signed_header(block);
+                    let validators = chain.validators(block);
+                    let header = &signed_header.header;
+                    assert_eq!(
+                        TendermintValidatorSet::new(validators.clone(), None).hash(),
+                        header.validators_hash
+                    );
+                    assert_eq!(
+                        TendermintValidatorSet::new(chain.validators(block + 1), None).hash(),
+                        header.next_validators_hash
+                    );
+                    let signed = get_validator_data_from_block::<32, GoldilocksField>(
+                        &validators,
+                        &signed_header,
+                    );
+                    assert_eq!(signed.iter().filter(|v| v.signed).count(), validators.len());
+                }
+            }
+        }
+
+        let powers = |validators: Vec<Info>| {
+            let mut powers: Vec<u64> = validators.iter().map(|v| v.power.value()).collect();
+            powers.sort();
+            powers
+        };
+        let mut chain = Scenario::new(10)
+            .rotate_power(50, 35)
+            .double_set(60)
+            .gain_power(70, 20)
+            .with_head(100)
+            .chain(7);
+        // Validators holding at least 35% of the power are replaced: 4 of them.
+        let before = chain.validators(49);
+        let after = chain.validators(50);
+        let kept = after.iter().filter(|v| before.contains(v)).count();
+        assert_eq!(kept, 6);
+        assert_eq!(powers(after), [10; 10]);
+        assert_eq!(chain.validators(60).len(), 20);
+        // 20% of the 200 total power.
+        assert_eq!(powers(chain.validators(70))[19], 50);
+        assert_eq!(chain.validators(70).len(), 20);
+    }
+
+    #[tokio::test]
+    async fn test_validator_set_growth() {
+        // The skips up to the growth fit the circuit of `VALIDATOR_SET_SIZE_MAX` validators, the
+        // skips across it need a larger one.
+        let server = MockTendermintServer::start(Scenario::growth().chain(7))
+            .await
+            .unwrap();
+        let mut fetcher = server.fetcher();
+        let trusted_hash = header_hash(&server.chain().header(7000));
+        let inputs = fetcher
+            .get_skip_inputs::<VALIDATOR_SET_SIZE_MAX, GoldilocksField>(
+                Height(7000),
+                trusted_hash,
+                Height(7999),
+            )
+            .await
+            .unwrap();
+        assert_eq!(inputs.nb_target_validators, 10);
+        let inputs = fetcher
+            .get_skip_inputs::<32, GoldilocksField>(Height(7000), trusted_hash, Height(8000))
+            .await
+            .unwrap();
+        assert_eq!(inputs.nb_target_validators, 20);
+        assert!(inputs.nb_target_validators > VALIDATOR_SET_SIZE_MAX);
+        // Which the smaller circuit fails on, rather than panicking.
+        let error = fetcher
+            .get_skip_inputs::<VALIDATOR_SET_SIZE_MAX, GoldilocksField>(
+                Height(7000),
+                trusted_hash,
+                Height(8000),
+            )
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("more than the"), "{:#}", error);
+    }
+
+    #[tokio::test]
+    async fn test_validator_pages() {
+        let chain = SyntheticChain::new(7).with_validators(150).with_head(10);
+        let server = MockTendermintServer::start(chain).await.unwrap();
+        let validators = server
+            .fetcher()
+            .get_validator_set_from_number(Height(5))
+            .await
+            .unwrap();
+        assert_eq!(
+            validators,
+            server.chain().validator_set(5).validators().clone()
+        );
+        assert_eq!(validators.len(), 150);
+    }
+
+    #[tokio::test]
+    async fn test_mutations() {
+        let server = MockTendermintServer::start(SyntheticChain::new(7))
+            .await
+            .unwrap();
+        let fetcher = server.fetcher();
+        let get = |route: &str| {
+            let url = format!("{}/{}", server.url(), route);
+            async move { reqwest::get(url).await.unwrap() }
+        };
+        let result = |route: &'static str| {
+            let response = get(route);
+            async move {
+                let body = response.await.text().await.unwrap();
+                serde_json::from_str::<Value>(&body).unwrap()["result"].clone()
+            }
+        };
+
+        let status = result("status").await;
+        assert_eq!(status["sync_info"]["latest_block_height"], "1000");
+        assert_eq!(status["node_info"]["network"], "mock-1");
+        let block = result("block?height=500").await;
+        assert_eq!(block["block"]["header"]["height"], "500");
+        assert_eq!(block["block"]["last_commit"]["height"], "499");
+
+        // A halted chain doesn't advance until resumed.
+        server.halt();
+        server.advance(10);
+        let head = fetcher.latest_signed_header().await.header.height;
+        assert_eq!(head.value(), 1000);
+        assert_eq!(get("commit?height=1001").await.status(), 500);
+        server.resume();
+        server.advance(10);
+        let head = fetcher.latest_signed_header().await.header.height;
+        assert_eq!(head.value(), 1010);
+
+        // Failing blocks answer with a 500, until cleared.
+        server.fail_heights(600..=700);
+        assert_eq!(get("commit?height=650").await.status(), 500);
+        assert_eq!(get("validators?height=600").await.status(), 500);
+        assert!(get("commit?height=701").await.status().is_success());
+        server.clear_failures();
+        assert!(get("commit?height=650").await.status().is_success());
+
+        // Rotating the validators changes the headers from the block before.
+        let before = fetcher.compute_header_hash(Height(799)).await.unwrap();
+        let unchanged = fetcher.compute_header_hash(Height(798)).await.unwrap();
+        server.rotate_validators(800, 1);
+        assert_ne!(
+            fetcher.compute_header_hash(Height(799)).await.unwrap(),
+            before
+        );
+        assert_eq!(
+            fetcher.compute_header_hash(Height(798)).await.unwrap(),
+            unchanged
+        );
+    }
+}
@@ -0,0 +1,210 @@
+//! Where the operator reads the trusted state of a target from: its latest block, the header
+//! hashes it stores, the largest skip it accepts and, if it has them, its function IDs.
+//!
+//! The state of an EVM target is its `TendermintX` contract. `LocalTrustedState` keeps it in
+//! memory, optionally persisted to a JSON file, for tests and for targets that aren't EVM chains.
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{anyhow, Context, Result};
+use async_trait::async_trait;
+use ethers::providers::Middleware;
+use serde::{Deserialize, Serialize};
+
+use crate::contract::TendermintXContract;
+use crate::target::FunctionIds;
+use crate::types::{HeaderHash, Height};
+
+/// The trusted state of a light client.
+#[async_trait]
+pub trait TrustedStateProvider: Send + Sync {
+    /// The latest block stored and its header hash.
+    async fn latest(&self) -> Result<(Height, HeaderHash)>;
+
+    /// The header hash stored for `height`, if any.
+    async fn hash_at(&self, height: Height) -> Result<Option<HeaderHash>>;
+
+    /// The largest skip accepted, in blocks.
+    async fn skip_max(&self) -> Result<u64>;
+
+    /// The latest block stored.
+    async fn latest_block(&self) -> Result<Height> {
+        Ok(self.latest().await?.0)
+    }
+
+    /// The step and skip function IDs proofs are requested with, if the light client has them.
+    async fn function_ids(&self) -> Result<Option<FunctionIds>> {
+        Ok(None)
+    }
+}
+
+/// The contract bindings take and return the raw ABI values.
+#[async_trait]
+impl<M: Middleware + 'static> TrustedStateProvider for TendermintXContract<M> {
+    async fn latest(&self) -> Result<(Height, HeaderHash)> {
+        let block = TendermintXContract::latest_block(self).await?;
+        let hash = self
+            .header_hash(block)
+            .await?
+            .ok_or_else(|| anyhow!("no header stored for latest block {}", block))?;
+        Ok((Height(block), HeaderHash(hash)))
+    }
+
+    async fn hash_at(&self, height: Height) -> Result<Option<HeaderHash>> {
+        Ok(self.header_hash(height.value()).await?.map(HeaderHash))
+    }
+
+    async fn skip_max(&self) -> Result<u64> {
+        TendermintXContract::skip_max(self).await
+    }
+
+    // One call instead of two.
+    async fn latest_block(&self) -> Result<Height> {
+        Ok(Height(TendermintXContract::latest_block(self).await?))
+    }
+
+    async fn function_ids(&self) -> Result<Option<FunctionIds>> {
+        TendermintXContract::function_ids(self).await
+    }
+}
+
+/// A trusted state kept in memory, and written to a JSON file on every change if opened from
+/// one.
+#[derive(Debug)]
+pub struct LocalTrustedState {
+    path: Option<PathBuf>,
+    state: Mutex<LocalState>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct LocalState {
+    skip_max: u64,
+    /// The header hash of every stored block, as hex.
+    headers: BTreeMap<u64, String>,
+}
+
+impl LocalTrustedState {
+    /// An empty state accepting skips of up to `skip_max` blocks.
+    pub fn new(skip_max: u64) -> Self {
+        Self {
+            path: None,
+            state: Mutex::new(LocalState {
+                skip_max,
+                headers: BTreeMap::new(),
+            }),
+        }
+    }
+
+    /// The state stored in `path`, or an empty one accepting skips of up to `skip_max` blocks if
+    /// the file doesn't exist yet.
+    pub fn open(path: impl AsRef<Path>, skip_max: u64) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let state = if path.exists() {
+            let contents = std::fs::read(&path)
+                .with_context(|| format!("failed to read {}", path.display()))?;
+            serde_json::from_slice(&contents)
+                .with_context(|| format!("invalid trusted state in {}", path.display()))?
+        } else {
+            LocalState {
+                skip_max,
+                headers: BTreeMap::new(),
+            }
+        };
+        Ok(Self {
+            path: Some(path),
+            state: Mutex::new(state),
+        })
+    }
+
+    /// Store the header hash of `height`, as a fulfilled request would.
+    pub fn insert(&self, height: Height, hash: HeaderHash) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        state.headers.insert(height.value(), hash.to_string());
+        self.save(&state)
+    }
+
+    fn save(&self, state: &LocalState) -> Result<()> {
+        let Some(path) = self.path.as_ref() else {
+            return Ok(());
+        };
+        let contents = serde_json::to_vec_pretty(state)?;
+        std::fs::write(path, contents)
+            .with_context(|| format!("failed to write {}", path.display()))
+    }
+}
+
+fn parse_hash(height: Height, hash: &str) -> Result<HeaderHash> {
+    hash.parse()
+        .with_context(|| format!("invalid header hash stored for block {}", height))
+}
+
+#[async_trait]
+impl TrustedStateProvider for LocalTrustedState {
+    async fn latest(&self) -> Result<(Height, HeaderHash)> {
+        let state = self.state.lock().unwrap();
+        let (&height, hash) = state
+            .headers
+            .last_key_value()
+            .ok_or_else(|| anyhow!("no header stored"))?;
+        Ok((Height(height), parse_hash(Height(height), hash)?))
+    }
+
+    async fn hash_at(&self, height: Height) -> Result<Option<HeaderHash>> {
+        let state = self.state.lock().unwrap();
+        state
+            .headers
+            .get(&height.value())
+            .map(|hash| parse_hash(height, hash))
+            .transpose()
+    }
+
+    async fn skip_max(&self) -> Result<u64> {
+        Ok(self.state.lock().unwrap().skip_max)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_local_trusted_state() {
+        let state = LocalTrustedState::new(500);
+        assert!(state.latest().await.is_err());
+        assert_eq!(state.skip_max().await.unwrap(), 500);
+
+        state.insert(Height(100), HeaderHash([0xab; 32])).unwrap();
+        state.insert(Height(200), HeaderHash([0xcd; 32])).unwrap();
+        assert_eq!(
+            state.latest().await.unwrap(),
+            (Height(200), HeaderHash([0xcd; 32]))
+        );
+        assert_eq!(state.latest_block().await.unwrap(), Height(200));
+        assert_eq!(
+            state.hash_at(Height(100)).await.unwrap(),
+            Some(HeaderHash([0xab; 32]))
+        );
+        assert_eq!(state.hash_at(Height(150)).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn test_local_trusted_state_persists() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trusted.json");
+        let state = LocalTrustedState::open(&path, 500).unwrap();
+        state.insert(Height(100), HeaderHash([0xab; 32])).unwrap();
+
+        // The skip max of the file wins over the default.
+        let reopened = LocalTrustedState::open(&path, 1000).unwrap();
+        assert_eq!(
+            reopened.latest().await.unwrap(),
+            (Height(100), HeaderHash([0xab; 32]))
+        );
+        assert_eq!(reopened.skip_max().await.unwrap(), 500);
+
+        std::fs::write(&path, "not json").unwrap();
+        assert!(LocalTrustedState::open(&path, 500).is_err());
+    }
+}
@@ -0,0 +1,252 @@
+//! The heights and header hashes of the Tendermint chain, as types of their own rather than bare
+//! `u64`s and `[u8; 32]`s, so that a hash can't be passed as a height or a height as a count.
+//!
+//! The ABI encoding of the inputs and calldata, and the contract bindings, still take the raw
+//! values: they convert at that boundary. Both serialize as they did as raw values, a height as a
+//! number and a hash as 0x-prefixed hex.
+
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{anyhow, Context, Result};
+use ethers_core::types::H256;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// The height of a block.
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(transparent)]
+pub struct Height(pub u64);
+
+impl Height {
+    pub const fn value(self) -> u64 {
+        self.0
+    }
+
+    /// The next block.
+    pub const fn next(self) -> Self {
+        Self(self.0 + 1)
+    }
+
+    /// The number of blocks from `earlier` to this one, or zero if `earlier` isn't earlier.
+    pub const fn blocks_since(self, earlier: Self) -> u64 {
+        self.0.saturating_sub(earlier.0)
+    }
+}
+
+impl fmt::Display for Height {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for Height {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let height = s
+            .trim()
+            .parse()
+            .with_context(|| format!("invalid block height {:?}", s))?;
+        Ok(Self(height))
+    }
+}
+
+impl From<u64> for Height {
+    fn from(height: u64) -> Self {
+        Self(height)
+    }
+}
+
+impl From<Height> for u64 {
+    fn from(height: Height) -> Self {
+        height.0
+    }
+}
+
+impl From<tendermint::block::Height> for Height {
+    fn from(height: tendermint::block::Height) -> Self {
+        Self(height.value())
+    }
+}
+
+impl TryFrom<Height> for tendermint::block::Height {
+    type Error = anyhow::Error;
+
+    fn try_from(height: Height) -> Result<Self> {
+        Self::try_from(height.0).map_err(|e| anyhow!("invalid block height {}: {}", height, e))
+    }
+}
+
+/// The hash of a block header.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct HeaderHash(pub [u8; 32]);
+
+impl HeaderHash {
+    pub const fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    pub const fn to_bytes(self) -> [u8; 32] {
+        self.0
+    }
+}
+
+impl fmt::Display for HeaderHash {
+    /// As 0x-prefixed lowercase hex.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#x}", H256(self.0))
+    }
+}
+
+impl fmt::Debug for HeaderHash {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "HeaderHash({})", self)
+    }
+}
+
+impl FromStr for HeaderHash {
+    type Err = anyhow::Error;
+
+    /// 64 hex digits, with or without a 0x prefix.
+    fn from_str(s: &str) -> Result<Self> {
+        let hash =
+            H256::from_str(s.trim()).map_err(|e| anyhow!("invalid header hash {:?}: {}", s, e))?;
+        Ok(Self(hash.0))
+    }
+}
+
+impl From<[u8; 32]> for HeaderHash {
+    fn from(hash: [u8; 32]) -> Self {
+        Self(hash)
+    }
+}
+
+impl From<HeaderHash> for [u8; 32] {
+    fn from(hash: HeaderHash) -> Self {
+        hash.0
+    }
+}
+
+impl From<H256> for HeaderHash {
+    fn from(hash: H256) -> Self {
+        Self(hash.0)
+    }
+}
+
+impl From<HeaderHash> for H256 {
+    fn from(hash: HeaderHash) -> Self {
+        H256(hash.0)
+    }
+}
+
+#[cfg(feature = "operator")]
+impl From<alloy_primitives::B256> for HeaderHash {
+    fn from(hash: alloy_primitives::B256) -> Self {
+        Self(hash.0)
+    }
+}
+
+#[cfg(feature = "operator")]
+impl From<HeaderHash> for alloy_primitives::B256 {
+    fn from(hash: HeaderHash) -> Self {
+        Self::from(hash.0)
+    }
+}
+
+impl TryFrom<tendermint::Hash> for HeaderHash {
+    type Error = anyhow::Error;
+
+    /// Only a SHA-256 hash is a header hash.
+    fn try_from(hash: tendermint::Hash) -> Result<Self> {
+        let bytes = hash.as_bytes().try_into().map_err(|_| {
+            anyhow!(
+                "expected a 32 byte header hash, got {} bytes",
+                hash.as_bytes().len()
+            )
+        })?;
+        Ok(Self(bytes))
+    }
+}
+
+impl Serialize for HeaderHash {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for HeaderHash {
+    /// Only 0x-prefixed hex, as the inputs and records have always been written.
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        if !s.starts_with("0x") {
+            return Err(D::Error::custom(format!(
+                "expected 0x-prefixed hex, got {:?}",
+                s
+            )));
+        }
+        s.parse::<Self>().map_err(D::Error::custom)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hash() -> HeaderHash {
+        let mut hash = [0u8; 32];
+        for (i, byte) in hash.iter_mut().enumerate() {
+            *byte = i as u8;
+        }
+        HeaderHash(hash)
+    }
+
+    const HASH_HEX: &str = "0x000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f";
+
+    #[test]
+    fn test_height() {
+        let height: Height = " 10000 ".parse().unwrap();
+        assert_eq!(height, Height(10000));
+        assert_eq!(height.to_string(), "10000");
+        assert_eq!(height.next(), Height(10001));
+        assert!(height < height.next());
+        assert_eq!(Height(10500).blocks_since(height), 500);
+        assert_eq!(height.blocks_since(Height(10500)), 0);
+        assert!("-1".parse::<Height>().is_err());
+        assert!("0x10".parse::<Height>().is_err());
+
+        let tendermint = tendermint::block::Height::try_from(height).unwrap();
+        assert_eq!(Height::from(tendermint), height);
+        // Tendermint heights are at most i64::MAX.
+        assert!(tendermint::block::Height::try_from(Height(u64::MAX)).is_err());
+
+        assert_eq!(serde_json::to_string(&height).unwrap(), "10000");
+        assert_eq!(serde_json::from_str::<Height>("10000").unwrap(), height);
+    }
+
+    #[test]
+    fn test_header_hash() {
+        assert_eq!(hash().to_string(), HASH_HEX);
+        assert_eq!(HASH_HEX.parse::<HeaderHash>().unwrap(), hash());
+        // The CLI takes hashes without a prefix, in either case.
+        assert_eq!(
+            HASH_HEX[2..].to_uppercase().parse::<HeaderHash>().unwrap(),
+            hash()
+        );
+        assert!("0x0001".parse::<HeaderHash>().is_err());
+        assert!(HASH_HEX.replace('f', "g").parse::<HeaderHash>().is_err());
+
+        let tendermint = tendermint::Hash::Sha256(hash().to_bytes());
+        assert_eq!(HeaderHash::try_from(tendermint).unwrap(), hash());
+        assert!(HeaderHash::try_from(tendermint::Hash::None).is_err());
+        assert_eq!(H256::from(hash()), H256(hash().to_bytes()));
+
+        let json = format!("\"{}\"", HASH_HEX);
+        assert_eq!(serde_json::to_string(&hash()).unwrap(), json);
+        assert_eq!(serde_json::from_str::<HeaderHash>(&json).unwrap(), hash());
+        let unprefixed = format!("\"{}\"", &HASH_HEX[2..]);
+        assert!(serde_json::from_str::<HeaderHash>(&unprefixed).is_err());
+    }
+}
@@ -0,0 +1,177 @@
+//! Chain upgrades at known heights.
+//!
+//! An upgrade can change the consensus parameters of a chain from its halt height on, and skips
+//! across it fail. The operator never proves past a halt height the contract hasn't reached: the
+//! selection is bounded by it (see `selector::halt_height_within`) until a request lands exactly on
+//! it, after which the operator proceeds as before. Halt heights are configured per chain, and can
+//! also be discovered from the upgrade module of Cosmos SDK chains: the `CurrentPlan` query of
+//! `cosmos.upgrade.v1beta1`, through the `abci_query` route of the Tendermint RPC.
+
+use std::collections::BTreeSet;
+
+use anyhow::{ensure, Context, Result};
+use prost::Message;
+use tracing::warn;
+
+use crate::input::InputDataFetcher;
+use crate::selector;
+
+/// The query path of the plan of the next upgrade.
+pub const CURRENT_PLAN_PATH: &str = "/cosmos.upgrade.v1beta1.Query/CurrentPlan";
+
+#[derive(Clone, PartialEq, Message)]
+struct QueryCurrentPlanResponse {
+    #[prost(message, optional, tag = "1")]
+    plan: Option<Plan>,
+}
+
+/// The fields of a `cosmos.upgrade.v1beta1.Plan` the operator reads.
+#[derive(Clone, PartialEq, Message)]
+struct Plan {
+    #[prost(string, tag = "1")]
+    name: String,
+    #[prost(int64, tag = "3")]
+    height: i64,
+}
+
+/// An upgrade the chain plans.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpgradePlan {
+    pub name: String,
+    pub height: u64,
+}
+
+impl UpgradePlan {
+    /// The plan of the protobuf encoded answer to a `CurrentPlan` query, `None` if no upgrade is
+    /// planned.
+    pub fn decode(response: &[u8]) -> Result<Option<Self>> {
+        let response =
+            QueryCurrentPlanResponse::decode(response).context("invalid upgrade plan")?;
+        let Some(plan) = response.plan else {
+            return Ok(None);
+        };
+        ensure!(
+            plan.height > 0,
+            "the upgrade {} is planned at no height",
+            plan.name
+        );
+        Ok(Some(Self {
+            name: plan.name,
+            height: plan.height as u64,
+        }))
+    }
+
+    /// The plan of the next upgrade of the chain `fetcher` queries.
+    pub async fn current(fetcher: &InputDataFetcher) -> Result<Option<Self>> {
+        Self::decode(&fetcher.abci_query(CURRENT_PLAN_PATH).await?)
+    }
+}
+
+/// The halt heights of a chain: those configured, and those discovered since the start.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct HaltHeights {
+    heights: BTreeSet<u64>,
+    /// Whether the upgrade module is asked for the next halt height.
+    discover: bool,
+}
+
+impl HaltHeights {
+    pub fn new(heights: impl IntoIterator<Item = u64>, discover: bool) -> Self {
+        Self {
+            heights: heights.into_iter().collect(),
+            discover,
+        }
+    }
+
+    pub fn heights(&self) -> &BTreeSet<u64> {
+        &self.heights
+    }
+
+    /// With discovery, add the height of the upgrade the chain plans, if any. A failed query is
+    /// logged and leaves the known heights, as is a cancelled plan: at worst, a request lands on
+    /// a height it didn't need to.
+    pub async fn discover(&mut self, fetcher: &InputDataFetcher) {
+        if !self.discover {
+            return;
+        }
+        match UpgradePlan::current(fetcher).await {
+            Ok(Some(plan)) => {
+                if self.heights.insert(plan.height) {
+                    warn!(
+                        height = plan.height,
+                        "Chain upgrade {} planned at block {}: no request will prove past it \
+                         before one lands on it",
+                        plan.name,
+                        plan.height
+                    );
+                }
+            }
+            Ok(None) => {}
+            Err(e) => warn!("Failed to read the chain's upgrade plan: {:#}", e),
+        }
+    }
+
+    /// The halt height a request from `current` to at most `max_end` is bounded by, if any.
+    pub fn within(&self, current: u64, max_end: u64) -> Option<u64> {
+        selector::halt_height_within(current, max_end, &self.heights)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing::{MockTendermintServer, SyntheticChain};
+
+    #[test]
+    fn test_decode() {
+        let response = QueryCurrentPlanResponse {
+            plan: Some(Plan {
+                name: "v2".to_string(),
+                height: 123_456,
+            }),
+        };
+        assert_eq!(
+            UpgradePlan::decode(&response.encode_to_vec()).unwrap(),
+            Some(UpgradePlan {
+                name: "v2".to_string(),
+                height: 123_456,
+            })
+        );
+        // No upgrade planned.
+        assert_eq!(UpgradePlan::decode(&[]).unwrap(), None);
+        let response = QueryCurrentPlanResponse {
+            plan: Some(Plan {
+                name: "v2".to_string(),
+                height: 0,
+            }),
+        };
+        assert!(UpgradePlan::decode(&response.encode_to_vec()).is_err());
+        assert!(UpgradePlan::decode(&[0x0a, 0x05, 0x0a]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_discover() {
+        let server = MockTendermintServer::start(SyntheticChain::new(7))
+            .await
+            .unwrap();
+        let fetcher = server.fetcher();
+        assert_eq!(UpgradePlan::current(&fetcher).await.unwrap(), None);
+
+        // Without discovery, only the configured heights.
+        server.plan_upgrade("v2", 1500);
+        let mut halt_heights = HaltHeights::new([900], false);
+        halt_heights.discover(&fetcher).await;
+        assert_eq!(halt_heights.heights(), &BTreeSet::from([900]));
+
+        let mut halt_heights = HaltHeights::new([900], true);
+        halt_heights.discover(&fetcher).await;
+        assert_eq!(halt_heights.heights(), &BTreeSet::from([900, 1500]));
+        assert_eq!(halt_heights.within(1000, 2000), Some(1500));
+        assert_eq!(halt_heights.within(1500, 2000), None);
+
+        // A failed query keeps the known heights.
+        drop(server);
+        halt_heights.discover(&fetcher).await;
+        assert_eq!(halt_heights.heights(), &BTreeSet::from([900, 1500]));
+    }
+}
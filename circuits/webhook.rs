@@ -0,0 +1,345 @@
+//! A listener for the fulfillment callbacks of the platform.
+//!
+//! The platform POSTs the new status of a request to the listener as JSON, signed with an
+//! HMAC-SHA256 of the body keyed with the shared secret and sent hex encoded in the
+//! `X-Signature` header. Verified callbacks update the request store and wake the run loop, so a
+//! fulfilled request doesn't wait for the next status poll. Polling remains the fallback for
+//! missed callbacks.
+
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use alloy_primitives::hex;
+use anyhow::{Context, Result};
+use hmac::{Hmac, Mac};
+use hyper::body::HttpBody;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use log::{debug, error, info, warn};
+use sha2::Sha256;
+use tokio::sync::Notify;
+
+use crate::audit::{AuditEvent, AuditLog};
+use crate::platform::{parse_callback, FulfillmentStatus};
+use crate::store::{RequestStatus, RequestStore, StatusUpdate};
+
+/// The header holding the signature of a callback.
+pub const SIGNATURE_HEADER: &str = "x-signature";
+
+/// The largest callback body accepted, in bytes.
+const MAX_BODY_LEN: u64 = 64 * 1024;
+
+/// The signature of a callback `body` with `secret`, hex encoded.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("any key length");
+    mac.update(body);
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// What was done with a callback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CallbackOutcome {
+    /// The request's status was updated in the store.
+    Updated,
+    /// The request was already in the reported status, or no longer pending.
+    Unchanged,
+    /// The request is not in the store. The callback is ignored.
+    UnknownRequest,
+    /// The signature is missing or wrong.
+    Unauthorized,
+    /// The body is not a valid callback.
+    BadRequest,
+}
+
+impl CallbackOutcome {
+    /// The HTTP status of the response. Accepted callbacks, including ones for unknown requests,
+    /// get a 200 so that the platform doesn't retry them.
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            CallbackOutcome::Updated
+            | CallbackOutcome::Unchanged
+            | CallbackOutcome::UnknownRequest => StatusCode::OK,
+            CallbackOutcome::Unauthorized => StatusCode::UNAUTHORIZED,
+            CallbackOutcome::BadRequest => StatusCode::BAD_REQUEST,
+        }
+    }
+}
+
+/// Applies verified callbacks to the request store.
+pub struct WebhookHandler {
+    store: Arc<RequestStore>,
+    secret: String,
+    /// Records the applied callbacks, if set.
+    audit: Option<Arc<AuditLog>>,
+    /// Notified after every update to the store.
+    updated: Arc<Notify>,
+}
+
+impl WebhookHandler {
+    pub fn new(store: Arc<RequestStore>, secret: impl Into<String>) -> Self {
+        Self {
+            store,
+            secret: secret.into(),
+            audit: None,
+            updated: Arc::new(Notify::new()),
+        }
+    }
+
+    pub fn with_audit(mut self, audit: Arc<AuditLog>) -> Self {
+        self.audit = Some(audit);
+        self
+    }
+
+    /// Notified whenever a callback updates the store.
+    pub fn updated(&self) -> Arc<Notify> {
+        self.updated.clone()
+    }
+
+    /// Verify a callback with its `signature` header and apply it to the store.
+    pub fn handle(&self, signature: Option<&str>, body: &[u8]) -> CallbackOutcome {
+        if !self.verify(signature, body) {
+            warn!("Rejected a callback with a missing or invalid signature");
+            return CallbackOutcome::Unauthorized;
+        }
+        let (request_id, status) = match std::str::from_utf8(body)
+            .map_err(anyhow::Error::from)
+            .and_then(parse_callback)
+        {
+            Ok(callback) => callback,
+            Err(e) => {
+                warn!("Rejected an invalid callback: {:#}", e);
+                return CallbackOutcome::BadRequest;
+            }
+        };
+        match self.apply(&request_id, status) {
+            Ok(outcome) => outcome,
+            Err(e) => {
+                warn!(
+                    "Failed to apply callback for request {}: {:#}",
+                    request_id, e
+                );
+                CallbackOutcome::Unchanged
+            }
+        }
+    }
+
+    fn verify(&self, signature: Option<&str>, body: &[u8]) -> bool {
+        let Some(signature) = signature.and_then(|s| hex::decode(s.trim()).ok()) else {
+            return false;
+        };
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(self.secret.as_bytes()).expect("any key length");
+        mac.update(body);
+        mac.verify_slice(&signature).is_ok()
+    }
+
+    fn apply(&self, request_id: &str, status: FulfillmentStatus) -> Result<CallbackOutcome> {
+        let Some(record) = self.store.get(request_id)? else {
+            info!("Ignoring callback for unknown request {}", request_id);
+            return Ok(CallbackOutcome::UnknownRequest);
+        };
+        let new_status = RequestStatus::from_fulfillment(&status, record.request_mode);
+        if !record.status.is_pending() || new_status == record.status {
+            debug!(
+                "Callback for request {} leaves it {} [{}]",
+                request_id, record.status, record.labels
+            );
+            return Ok(CallbackOutcome::Unchanged);
+        }
+        let event = AuditEvent::fulfillment(request_id, "callback", &status, record.request_mode);
+        let proof_location = match (&new_status, status) {
+            (RequestStatus::Delivered, FulfillmentStatus::Proved { proof_id }) => proof_id,
+            _ => None,
+        };
+        self.store.update_statuses(&[StatusUpdate {
+            request_id: request_id.to_string(),
+            status: new_status,
+            proof_location,
+        }])?;
+        if let Some(audit) = self.audit.as_ref() {
+            if let Err(e) = audit.record(event) {
+                error!("Failed to write audit log entry: {:#}", e);
+            }
+        }
+        info!(
+            "Callback: request {} is now {} [{}]",
+            request_id, new_status, record.labels
+        );
+        self.updated.notify_one();
+        Ok(CallbackOutcome::Updated)
+    }
+
+    /// Respond to an HTTP request to the listener.
+    pub async fn respond(&self, request: Request<Body>) -> Response<Body> {
+        if request.method() != Method::POST {
+            return empty_response(StatusCode::METHOD_NOT_ALLOWED);
+        }
+        let signature = request
+            .headers()
+            .get(SIGNATURE_HEADER)
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string);
+        let body = match read_limited(request.into_body()).await {
+            Ok(Some(body)) => body,
+            Ok(None) => return empty_response(StatusCode::PAYLOAD_TOO_LARGE),
+            Err(e) => {
+                warn!("Failed to read a callback: {}", e);
+                return empty_response(StatusCode::BAD_REQUEST);
+            }
+        };
+        empty_response(self.handle(signature.as_deref(), &body).status_code())
+    }
+}
+
+/// Read `body`, or None as soon as it is longer than `MAX_BODY_LEN`, so a chunked body without
+/// a declared length can't exceed it either.
+async fn read_limited(mut body: Body) -> Result<Option<Vec<u8>>, hyper::Error> {
+    if body.size_hint().lower() > MAX_BODY_LEN {
+        return Ok(None);
+    }
+    let mut bytes = Vec::new();
+    while let Some(chunk) = body.data().await {
+        let chunk = chunk?;
+        if (bytes.len() + chunk.len()) as u64 > MAX_BODY_LEN {
+            return Ok(None);
+        }
+        bytes.extend_from_slice(&chunk);
+    }
+    Ok(Some(bytes))
+}
+
+fn empty_response(status: StatusCode) -> Response<Body> {
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = status;
+    response
+}
+
+/// Serve the callbacks posted to `addr` until the listener fails.
+pub async fn serve(handler: Arc<WebhookHandler>, addr: SocketAddr) -> Result<()> {
+    let make_service = make_service_fn(move |_| {
+        let handler = handler.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let handler = handler.clone();
+                async move { Ok::<_, Infallible>(handler.respond(request).await) }
+            }))
+        }
+    });
+    let server = Server::try_bind(&addr)
+        .with_context(|| format!("failed to bind the callback listener to {}", addr))?;
+    info!("Listening for platform callbacks on {}", addr);
+    server.serve(make_service).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::tests::new_request;
+
+    const SECRET: &str = "webhook-secret";
+
+    fn handler() -> (tempfile::TempDir, WebhookHandler) {
+        let dir = tempfile::tempdir().unwrap();
+        let store = RequestStore::open(dir.path().join("requests.db")).unwrap();
+        store.insert(&new_request("req_1", 100, 101)).unwrap();
+        (dir, WebhookHandler::new(Arc::new(store), SECRET))
+    }
+
+    fn post(body: &str, signature: Option<String>) -> Request<Body> {
+        let mut request = Request::post("/callback");
+        if let Some(signature) = signature {
+            request = request.header(SIGNATURE_HEADER, signature);
+        }
+        request.body(Body::from(body.to_string())).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_signed_callback() {
+        let (_dir, handler) = handler();
+        let body = r#"{"id": "req_1", "status": "SUCCESS", "transaction_hash": "0xab"}"#;
+
+        let response = handler
+            .respond(post(body, Some(sign(SECRET, body.as_bytes()))))
+            .await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            handler.store.get("req_1").unwrap().unwrap().status,
+            RequestStatus::Relayed
+        );
+        // The update woke the run loop.
+        tokio::time::timeout(
+            std::time::Duration::from_secs(1),
+            handler.updated().notified(),
+        )
+        .await
+        .unwrap();
+
+        // Repeated callbacks are accepted without changing anything.
+        assert_eq!(
+            handler.handle(Some(&sign(SECRET, body.as_bytes())), body.as_bytes()),
+            CallbackOutcome::Unchanged
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejected_callbacks() {
+        let (_dir, handler) = handler();
+        let body = r#"{"id": "req_1", "status": "FAILURE"}"#;
+
+        let response = handler.respond(post(body, None)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = handler
+            .respond(post(body, Some(sign("wrong-secret", body.as_bytes()))))
+            .await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(
+            handler.store.get("req_1").unwrap().unwrap().status,
+            RequestStatus::Pending
+        );
+
+        let unknown = r#"{"id": "req_2", "status": "FAILURE"}"#;
+        assert_eq!(
+            handler.handle(Some(&sign(SECRET, unknown.as_bytes())), unknown.as_bytes()),
+            CallbackOutcome::UnknownRequest
+        );
+        let invalid = r#"{"status": "FAILURE"}"#;
+        assert_eq!(
+            handler.handle(Some(&sign(SECRET, invalid.as_bytes())), invalid.as_bytes()),
+            CallbackOutcome::BadRequest
+        );
+        let response = handler
+            .respond(Request::get("/callback").body(Body::empty()).unwrap())
+            .await;
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    #[tokio::test]
+    async fn test_body_limit() {
+        let (_dir, handler) = handler();
+        let request = |body| Request::post("/callback").body(body).unwrap();
+
+        let body = Body::from(vec![b'a'; MAX_BODY_LEN as usize + 1]);
+        let response = handler.respond(request(body)).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // A chunked body declares no length, and is cut off once it exceeds the limit.
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(
+            async move { while sender.send_data(vec![b'a'; 1024].into()).await.is_ok() {} },
+        );
+        let response = handler.respond(request(body)).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+
+        // A chunked body within the limit is read whole, and rejected for its missing signature.
+        let (mut sender, body) = Body::channel();
+        tokio::spawn(async move {
+            for _ in 0..4 {
+                sender.send_data(vec![b'a'; 1024].into()).await.unwrap();
+            }
+        });
+        let response = handler.respond(request(body)).await;
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+}
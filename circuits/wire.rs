@@ -0,0 +1,89 @@
+//! The serialized forms of the operator's settings, plans and records: the JSON written by
+//! `export-input`, the audit log and the `requests` commands, and read back by other tools.
+//!
+//! Every field names its key explicitly, so that renaming a field doesn't change the format. Byte
+//! fields are 0x-prefixed hex, as the addresses and hashes of `alloy_primitives` are, and
+//! durations are whole seconds (or milliseconds for block times) under a key saying so. The
+//! snapshot tests of each type pin its wire format, so a change to it has to change a fixture
+//! in `fixtures/serde` too.
+
+use alloy_primitives::hex;
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serializer};
+use serde_with::{DeserializeAs, SerializeAs};
+
+/// A fixed size byte array as 0x-prefixed hex, for `#[serde_as(as = "PrefixedHex")]`.
+pub struct PrefixedHex;
+
+impl<const N: usize> SerializeAs<[u8; N]> for PrefixedHex {
+    fn serialize_as<S: Serializer>(bytes: &[u8; N], serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&hex::encode_prefixed(bytes))
+    }
+}
+
+impl<'de, const N: usize> DeserializeAs<'de, [u8; N]> for PrefixedHex {
+    fn deserialize_as<D: Deserializer<'de>>(deserializer: D) -> Result<[u8; N], D::Error> {
+        let s = String::deserialize(deserializer)?;
+        let digits = s
+            .strip_prefix("0x")
+            .ok_or_else(|| D::Error::custom(format!("expected 0x-prefixed hex, got {:?}", s)))?;
+        let bytes = hex::decode(digits).map_err(D::Error::custom)?;
+        let len = bytes.len();
+        bytes
+            .try_into()
+            .map_err(|_| D::Error::custom(format!("expected {} bytes, got {}", N, len)))
+    }
+}
+
+/// Check that `value` serializes to the JSON in `snapshot`, and that `snapshot` deserializes back
+/// to `value`.
+#[cfg(test)]
+pub(crate) fn assert_snapshot<T>(value: &T, snapshot: &str)
+where
+    T: serde::Serialize + serde::de::DeserializeOwned + PartialEq + std::fmt::Debug,
+{
+    let expected: serde_json::Value = serde_json::from_str(snapshot).unwrap();
+    assert_eq!(serde_json::to_value(value).unwrap(), expected);
+    assert_eq!(&serde_json::from_str::<T>(snapshot).unwrap(), value);
+}
+
+/// `assert_snapshot` for a type that can't be compared: `snapshot` deserializes to a value that
+/// serializes back to it.
+#[cfg(test)]
+pub(crate) fn assert_json_snapshot<T>(value: &T, snapshot: &str)
+where
+    T: serde::Serialize + serde::de::DeserializeOwned,
+{
+    let expected: serde_json::Value = serde_json::from_str(snapshot).unwrap();
+    assert_eq!(serde_json::to_value(value).unwrap(), expected);
+    let parsed: T = serde_json::from_str(snapshot).unwrap();
+    assert_eq!(serde_json::to_value(parsed).unwrap(), expected);
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Serialize;
+    use serde_with::serde_as;
+
+    use super::*;
+
+    #[serde_as]
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Hash(#[serde_as(as = "PrefixedHex")] [u8; 4]);
+
+    #[test]
+    fn test_prefixed_hex() {
+        let hash = Hash([0xde, 0xad, 0xbe, 0xef]);
+        assert_eq!(serde_json::to_string(&hash).unwrap(), "\"0xdeadbeef\"");
+        assert_eq!(
+            serde_json::from_str::<Hash>("\"0xDEADBEEF\"").unwrap(),
+            hash
+        );
+
+        let error = serde_json::from_str::<Hash>("\"deadbeef\"").unwrap_err();
+        assert!(error.to_string().contains("expected 0x-prefixed hex"));
+        let error = serde_json::from_str::<Hash>("\"0xdead\"").unwrap_err();
+        assert!(error.to_string().contains("expected 4 bytes, got 2"));
+        assert!(serde_json::from_str::<Hash>("\"0xdeadbeeg\"").is_err());
+    }
+}
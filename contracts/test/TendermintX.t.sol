@@ -5,6 +5,24 @@ import "forge-std/Test.sol";
 import "../src/TendermintX.sol";
 
 contract TendermintXTest is Test {
+    // The fields of the golden vectors, in the alphabetical order `parseJson` decodes them in.
+    struct StepVector {
+        bytes callData;
+        bytes input;
+        string name;
+        uint256 trustedBlock;
+        bytes32 trustedHeaderHash;
+    }
+
+    struct SkipVector {
+        bytes callData;
+        bytes input;
+        string name;
+        uint256 targetBlock;
+        uint256 trustedBlock;
+        bytes32 trustedHeaderHash;
+    }
+
     TendermintX public lightClient;
 
     function setUp() public {
@@ -31,4 +49,56 @@ contract TendermintXTest is Test {
         );
         console.logBytes(encodedInput);
     }
+
+    // The encodings the operator's golden vectors pin, from `cargo run --bin golden_encodings`.
+    function testGoldenEncodings() public {
+        string memory json = vm.readFile(
+            string.concat(vm.projectRoot(), "/test/fixtures/encodings.json")
+        );
+
+        StepVector[] memory steps = abi.decode(
+            vm.parseJson(json, ".step"),
+            (StepVector[])
+        );
+        assertGt(steps.length, 0);
+        for (uint256 i = 0; i < steps.length; i++) {
+            StepVector memory v = steps[i];
+            uint64 trustedBlock = uint64(v.trustedBlock);
+            assertEq(
+                abi.encodePacked(trustedBlock, v.trustedHeaderHash),
+                v.input,
+                v.name
+            );
+            assertEq(
+                abi.encodeWithSelector(lightClient.step.selector, trustedBlock),
+                v.callData,
+                v.name
+            );
+        }
+
+        SkipVector[] memory skips = abi.decode(
+            vm.parseJson(json, ".skip"),
+            (SkipVector[])
+        );
+        assertGt(skips.length, 0);
+        for (uint256 i = 0; i < skips.length; i++) {
+            SkipVector memory v = skips[i];
+            uint64 trustedBlock = uint64(v.trustedBlock);
+            uint64 targetBlock = uint64(v.targetBlock);
+            assertEq(
+                abi.encodePacked(trustedBlock, v.trustedHeaderHash, targetBlock),
+                v.input,
+                v.name
+            );
+            assertEq(
+                abi.encodeWithSelector(
+                    lightClient.skip.selector,
+                    trustedBlock,
+                    targetBlock
+                ),
+                v.callData,
+                v.name
+            );
+        }
+    }
 }
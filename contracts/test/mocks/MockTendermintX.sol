@@ -0,0 +1,37 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.13;
+
+/// @notice The state of the TendermintX contract that the operator reads, with a head that can be
+/// set directly instead of through a proof. Deployed on anvil by the operator's end-to-end test.
+contract MockTendermintX {
+    /// @notice Emitted like the TendermintX contract does when a header is stored.
+    event HeadUpdate(uint64 blockNumber, bytes32 headerHash);
+
+    /// @notice The maximum number of blocks that can be skipped, settable unlike the constant of
+    /// the TendermintX contract.
+    uint64 public SKIP_MAX = 100800;
+
+    /// @notice The latest block that has been committed.
+    uint64 public latestBlock;
+
+    /// @notice Maps block heights to their header hashes.
+    mapping(uint64 => bytes32) public blockHeightToHeaderHash;
+
+    function setSkipMax(uint64 _skipMax) external {
+        SKIP_MAX = _skipMax;
+    }
+
+    /// @notice Store `_header` as the header of `_height`, and make it the latest block.
+    function setHead(uint64 _height, bytes32 _header) external {
+        blockHeightToHeaderHash[_height] = _header;
+        latestBlock = _height;
+        emit HeadUpdate(_height, _header);
+    }
+
+    /// @notice Like the TendermintX contract, store `_header` as the header of `_height` and make
+    /// it the latest block, without an event.
+    function setGenesisHeader(uint64 _height, bytes32 _header) external {
+        blockHeightToHeaderHash[_height] = _header;
+        latestBlock = _height;
+    }
+}
@@ -0,0 +1,293 @@
+//! The operator end to end: a mock TendermintX contract deployed on anvil, the mock Tendermint RPC
+//! of `tendermintx::testing` and the `MockBackend`, through `run_once`. And the migration from one
+//! mock contract to another.
+//!
+//! Ignored by default, as it needs `anvil` and `forge` from Foundry on the PATH, and the
+//! `forge-std` submodule checked out. Run it with `cargo e2e`.
+
+#![cfg(all(feature = "operator", feature = "testing"))]
+
+use std::path::Path;
+use std::process::Command;
+use std::sync::Arc;
+
+use alloy_primitives::{hex, Address, B256};
+use alloy_sol_types::{sol, SolCall};
+use ethers::providers::{Http, Middleware, Provider};
+use ethers::signers::LocalWallet;
+use ethers::types::{TransactionRequest, H160};
+use ethers::utils::{Anvil, AnvilInstance};
+use tendermintx::backend::mock::MockBackend;
+use tendermintx::backend::RequestKind;
+use tendermintx::contract::TendermintXContract;
+use tendermintx::encoding::{
+    encode_skip_calldata, encode_skip_input, encode_step_calldata, encode_step_input,
+};
+use tendermintx::input::header_hash;
+use tendermintx::labels::Labels;
+use tendermintx::migrate::{self, ContractState};
+use tendermintx::operator::{TendermintXConfig, TendermintXOperator};
+use tendermintx::signer::signer_client;
+use tendermintx::target::{RequestMode, RequestTarget};
+use tendermintx::testing::{MockTendermintServer, SyntheticChain};
+use tendermintx::types::{HeaderHash, Height};
+
+sol! {
+    function setSkipMax(uint64 _skipMax) external;
+    function setHead(uint64 _height, bytes32 _header) external;
+}
+
+/// The mock contract deployed on anvil, and the account sending its transactions.
+struct MockContract {
+    provider: Provider<Http>,
+    address: H160,
+    from: H160,
+}
+
+impl MockContract {
+    /// Build the mock contract with forge and deploy it from the first account of `anvil`.
+    async fn deploy(anvil: &AnvilInstance) -> Self {
+        let contracts = Path::new(env!("CARGO_MANIFEST_DIR")).join("contracts");
+        let status = Command::new("forge")
+            .arg("build")
+            .current_dir(&contracts)
+            .status()
+            .expect("forge is not installed");
+        assert!(status.success(), "forge build failed");
+        let artifact = contracts.join("out/MockTendermintX.sol/MockTendermintX.json");
+        let artifact: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(artifact).unwrap()).unwrap();
+        let bytecode = hex::decode(artifact["bytecode"]["object"].as_str().unwrap()).unwrap();
+
+        let provider = Provider::<Http>::try_from(anvil.endpoint()).unwrap();
+        let from = anvil.addresses()[0];
+        let tx = TransactionRequest::new().from(from).data(bytecode);
+        let receipt = provider
+            .send_transaction(tx, None)
+            .await
+            .unwrap()
+            .await
+            .unwrap()
+            .unwrap();
+        let address = receipt.contract_address.unwrap();
+        Self {
+            provider,
+            address,
+            from,
+        }
+    }
+
+    async fn send(&self, calldata: Vec<u8>) {
+        let tx = TransactionRequest::new()
+            .from(self.from)
+            .to(self.address)
+            .data(calldata);
+        let receipt = self
+            .provider
+            .send_transaction(tx, None)
+            .await
+            .unwrap()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(receipt.status.unwrap().as_u64(), 1);
+    }
+
+    async fn set_skip_max(&self, skip_max: u64) {
+        self.send(setSkipMaxCall { _skipMax: skip_max }.abi_encode())
+            .await;
+    }
+
+    async fn set_head(&self, height: u64, header: HeaderHash) {
+        let call = setHeadCall {
+            _height: height,
+            _header: header.to_bytes().into(),
+        };
+        self.send(call.abi_encode()).await;
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs anvil and forge, run with `cargo e2e`"]
+async fn test_operator_against_anvil() {
+    let anvil = Anvil::new().spawn();
+    let contract = MockContract::deploy(&anvil).await;
+    let server = MockTendermintServer::start(SyntheticChain::new(1))
+        .await
+        .unwrap();
+    let hash = |height| header_hash(&server.chain().header(height));
+
+    contract.set_skip_max(500).await;
+    contract.set_head(100, hash(100)).await;
+
+    let target = RequestTarget {
+        chain_id: anvil.chain_id() as u32,
+        address: Address::from(contract.address.0),
+        step_function_id: B256::repeat_byte(0x22),
+        skip_function_id: B256::repeat_byte(0x33),
+        data_commitment_function_id: None,
+        request_mode: RequestMode::Platform,
+        labels: Labels::new(),
+        mirror_chain_ids: Vec::new(),
+    };
+    let store = tempfile::tempdir().unwrap();
+    let mut config = TendermintXConfig::new(vec![target.clone()]);
+    config.store_path = Some(store.path().join("requests.db"));
+    let backend = Arc::new(MockBackend::new());
+    let provider = Arc::new(contract.provider.clone());
+    let mut operator = TendermintXOperator::new(
+        config,
+        server.fetcher(),
+        Box::new(backend.clone()),
+        vec![provider],
+    )
+    .unwrap();
+
+    // The largest skip within the contract's skip max.
+    assert!(operator.run_once().await.unwrap().any_submitted);
+    let requests = backend.requests();
+    assert_eq!(requests.len(), 1);
+    let request = &requests[0];
+    assert_eq!(request.kind, RequestKind::Skip);
+    assert_eq!(request.target, target);
+    assert_eq!((request.trusted_block, request.target_block), (100, 600));
+    assert_eq!(request.function_id, target.skip_function_id);
+    assert_eq!(
+        request.input.as_ref(),
+        &encode_skip_input(Height(100), hash(100), Height(600))[..]
+    );
+    assert_eq!(
+        request.calldata.as_ref(),
+        &encode_skip_calldata(Height(100), Height(600))[..]
+    );
+
+    // The request is still pending: it isn't submitted again.
+    operator.run_once().await.unwrap();
+    assert_eq!(backend.requests().len(), 1);
+
+    // A header that isn't the chain's stops the iteration before anything is submitted.
+    contract.set_head(600, HeaderHash([0xab; 32])).await;
+    let error = operator.run_once().await.unwrap_err();
+    assert!(error.to_string().contains("does not match"), "{:#}", error);
+    assert_eq!(backend.requests().len(), 1);
+
+    // Once the skip lands, the next one goes up to the chain head.
+    contract.set_head(600, hash(600)).await;
+    operator.run_once().await.unwrap();
+    let requests = backend.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(
+        (requests[1].trusted_block, requests[1].target_block),
+        (600, 1000)
+    );
+    assert_eq!(
+        requests[1].input.as_ref(),
+        &encode_skip_input(Height(600), hash(600), Height(1000))[..]
+    );
+
+    // A single block behind, the next request is a step.
+    contract.set_head(1000, hash(1000)).await;
+    server.advance(1);
+    operator.run_once().await.unwrap();
+    let requests = backend.requests();
+    assert_eq!(requests.len(), 3);
+    let request = &requests[2];
+    assert_eq!(request.kind, RequestKind::Step);
+    assert_eq!((request.trusted_block, request.target_block), (1000, 1001));
+    assert_eq!(request.function_id, target.step_function_id);
+    assert_eq!(
+        request.input.as_ref(),
+        &encode_step_input(Height(1000), hash(1000))[..]
+    );
+    assert_eq!(
+        request.calldata.as_ref(),
+        &encode_step_calldata(Height(1000))[..]
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs anvil and forge, run with `cargo e2e`"]
+async fn test_migrate_against_anvil() {
+    let anvil = Anvil::new().spawn();
+    let old = MockContract::deploy(&anvil).await;
+    let new = MockContract::deploy(&anvil).await;
+    let server = MockTendermintServer::start(SyntheticChain::new(1))
+        .await
+        .unwrap();
+    let fetcher = server.fetcher();
+    let hash = |height| header_hash(&server.chain().header(height));
+
+    let reader = |contract: &MockContract| {
+        TendermintXContract::new(
+            Address::from(contract.address.0),
+            Arc::new(contract.provider.clone()),
+        )
+    };
+    let wallet = LocalWallet::from(anvil.keys()[0].clone());
+    let client = signer_client(new.provider.clone(), wallet).await.unwrap();
+    let signing = TendermintXContract::new(Address::from(new.address.0), Arc::new(client));
+
+    // The old contract's head isn't the chain's header: nothing is sent to the new one.
+    old.set_head(600, HeaderHash([0xab; 32])).await;
+    let error = migrate::migrate(&reader(&old), &signing, &fetcher)
+        .await
+        .unwrap_err();
+    assert!(
+        error.to_string().starts_with("refusing to migrate from"),
+        "{:#}",
+        error
+    );
+    assert_eq!(reader(&new).latest_block().await.unwrap(), 0);
+
+    old.set_head(600, hash(600)).await;
+    let plan = migrate::plan(&reader(&old), &reader(&new), &fetcher)
+        .await
+        .unwrap();
+    let state = ContractState {
+        block: Height(600),
+        header_hash: hash(600),
+    };
+    assert_eq!(plan.state, state);
+    assert!(!plan.seeded);
+
+    let migration = migrate::migrate(&reader(&old), &signing, &fetcher)
+        .await
+        .unwrap();
+    assert!(migration.tx_hash.is_some());
+    assert_eq!(reader(&new).latest_block().await.unwrap(), 600);
+    assert_eq!(
+        reader(&new).header_hash(600).await.unwrap(),
+        Some(hash(600).to_bytes())
+    );
+
+    // Run again, the new contract is only checked.
+    let migration = migrate::migrate(&reader(&old), &signing, &fetcher)
+        .await
+        .unwrap();
+    assert!(migration.plan.seeded);
+    assert_eq!(migration.tx_hash, None);
+
+    // Once the new contract moved on, it isn't seeded again.
+    new.set_head(1000, hash(1000)).await;
+    let error = migrate::plan(&reader(&old), &reader(&new), &fetcher)
+        .await
+        .unwrap_err();
+    assert_eq!(
+        error.to_string(),
+        format!(
+            "{} is already initialized at block 1000",
+            Address::from(new.address.0)
+        )
+    );
+
+    // A new contract that doesn't store the chain's header fails the consistency check.
+    new.set_head(1000, HeaderHash([0xcd; 32])).await;
+    let error = migrate::check_deployment(&reader(&new), &fetcher, &state)
+        .await
+        .unwrap_err();
+    assert!(
+        error.to_string().starts_with("the consistency check of"),
+        "{:#}",
+        error
+    );
+}
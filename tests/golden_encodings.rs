@@ -0,0 +1,72 @@
+//! The step and skip encodings against the golden vectors in `contracts/test/fixtures`, which the
+//! contract's Foundry tests check too. If these fail after a deliberate change to the encodings,
+//! regenerate the vectors with `cargo run --bin golden_encodings -- --regenerate`.
+
+#![cfg(feature = "operator")]
+
+use tendermintx::encoding::{
+    encode_skip_calldata, encode_skip_input, encode_step_calldata, encode_step_input, SkipInput,
+    StepInput,
+};
+use tendermintx::golden::{EncodingVectors, CASES};
+
+const VECTORS: &str = include_str!("../contracts/test/fixtures/encodings.json");
+
+fn vectors() -> EncodingVectors {
+    EncodingVectors::parse(VECTORS).unwrap()
+}
+
+#[test]
+fn test_step_vectors() {
+    let vectors = vectors();
+    assert_eq!(vectors.step.len(), CASES.len());
+    for vector in &vectors.step {
+        let input = encode_step_input(vector.trusted_block, vector.trusted_header_hash);
+        assert_eq!(input, vector.input.as_ref(), "step {}", vector.name);
+        assert_eq!(
+            encode_step_calldata(vector.trusted_block),
+            vector.calldata.as_ref(),
+            "step {}",
+            vector.name
+        );
+        let decoded = StepInput::decode(&vector.input).unwrap();
+        assert_eq!(decoded.trusted_block, vector.trusted_block);
+        assert_eq!(decoded.trusted_header_hash, vector.trusted_header_hash);
+    }
+}
+
+#[test]
+fn test_skip_vectors() {
+    let vectors = vectors();
+    assert_eq!(vectors.skip.len(), CASES.len());
+    for vector in &vectors.skip {
+        let input = encode_skip_input(
+            vector.trusted_block,
+            vector.trusted_header_hash,
+            vector.target_block,
+        );
+        assert_eq!(input, vector.input.as_ref(), "skip {}", vector.name);
+        assert_eq!(
+            encode_skip_calldata(vector.trusted_block, vector.target_block),
+            vector.calldata.as_ref(),
+            "skip {}",
+            vector.name
+        );
+        let decoded = SkipInput::decode(&vector.input).unwrap();
+        assert_eq!(decoded.trusted_block, vector.trusted_block);
+        assert_eq!(decoded.trusted_header_hash, vector.trusted_header_hash);
+        assert_eq!(decoded.target_block, vector.target_block);
+    }
+}
+
+#[test]
+fn test_vectors_are_current() {
+    // Also fails on a case added to or removed from `CASES` without regenerating.
+    let differences = EncodingVectors::generate().diff(&vectors());
+    assert!(
+        differences.is_empty(),
+        "the golden vectors are out of date:\n{}",
+        differences.join("\n")
+    );
+    assert_eq!(EncodingVectors::generate().to_json(), VECTORS);
+}